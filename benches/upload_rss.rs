@@ -0,0 +1,149 @@
+//! Peak memory usage of blob uploads from local files.
+//!
+//! Each upload strategy runs in a child process, so that its peak resident set size can be
+//! compared with the others. The blob size defaults to 1 GiB and can be changed with
+//! `UPLOAD_BENCH_SIZE_MB`. Run with `cargo bench --bench upload_rss [--features mmap]`.
+//!
+//! Pages of a memory-mapped file count toward the RSS of the `mmap` mode, but they are backed
+//! by the page cache rather than the heap and can be reclaimed by the kernel at any time.
+
+use std::{io::Write, process::Command, time::Instant};
+
+use docker_registry::v2::{Client, FileUploadOptions};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+  net::TcpListener,
+};
+
+const MODES: &[&str] = &[
+  "buffered",
+  "file",
+  #[cfg(feature = "mmap")]
+  "mmap",
+];
+
+fn main() {
+  let mut args = std::env::args().skip(1).filter(|a| a != "--bench");
+  match (args.next(), args.next()) {
+    (Some(mode), Some(path)) if MODES.contains(&mode.as_str()) => run(&mode, &path),
+    _ => compare(),
+  }
+}
+
+fn compare() {
+  let size_mb: usize = std::env::var("UPLOAD_BENCH_SIZE_MB")
+    .ok()
+    .and_then(|s| s.parse().ok())
+    .unwrap_or(1024);
+
+  let mut file = tempfile::NamedTempFile::new().unwrap();
+  let chunk = vec![0x5a; 1 << 20];
+  for _ in 0..size_mb {
+    file.write_all(&chunk).unwrap();
+  }
+  file.flush().unwrap();
+
+  println!("uploading a {} MiB blob", size_mb);
+  for mode in MODES {
+    let output = Command::new(std::env::current_exe().unwrap())
+      .arg(mode)
+      .arg(file.path())
+      .output()
+      .unwrap();
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+  }
+}
+
+#[tokio::main]
+async fn run(mode: &str, path: &str) {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  tokio::spawn(serve(listener));
+
+  let client = Client::configure()
+    .registry(&addr.to_string())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  let digest = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+
+  let start = Instant::now();
+  match mode {
+    "buffered" => {
+      let data = tokio::fs::read(path).await.unwrap();
+      client.push_blob("bench", &data, digest).await.unwrap();
+    }
+    "file" => {
+      let options = FileUploadOptions::default();
+      client
+        .push_blob_file("bench", path, Some(digest), &options)
+        .await
+        .unwrap();
+    }
+    #[cfg(feature = "mmap")]
+    "mmap" => {
+      let options = FileUploadOptions::default().mmap(true);
+      client
+        .push_blob_file("bench", path, Some(digest), &options)
+        .await
+        .unwrap();
+    }
+    _ => unreachable!(),
+  }
+
+  println!(
+    "{:>10}: {:>8.2?}, peak RSS {:>6} MiB",
+    mode,
+    start.elapsed(),
+    peak_rss_kb() / 1024
+  );
+}
+
+/// Peak resident set size of the current process, in KiB.
+fn peak_rss_kb() -> u64 {
+  let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+  status
+    .lines()
+    .find_map(|l| l.strip_prefix("VmHWM:"))
+    .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+    .unwrap_or(0)
+}
+
+/// A minimal registry which accepts blob uploads and discards their content.
+async fn serve(listener: TcpListener) {
+  loop {
+    let (stream, _) = listener.accept().await.unwrap();
+    tokio::spawn(async move {
+      let mut stream = BufReader::new(stream);
+      loop {
+        let mut request_line = String::new();
+        if stream.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+          return;
+        }
+        let mut content_length = 0u64;
+        loop {
+          let mut line = String::new();
+          stream.read_line(&mut line).await.unwrap();
+          if line == "\r\n" {
+            break;
+          }
+          if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+              content_length = value.trim().parse().unwrap();
+            }
+          }
+        }
+        tokio::io::copy(&mut (&mut stream).take(content_length), &mut tokio::io::sink())
+          .await
+          .unwrap();
+
+        let response: &[u8] = if request_line.starts_with("POST") {
+          b"HTTP/1.1 202 Accepted\r\nLocation: /v2/bench/blobs/uploads/1\r\nContent-Length: 0\r\n\r\n"
+        } else {
+          b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n"
+        };
+        stream.get_mut().write_all(response).await.unwrap();
+      }
+    });
+  }
+}
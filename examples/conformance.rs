@@ -0,0 +1,58 @@
+use std::{boxed, error};
+
+use docker_registry::conformance::Conformance;
+use tracing::{error, info, warn};
+
+#[tokio::main]
+async fn main() {
+  tracing_subscriber::fmt()
+    .pretty()
+    .with_max_level(tracing::Level::INFO)
+    .init();
+
+  let registry = match std::env::args().nth(1) {
+    Some(x) => x,
+    None => "localhost:5000".into(),
+  };
+
+  let repository = match std::env::args().nth(2) {
+    Some(x) => x,
+    None => "conformance/test".into(),
+  };
+
+  let user = std::env::var("DOCKER_REGISTRY_USER").ok();
+  if user.is_none() {
+    warn!("[{registry}] no $DOCKER_REGISTRY_USER for login user");
+  }
+  let password = std::env::var("DOCKER_REGISTRY_PASSWD").ok();
+  if password.is_none() {
+    warn!("[{registry}] no $DOCKER_REGISTRY_PASSWD for login password");
+  }
+
+  match run(&registry, &repository, user, password).await {
+    Ok(true) => {}
+    Ok(false) => std::process::exit(1),
+    Err(e) => {
+      error!("[{registry}] {e}");
+      std::process::exit(1);
+    }
+  };
+}
+
+async fn run(
+  host: &str,
+  repository: &str,
+  user: Option<String>,
+  passwd: Option<String>,
+) -> Result<bool, boxed::Box<dyn error::Error>> {
+  let client = docker_registry::v2::Client::configure()
+    .registry(host)
+    .insecure_registry(host.starts_with("localhost"))
+    .username(user)
+    .password(passwd)
+    .build()?;
+
+  let report = Conformance::new(client, repository).run().await?;
+  info!("{report}");
+  Ok(report.is_success())
+}
@@ -0,0 +1,266 @@
+//! Images as tar archives, streamed while they are pulled.
+//!
+//! [`pull_to_tar`] writes an image as an [OCI image layout][layout] or `docker save` archive
+//! to any `AsyncWrite`, without staging it on disk: blobs are streamed from the registry into
+//! the archive as they are downloaded. This makes it possible to serve an image as a tarball
+//! from a web service, or to pipe it into `docker load`.
+//!
+//! Docker archives are written in the format of `docker save` since Docker 25: an OCI image
+//! layout with a `manifest.json`, which both `docker load` and OCI tools can read.
+//!
+//! [layout]: https://github.com/opencontainers/image-spec/blob/v1.1.0/image-layout.md
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   archive::{pull_to_tar, ArchiveFormat, ArchiveOptions},
+//!   v2::Client,
+//! };
+//!
+//! let client = Client::configure().registry("quay.io").build()?;
+//! let options = ArchiveOptions {
+//!   format: ArchiveFormat::DockerSave,
+//!   ..Default::default()
+//! };
+//! // Equivalent to `docker save quay.io/coreos/etcd:v3.1.0 > etcd.tar`.
+//! let file = tokio::fs::File::create("etcd.tar").await?;
+//! pull_to_tar(&client, "coreos/etcd", "v3.1.0", file, &options).await?;
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::path::Path;
+
+use futures::TryStreamExt;
+use log::trace;
+use serde_json::{json, Value};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+  copy::{self, ManifestKind, PullOptions, StoredImage, MANIFEST_MEDIA_TYPES, REF_NAME_ANNOTATION},
+  errors::{Error, Result},
+  v2::{manifest::ManifestError, Client, Descriptor},
+};
+
+/// Size of tar blocks.
+const BLOCK_SIZE: usize = 512;
+
+/// Layout of an image archive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArchiveFormat {
+  /// An OCI image layout, as read by `skopeo copy oci-archive:` or `podman load`.
+  #[default]
+  OciLayout,
+  /// An OCI image layout with the `manifest.json` of `docker save`, as read by `docker load`.
+  ///
+  /// Only image manifests are supported, not manifest lists or OCI indexes.
+  DockerSave,
+}
+
+/// Options for [`pull_to_tar`].
+#[derive(Clone, Debug, Default)]
+pub struct ArchiveOptions {
+  pub format: ArchiveFormat,
+  /// Tag verification and layers to include; the archive fails with `Error::LayerNotArchived`
+  /// rather than leave out a layer that is not selected, as its manifest and config still
+  /// reference it.
+  pub pull: PullOptions,
+  /// Name and tag of the image once loaded by `docker load`, `<name>:<tag>` if not set.
+  pub repo_tag: Option<String>,
+}
+
+/// Pull an image, manifest list or OCI index into a tar archive written to `writer`.
+///
+/// `reference` is handled as by [`Client::pull_image`]; the tag, if any, names the image in the
+/// archive. The archive is complete once this returns successfully; on error, what was written
+/// so far is not a valid archive.
+///
+/// Every layer is archived: layers not selected by the layer filter, and foreign layers the
+/// registry does not serve, fail with `Error::LayerNotArchived`. Manifests are verified against
+/// the digest they are pulled by, or the one reported by the registry.
+pub async fn pull_to_tar<W>(
+  client: &Client,
+  name: &str,
+  reference: &str,
+  writer: W,
+  options: &ArchiveOptions,
+) -> Result<StoredImage>
+where
+  W: AsyncWrite + Unpin + Send,
+{
+  let (tag, reference) = copy::split_tag_hint(client, name, reference, &options.pull).await?;
+  let (manifest, media_type, digest) = client
+    .get_raw_manifest(name, reference, Some(MANIFEST_MEDIA_TYPES))
+    .await?;
+  let digest = copy::manifest_digest(reference, digest, &manifest)?;
+  let kind = copy::manifest_kind(&media_type)?;
+  if kind != ManifestKind::Image && options.format == ArchiveFormat::DockerSave {
+    return Err(Error::UnsupportedMediaType(copy::parse_media_type(&media_type)?));
+  }
+
+  let mut image = StoredImage {
+    descriptor: Descriptor::from_digest(&media_type, &digest, manifest.len() as u64),
+    tag,
+    layers: Vec::new(),
+    skipped: Vec::new(),
+  };
+  let mut archive = TarWriter::new(writer);
+  let mut written = Vec::new();
+
+  let mut images = Vec::new();
+  match kind {
+    ManifestKind::Image => images.push((digest.clone(), manifest.clone())),
+    ManifestKind::Index => {
+      for child in copy::index_children(&manifest)? {
+        let (child_manifest, child_media_type, _) = client
+          .get_raw_manifest(name, &child.digest, Some(MANIFEST_MEDIA_TYPES))
+          .await?;
+        copy::manifest_digest(&child.digest, None, &child_manifest)?;
+        if copy::manifest_kind(&child_media_type)? != ManifestKind::Image {
+          return Err(Error::UnsupportedMediaType(copy::parse_media_type(&child_media_type)?));
+        }
+        images.push((child.digest, child_manifest));
+      }
+    }
+  }
+
+  let mut config = None;
+  let mut layer_paths = Vec::new();
+  for (image_digest, image_manifest) in &images {
+    let value: Value = serde_json::from_slice(image_manifest)?;
+    let config_descriptor = copy::descriptor(&value["config"])?;
+    let mut blobs = vec![config_descriptor.clone()];
+    for layer in copy::layers_of(&value)? {
+      let layer = copy::descriptor(layer)?;
+      if !options.pull.layer_filter.matches(&layer) || copy::is_foreign(&layer) {
+        return Err(Error::LayerNotArchived(layer.digest));
+      }
+      layer_paths.push(blob_path(&layer.digest)?);
+      image.layers.push(layer.clone());
+      blobs.push(layer);
+    }
+
+    for blob in blobs {
+      if written.contains(&blob.digest) {
+        continue;
+      }
+      trace!("Archiving blob {}", blob.digest);
+      let chunks = client.get_blob_stream(name, &blob.digest).await?;
+      archive
+        .append_stream(&blob_path(&blob.digest)?, blob.size, chunks)
+        .await?;
+      written.push(blob.digest);
+    }
+    if !written.contains(image_digest) {
+      archive.append(&blob_path(image_digest)?, image_manifest).await?;
+      written.push(image_digest.clone());
+    }
+    config = Some(config_descriptor);
+  }
+  if !written.contains(&digest) {
+    archive.append(&blob_path(&digest)?, &manifest).await?;
+  }
+
+  let mut entry = json!({
+    "mediaType": media_type,
+    "digest": digest,
+    "size": manifest.len(),
+  });
+  if let Some(tag) = &image.tag {
+    entry["annotations"] = json!({ REF_NAME_ANNOTATION: tag });
+  }
+  let index = json!({"schemaVersion": 2, "manifests": [entry]});
+  archive.append("index.json", &serde_json::to_vec(&index)?).await?;
+  archive
+    .append("oci-layout", br#"{"imageLayoutVersion":"1.0.0"}"#)
+    .await?;
+
+  if let (ArchiveFormat::DockerSave, Some(config)) = (options.format, config) {
+    let repo_tag = match (&options.repo_tag, &image.tag) {
+      (Some(repo_tag), _) => Some(repo_tag.clone()),
+      (None, Some(tag)) => Some(format!("{}:{}", name, tag)),
+      (None, None) => None,
+    };
+    let docker_manifest = json!([{
+      "Config": blob_path(&config.digest)?,
+      "RepoTags": repo_tag.into_iter().collect::<Vec<_>>(),
+      "Layers": layer_paths,
+    }]);
+    archive
+      .append("manifest.json", &serde_json::to_vec(&docker_manifest)?)
+      .await?;
+  }
+
+  archive.finish().await?;
+  Ok(image)
+}
+
+/// Path of the blob `digest` in the archive, rejecting digests which are not valid file names.
+fn blob_path(digest: &str) -> Result<String> {
+  copy::blob_path(Path::new(""), digest)?;
+  Ok(format!("blobs/{}", digest.replacen(':', "/", 1)))
+}
+
+/// Writer of tar entries whose size is known before their content.
+struct TarWriter<W> {
+  writer: W,
+}
+
+impl<W: AsyncWrite + Unpin> TarWriter<W> {
+  fn new(writer: W) -> Self {
+    Self { writer }
+  }
+
+  async fn append(&mut self, path: &str, data: &[u8]) -> Result<()> {
+    let chunks = futures::stream::once(futures::future::ready(Ok(data.to_vec())));
+    self.append_stream(path, data.len() as u64, chunks).await
+  }
+
+  /// Append an entry of `size` bytes, failing if `chunks` do not add up to it.
+  async fn append_stream<S>(&mut self, path: &str, size: u64, chunks: S) -> Result<()>
+  where
+    S: futures::Stream<Item = Result<Vec<u8>>>,
+  {
+    let mut header = tar::Header::new_ustar();
+    header.set_path(path)?;
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_cksum();
+    self.writer.write_all(header.as_bytes()).await?;
+
+    let mut written = 0;
+    futures::pin_mut!(chunks);
+    while let Some(chunk) = chunks.try_next().await? {
+      written += chunk.len() as u64;
+      if written > size {
+        break;
+      }
+      self.writer.write_all(&chunk).await?;
+    }
+    if written != size {
+      return Err(ManifestError::Invalid(format!("{} is not {} bytes long as described", path, size)).into());
+    }
+    let padding = (BLOCK_SIZE - (size as usize % BLOCK_SIZE)) % BLOCK_SIZE;
+    self.writer.write_all(&[0; BLOCK_SIZE][..padding]).await?;
+    Ok(())
+  }
+
+  /// Write the end-of-archive marker and flush.
+  async fn finish(mut self) -> Result<()> {
+    self.writer.write_all(&[0; 2 * BLOCK_SIZE]).await?;
+    self.writer.flush().await?;
+    Ok(())
+  }
+}
@@ -0,0 +1,337 @@
+//! Pulling large artifacts, such as machine learning models, to files.
+//!
+//! Repositories of models store a few multi-GB layers, one per file of the model, rather than
+//! the layers of an image. [`Client::pull_artifact`] writes each layer to a file of the
+//! destination directory, named after its `org.opencontainers.image.title` annotation, and
+//! enables by default what such downloads need:
+//!
+//! - layers are downloaded as several `Range` requests in parallel, see [`ParallelDownloads`];
+//! - downloads are written to `<file>.partial` first, and an interrupted pull resumes from where that file ends;
+//! - each file is checked against the digest of its layer before being moved in place, and files already pulled are
+//!   kept if they still match.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::v2::Client;
+//!
+//! let client = Client::configure().registry("localhost:5000").build()?;
+//! let artifact = client
+//!   .pull_artifact("models/llama", "7b", "/srv/models/llama-7b")
+//!   .await?;
+//! for file in &artifact.files {
+//!   println!("{} ({} bytes)", file.path.display(), file.size);
+//! }
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use futures::{Stream, StreamExt};
+use log::trace;
+use reqwest::StatusCode;
+use serde_json::Value;
+use tokio::{
+  fs,
+  io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use crate::{
+  copy::{blob_path, descriptor, layers_of, manifest_kind, parse_media_type, ManifestKind, MANIFEST_MEDIA_TYPES},
+  errors::{Error, Result},
+  v2::{manifest::ManifestError, sha256_digest, Client, ContentDigest, Descriptor, ParallelDownloads},
+};
+
+/// Annotation naming the file a layer is written to.
+pub const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+/// Suffix of the files downloads are written to until they are complete.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+/// Options of [`Client::pull_artifact_with`].
+#[derive(Clone, Debug)]
+pub struct ArtifactOptions {
+  parts: ParallelDownloads,
+  resume: bool,
+  verify: bool,
+}
+
+impl Default for ArtifactOptions {
+  fn default() -> Self {
+    Self {
+      parts: ParallelDownloads::default(),
+      resume: true,
+      verify: true,
+    }
+  }
+}
+
+impl ArtifactOptions {
+  /// Set the size and concurrency of the parts layers are downloaded as.
+  pub fn parts(mut self, parts: ParallelDownloads) -> Self {
+    self.parts = parts;
+    self
+  }
+
+  /// Resume from leftover `.partial` files, true by default; they are overwritten otherwise.
+  pub fn resume(mut self, resume: bool) -> Self {
+    self.resume = resume;
+    self
+  }
+
+  /// Check files against the digest of their layer, true by default.
+  ///
+  /// Without verification, only the size of files already pulled is checked before keeping
+  /// them, and resumed downloads are not re-read to be hashed.
+  pub fn verify(mut self, verify: bool) -> Self {
+    self.verify = verify;
+    self
+  }
+}
+
+/// A layer of an artifact, written to a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PulledFile {
+  pub path: PathBuf,
+  pub digest: String,
+  pub size: u64,
+  /// Bytes which were already on disk, from an earlier pull, and were not downloaded again.
+  pub reused: u64,
+}
+
+/// Outcome of [`Client::pull_artifact`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PulledArtifact {
+  /// Digest of the manifest of the artifact.
+  pub digest: String,
+  /// The `artifactType` of the manifest, or the media type of its config.
+  pub artifact_type: Option<String>,
+  pub files: Vec<PulledFile>,
+}
+
+impl Client {
+  /// Pull the layers of the artifact `reference` of the repository `name` to files of the
+  /// directory `dest`, with the default [`ArtifactOptions`].
+  pub async fn pull_artifact<P: AsRef<Path>>(&self, name: &str, reference: &str, dest: P) -> Result<PulledArtifact> {
+    self
+      .pull_artifact_with(name, reference, dest, &ArtifactOptions::default())
+      .await
+  }
+
+  /// Pull the layers of the artifact `reference` of the repository `name` to files of the
+  /// directory `dest`, which is created if needed.
+  ///
+  /// Layers are written to the file named by their `org.opencontainers.image.title`
+  /// annotation, or after their digest as `<algorithm>-<hex>` if they have none or it is not a
+  /// plain file name. Manifest lists and OCI indexes are not supported.
+  pub async fn pull_artifact_with<P: AsRef<Path>>(
+    &self,
+    name: &str,
+    reference: &str,
+    dest: P,
+    options: &ArtifactOptions,
+  ) -> Result<PulledArtifact> {
+    let dest = dest.as_ref();
+    let (manifest, media_type, digest) = self
+      .get_raw_manifest(name, reference, Some(MANIFEST_MEDIA_TYPES))
+      .await?;
+    if manifest_kind(&media_type)? != ManifestKind::Image {
+      return Err(Error::UnsupportedMediaType(parse_media_type(&media_type)?));
+    }
+    let value: Value = serde_json::from_slice(&manifest)?;
+    let artifact_type = value["artifactType"]
+      .as_str()
+      .or_else(|| value["config"]["mediaType"].as_str())
+      .map(str::to_string);
+
+    let mut layers = Vec::new();
+    for layer in layers_of(&value)? {
+      let layer = descriptor(layer)?;
+      let path = dest.join(file_name(&layer)?);
+      if layers.iter().any(|(_, p)| *p == path) {
+        let message = format!("several layers are named {}", path.display());
+        return Err(ManifestError::Invalid(message).into());
+      }
+      layers.push((layer, path));
+    }
+
+    fs::create_dir_all(dest).await?;
+    let mut files = Vec::new();
+    for (layer, path) in layers {
+      files.push(self.pull_file(name, &layer, path, options).await?);
+    }
+    Ok(PulledArtifact {
+      digest: digest.unwrap_or_else(|| sha256_digest(&manifest)),
+      artifact_type,
+      files,
+    })
+  }
+
+  /// Download the blob of `layer` to `path`, through its partial file.
+  async fn pull_file(
+    &self,
+    name: &str,
+    layer: &Descriptor,
+    path: PathBuf,
+    options: &ArtifactOptions,
+  ) -> Result<PulledFile> {
+    let pulled = |reused| PulledFile {
+      path: path.clone(),
+      digest: layer.digest.clone(),
+      size: layer.size,
+      reused,
+    };
+    if let Ok(metadata) = fs::metadata(&path).await {
+      if metadata.len() == layer.size && (!options.verify || matches_digest(&path, &layer.digest).await?) {
+        trace!("{} is already pulled", path.display());
+        return Ok(pulled(layer.size));
+      }
+    }
+
+    let mut partial = path.clone().into_os_string();
+    partial.push(PARTIAL_SUFFIX);
+    let partial = PathBuf::from(partial);
+    let mut offset = match fs::metadata(&partial).await {
+      Ok(metadata) if options.resume && metadata.len() <= layer.size => metadata.len(),
+      _ => 0,
+    };
+    let mut digest = match options.verify {
+      true => Some(ContentDigest::try_new(&layer.digest)?),
+      false => None,
+    };
+    if let (Some(digest), true) = (&mut digest, offset > 0) {
+      hash_file(&partial, digest).await?;
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&partial).await?;
+    file.set_len(offset).await?;
+    trace!(
+      "Pulling {} to {} from offset {}",
+      layer.digest,
+      partial.display(),
+      offset
+    );
+
+    let parts = self.get_blob_range(name, &layer.digest, offset, layer.size, &options.parts)?;
+    match write_stream(&mut file, digest.as_mut(), parts).await {
+      Ok(()) => {}
+      Err(Error::UnexpectedHttpStatus(StatusCode::OK)) => {
+        trace!("Ranges not supported, downloading {} in one request", layer.digest);
+        file.set_len(0).await?;
+        offset = 0;
+        // The blob is verified by its stream as a whole.
+        digest = None;
+        let whole = self.get_blob_response(name, &layer.digest).await?.stream();
+        write_stream(&mut file, None, whole).await?;
+      }
+      Err(e) => return Err(e),
+    }
+    file.sync_all().await?;
+    drop(file);
+
+    if let Some(digest) = digest {
+      if let Err(e) = digest.verify() {
+        // Start over next time, the partial file may be what is corrupted.
+        fs::remove_file(&partial).await?;
+        return Err(e.into());
+      }
+    }
+    fs::rename(&partial, &path).await?;
+    Ok(pulled(offset))
+  }
+}
+
+/// Append the chunks of `stream` to `file`, hashing them into `digest`.
+async fn write_stream<S>(file: &mut fs::File, mut digest: Option<&mut ContentDigest>, stream: S) -> Result<()>
+where
+  S: Stream<Item = Result<Vec<u8>>>,
+{
+  let mut stream = Box::pin(stream);
+  while let Some(chunk) = stream.next().await {
+    let chunk = chunk?;
+    if let Some(digest) = digest.as_deref_mut() {
+      digest.update(&chunk);
+    }
+    file.write_all(&chunk).await?;
+  }
+  Ok(())
+}
+
+/// Hash the content of the file `path` into `digest`.
+async fn hash_file(path: &Path, digest: &mut ContentDigest) -> Result<()> {
+  let mut file = fs::File::open(path).await?;
+  let mut buffer = vec![0; 1 << 20];
+  loop {
+    match file.read(&mut buffer).await? {
+      0 => return Ok(()),
+      n => digest.update(&buffer[..n]),
+    }
+  }
+}
+
+/// Whether the content of the file `path` matches `digest`.
+async fn matches_digest(path: &Path, digest: &str) -> Result<bool> {
+  let mut content_digest = ContentDigest::try_new(digest)?;
+  hash_file(path, &mut content_digest).await?;
+  Ok(content_digest.verify().is_ok())
+}
+
+/// The name of the file `layer` is written to: its title if it is a plain file name, or its
+/// digest as `<algorithm>-<hex>`.
+fn file_name(layer: &Descriptor) -> Result<String> {
+  let title = layer
+    .annotations
+    .as_ref()
+    .and_then(|annotations| annotations.get(TITLE_ANNOTATION));
+  match title {
+    Some(title) if is_plain_file_name(title) => Ok(title.clone()),
+    _ => {
+      // Rejects digests which are not safe as file names.
+      blob_path(Path::new(""), &layer.digest)?;
+      Ok(layer.digest.replacen(':', "-", 1))
+    }
+  }
+}
+
+/// Whether `name` names a file of the destination directory itself, and not a partial file.
+fn is_plain_file_name(name: &str) -> bool {
+  !name.is_empty()
+    && name != "."
+    && name != ".."
+    && !name.contains(['/', '\\', '\0'])
+    && !name.ends_with(PARTIAL_SUFFIX)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use test_case::test_case;
+
+  use super::*;
+
+  const DIGEST: &str = "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab";
+
+  #[test_case(Some("model.safetensors") => "model.safetensors"; "title")]
+  #[test_case(None => "sha256-9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab"; "no title")]
+  #[test_case(Some("../model.bin") => "sha256-9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab"; "path")]
+  #[test_case(Some("..") => "sha256-9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab"; "parent")]
+  #[test_case(Some("model.bin.partial") => "sha256-9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab"; "partial")]
+  fn layer_file_name(title: Option<&str>) -> String {
+    let layer = Descriptor {
+      digest: DIGEST.to_string(),
+      annotations: title.map(|title| HashMap::from([(TITLE_ANNOTATION.to_string(), title.to_string())])),
+      ..Default::default()
+    };
+    file_name(&layer).unwrap()
+  }
+}
@@ -0,0 +1,514 @@
+//! Storage for blobs addressed by their digest.
+//!
+//! A [`BlobStore`] is where images are pulled to, or pushed from, without going through a
+//! registry: a directory ([`FsBlobStore`], which is also the `blobs` directory of an OCI image
+//! layout), a containerd content store ([`ContainerdContentStore`]), memory
+//! ([`MemoryBlobStore`]) or a repository of a registry ([`RegistryBlobStore`]).
+//! Other stores, such as object storage buckets, can be plugged in by implementing the trait.
+//! The pull-through caches of [`proxy`](crate::proxy) keep their content in a `BlobStore` too.
+//!
+//! Manifests are stored as blobs too, so that an image can be read back from its manifest digest.
+//! See [`pull_to_store`](crate::copy::pull_to_store) and
+//! [`push_from_store`](crate::copy::push_from_store).
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   blob_store::FsBlobStore,
+//!   copy::{pull_to_store, push_from_store, PullOptions},
+//!   v2::Client,
+//! };
+//!
+//! // Carry an image to an air-gapped registry.
+//! let src = Client::configure().registry("quay.io").build()?;
+//! let store = FsBlobStore::new("/mnt/transfer/blobs");
+//! let image = pull_to_store(
+//!   &src,
+//!   "coreos/etcd",
+//!   "v3.1.0",
+//!   &store,
+//!   &PullOptions::default(),
+//! )
+//! .await?;
+//!
+//! let dst = Client::configure().registry("localhost:5000").build()?;
+//! push_from_store(&store, &image.descriptor, &dst, "etcd", "v3.1.0").await?;
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::{
+  collections::HashMap,
+  fmt,
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+
+use futures::{
+  future::BoxFuture,
+  stream::{self, BoxStream},
+  StreamExt, TryStreamExt,
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+  errors::{Error, Result},
+  staging::{self, StagingDir, StagingFile},
+  v2::{Client, ContentDigest},
+};
+
+/// Content of a blob, in chunks.
+pub type BlobChunks<'a> = BoxStream<'a, Result<Vec<u8>>>;
+
+/// Future returned by [`BlobStore`] methods.
+pub type BlobFuture<'a, T> = BoxFuture<'a, Result<T>>;
+
+/// Storage for blobs addressed by their digest.
+pub trait BlobStore: fmt::Debug + Send + Sync {
+  /// Whether the blob `digest` is stored.
+  fn has<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, bool>;
+
+  /// Read the blob `digest`, failing with `Error::BlobNotFound` if it is not stored.
+  fn get<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, BlobChunks<'a>>;
+
+  /// Store `data` as the blob `digest`.
+  ///
+  /// Implementations must not store data which does not match its digest.
+  fn put<'a>(&'a self, digest: &'a str, data: BlobChunks<'a>) -> BlobFuture<'a, ()>;
+
+  /// Check that `bytes` of new blobs can be stored, before any of them is written.
+  ///
+  /// Stores on disk check for free space; by default, nothing is checked.
+  fn reserve(&self, bytes: u64) -> BlobFuture<'_, ()> {
+    let _ = bytes;
+    Box::pin(async { Ok(()) })
+  }
+}
+
+/// Read the whole blob `digest` from `store`, e.g. a manifest; stream layers with `BlobStore::get`.
+pub async fn get_bytes(store: &dyn BlobStore, digest: &str) -> Result<Vec<u8>> {
+  let chunks: Vec<Vec<u8>> = store.get(digest).await?.try_collect().await?;
+  Ok(chunks.concat())
+}
+
+/// Store `data` as the blob `digest` in `store`.
+pub async fn put_bytes(store: &dyn BlobStore, digest: &str, data: Vec<u8>) -> Result<()> {
+  store.put(digest, stream::once(async { Ok(data) }).boxed()).await
+}
+
+/// Blobs kept in memory, e.g. for tests or to inspect images before pushing them.
+#[derive(Debug, Default)]
+pub struct MemoryBlobStore {
+  blobs: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryBlobStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Digests of the stored blobs, sorted.
+  pub fn digests(&self) -> Vec<String> {
+    let mut digests: Vec<String> = self.blobs.lock().unwrap().keys().cloned().collect();
+    digests.sort();
+    digests
+  }
+}
+
+impl BlobStore for MemoryBlobStore {
+  fn has<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, bool> {
+    Box::pin(async move { Ok(self.blobs.lock().unwrap().contains_key(digest)) })
+  }
+
+  fn get<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, BlobChunks<'a>> {
+    Box::pin(async move {
+      match self.blobs.lock().unwrap().get(digest) {
+        Some(data) => Ok(stream::once(futures::future::ready(Ok(data.clone()))).boxed()),
+        None => Err(Error::BlobNotFound(digest.to_string())),
+      }
+    })
+  }
+
+  fn put<'a>(&'a self, digest: &'a str, mut data: BlobChunks<'a>) -> BlobFuture<'a, ()> {
+    Box::pin(async move {
+      let mut verifier = ContentDigest::try_new(digest)?;
+      let mut blob = Vec::new();
+      while let Some(chunk) = data.try_next().await? {
+        verifier.update(&chunk);
+        blob.extend_from_slice(&chunk);
+      }
+      verifier.verify()?;
+      self.blobs.lock().unwrap().insert(digest.to_string(), blob);
+      Ok(())
+    })
+  }
+}
+
+/// Blobs stored as files named `<root>/<algorithm>/<hex>`.
+///
+/// This is the layout of the `blobs` directory of an [OCI image layout][layout]. Blobs are
+/// written to a staging file first, and only moved in place once their digest was verified.
+/// Staging files are next to the blobs unless configured with [`FsBlobStore::staging`].
+///
+/// [layout]: https://github.com/opencontainers/image-spec/blob/v1.1.0/image-layout.md
+#[derive(Clone, Debug)]
+pub struct FsBlobStore {
+  root: PathBuf,
+  staging: Option<StagingDir>,
+}
+
+impl FsBlobStore {
+  pub fn new<P: AsRef<Path>>(root: P) -> Self {
+    Self {
+      root: root.as_ref().to_path_buf(),
+      staging: None,
+    }
+  }
+
+  /// Stage blobs in `staging`, ideally on the same filesystem so that they are moved rather
+  /// than copied into the store.
+  pub fn staging(mut self, staging: StagingDir) -> Self {
+    self.staging = Some(staging);
+    self
+  }
+
+  /// The blobs of the OCI image layout in `dir`.
+  pub fn oci_layout<P: AsRef<Path>>(dir: P) -> Self {
+    Self::new(dir.as_ref().join("blobs"))
+  }
+
+  /// Path of the blob `digest`, rejecting digests which are not of the form `<algorithm>:<hex>`.
+  pub fn path(&self, digest: &str) -> Result<PathBuf> {
+    crate::copy::blob_path(&self.root, digest)
+  }
+}
+
+impl BlobStore for FsBlobStore {
+  fn has<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, bool> {
+    Box::pin(async move { Ok(fs::try_exists(self.path(digest)?).await?) })
+  }
+
+  fn get<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, BlobChunks<'a>> {
+    Box::pin(async move { read_file(&self.path(digest)?, digest).await })
+  }
+
+  fn put<'a>(&'a self, digest: &'a str, data: BlobChunks<'a>) -> BlobFuture<'a, ()> {
+    Box::pin(async move {
+      let path = self.path(digest)?;
+      match &self.staging {
+        Some(staging) => write_file(&path, digest, data, staging).await,
+        None => write_file(&path, digest, data, &staging_beside(&path)).await,
+      }
+    })
+  }
+
+  fn reserve(&self, bytes: u64) -> BlobFuture<'_, ()> {
+    Box::pin(async move {
+      if let Some(staging) = &self.staging {
+        staging.check_space(bytes)?;
+      }
+      std::fs::create_dir_all(&self.root)?;
+      staging::check_space(&self.root, bytes)
+    })
+  }
+}
+
+/// Staging directory of files written to `path`: the directory of `path` itself.
+pub(crate) fn staging_beside(path: &Path) -> StagingDir {
+  StagingDir::new(path.parent().unwrap_or(Path::new(".")))
+}
+
+/// Stream the file at `path`, holding the blob `digest`.
+pub(crate) async fn read_file(path: &Path, digest: &str) -> Result<BlobChunks<'static>> {
+  let file = match fs::File::open(path).await {
+    Ok(file) => file,
+    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::BlobNotFound(digest.to_string())),
+    Err(e) => return Err(e.into()),
+  };
+  Ok(
+    tokio_util::io::ReaderStream::new(file)
+      .map(|chunk| Ok(chunk?.to_vec()))
+      .boxed(),
+  )
+}
+
+/// Write the blob `digest` to `path`, through a file in `staging` moved in place once verified.
+pub(crate) async fn write_file(
+  path: &Path,
+  digest: &str,
+  mut data: BlobChunks<'_>,
+  staging: &StagingDir,
+) -> Result<()> {
+  let mut verifier = ContentDigest::try_new(digest)?;
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).await?;
+  }
+  let (staged, file) = staging.create_file()?;
+  let mut file = fs::File::from_std(file);
+  while let Some(chunk) = data.try_next().await? {
+    verifier.update(&chunk);
+    file.write_all(&chunk).await?;
+  }
+  file.flush().await?;
+  drop(file);
+  verifier.verify()?;
+
+  let path = path.to_path_buf();
+  tokio::task::spawn_blocking(move || staged.persist(&path))
+    .await
+    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+}
+
+/// Blobs stored in a [containerd content store][content], e.g.
+/// `/var/lib/containerd/io.containerd.content.v1.content`.
+///
+/// Blobs are written the way containerd writes them: into `ingest/<sha256 of the ref>/data`
+/// along with the `ref`, `startedat` and `updatedat` files describing the ingest, then made
+/// read-only and moved to `blobs/<algorithm>/<hex>` once their digest was verified. Images
+/// stored here can be imported with `ctr`, or used by tools which read a content store directly.
+///
+/// containerd's metadata database does not know about blobs written behind its back, and its
+/// garbage collector may remove them: point the store at a content store which is not in use by
+/// a running containerd, such as a staging copy.
+///
+/// [content]: https://github.com/containerd/containerd/blob/main/docs/content-flow.md
+#[derive(Clone, Debug)]
+pub struct ContainerdContentStore {
+  root: PathBuf,
+  blobs: FsBlobStore,
+}
+
+impl ContainerdContentStore {
+  pub fn new<P: AsRef<Path>>(root: P) -> Self {
+    let root = root.as_ref().to_path_buf();
+    Self {
+      blobs: FsBlobStore::new(root.join("blobs")),
+      root,
+    }
+  }
+
+  /// Path of the blob `digest`, see [`FsBlobStore::path`].
+  pub fn path(&self, digest: &str) -> Result<PathBuf> {
+    self.blobs.path(digest)
+  }
+
+  /// Directory of the ingest named `reference`, named after the SHA-256 of the name.
+  fn ingest_dir(&self, reference: &str) -> PathBuf {
+    let digest = crate::v2::sha256_digest(reference.as_bytes());
+    self.root.join("ingest").join(&digest["sha256:".len()..])
+  }
+}
+
+impl BlobStore for ContainerdContentStore {
+  fn has<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, bool> {
+    self.blobs.has(digest)
+  }
+
+  fn get<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, BlobChunks<'a>> {
+    self.blobs.get(digest)
+  }
+
+  fn put<'a>(&'a self, digest: &'a str, mut data: BlobChunks<'a>) -> BlobFuture<'a, ()> {
+    Box::pin(async move {
+      let path = self.path(digest)?;
+      let mut verifier = ContentDigest::try_new(digest)?;
+      let reference = format!("docker-registry-{}", digest);
+      let ingest = self.ingest_dir(&reference);
+      // A leftover ingest of the same blob is restarted from scratch.
+      fs::create_dir_all(&ingest).await?;
+      // Removes the ingest once done, even if the write is cancelled.
+      let _ingest_guard = StagingFile::new(ingest.clone());
+
+      let written = async {
+        let started = rfc3339(crate::v2::system_now());
+        fs::write(ingest.join("ref"), &reference).await?;
+        fs::write(ingest.join("startedat"), &started).await?;
+        fs::write(ingest.join("updatedat"), &started).await?;
+
+        let mut file = fs::File::create(ingest.join("data")).await?;
+        while let Some(chunk) = data.try_next().await? {
+          verifier.update(&chunk);
+          file.write_all(&chunk).await?;
+        }
+        file.sync_all().await?;
+        drop(file);
+        fs::write(ingest.join("updatedat"), rfc3339(crate::v2::system_now())).await?;
+        verifier.verify()?;
+
+        #[cfg(unix)]
+        {
+          use std::os::unix::fs::PermissionsExt;
+          fs::set_permissions(ingest.join("data"), std::fs::Permissions::from_mode(0o444)).await?;
+        }
+        if let Some(parent) = path.parent() {
+          fs::create_dir_all(parent).await?;
+        }
+        // Another writer may have committed the blob meanwhile; both copies are identical.
+        if !fs::try_exists(&path).await? {
+          fs::rename(ingest.join("data"), &path).await?;
+        }
+        Ok(())
+      }
+      .await;
+      written
+    })
+  }
+
+  fn reserve(&self, bytes: u64) -> BlobFuture<'_, ()> {
+    Box::pin(async move {
+      std::fs::create_dir_all(&self.root)?;
+      staging::check_space(&self.root, bytes)
+    })
+  }
+}
+
+/// Format `time` as an RFC 3339 UTC timestamp with nanoseconds, as containerd writes them.
+pub(crate) fn rfc3339(time: std::time::SystemTime) -> String {
+  let since_epoch = time.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+  let secs = since_epoch.as_secs();
+  let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+
+  // Civil date from days since the epoch, after Howard Hinnant's `civil_from_days`.
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z.rem_euclid(146_097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + i64::from(month <= 2);
+
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+    year,
+    month,
+    day,
+    rem / 3600,
+    rem % 3600 / 60,
+    rem % 60,
+    since_epoch.subsec_nanos()
+  )
+}
+
+/// Blobs of a repository of a registry.
+///
+/// Blobs are buffered in memory before they are pushed.
+#[derive(Clone, Debug)]
+pub struct RegistryBlobStore {
+  client: Client,
+  name: String,
+}
+
+impl RegistryBlobStore {
+  pub fn new(client: Client, name: &str) -> Self {
+    Self {
+      client,
+      name: name.to_string(),
+    }
+  }
+}
+
+impl BlobStore for RegistryBlobStore {
+  fn has<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, bool> {
+    Box::pin(self.client.has_blob(&self.name, digest))
+  }
+
+  fn get<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, BlobChunks<'a>> {
+    Box::pin(async move {
+      match self.client.get_blob_stream(&self.name, digest).await {
+        Ok(chunks) => Ok(chunks.boxed()),
+        Err(Error::Api(e)) if e.errors().iter().flatten().any(|e| e.code() == "BLOB_UNKNOWN") => {
+          Err(Error::BlobNotFound(digest.to_string()))
+        }
+        Err(e) => Err(e),
+      }
+    })
+  }
+
+  fn put<'a>(&'a self, digest: &'a str, data: BlobChunks<'a>) -> BlobFuture<'a, ()> {
+    Box::pin(async move {
+      let chunks: Vec<Vec<u8>> = data.try_collect().await?;
+      self.client.push_blob(&self.name, &chunks.concat(), digest).await?;
+      Ok(())
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, UNIX_EPOCH};
+
+  use test_case::test_case;
+
+  use super::*;
+  use crate::v2::sha256_digest;
+
+  #[tokio::test]
+  async fn fs_blob_store_round_trips_and_verifies() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = FsBlobStore::oci_layout(dir.path());
+    let digest = sha256_digest(b"hello");
+
+    assert!(!store.has(&digest).await.unwrap());
+    assert!(matches!(get_bytes(&store, &digest).await, Err(Error::BlobNotFound(_))));
+
+    put_bytes(&store, &digest, b"hello".to_vec()).await.unwrap();
+    assert!(store.has(&digest).await.unwrap());
+    assert_eq!(get_bytes(&store, &digest).await.unwrap(), b"hello");
+    assert!(dir.path().join("blobs/sha256").join(&digest[7..]).is_file());
+
+    let other = sha256_digest(b"other");
+    assert!(put_bytes(&store, &other, b"tampered".to_vec()).await.is_err());
+    assert!(!store.has(&other).await.unwrap());
+    assert_eq!(std::fs::read_dir(dir.path().join("blobs/sha256")).unwrap().count(), 1);
+
+    assert!(store.path("sha256:../../etc").is_err());
+  }
+
+  #[tokio::test]
+  async fn containerd_content_store_commits_ingests() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = ContainerdContentStore::new(dir.path());
+    let digest = sha256_digest(b"hello");
+
+    put_bytes(&store, &digest, b"hello".to_vec()).await.unwrap();
+    let path = dir.path().join("blobs/sha256").join(&digest[7..]);
+    assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+    #[cfg(unix)]
+    assert!(std::fs::metadata(&path).unwrap().permissions().readonly());
+    assert_eq!(std::fs::read_dir(dir.path().join("ingest")).unwrap().count(), 0);
+    assert_eq!(get_bytes(&store, &digest).await.unwrap(), b"hello");
+
+    let other = sha256_digest(b"other");
+    assert!(put_bytes(&store, &other, b"tampered".to_vec()).await.is_err());
+    assert!(!store.has(&other).await.unwrap());
+    assert_eq!(std::fs::read_dir(dir.path().join("ingest")).unwrap().count(), 0);
+  }
+
+  #[test_case(UNIX_EPOCH => "1970-01-01T00:00:00.000000000Z"; "epoch")]
+  #[test_case(UNIX_EPOCH + Duration::new(784_111_777, 5) => "1994-11-06T08:49:37.000000005Z"; "before leap day")]
+  #[test_case(UNIX_EPOCH + Duration::from_secs(951_868_799) => "2000-02-29T23:59:59.000000000Z"; "leap day")]
+  fn formats_rfc3339(time: std::time::SystemTime) -> String {
+    rfc3339(time)
+  }
+
+  #[tokio::test]
+  async fn memory_blob_store_verifies() {
+    let store = MemoryBlobStore::new();
+    let digest = sha256_digest(b"hello");
+    assert!(put_bytes(&store, &digest, b"tampered".to_vec()).await.is_err());
+    put_bytes(&store, &digest, b"hello".to_vec()).await.unwrap();
+    assert_eq!(store.digests(), vec![digest]);
+  }
+}
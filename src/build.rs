@@ -0,0 +1,303 @@
+//! Building images from scratch, from a config and layer archives.
+//!
+//! A [`BuiltImage`] assembles the OCI config and manifest of an image whose layers are given as
+//! [`LayerSource`]s, computing the diff IDs of the layers itself, so that images such as
+//! distroless ones can be created without a container runtime or BuildKit. Nothing depends on
+//! the time of the build: the same config and layers always yield the same digest, which is
+//! known before the image is pushed.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   build::{ImageConfig, LayerSource},
+//!   v2::Client,
+//! };
+//!
+//! let client = Client::configure().registry("localhost:5000").build()?;
+//! let config = ImageConfig {
+//!   entrypoint: Some(vec!["/app".to_string()]),
+//!   user: Some("65532".to_string()),
+//!   ..Default::default()
+//! };
+//! let tar = std::fs::read("app.tar")?;
+//! let digest = client
+//!   .build_and_push("app", "v1", &config, vec![LayerSource::tar(tar)])
+//!   .await?;
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::{json, Value};
+
+use crate::{
+  copy,
+  errors::{Error, Result},
+  layer::Compression,
+  v2::{sha256_digest, Client, Descriptor},
+};
+
+/// Media type of the manifests of built images.
+pub const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+/// Media type of the configs of built images.
+pub const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+
+/// The config of an image to build, see the OCI image configuration for the meaning of the
+/// fields.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageConfig {
+  /// Architecture of the image, e.g. `amd64` or `arm64`.
+  pub architecture: String,
+  /// Operating system of the image, e.g. `linux`.
+  pub os: String,
+  /// Variant of the architecture, e.g. `v8` for `arm64`.
+  pub variant: Option<String>,
+  /// Timestamp (RFC 3339) of the image; none is written if unset.
+  pub created: Option<String>,
+  pub author: Option<String>,
+  /// User the process runs as, as `user[:group]`.
+  pub user: Option<String>,
+  /// Environment variables, as `KEY=value`.
+  pub env: Vec<String>,
+  pub entrypoint: Option<Vec<String>>,
+  pub cmd: Option<Vec<String>>,
+  pub working_dir: Option<String>,
+  pub labels: BTreeMap<String, String>,
+  /// Ports to expose, as `port/protocol` (e.g. `8080/tcp`).
+  pub exposed_ports: BTreeSet<String>,
+  pub stop_signal: Option<String>,
+}
+
+impl Default for ImageConfig {
+  fn default() -> Self {
+    Self {
+      architecture: "amd64".to_string(),
+      os: "linux".to_string(),
+      variant: None,
+      created: None,
+      author: None,
+      user: None,
+      env: Vec::new(),
+      entrypoint: None,
+      cmd: None,
+      working_dir: None,
+      labels: BTreeMap::new(),
+      exposed_ports: BTreeSet::new(),
+      stop_signal: None,
+    }
+  }
+}
+
+impl ImageConfig {
+  /// The image config document, with the given diff IDs of the layers.
+  fn to_json(&self, diff_ids: &[String]) -> Value {
+    let mut container = json!({});
+    let mut set = |key: &str, value: Value| {
+      if !value.is_null() {
+        container[key] = value;
+      }
+    };
+    set("User", json!(self.user));
+    if !self.env.is_empty() {
+      set("Env", json!(self.env));
+    }
+    set("Entrypoint", json!(self.entrypoint));
+    set("Cmd", json!(self.cmd));
+    set("WorkingDir", json!(self.working_dir));
+    if !self.labels.is_empty() {
+      set("Labels", json!(self.labels));
+    }
+    if !self.exposed_ports.is_empty() {
+      let ports: BTreeMap<&String, Value> = self.exposed_ports.iter().map(|p| (p, json!({}))).collect();
+      set("ExposedPorts", json!(ports));
+    }
+    set("StopSignal", json!(self.stop_signal));
+
+    let mut config = json!({
+      "architecture": self.architecture,
+      "os": self.os,
+      "config": container,
+      "rootfs": {"type": "layers", "diff_ids": diff_ids},
+    });
+    for (key, value) in [
+      ("variant", &self.variant),
+      ("created", &self.created),
+      ("author", &self.author),
+    ] {
+      if let Some(value) = value {
+        config[key] = Value::from(value.as_str());
+      }
+    }
+    config
+  }
+}
+
+/// A layer of an image to build.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum LayerSource {
+  /// An uncompressed tar archive, compressed with `compression` when the image is built.
+  Tar { data: Vec<u8>, compression: Compression },
+  /// A layer blob already compressed, of the given layer media type.
+  Compressed { data: Vec<u8>, media_type: String },
+  /// A layer blob already stored in the repository the image is pushed to, e.g. a layer shared
+  /// with other images, with the diff ID of its uncompressed archive.
+  Existing { descriptor: Descriptor, diff_id: String },
+}
+
+impl LayerSource {
+  /// The uncompressed tar archive `data`, compressed with gzip.
+  pub fn tar(data: Vec<u8>) -> Self {
+    LayerSource::Tar {
+      data,
+      compression: Compression::Gzip,
+    }
+  }
+}
+
+/// An image built from a config and layers, ready to be pushed.
+#[derive(Clone, Debug)]
+pub struct BuiltImage {
+  manifest: Vec<u8>,
+  config: Vec<u8>,
+  /// Layer descriptors, with the blobs to upload for the layers which are not `Existing`.
+  layers: Vec<(Descriptor, Option<Vec<u8>>)>,
+}
+
+impl BuiltImage {
+  /// Assemble the image made of `layers`, lowest first, with `config`.
+  ///
+  /// Fails with `Error::UnsupportedLayerMediaType` for compressed layers which are not tar
+  /// archives with a supported compression, whose diff IDs cannot be computed.
+  pub fn new(config: &ImageConfig, layers: Vec<LayerSource>) -> Result<Self> {
+    let mut diff_ids = Vec::with_capacity(layers.len());
+    let mut built = Vec::with_capacity(layers.len());
+    for layer in layers {
+      let (descriptor, data, diff_id) = match layer {
+        LayerSource::Tar { data, compression } => {
+          let blob = compression.compress(&data)?;
+          let media_type = compression.layer_media_type(true);
+          (Descriptor::new(media_type, &blob), Some(blob), sha256_digest(&data))
+        }
+        LayerSource::Compressed { data, media_type } => {
+          let diff_id = sha256_digest(&Compression::from_media_type(&media_type)?.decompress(&data)?);
+          (Descriptor::new(&media_type, &data), Some(data), diff_id)
+        }
+        LayerSource::Existing { descriptor, diff_id } => (descriptor, None, diff_id),
+      };
+      diff_ids.push(diff_id);
+      built.push((descriptor, data));
+    }
+
+    let config = serde_json::to_vec(&config.to_json(&diff_ids))?;
+    let manifest = json!({
+      "schemaVersion": 2,
+      "mediaType": MANIFEST_MEDIA_TYPE,
+      "config": Descriptor::new(CONFIG_MEDIA_TYPE, &config),
+      "layers": built.iter().map(|(descriptor, _)| descriptor).collect::<Vec<_>>(),
+    });
+    Ok(Self {
+      manifest: serde_json::to_vec(&manifest)?,
+      config,
+      layers: built,
+    })
+  }
+
+  /// Digest the manifest will have once pushed.
+  pub fn digest(&self) -> String {
+    sha256_digest(&self.manifest)
+  }
+
+  /// The manifest, as it is pushed.
+  pub fn manifest(&self) -> &[u8] {
+    &self.manifest
+  }
+
+  /// The image config, as it is pushed.
+  pub fn config(&self) -> &[u8] {
+    &self.config
+  }
+
+  /// Push the image as `name:reference`, returning the digest of the manifest.
+  ///
+  /// Only the layers missing from `name` are uploaded. Fails with `Error::BlobNotFound` if an
+  /// `Existing` layer is not stored in `name`.
+  pub async fn push(&self, client: &Client, name: &str, reference: &str) -> Result<String> {
+    client.ensure_writable()?;
+    for (descriptor, data) in &self.layers {
+      match data {
+        Some(data) => copy::push_blob_if_missing(client, name, data, &descriptor.digest).await?,
+        None if client.has_blob(name, &descriptor.digest).await? => {}
+        None => return Err(Error::BlobNotFound(descriptor.digest.clone())),
+      }
+    }
+    copy::push_blob_if_missing(client, name, &self.config, &sha256_digest(&self.config)).await?;
+    client
+      .put_manifest(name, reference, MANIFEST_MEDIA_TYPE, &self.manifest)
+      .await
+  }
+}
+
+impl Client {
+  /// Build the image made of `layers`, lowest first, with `config`, and push it as
+  /// `name:reference`, returning the digest of the manifest.
+  ///
+  /// See [`BuiltImage`] to know the digest before pushing.
+  pub async fn build_and_push(
+    &self,
+    name: &str,
+    reference: &str,
+    config: &ImageConfig,
+    layers: Vec<LayerSource>,
+  ) -> Result<String> {
+    BuiltImage::new(config, layers)?.push(self, name, reference).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn builds_deterministic_images() {
+    let config = ImageConfig {
+      entrypoint: Some(vec!["/app".to_string()]),
+      exposed_ports: BTreeSet::from(["8080/tcp".to_string()]),
+      ..Default::default()
+    };
+    let tar = b"not really a tar".to_vec();
+    let gzipped = Compression::Gzip.compress(&tar).unwrap();
+    let layers = || {
+      vec![
+        LayerSource::tar(tar.clone()),
+        LayerSource::Compressed {
+          data: gzipped.clone(),
+          media_type: "application/vnd.oci.image.layer.v1.tar+gzip".to_string(),
+        },
+      ]
+    };
+
+    let image = BuiltImage::new(&config, layers()).unwrap();
+    assert_eq!(image.digest(), BuiltImage::new(&config, layers()).unwrap().digest());
+
+    let config: Value = serde_json::from_slice(image.config()).unwrap();
+    assert_eq!(
+      config["rootfs"]["diff_ids"],
+      json!([sha256_digest(&tar), sha256_digest(&tar)])
+    );
+    assert_eq!(config["config"]["Entrypoint"], json!(["/app"]));
+    assert_eq!(config["config"]["ExposedPorts"], json!({"8080/tcp": {}}));
+    assert!(config.get("created").is_none());
+  }
+}
@@ -0,0 +1,991 @@
+//! Bulk operations with per-item reporting.
+//!
+//! Operations over many items (deleting tags, copying images) do not fail fast by default:
+//! every item is attempted and its outcome is recorded in a [`BulkReport`], including the
+//! error codes returned by the registry.
+//!
+//! Items are processed concurrently. For large migrations against registries with unknown
+//! limits, [`AdaptiveConcurrency`] replaces the fixed concurrency: it is raised while items
+//! succeed, and halved when the registry answers `429 Too Many Requests`, fails with a server
+//! error or times out.
+//!
+//! Long-running jobs are journaled with a [`WorkQueue`]: tasks are persisted to a file as they
+//! complete, so that a job interrupted by a crash or a restart resumes where it stopped, and
+//! its progress can be reported while it runs. Copies and tag deletions take a queue with
+//! `copy::copy_images`, [`Client::delete_queued_tags`] and `GitLabRegistry::delete_queued_tags`;
+//! inventories are not journaled, but `Inventory::refresh` only fetches what changed.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{bulk::BulkOptions, v2::Client};
+//!
+//! let client = Client::configure().registry("localhost:5000").build()?;
+//! let digests = vec!["sha256:...".to_string()];
+//! let report = client
+//!   .delete_manifests("library/busybox", digests, &BulkOptions::default())
+//!   .await;
+//! for item in report.failed() {
+//!   println!("{}: {:?}", item.item, item.error_codes());
+//! }
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::{collections::HashMap, future::Future, time::Duration};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  fs::{self, File, OpenOptions},
+  io::{self, Write},
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+
+use futures::{stream::FuturesOrdered, StreamExt, TryStreamExt};
+use log::debug;
+#[cfg(not(target_arch = "wasm32"))]
+use log::warn;
+use reqwest::StatusCode;
+#[cfg(not(target_arch = "wasm32"))]
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+  errors::{Error, Result},
+  v2::{sleep, Client, Instant, RetryPolicy, TagOperation},
+};
+
+/// Options controlling how bulk operations are run.
+#[derive(Clone, Debug)]
+pub struct BulkOptions {
+  /// Maximum number of items processed concurrently, or the initial one with `adaptive`.
+  pub concurrency: usize,
+  /// Stop at the first failed item, reporting the remaining items as skipped.
+  pub stop_on_error: bool,
+  /// Adjust the concurrency to the load the registry accepts.
+  pub adaptive: Option<AdaptiveConcurrency>,
+  /// Attempt items again, with the backoff of the policy, when they fail with
+  /// `429 Too Many Requests`, a server error or a timeout.
+  ///
+  /// Unlike the retry policy of the client, which repeats single requests, this repeats
+  /// whole items, e.g. a copy which failed halfway.
+  pub retry: Option<RetryPolicy>,
+}
+
+impl Default for BulkOptions {
+  fn default() -> Self {
+    Self {
+      concurrency: 4,
+      stop_on_error: false,
+      adaptive: None,
+      retry: None,
+    }
+  }
+}
+
+/// Concurrency adjusted with additive increase and multiplicative decrease ("AIMD").
+///
+/// The concurrency grows by one for every round of items which succeeded without congestion,
+/// and is multiplied by the `backoff` factor when an item fails with `429 Too Many Requests`,
+/// a server error or a timeout, or takes longer than the latency target. Items which were
+/// already in flight when the concurrency decreased do not decrease it further.
+///
+/// Congestion is only observed once requests are no longer retried: with a retry policy,
+/// setting a latency target also detects items which only succeeded after backing off.
+#[derive(Clone, Debug)]
+pub struct AdaptiveConcurrency {
+  min: usize,
+  max: usize,
+  backoff: f64,
+  latency_target: Option<Duration>,
+}
+
+impl Default for AdaptiveConcurrency {
+  fn default() -> Self {
+    Self {
+      min: 1,
+      max: 64,
+      backoff: 0.5,
+      latency_target: None,
+    }
+  }
+}
+
+impl AdaptiveConcurrency {
+  /// Set the minimum concurrency, 1 by default.
+  pub fn min(mut self, min: usize) -> Self {
+    self.min = min.max(1);
+    self.max = self.max.max(self.min);
+    self
+  }
+
+  /// Set the maximum concurrency, 64 by default.
+  pub fn max(mut self, max: usize) -> Self {
+    self.max = max.max(1);
+    self.min = self.min.min(self.max);
+    self
+  }
+
+  /// Set the factor applied to the concurrency on congestion, between 0 and 1, 0.5 by default.
+  pub fn backoff(mut self, factor: f64) -> Self {
+    self.backoff = factor.clamp(0.0, 1.0);
+    self
+  }
+
+  /// Also treat items taking longer than `target` as a sign of congestion.
+  ///
+  /// Only meaningful if items take comparable times, e.g. not for copies of images of very
+  /// different sizes.
+  pub fn latency_target(mut self, target: Option<Duration>) -> Self {
+    self.latency_target = target;
+    self
+  }
+}
+
+/// Current concurrency limit of a bulk operation.
+struct Controller {
+  adaptive: Option<AdaptiveConcurrency>,
+  limit: f64,
+  /// Sequence number of the first item started after the last decrease.
+  decreased_at: usize,
+}
+
+impl Controller {
+  fn new(options: &BulkOptions) -> Self {
+    let limit = match &options.adaptive {
+      Some(adaptive) => options.concurrency.clamp(adaptive.min, adaptive.max),
+      None => options.concurrency.max(1),
+    };
+    Self {
+      adaptive: options.adaptive.clone(),
+      limit: limit as f64,
+      decreased_at: 0,
+    }
+  }
+
+  fn limit(&self) -> usize {
+    self.limit as usize
+  }
+
+  /// Adjust the limit to the outcome of item `seq`, once `started` items were started.
+  fn observe<T>(&mut self, seq: usize, started: usize, result: &Result<T>, elapsed: Duration) {
+    let adaptive = match &self.adaptive {
+      Some(adaptive) => adaptive,
+      None => return,
+    };
+    let before = self.limit();
+    let congested =
+      matches!(result, Err(e) if is_congestion(e)) || adaptive.latency_target.is_some_and(|target| elapsed > target);
+    if !congested {
+      self.limit = (self.limit + 1.0 / self.limit).min(adaptive.max as f64);
+    } else if seq >= self.decreased_at {
+      self.limit = (self.limit * adaptive.backoff).max(adaptive.min as f64);
+      self.decreased_at = started;
+    }
+    if self.limit() != before {
+      debug!("Bulk operation concurrency changed from {} to {}", before, self.limit());
+    }
+  }
+}
+
+/// Whether `error` shows that the registry is overloaded or rate limiting.
+fn is_congestion(error: &Error) -> bool {
+  match error {
+    Error::Api(e) => e.errors().iter().flatten().any(|e| e.code() == "TOOMANYREQUESTS"),
+    Error::Client { status } => *status == StatusCode::TOO_MANY_REQUESTS,
+    Error::Server { .. } => true,
+    Error::Reqwest(e) => {
+      e.is_timeout()
+        || e
+          .status()
+          .is_some_and(|s| s == StatusCode::TOO_MANY_REQUESTS || s.is_server_error())
+    }
+    Error::Correlated { source, .. } => is_congestion(source),
+    _ => false,
+  }
+}
+
+/// What `Client::delete_tags` does with a manifest pointed to by several tags.
+///
+/// Registries delete tags by deleting the manifest they point to, which removes every tag
+/// pointing to that manifest, requested or not.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SharedDigestPolicy {
+  /// Delete the manifest once, on behalf of every requested tag pointing to it.
+  #[default]
+  DeleteOnce,
+  /// Leave manifests pointed to by more than one of the requested tags alone.
+  Skip,
+  /// Leave manifests also pointed to by tags which were not requested alone.
+  ///
+  /// This lists and resolves every tag of the repository.
+  ProtectOtherTags,
+}
+
+/// Options of `Client::delete_tags`.
+#[derive(Clone, Debug, Default)]
+pub struct DeleteTagsOptions {
+  /// Concurrency and error handling, for resolving tags as well as deleting manifests.
+  pub bulk: BulkOptions,
+  /// Handling of manifests pointed to by several tags.
+  pub shared_digests: SharedDigestPolicy,
+  /// Retry policy used unless the client was configured with its own.
+  ///
+  /// It backs off when the registry answers `429 Too Many Requests`, honouring `Retry-After`.
+  pub retry_policy: RetryPolicy,
+}
+
+/// Outcome of a bulk operation.
+#[derive(Debug)]
+pub struct BulkReport<K, T = ()> {
+  /// Items which were attempted, in input order.
+  pub items: Vec<BulkItem<K, T>>,
+  /// Items which were not attempted, or were cancelled, because of `stop_on_error`.
+  pub skipped: Vec<K>,
+}
+
+/// Outcome of a single item of a bulk operation.
+#[derive(Debug)]
+pub struct BulkItem<K, T = ()> {
+  pub item: K,
+  pub result: Result<T>,
+}
+
+impl<K, T> BulkItem<K, T> {
+  /// The registry error codes reported for this item, if it failed with an API error.
+  pub fn error_codes(&self) -> Vec<&str> {
+    match &self.result {
+      Err(
+        Error::Api(e)
+        | Error::TagImmutable(e)
+        | Error::RetentionLocked(e)
+        | Error::Unauthorized { source: e, .. }
+        | Error::Denied { source: e, .. },
+      ) => e.errors().iter().flatten().map(|e| e.code()).collect(),
+      _ => Vec::new(),
+    }
+  }
+}
+
+impl<K, T> BulkReport<K, T> {
+  /// Whether every item was attempted and succeeded.
+  pub fn is_success(&self) -> bool {
+    self.skipped.is_empty() && self.items.iter().all(|i| i.result.is_ok())
+  }
+
+  /// Items which succeeded.
+  pub fn succeeded(&self) -> impl Iterator<Item = &BulkItem<K, T>> {
+    self.items.iter().filter(|i| i.result.is_ok())
+  }
+
+  /// Items which failed.
+  pub fn failed(&self) -> impl Iterator<Item = &BulkItem<K, T>> {
+    self.items.iter().filter(|i| i.result.is_err())
+  }
+}
+
+/// Run `op` on every item with the given options, collecting the outcomes into a report.
+///
+/// With `stop_on_error`, operations already in flight when an item fails are cancelled and
+/// reported as skipped; use a concurrency of 1 to make sure no operation is interrupted.
+pub async fn run<K, T, F, Fut>(items: Vec<K>, options: &BulkOptions, op: F) -> BulkReport<K, T>
+where
+  K: Clone,
+  F: Fn(K) -> Fut,
+  Fut: Future<Output = Result<T>>,
+{
+  let mut controller = Controller::new(options);
+  let mut remaining = items.clone().into_iter();
+  let mut pending = items.into_iter();
+  let mut started = 0;
+  let mut in_flight = FuturesOrdered::new();
+
+  let mut report = BulkReport {
+    items: Vec::new(),
+    skipped: Vec::new(),
+  };
+  loop {
+    while in_flight.len() < controller.limit() {
+      let item = match pending.next() {
+        Some(item) => item,
+        None => break,
+      };
+      let fut = attempt(&op, item.clone(), options.retry.as_ref());
+      let seq = started;
+      started += 1;
+      in_flight.push_back(async move {
+        let start = Instant::now();
+        let result = fut.await;
+        (item, result, seq, start.elapsed())
+      });
+    }
+    let (item, result, seq, elapsed) = match in_flight.next().await {
+      Some(outcome) => outcome,
+      None => break,
+    };
+    remaining.next();
+    controller.observe(seq, started, &result, elapsed);
+    let failed = result.is_err();
+    report.items.push(BulkItem { item, result });
+    if failed && options.stop_on_error {
+      report.skipped = remaining.collect();
+      break;
+    }
+  }
+  report
+}
+
+/// Run `op` on `item`, attempting it again with the backoff of `retry` while it fails because
+/// of congestion.
+async fn attempt<K, T, F, Fut>(op: &F, item: K, retry: Option<&RetryPolicy>) -> Result<T>
+where
+  K: Clone,
+  F: Fn(K) -> Fut,
+  Fut: Future<Output = Result<T>>,
+{
+  let mut attempt = 0;
+  loop {
+    let result = op(item.clone()).await;
+    let delay = match (&result, retry) {
+      (Err(e), Some(retry)) if is_congestion(e) => retry.attempt_delay(attempt),
+      _ => None,
+    };
+    let Some(delay) = delay else {
+      return result;
+    };
+    debug!("Attempting bulk item again in {:?}", delay);
+    sleep(delay).await;
+    attempt += 1;
+  }
+}
+
+/// Outcome of a task of a [`WorkQueue`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TaskStatus {
+  /// The task was not attempted yet.
+  Pending,
+  Done,
+  /// The task failed with `error` the last time it was attempted; it is attempted again by
+  /// the next run of the queue.
+  Failed {
+    error: String,
+  },
+}
+
+/// Progress of the tasks of a [`WorkQueue`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BulkProgress {
+  pub total: usize,
+  pub done: usize,
+  pub failed: usize,
+  pub pending: usize,
+}
+
+/// Entry of the journal of a [`WorkQueue`], one per line.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum QueueEntry<K> {
+  Task(K),
+  Done(usize),
+  Failed { task: usize, error: String },
+}
+
+/// A list of tasks journaled to a file, so that a bulk operation survives restarts.
+///
+/// The journal is a JSON Lines file recording the tasks of the queue and their outcome as they
+/// complete. Opening the same file again restores the queue: [`WorkQueue::run`] then only
+/// attempts the tasks which are pending or failed. A line truncated by a crash is ignored.
+///
+/// ```rust,no_run
+/// # use tokio;
+/// # #[tokio::main]
+/// # async fn main() {
+/// # async fn run() -> docker_registry::errors::Result<()> {
+/// use docker_registry::{
+///   bulk::{BulkOptions, WorkQueue},
+///   v2::Client,
+/// };
+///
+/// let client = Client::configure().registry("localhost:5000").build()?;
+/// let queue = WorkQueue::open("cleanup.jsonl")?;
+/// queue.push(vec!["sha256:...".to_string()])?;
+/// let report = queue
+///   .run(&BulkOptions::default(), |digest| {
+///     let client = &client;
+///     async move { client.delete_manifest("library/busybox", &digest).await }
+///   })
+///   .await?;
+/// println!("{:?}, {} failed", queue.progress(), report.failed().count());
+/// # Ok(())
+/// # };
+/// # run().await.unwrap();
+/// # }
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct WorkQueue<K> {
+  path: PathBuf,
+  tasks: Mutex<Vec<(K, TaskStatus)>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<K> WorkQueue<K>
+where
+  K: Clone + PartialEq + Serialize + DeserializeOwned,
+{
+  /// Open the queue journaled at `path`, restoring its tasks if the file exists.
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+    let path = path.as_ref().to_path_buf();
+    let content = match fs::read_to_string(&path) {
+      Ok(content) => content,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+      Err(e) => return Err(e.into()),
+    };
+    let mut tasks = Vec::new();
+    let mut lines = content.lines().filter(|l| !l.trim().is_empty()).peekable();
+    while let Some(line) = lines.next() {
+      let entry = match serde_json::from_str(line) {
+        Ok(entry) => entry,
+        Err(_) if lines.peek().is_none() => {
+          warn!("Ignoring the truncated last entry of {}", path.display());
+          break;
+        }
+        Err(e) => return Err(e.into()),
+      };
+      match entry {
+        QueueEntry::Task(task) => tasks.push((task, TaskStatus::Pending)),
+        QueueEntry::Done(i) => set_status(&mut tasks, i, TaskStatus::Done),
+        QueueEntry::Failed { task, error } => set_status(&mut tasks, task, TaskStatus::Failed { error }),
+      }
+    }
+    Ok(Self {
+      path,
+      tasks: Mutex::new(tasks),
+    })
+  }
+
+  /// Add tasks to the queue, ignoring those already queued.
+  pub fn push<I: IntoIterator<Item = K>>(&self, tasks: I) -> Result<()> {
+    let mut queued = self.tasks.lock().unwrap();
+    let mut entries = Vec::new();
+    for task in tasks {
+      if queued.iter().any(|(t, _)| *t == task) {
+        continue;
+      }
+      entries.push(QueueEntry::Task(task.clone()));
+      queued.push((task, TaskStatus::Pending));
+    }
+    self.append(&entries)
+  }
+
+  /// The tasks of the queue, in the order they were pushed, with their status.
+  pub fn tasks(&self) -> Vec<(K, TaskStatus)> {
+    self.tasks.lock().unwrap().clone()
+  }
+
+  /// The progress of the queue, which may be polled while it runs.
+  pub fn progress(&self) -> BulkProgress {
+    let tasks = self.tasks.lock().unwrap();
+    let mut progress = BulkProgress {
+      total: tasks.len(),
+      ..Default::default()
+    };
+    for (_, status) in tasks.iter() {
+      match status {
+        TaskStatus::Pending => progress.pending += 1,
+        TaskStatus::Done => progress.done += 1,
+        TaskStatus::Failed { .. } => progress.failed += 1,
+      }
+    }
+    progress
+  }
+
+  /// Whether every task of the queue is done.
+  pub fn is_complete(&self) -> bool {
+    self.tasks.lock().unwrap().iter().all(|(_, s)| *s == TaskStatus::Done)
+  }
+
+  /// Run `op` on the tasks which are pending or failed, journaling their outcome as they
+  /// complete, like [`run`] does.
+  ///
+  /// The report only holds the tasks attempted by this run. Fails if the outcome of a task
+  /// cannot be journaled; such tasks are attempted again by the next run.
+  pub async fn run<T, F, Fut>(&self, options: &BulkOptions, op: F) -> Result<BulkReport<K, T>>
+  where
+    F: Fn(K) -> Fut,
+    Fut: Future<Output = Result<T>>,
+  {
+    let todo: Vec<(usize, K)> = self
+      .tasks
+      .lock()
+      .unwrap()
+      .iter()
+      .enumerate()
+      .filter(|(_, (_, status))| *status != TaskStatus::Done)
+      .map(|(i, (task, _))| (i, task.clone()))
+      .collect();
+    let op = &op;
+    let journal_error = Mutex::new(None);
+    let journal_error = &journal_error;
+    let report = run(todo, options, |(i, task)| async move {
+      let result = op(task).await;
+      let (entry, status) = match &result {
+        Ok(_) => (QueueEntry::Done(i), TaskStatus::Done),
+        Err(e) => (
+          QueueEntry::Failed {
+            task: i,
+            error: e.to_string(),
+          },
+          TaskStatus::Failed { error: e.to_string() },
+        ),
+      };
+      match self.append(&[entry]) {
+        Ok(()) => set_status(&mut self.tasks.lock().unwrap(), i, status),
+        Err(e) => {
+          warn!("Cannot journal task {} to {}: {}", i, self.path.display(), e);
+          journal_error.lock().unwrap().get_or_insert(e);
+        }
+      }
+      result
+    })
+    .await;
+    if let Some(e) = journal_error.lock().unwrap().take() {
+      return Err(e);
+    }
+    Ok(BulkReport {
+      items: report
+        .items
+        .into_iter()
+        .map(|item| BulkItem {
+          item: item.item.1,
+          result: item.result,
+        })
+        .collect(),
+      skipped: report.skipped.into_iter().map(|(_, task)| task).collect(),
+    })
+  }
+
+  /// Run `op` once on all the tasks which are pending or failed, for operations handling their
+  /// tasks together, and journal the outcome of every task of the report it returns.
+  ///
+  /// Skipped tasks remain pending.
+  pub(crate) async fn run_batch<T, F, Fut>(&self, op: F) -> Result<BulkReport<K, T>>
+  where
+    F: FnOnce(Vec<K>) -> Fut,
+    Fut: Future<Output = Result<BulkReport<K, T>>>,
+  {
+    let todo: Vec<K> = self
+      .tasks
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|(_, status)| *status != TaskStatus::Done)
+      .map(|(task, _)| task.clone())
+      .collect();
+    let report = op(todo).await?;
+
+    let mut tasks = self.tasks.lock().unwrap();
+    let mut entries = Vec::new();
+    let mut statuses = Vec::new();
+    for item in &report.items {
+      let Some(i) = tasks.iter().position(|(task, _)| *task == item.item) else {
+        continue;
+      };
+      let (entry, status) = match &item.result {
+        Ok(_) => (QueueEntry::Done(i), TaskStatus::Done),
+        Err(e) => (
+          QueueEntry::Failed {
+            task: i,
+            error: e.to_string(),
+          },
+          TaskStatus::Failed { error: e.to_string() },
+        ),
+      };
+      entries.push(entry);
+      statuses.push((i, status));
+    }
+    self.append(&entries)?;
+    for (i, status) in statuses {
+      set_status(&mut tasks, i, status);
+    }
+    drop(tasks);
+    Ok(report)
+  }
+
+  fn append(&self, entries: &[QueueEntry<K>]) -> Result<()> {
+    if entries.is_empty() {
+      return Ok(());
+    }
+    let mut lines = Vec::new();
+    for entry in entries {
+      serde_json::to_writer(&mut lines, entry)?;
+      lines.push(b'\n');
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+    file.write_all(&lines)?;
+    File::sync_data(&file)?;
+    Ok(())
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn set_status<K>(tasks: &mut [(K, TaskStatus)], i: usize, status: TaskStatus) {
+  if let Some((_, s)) = tasks.get_mut(i) {
+    *s = status;
+  }
+}
+
+impl Client {
+  /// Delete many manifests of a repository, reporting the outcome of every deletion.
+  pub async fn delete_manifests(
+    &self,
+    name: &str,
+    references: Vec<String>,
+    options: &BulkOptions,
+  ) -> BulkReport<String> {
+    run(references, options, |reference| async move {
+      self.delete_manifest(name, &reference).await
+    })
+    .await
+  }
+
+  /// Delete tags of a repository, reporting the outcome of every tag.
+  ///
+  /// Tags are resolved to the digests of the manifests they point to, and every manifest is
+  /// deleted once, according to `options.shared_digests`. Successful items hold the digest of
+  /// the deleted manifest; tags left alone because of the policy fail with `Error::SharedDigest`.
+  ///
+  /// Only fails if the client is read-only, or if `SharedDigestPolicy::ProtectOtherTags` is used
+  /// and the other tags of the repository cannot be listed or resolved.
+  pub async fn delete_tags(
+    &self,
+    name: &str,
+    tags: Vec<String>,
+    options: &DeleteTagsOptions,
+  ) -> Result<BulkReport<String, String>> {
+    self.ensure_writable()?;
+    let client = &self.with_default_retry_policy(&options.retry_policy);
+
+    let mut order = HashMap::new();
+    let tags: Vec<String> = tags
+      .into_iter()
+      .filter(|tag| match order.contains_key(tag) {
+        true => false,
+        false => {
+          order.insert(tag.clone(), order.len());
+          true
+        }
+      })
+      .collect();
+
+    // Protected tags are reported before anything is deleted, with `Config::check_tag_mutability`.
+    let resolved = run(tags, &options.bulk, |tag| async move {
+      client.check_tag_mutability(name, &tag, TagOperation::Delete).await?;
+      client.resolve_digest(name, &tag).await
+    })
+    .await;
+
+    let mut report = BulkReport {
+      items: Vec::new(),
+      skipped: resolved.skipped,
+    };
+    let mut groups: Vec<(String, Vec<String>)> = Vec::new();
+    for item in resolved.items {
+      match item.result {
+        Ok(digest) => match groups.iter_mut().find(|(d, _)| *d == digest) {
+          Some((_, tags)) => tags.push(item.item),
+          None => groups.push((digest, vec![item.item])),
+        },
+        Err(e) => report.items.push(BulkItem {
+          item: item.item,
+          result: Err(e),
+        }),
+      }
+    }
+    if options.bulk.stop_on_error && !report.items.is_empty() {
+      report.skipped.extend(groups.into_iter().flat_map(|(_, tags)| tags));
+      sort_by_input(&mut report, &order);
+      return Ok(report);
+    }
+
+    let mut other_tags: HashMap<String, Vec<String>> = HashMap::new();
+    if options.shared_digests == SharedDigestPolicy::ProtectOtherTags {
+      let others: Vec<String> = client
+        .get_tags(name, None)
+        .try_filter(|tag| futures::future::ready(!order.contains_key(tag)))
+        .try_collect()
+        .await?;
+      let resolve_options = BulkOptions {
+        stop_on_error: true,
+        ..options.bulk.clone()
+      };
+      let resolved = run(others, &resolve_options, |tag| async move {
+        client.resolve_digest(name, &tag).await
+      })
+      .await;
+      for item in resolved.items {
+        other_tags.entry(item.result?).or_default().push(item.item);
+      }
+    }
+
+    let mut digests = Vec::new();
+    let mut tags_of = HashMap::new();
+    for (digest, tags) in groups {
+      let kept_for = match options.shared_digests {
+        SharedDigestPolicy::Skip if tags.len() > 1 => Some(tags.clone()),
+        SharedDigestPolicy::ProtectOtherTags => other_tags.remove(&digest),
+        _ => None,
+      };
+      match kept_for {
+        Some(others) => {
+          for tag in tags {
+            let others = others.iter().filter(|t| **t != tag).cloned().collect();
+            report.items.push(BulkItem {
+              item: tag,
+              result: Err(Error::SharedDigest {
+                digest: digest.clone(),
+                tags: others,
+              }),
+            });
+          }
+        }
+        None => {
+          digests.push(digest.clone());
+          tags_of.insert(digest, tags);
+        }
+      }
+    }
+
+    let deleted = run(digests, &options.bulk, |digest| async move {
+      client.delete_manifest(name, &digest).await
+    })
+    .await;
+    for item in deleted.items {
+      let mut tags = tags_of.remove(&item.item).unwrap_or_default().into_iter();
+      match item.result {
+        Ok(()) => report.items.extend(tags.map(|tag| BulkItem {
+          item: tag,
+          result: Ok(item.item.clone()),
+        })),
+        Err(e) => {
+          let first = tags.next().unwrap_or_default();
+          report.items.extend(tags.map(|tag| BulkItem {
+            item: tag,
+            result: Err(Error::SharedDeletionFailed {
+              digest: item.item.clone(),
+              tag: first.clone(),
+            }),
+          }));
+          report.items.push(BulkItem {
+            item: first,
+            result: Err(e),
+          });
+        }
+      }
+    }
+    for digest in deleted.skipped {
+      report.skipped.extend(tags_of.remove(&digest).unwrap_or_default());
+    }
+
+    sort_by_input(&mut report, &order);
+    Ok(report)
+  }
+
+  /// Delete the tags queued in `queue`, like [`Client::delete_tags`] does, journaling the
+  /// outcome of every tag.
+  ///
+  /// Tags already deleted by a previous run of the queue are not resolved or deleted again.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub async fn delete_queued_tags(
+    &self,
+    name: &str,
+    queue: &WorkQueue<String>,
+    options: &DeleteTagsOptions,
+  ) -> Result<BulkReport<String, String>> {
+    self.ensure_writable()?;
+    queue.run_batch(|tags| self.delete_tags(name, tags, options)).await
+  }
+
+  /// Digest of the manifest `reference` points to.
+  pub(crate) async fn resolve_digest(&self, name: &str, reference: &str) -> Result<String> {
+    if let Some(digest) = self.get_manifestref(name, reference).await? {
+      return Ok(digest);
+    }
+    let (_, _, digest) = self.get_raw_manifest(name, reference, None).await?;
+    digest.ok_or(Error::MissingHeader("Docker-Content-Digest"))
+  }
+}
+
+/// Restore the input order of the tags of a report.
+fn sort_by_input<T>(report: &mut BulkReport<String, T>, order: &HashMap<String, usize>) {
+  report.items.sort_by_key(|i| order.get(&i.item).copied());
+  report.skipped.sort_by_key(|t| order.get(t).copied());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn fail_odd(i: u32) -> Result<u32> {
+    match i % 2 {
+      0 => Ok(i),
+      _ => Err(Error::NoCredentials),
+    }
+  }
+
+  #[tokio::test]
+  async fn reports_every_item() {
+    let report = run(
+      vec![0, 1, 2, 3],
+      &BulkOptions::default(),
+      |i| async move { fail_odd(i) },
+    )
+    .await;
+    assert_eq!(report.items.len(), 4);
+    assert_eq!(report.succeeded().map(|i| i.item).collect::<Vec<_>>(), vec![0, 2]);
+    assert_eq!(report.failed().map(|i| i.item).collect::<Vec<_>>(), vec![1, 3]);
+    assert!(!report.is_success());
+  }
+
+  #[tokio::test]
+  async fn stops_on_first_error() {
+    let options = BulkOptions {
+      concurrency: 1,
+      stop_on_error: true,
+      ..Default::default()
+    };
+    let report = run(vec![0, 1, 2, 3], &options, |i| async move { fail_odd(i) }).await;
+    assert_eq!(report.items.len(), 2);
+    assert_eq!(report.skipped, vec![2, 3]);
+  }
+
+  #[tokio::test]
+  async fn raises_adaptive_concurrency() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let options = BulkOptions {
+      concurrency: 1,
+      adaptive: Some(AdaptiveConcurrency::default().max(3)),
+      ..Default::default()
+    };
+    let report = run((0..20).collect(), &options, |i: u32| {
+      let (running, peak) = (&running, &peak);
+      async move {
+        peak.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+        tokio::task::yield_now().await;
+        running.fetch_sub(1, Ordering::SeqCst);
+        Ok(i)
+      }
+    })
+    .await;
+    assert!(report.is_success());
+    assert_eq!(peak.load(Ordering::SeqCst), 3);
+  }
+
+  #[test]
+  fn halves_adaptive_concurrency_once_per_round() {
+    let options = BulkOptions {
+      concurrency: 8,
+      adaptive: Some(AdaptiveConcurrency::default().min(2)),
+      ..Default::default()
+    };
+    let mut controller = Controller::new(&options);
+    let throttled: Result<()> = Err(Error::Server {
+      status: StatusCode::SERVICE_UNAVAILABLE,
+    });
+
+    controller.observe(0, 8, &throttled, Duration::ZERO);
+    assert_eq!(controller.limit(), 4);
+    // Started before the decrease.
+    controller.observe(7, 9, &throttled, Duration::ZERO);
+    assert_eq!(controller.limit(), 4);
+    controller.observe(8, 9, &throttled, Duration::ZERO);
+    assert_eq!(controller.limit(), 2);
+    controller.observe(9, 10, &throttled, Duration::ZERO);
+    assert_eq!(controller.limit(), 2);
+
+    for seq in 10..14 {
+      controller.observe(seq, 14, &Ok(()), Duration::ZERO);
+    }
+    assert_eq!(controller.limit(), 3);
+    controller.observe(14, 15, &Err::<(), _>(Error::NoCredentials), Duration::ZERO);
+    assert_eq!(controller.limit(), 3);
+  }
+
+  #[tokio::test]
+  async fn attempts_congested_items_again() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let calls = AtomicUsize::new(0);
+    let options = BulkOptions {
+      retry: Some(RetryPolicy::default().initial_backoff(Duration::ZERO).max_retries(2)),
+      ..Default::default()
+    };
+    let report: BulkReport<u32> = run(vec![0, 1], &options, |i: u32| {
+      let calls = &calls;
+      async move {
+        calls.fetch_add(1, Ordering::SeqCst);
+        match i {
+          0 => Err(Error::Server {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+          }),
+          _ => Err(Error::NoCredentials),
+        }
+      }
+    })
+    .await;
+    assert_eq!(report.failed().count(), 2);
+    // The congested item is attempted three times, the other one once.
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+  }
+
+  #[tokio::test]
+  async fn resumes_work_queue() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("queue.jsonl");
+
+    let queue = WorkQueue::open(&path).unwrap();
+    queue.push(vec![0u32, 1, 2, 3]).unwrap();
+    let report = queue
+      .run(&BulkOptions::default(), |i| async move { fail_odd(i) })
+      .await
+      .unwrap();
+    assert_eq!(report.items.len(), 4);
+    assert_eq!(
+      queue.progress(),
+      BulkProgress {
+        total: 4,
+        done: 2,
+        failed: 2,
+        pending: 0
+      }
+    );
+
+    // A crash while journaling leaves a truncated line behind.
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"{\"done\":").unwrap();
+
+    let queue = WorkQueue::<u32>::open(&path).unwrap();
+    queue.push(vec![2, 4]).unwrap();
+    assert_eq!(queue.progress().pending, 1);
+    let report = queue
+      .run(&BulkOptions::default(), |i| async move { Ok(i) })
+      .await
+      .unwrap();
+    assert_eq!(report.items.iter().map(|i| i.item).collect::<Vec<_>>(), vec![1, 3, 4]);
+    assert!(queue.is_complete());
+    assert_eq!(queue.tasks()[1], (1, TaskStatus::Done));
+  }
+}
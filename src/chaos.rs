@@ -0,0 +1,277 @@
+//! Fault injection, to test how applications cope with misbehaving registries.
+//!
+//! A [`Chaos`] layer set with `Config::chaos` makes the client inject delays, dropped
+//! connections, truncated bodies and `5xx`/`429` responses at random, with probabilities set
+//! per [`EndpointClass`]. Faults are injected on every attempt of a request, before retries, so
+//! they exercise the retry policy and the resume logic of the application alike.
+//!
+//! This module is only available with the `test-support` feature.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use std::time::Duration;
+//!
+//! use docker_registry::{
+//!   chaos::{Chaos, EndpointClass, Faults},
+//!   v2::{Client, RetryPolicy},
+//! };
+//!
+//! let chaos = Chaos::new(42)
+//!   .faults(Faults::default().delay(0.1, Duration::from_millis(500)))
+//!   .endpoint(
+//!     EndpointClass::Blob,
+//!     Faults::default().truncate_body(0.2).server_error(0.1),
+//!   );
+//! let client = Client::configure()
+//!   .registry("localhost:5000")
+//!   .retry_policy(Some(RetryPolicy::default()))
+//!   .chaos(chaos)
+//!   .build()?;
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::{collections::HashMap, io, sync::Mutex, time::Duration};
+
+use bytes::Bytes;
+use http::response::Builder;
+use log::debug;
+use reqwest::{header, Method, Request, Response, ResponseBuilderExt, StatusCode, Url};
+
+pub use crate::v2::EndpointClass;
+
+/// Probabilities of the faults injected into requests, between 0 and 1.
+///
+/// Every fault is drawn independently, in the order of the methods below: a delayed request may
+/// still be dropped, and a request is only sent to the registry if no error response was drawn.
+#[derive(Clone, Debug, Default)]
+pub struct Faults {
+  delay: f64,
+  delay_duration: Duration,
+  drop_connection: f64,
+  server_error: f64,
+  too_many_requests: f64,
+  truncate_body: f64,
+}
+
+impl Faults {
+  /// Delay requests by `duration` before sending them.
+  pub fn delay(mut self, probability: f64, duration: Duration) -> Self {
+    self.delay = probability;
+    self.delay_duration = duration;
+    self
+  }
+
+  /// Fail requests with a connection error, without sending them.
+  pub fn drop_connection(mut self, probability: f64) -> Self {
+    self.drop_connection = probability;
+    self
+  }
+
+  /// Answer requests with a `500`, `502`, `503` or `504` response, without sending them.
+  pub fn server_error(mut self, probability: f64) -> Self {
+    self.server_error = probability;
+    self
+  }
+
+  /// Answer requests with a `429 Too Many Requests` response asking to retry right away,
+  /// without sending them.
+  pub fn too_many_requests(mut self, probability: f64) -> Self {
+    self.too_many_requests = probability;
+    self
+  }
+
+  /// Cut successful response bodies in half, failing reads past the cut like a connection
+  /// closed by the registry.
+  pub fn truncate_body(mut self, probability: f64) -> Self {
+    self.truncate_body = probability;
+    self
+  }
+}
+
+/// Fault injection layer, set on a client with `Config::chaos`.
+#[derive(Debug)]
+pub struct Chaos {
+  faults: Faults,
+  endpoints: HashMap<EndpointClass, Faults>,
+  state: Mutex<u64>,
+}
+
+impl Chaos {
+  /// Create a layer injecting no faults, drawing them from a generator seeded with `seed`.
+  ///
+  /// The same seed draws the same faults for the same sequence of requests.
+  pub fn new(seed: u64) -> Self {
+    Self {
+      faults: Faults::default(),
+      endpoints: HashMap::new(),
+      state: Mutex::new(seed),
+    }
+  }
+
+  /// Inject `faults` into requests to endpoints without faults of their own.
+  pub fn faults(mut self, faults: Faults) -> Self {
+    self.faults = faults;
+    self
+  }
+
+  /// Inject `faults` into requests to endpoints of the given class.
+  pub fn endpoint(mut self, class: EndpointClass, faults: Faults) -> Self {
+    self.endpoints.insert(class, faults);
+    self
+  }
+
+  /// Send `request` with `client`, injecting faults.
+  pub(crate) async fn execute(&self, client: &reqwest::Client, mut request: Request) -> reqwest::Result<Response> {
+    let class = EndpointClass::of(request.url());
+    let faults = self.endpoints.get(&class).unwrap_or(&self.faults);
+
+    if self.draw(faults.delay) {
+      debug!(
+        "chaos: delaying {} {} by {:?}",
+        request.method(),
+        request.url(),
+        faults.delay_duration
+      );
+      tokio::time::sleep(faults.delay_duration).await;
+    }
+    if self.draw(faults.drop_connection) {
+      debug!("chaos: dropping {} {}", request.method(), request.url());
+      // Connecting to port 0 fails right away, yielding a genuine connection error.
+      let url = request.url().clone();
+      *request.url_mut() = Url::parse("http://127.0.0.1:0/").expect("static URL is valid");
+      return client.execute(request).await.map_err(|e| e.with_url(url));
+    }
+    if self.draw(faults.server_error) {
+      let status = [
+        StatusCode::INTERNAL_SERVER_ERROR,
+        StatusCode::BAD_GATEWAY,
+        StatusCode::SERVICE_UNAVAILABLE,
+        StatusCode::GATEWAY_TIMEOUT,
+      ][(self.next() % 4) as usize];
+      debug!(
+        "chaos: answering {} {} with {}",
+        request.method(),
+        request.url(),
+        status
+      );
+      return Ok(error_response(&request, status, "UNAVAILABLE", Builder::new()));
+    }
+    if self.draw(faults.too_many_requests) {
+      debug!("chaos: throttling {} {}", request.method(), request.url());
+      let builder = Builder::new().header(header::RETRY_AFTER, "0");
+      return Ok(error_response(
+        &request,
+        StatusCode::TOO_MANY_REQUESTS,
+        "TOOMANYREQUESTS",
+        builder,
+      ));
+    }
+
+    let response = client.execute(request).await?;
+    if !response.status().is_success() || !self.draw(faults.truncate_body) {
+      return Ok(response);
+    }
+    debug!("chaos: truncating body of {}", response.url());
+    Ok(truncate(response).await)
+  }
+
+  /// Whether a fault of the given probability occurs.
+  fn draw(&self, probability: f64) -> bool {
+    // The 53 high bits make a uniform float in [0, 1).
+    let sample = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+    probability > 0.0 && sample < probability
+  }
+
+  /// Next output of a SplitMix64 generator.
+  fn next(&self) -> u64 {
+    let mut state = self.state.lock().unwrap();
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+  }
+}
+
+/// A response with the given status and a registry error body with the given code.
+fn error_response(request: &Request, status: StatusCode, code: &str, builder: Builder) -> Response {
+  let body = match *request.method() {
+    Method::HEAD => String::new(),
+    _ => format!(r#"{{"errors":[{{"code":"{code}","message":"injected fault"}}]}}"#),
+  };
+  let response = builder
+    .status(status)
+    .header(header::CONTENT_TYPE, "application/json")
+    .url(request.url().clone())
+    .body(body)
+    .expect("static response parts are valid");
+  Response::from(response)
+}
+
+/// The response with a body cut in half, whose reads fail past the cut.
+async fn truncate(response: Response) -> Response {
+  let mut builder = Builder::new()
+    .status(response.status())
+    .version(response.version())
+    .url(response.url().clone());
+  if let Some(headers) = builder.headers_mut() {
+    *headers = response.headers().clone();
+  }
+  let chunks = match response.bytes().await {
+    Ok(body) => vec![
+      Ok(body.slice(..body.len() / 2)),
+      Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "connection closed by injected fault",
+      )),
+    ],
+    Err(e) => vec![Ok(Bytes::new()), Err(io::Error::new(io::ErrorKind::Other, e))],
+  };
+  let body = reqwest::Body::wrap_stream(futures::stream::iter(chunks));
+  Response::from(builder.body(body).expect("parts of a valid response are valid"))
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case("/v2/" => EndpointClass::Base; "base")]
+  #[test_case("/v2/_catalog" => EndpointClass::Catalog; "catalog")]
+  #[test_case("/v2/library/busybox/manifests/latest" => EndpointClass::Manifest; "manifest")]
+  #[test_case("/v2/library/busybox/blobs/sha256:abcd" => EndpointClass::Blob; "blob")]
+  #[test_case("/v2/library/busybox/blobs/uploads/" => EndpointClass::Upload; "upload")]
+  #[test_case("/v2/library/busybox/blobs/uploads/1234" => EndpointClass::Upload; "upload session")]
+  #[test_case("/v2/library/busybox/tags/list" => EndpointClass::Tags; "tags")]
+  #[test_case("/v2/library/busybox/referrers/sha256:abcd" => EndpointClass::Referrers; "referrers")]
+  #[test_case("/token" => EndpointClass::Other; "token")]
+  fn classifies_endpoints(path: &str) -> EndpointClass {
+    EndpointClass::of(&Url::parse("https://registry.example.com").unwrap().join(path).unwrap())
+  }
+
+  #[test]
+  fn draws_are_seeded() {
+    let draws = |seed| {
+      let chaos = Chaos::new(seed);
+      (0..1000).map(|_| chaos.draw(0.25)).collect::<Vec<_>>()
+    };
+    assert_eq!(draws(7), draws(7));
+    assert_ne!(draws(7), draws(8));
+
+    let hits = draws(7).into_iter().filter(|d| *d).count();
+    assert!((200..300).contains(&hits), "{hits} hits");
+    assert!(!Chaos::new(7).draw(0.0));
+  }
+}
@@ -0,0 +1,597 @@
+//! Conformance checks of registries against the OCI distribution specification.
+//!
+//! [`Conformance`] runs the scenarios of the [conformance suite][suite] of the distribution
+//! specification against the registry of a client: pull, push, content discovery and content
+//! management. It pushes a small image, a second tag of it and an artifact referring to it to the
+//! repository it is given, and deletes them again in the content management checks. The
+//! [`Report`] returned lists the checks which passed, failed or were skipped, and prints as one
+//! line per check.
+//!
+//! This module is only available with the `test-support` feature.
+//!
+//! [suite]: https://github.com/opencontainers/distribution-spec/tree/main/conformance
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   conformance::{Category, Conformance},
+//!   v2::Client,
+//! };
+//!
+//! let client = Client::configure().registry("localhost:5000").build()?;
+//! let report = Conformance::new(client, "conformance/test")
+//!   .categories(&[Category::Pull, Category::Push])
+//!   .run()
+//!   .await?;
+//! println!("{}", report);
+//! assert!(report.is_success());
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::{collections::BTreeSet, fmt};
+
+use futures::{StreamExt, TryStreamExt};
+use log::debug;
+use reqwest::{header::HeaderMap, Method, StatusCode};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+  build::CONFIG_MEDIA_TYPE,
+  errors::{Error, Result},
+  layer::Compression,
+  mediatypes::MediaTypes,
+  v2::{sha256_digest, Client, EMPTY_CONFIG_MEDIA_TYPE},
+};
+
+/// Artifact type of the artifact pushed to check the referrers API.
+const ARTIFACT_TYPE: &str = "application/vnd.docker-registry.conformance.test";
+
+/// Scenario of the conformance suite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+  /// Pulling manifests and blobs, which every registry must support.
+  Pull,
+  /// Pushing blobs, monolithically and in chunks, and manifests.
+  Push,
+  /// Listing tags and referrers.
+  ContentDiscovery,
+  /// Deleting tags, manifests and blobs.
+  ContentManagement,
+}
+
+impl Category {
+  /// Every scenario, in the order they run.
+  pub const ALL: [Category; 4] = [
+    Category::Push,
+    Category::Pull,
+    Category::ContentDiscovery,
+    Category::ContentManagement,
+  ];
+}
+
+impl fmt::Display for Category {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Category::Pull => "pull",
+      Category::Push => "push",
+      Category::ContentDiscovery => "content discovery",
+      Category::ContentManagement => "content management",
+    })
+  }
+}
+
+/// Outcome of a check.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "reason", rename_all = "lowercase")]
+pub enum Outcome {
+  /// The registry behaves as the specification requires.
+  Passed,
+  /// The registry does not behave as the specification requires, for the given reason.
+  Failed(String),
+  /// The check did not run, e.g. because the registry does not implement an optional feature.
+  Skipped(String),
+}
+
+/// A check of the conformance suite and its outcome.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Check {
+  pub category: Category,
+  pub name: String,
+  pub outcome: Outcome,
+}
+
+/// Report of a conformance run, see [`Conformance::run`].
+///
+/// It serializes to JSON for CI systems, and displays as one line per check followed by a
+/// summary.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Report {
+  /// Host and port of the registry.
+  pub registry: String,
+  /// Repository the test content was pushed to.
+  pub repository: String,
+  /// Checks, in the order they ran.
+  pub checks: Vec<Check>,
+}
+
+impl Report {
+  /// Number of checks which passed.
+  pub fn passed(&self) -> usize {
+    self.count(|o| matches!(o, Outcome::Passed))
+  }
+
+  /// Number of checks which failed.
+  pub fn failed(&self) -> usize {
+    self.count(|o| matches!(o, Outcome::Failed(_)))
+  }
+
+  /// Number of checks which were skipped.
+  pub fn skipped(&self) -> usize {
+    self.count(|o| matches!(o, Outcome::Skipped(_)))
+  }
+
+  /// Whether no check failed.
+  pub fn is_success(&self) -> bool {
+    self.failed() == 0
+  }
+
+  /// The checks which failed.
+  pub fn failures(&self) -> impl Iterator<Item = &Check> {
+    self.checks.iter().filter(|c| matches!(c.outcome, Outcome::Failed(_)))
+  }
+
+  fn count(&self, f: impl Fn(&Outcome) -> bool) -> usize {
+    self.checks.iter().filter(|c| f(&c.outcome)).count()
+  }
+
+  fn record(&mut self, category: Category, name: &str, outcome: Outcome) {
+    debug!("conformance: {} / {}: {:?}", category, name, outcome);
+    self.checks.push(Check {
+      category,
+      name: name.to_string(),
+      outcome,
+    });
+  }
+}
+
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "{}/{}", self.registry, self.repository)?;
+    for check in &self.checks {
+      match &check.outcome {
+        Outcome::Passed => writeln!(f, "PASS {}: {}", check.category, check.name)?,
+        Outcome::Failed(reason) => writeln!(f, "FAIL {}: {}: {}", check.category, check.name, reason)?,
+        Outcome::Skipped(reason) => writeln!(f, "SKIP {}: {}: {}", check.category, check.name, reason)?,
+      }
+    }
+    write!(
+      f,
+      "{} passed, {} failed, {} skipped",
+      self.passed(),
+      self.failed(),
+      self.skipped()
+    )
+  }
+}
+
+/// Runner of the conformance suite against the registry of a client.
+///
+/// Every scenario pushes the test content first: the push checks are only reported when
+/// [`Category::Push`] is selected, and the other scenarios fail with a single `setup` check if
+/// the content could not be pushed.
+#[derive(Debug)]
+pub struct Conformance {
+  client: Client,
+  name: String,
+  tag: String,
+  chunk_size: usize,
+  categories: BTreeSet<Category>,
+}
+
+impl Conformance {
+  /// Create a runner of every scenario, pushing its test content to repository `name`.
+  ///
+  /// The client is authorized for the repository if the registry requires it: its credentials
+  /// must allow pushing to and deleting from it.
+  pub fn new(client: Client, name: &str) -> Self {
+    Self {
+      client,
+      name: name.to_string(),
+      tag: "conformance".to_string(),
+      chunk_size: 1024,
+      categories: Category::ALL.into_iter().collect(),
+    }
+  }
+
+  /// Run only the given scenarios.
+  pub fn categories(mut self, categories: &[Category]) -> Self {
+    self.categories = categories.iter().copied().collect();
+    self
+  }
+
+  /// Push the test image as `tag`, and its second tag as `<tag>-2`, defaults to `conformance`.
+  pub fn tag(mut self, tag: &str) -> Self {
+    self.tag = tag.to_string();
+    self
+  }
+
+  /// Push blobs in chunks of `chunk_size` bytes in the chunked upload check, defaults to 1 KiB.
+  ///
+  /// Registries storing uploads in object storage may require larger chunks.
+  pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+    self.chunk_size = chunk_size.max(1);
+    self
+  }
+
+  /// Run the selected scenarios.
+  ///
+  /// Only errors authorizing the client fail the run: every other error is reported as the
+  /// failure of the check it occurred in.
+  pub async fn run(&self) -> Result<Report> {
+    let client = match self.client.auth_challenge().await? {
+      Some(_) => {
+        let scope = format!("repository:{}:pull,push,delete", self.name);
+        self.client.clone().authenticate(&[&scope]).await?
+      }
+      None => self.client.clone(),
+    };
+    let run = Run {
+      client,
+      name: &self.name,
+      fixture: Fixture::new(&self.tag, self.chunk_size),
+    };
+    let mut report = Report {
+      registry: self.client.registry_host(),
+      repository: self.name.clone(),
+      checks: Vec::new(),
+    };
+
+    let mut pushed = Report::default();
+    run.push(&mut pushed).await;
+    if self.categories.contains(&Category::Push) {
+      report.checks.extend(pushed.checks.iter().cloned());
+    }
+    if let Some(failure) = pushed.failures().next() {
+      let reason = format!("the test content could not be pushed: {} failed", failure.name);
+      for category in self.categories.iter().filter(|c| **c != Category::Push) {
+        report.record(*category, "setup", Outcome::Failed(reason.clone()));
+      }
+      return Ok(report);
+    }
+
+    for category in &self.categories {
+      match category {
+        Category::Push => {}
+        Category::Pull => run.pull(&mut report).await,
+        Category::ContentDiscovery => run.discover(&mut report).await,
+        Category::ContentManagement => run.manage(&mut report).await,
+      }
+    }
+    Ok(report)
+  }
+}
+
+/// Content pushed to the registry by the checks.
+#[derive(Debug)]
+struct Fixture {
+  tag: String,
+  chunk_size: usize,
+  config: Vec<u8>,
+  layer: Vec<u8>,
+  manifest: Vec<u8>,
+  artifact: Vec<u8>,
+}
+
+impl Fixture {
+  fn new(tag: &str, chunk_size: usize) -> Self {
+    let layer: Vec<u8> = (0..4096u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+    let config = serde_json::to_vec(&json!({
+      "architecture": "amd64",
+      "os": "linux",
+      "rootfs": {"type": "layers", "diff_ids": [sha256_digest(&layer)]},
+    }))
+    .expect("static JSON serializes");
+    let manifest = serde_json::to_vec(&json!({
+      "schemaVersion": 2,
+      "mediaType": MediaTypes::OciImageManifest.to_string(),
+      "config": {
+        "mediaType": CONFIG_MEDIA_TYPE,
+        "digest": sha256_digest(&config),
+        "size": config.len(),
+      },
+      "layers": [{
+        "mediaType": Compression::None.layer_media_type(true),
+        "digest": sha256_digest(&layer),
+        "size": layer.len(),
+      }],
+    }))
+    .expect("static JSON serializes");
+    let artifact = serde_json::to_vec(&json!({
+      "schemaVersion": 2,
+      "mediaType": MediaTypes::OciImageManifest.to_string(),
+      "artifactType": ARTIFACT_TYPE,
+      "config": {
+        "mediaType": EMPTY_CONFIG_MEDIA_TYPE,
+        "digest": sha256_digest(b"{}"),
+        "size": 2,
+      },
+      "layers": [{
+        "mediaType": EMPTY_CONFIG_MEDIA_TYPE,
+        "digest": sha256_digest(b"{}"),
+        "size": 2,
+      }],
+      "subject": {
+        "mediaType": MediaTypes::OciImageManifest.to_string(),
+        "digest": sha256_digest(&manifest),
+        "size": manifest.len(),
+      },
+    }))
+    .expect("static JSON serializes");
+    Self {
+      tag: tag.to_string(),
+      chunk_size,
+      config,
+      layer,
+      manifest,
+      artifact,
+    }
+  }
+
+  fn second_tag(&self) -> String {
+    format!("{}-2", self.tag)
+  }
+}
+
+/// A conformance run, with the client authorized for the repository.
+struct Run<'a> {
+  client: Client,
+  name: &'a str,
+  fixture: Fixture,
+}
+
+impl Run<'_> {
+  async fn push(&self, report: &mut Report) {
+    let (client, name, fixture) = (&self.client, self.name, &self.fixture);
+    let media_type = MediaTypes::OciImageManifest.to_string();
+
+    let digest = sha256_digest(&fixture.config);
+    let outcome = expect_eq(client.push_blob(name, &fixture.config, &digest).await, &digest);
+    report.record(Category::Push, "push blob monolithically", outcome);
+
+    let digest = sha256_digest(&fixture.layer);
+    let pushed = client
+      .push_blob_chunked(name, &fixture.layer, &digest, fixture.chunk_size)
+      .await;
+    report.record(Category::Push, "push blob in chunks", expect_eq(pushed, &digest));
+
+    let digest = sha256_digest(&fixture.manifest);
+    let mut outcome = Outcome::Passed;
+    for tag in [fixture.tag.clone(), fixture.second_tag()] {
+      let pushed = client.put_manifest(name, &tag, &media_type, &fixture.manifest).await;
+      outcome = expect_eq(pushed, &digest);
+      if outcome != Outcome::Passed {
+        break;
+      }
+    }
+    report.record(Category::Push, "push manifest by tag", outcome);
+
+    let digest = sha256_digest(&fixture.artifact);
+    let pushed = match client.push_blob(name, b"{}", &sha256_digest(b"{}")).await {
+      Ok(_) => client.put_manifest(name, &digest, &media_type, &fixture.artifact).await,
+      Err(e) => Err(e),
+    };
+    report.record(Category::Push, "push manifest by digest", expect_eq(pushed, &digest));
+  }
+
+  async fn pull(&self, report: &mut Report) {
+    let (client, name, fixture) = (&self.client, self.name, &self.fixture);
+    let accept = [MediaTypes::OciImageManifest.to_string()];
+    let accept = [accept[0].as_str()];
+    let digest = sha256_digest(&fixture.manifest);
+
+    let pulled = client.get_raw_manifest(name, &fixture.tag, Some(&accept)).await;
+    let outcome = expect_eq(pulled.map(|(body, ..)| body), &fixture.manifest);
+    report.record(Category::Pull, "pull manifest by tag", outcome);
+
+    let pulled = client.get_raw_manifest(name, &digest, Some(&accept)).await;
+    let outcome = expect_eq(pulled.map(|(body, ..)| body), &fixture.manifest);
+    report.record(Category::Pull, "pull manifest by digest", outcome);
+
+    let found = client.has_manifest(name, &fixture.tag, Some(&accept)).await;
+    let outcome = expect(found, |f| f.is_some(), "the manifest was not found");
+    report.record(Category::Pull, "check manifest exists", outcome);
+
+    let layer_digest = sha256_digest(&fixture.layer);
+    let found = client.has_blob(name, &layer_digest).await;
+    report.record(
+      Category::Pull,
+      "check blob exists",
+      expect(found, |f| *f, "the blob was not found"),
+    );
+
+    let pulled = client.get_blob(name, &layer_digest).await;
+    report.record(Category::Pull, "pull blob", expect_eq(pulled, &fixture.layer));
+
+    let missing = sha256_digest(b"docker-registry conformance: missing content");
+    let status = self.status(Method::GET, &format!("manifests/{}", missing)).await;
+    report.record(
+      Category::Pull,
+      "pull missing manifest",
+      expect_eq(status, &StatusCode::NOT_FOUND),
+    );
+
+    let status = self.status(Method::GET, &format!("blobs/{}", missing)).await;
+    report.record(
+      Category::Pull,
+      "pull missing blob",
+      expect_eq(status, &StatusCode::NOT_FOUND),
+    );
+  }
+
+  async fn discover(&self, report: &mut Report) {
+    let (client, name, fixture) = (&self.client, self.name, &self.fixture);
+    let tags = [fixture.tag.clone(), fixture.second_tag()];
+
+    let listed: Result<Vec<String>> = client.get_tags(name, None).try_collect().await;
+    let outcome = expect(
+      listed,
+      |l| tags.iter().all(|t| l.contains(t)),
+      "the tags pushed are not listed",
+    );
+    report.record(Category::ContentDiscovery, "list tags", outcome);
+
+    // Pages of a single tag are only complete if the client follows the `Link` headers.
+    let listed: Vec<Result<String>> = client.get_tags(name, Some(1)).collect().await;
+    let listed: Result<Vec<String>> = listed.into_iter().collect();
+    let outcome = expect(
+      listed,
+      |l| tags.iter().all(|t| l.iter().filter(|l| *l == t).count() == 1),
+      "the tags pushed are not listed exactly once across pages",
+    );
+    report.record(Category::ContentDiscovery, "list tags with pagination", outcome);
+
+    let digest = sha256_digest(&fixture.manifest);
+    let artifact = sha256_digest(&fixture.artifact);
+    let listed = client.get_referrers(name, &digest, None).await;
+    let outcome = expect(
+      listed,
+      |l| l.iter().any(|d| d.digest == artifact),
+      "the artifact is not listed",
+    );
+    report.record(Category::ContentDiscovery, "list referrers", outcome);
+
+    let listed = client.get_referrers(name, &digest, Some(ARTIFACT_TYPE)).await;
+    let outcome = expect(
+      listed,
+      |l| l.iter().all(|d| d.artifact_type.as_deref() == Some(ARTIFACT_TYPE)) && !l.is_empty(),
+      "the referrers are not filtered by artifact type",
+    );
+    report.record(Category::ContentDiscovery, "list referrers by artifact type", outcome);
+  }
+
+  async fn manage(&self, report: &mut Report) {
+    let (client, name, fixture) = (&self.client, self.name, &self.fixture);
+
+    // Registries may refuse to delete tags, and blobs, with `400` or `405`.
+    let status = self
+      .status(Method::DELETE, &format!("manifests/{}", fixture.second_tag()))
+      .await;
+    report.record(
+      Category::ContentManagement,
+      "delete tag",
+      optional_deletion(status, "tags"),
+    );
+
+    let mut outcome = Outcome::Passed;
+    for manifest in [&fixture.artifact, &fixture.manifest] {
+      let digest = sha256_digest(manifest);
+      outcome = match client.delete_manifest(name, &digest).await {
+        Ok(()) => {
+          let status = self.status(Method::GET, &format!("manifests/{}", digest)).await;
+          expect_eq(status, &StatusCode::NOT_FOUND)
+        }
+        Err(e) => Outcome::Failed(e.to_string()),
+      };
+      if outcome != Outcome::Passed {
+        break;
+      }
+    }
+    report.record(Category::ContentManagement, "delete manifest", outcome);
+
+    let digest = sha256_digest(&fixture.layer);
+    let status = self.status(Method::DELETE, &format!("blobs/{}", digest)).await;
+    let outcome = match optional_deletion(status, "blobs") {
+      Outcome::Passed => expect(client.has_blob(name, &digest).await, |f| !*f, "the blob is still found"),
+      outcome => outcome,
+    };
+    report.record(Category::ContentManagement, "delete blob", outcome);
+  }
+
+  /// Status of the response to a request to `path` of the repository.
+  async fn status(&self, method: Method, path: &str) -> Result<StatusCode> {
+    let path = format!("/v2/{}/{}", self.name, path);
+    let response = self.client.raw_request(method, &path, HeaderMap::new(), None).await?;
+    Ok(response.status)
+  }
+}
+
+/// `Passed` if `result` holds a value satisfying `check`, `Failed` with `failure` otherwise.
+fn expect<T>(result: Result<T>, check: impl FnOnce(&T) -> bool, failure: &str) -> Outcome {
+  match result {
+    Ok(value) if check(&value) => Outcome::Passed,
+    Ok(_) => Outcome::Failed(failure.to_string()),
+    Err(e) => Outcome::Failed(e.to_string()),
+  }
+}
+
+/// `Passed` if `result` holds `expected`.
+fn expect_eq<T: PartialEq + fmt::Debug>(result: Result<T>, expected: &T) -> Outcome {
+  match result {
+    Ok(value) if value == *expected => Outcome::Passed,
+    Ok(value) => Outcome::Failed(format!("expected {:?}, got {:?}", expected, truncated(&value))),
+    Err(e) => Outcome::Failed(e.to_string()),
+  }
+}
+
+/// Outcome of a deletion of `what`, which registries may not support.
+fn optional_deletion(status: Result<StatusCode>, what: &str) -> Outcome {
+  match status {
+    Ok(StatusCode::ACCEPTED) => Outcome::Passed,
+    Ok(StatusCode::BAD_REQUEST | StatusCode::METHOD_NOT_ALLOWED) => {
+      Outcome::Skipped(format!("the registry does not support deleting {}", what))
+    }
+    Ok(status) => Outcome::Failed(Error::UnexpectedHttpStatus(status).to_string()),
+    Err(e) => Outcome::Failed(e.to_string()),
+  }
+}
+
+/// Debug representation of `value`, shortened for the report.
+fn truncated<T: fmt::Debug>(value: &T) -> String {
+  let debug = format!("{:?}", value);
+  match debug.char_indices().nth(80) {
+    Some((end, _)) => format!("{}...", &debug[..end]),
+    None => debug,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reports_outcomes() {
+    let mut report = Report {
+      registry: "localhost:5000".to_string(),
+      repository: "conformance".to_string(),
+      checks: Vec::new(),
+    };
+    report.record(Category::Pull, "pull blob", Outcome::Passed);
+    report.record(Category::Push, "push blob", Outcome::Failed("boom".to_string()));
+    report.record(
+      Category::ContentManagement,
+      "delete tag",
+      Outcome::Skipped("unsupported".to_string()),
+    );
+
+    assert!(!report.is_success());
+    assert_eq!(report.failures().count(), 1);
+    assert_eq!(
+      report.to_string(),
+      "localhost:5000/conformance\nPASS pull: pull blob\nFAIL push: push blob: boom\nSKIP content management: delete \
+       tag: unsupported\n1 passed, 1 failed, 1 skipped"
+    );
+  }
+}
@@ -0,0 +1,1388 @@
+//! Pulling images and copying them between registries.
+//!
+//! Images are transferred blob by blob: blobs already present in the destination
+//! repository are not uploaded again. Manifest lists and OCI indexes are copied
+//! together with every manifest they reference.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   copy::{copy_image, CopyOptions, LayerFilter},
+//!   v2::Client,
+//! };
+//!
+//! let src = Client::configure().registry("quay.io").build()?;
+//! let dst = Client::configure().registry("localhost:5000").build()?;
+//!
+//! // Leave in-toto attestation layers behind.
+//! let options = CopyOptions {
+//!   layer_filter: LayerFilter::default()
+//!     .exclude_media_type("application/vnd.in-toto+json"),
+//!   ..Default::default()
+//! };
+//! let digest = copy_image(
+//!   &src,
+//!   "coreos/etcd",
+//!   "v3.1.0",
+//!   &dst,
+//!   "etcd",
+//!   "v3.1.0",
+//!   &options,
+//! )
+//! .await?;
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt,
+  str::FromStr,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use futures::StreamExt;
+use log::trace;
+#[cfg(not(target_arch = "wasm32"))]
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+  blob_store::{self, BlobStore},
+  bulk::{BulkOptions, BulkReport, WorkQueue},
+};
+use crate::{
+  errors::{Error, Result},
+  layer::{self, Compression},
+  mediatypes::MediaTypes,
+  reference::Tag,
+  v2::{digest_like, manifest::ManifestError, sha256_digest, Client, ContentDigest, Descriptor},
+};
+
+/// Manifest media types understood by pull and copy.
+pub(crate) const MANIFEST_MEDIA_TYPES: &[&str] = &[
+  "application/vnd.oci.image.manifest.v1+json",
+  "application/vnd.oci.image.index.v1+json",
+  "application/vnd.docker.distribution.manifest.v2+json",
+  "application/vnd.docker.distribution.manifest.list.v2+json",
+];
+
+/// Selects the layers transferred by pull and copy, by media type or annotation.
+///
+/// A layer is selected if it matches at least one include rule (or no include rule is set)
+/// and no exclude rule. Media type rules ending with `*` match any media type with that prefix.
+#[derive(Clone, Debug, Default)]
+pub struct LayerFilter {
+  include_media_types: Vec<String>,
+  exclude_media_types: Vec<String>,
+  include_annotations: Vec<(String, Option<String>)>,
+  exclude_annotations: Vec<(String, Option<String>)>,
+}
+
+impl LayerFilter {
+  /// Select layers of the given media type.
+  pub fn include_media_type(mut self, media_type: &str) -> Self {
+    self.include_media_types.push(media_type.to_string());
+    self
+  }
+
+  /// Skip layers of the given media type.
+  pub fn exclude_media_type(mut self, media_type: &str) -> Self {
+    self.exclude_media_types.push(media_type.to_string());
+    self
+  }
+
+  /// Select layers carrying the annotation `key`, with the given value if `value` is set.
+  pub fn include_annotation(mut self, key: &str, value: Option<&str>) -> Self {
+    self
+      .include_annotations
+      .push((key.to_string(), value.map(String::from)));
+    self
+  }
+
+  /// Skip layers carrying the annotation `key`, with the given value if `value` is set.
+  pub fn exclude_annotation(mut self, key: &str, value: Option<&str>) -> Self {
+    self
+      .exclude_annotations
+      .push((key.to_string(), value.map(String::from)));
+    self
+  }
+
+  /// Whether no rule is set, so that every layer is selected.
+  pub fn is_empty(&self) -> bool {
+    self.include_media_types.is_empty()
+      && self.exclude_media_types.is_empty()
+      && self.include_annotations.is_empty()
+      && self.exclude_annotations.is_empty()
+  }
+
+  /// Whether the layer described by `descriptor` is selected.
+  pub fn matches(&self, descriptor: &Descriptor) -> bool {
+    let media_type = |patterns: &[String]| {
+      patterns.iter().any(|p| match p.strip_suffix('*') {
+        Some(prefix) => descriptor.media_type.starts_with(prefix),
+        None => descriptor.media_type == *p,
+      })
+    };
+    let annotation = |rules: &[(String, Option<String>)]| {
+      let annotations = match &descriptor.annotations {
+        Some(a) => a,
+        None => return false,
+      };
+      rules.iter().any(|(key, value)| match (annotations.get(key), value) {
+        (Some(actual), Some(expected)) => actual == expected,
+        (Some(_), None) => true,
+        (None, _) => false,
+      })
+    };
+
+    let included = (self.include_media_types.is_empty() && self.include_annotations.is_empty())
+      || media_type(&self.include_media_types)
+      || annotation(&self.include_annotations);
+    included && !media_type(&self.exclude_media_types) && !annotation(&self.exclude_annotations)
+  }
+}
+
+/// Options for [`Client::pull_image`].
+#[derive(Clone, Debug, Default)]
+pub struct PullOptions {
+  /// Layers to download; other layers are listed in [`PulledImage::skipped`].
+  pub layer_filter: LayerFilter,
+  /// Whether to check that the tag hint of a `tag@digest` reference still points to the digest,
+  /// failing with `Error::TagMismatch` otherwise.
+  pub verify_tag: bool,
+}
+
+/// Options for [`copy_image`].
+#[derive(Clone, Debug, Default)]
+pub struct CopyOptions {
+  /// Layers to copy; other layers are removed from the copied manifests.
+  pub layer_filter: LayerFilter,
+  /// Transformations applied, in order, to every copied image.
+  pub transforms: Vec<Transform>,
+}
+
+/// An image copied by [`copy_images`], by repository and reference at the source and at the
+/// destination.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CopyTask {
+  pub source_name: String,
+  pub source_reference: String,
+  pub destination_name: String,
+  pub destination_reference: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CopyTask {
+  /// An image copied under the same repository and reference.
+  pub fn new(name: &str, reference: &str) -> Self {
+    Self {
+      source_name: name.to_string(),
+      source_reference: reference.to_string(),
+      destination_name: name.to_string(),
+      destination_reference: reference.to_string(),
+    }
+  }
+
+  /// Copy the image under another repository and reference at the destination.
+  pub fn to(mut self, name: &str, reference: &str) -> Self {
+    self.destination_name = name.to_string();
+    self.destination_reference = reference.to_string();
+    self
+  }
+}
+
+/// Content referenced by an index but missing from the repository it is pushed to, see
+/// [`push_index`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingContent {
+  /// Digest of the child manifest of the index the content belongs to.
+  pub manifest: String,
+  /// Digest of the missing manifest or blob.
+  pub digest: String,
+  /// What is missing: `manifest`, `config` or `layer`.
+  pub kind: &'static str,
+}
+
+impl fmt::Display for MissingContent {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.digest == self.manifest {
+      true => write!(f, "{} {}", self.kind, self.digest),
+      false => write!(f, "{} {} of {}", self.kind, self.digest, self.manifest),
+    }
+  }
+}
+
+/// A transformation applied to images while they are copied.
+///
+/// Transformed images are downloaded entirely, so that their layers and config can be
+/// rewritten; the digests of the resulting blobs and manifests are recomputed.
+#[derive(Clone, Debug)]
+pub enum Transform {
+  /// Flatten all layers into a single gzip-compressed layer.
+  ///
+  /// The config is updated to list a single diff ID, and the history entries which created
+  /// layers are replaced with one entry describing the squash.
+  Squash,
+  /// Recompress every tar layer with `compression`, e.g. from gzip to zstd.
+  ///
+  /// `level` selects the compression level where supported, i.e. for zstd. Layers already
+  /// using `compression` are only recompressed if a level is given and supported. Non-tar
+  /// layers are left untouched; the config is unchanged, as diff IDs refer to the uncompressed
+  /// content. Docker manifests cannot reference zstd layers: recompressing their layers to zstd
+  /// fails with `Error::UnsupportedMediaType`.
+  Recompress {
+    compression: Compression,
+    level: Option<i32>,
+  },
+  /// Set (`Some`) or remove (`None`) manifest annotations, e.g. to record provenance.
+  ///
+  /// Only OCI manifests have annotations: copying a Docker manifest with this transform fails
+  /// with `Error::UnsupportedMediaType`.
+  Annotations(BTreeMap<String, Option<String>>),
+  /// Set (`Some`) or remove (`None`) labels in the image config.
+  Labels(BTreeMap<String, Option<String>>),
+}
+
+impl Transform {
+  /// Whether the transform rewrites layers, so that they must be downloaded first.
+  fn needs_layers(&self) -> bool {
+    matches!(self, Transform::Squash | Transform::Recompress { .. })
+  }
+
+  fn apply(&self, image: &mut TransformedImage) -> Result<()> {
+    match self {
+      Transform::Squash => squash(image),
+      Transform::Recompress { compression, level } => recompress(image, *compression, *level),
+      Transform::Annotations(changes) => {
+        if !image.oci {
+          return Err(Error::UnsupportedMediaType(MediaTypes::ManifestV2S2));
+        }
+        apply_changes(&mut image.annotations, changes);
+        Ok(())
+      }
+      Transform::Labels(changes) => {
+        if changes.values().all(Option::is_none) && !image.config["config"]["Labels"].is_object() {
+          return Ok(());
+        }
+        if !image.config["config"].is_object() {
+          image.config["config"] = Value::Object(serde_json::Map::new());
+        }
+        let labels = &mut image.config["config"]["Labels"];
+        if !labels.is_object() {
+          *labels = Value::Object(serde_json::Map::new());
+        }
+        if let Some(labels) = labels.as_object_mut() {
+          apply_changes(labels, changes);
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+/// Set or remove the entries of `map` listed in `changes`.
+fn apply_changes(map: &mut serde_json::Map<String, Value>, changes: &BTreeMap<String, Option<String>>) {
+  for (key, value) in changes {
+    match value {
+      Some(value) => map.insert(key.clone(), Value::from(value.as_str())),
+      None => map.remove(key),
+    };
+  }
+}
+
+/// An image whose layers and config are being rewritten.
+struct TransformedImage {
+  /// Whether the image has an OCI manifest rather than a Docker one.
+  oci: bool,
+  /// Manifest annotations.
+  annotations: serde_json::Map<String, Value>,
+  config: Value,
+  layers: Vec<LayerBlob>,
+}
+
+struct LayerBlob {
+  descriptor: Value,
+  /// Content of the layer, if it was downloaded.
+  data: Option<Vec<u8>>,
+}
+
+impl LayerBlob {
+  fn new(media_type: &str, data: Vec<u8>) -> Self {
+    Self {
+      descriptor: serde_json::json!({
+        "mediaType": media_type,
+        "digest": sha256_digest(&data),
+        "size": data.len(),
+      }),
+      data: Some(data),
+    }
+  }
+
+  /// Content of the layer, which must have been downloaded.
+  fn data(&self) -> Result<&[u8]> {
+    match &self.data {
+      Some(data) => Ok(data),
+      None => Err(Error::LayerNotDownloaded(descriptor(&self.descriptor)?.digest)),
+    }
+  }
+
+  /// Decompress the layer to a tar archive.
+  fn tar(&self) -> Result<Vec<u8>> {
+    let media_type = descriptor(&self.descriptor)?.media_type;
+    Compression::from_media_type(&media_type)?.decompress(self.data()?)
+  }
+}
+
+fn recompress(image: &mut TransformedImage, compression: Compression, level: Option<i32>) -> Result<()> {
+  #[cfg(feature = "zstd")]
+  if compression == Compression::Zstd && !image.oci {
+    return Err(Error::UnsupportedMediaType(MediaTypes::ManifestV2S2));
+  }
+  let level = level.filter(|_| compression.has_levels());
+  for layer in &mut image.layers {
+    let media_type = descriptor(&layer.descriptor)?.media_type;
+    let current = match Compression::from_media_type(&media_type) {
+      Ok(current) => current,
+      Err(_) => continue,
+    };
+    if (current == compression && level.is_none()) || media_type.contains("nondistributable") {
+      continue;
+    }
+
+    let tar = current.decompress(layer.data()?)?;
+    let data = compression.compress_with_level(&tar, level)?;
+    layer.descriptor["mediaType"] = Value::from(compression.layer_media_type(image.oci));
+    layer.descriptor["digest"] = Value::from(sha256_digest(&data));
+    layer.descriptor["size"] = Value::from(data.len());
+    layer.data = Some(data);
+  }
+  Ok(())
+}
+
+fn squash(image: &mut TransformedImage) -> Result<()> {
+  if image.layers.is_empty() {
+    return Ok(());
+  }
+  let tars = image.layers.iter().map(LayerBlob::tar).collect::<Result<Vec<_>>>()?;
+  let tar = layer::squash(&tars)?;
+
+  let count = image.layers.len();
+  let compression = Compression::Gzip;
+  image.layers = vec![LayerBlob::new(
+    compression.layer_media_type(image.oci),
+    compression.compress(&tar)?,
+  )];
+
+  image.config["rootfs"] = serde_json::json!({"type": "layers", "diff_ids": [sha256_digest(&tar)]});
+  let created = image.config.get("created").cloned();
+  if let Some(history) = image.config.get_mut("history").and_then(Value::as_array_mut) {
+    history.retain(|entry| entry["empty_layer"].as_bool() == Some(true));
+    let mut entry = serde_json::json!({
+      "created_by": "squash",
+      "comment": format!("squashed {} layers", count),
+    });
+    if let Some(created) = created {
+      entry["created"] = created;
+    }
+    history.push(entry);
+  }
+  Ok(())
+}
+
+/// An image downloaded with [`Client::pull_image`].
+#[derive(Clone, Debug)]
+pub struct PulledImage {
+  /// Digest of the manifest.
+  pub digest: String,
+  /// Tag the image was pulled by, or the tag hint of a `tag@digest` reference.
+  pub tag: Option<String>,
+  /// Media type of the manifest.
+  pub media_type: String,
+  /// The manifest, as served by the registry.
+  pub manifest: Vec<u8>,
+  /// The config blob.
+  pub config: Vec<u8>,
+  /// Selected layers and their content, in manifest order.
+  pub layers: Vec<(Descriptor, Vec<u8>)>,
+  /// Layers which were not selected by the layer filter.
+  pub skipped: Vec<Descriptor>,
+}
+
+/// Annotation naming the tag of a manifest in an OCI image layout.
+pub(crate) const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+impl PulledImage {
+  /// Write the image to the [OCI image layout][layout] in `dir`, creating it if needed.
+  ///
+  /// The manifest is added to the `index.json` of the layout, replacing any manifest tagged
+  /// `tag` already there. Skipped layers are not written, so the layout is only complete if the
+  /// layer filter selected every layer.
+  ///
+  /// [layout]: https://github.com/opencontainers/image-spec/blob/v1.1.0/image-layout.md
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn write_oci_layout(&self, dir: &Path, tag: Option<&str>) -> Result<()> {
+    let manifest: Value = serde_json::from_slice(&self.manifest)?;
+    let config = descriptor(&manifest["config"])?;
+
+    write_layout_blob(dir, &self.digest, &self.manifest)?;
+    write_layout_blob(dir, &config.digest, &self.config)?;
+    for (layer, data) in &self.layers {
+      write_layout_blob(dir, &layer.digest, data)?;
+    }
+    let manifest = Descriptor::from_digest(&self.media_type, &self.digest, self.manifest.len() as u64);
+    add_to_oci_layout(dir, &manifest, tag)
+  }
+}
+
+/// Add a manifest to the `index.json` of the [OCI image layout][layout] in `dir`, creating the
+/// layout if needed.
+///
+/// The manifest and the blobs it references are expected to be in the `blobs` directory already,
+/// e.g. stored with [`pull_to_store`] and [`FsBlobStore::oci_layout`](crate::blob_store::FsBlobStore::oci_layout).
+/// Any manifest tagged `tag` in the index is replaced.
+///
+/// [layout]: https://github.com/opencontainers/image-spec/blob/v1.1.0/image-layout.md
+#[cfg(not(target_arch = "wasm32"))]
+pub fn add_to_oci_layout(dir: &Path, manifest: &Descriptor, tag: Option<&str>) -> Result<()> {
+  fs::create_dir_all(dir)?;
+  fs::write(dir.join("oci-layout"), br#"{"imageLayoutVersion":"1.0.0"}"#)?;
+
+  let index_path = dir.join("index.json");
+  let mut index = match fs::read(&index_path) {
+    Ok(data) => serde_json::from_slice(&data)?,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => serde_json::json!({"schemaVersion": 2, "manifests": []}),
+    Err(e) => return Err(e.into()),
+  };
+  let manifests = manifests_of_mut(&mut index)?;
+  let mut entry = serde_json::json!({
+    "mediaType": manifest.media_type,
+    "digest": manifest.digest,
+    "size": manifest.size,
+  });
+  if let Some(tag) = tag {
+    manifests.retain(|m| m["annotations"][REF_NAME_ANNOTATION].as_str() != Some(tag));
+    entry["annotations"] = serde_json::json!({ REF_NAME_ANNOTATION: tag });
+  }
+  manifests.push(entry);
+  fs::write(index_path, serde_json::to_vec_pretty(&index)?)?;
+  Ok(())
+}
+
+/// Write `data` as the blob `digest` of the OCI image layout in `dir`.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_layout_blob(dir: &Path, digest: &str, data: &[u8]) -> Result<()> {
+  let path = blob_path(&dir.join("blobs"), digest)?;
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  fs::write(path, data)?;
+  Ok(())
+}
+
+/// Path of the blob `digest` in the directory `root`, as `<algorithm>/<hex>`.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn blob_path(root: &Path, digest: &str) -> Result<PathBuf> {
+  let valid = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+  match digest.split_once(':') {
+    Some((algorithm, hex)) if valid(algorithm) && valid(hex) => Ok(root.join(algorithm).join(hex)),
+    _ => Err(crate::v2::ContentDigestError::BadDigest(digest.to_string()).into()),
+  }
+}
+
+impl Client {
+  /// Download an image manifest, its config and the layers selected by `options`.
+  ///
+  /// `reference` is a tag, a digest, or a tag and a digest as `tag@digest`: the image is then
+  /// pulled by digest, and the tag is only recorded in [`PulledImage::tag`] unless
+  /// [`PullOptions::verify_tag`] is set.
+  ///
+  /// Manifest lists and OCI indexes are not supported: pull one of the manifests they reference.
+  pub async fn pull_image(&self, name: &str, reference: &str, options: &PullOptions) -> Result<PulledImage> {
+    let (tag, reference) = split_tag_hint(self, name, reference, options).await?;
+
+    let (manifest, media_type, digest) = self
+      .get_raw_manifest(name, reference, Some(MANIFEST_MEDIA_TYPES))
+      .await?;
+    let digest = manifest_digest(reference, digest, &manifest)?;
+    if manifest_kind(&media_type)? != ManifestKind::Image {
+      return Err(Error::UnsupportedMediaType(parse_media_type(&media_type)?));
+    }
+
+    let value: Value = serde_json::from_slice(&manifest)?;
+    let config = self.get_blob(name, &descriptor(&value["config"])?.digest).await?;
+
+    let mut layers = Vec::new();
+    let mut skipped = Vec::new();
+    for layer in layers_of(&value)? {
+      let layer = descriptor(layer)?;
+      if options.layer_filter.matches(&layer) {
+        trace!("Pulling layer {}", layer.digest);
+        let data = self.get_blob(name, &layer.digest).await?;
+        layers.push((layer, data));
+      } else {
+        trace!("Skipping layer {} of type {}", layer.digest, layer.media_type);
+        skipped.push(layer);
+      }
+    }
+
+    Ok(PulledImage {
+      digest,
+      tag,
+      media_type,
+      manifest,
+      config,
+      layers,
+      skipped,
+    })
+  }
+}
+
+/// Digest of `manifest`, fetched by `reference`, verified against the digest the caller pinned
+/// or, for tags, against the `Docker-Content-Digest` reported by the registry.
+pub(crate) fn manifest_digest(reference: &str, reported: Option<String>, manifest: &[u8]) -> Result<String> {
+  let expected = match ContentDigest::try_new(reference) {
+    Ok(_) => Some(reference.to_string()),
+    Err(_) => reported,
+  };
+  match expected {
+    Some(expected) => {
+      let mut verifier = ContentDigest::try_new(&expected)?;
+      verifier.update(manifest);
+      verifier.verify()?;
+      Ok(expected)
+    }
+    None => Ok(sha256_digest(manifest)),
+  }
+}
+
+/// Split a `tag@digest` reference into its tag hint and the digest, verifying the tag if asked.
+///
+/// Plain tags are returned as the tag hint of themselves.
+pub(crate) async fn split_tag_hint<'a>(
+  client: &Client,
+  name: &str,
+  reference: &'a str,
+  options: &PullOptions,
+) -> Result<(Option<String>, &'a str)> {
+  match reference.split_once('@') {
+    Some((tag, digest)) => {
+      let tag = Tag::parse(tag)?.to_string();
+      if options.verify_tag {
+        let actual = client.resolve_digest(name, &tag).await?;
+        if actual != digest {
+          return Err(Error::TagMismatch {
+            tag,
+            expected: digest.to_string(),
+            actual,
+          });
+        }
+      }
+      Ok((Some(tag), digest))
+    }
+    None => Ok((Tag::parse(reference).ok().map(|t| t.to_string()), reference)),
+  }
+}
+
+/// An image, manifest list or OCI index stored with [`pull_to_store`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct StoredImage {
+  /// Descriptor of the manifest, which is stored as a blob.
+  pub descriptor: Descriptor,
+  /// Tag the image was pulled by, or the tag hint of a `tag@digest` reference.
+  pub tag: Option<String>,
+  /// Selected layers, of every image of an index, in manifest order.
+  pub layers: Vec<Descriptor>,
+  /// Layers which were not selected by the layer filter, and foreign layers.
+  pub skipped: Vec<Descriptor>,
+}
+
+/// Pull an image, manifest list or OCI index into a blob store.
+///
+/// Manifests, configs and the layers selected by `options` are streamed into `store`, skipping
+/// blobs it already has. Manifests are stored after the blobs they reference, so that a
+/// manifest in the store implies that its selected blobs are there too. Space for the blobs is
+/// reserved with [`BlobStore::reserve`] before any is downloaded. `reference` is handled as by
+/// [`Client::pull_image`]. Fails with `Error::DeadlineExceededAfter` if the deadline of the
+/// client passes while blobs are downloaded, listing the blobs already in the store.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn pull_to_store(
+  client: &Client,
+  name: &str,
+  reference: &str,
+  store: &dyn BlobStore,
+  options: &PullOptions,
+) -> Result<StoredImage> {
+  let pulled = pull_blobs(client, name, reference, store, options).await?;
+  for (child, child_manifest) in pulled.children {
+    if !store.has(&child.digest).await? {
+      blob_store::put_bytes(store, &child.digest, child_manifest).await?;
+    }
+  }
+  if !store.has(&pulled.image.descriptor.digest).await? {
+    blob_store::put_bytes(store, &pulled.image.descriptor.digest, pulled.manifest).await?;
+  }
+  Ok(pulled.image)
+}
+
+/// Manifests pulled with [`pull_blobs`], left for the caller to store.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct PulledManifests {
+  pub image: StoredImage,
+  pub manifest: Vec<u8>,
+  /// Manifests referenced by an index, with their descriptors in the index.
+  pub children: Vec<(Descriptor, Vec<u8>)>,
+}
+
+/// Stream the configs and selected layers of an image, manifest list or OCI index into `store`,
+/// returning the manifests. A deadline passing while blobs are downloaded fails with
+/// `Error::DeadlineExceededAfter`, listing the blobs in the store.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn pull_blobs(
+  client: &Client,
+  name: &str,
+  reference: &str,
+  store: &dyn BlobStore,
+  options: &PullOptions,
+) -> Result<PulledManifests> {
+  let (tag, reference) = split_tag_hint(client, name, reference, options).await?;
+  let (manifest, media_type, digest) = client
+    .get_raw_manifest(name, reference, Some(MANIFEST_MEDIA_TYPES))
+    .await?;
+  let digest = manifest_digest(reference, digest, &manifest)?;
+  let mut image = StoredImage {
+    descriptor: Descriptor::from_digest(&media_type, &digest, manifest.len() as u64),
+    tag,
+    layers: Vec::new(),
+    skipped: Vec::new(),
+  };
+
+  let mut children = Vec::new();
+  let mut blobs = Vec::new();
+  match manifest_kind(&media_type)? {
+    ManifestKind::Image => select_blobs(&manifest, options, &mut image, &mut blobs)?,
+    ManifestKind::Index => {
+      for child in index_children(&manifest)? {
+        let (child_manifest, child_media_type, _) = client
+          .get_raw_manifest(name, &child.digest, Some(MANIFEST_MEDIA_TYPES))
+          .await?;
+        if manifest_kind(&child_media_type)? != ManifestKind::Image {
+          return Err(Error::UnsupportedMediaType(parse_media_type(&child_media_type)?));
+        }
+        select_blobs(&child_manifest, options, &mut image, &mut blobs)?;
+        children.push((child, child_manifest));
+      }
+    }
+  }
+
+  let mut missing = Vec::new();
+  let mut completed = Vec::new();
+  for blob in blobs {
+    if store.has(&blob.digest).await? {
+      trace!("Blob {} already stored", blob.digest);
+      completed.push(blob.digest);
+    } else if !missing.iter().any(|b: &Descriptor| b.digest == blob.digest) {
+      missing.push(blob);
+    }
+  }
+  // Fail before downloading anything rather than run out of space halfway.
+  store.reserve(missing.iter().map(|b| b.size).sum()).await?;
+  for blob in missing {
+    trace!("Storing blob {}", blob.digest);
+    let stored = async {
+      let chunks = client.get_blob_stream(name, &blob.digest).await?;
+      store.put(&blob.digest, chunks.boxed()).await
+    };
+    if let Err(e) = stored.await {
+      return Err(with_progress(e, completed));
+    }
+    completed.push(blob.digest);
+  }
+
+  Ok(PulledManifests {
+    image,
+    manifest,
+    children,
+  })
+}
+
+/// Add the config and the selected layers of an image manifest to `blobs`.
+#[cfg(not(target_arch = "wasm32"))]
+fn select_blobs(
+  manifest: &[u8],
+  options: &PullOptions,
+  image: &mut StoredImage,
+  blobs: &mut Vec<Descriptor>,
+) -> Result<()> {
+  let value: Value = serde_json::from_slice(manifest)?;
+  blobs.push(descriptor(&value["config"])?);
+  for layer in layers_of(&value)? {
+    let layer = descriptor(layer)?;
+    if options.layer_filter.matches(&layer) && !is_foreign(&layer) {
+      image.layers.push(layer.clone());
+      blobs.push(layer);
+    } else {
+      trace!("Skipping layer {} of type {}", layer.digest, layer.media_type);
+      image.skipped.push(layer);
+    }
+  }
+  Ok(())
+}
+
+/// Push an image, manifest list or OCI index from a blob store to a repository.
+///
+/// `manifest` describes a manifest stored in `store`, e.g. [`StoredImage::descriptor`]. Blobs
+/// already present in the destination are not uploaded again; foreign layers are not uploaded.
+/// Returns the digest of the manifest pushed as `dst_reference`.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn push_from_store(
+  store: &dyn BlobStore,
+  manifest: &Descriptor,
+  dst: &Client,
+  dst_name: &str,
+  dst_reference: &str,
+) -> Result<String> {
+  dst.ensure_writable()?;
+  let data = blob_store::get_bytes(store, &manifest.digest).await?;
+  let mut children = Vec::new();
+  if manifest_kind(&manifest.media_type)? == ManifestKind::Index {
+    for child in index_children(&data)? {
+      let child_manifest = blob_store::get_bytes(store, &child.digest).await?;
+      children.push((child, child_manifest));
+    }
+  }
+  push_manifests(
+    store,
+    &manifest.media_type,
+    &data,
+    &children,
+    dst,
+    dst_name,
+    dst_reference,
+  )
+  .await
+}
+
+/// Push the blobs of a manifest, and of the `children` of an index, from `store`, then the
+/// manifests themselves.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn push_manifests(
+  store: &dyn BlobStore,
+  media_type: &str,
+  manifest: &[u8],
+  children: &[(Descriptor, Vec<u8>)],
+  dst: &Client,
+  dst_name: &str,
+  dst_reference: &str,
+) -> Result<String> {
+  match manifest_kind(media_type)? {
+    ManifestKind::Image => push_stored_blobs(store, manifest, dst, dst_name).await?,
+    ManifestKind::Index => {
+      for (child, child_manifest) in children {
+        if manifest_kind(&child.media_type)? != ManifestKind::Image {
+          return Err(Error::UnsupportedMediaType(parse_media_type(&child.media_type)?));
+        }
+        push_stored_blobs(store, child_manifest, dst, dst_name).await?;
+        dst
+          .put_manifest(dst_name, &child.digest, &child.media_type, child_manifest)
+          .await?;
+      }
+    }
+  }
+  dst.put_manifest(dst_name, dst_reference, media_type, manifest).await
+}
+
+/// Upload the config and layers of an image manifest from `store`, unless the destination has them.
+#[cfg(not(target_arch = "wasm32"))]
+async fn push_stored_blobs(store: &dyn BlobStore, manifest: &[u8], dst: &Client, dst_name: &str) -> Result<()> {
+  let value: Value = serde_json::from_slice(manifest)?;
+  let mut blobs = vec![descriptor(&value["config"])?];
+  for layer in layers_of(&value)? {
+    blobs.push(descriptor(layer)?);
+  }
+  for blob in blobs.iter().filter(|blob| !is_foreign(blob)) {
+    if dst.has_blob(dst_name, &blob.digest).await? {
+      trace!("Blob {} already present in {}", blob.digest, dst_name);
+      continue;
+    }
+    // Stream the blob from the store rather than hold layers in memory; the upload is cancelled
+    // unless the data matches the digest.
+    dst
+      .coalesce_upload(dst_name, &blob.digest, || async {
+        let chunks = store.get(&blob.digest).await?;
+        dst.upload_blob_stream(dst_name, chunks, &blob.digest).await
+      })
+      .await?;
+  }
+  Ok(())
+}
+
+/// Copy an image, manifest list or OCI index from one repository to another.
+///
+/// `src` and `dst` may be clients for the same registry. Blobs already present in the
+/// destination are not transferred again. Manifests are pushed unchanged unless a layer is
+/// removed by the layer filter; in that case the image config is rewritten to match the
+/// remaining layers. Returns the digest of the manifest pushed as `dst_reference`.
+///
+/// Fails with `Error::DeadlineExceededAfter` if the deadline of either client passes, listing
+/// the blobs and manifests already at the destination.
+pub async fn copy_image(
+  src: &Client,
+  src_name: &str,
+  src_reference: &str,
+  dst: &Client,
+  dst_name: &str,
+  dst_reference: &str,
+  options: &CopyOptions,
+) -> Result<String> {
+  dst.ensure_writable()?;
+  let mut completed = Vec::new();
+  copy_image_tracked(
+    src,
+    src_name,
+    src_reference,
+    dst,
+    dst_name,
+    dst_reference,
+    options,
+    &mut completed,
+  )
+  .await
+  .map_err(|e| with_progress(e, completed))
+}
+
+/// Copy an image like [`copy_image`], adding the blobs and manifests at the destination to
+/// `completed` as it goes.
+#[allow(clippy::too_many_arguments)]
+async fn copy_image_tracked(
+  src: &Client,
+  src_name: &str,
+  src_reference: &str,
+  dst: &Client,
+  dst_name: &str,
+  dst_reference: &str,
+  options: &CopyOptions,
+  completed: &mut Vec<String>,
+) -> Result<String> {
+  let (manifest, media_type, _) = src
+    .get_raw_manifest(src_name, src_reference, Some(MANIFEST_MEDIA_TYPES))
+    .await?;
+
+  let manifest = match manifest_kind(&media_type)? {
+    ManifestKind::Image => {
+      copy_image_manifest(src, src_name, dst, dst_name, manifest, &media_type, options, completed).await?
+    }
+    ManifestKind::Index => {
+      let mut index: Value = serde_json::from_slice(&manifest)?;
+      let mut changed = false;
+      for child in manifests_of_mut(&mut index)? {
+        let child_descriptor = descriptor(child)?;
+        let (child_manifest, child_media_type, _) = src
+          .get_raw_manifest(src_name, &child_descriptor.digest, Some(MANIFEST_MEDIA_TYPES))
+          .await?;
+        if manifest_kind(&child_media_type)? != ManifestKind::Image {
+          return Err(Error::UnsupportedMediaType(parse_media_type(&child_media_type)?));
+        }
+
+        let copied = copy_image_manifest(
+          src,
+          src_name,
+          dst,
+          dst_name,
+          child_manifest,
+          &child_media_type,
+          options,
+          completed,
+        )
+        .await?;
+        let digest = digest_like(&child_descriptor.digest, &copied);
+        dst.put_manifest(dst_name, &digest, &child_media_type, &copied).await?;
+        completed.push(digest.clone());
+
+        if digest != child_descriptor.digest {
+          child["digest"] = Value::from(digest);
+          child["size"] = Value::from(copied.len());
+          changed = true;
+        }
+      }
+      match changed {
+        true => serde_json::to_vec(&index)?,
+        false => manifest,
+      }
+    }
+  };
+
+  dst.put_manifest(dst_name, dst_reference, &media_type, &manifest).await
+}
+
+/// Copy the images queued in `queue` from `src` to `dst`, like [`copy_image`] does, journaling
+/// every copy as it completes.
+///
+/// Images already copied by a previous run of the queue are not copied again. Successful items
+/// hold the digest of the manifest pushed at the destination.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn copy_images(
+  src: &Client,
+  dst: &Client,
+  queue: &WorkQueue<CopyTask>,
+  options: &CopyOptions,
+  bulk: &BulkOptions,
+) -> Result<BulkReport<CopyTask, String>> {
+  dst.ensure_writable()?;
+  queue
+    .run(bulk, |task| async move {
+      copy_image(
+        src,
+        &task.source_name,
+        &task.source_reference,
+        dst,
+        &task.destination_name,
+        &task.destination_reference,
+        options,
+      )
+      .await
+    })
+    .await
+}
+
+/// Push a manifest list or OCI index, after checking that the destination has the manifests it
+/// references and their blobs.
+///
+/// Missing manifests and blobs are copied from `source`, a client and repository holding them,
+/// if given. Otherwise nothing is pushed and the push fails with `Error::MissingContent`, listing
+/// everything missing. Manifests of nested indexes are checked, but not their own children.
+/// Returns the digest of the index pushed as `dst_reference`.
+pub async fn push_index(
+  dst: &Client,
+  dst_name: &str,
+  dst_reference: &str,
+  media_type: &str,
+  index: &[u8],
+  source: Option<(&Client, &str)>,
+) -> Result<String> {
+  dst.ensure_writable()?;
+  if manifest_kind(media_type)? != ManifestKind::Index {
+    return Err(Error::UnsupportedMediaType(parse_media_type(media_type)?));
+  }
+  let mut missing = Vec::new();
+  for child in index_children(index)? {
+    let present = dst
+      .has_manifest(dst_name, &child.digest, Some(&[child.media_type.as_str()]))
+      .await?
+      .is_some();
+    match (present, source) {
+      (true, _) => {
+        if manifest_kind(&child.media_type)? != ManifestKind::Image {
+          continue;
+        }
+        let (manifest, _, _) = dst.get_raw_manifest(dst_name, &child.digest, None).await?;
+        for (kind, blob) in image_blobs(&manifest)? {
+          if dst.has_blob(dst_name, &blob.digest).await? {
+            continue;
+          }
+          match source {
+            Some((src, src_name)) => copy_blob(src, src_name, dst, dst_name, &blob).await?,
+            None => missing.push(MissingContent {
+              manifest: child.digest.clone(),
+              digest: blob.digest,
+              kind,
+            }),
+          }
+        }
+      }
+      (false, Some((src, src_name))) => {
+        trace!("Copying missing manifest {} from {}", child.digest, src_name);
+        let (manifest, child_media_type, _) = src
+          .get_raw_manifest(src_name, &child.digest, Some(MANIFEST_MEDIA_TYPES))
+          .await?;
+        if manifest_kind(&child_media_type)? != ManifestKind::Image {
+          return Err(Error::UnsupportedMediaType(parse_media_type(&child_media_type)?));
+        }
+        for (_, blob) in image_blobs(&manifest)? {
+          copy_blob(src, src_name, dst, dst_name, &blob).await?;
+        }
+        dst
+          .put_manifest(dst_name, &child.digest, &child_media_type, &manifest)
+          .await?;
+      }
+      (false, None) => missing.push(MissingContent {
+        manifest: child.digest.clone(),
+        digest: child.digest,
+        kind: "manifest",
+      }),
+    }
+  }
+  if !missing.is_empty() {
+    return Err(Error::MissingContent {
+      repository: dst_name.to_string(),
+      missing,
+    });
+  }
+  dst.put_manifest(dst_name, dst_reference, media_type, index).await
+}
+
+/// The config and layers of an image manifest, with their kind, leaving out foreign layers.
+fn image_blobs(manifest: &[u8]) -> Result<Vec<(&'static str, Descriptor)>> {
+  let value: Value = serde_json::from_slice(manifest)?;
+  let mut blobs = vec![("config", descriptor(&value["config"])?)];
+  for layer in layers_of(&value)? {
+    blobs.push(("layer", descriptor(layer)?));
+  }
+  blobs.retain(|(_, blob)| !is_foreign(blob));
+  Ok(blobs)
+}
+
+/// Copy the blobs of an image manifest, returning the manifest to push.
+#[allow(clippy::too_many_arguments)]
+async fn copy_image_manifest(
+  src: &Client,
+  src_name: &str,
+  dst: &Client,
+  dst_name: &str,
+  manifest: Vec<u8>,
+  media_type: &str,
+  options: &CopyOptions,
+  completed: &mut Vec<String>,
+) -> Result<Vec<u8>> {
+  let mut value: Value = serde_json::from_slice(&manifest)?;
+
+  let mut removed = BTreeSet::new();
+  let mut layers = Vec::new();
+  let layer_count = layers_of(&value)?.len();
+  for (i, layer) in layers_of(&value)?.iter().enumerate() {
+    let layer_descriptor = descriptor(layer)?;
+    if options.layer_filter.matches(&layer_descriptor) {
+      layers.push(layer.clone());
+    } else {
+      trace!(
+        "Skipping layer {} of type {}",
+        layer_descriptor.digest,
+        layer_descriptor.media_type
+      );
+      removed.insert(i);
+    }
+  }
+
+  let config = descriptor(&value["config"])?;
+  if removed.is_empty() && options.transforms.is_empty() {
+    for layer in &layers {
+      let layer = descriptor(layer)?;
+      copy_blob(src, src_name, dst, dst_name, &layer).await?;
+      if !is_foreign(&layer) {
+        completed.push(layer.digest);
+      }
+    }
+    copy_blob(src, src_name, dst, dst_name, &config).await?;
+    completed.push(config.digest);
+    return Ok(manifest);
+  }
+
+  let config_blob = src.get_blob(src_name, &config.digest).await?;
+  let original_config: Value = serde_json::from_slice(&config_blob)?;
+  let mut image = TransformedImage {
+    oci: parse_media_type(media_type)? == MediaTypes::OciImageManifest,
+    annotations: match value.get("annotations") {
+      Some(Value::Object(annotations)) => annotations.clone(),
+      _ => serde_json::Map::new(),
+    },
+    config: original_config.clone(),
+    layers: layers
+      .into_iter()
+      .map(|descriptor| LayerBlob { descriptor, data: None })
+      .collect(),
+  };
+  remove_config_layers(&mut image.config, layer_count, &removed);
+
+  if options.transforms.iter().any(Transform::needs_layers) {
+    for layer in &mut image.layers {
+      let digest = descriptor(&layer.descriptor)?.digest;
+      layer.data = Some(src.get_blob(src_name, &digest).await?);
+    }
+  }
+  for transform in &options.transforms {
+    trace!("Applying {:?} to {}", transform, src_name);
+    transform.apply(&mut image)?;
+  }
+
+  for layer in &image.layers {
+    let layer_descriptor = descriptor(&layer.descriptor)?;
+    match &layer.data {
+      Some(data) => push_blob_if_missing(dst, dst_name, data, &layer_descriptor.digest).await?,
+      None if is_foreign(&layer_descriptor) => continue,
+      None => copy_blob(src, src_name, dst, dst_name, &layer_descriptor).await?,
+    }
+    completed.push(layer_descriptor.digest);
+  }
+
+  // Keep the original config, and its digest, unless a transform changed it.
+  let config_blob = match image.config == original_config {
+    true => config_blob,
+    false => serde_json::to_vec(&image.config)?,
+  };
+  let config_digest = sha256_digest(&config_blob);
+  push_blob_if_missing(dst, dst_name, &config_blob, &config_digest).await?;
+  completed.push(config_digest.clone());
+
+  value["config"]["digest"] = Value::from(config_digest);
+  value["config"]["size"] = Value::from(config_blob.len());
+  value["layers"] = Value::Array(image.layers.into_iter().map(|l| l.descriptor).collect());
+  if let Some(manifest) = value.as_object_mut() {
+    match image.annotations.is_empty() {
+      true => manifest.remove("annotations"),
+      false => manifest.insert("annotations".to_string(), Value::Object(image.annotations)),
+    };
+  }
+  Ok(serde_json::to_vec(&value)?)
+}
+
+/// `Error::DeadlineExceededAfter` listing `completed` for a deadline passing during a composite
+/// operation, other errors as they are.
+pub(crate) fn with_progress(error: Error, completed: Vec<String>) -> Error {
+  match error {
+    Error::DeadlineExceeded => Error::DeadlineExceededAfter { completed },
+    error => error,
+  }
+}
+
+/// Upload a blob, unless the destination already has it.
+pub(crate) async fn push_blob_if_missing(dst: &Client, dst_name: &str, data: &[u8], digest: &str) -> Result<()> {
+  if dst.has_blob(dst_name, digest).await? {
+    trace!("Blob {} already present in {}", digest, dst_name);
+    return Ok(());
+  }
+  dst.push_blob(dst_name, data, digest).await?;
+  Ok(())
+}
+
+/// Transfer a blob, unless the destination already has it.
+pub(crate) async fn copy_blob(
+  src: &Client,
+  src_name: &str,
+  dst: &Client,
+  dst_name: &str,
+  blob: &Descriptor,
+) -> Result<()> {
+  if is_foreign(blob) {
+    return Ok(());
+  }
+  if dst.has_blob(dst_name, &blob.digest).await? {
+    trace!("Blob {} already present in {}", blob.digest, dst_name);
+    return Ok(());
+  }
+  // Concurrent copies of the blob download and upload it once, streamed from one registry to
+  // the other: the upload is cancelled unless the data matches the digest.
+  dst
+    .coalesce_upload(dst_name, &blob.digest, || async {
+      let chunks = src.get_blob_stream(src_name, &blob.digest).await?;
+      dst.upload_blob_stream(dst_name, chunks, &blob.digest).await
+    })
+    .await?;
+  Ok(())
+}
+
+/// Whether a layer is foreign, i.e. served from its own URLs rather than from the registry.
+pub(crate) fn is_foreign(blob: &Descriptor) -> bool {
+  blob.media_type.contains("foreign") || blob.media_type.contains("nondistributable")
+}
+
+/// Remove the `diff_ids` and `history` entries of the layers at the given indexes of the
+/// manifest, which has `layer_count` layers.
+///
+/// History entries marked `empty_layer` describe no layer and are kept. `diff_ids`, or history
+/// entries, which do not match the layers one to one are left as-is, as are configs which are not
+/// image configs.
+fn remove_config_layers(config: &mut Value, layer_count: usize, removed: &BTreeSet<usize>) {
+  if let Some(diff_ids) = config["rootfs"]["diff_ids"].as_array_mut() {
+    if diff_ids.len() == layer_count {
+      let mut i = 0;
+      diff_ids.retain(|_| {
+        i += 1;
+        !removed.contains(&(i - 1))
+      });
+    }
+  }
+  if let Some(history) = config.get_mut("history").and_then(Value::as_array_mut) {
+    let is_empty_layer = |entry: &Value| entry["empty_layer"].as_bool() == Some(true);
+    if history.iter().filter(|entry| !is_empty_layer(entry)).count() == layer_count {
+      let mut layer = 0;
+      history.retain(|entry| {
+        if is_empty_layer(entry) {
+          return true;
+        }
+        layer += 1;
+        !removed.contains(&(layer - 1))
+      });
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ManifestKind {
+  Image,
+  Index,
+}
+
+pub(crate) fn parse_media_type(media_type: &str) -> Result<MediaTypes> {
+  let essence = media_type.split(';').next().unwrap_or_default().trim();
+  Ok(MediaTypes::from_str(essence)?)
+}
+
+pub(crate) fn manifest_kind(media_type: &str) -> Result<ManifestKind> {
+  match parse_media_type(media_type)? {
+    MediaTypes::ManifestV2S2 | MediaTypes::OciImageManifest => Ok(ManifestKind::Image),
+    MediaTypes::ManifestList | MediaTypes::OciImageIndexV1 => Ok(ManifestKind::Index),
+    unsupported => Err(Error::UnsupportedMediaType(unsupported)),
+  }
+}
+
+pub(crate) fn descriptor(value: &Value) -> Result<Descriptor> {
+  Descriptor::try_from(value).map_err(|e| ManifestError::Invalid(format!("invalid descriptor: {}", e)).into())
+}
+
+pub(crate) fn layers_of(manifest: &Value) -> Result<&Vec<Value>> {
+  manifest["layers"]
+    .as_array()
+    .ok_or_else(|| ManifestError::Invalid("missing layers".to_string()).into())
+}
+
+/// Descriptors of the manifests referenced by a manifest list or OCI index.
+pub(crate) fn index_children(index: &[u8]) -> Result<Vec<Descriptor>> {
+  let index: Value = serde_json::from_slice(index)?;
+  index["manifests"]
+    .as_array()
+    .ok_or_else(|| Error::from(ManifestError::Invalid("missing manifests".to_string())))?
+    .iter()
+    .map(descriptor)
+    .collect()
+}
+
+fn manifests_of_mut(index: &mut Value) -> Result<&mut Vec<Value>> {
+  index["manifests"]
+    .as_array_mut()
+    .ok_or_else(|| ManifestError::Invalid("missing manifests".to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use test_case::test_case;
+
+  use super::*;
+
+  fn layer(media_type: &str, annotation: Option<(&str, &str)>) -> Descriptor {
+    Descriptor {
+      media_type: media_type.to_string(),
+      annotations: annotation.map(|(k, v)| HashMap::from([(k.to_string(), v.to_string())])),
+      ..Default::default()
+    }
+  }
+
+  #[test_case(LayerFilter::default() => true; "no rules")]
+  #[test_case(LayerFilter::default().exclude_media_type("application/vnd.in-toto+json") => true; "other media type excluded")]
+  #[test_case(LayerFilter::default().exclude_media_type("application/vnd.oci.image.layer.v1.tar*") => false; "prefix excluded")]
+  #[test_case(LayerFilter::default().include_media_type("application/vnd.oci.image.layer.v1.squashfs") => false; "not included")]
+  #[test_case(LayerFilter::default().include_media_type("application/vnd.oci.image.layer.v1.squashfs").include_annotation("kind", None) => true; "included by annotation")]
+  #[test_case(LayerFilter::default().exclude_annotation("kind", Some("attestation")) => false; "annotation value excluded")]
+  #[test_case(LayerFilter::default().exclude_annotation("kind", Some("sbom")) => true; "other annotation value excluded")]
+  fn layer_filter(filter: LayerFilter) -> bool {
+    filter.matches(&layer(
+      "application/vnd.oci.image.layer.v1.tar+gzip",
+      Some(("kind", "attestation")),
+    ))
+  }
+
+  #[test]
+  fn remove_config_layers_keeps_empty_history() {
+    let mut config = serde_json::json!({
+      "rootfs": {"type": "layers", "diff_ids": ["sha256:a", "sha256:b", "sha256:c"]},
+      "history": [
+        {"created_by": "ADD a"},
+        {"created_by": "ENV x=y", "empty_layer": true},
+        {"created_by": "ADD b"},
+        {"created_by": "ADD c"},
+      ],
+    });
+    remove_config_layers(&mut config, 3, &BTreeSet::from([1]));
+    assert_eq!(
+      config["rootfs"]["diff_ids"],
+      serde_json::json!(["sha256:a", "sha256:c"])
+    );
+    assert_eq!(
+      config["history"],
+      serde_json::json!([
+        {"created_by": "ADD a"},
+        {"created_by": "ENV x=y", "empty_layer": true},
+        {"created_by": "ADD c"},
+      ])
+    );
+  }
+
+  #[test]
+  fn remove_config_layers_keeps_mismatched_entries() {
+    let original = serde_json::json!({
+      "rootfs": {"type": "layers", "diff_ids": ["sha256:a", "sha256:b"]},
+      "history": [{"created_by": "ADD a"}],
+    });
+    let mut config = original.clone();
+    remove_config_layers(&mut config, 3, &BTreeSet::from([1]));
+    assert_eq!(config, original);
+  }
+
+  fn gzipped_image(oci: bool, data: Option<Vec<u8>>) -> TransformedImage {
+    TransformedImage {
+      oci,
+      annotations: Default::default(),
+      config: Value::Null,
+      layers: vec![LayerBlob {
+        descriptor: serde_json::json!({
+          "mediaType": Compression::Gzip.layer_media_type(oci),
+          "digest": "sha256:a",
+          "size": 1,
+        }),
+        data,
+      }],
+    }
+  }
+
+  #[test]
+  fn recompress_skips_ignored_levels() {
+    let mut image = gzipped_image(true, None);
+    recompress(&mut image, Compression::Gzip, Some(9)).unwrap();
+    assert_eq!(image.layers[0].descriptor["digest"], "sha256:a");
+  }
+
+  #[test]
+  fn recompress_requires_downloaded_layers() {
+    let mut image = gzipped_image(true, None);
+    let err = recompress(&mut image, Compression::None, None).unwrap_err();
+    assert!(matches!(err, Error::LayerNotDownloaded(digest) if digest == "sha256:a"));
+  }
+
+  #[cfg(feature = "zstd")]
+  #[test]
+  fn recompress_rejects_zstd_for_docker_manifests() {
+    let data = Compression::Gzip.compress(b"").unwrap();
+    let mut image = gzipped_image(false, Some(data));
+    let err = recompress(&mut image, Compression::Zstd, None).unwrap_err();
+    assert!(matches!(err, Error::UnsupportedMediaType(MediaTypes::ManifestV2S2)));
+  }
+}
@@ -0,0 +1,235 @@
+//! The `dir:` transport of skopeo and podman.
+//!
+//! A `dir:` directory holds the manifest of an image as `manifest.json`, a `version` file, and
+//! its blobs as files named after their hex digest. Manifest lists and OCI indexes are stored
+//! with the manifests they reference, as `<hex>.manifest.json`, as `skopeo copy --all` does.
+//! Images can be exported to such a directory with [`pull_to_dir`], and pushed from it with
+//! [`push_from_dir`], e.g. to exchange images with `skopeo copy docker://... dir:...`.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use std::path::Path;
+//!
+//! use docker_registry::{copy::PullOptions, dir_transport, v2::Client};
+//!
+//! let src = Client::configure().registry("quay.io").build()?;
+//! let dir = Path::new("/tmp/etcd");
+//! dir_transport::pull_to_dir(&src, "coreos/etcd", "v3.1.0", dir, &PullOptions::default()).await?;
+//!
+//! // Equivalent to `skopeo copy dir:/tmp/etcd docker://localhost:5000/etcd:v3.1.0`.
+//! let dst = Client::configure().registry("localhost:5000").build()?;
+//! dir_transport::push_from_dir(dir, &dst, "etcd", "v3.1.0").await?;
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use tokio::fs;
+
+use crate::{
+  blob_store::{self, BlobChunks, BlobFuture, BlobStore},
+  copy::{self, PullOptions, StoredImage},
+  errors::{Error, Result},
+  mediatypes::MediaTypes,
+  staging,
+  v2::{Client, ContentDigestError},
+};
+
+/// Content of the `version` file written by this crate and by current skopeo versions.
+pub const VERSION: &str = "Directory Transport Version: 1.1\n";
+
+/// A `dir:` directory, storing blobs as files named after their digest.
+///
+/// SHA-256 blobs are named by their hex digest alone, other blobs `<algorithm>-<hex>`.
+#[derive(Clone, Debug)]
+pub struct DirTransport {
+  dir: PathBuf,
+}
+
+impl DirTransport {
+  pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+    Self {
+      dir: dir.as_ref().to_path_buf(),
+    }
+  }
+
+  /// Path of the blob `digest`.
+  pub fn path(&self, digest: &str) -> Result<PathBuf> {
+    let valid = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+    match digest.split_once(':') {
+      Some(("sha256", hex)) if valid(hex) => Ok(self.dir.join(hex)),
+      Some((algorithm, hex)) if valid(algorithm) && valid(hex) => Ok(self.dir.join(format!("{}-{}", algorithm, hex))),
+      _ => Err(ContentDigestError::BadDigest(digest.to_string()).into()),
+    }
+  }
+
+  /// Path of the manifest, or of the manifest `instance` referenced by the manifest list.
+  pub fn manifest_path(&self, instance: Option<&str>) -> Result<PathBuf> {
+    match instance {
+      Some(digest) => {
+        let mut path = self.path(digest)?.into_os_string();
+        path.push(".manifest.json");
+        Ok(path.into())
+      }
+      None => Ok(self.dir.join("manifest.json")),
+    }
+  }
+
+  /// Read the manifest, or the manifest `instance`, and its media type.
+  ///
+  /// The directory does not record media types, so they are guessed from the manifest as skopeo
+  /// does: from its `mediaType` field, or else from its shape.
+  pub async fn read_manifest(&self, instance: Option<&str>) -> Result<(Vec<u8>, String)> {
+    let path = self.manifest_path(instance)?;
+    let manifest = match fs::read(&path).await {
+      Ok(manifest) => manifest,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        return Err(Error::BlobNotFound(path.display().to_string()))
+      }
+      Err(e) => return Err(e.into()),
+    };
+    let media_type = guess_media_type(&manifest)?;
+    Ok((manifest, media_type))
+  }
+
+  /// Write the manifest, or the manifest `instance`, and the `version` file.
+  pub async fn write_manifest(&self, manifest: &[u8], instance: Option<&str>) -> Result<()> {
+    fs::create_dir_all(&self.dir).await?;
+    fs::write(self.dir.join("version"), VERSION).await?;
+    fs::write(self.manifest_path(instance)?, manifest).await?;
+    Ok(())
+  }
+
+  /// Fail unless the `version` file is one of a version 1 directory.
+  pub async fn check_version(&self) -> Result<()> {
+    let version = fs::read_to_string(self.dir.join("version")).await?;
+    match version.starts_with("Directory Transport Version: 1.") {
+      true => Ok(()),
+      false => Err(Error::DirTransportVersion(version.trim().to_string())),
+    }
+  }
+}
+
+impl BlobStore for DirTransport {
+  fn has<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, bool> {
+    Box::pin(async move { Ok(fs::try_exists(self.path(digest)?).await?) })
+  }
+
+  fn get<'a>(&'a self, digest: &'a str) -> BlobFuture<'a, BlobChunks<'a>> {
+    Box::pin(async move { blob_store::read_file(&self.path(digest)?, digest).await })
+  }
+
+  fn put<'a>(&'a self, digest: &'a str, data: BlobChunks<'a>) -> BlobFuture<'a, ()> {
+    Box::pin(async move {
+      let path = self.path(digest)?;
+      blob_store::write_file(&path, digest, data, &blob_store::staging_beside(&path)).await
+    })
+  }
+
+  fn reserve(&self, bytes: u64) -> BlobFuture<'_, ()> {
+    Box::pin(async move {
+      std::fs::create_dir_all(&self.dir)?;
+      staging::check_space(&self.dir, bytes)
+    })
+  }
+}
+
+/// Media type of a manifest read from a `dir:` directory.
+fn guess_media_type(manifest: &[u8]) -> Result<String> {
+  let value: Value = serde_json::from_slice(manifest)?;
+  if let Some(media_type) = value["mediaType"].as_str() {
+    return Ok(media_type.to_string());
+  }
+  let media_type = match () {
+    _ if value["schemaVersion"] == 1 => MediaTypes::ManifestV2S1Signed,
+    _ if value.get("manifests").is_some() => MediaTypes::OciImageIndexV1,
+    _ if value.get("config").is_some() => MediaTypes::OciImageManifest,
+    _ => return Err(Error::MediaTypeSniff),
+  };
+  Ok(media_type.to_string())
+}
+
+/// Export an image, manifest list or OCI index to the `dir:` directory `dir`.
+///
+/// Blobs already in the directory are not downloaded again. Layers not selected by `options`
+/// are not written, so the directory is only complete if the layer filter selected every layer.
+pub async fn pull_to_dir(
+  client: &Client,
+  name: &str,
+  reference: &str,
+  dir: &Path,
+  options: &PullOptions,
+) -> Result<StoredImage> {
+  let transport = DirTransport::new(dir);
+  let pulled = copy::pull_blobs(client, name, reference, &transport, options).await?;
+  for (child, child_manifest) in &pulled.children {
+    transport.write_manifest(child_manifest, Some(&child.digest)).await?;
+  }
+  transport.write_manifest(&pulled.manifest, None).await?;
+  Ok(pulled.image)
+}
+
+/// Push the image, manifest list or OCI index of the `dir:` directory `dir` to a repository.
+///
+/// Blobs already present in the destination are not uploaded again. Returns the digest of the
+/// manifest pushed as `dst_reference`.
+pub async fn push_from_dir(dir: &Path, dst: &Client, dst_name: &str, dst_reference: &str) -> Result<String> {
+  dst.ensure_writable()?;
+  let transport = DirTransport::new(dir);
+  transport.check_version().await?;
+  let (manifest, media_type) = transport.read_manifest(None).await?;
+  let mut children = Vec::new();
+  if copy::manifest_kind(&media_type)? == copy::ManifestKind::Index {
+    for child in copy::index_children(&manifest)? {
+      let (child_manifest, _) = transport.read_manifest(Some(&child.digest)).await?;
+      children.push((child, child_manifest));
+    }
+  }
+  copy::push_manifests(
+    &transport,
+    &media_type,
+    &manifest,
+    &children,
+    dst,
+    dst_name,
+    dst_reference,
+  )
+  .await
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case("sha256:abc" => Some("dir/abc".to_string()); "sha256")]
+  #[test_case("sha512:abc" => Some("dir/sha512-abc".to_string()); "other algorithm")]
+  #[test_case("sha256:../abc" => None; "traversal")]
+  fn blob_paths(digest: &str) -> Option<String> {
+    DirTransport::new("dir")
+      .path(digest)
+      .ok()
+      .map(|p| p.display().to_string())
+  }
+
+  #[test_case(br#"{"schemaVersion":2,"mediaType":"application/vnd.docker.distribution.manifest.v2+json"}"# => Some("application/vnd.docker.distribution.manifest.v2+json".to_string()); "media type field")]
+  #[test_case(br#"{"schemaVersion":2,"config":{}}"# => Some("application/vnd.oci.image.manifest.v1+json".to_string()); "oci manifest")]
+  #[test_case(br#"{"schemaVersion":2,"manifests":[]}"# => Some("application/vnd.oci.image.index.v1+json".to_string()); "oci index")]
+  #[test_case(br#"{"schemaVersion":2}"# => None; "unknown")]
+  fn guesses_media_type(manifest: &[u8]) -> Option<String> {
+    guess_media_type(manifest).ok()
+  }
+}
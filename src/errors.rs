@@ -5,12 +5,35 @@
 pub enum Error {
   #[error("Api Error: {0}")]
   Api(#[from] crate::v2::ApiErrors),
+  #[error("tag is immutable: {0}")]
+  TagImmutable(crate::v2::ApiErrors),
+  #[error("tag is locked by a retention policy: {0}")]
+  RetentionLocked(crate::v2::ApiErrors),
+  /// The registry answered 401: credentials are missing, invalid or expired.
+  #[error("authentication required{}: {source}", access(.action, .repository))]
+  Unauthorized {
+    repository: Option<String>,
+    action: Option<String>,
+    source: crate::v2::ApiErrors,
+  },
+  /// The registry answered 403: the credentials are valid, but lack the permission.
+  #[error("access denied{}: {source}", access(.action, .repository))]
+  Denied {
+    repository: Option<String>,
+    action: Option<String>,
+    source: crate::v2::ApiErrors,
+  },
   #[error("base64 decode error")]
   Base64Decode(#[from] base64::DecodeError),
   #[error("header parse error")]
-  HeaderParse(#[from] reqwest::header::ToStrError),
+  HeaderParse(#[from] http::header::ToStrError),
+  #[error("invalid header value")]
+  InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
+  #[error("invalid header name")]
+  InvalidHeaderName(#[from] http::header::InvalidHeaderName),
   #[error("json error")]
   Json(#[from] serde_json::Error),
+  #[cfg(feature = "client")]
   #[error("http transport error: {0}")]
   Reqwest(#[from] reqwest::Error),
   #[error("URI parse error")]
@@ -30,19 +53,20 @@ pub enum Error {
   #[error("missing authentication header {0}")]
   MissingAuthHeader(&'static str),
   #[error("unexpected HTTP status {0}")]
-  UnexpectedHttpStatus(reqwest::StatusCode),
+  UnexpectedHttpStatus(http::StatusCode),
   #[error("invalid auth token '{0}'")]
   InvalidAuthToken(String),
   #[error("API V2 not supported")]
   V2NotSupported,
   #[error("obtained token is invalid")]
   LoginReturnedBadToken,
+  #[cfg(feature = "client")]
   #[error("www-authenticate header parse error")]
   Www(#[from] crate::v2::WwwHeaderParseError),
   #[error("request failed with status {status}")]
-  Client { status: reqwest::StatusCode },
+  Client { status: http::StatusCode },
   #[error("request failed with status {status}")]
-  Server { status: reqwest::StatusCode },
+  Server { status: http::StatusCode },
   #[error("content digest error")]
   ContentDigestParse(#[from] crate::v2::ContentDigestError),
   #[error("no header Content-Type given and no workaround to apply")]
@@ -51,10 +75,171 @@ pub enum Error {
   Manifest(#[from] crate::v2::manifest::ManifestError),
   #[error("reference is invalid")]
   ReferenceParse(#[from] crate::reference::ReferenceParseError),
+  #[error("tag is invalid: {0}")]
+  TagParse(#[from] crate::reference::TagParseError),
   #[error("requested operation requires that credentials are available")]
   NoCredentials,
   #[error("did not receive auth token")]
   NoTokenReceived,
+  #[error("invalid certificate fingerprint '{0}': expected 32 hex-encoded bytes")]
+  InvalidCertificatePin(String),
+  #[error("certificate presented by {host} does not match any pinned fingerprint (got {fingerprint:?})")]
+  CertificatePinMismatch { host: String, fingerprint: Option<String> },
+  #[error("layer {0} was not downloaded")]
+  LayerNotDownloaded(String),
+  #[error("layer {0} would be left out of the archive")]
+  LayerNotArchived(String),
+  #[cfg(feature = "client")]
+  #[error("redirect refused: {0}")]
+  Redirect(#[from] crate::v2::RedirectError),
+  #[error("io error")]
+  Io(#[from] std::io::Error),
+  #[error("missing header {0}")]
+  MissingHeader(&'static str),
+  #[error("{kind} response exceeds the limit of {limit} bytes")]
+  ResponseTooLarge { kind: &'static str, limit: u64 },
+  #[error("{kind} response has unsupported Content-Encoding {encoding}")]
+  UnsupportedContentEncoding { kind: &'static str, encoding: String },
+  #[error("manifest has {count} {kind}, more than the limit of {limit}")]
+  ManifestTooComplex {
+    kind: &'static str,
+    count: usize,
+    limit: usize,
+  },
+  #[error("invalid upload range '{0}'")]
+  UploadRangeParse(String),
+  #[error("invalid content range '{0}'")]
+  ContentRangeParse(String),
+  #[error("upload made no progress past offset {0}")]
+  UploadStalled(u64),
+  #[error("no upload journal configured")]
+  NoUploadJournal,
+  #[error("manifest {digest} is also tagged {}", .tags.join(", "))]
+  SharedDigest { digest: String, tags: Vec<String> },
+  #[error("manifest {digest} could not be deleted, see tag {tag}")]
+  SharedDeletionFailed { digest: String, tag: String },
+  #[error("tag {tag} points to {actual}, not {expected}")]
+  TagMismatch {
+    tag: String,
+    expected: String,
+    actual: String,
+  },
+  #[error("blob {0} not found in the blob store")]
+  BlobNotFound(String),
+  #[error("{needed} bytes needed in {}, only {available} available", .path.display())]
+  InsufficientSpace {
+    path: std::path::PathBuf,
+    needed: u64,
+    available: u64,
+  },
+  #[error("unsupported dir: transport version '{0}'")]
+  DirTransportVersion(String),
+  #[error("image does not match its base: {0}")]
+  BaseMismatch(String),
+  #[error("unsupported layer media type {0}")]
+  UnsupportedLayerMediaType(String),
+  #[error("registry does not support search at {0}")]
+  SearchUnsupported(String),
+  #[error("content trust verification of {gun} failed: {reason}")]
+  TrustVerification { gun: String, reason: String },
+  #[error("tag {tag} of {gun} is not signed")]
+  NotSigned { gun: String, tag: String },
+  #[error("{0} is not a Helm chart")]
+  NotHelmChart(String),
+  #[error("repository {0} not found")]
+  RepositoryNotFound(String),
+  #[error("unknown identity {0}")]
+  UnknownIdentity(String),
+  #[error("the client is read-only")]
+  ReadOnlyClient,
+  /// The deadline set with `Client::with_deadline` passed before the operation completed.
+  #[error("deadline exceeded")]
+  DeadlineExceeded,
+  /// The deadline set with `Client::with_deadline` passed during a copy or a pull, once the
+  /// blobs and manifests listed, which are kept, were at the destination.
+  #[error("deadline exceeded with {} blobs and manifests at the destination", .completed.len())]
+  DeadlineExceededAfter { completed: Vec<String> },
+  /// The registry refused a write to a repository which only serves reads, e.g. a group
+  /// repository of Nexus.
+  #[error("repository{} is read-only: {source}", named(.repository))]
+  ReadOnlyRepository {
+    repository: Option<String>,
+    source: crate::v2::ApiErrors,
+  },
+  /// An index references manifests or blobs the repository it is pushed to does not have.
+  #[cfg(feature = "client")]
+  #[error("index references content missing from {repository}: {}", list(.missing))]
+  MissingContent {
+    repository: String,
+    missing: Vec<crate::copy::MissingContent>,
+  },
+  #[error("promotion rejected: {0}")]
+  PromotionRejected(String),
+  #[error("invalid rate limit header '{0}'")]
+  RateLimitParse(String),
+  #[cfg(feature = "client")]
+  #[error("request signing failed: {0}")]
+  RequestSigning(crate::v2::HookError),
+  #[cfg(feature = "client")]
+  #[error("request interceptor failed: {0}")]
+  Interceptor(crate::v2::HookError),
+  #[cfg(feature = "client")]
+  #[error("device login prompt failed: {0}")]
+  DevicePrompt(crate::v2::HookError),
+  #[cfg(feature = "client")]
+  #[error("credential provider failed: {0}")]
+  CredentialProvider(crate::v2::HookError),
+  #[error("device login failed: {0}")]
+  DeviceLogin(String),
+  /// The registry does not implement an optional feature, see `Config::soft_fail`.
+  #[cfg(feature = "client")]
+  #[error("the registry does not support {0}")]
+  Unsupported(crate::v2::OptionalFeature),
+  /// The table of contents of a layer pulled lazily is missing or does not match the layer.
+  #[error("invalid table of contents in layer {digest}: {reason}")]
+  InvalidToc { digest: String, reason: String },
+  #[error("no regular file {0} in the layer")]
+  LayerFileNotFound(String),
+  #[error("{source} (request id {request_id})")]
+  Correlated { request_id: String, source: Box<Error> },
+}
+
+impl Error {
+  /// Request ID of the operation which failed, see `Client::correlate`.
+  pub fn request_id(&self) -> Option<&str> {
+    match self {
+      Error::Correlated { request_id, .. } => Some(request_id),
+      _ => None,
+    }
+  }
+
+  /// Whether the deadline set with `Client::with_deadline` passed, with or without progress.
+  pub fn is_deadline_exceeded(&self) -> bool {
+    match self {
+      Error::DeadlineExceeded | Error::DeadlineExceededAfter { .. } => true,
+      Error::Correlated { source, .. } => source.is_deadline_exceeded(),
+      _ => false,
+    }
+  }
+}
+
+/// Describe the access which was refused, e.g. " to push library/busybox".
+fn access(action: &Option<String>, repository: &Option<String>) -> String {
+  match (action, repository) {
+    (Some(action), Some(repository)) => format!(" to {} {}", action, repository),
+    (Some(action), None) => format!(" to {}", action),
+    (None, Some(repository)) => format!(" to {}", repository),
+    (None, None) => String::new(),
+  }
+}
+
+fn named(repository: &Option<String>) -> String {
+  repository.as_ref().map(|r| format!(" {}", r)).unwrap_or_default()
+}
+
+#[cfg(feature = "client")]
+fn list(missing: &[crate::copy::MissingContent]) -> String {
+  missing.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
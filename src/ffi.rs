@@ -0,0 +1,369 @@
+//! C bindings for pulling and inspecting images.
+//!
+//! This module exposes a small C ABI over the client, so that other languages can embed it
+//! without a Rust toolchain of their own. Build it as a shared library with
+//! `cargo rustc --release --features ffi --crate-type cdylib`, and declare the functions as:
+//!
+//! ```c
+//! typedef struct DockerRegistryClient DockerRegistryClient;
+//!
+//! int docker_registry_client_new(const char *config_json, DockerRegistryClient **client);
+//! void docker_registry_client_free(DockerRegistryClient *client);
+//! int docker_registry_inspect(const DockerRegistryClient *client, const char *name,
+//!                             const char *reference, char **json);
+//! int docker_registry_list_tags(const DockerRegistryClient *client, const char *name, char **json);
+//! int docker_registry_pull_to_layout(const DockerRegistryClient *client, const char *name,
+//!                                    const char *reference, const char *dir, char **json);
+//! const char *docker_registry_last_error(void);
+//! void docker_registry_string_free(char *s);
+//! ```
+//!
+//! Every string is UTF-8 and NUL-terminated, and structured data is passed as JSON. The client
+//! is configured with an object such as
+//! `{"registry": "quay.io", "insecure": false, "username": "...", "password": "..."}`, where
+//! only `registry` is required. Functions return a [`Status`], `0` on success; on failure the
+//! message of the error is available from `docker_registry_last_error` on the same thread.
+//! Strings returned through `json` are owned by the caller and released with
+//! `docker_registry_string_free`.
+//!
+//! This module is only available with the `ffi` feature.
+
+use std::{
+  cell::RefCell,
+  ffi::{c_char, CStr, CString},
+  panic::{self, AssertUnwindSafe},
+  path::Path,
+  ptr,
+  time::UNIX_EPOCH,
+};
+
+use futures::TryStreamExt;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+  blob_store::FsBlobStore,
+  copy::{add_to_oci_layout, pull_to_store, PullOptions, MANIFEST_MEDIA_TYPES},
+  errors::Error,
+  v2::Client,
+};
+
+/// Result of a call through the C bindings.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+  Ok = 0,
+  /// A pointer was null, a string was not UTF-8 or the configuration was invalid.
+  InvalidArgument = 1,
+  /// The repository, manifest or blob does not exist.
+  NotFound = 2,
+  /// The registry rejected the credentials, or none were given.
+  Unauthorized = 3,
+  /// The registry could not be reached or answered with an error.
+  Registry = 4,
+  /// Writing the image layout failed.
+  Io = 5,
+  /// The library panicked.
+  Panic = 6,
+}
+
+/// Client handle, opaque to C callers.
+#[derive(Debug)]
+pub struct DockerRegistryClient {
+  client: Client,
+  runtime: tokio::runtime::Runtime,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientConfig {
+  registry: String,
+  #[serde(default)]
+  insecure: bool,
+  username: Option<String>,
+  password: Option<String>,
+}
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Failure of a call, with the message reported by `docker_registry_last_error`.
+struct Failure(Status, String);
+
+impl From<Error> for Failure {
+  fn from(error: Error) -> Self {
+    let status = match &error {
+      Error::Client {
+        status: StatusCode::NOT_FOUND,
+      } => Status::NotFound,
+      Error::Client {
+        status: StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN,
+      }
+      | Error::Unauthorized { .. }
+      | Error::Denied { .. }
+      | Error::DeviceLogin(_)
+      | Error::NoCredentials => Status::Unauthorized,
+      Error::Api(errors) => match errors.errors().iter().flatten().next().map(|e| e.code()) {
+        Some("BLOB_UNKNOWN" | "MANIFEST_UNKNOWN" | "NAME_UNKNOWN") => Status::NotFound,
+        Some("UNAUTHORIZED" | "DENIED") => Status::Unauthorized,
+        _ => Status::Registry,
+      },
+      Error::Io(_) => Status::Io,
+      _ => Status::Registry,
+    };
+    Failure(status, error.to_string())
+  }
+}
+
+impl From<serde_json::Error> for Failure {
+  fn from(error: serde_json::Error) -> Self {
+    Failure(Status::InvalidArgument, error.to_string())
+  }
+}
+
+/// Run `f`, recording its error for `docker_registry_last_error` and catching panics.
+fn call<F: FnOnce() -> Result<(), Failure>>(f: F) -> Status {
+  let (status, message) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+    Ok(Ok(())) => (Status::Ok, None),
+    Ok(Err(Failure(status, message))) => (status, Some(message)),
+    Err(_) => (Status::Panic, Some("panic in docker-registry".to_string())),
+  };
+  LAST_ERROR.with(|last| {
+    *last.borrow_mut() = message.map(|m| CString::new(m.replace('\0', " ")).unwrap_or_default());
+  });
+  status
+}
+
+/// Borrow the string `s` from C.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string valid for `'a`.
+unsafe fn borrow_str<'a>(s: *const c_char, what: &str) -> Result<&'a str, Failure> {
+  if s.is_null() {
+    return Err(Failure(Status::InvalidArgument, format!("{} is null", what)));
+  }
+  CStr::from_ptr(s)
+    .to_str()
+    .map_err(|_| Failure(Status::InvalidArgument, format!("{} is not UTF-8", what)))
+}
+
+/// Hand `value` over to C through `out`, as a JSON string.
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+unsafe fn give_json(out: *mut *mut c_char, value: &Value) -> Result<(), Failure> {
+  if out.is_null() {
+    return Err(Failure(Status::InvalidArgument, "output pointer is null".to_string()));
+  }
+  let json = CString::new(value.to_string()).expect("JSON escapes NUL characters");
+  *out = json.into_raw();
+  Ok(())
+}
+
+impl DockerRegistryClient {
+  /// The client, authorized to pull from the repository `name` if the registry requires it.
+  async fn authorized(&self, name: &str) -> crate::errors::Result<Client> {
+    match self.client.auth_challenge().await? {
+      Some(_) => {
+        let scope = format!("repository:{}:pull", name);
+        self.client.clone().authenticate(&[&scope]).await
+      }
+      None => Ok(self.client.clone()),
+    }
+  }
+
+  async fn inspect(&self, name: &str, reference: &str) -> crate::errors::Result<Value> {
+    let client = self.authorized(name).await?;
+    let (manifest, media_type, digest) = client
+      .get_raw_manifest(name, reference, Some(MANIFEST_MEDIA_TYPES))
+      .await?;
+    let manifest: Value = serde_json::from_slice(&manifest)?;
+    let config = match manifest["config"]["digest"].as_str() {
+      Some(digest) => Some(serde_json::from_slice::<Value>(&client.get_blob(name, digest).await?)?),
+      None => None,
+    };
+    let last_modified = client
+      .head_manifest(name, reference)
+      .await?
+      .last_modified
+      .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+      .map(|d| d.as_secs());
+    Ok(json!({
+      "name": name,
+      "reference": reference,
+      "digest": digest,
+      "mediaType": media_type,
+      "lastModified": last_modified,
+      "manifest": manifest,
+      "config": config,
+    }))
+  }
+
+  async fn list_tags(&self, name: &str) -> crate::errors::Result<Value> {
+    let client = self.authorized(name).await?;
+    let tags: Vec<String> = client.get_tags(name, None).try_collect().await?;
+    Ok(json!({ "name": name, "tags": tags }))
+  }
+
+  async fn pull_to_layout(&self, name: &str, reference: &str, dir: &Path) -> crate::errors::Result<Value> {
+    let client = self.authorized(name).await?;
+    let store = FsBlobStore::oci_layout(dir);
+    let image = pull_to_store(&client, name, reference, &store, &PullOptions::default()).await?;
+    add_to_oci_layout(dir, &image.descriptor, image.tag.as_deref())?;
+    Ok(json!({
+      "digest": image.descriptor.digest,
+      "tag": image.tag,
+      "mediaType": image.descriptor.media_type,
+      "layers": image.layers.iter().map(|layer| &layer.digest).collect::<Vec<_>>(),
+    }))
+  }
+}
+
+/// Create a client from a JSON configuration, storing its handle in `client`.
+///
+/// # Safety
+///
+/// `config_json` must be null or a NUL-terminated string, and `client` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn docker_registry_client_new(
+  config_json: *const c_char,
+  client: *mut *mut DockerRegistryClient,
+) -> Status {
+  call(|| {
+    let config: ClientConfig = serde_json::from_str(borrow_str(config_json, "configuration")?)?;
+    if client.is_null() {
+      return Err(Failure(Status::InvalidArgument, "output pointer is null".to_string()));
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+      .enable_all()
+      .build()
+      .map_err(|e| Failure(Status::Io, e.to_string()))?;
+    let handle = DockerRegistryClient {
+      client: Client::configure()
+        .registry(&config.registry)
+        .insecure_registry(config.insecure)
+        .username(config.username)
+        .password(config.password)
+        .build()?,
+      runtime,
+    };
+    *client = Box::into_raw(Box::new(handle));
+    Ok(())
+  })
+}
+
+/// Release a client created by `docker_registry_client_new`.
+///
+/// # Safety
+///
+/// `client` must be null or a handle returned by `docker_registry_client_new` which was not
+/// released yet, and must not be used by another thread.
+#[no_mangle]
+pub unsafe extern "C" fn docker_registry_client_free(client: *mut DockerRegistryClient) {
+  if !client.is_null() {
+    drop(Box::from_raw(client));
+  }
+}
+
+/// Inspect an image, storing its digest, media type, manifest and config as JSON in `json`.
+///
+/// The config is `null` for manifest lists and OCI indexes. `lastModified` is the time the
+/// image was last pushed, in seconds since the Unix epoch, or `null` if the registry does not
+/// report it.
+///
+/// # Safety
+///
+/// `client` must be null or a live handle, `name` and `reference` null or NUL-terminated
+/// strings, and `json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn docker_registry_inspect(
+  client: *const DockerRegistryClient,
+  name: *const c_char,
+  reference: *const c_char,
+  json: *mut *mut c_char,
+) -> Status {
+  call(|| {
+    let client = client
+      .as_ref()
+      .ok_or_else(|| Failure(Status::InvalidArgument, "client is null".to_string()))?;
+    let name = borrow_str(name, "name")?;
+    let reference = borrow_str(reference, "reference")?;
+    let value = client.runtime.block_on(client.inspect(name, reference))?;
+    give_json(json, &value)
+  })
+}
+
+/// List the tags of the repository `name`, storing them as JSON in `json`.
+///
+/// # Safety
+///
+/// `client` must be null or a live handle, `name` null or a NUL-terminated string, and `json`
+/// null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn docker_registry_list_tags(
+  client: *const DockerRegistryClient,
+  name: *const c_char,
+  json: *mut *mut c_char,
+) -> Status {
+  call(|| {
+    let client = client
+      .as_ref()
+      .ok_or_else(|| Failure(Status::InvalidArgument, "client is null".to_string()))?;
+    let name = borrow_str(name, "name")?;
+    let value = client.runtime.block_on(client.list_tags(name))?;
+    give_json(json, &value)
+  })
+}
+
+/// Pull an image, manifest list or OCI index into the OCI image layout in `dir`, storing its
+/// digest, tag, media type and layer digests as JSON in `json`.
+///
+/// If `reference` is a tag, or a tag and a digest as `tag@digest`, the image is tagged with it
+/// in the layout.
+///
+/// # Safety
+///
+/// `client` must be null or a live handle, `name`, `reference` and `dir` null or NUL-terminated
+/// strings, and `json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn docker_registry_pull_to_layout(
+  client: *const DockerRegistryClient,
+  name: *const c_char,
+  reference: *const c_char,
+  dir: *const c_char,
+  json: *mut *mut c_char,
+) -> Status {
+  call(|| {
+    let client = client
+      .as_ref()
+      .ok_or_else(|| Failure(Status::InvalidArgument, "client is null".to_string()))?;
+    let name = borrow_str(name, "name")?;
+    let reference = borrow_str(reference, "reference")?;
+    let dir = Path::new(borrow_str(dir, "directory")?);
+    let value = client.runtime.block_on(client.pull_to_layout(name, reference, dir))?;
+    give_json(json, &value)
+  })
+}
+
+/// The message of the last error on this thread, or null if the last call succeeded.
+///
+/// The string is owned by the library and valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn docker_registry_last_error() -> *const c_char {
+  LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Release a string returned by the library.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned through a `json` argument which was not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn docker_registry_string_free(s: *mut c_char) {
+  if !s.is_null() {
+    drop(CString::from_raw(s));
+  }
+}
@@ -0,0 +1,331 @@
+//! Managing the tags of GitLab container registries with the GitLab API.
+//!
+//! The GitLab container registry does not support deleting tags with the registry API, and
+//! does not report when tags were created. [`GitLabRegistry`] uses the container registry API
+//! of GitLab instead, resolving the registry repositories to their GitLab project, so that
+//! cleanup tooling works against GitLab out of the box.
+//!
+//! The API is served by GitLab itself rather than its registry, e.g. `gitlab.com` for
+//! `registry.gitlab.com`.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   gitlab::{GitLabCleanup, GitLabRegistry},
+//!   v2::Client,
+//! };
+//!
+//! let api = Client::configure()
+//!   .registry("gitlab.com")
+//!   .username(Some("user".to_string()))
+//!   .password(Some("glpat-token".to_string()))
+//!   .build()?;
+//! let gitlab = GitLabRegistry::new(api);
+//! gitlab
+//!   .delete_tags_matching(
+//!     "group/project/app",
+//!     &GitLabCleanup::new(".*").keep_regex("^v.*").keep_n(5),
+//!   )
+//!   .await?;
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use log::trace;
+use reqwest::{
+  header::{self, HeaderMap, HeaderName, HeaderValue},
+  Method, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use url::{form_urlencoded, Position, Url};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::bulk::WorkQueue;
+use crate::{
+  bulk::{run, BulkOptions, BulkReport},
+  errors::{Error, Result},
+  pagination,
+  v2::{Client, RawResponse},
+};
+
+/// Username of the credentials of GitLab CI jobs, whose password is a job token.
+const JOB_TOKEN_USER: &str = "gitlab-ci-token";
+
+/// Container registry API of a GitLab instance.
+#[derive(Debug)]
+pub struct GitLabRegistry {
+  api: Client,
+  auth: Option<(HeaderName, HeaderValue)>,
+}
+
+/// A repository of the GitLab container registry.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct GitLabRepository {
+  /// Identifier of the repository in the GitLab API.
+  pub id: u64,
+  /// Identifier of the project the repository belongs to.
+  pub project_id: u64,
+  /// Full name of the repository in the registry, e.g. `group/project/app`.
+  pub path: String,
+}
+
+/// Details of a tag, as reported by the GitLab API.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct GitLabTag {
+  pub name: String,
+  /// Digest of the manifest the tag points to.
+  #[serde(default)]
+  pub digest: Option<String>,
+  /// Time the tag was pushed, in RFC 3339 format.
+  #[serde(default)]
+  pub created_at: Option<String>,
+  /// Size of the layers of the image, in bytes.
+  #[serde(default)]
+  pub total_size: Option<u64>,
+}
+
+/// Criteria of `GitLabRegistry::delete_tags_matching`, as for the cleanup policies of GitLab.
+#[derive(Clone, Debug)]
+pub struct GitLabCleanup {
+  delete_regex: String,
+  keep_regex: Option<String>,
+  keep_n: Option<u32>,
+  older_than: Option<String>,
+}
+
+impl GitLabCleanup {
+  /// Delete the tags whose name matches `delete_regex`, a regular expression in the RE2 syntax.
+  pub fn new(delete_regex: &str) -> Self {
+    Self {
+      delete_regex: delete_regex.to_string(),
+      keep_regex: None,
+      keep_n: None,
+      older_than: None,
+    }
+  }
+
+  /// Keep the tags whose name matches `regex`, even if they match the deletion expression.
+  pub fn keep_regex(mut self, regex: &str) -> Self {
+    self.keep_regex = Some(regex.to_string());
+    self
+  }
+
+  /// Keep the `n` most recent tags.
+  pub fn keep_n(mut self, n: u32) -> Self {
+    self.keep_n = Some(n);
+    self
+  }
+
+  /// Only delete tags older than `age`, in the syntax of GitLab, e.g. `1h`, `7d` or `1month`.
+  pub fn older_than(mut self, age: &str) -> Self {
+    self.older_than = Some(age.to_string());
+    self
+  }
+}
+
+impl GitLabRegistry {
+  /// Use the GitLab API through `api`, a client configured for the GitLab host.
+  ///
+  /// The credentials of `api` are those used for the registry: a username with a personal,
+  /// project or group access token, or `gitlab-ci-token` with the job token of a CI job. A
+  /// client without credentials sends its requests as they are, e.g. with an OAuth token set
+  /// with `Client::with_bearer_token`.
+  pub fn new(api: Client) -> Self {
+    let auth = api.credentials().and_then(|(user, token)| {
+      let name = match user {
+        JOB_TOKEN_USER => "job-token",
+        _ => "private-token",
+      };
+      Some((HeaderName::from_static(name), HeaderValue::from_str(token).ok()?))
+    });
+    Self { api, auth }
+  }
+
+  /// Find the repository `name` of the registry, e.g. `group/project/app`.
+  ///
+  /// Repositories are named after their project, optionally followed by an image name, so the
+  /// repositories of the longest existing project whose path is a prefix of `name` are searched.
+  pub async fn repository(&self, name: &str) -> Result<GitLabRepository> {
+    let segments: Vec<&str> = name.split('/').collect();
+    for end in (1..=segments.len()).rev() {
+      let project = segments[..end].join("/");
+      let path = format!(
+        "/api/v4/projects/{}/registry/repositories?per_page=100",
+        encode(&project)
+      );
+      let repositories: Vec<GitLabRepository> = match self.get_linked_pages(&path).await {
+        Ok(repositories) => repositories,
+        Err(Error::UnexpectedHttpStatus(StatusCode::NOT_FOUND)) => continue,
+        Err(e) => return Err(e),
+      };
+      // Projects cannot be nested in other projects: shorter paths are groups.
+      return repositories
+        .into_iter()
+        .find(|r| r.path == name)
+        .ok_or_else(|| Error::RepositoryNotFound(name.to_string()));
+    }
+    Err(Error::RepositoryNotFound(name.to_string()))
+  }
+
+  /// Get the details of the tag `tag` of the repository `name`, or `None` if it does not exist.
+  pub async fn tag(&self, name: &str, tag: &str) -> Result<Option<GitLabTag>> {
+    let path = tag_path(&self.repository(name).await?, tag);
+    let res = self.request(Method::GET, &path, HeaderMap::new(), None).await?;
+    match res.status {
+      StatusCode::NOT_FOUND => Ok(None),
+      _ => parse(res).map(Some),
+    }
+  }
+
+  /// Delete the tag `tag` of the repository `name`.
+  ///
+  /// Unlike deleting manifests with the registry API, this does not affect the other tags
+  /// pointing to the same manifest.
+  pub async fn delete_tag(&self, name: &str, tag: &str) -> Result<()> {
+    let repository = self.repository(name).await?;
+    self.delete_repository_tag(&repository, tag).await
+  }
+
+  /// Delete many tags of the repository `name`, reporting the outcome of every deletion.
+  pub async fn delete_tags(&self, name: &str, tags: Vec<String>, options: &BulkOptions) -> Result<BulkReport<String>> {
+    let repository = self.repository(name).await?;
+    let repository = &repository;
+    Ok(
+      run(tags, options, |tag| async move {
+        self.delete_repository_tag(repository, &tag).await
+      })
+      .await,
+    )
+  }
+
+  /// Delete the tags queued in `queue` of the repository `name`, like
+  /// [`delete_tags`](Self::delete_tags) does, journaling every deletion as it completes.
+  ///
+  /// Tags already deleted by a previous run of the queue are not deleted again.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub async fn delete_queued_tags(
+    &self,
+    name: &str,
+    queue: &WorkQueue<String>,
+    options: &BulkOptions,
+  ) -> Result<BulkReport<String>> {
+    self.api.ensure_writable()?;
+    let repository = self.repository(name).await?;
+    let repository = &repository;
+    queue
+      .run(options, |tag| async move {
+        self.delete_repository_tag(repository, &tag).await
+      })
+      .await
+  }
+
+  /// Delete the tags of the repository `name` matching `cleanup`, in bulk.
+  ///
+  /// GitLab deletes the tags in the background, after this returns.
+  pub async fn delete_tags_matching(&self, name: &str, cleanup: &GitLabCleanup) -> Result<()> {
+    self.api.ensure_writable()?;
+    let repository = self.repository(name).await?;
+    let mut form = form_urlencoded::Serializer::new(String::new());
+    form.append_pair("name_regex_delete", &cleanup.delete_regex);
+    if let Some(regex) = &cleanup.keep_regex {
+      form.append_pair("name_regex_keep", regex);
+    }
+    if let Some(n) = cleanup.keep_n {
+      form.append_pair("keep_n", &n.to_string());
+    }
+    if let Some(age) = &cleanup.older_than {
+      form.append_pair("older_than", age);
+    }
+    let mut headers = HeaderMap::new();
+    headers.insert(
+      header::CONTENT_TYPE,
+      HeaderValue::from_static("application/x-www-form-urlencoded"),
+    );
+    let path = format!(
+      "/api/v4/projects/{}/registry/repositories/{}/tags",
+      repository.project_id, repository.id
+    );
+    let res = self
+      .request(Method::DELETE, &path, headers, Some(form.finish().into_bytes()))
+      .await?;
+    match res.status {
+      StatusCode::OK | StatusCode::ACCEPTED => Ok(()),
+      status => Err(Error::UnexpectedHttpStatus(status)),
+    }
+  }
+
+  async fn delete_repository_tag(&self, repository: &GitLabRepository, tag: &str) -> Result<()> {
+    self.api.ensure_writable()?;
+    let res = self
+      .request(Method::DELETE, &tag_path(repository, tag), HeaderMap::new(), None)
+      .await?;
+    match res.status {
+      StatusCode::OK | StatusCode::ACCEPTED | StatusCode::NO_CONTENT => Ok(()),
+      status => Err(Error::UnexpectedHttpStatus(status)),
+    }
+  }
+
+  async fn request(
+    &self,
+    method: Method,
+    path: &str,
+    mut headers: HeaderMap,
+    body: Option<Vec<u8>>,
+  ) -> Result<RawResponse> {
+    trace!("GitLab API: {} {}", method, path);
+    if let Some((name, value)) = &self.auth {
+      headers.insert(name.clone(), value.clone());
+    }
+    self.api.raw_request(method, path, headers, body).await
+  }
+
+  /// GET the JSON list at `path`, following the `next` links of the `Link` header.
+  async fn get_linked_pages<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<T>> {
+    // Raw requests are relative to the API: only the path and the query of links are used.
+    let base = Url::parse("http://gitlab.invalid/")?;
+    let mut items = Vec::new();
+    let mut path = Some(path.to_string());
+    while let Some(p) = path.take() {
+      let res = self.request(Method::GET, &p, HeaderMap::new(), None).await?;
+      let next = res
+        .headers
+        .get(header::LINK)
+        .and_then(|link| link.to_str().ok())
+        .and_then(|link| pagination::next_url(link, &base.join(&p).ok()?));
+      items.extend(parse::<Vec<T>>(res)?);
+      path = next.map(|url| url[Position::BeforePath..].to_string());
+    }
+    Ok(items)
+  }
+}
+
+fn tag_path(repository: &GitLabRepository, tag: &str) -> String {
+  format!(
+    "/api/v4/projects/{}/registry/repositories/{}/tags/{}",
+    repository.project_id,
+    repository.id,
+    encode(tag)
+  )
+}
+
+fn parse<T: DeserializeOwned>(res: RawResponse) -> Result<T> {
+  if !res.status.is_success() {
+    return Err(Error::UnexpectedHttpStatus(res.status));
+  }
+  Ok(serde_json::from_slice(&res.body)?)
+}
+
+/// Encode `segment` as a single path segment, including its slashes.
+fn encode(segment: &str) -> String {
+  form_urlencoded::byte_serialize(segment.as_bytes()).collect()
+}
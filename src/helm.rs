@@ -0,0 +1,178 @@
+//! Pulling Helm charts stored in OCI registries.
+//!
+//! Helm stores a chart as an image whose config is the content of its `Chart.yaml` in JSON,
+//! with a layer holding the packaged chart and, for signed charts, a layer holding its
+//! provenance file. [`Client::pull_helm_chart`] returns them as a [`HelmChart`], with the
+//! metadata of the chart typed as [`ChartMetadata`].
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::v2::Client;
+//!
+//! let client = Client::configure().registry("ghcr.io").build()?;
+//! let chart = client.pull_helm_chart("org/charts/nginx", "1.2.3").await?;
+//! println!("{} {}", chart.metadata.name, chart.metadata.version);
+//! std::fs::write("nginx-1.2.3.tgz", &chart.chart)?;
+//! if let Some(provenance) = &chart.provenance {
+//!   std::fs::write("nginx-1.2.3.tgz.prov", provenance)?;
+//! }
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+  copy::{descriptor, layers_of, manifest_kind, parse_media_type, ManifestKind, MANIFEST_MEDIA_TYPES},
+  errors::{Error, Result},
+  v2::{manifest::ManifestError, sha256_digest, Client, Descriptor},
+};
+
+/// Media type of the config of Helm charts.
+pub const CONFIG_MEDIA_TYPE: &str = "application/vnd.cncf.helm.config.v1+json";
+/// Media type of the layer holding the packaged chart.
+pub const CHART_MEDIA_TYPE: &str = "application/vnd.cncf.helm.chart.content.v1.tar+gzip";
+/// Media type of the layer holding the provenance file of a signed chart.
+pub const PROVENANCE_MEDIA_TYPE: &str = "application/vnd.cncf.helm.chart.provenance.v1.prov";
+
+/// The metadata of a chart, from its `Chart.yaml`.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartMetadata {
+  pub name: String,
+  pub version: String,
+  /// Version of the chart API, `v1` or `v2`.
+  #[serde(default)]
+  pub api_version: Option<String>,
+  /// Version of the application packaged by the chart.
+  #[serde(default)]
+  pub app_version: Option<String>,
+  #[serde(default)]
+  pub description: Option<String>,
+  /// Type of the chart, `application` or `library`.
+  #[serde(default, rename = "type")]
+  pub chart_type: Option<String>,
+  /// Versions of Kubernetes the chart supports, as a semantic version range.
+  #[serde(default)]
+  pub kube_version: Option<String>,
+  #[serde(default)]
+  pub keywords: Vec<String>,
+  #[serde(default)]
+  pub home: Option<String>,
+  #[serde(default)]
+  pub sources: Vec<String>,
+  #[serde(default)]
+  pub icon: Option<String>,
+  #[serde(default)]
+  pub maintainers: Vec<ChartMaintainer>,
+  #[serde(default)]
+  pub dependencies: Vec<ChartDependency>,
+  #[serde(default)]
+  pub deprecated: bool,
+  #[serde(default)]
+  pub annotations: BTreeMap<String, String>,
+}
+
+/// A maintainer of a chart.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct ChartMaintainer {
+  pub name: String,
+  #[serde(default)]
+  pub email: Option<String>,
+  #[serde(default)]
+  pub url: Option<String>,
+}
+
+/// A chart a chart depends on.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct ChartDependency {
+  pub name: String,
+  /// Version of the dependency, as a semantic version range.
+  #[serde(default)]
+  pub version: Option<String>,
+  /// Repository of the dependency, e.g. `oci://registry.example.com/charts`.
+  #[serde(default)]
+  pub repository: Option<String>,
+  #[serde(default)]
+  pub condition: Option<String>,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  #[serde(default)]
+  pub alias: Option<String>,
+}
+
+/// A chart pulled by [`Client::pull_helm_chart`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HelmChart {
+  /// Digest of the manifest of the chart.
+  pub digest: String,
+  pub metadata: ChartMetadata,
+  /// The packaged chart, as a gzip-compressed tar archive.
+  pub chart: Vec<u8>,
+  /// The provenance file of the chart, if it is signed.
+  pub provenance: Option<String>,
+}
+
+impl Client {
+  /// Pull the Helm chart `reference` of the repository `name`, with its provenance if the
+  /// chart is signed.
+  ///
+  /// Fails with `Error::NotHelmChart` if the image is not a Helm chart.
+  pub async fn pull_helm_chart(&self, name: &str, reference: &str) -> Result<HelmChart> {
+    let (digest, metadata, layers) = self.fetch_helm_chart(name, reference).await?;
+    let layer = |media_type: &str| layers.iter().find(|l| l.media_type == media_type);
+    let chart = layer(CHART_MEDIA_TYPE).ok_or_else(|| ManifestError::Invalid("missing chart layer".to_string()))?;
+    let chart = self.get_blob(name, &chart.digest).await?;
+    let provenance = match layer(PROVENANCE_MEDIA_TYPE) {
+      Some(layer) => Some(String::from_utf8(self.get_blob(name, &layer.digest).await?)?),
+      None => None,
+    };
+    Ok(HelmChart {
+      digest,
+      metadata,
+      chart,
+      provenance,
+    })
+  }
+
+  /// Get the metadata of the Helm chart `reference` of the repository `name`, without pulling
+  /// the chart.
+  pub async fn helm_chart_metadata(&self, name: &str, reference: &str) -> Result<ChartMetadata> {
+    Ok(self.fetch_helm_chart(name, reference).await?.1)
+  }
+
+  /// Fetch the manifest and the config of a chart, returning the digest of the manifest, the
+  /// metadata of the chart and its layers.
+  async fn fetch_helm_chart(&self, name: &str, reference: &str) -> Result<(String, ChartMetadata, Vec<Descriptor>)> {
+    let (manifest, media_type, digest) = self
+      .get_raw_manifest(name, reference, Some(MANIFEST_MEDIA_TYPES))
+      .await?;
+    if manifest_kind(&media_type)? != ManifestKind::Image {
+      return Err(Error::UnsupportedMediaType(parse_media_type(&media_type)?));
+    }
+    let value: Value = serde_json::from_slice(&manifest)?;
+    let config = descriptor(&value["config"])?;
+    if config.media_type != CONFIG_MEDIA_TYPE {
+      return Err(Error::NotHelmChart(format!("{}:{}", name, reference)));
+    }
+    let layers = layers_of(&value)?.iter().map(descriptor).collect::<Result<_>>()?;
+    let metadata = serde_json::from_slice(&self.get_blob(name, &config.digest).await?)?;
+    Ok((digest.unwrap_or_else(|| sha256_digest(&manifest)), metadata, layers))
+  }
+}
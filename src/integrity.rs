@@ -0,0 +1,302 @@
+//! Auditing the integrity of the content stored in a repository.
+//!
+//! After a storage migration or a backend incident, [`Client::verify_repository`] walks every
+//! tag of a repository, the manifests they point to and every blob those reference, and reports
+//! content which is missing or does not match its descriptor.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   integrity::{BlobCheck, VerifyOptions},
+//!   v2::Client,
+//! };
+//!
+//! let client = Client::configure().registry("localhost:5000").build()?;
+//! let options = VerifyOptions {
+//!   blob_check: BlobCheck::Download,
+//!   ..Default::default()
+//! };
+//! let report = client.verify_repository("etcd", &options).await?;
+//! println!("{} blobs checked", report.blobs);
+//! for corruption in &report.corruptions {
+//!   println!("{:?}", corruption);
+//! }
+//! for (digest, e) in &report.errors {
+//!   println!("{}: cannot verify: {}", digest, e);
+//! }
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use futures::{StreamExt, TryStreamExt};
+use log::trace;
+use serde_json::Value;
+
+use crate::{
+  bulk::{self, BulkOptions},
+  copy::{descriptor, index_children, is_foreign, layers_of, manifest_kind, ManifestKind, MANIFEST_MEDIA_TYPES},
+  errors::{Error, Result},
+  v2::{digest_like, sha256_digest, Client, Descriptor},
+};
+
+/// How blobs are checked by [`Client::verify_repository`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlobCheck {
+  /// Check that blobs exist and have the size of their descriptor, with `HEAD` requests.
+  #[default]
+  Head,
+  /// Download blobs and check their content against their digest and size.
+  Download,
+}
+
+/// Options of [`Client::verify_repository`].
+#[derive(Clone, Debug, Default)]
+pub struct VerifyOptions {
+  /// Concurrency of blob checks; `stop_on_error` is ignored.
+  pub bulk: BulkOptions,
+  pub blob_check: BlobCheck,
+  /// Tags to verify, instead of every tag of the repository.
+  pub tags: Option<Vec<String>>,
+}
+
+/// Content of a repository which is missing or does not match its descriptor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Corruption {
+  /// A listed tag, or a manifest referenced by an index, cannot be found.
+  MissingManifest { reference: String },
+  /// A manifest does not match the digest it is served or referenced by.
+  CorruptManifest {
+    reference: String,
+    expected: String,
+    actual: String,
+  },
+  /// A config or layer blob referenced by `manifest` cannot be found.
+  MissingBlob { digest: String, manifest: String },
+  /// A blob does not have the size of its descriptor in `manifest`.
+  SizeMismatch {
+    digest: String,
+    manifest: String,
+    expected: u64,
+    actual: u64,
+  },
+  /// A downloaded blob does not match its digest.
+  CorruptBlob { digest: String, manifest: String },
+}
+
+/// Outcome of [`Client::verify_repository`].
+#[derive(Debug, Default)]
+pub struct RepositoryReport {
+  /// Number of tags verified.
+  pub tags: usize,
+  /// Number of distinct manifests verified.
+  pub manifests: usize,
+  /// Number of distinct blobs verified.
+  pub blobs: usize,
+  pub corruptions: Vec<Corruption>,
+  /// Manifests or blobs which could not be verified, e.g. because of network errors.
+  pub errors: Vec<(String, Error)>,
+}
+
+impl RepositoryReport {
+  /// Whether everything was verified and no corruption was found.
+  pub fn is_intact(&self) -> bool {
+    self.corruptions.is_empty() && self.errors.is_empty()
+  }
+}
+
+impl Client {
+  /// Verify the manifests and blobs of every tag of the repository `name`.
+  ///
+  /// Manifests are downloaded and checked against the digest they are served or referenced by;
+  /// manifest lists and OCI indexes are verified together with every manifest they reference.
+  /// Blobs shared by several manifests are checked once, as configured by
+  /// [`VerifyOptions::blob_check`]. Foreign layers are not checked, registries do not store them.
+  ///
+  /// Only listing the tags fails the whole verification: other errors are reported in
+  /// [`RepositoryReport::errors`].
+  pub async fn verify_repository(&self, name: &str, options: &VerifyOptions) -> Result<RepositoryReport> {
+    let tags = match &options.tags {
+      Some(tags) => tags.clone(),
+      None => self.get_tags(name, None).try_collect().await?,
+    };
+    let mut report = RepositoryReport {
+      tags: tags.len(),
+      ..Default::default()
+    };
+
+    // Blobs to check, with the first manifest referencing them.
+    let mut blobs = BTreeMap::new();
+    let mut verified = Vec::new();
+    let mut pending: Vec<(String, Option<String>)> = tags.into_iter().map(|tag| (tag, None)).collect();
+    while let Some((reference, expected)) = pending.pop() {
+      let checked = self
+        .verify_manifest(name, &reference, expected.as_deref(), &mut report.corruptions)
+        .await;
+      let (manifest, media_type, digest) = match checked {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => continue,
+        Err(e) => {
+          report.errors.push((reference, e));
+          continue;
+        }
+      };
+      if verified.contains(&digest) {
+        continue;
+      }
+      verified.push(digest.clone());
+
+      let referenced = match manifest_kind(&media_type) {
+        Ok(ManifestKind::Image) => image_blobs(&manifest),
+        Ok(ManifestKind::Index) => index_children(&manifest).map(|children| {
+          let children = children
+            .into_iter()
+            .map(|child| (child.digest.clone(), Some(child.digest)));
+          pending.extend(children);
+          Vec::new()
+        }),
+        Err(e) => Err(e),
+      };
+      match referenced {
+        Ok(referenced) => {
+          for blob in referenced {
+            blobs.entry(blob.digest.clone()).or_insert((blob, digest.clone()));
+          }
+        }
+        Err(e) => report.errors.push((digest, e)),
+      }
+    }
+    report.manifests = verified.len();
+    report.blobs = blobs.len();
+
+    let check = options.blob_check;
+    let bulk_options = BulkOptions {
+      stop_on_error: false,
+      ..options.bulk.clone()
+    };
+    let checked = bulk::run(
+      blobs.into_values().collect(),
+      &bulk_options,
+      |(blob, manifest)| async move { self.verify_blob(name, &blob, &manifest, check).await },
+    )
+    .await;
+    for item in checked.items {
+      match item.result {
+        Ok(corruption) => report.corruptions.extend(corruption),
+        Err(e) => report.errors.push((item.item.0.digest, e)),
+      }
+    }
+    Ok(report)
+  }
+
+  /// Fetch a manifest and check it against `expected`, or the digest it is served with.
+  ///
+  /// Returns the manifest, its media type and its digest, or `None` if it is missing or corrupt.
+  async fn verify_manifest(
+    &self,
+    name: &str,
+    reference: &str,
+    expected: Option<&str>,
+    corruptions: &mut Vec<Corruption>,
+  ) -> Result<Option<(Vec<u8>, String, String)>> {
+    let fetched = self.get_raw_manifest(name, reference, Some(MANIFEST_MEDIA_TYPES)).await;
+    let (manifest, media_type, served) = match fetched {
+      Ok(fetched) => fetched,
+      Err(Error::Api(e)) if e.errors().iter().flatten().any(|e| e.code() == "MANIFEST_UNKNOWN") => {
+        corruptions.push(Corruption::MissingManifest {
+          reference: reference.to_string(),
+        });
+        return Ok(None);
+      }
+      Err(e) => return Err(e),
+    };
+    trace!("Verifying manifest {}", reference);
+
+    // Without a digest to check against, e.g. for a tag without `Docker-Content-Digest`, the
+    // manifest cannot be verified but its blobs still are.
+    let expected = match expected.map(str::to_string).or(served) {
+      Some(expected) => expected,
+      None => {
+        let digest = sha256_digest(&manifest);
+        return Ok(Some((manifest, media_type, digest)));
+      }
+    };
+    let actual = digest_like(&expected, &manifest);
+    if actual != expected {
+      corruptions.push(Corruption::CorruptManifest {
+        reference: reference.to_string(),
+        expected,
+        actual,
+      });
+      return Ok(None);
+    }
+    Ok(Some((manifest, media_type, expected)))
+  }
+
+  async fn verify_blob(
+    &self,
+    name: &str,
+    blob: &Descriptor,
+    manifest: &str,
+    check: BlobCheck,
+  ) -> Result<Option<Corruption>> {
+    let (digest, manifest) = (blob.digest.clone(), manifest.to_string());
+    let size = match check {
+      BlobCheck::Head => self.get_blob_size(name, &blob.digest).await?,
+      BlobCheck::Download => {
+        trace!("Downloading blob {}", blob.digest);
+        let chunks = match self.get_blob_stream(name, &blob.digest).await {
+          Ok(chunks) => chunks,
+          Err(Error::Api(e)) if e.errors().iter().flatten().any(|e| e.code() == "BLOB_UNKNOWN") => {
+            return Ok(Some(Corruption::MissingBlob { digest, manifest }))
+          }
+          Err(e) => return Err(e),
+        };
+        futures::pin_mut!(chunks);
+        let mut size = 0;
+        while let Some(chunk) = chunks.next().await {
+          match chunk {
+            Ok(chunk) => size += chunk.len() as u64,
+            Err(Error::ContentDigestParse(_)) => return Ok(Some(Corruption::CorruptBlob { digest, manifest })),
+            Err(e) => return Err(e),
+          }
+        }
+        Some(size)
+      }
+    };
+    Ok(match size {
+      None => Some(Corruption::MissingBlob { digest, manifest }),
+      Some(actual) if actual != blob.size => Some(Corruption::SizeMismatch {
+        digest,
+        manifest,
+        expected: blob.size,
+        actual,
+      }),
+      Some(_) => None,
+    })
+  }
+}
+
+/// The config and the layers of an image manifest, but foreign layers.
+fn image_blobs(manifest: &[u8]) -> Result<Vec<Descriptor>> {
+  let value: Value = serde_json::from_slice(manifest)?;
+  let mut blobs = vec![descriptor(&value["config"])?];
+  for layer in layers_of(&value)? {
+    let layer = descriptor(layer)?;
+    if !is_foreign(&layer) {
+      blobs.push(layer);
+    }
+  }
+  Ok(blobs)
+}
@@ -0,0 +1,612 @@
+//! Registry inventory export.
+//!
+//! This module walks a registry catalog, the tags of every repository and the
+//! manifests they point to, producing a serializable [`Inventory`] suitable for
+//! audits and chargeback tooling.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   inventory::{Inventory, InventoryOptions},
+//!   v2::Client,
+//! };
+//!
+//! let client = Client::configure().registry("localhost:5000").build()?;
+//! let options = InventoryOptions::default();
+//! let inventory = Inventory::collect(&client, &options).await?;
+//!
+//! // Later on, only manifests whose digest changed are fetched again.
+//! let inventory = inventory.refresh(&client, &options).await?;
+//! println!("{}", serde_json::to_string_pretty(&inventory)?);
+//!
+//! // Find out where the storage goes.
+//! let report = inventory.storage_report(10);
+//! println!("registry uses {} bytes", report.total_bytes);
+//!
+//! // Find tags which were not pushed for 90 days, if the registry reports it.
+//! let cutoff = inventory
+//!   .collected_at
+//!   .unwrap_or_default()
+//!   .saturating_sub(90 * 24 * 3600);
+//! let stale = inventory
+//!   .entries
+//!   .iter()
+//!   .filter(|e| e.last_modified.is_some_and(|t| t < cutoff));
+//! println!("{} stale tags", stale.count());
+//!
+//! // Audit what changed since a snapshot saved earlier.
+//! let old: Inventory =
+//!   serde_json::from_reader(std::fs::File::open("inventory.json")?)?;
+//! let changes = docker_registry::inventory::diff(&old, &inventory);
+//! for change in &changes.changed_tags {
+//!   println!("{}:{} was retagged", change.repository, change.tag);
+//! }
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
+  time::UNIX_EPOCH,
+};
+
+use futures::{stream, StreamExt, TryStreamExt};
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  errors::{Error, Result},
+  v2::{manifest::Manifest, system_now, Client},
+};
+
+/// Options controlling how an inventory is collected.
+#[derive(Clone, Debug)]
+pub struct InventoryOptions {
+  /// Maximum number of manifests fetched concurrently.
+  pub concurrency: usize,
+  /// Page size requested when listing the catalog and tags.
+  pub page_size: Option<u32>,
+  /// Whether to fetch the manifests referenced by manifest lists, to account for their blobs.
+  pub expand_manifest_lists: bool,
+}
+
+impl Default for InventoryOptions {
+  fn default() -> Self {
+    Self {
+      concurrency: 8,
+      page_size: None,
+      expand_manifest_lists: true,
+    }
+  }
+}
+
+/// Snapshot of the images available in a registry.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Inventory {
+  pub entries: Vec<InventoryEntry>,
+  /// Time the inventory was collected at, in seconds since the Unix epoch.
+  #[serde(default)]
+  pub collected_at: Option<u64>,
+}
+
+/// A single tagged image in an [`Inventory`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct InventoryEntry {
+  pub repository: String,
+  pub tag: String,
+  /// Manifest digest, if the registry reported one.
+  pub digest: Option<String>,
+  /// Platforms as `os/architecture[/variant]`, or the bare architecture when the OS is unknown.
+  pub platforms: Vec<String>,
+  /// Size in bytes: config and layers for images, referenced manifests for manifest lists.
+  pub size: Option<u64>,
+  /// Creation timestamp from the image config, if available.
+  pub created: Option<String>,
+  /// Time the tag was last pushed, in seconds since the Unix epoch, if the registry reports it.
+  #[serde(default)]
+  pub last_modified: Option<u64>,
+  /// Blobs stored for this image, including manifests referenced by a manifest list.
+  #[serde(default)]
+  pub blobs: Vec<BlobUsage>,
+}
+
+/// A blob and its size in bytes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BlobUsage {
+  pub digest: String,
+  pub size: u64,
+}
+
+impl Inventory {
+  /// Collect a full inventory of the registry.
+  pub async fn collect(client: &Client, options: &InventoryOptions) -> Result<Self> {
+    Self::default().refresh(client, options).await
+  }
+
+  /// Collect a new inventory, reusing entries from `self` whose manifest digest did not change.
+  ///
+  /// Digests are resolved with a cheap `HEAD` request; only manifests with a new digest are downloaded.
+  pub async fn refresh(&self, client: &Client, options: &InventoryOptions) -> Result<Self> {
+    let known: HashMap<(&str, &str), &InventoryEntry> = self
+      .entries
+      .iter()
+      .filter_map(|e| Some(((e.repository.as_str(), e.digest.as_deref()?), e)))
+      .collect();
+
+    let mut images = Vec::new();
+    let repositories: Vec<String> = client.get_catalog(options.page_size).try_collect().await?;
+    for repository in repositories {
+      let tags: Vec<String> = client.get_tags(&repository, options.page_size).try_collect().await?;
+      images.extend(tags.into_iter().map(|tag| (repository.clone(), tag)));
+    }
+
+    let entries = stream::iter(images)
+      .map(|(repository, tag)| {
+        let known = &known;
+        async move {
+          let head = client.head_manifest(&repository, &tag).await?;
+          let last_modified = head
+            .last_modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+          if let Some(entry) = head
+            .digest
+            .as_deref()
+            .and_then(|d| known.get(&(repository.as_str(), d)))
+          {
+            trace!("Reusing inventory entry for {}:{}", repository, tag);
+            return Ok(InventoryEntry {
+              tag,
+              last_modified,
+              ..(*entry).clone()
+            });
+          }
+
+          let (manifest, digest) = client.get_manifest_and_ref(&repository, &tag).await?;
+          let blobs = blobs(client, &repository, &manifest, options.expand_manifest_lists).await?;
+          Ok::<_, Error>(InventoryEntry {
+            platforms: platforms(&manifest),
+            size: size(&manifest),
+            created: created(&manifest),
+            last_modified,
+            blobs,
+            repository,
+            tag,
+            digest,
+          })
+        }
+      })
+      .buffered(options.concurrency.max(1))
+      .try_collect()
+      .await?;
+
+    Ok(Self {
+      entries,
+      collected_at: system_now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()),
+    })
+  }
+}
+
+/// Changes between two inventories, as computed by [`diff`].
+///
+/// Every list is sorted by repository, then by tag.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct InventoryDiff {
+  /// Collection time of the old inventory.
+  pub old_collected_at: Option<u64>,
+  /// Collection time of the new inventory.
+  pub new_collected_at: Option<u64>,
+  /// Repositories with tags in the new inventory only.
+  pub added_repositories: Vec<String>,
+  /// Repositories with tags in the old inventory only.
+  pub removed_repositories: Vec<String>,
+  /// Tags in the new inventory only, including the tags of added repositories.
+  pub added_tags: Vec<TagChange>,
+  /// Tags in the old inventory only, including the tags of removed repositories.
+  pub removed_tags: Vec<TagChange>,
+  /// Tags pointing to a different manifest digest in the new inventory.
+  pub changed_tags: Vec<TagChange>,
+}
+
+impl InventoryDiff {
+  /// Whether the inventories have the same tags, pointing to the same digests.
+  pub fn is_empty(&self) -> bool {
+    self.added_repositories.is_empty()
+      && self.removed_repositories.is_empty()
+      && self.added_tags.is_empty()
+      && self.removed_tags.is_empty()
+      && self.changed_tags.is_empty()
+  }
+}
+
+/// A tag added, removed or moved between two inventories.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TagChange {
+  pub repository: String,
+  pub tag: String,
+  /// Digest in the old inventory, `None` for added tags.
+  pub old_digest: Option<String>,
+  /// Digest in the new inventory, `None` for removed tags.
+  pub new_digest: Option<String>,
+}
+
+/// Compare two inventories of the same registry, e.g. snapshots saved as JSON at different times.
+///
+/// Repositories are only known through their tags: a repository without tags does not appear in
+/// an inventory, so emptying a repository reports it as removed.
+pub fn diff(old: &Inventory, new: &Inventory) -> InventoryDiff {
+  let tags = |inventory: &Inventory| -> BTreeMap<(String, String), Option<String>> {
+    inventory
+      .entries
+      .iter()
+      .map(|e| ((e.repository.clone(), e.tag.clone()), e.digest.clone()))
+      .collect()
+  };
+  let (old_tags, new_tags) = (tags(old), tags(new));
+
+  let mut changes = InventoryDiff {
+    old_collected_at: old.collected_at,
+    new_collected_at: new.collected_at,
+    ..Default::default()
+  };
+  for ((repository, tag), old_digest) in &old_tags {
+    let change = |new_digest: Option<&String>| TagChange {
+      repository: repository.clone(),
+      tag: tag.clone(),
+      old_digest: old_digest.clone(),
+      new_digest: new_digest.cloned(),
+    };
+    match new_tags.get(&(repository.clone(), tag.clone())) {
+      None => changes.removed_tags.push(change(None)),
+      Some(new_digest) if new_digest != old_digest => changes.changed_tags.push(change(new_digest.as_ref())),
+      Some(_) => {}
+    }
+  }
+  changes.added_tags = new_tags
+    .iter()
+    .filter(|(key, _)| !old_tags.contains_key(*key))
+    .map(|((repository, tag), new_digest)| TagChange {
+      repository: repository.clone(),
+      tag: tag.clone(),
+      old_digest: None,
+      new_digest: new_digest.clone(),
+    })
+    .collect();
+
+  let repositories = |tags: &BTreeMap<(String, String), Option<String>>| -> BTreeSet<String> {
+    tags.keys().map(|(repository, _)| repository.clone()).collect()
+  };
+  let (old_repositories, new_repositories) = (repositories(&old_tags), repositories(&new_tags));
+  changes.added_repositories = new_repositories.difference(&old_repositories).cloned().collect();
+  changes.removed_repositories = old_repositories.difference(&new_repositories).cloned().collect();
+  changes
+}
+
+/// Deduplicated blob storage usage, as computed by [`Inventory::storage_report`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StorageReport {
+  /// Bytes used by all distinct blobs in the registry.
+  pub total_bytes: u64,
+  /// Per-repository usage, largest first.
+  pub repositories: Vec<RepositoryUsage>,
+  /// Largest distinct blobs (layers, configs and child manifests), largest first.
+  pub largest_blobs: Vec<SharedBlobUsage>,
+  /// Largest distinct images, largest first.
+  pub largest_images: Vec<ImageUsage>,
+}
+
+/// Storage used by a single repository.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RepositoryUsage {
+  pub repository: String,
+  /// Bytes used by the distinct blobs referenced from this repository.
+  pub bytes: u64,
+  /// Bytes used by blobs referenced only from this repository, which deleting it would free.
+  pub exclusive_bytes: u64,
+}
+
+/// A blob and the repositories referencing it.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SharedBlobUsage {
+  pub digest: String,
+  pub size: u64,
+  pub repositories: Vec<String>,
+}
+
+/// Storage used by a single image, identified by its manifest digest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ImageUsage {
+  pub repository: String,
+  pub digest: Option<String>,
+  pub tags: Vec<String>,
+  /// Bytes used by the distinct blobs of the image.
+  pub bytes: u64,
+}
+
+impl Inventory {
+  /// Compute deduplicated storage usage, keeping the `top_n` largest blobs and images.
+  ///
+  /// Blobs shared by several images or repositories are only accounted once in each total.
+  pub fn storage_report(&self, top_n: usize) -> StorageReport {
+    let mut blobs: HashMap<&str, (u64, BTreeSet<&str>)> = HashMap::new();
+    let mut images: HashMap<(&str, Option<&str>), ImageUsage> = HashMap::new();
+
+    for entry in &self.entries {
+      for blob in &entry.blobs {
+        blobs
+          .entry(blob.digest.as_str())
+          .or_insert_with(|| (blob.size, BTreeSet::new()))
+          .1
+          .insert(entry.repository.as_str());
+      }
+
+      let image = images
+        .entry((entry.repository.as_str(), entry.digest.as_deref()))
+        .or_insert_with(|| ImageUsage {
+          repository: entry.repository.clone(),
+          digest: entry.digest.clone(),
+          tags: Vec::new(),
+          bytes: distinct_size(&entry.blobs),
+        });
+      image.tags.push(entry.tag.clone());
+    }
+
+    let mut repositories: HashMap<&str, RepositoryUsage> = HashMap::new();
+    for (size, repos) in blobs.values() {
+      for repo in repos {
+        let usage = repositories.entry(repo).or_insert_with(|| RepositoryUsage {
+          repository: repo.to_string(),
+          ..Default::default()
+        });
+        usage.bytes += size;
+        if repos.len() == 1 {
+          usage.exclusive_bytes += size;
+        }
+      }
+    }
+
+    let mut repositories: Vec<_> = repositories.into_values().collect();
+    repositories.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.repository.cmp(&b.repository)));
+
+    let mut largest_blobs: Vec<_> = blobs
+      .iter()
+      .map(|(digest, (size, repos))| SharedBlobUsage {
+        digest: digest.to_string(),
+        size: *size,
+        repositories: repos.iter().map(|r| r.to_string()).collect(),
+      })
+      .collect();
+    largest_blobs.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.digest.cmp(&b.digest)));
+    largest_blobs.truncate(top_n);
+
+    let mut largest_images: Vec<_> = images.into_values().collect();
+    largest_images.sort_by(|a, b| {
+      b.bytes
+        .cmp(&a.bytes)
+        .then_with(|| a.repository.cmp(&b.repository))
+        .then_with(|| a.digest.cmp(&b.digest))
+    });
+    largest_images.truncate(top_n);
+
+    StorageReport {
+      total_bytes: blobs.values().map(|(size, _)| size).sum(),
+      repositories,
+      largest_blobs,
+      largest_images,
+    }
+  }
+}
+
+fn distinct_size(blobs: &[BlobUsage]) -> u64 {
+  let mut seen = BTreeSet::new();
+  blobs
+    .iter()
+    .filter(|b| seen.insert(b.digest.as_str()))
+    .map(|b| b.size)
+    .sum()
+}
+
+async fn blobs(client: &Client, repository: &str, manifest: &Manifest, expand: bool) -> Result<Vec<BlobUsage>> {
+  let mut blobs = Vec::new();
+  match manifest {
+    // Schema 1 and custom manifests do not record blob sizes.
+    Manifest::S1Signed(_) | Manifest::Custom(_) => {}
+    Manifest::S2(m) => blobs.extend(
+      m.blob_sizes()
+        .into_iter()
+        .map(|(digest, size)| BlobUsage { digest, size }),
+    ),
+    Manifest::ML(m) => {
+      for mo in m.manifests() {
+        blobs.push(BlobUsage {
+          digest: mo.digest(),
+          size: mo.size(),
+        });
+        if expand {
+          if let Manifest::S2(child) = client.get_manifest(repository, &mo.digest).await? {
+            blobs.extend(
+              child
+                .blob_sizes()
+                .into_iter()
+                .map(|(digest, size)| BlobUsage { digest, size }),
+            );
+          }
+        }
+      }
+    }
+  }
+  Ok(blobs)
+}
+
+fn platforms(manifest: &Manifest) -> Vec<String> {
+  match manifest {
+    Manifest::S1Signed(m) => vec![m.architecture.clone()],
+    Manifest::S2(m) => match m.os() {
+      Some(os) => vec![format!("{}/{}", os, m.architecture())],
+      None => vec![m.architecture()],
+    },
+    Manifest::ML(m) => m
+      .manifests()
+      .iter()
+      .filter_map(|mo| mo.platform.as_ref().map(ToString::to_string))
+      .collect(),
+    Manifest::Custom(_) => Vec::new(),
+  }
+}
+
+fn size(manifest: &Manifest) -> Option<u64> {
+  match manifest {
+    Manifest::S1Signed(_) | Manifest::Custom(_) => None,
+    Manifest::S2(m) => Some(m.size()),
+    Manifest::ML(m) => Some(m.manifests().iter().map(|mo| mo.size()).sum()),
+  }
+}
+
+fn created(manifest: &Manifest) -> Option<String> {
+  match manifest {
+    Manifest::S2(m) => m.created(),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(repository: &str, tag: &str, digest: &str, blobs: &[(&str, u64)]) -> InventoryEntry {
+    InventoryEntry {
+      repository: repository.to_string(),
+      tag: tag.to_string(),
+      digest: Some(digest.to_string()),
+      blobs: blobs
+        .iter()
+        .map(|(digest, size)| BlobUsage {
+          digest: digest.to_string(),
+          size: *size,
+        })
+        .collect(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn storage_report_deduplicates_shared_blobs() {
+    let inventory = Inventory {
+      entries: vec![
+        entry("a", "v1", "sha256:m1", &[("sha256:base", 100), ("sha256:app1", 10)]),
+        entry("a", "latest", "sha256:m1", &[("sha256:base", 100), ("sha256:app1", 10)]),
+        entry("a", "v2", "sha256:m2", &[("sha256:base", 100), ("sha256:app2", 20)]),
+        entry("b", "v1", "sha256:m3", &[("sha256:base", 100), ("sha256:big", 500)]),
+      ],
+      ..Default::default()
+    };
+
+    let report = inventory.storage_report(2);
+
+    assert_eq!(report.total_bytes, 630);
+    assert_eq!(
+      report.repositories,
+      vec![
+        RepositoryUsage {
+          repository: "b".to_string(),
+          bytes: 600,
+          exclusive_bytes: 500,
+        },
+        RepositoryUsage {
+          repository: "a".to_string(),
+          bytes: 130,
+          exclusive_bytes: 30,
+        },
+      ]
+    );
+
+    assert_eq!(report.largest_blobs.len(), 2);
+    assert_eq!(report.largest_blobs[0].digest, "sha256:big");
+    assert_eq!(report.largest_blobs[1].digest, "sha256:base");
+    assert_eq!(report.largest_blobs[1].repositories, vec!["a", "b"]);
+
+    assert_eq!(report.largest_images.len(), 2);
+    assert_eq!(report.largest_images[0].digest.as_deref(), Some("sha256:m3"));
+    assert_eq!(report.largest_images[1].digest.as_deref(), Some("sha256:m2"));
+  }
+
+  #[test]
+  fn storage_report_groups_tags_of_same_image() {
+    let inventory = Inventory {
+      entries: vec![
+        entry("a", "v1", "sha256:m1", &[("sha256:l1", 1)]),
+        entry("a", "latest", "sha256:m1", &[("sha256:l1", 1)]),
+      ],
+      ..Default::default()
+    };
+
+    let report = inventory.storage_report(10);
+
+    assert_eq!(report.largest_images.len(), 1);
+    assert_eq!(report.largest_images[0].tags, vec!["v1", "latest"]);
+    assert_eq!(report.largest_images[0].bytes, 1);
+  }
+
+  #[test]
+  fn diff_reports_tag_and_repository_changes() {
+    let old = Inventory {
+      entries: vec![
+        entry("a", "v1", "sha256:m1", &[]),
+        entry("a", "latest", "sha256:m1", &[]),
+        entry("gone", "v1", "sha256:m2", &[]),
+      ],
+      collected_at: Some(1000),
+    };
+    let new = Inventory {
+      entries: vec![
+        entry("a", "v1", "sha256:m1", &[]),
+        entry("a", "latest", "sha256:m3", &[]),
+        entry("a", "v2", "sha256:m3", &[]),
+        entry("new", "v1", "sha256:m4", &[]),
+      ],
+      collected_at: Some(2000),
+    };
+    let change = |repository: &str, tag: &str, old: Option<&str>, new: Option<&str>| TagChange {
+      repository: repository.to_string(),
+      tag: tag.to_string(),
+      old_digest: old.map(str::to_string),
+      new_digest: new.map(str::to_string),
+    };
+
+    let changes = diff(&old, &new);
+
+    assert_eq!(
+      (changes.old_collected_at, changes.new_collected_at),
+      (Some(1000), Some(2000))
+    );
+    assert_eq!(changes.added_repositories, vec!["new"]);
+    assert_eq!(changes.removed_repositories, vec!["gone"]);
+    assert_eq!(
+      changes.added_tags,
+      vec![
+        change("a", "v2", None, Some("sha256:m3")),
+        change("new", "v1", None, Some("sha256:m4")),
+      ]
+    );
+    assert_eq!(
+      changes.removed_tags,
+      vec![change("gone", "v1", Some("sha256:m2"), None)]
+    );
+    assert_eq!(
+      changes.changed_tags,
+      vec![change("a", "latest", Some("sha256:m1"), Some("sha256:m3"))]
+    );
+    assert!(!changes.is_empty());
+    assert!(diff(&new, &new).is_empty());
+  }
+}
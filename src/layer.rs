@@ -0,0 +1,315 @@
+//! Layer archive helpers: compression and flattening of layer tarballs.
+
+use std::{
+  collections::BTreeMap,
+  io::{Read, Write},
+  path::{Path, PathBuf},
+};
+
+use libflate::gzip;
+
+use crate::errors::{Error, Result};
+
+/// Compression of a layer blob.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+  /// Plain tar archive.
+  None,
+  /// Gzip-compressed tar archive.
+  Gzip,
+  /// Zstandard-compressed tar archive.
+  #[cfg(feature = "zstd")]
+  Zstd,
+}
+
+impl Compression {
+  /// Compression of a layer of the given media type.
+  ///
+  /// Fails with `Error::UnsupportedLayerMediaType` for layers which are not tar archives
+  /// or use an unsupported compression.
+  pub fn from_media_type(media_type: &str) -> Result<Self> {
+    match media_type {
+      "application/vnd.oci.image.layer.v1.tar"
+      | "application/vnd.oci.image.layer.nondistributable.v1.tar"
+      | "application/vnd.docker.image.rootfs.diff.tar" => Ok(Compression::None),
+      "application/vnd.oci.image.layer.v1.tar+gzip"
+      | "application/vnd.oci.image.layer.nondistributable.v1.tar+gzip"
+      | "application/vnd.docker.image.rootfs.diff.tar.gzip"
+      | "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip" => Ok(Compression::Gzip),
+      #[cfg(feature = "zstd")]
+      "application/vnd.oci.image.layer.v1.tar+zstd"
+      | "application/vnd.oci.image.layer.nondistributable.v1.tar+zstd" => Ok(Compression::Zstd),
+      other => Err(Error::UnsupportedLayerMediaType(other.to_string())),
+    }
+  }
+
+  /// Media type of a layer with this compression, for OCI or Docker manifests.
+  ///
+  /// Docker defines no media type for zstd layers, so the OCI one is used for both.
+  pub fn layer_media_type(self, oci: bool) -> &'static str {
+    match (self, oci) {
+      #[cfg(feature = "zstd")]
+      (Compression::Zstd, _) => "application/vnd.oci.image.layer.v1.tar+zstd",
+      (Compression::None, true) => "application/vnd.oci.image.layer.v1.tar",
+      (Compression::Gzip, true) => "application/vnd.oci.image.layer.v1.tar+gzip",
+      (Compression::None, false) => "application/vnd.docker.image.rootfs.diff.tar",
+      (Compression::Gzip, false) => "application/vnd.docker.image.rootfs.diff.tar.gzip",
+    }
+  }
+
+  /// The HTTP content coding of bodies compressed this way, e.g. `gzip`.
+  pub fn content_encoding(self) -> &'static str {
+    match self {
+      Compression::None => "identity",
+      Compression::Gzip => "gzip",
+      #[cfg(feature = "zstd")]
+      Compression::Zstd => "zstd",
+    }
+  }
+
+  /// Compression of bodies with the `Content-Encoding` `value`, `None` if it is not supported.
+  pub fn from_content_encoding(value: &str) -> Option<Self> {
+    match value.trim().to_ascii_lowercase().as_str() {
+      "" | "identity" => Some(Compression::None),
+      "gzip" | "x-gzip" => Some(Compression::Gzip),
+      #[cfg(feature = "zstd")]
+      "zstd" => Some(Compression::Zstd),
+      _ => None,
+    }
+  }
+
+  /// Decompress a layer blob to a tar archive.
+  pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+    match self {
+      Compression::None => Ok(data.to_vec()),
+      Compression::Gzip => {
+        let mut tar = Vec::new();
+        gzip::Decoder::new(data)?.read_to_end(&mut tar)?;
+        Ok(tar)
+      }
+      #[cfg(feature = "zstd")]
+      Compression::Zstd => Ok(zstd::decode_all(data)?),
+    }
+  }
+
+  /// Compress a tar archive to a layer blob, with the default compression level.
+  pub fn compress(self, tar: &[u8]) -> Result<Vec<u8>> {
+    self.compress_with_level(tar, None)
+  }
+
+  /// Whether `compress_with_level` honours the compression level.
+  #[cfg(feature = "client")]
+  pub(crate) fn has_levels(self) -> bool {
+    match self {
+      Compression::None | Compression::Gzip => false,
+      #[cfg(feature = "zstd")]
+      Compression::Zstd => true,
+    }
+  }
+
+  /// Compress a tar archive to a layer blob.
+  ///
+  /// `level` is only honoured by zstd; `None` selects the default level.
+  #[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+  pub fn compress_with_level(self, tar: &[u8], level: Option<i32>) -> Result<Vec<u8>> {
+    match self {
+      Compression::None => Ok(tar.to_vec()),
+      Compression::Gzip => {
+        // Leave the modification time out of the header, so that the blob only depends on `tar`.
+        let header = gzip::HeaderBuilder::new().modification_time(0).finish();
+        let mut encoder = gzip::Encoder::with_options(Vec::new(), gzip::EncodeOptions::new().header(header))?;
+        encoder.write_all(tar)?;
+        Ok(encoder.finish().into_result()?)
+      }
+      #[cfg(feature = "zstd")]
+      Compression::Zstd => Ok(zstd::encode_all(tar, level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL))?),
+    }
+  }
+}
+
+struct SquashedEntry {
+  layer: usize,
+  header: tar::Header,
+  link_name: Option<PathBuf>,
+  data: Vec<u8>,
+}
+
+/// Flatten an ordered list of uncompressed layer tarballs into a single tarball.
+///
+/// Layers are applied lowest first, honouring whiteout and opaque whiteout entries, which are
+/// not part of the result. Entries are written sorted by path, so that the output only depends
+/// on the content of the layers.
+pub fn squash(layers: &[Vec<u8>]) -> Result<Vec<u8>> {
+  let mut files: BTreeMap<String, SquashedEntry> = BTreeMap::new();
+
+  for (layer, tar) in layers.iter().enumerate() {
+    let mut archive = tar::Archive::new(tar.as_slice());
+    for entry in archive.entries()? {
+      let mut entry = entry?;
+      let path = normalize_path(&entry.path()?);
+      if path.is_empty() {
+        continue;
+      }
+      let (parent, name) = match path.rsplit_once('/') {
+        Some((parent, name)) => (Some(parent), name),
+        None => (None, path.as_str()),
+      };
+
+      if name == ".wh..wh..opq" {
+        // Hide whatever lower layers put in the directory.
+        let prefix = parent.map(|p| format!("{}/", p)).unwrap_or_default();
+        files.retain(|p, e| e.layer == layer || !p.starts_with(&prefix));
+        continue;
+      }
+      if let Some(hidden) = name.strip_prefix(".wh.") {
+        let hidden = match parent {
+          Some(parent) => format!("{}/{}", parent, hidden),
+          None => hidden.to_string(),
+        };
+        remove_tree(&mut files, &hidden);
+        continue;
+      }
+
+      let header = entry.header().clone();
+      if !header.entry_type().is_dir() {
+        remove_tree(&mut files, &path);
+      }
+      let link_name = entry.link_name()?.map(|l| l.into_owned());
+      let mut data = Vec::new();
+      entry.read_to_end(&mut data)?;
+      files.insert(
+        path,
+        SquashedEntry {
+          layer,
+          header,
+          link_name,
+          data,
+        },
+      );
+    }
+  }
+
+  let mut builder = tar::Builder::new(Vec::new());
+  for (path, mut entry) in files {
+    match entry.link_name {
+      Some(link_name) => builder.append_link(&mut entry.header, &path, link_name)?,
+      None => builder.append_data(&mut entry.header, &path, entry.data.as_slice())?,
+    }
+  }
+  Ok(builder.into_inner()?)
+}
+
+/// Remove `path` and, if it is a directory, everything below it.
+fn remove_tree(files: &mut BTreeMap<String, SquashedEntry>, path: &str) {
+  let prefix = format!("{}/", path);
+  files.retain(|p, _| p != path && !p.starts_with(&prefix));
+}
+
+/// Normalize an archive path to a relative path without `.` components or trailing slash.
+fn normalize_path(path: &Path) -> String {
+  path
+    .components()
+    .filter_map(|c| match c {
+      std::path::Component::Normal(c) => Some(c.to_string_lossy()),
+      _ => None,
+    })
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn layer(entries: &[(&str, Option<&[u8]>)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, data) in entries {
+      let mut header = tar::Header::new_gnu();
+      match data {
+        Some(data) => {
+          header.set_entry_type(tar::EntryType::Regular);
+          header.set_size(data.len() as u64);
+          header.set_mode(0o644);
+          builder.append_data(&mut header, path, *data).unwrap();
+        }
+        None => {
+          header.set_entry_type(tar::EntryType::Directory);
+          header.set_size(0);
+          header.set_mode(0o755);
+          builder.append_data(&mut header, path, &[][..]).unwrap();
+        }
+      }
+    }
+    builder.into_inner().unwrap()
+  }
+
+  fn contents(tar: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut archive = tar::Archive::new(tar);
+    archive
+      .entries()
+      .unwrap()
+      .map(|e| {
+        let mut e = e.unwrap();
+        let path = e.path().unwrap().to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        e.read_to_end(&mut data).unwrap();
+        (path, data)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn squash_applies_whiteouts() {
+    let lower = layer(&[
+      ("etc", None),
+      ("etc/hostname", Some(b"lower")),
+      ("etc/passwd", Some(b"root")),
+      ("var", None),
+      ("var/cache", None),
+      ("var/cache/a", Some(b"a")),
+    ]);
+    let upper = layer(&[
+      ("./etc/hostname", Some(b"upper")),
+      ("etc/.wh.passwd", Some(b"")),
+      ("var/cache/.wh..wh..opq", Some(b"")),
+      ("var/cache/b", Some(b"b")),
+    ]);
+
+    let squashed = squash(&[lower, upper]).unwrap();
+    assert_eq!(
+      contents(&squashed),
+      vec![
+        ("etc".to_string(), vec![]),
+        ("etc/hostname".to_string(), b"upper".to_vec()),
+        ("var".to_string(), vec![]),
+        ("var/cache".to_string(), vec![]),
+        ("var/cache/b".to_string(), b"b".to_vec()),
+      ]
+    );
+  }
+
+  #[test]
+  fn gzip_round_trip() {
+    let tar = layer(&[("file", Some(b"content"))]);
+    let compressed = Compression::Gzip.compress(&tar).unwrap();
+    assert_eq!(Compression::Gzip.decompress(&compressed).unwrap(), tar);
+    assert_eq!(&compressed[4..8], &[0; 4], "modification time must not be recorded");
+    assert_eq!(
+      Compression::from_media_type("application/vnd.oci.image.layer.v1.tar+gzip").unwrap(),
+      Compression::Gzip
+    );
+    assert!(Compression::from_media_type("application/vnd.in-toto+json").is_err());
+  }
+
+  #[cfg(feature = "zstd")]
+  #[test]
+  fn zstd_round_trip() {
+    let tar = layer(&[("file", Some(b"content"))]);
+    let compressed = Compression::Zstd.compress_with_level(&tar, Some(19)).unwrap();
+    assert_eq!(Compression::Zstd.decompress(&compressed).unwrap(), tar);
+    assert_eq!(
+      Compression::from_media_type(Compression::Zstd.layer_media_type(false)).unwrap(),
+      Compression::Zstd
+    );
+  }
+}
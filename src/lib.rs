@@ -33,13 +33,69 @@
 
 #![deny(missing_debug_implementations)]
 
+#[cfg(all(feature = "native-tls", feature = "rustls"))]
+compile_error!("features `native-tls` and `rustls` are mutually exclusive, disable default features to use `rustls`");
+
 use log::trace;
 use serde::{Deserialize, Serialize};
 
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod archive;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod artifact;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod blob_store;
+#[cfg(feature = "client")]
+pub mod build;
+#[cfg(feature = "client")]
+pub mod bulk;
+#[cfg(all(feature = "test-support", not(target_arch = "wasm32")))]
+pub mod chaos;
+#[cfg(all(feature = "test-support", not(target_arch = "wasm32")))]
+pub mod conformance;
+#[cfg(feature = "client")]
+pub mod copy;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod dir_transport;
 pub mod errors;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+#[cfg(feature = "gitlab")]
+pub mod gitlab;
+#[cfg(feature = "helm")]
+pub mod helm;
+#[cfg(feature = "client")]
+pub mod integrity;
+#[cfg(feature = "client")]
+pub mod inventory;
+pub mod layer;
 pub mod mediatypes;
+#[cfg(feature = "client")]
+pub mod mutate;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod namespaces;
+#[cfg(all(feature = "notary", not(target_arch = "wasm32")))]
+pub mod notary;
+#[cfg(feature = "client")]
+pub mod orphans;
+pub mod pagination;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod promote;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod proxy;
 pub mod reference;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod registry_client;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod render;
+#[cfg(feature = "client")]
+pub mod replication;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod session;
+pub mod signing;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub mod staging;
+pub mod types;
 pub mod v2;
 
 use std::{collections::HashMap, io::Read};
@@ -47,8 +103,9 @@ use std::{collections::HashMap, io::Read};
 use base64::prelude::*;
 use errors::{Error, Result};
 
-/// Default User-Agent client identity.
-pub static USER_AGENT: &str = "clowdhaus/docker-registry/0.0";
+/// Product token identifying this library, sent as the User-Agent by default and appended to the
+/// User-Agent of applications identifying themselves with `Config::user_agent_prefix`.
+pub static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
 /// Get registry credentials from a JSON config reader.
 ///
@@ -0,0 +1,432 @@
+//! Mutation of image manifests and configs.
+//!
+//! A [`MutableImage`] is loaded from a registry, changed locally and pushed back,
+//! to the same or another repository. Only new layers are uploaded; the digests of
+//! the config and manifest are recomputed, and every change is recorded in the
+//! config history. Timestamps are only written when set with
+//! [`MutableImage::created`], so that applying the same mutations to the same image
+//! always yields the same digest.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{mutate::MutableImage, v2::Client};
+//!
+//! let client = Client::configure().registry("localhost:5000").build()?;
+//! let mut image = MutableImage::load(&client, "app", "v1").await?;
+//! image
+//!   .env("LOG_LEVEL", "debug")
+//!   .user("1000")
+//!   .entrypoint(Some(vec!["/bin/app".to_string()]));
+//! let digest = image.push(&client, "app", "v1-debug").await?;
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{
+  copy::{self, ManifestKind, MANIFEST_MEDIA_TYPES},
+  errors::{Error, Result},
+  layer::Compression,
+  mediatypes::MediaTypes,
+  v2::{sha256_digest, Client, ContentDigest},
+};
+
+/// Options for [`Client::append_layer`].
+#[derive(Clone, Debug)]
+pub struct AppendLayerOptions {
+  /// Compression of the new layer.
+  pub compression: Compression,
+  /// History entry recorded for the new layer.
+  pub created_by: String,
+  /// Timestamp (RFC 3339) of the new image and history entry; none is written if unset.
+  pub created: Option<String>,
+  /// Tag to push the new image as. Defaults to the reference of the original image when it
+  /// is a tag; images referenced by digest are only pushed by their new digest.
+  pub tag: Option<String>,
+}
+
+impl Default for AppendLayerOptions {
+  fn default() -> Self {
+    Self {
+      compression: Compression::Gzip,
+      created_by: "append layer".to_string(),
+      created: None,
+      tag: None,
+    }
+  }
+}
+
+impl Client {
+  /// Add a layer with the content of the tar archive `tar` on top of `name:reference`.
+  ///
+  /// The archive is buffered in memory and compressed according to `options`. Only the new
+  /// layer and the updated config are uploaded. Returns the digest of the new manifest.
+  pub async fn append_layer<R>(
+    &self,
+    name: &str,
+    reference: &str,
+    mut tar: R,
+    options: &AppendLayerOptions,
+  ) -> Result<String>
+  where
+    R: AsyncRead + Unpin,
+  {
+    self.ensure_writable()?;
+    let mut image = MutableImage::load(self, name, reference).await?;
+    if let Some(created) = &options.created {
+      image.created(created);
+    }
+
+    let mut buffer = Vec::new();
+    tar.read_to_end(&mut buffer).await?;
+    image.add_layer(&buffer, options.compression, &options.created_by)?;
+
+    let digest = image.digest()?;
+    let tag = match &options.tag {
+      Some(tag) => Some(tag.as_str()),
+      None if ContentDigest::try_new(reference).is_err() => Some(reference),
+      None => None,
+    };
+    image.push(self, name, tag.unwrap_or(&digest)).await
+  }
+}
+
+/// An image manifest and config loaded for modification.
+#[derive(Clone, Debug)]
+pub struct MutableImage {
+  source: Client,
+  source_name: String,
+  media_type: String,
+  manifest: Value,
+  original_manifest: Vec<u8>,
+  config: Value,
+  original_config: Vec<u8>,
+  created: Option<String>,
+  new_layers: HashMap<String, Vec<u8>>,
+  /// Repositories holding layers which do not come from `source`, e.g. after a rebase.
+  layer_sources: HashMap<String, (Client, String)>,
+}
+
+impl MutableImage {
+  /// Load the image manifest and config of `name:reference`.
+  ///
+  /// Manifest lists and OCI indexes are not supported: load one of the manifests they reference.
+  pub async fn load(client: &Client, name: &str, reference: &str) -> Result<Self> {
+    let (manifest, media_type, _) = client
+      .get_raw_manifest(name, reference, Some(MANIFEST_MEDIA_TYPES))
+      .await?;
+    if copy::manifest_kind(&media_type)? != ManifestKind::Image {
+      return Err(Error::UnsupportedMediaType(copy::parse_media_type(&media_type)?));
+    }
+
+    let value: Value = serde_json::from_slice(&manifest)?;
+    let config = client
+      .get_blob(name, &copy::descriptor(&value["config"])?.digest)
+      .await?;
+
+    Ok(Self {
+      source: client.clone(),
+      source_name: name.to_string(),
+      media_type,
+      manifest: value,
+      original_manifest: manifest,
+      config: serde_json::from_slice(&config)?,
+      original_config: config,
+      created: None,
+      new_layers: HashMap::new(),
+      layer_sources: HashMap::new(),
+    })
+  }
+
+  /// Media type of the manifest.
+  pub fn media_type(&self) -> &str {
+    &self.media_type
+  }
+
+  /// The image config, including the changes made so far.
+  pub fn config(&self) -> &Value {
+    &self.config
+  }
+
+  /// Set the timestamp (RFC 3339) of the image and of the history entries added from now on.
+  pub fn created(&mut self, created: &str) -> &mut Self {
+    self.created = Some(created.to_string());
+    self.config["created"] = Value::from(created);
+    self
+  }
+
+  /// Set the entrypoint; `None` clears it.
+  pub fn entrypoint(&mut self, entrypoint: Option<Vec<String>>) -> &mut Self {
+    let history = format!("ENTRYPOINT {}", Value::from(entrypoint.clone()));
+    self.container_config()["Entrypoint"] = Value::from(entrypoint);
+    self.record(&history)
+  }
+
+  /// Set the default command; `None` clears it.
+  pub fn cmd(&mut self, cmd: Option<Vec<String>>) -> &mut Self {
+    let history = format!("CMD {}", Value::from(cmd.clone()));
+    self.container_config()["Cmd"] = Value::from(cmd);
+    self.record(&history)
+  }
+
+  /// Set an environment variable, replacing any previous value.
+  pub fn env(&mut self, key: &str, value: &str) -> &mut Self {
+    let prefix = format!("{}=", key);
+    let entry = format!("{}{}", prefix, value);
+    let env = array(&mut self.container_config()["Env"]);
+    match env
+      .iter_mut()
+      .find(|e| e.as_str().is_some_and(|e| e.starts_with(&prefix)))
+    {
+      Some(existing) => *existing = Value::from(entry.as_str()),
+      None => env.push(Value::from(entry.as_str())),
+    }
+    self.record(&format!("ENV {}", entry))
+  }
+
+  /// Remove an environment variable.
+  pub fn remove_env(&mut self, key: &str) -> &mut Self {
+    let prefix = format!("{}=", key);
+    if let Some(env) = self.container_config()["Env"].as_array_mut() {
+      env.retain(|e| !e.as_str().is_some_and(|e| e.starts_with(&prefix)));
+    }
+    self
+  }
+
+  /// Set a label.
+  pub fn label(&mut self, key: &str, value: &str) -> &mut Self {
+    object(&mut self.container_config()["Labels"]).insert(key.to_string(), Value::from(value));
+    self.record(&format!("LABEL {}={}", key, Value::from(value)))
+  }
+
+  /// Remove a label.
+  pub fn remove_label(&mut self, key: &str) -> &mut Self {
+    if let Some(labels) = self.container_config()["Labels"].as_object_mut() {
+      labels.remove(key);
+    }
+    self
+  }
+
+  /// Set the user, as `user[:group]`.
+  pub fn user(&mut self, user: &str) -> &mut Self {
+    self.container_config()["User"] = Value::from(user);
+    self.record(&format!("USER {}", user))
+  }
+
+  /// Set the working directory.
+  pub fn working_dir(&mut self, dir: &str) -> &mut Self {
+    self.container_config()["WorkingDir"] = Value::from(dir);
+    self.record(&format!("WORKDIR {}", dir))
+  }
+
+  /// Expose a port, as `port[/protocol]` (e.g. `8080/tcp`).
+  pub fn expose_port(&mut self, port: &str) -> &mut Self {
+    let port = match port.contains('/') {
+      true => port.to_string(),
+      false => format!("{}/tcp", port),
+    };
+    object(&mut self.container_config()["ExposedPorts"]).insert(port.clone(), json!({}));
+    self.record(&format!("EXPOSE {}", port))
+  }
+
+  /// Add a layer on top of the image from an uncompressed tar archive.
+  ///
+  /// The layer is compressed with `compression`, its diff ID is added to the config and a
+  /// history entry with `created_by` is recorded. Returns the digest of the new layer blob.
+  pub fn add_layer(&mut self, tar: &[u8], compression: Compression, created_by: &str) -> Result<String> {
+    let oci = copy::parse_media_type(&self.media_type)? == MediaTypes::OciImageManifest;
+    let data = compression.compress(tar)?;
+    let digest = sha256_digest(&data);
+
+    let layer = json!({
+      "mediaType": compression.layer_media_type(oci),
+      "digest": digest,
+      "size": data.len(),
+    });
+    array(&mut self.manifest["layers"]).push(layer);
+    self.new_layers.insert(digest.clone(), data);
+
+    if !self.config["rootfs"].is_object() {
+      self.config["rootfs"] = json!({"type": "layers"});
+    }
+    array(&mut self.config["rootfs"]["diff_ids"]).push(Value::from(sha256_digest(tar)));
+    self.push_history(created_by, false);
+    Ok(digest)
+  }
+
+  /// Replace the layers of `old_base` at the bottom of this image with those of `new_base`.
+  ///
+  /// The layers, diff IDs and history entries of the old base must be a prefix of those of
+  /// this image, otherwise this fails with `Error::BaseMismatch` and the image is left unchanged.
+  /// The rest of the config (environment, entrypoint, labels...) is kept as is.
+  pub fn rebase(&mut self, old_base: &MutableImage, new_base: &MutableImage) -> Result<&mut Self> {
+    let digests = |image: &MutableImage| -> Result<Vec<String>> {
+      copy::layers_of(&image.manifest)?
+        .iter()
+        .map(|l| Ok(copy::descriptor(l)?.digest))
+        .collect()
+    };
+    let layers = digests(self)?;
+    let old_layers = digests(old_base)?;
+    if !layers.starts_with(&old_layers) {
+      return Err(Error::BaseMismatch(
+        "layers of the old base are not at the bottom of the image".into(),
+      ));
+    }
+
+    let diff_ids = array_of(&self.config["rootfs"]["diff_ids"]);
+    let old_diff_ids = array_of(&old_base.config["rootfs"]["diff_ids"]);
+    if !diff_ids.starts_with(&old_diff_ids) {
+      return Err(Error::BaseMismatch(
+        "diff IDs of the old base are not at the bottom of the image".into(),
+      ));
+    }
+
+    let history = array_of(&self.config["history"]);
+    let old_history = array_of(&old_base.config["history"]);
+    if !history.starts_with(&old_history) {
+      return Err(Error::BaseMismatch(
+        "history of the old base is not at the start of the image history".into(),
+      ));
+    }
+
+    let mut new_layers = copy::layers_of(&new_base.manifest)?.clone();
+    new_layers.extend_from_slice(&copy::layers_of(&self.manifest)?[old_layers.len()..]);
+    self.manifest["layers"] = Value::Array(new_layers);
+
+    let mut new_diff_ids = array_of(&new_base.config["rootfs"]["diff_ids"]);
+    new_diff_ids.extend_from_slice(&diff_ids[old_diff_ids.len()..]);
+    if !self.config["rootfs"].is_object() {
+      self.config["rootfs"] = json!({"type": "layers"});
+    }
+    self.config["rootfs"]["diff_ids"] = Value::Array(new_diff_ids);
+
+    if !history.is_empty() {
+      let mut new_history = array_of(&new_base.config["history"]);
+      new_history.extend_from_slice(&history[old_history.len()..]);
+      self.config["history"] = Value::Array(new_history);
+    }
+
+    for layer in &digests(new_base)? {
+      match new_base.new_layers.get(layer) {
+        Some(data) => {
+          self.new_layers.insert(layer.clone(), data.clone());
+        }
+        None => {
+          let source = match new_base.layer_sources.get(layer) {
+            Some(source) => source.clone(),
+            None => (new_base.source.clone(), new_base.source_name.clone()),
+          };
+          self.layer_sources.insert(layer.clone(), source);
+        }
+      }
+    }
+    Ok(self)
+  }
+
+  /// Serialize the config and manifest, keeping the original bytes of unchanged documents.
+  fn render(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+    let config = match serde_json::from_slice::<Value>(&self.original_config)? == self.config {
+      true => self.original_config.clone(),
+      false => serde_json::to_vec(&self.config)?,
+    };
+
+    let mut manifest = self.manifest.clone();
+    manifest["config"]["digest"] = Value::from(sha256_digest(&config));
+    manifest["config"]["size"] = Value::from(config.len());
+    let manifest = match serde_json::from_slice::<Value>(&self.original_manifest)? == manifest {
+      true => self.original_manifest.clone(),
+      false => serde_json::to_vec(&manifest)?,
+    };
+    Ok((config, manifest))
+  }
+
+  /// Digest the manifest will have once pushed.
+  pub fn digest(&self) -> Result<String> {
+    Ok(sha256_digest(&self.render()?.1))
+  }
+
+  /// Push the image as `name:reference`, returning the digest of the manifest.
+  ///
+  /// Layers of the original image missing from `name` are copied from the repository the
+  /// image was loaded from, and layers of a new base from the repository of that base.
+  pub async fn push(&self, client: &Client, name: &str, reference: &str) -> Result<String> {
+    client.ensure_writable()?;
+    for layer in copy::layers_of(&self.manifest)? {
+      let layer = copy::descriptor(layer)?;
+      if let Some(data) = self.new_layers.get(&layer.digest) {
+        copy::push_blob_if_missing(client, name, data, &layer.digest).await?;
+        continue;
+      }
+      let (source, source_name) = match self.layer_sources.get(&layer.digest) {
+        Some((source, source_name)) => (source, source_name),
+        None => (&self.source, &self.source_name),
+      };
+      copy::copy_blob(source, source_name, client, name, &layer).await?;
+    }
+
+    let (config, manifest) = self.render()?;
+    copy::push_blob_if_missing(client, name, &config, &sha256_digest(&config)).await?;
+    client.put_manifest(name, reference, &self.media_type, &manifest).await
+  }
+
+  /// The `config` section of the image config, created if missing.
+  fn container_config(&mut self) -> &mut Value {
+    if !self.config["config"].is_object() {
+      self.config["config"] = json!({});
+    }
+    &mut self.config["config"]
+  }
+
+  /// Record a change which does not add a layer in the history.
+  fn record(&mut self, created_by: &str) -> &mut Self {
+    self.push_history(created_by, true);
+    self
+  }
+
+  fn push_history(&mut self, created_by: &str, empty_layer: bool) {
+    let mut entry = json!({ "created_by": created_by });
+    if empty_layer {
+      entry["empty_layer"] = Value::from(true);
+    }
+    if let Some(created) = &self.created {
+      entry["created"] = Value::from(created.as_str());
+    }
+    array(&mut self.config["history"]).push(entry);
+  }
+}
+
+/// A copy of the array held by `value`, or an empty array.
+fn array_of(value: &Value) -> Vec<Value> {
+  value.as_array().cloned().unwrap_or_default()
+}
+
+/// The array held by `value`, replacing anything else with an empty array.
+fn array(value: &mut Value) -> &mut Vec<Value> {
+  if !value.is_array() {
+    *value = json!([]);
+  }
+  value.as_array_mut().expect("value is an array")
+}
+
+/// The object held by `value`, replacing anything else with an empty object.
+fn object(value: &mut Value) -> &mut Map<String, Value> {
+  if !value.is_object() {
+    *value = json!({});
+  }
+  value.as_object_mut().expect("value is an object")
+}
@@ -0,0 +1,282 @@
+//! Listing the namespaces of multi-tenant registries, and the repositories of each namespace.
+//!
+//! The registry API has no notion of namespaces, and the catalog is often disabled. The
+//! [`NamespaceList`] trait lists them with the APIs of the registry vendors instead: Docker Hub
+//! organizations with [`DockerHubOrganizations`], Harbor projects with [`HarborProjects`] and
+//! GitLab groups with [`GitLabGroups`].
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   namespaces::{HarborProjects, NamespaceList},
+//!   v2::Client,
+//! };
+//!
+//! let client = Client::configure()
+//!   .registry("harbor.example.com")
+//!   .username(Some("admin".to_string()))
+//!   .password(Some("password".to_string()))
+//!   .build()?;
+//! let projects = HarborProjects::new(&client);
+//! for project in projects.namespaces().await? {
+//!   for repository in projects.repositories(&project).await? {
+//!     println!("{}", repository);
+//!   }
+//! }
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::fmt;
+
+use futures::future::BoxFuture;
+use log::trace;
+use reqwest::{
+  header::{self, HeaderMap, HeaderValue},
+  Method,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use url::{form_urlencoded, Position, Url};
+
+use crate::{
+  errors::{Error, Result},
+  pagination,
+  v2::{Client, RawResponse},
+};
+
+/// Future returned by [`NamespaceList`] methods.
+pub type NamespaceFuture<'a, T> = BoxFuture<'a, Result<T>>;
+
+/// Enumeration of the namespaces of a registry, e.g. organizations or projects, and of the
+/// repositories they contain.
+pub trait NamespaceList: fmt::Debug + Send + Sync {
+  /// List the namespaces visible to the client.
+  fn namespaces(&self) -> NamespaceFuture<'_, Vec<String>>;
+
+  /// List the repositories of `namespace`, by their full name in the registry.
+  fn repositories<'a>(&'a self, namespace: &'a str) -> NamespaceFuture<'a, Vec<String>>;
+}
+
+/// Organizations of a Docker Hub user, listed with the Docker Hub API.
+///
+/// The namespaces are the user's own namespace and the organizations they belong to.
+#[derive(Debug)]
+pub struct DockerHubOrganizations {
+  hub: Client,
+  username: String,
+}
+
+impl DockerHubOrganizations {
+  /// Log in to the Docker Hub API through `hub`, a client configured for `hub.docker.com`,
+  /// with a password or a personal access token.
+  pub async fn login(hub: Client, username: &str, password: &str) -> Result<Self> {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    let body = serde_json::json!({"username": username, "password": password});
+    let res = hub
+      .raw_request(
+        Method::POST,
+        "/v2/users/login",
+        headers,
+        Some(serde_json::to_vec(&body)?),
+      )
+      .await?;
+    let login: HubLogin = parse(res)?;
+    Ok(Self {
+      hub: hub.with_bearer_token(&login.token),
+      username: username.to_string(),
+    })
+  }
+
+  /// List the API, e.g. `/v2/user/orgs/`, following the `next` page links.
+  async fn list<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<T>> {
+    let mut items = Vec::new();
+    let mut path = Some(format!("{}?page_size=100", path));
+    while let Some(p) = path.take() {
+      let page: HubPage<T> = parse(get(&self.hub, &p).await?)?;
+      items.extend(page.results);
+      path = page.next.as_deref().and_then(|next| Url::parse(next).ok()).map(path_of);
+    }
+    Ok(items)
+  }
+}
+
+#[derive(Deserialize)]
+struct HubLogin {
+  token: String,
+}
+
+#[derive(Deserialize)]
+struct HubPage<T> {
+  next: Option<String>,
+  results: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct HubOrganization {
+  orgname: String,
+}
+
+#[derive(Deserialize)]
+struct HubRepository {
+  namespace: String,
+  name: String,
+}
+
+impl NamespaceList for DockerHubOrganizations {
+  fn namespaces(&self) -> NamespaceFuture<'_, Vec<String>> {
+    Box::pin(async move {
+      let organizations: Vec<HubOrganization> = self.list("/v2/user/orgs/").await?;
+      let organizations = organizations.into_iter().map(|o| o.orgname);
+      Ok(std::iter::once(self.username.clone()).chain(organizations).collect())
+    })
+  }
+
+  fn repositories<'a>(&'a self, namespace: &'a str) -> NamespaceFuture<'a, Vec<String>> {
+    Box::pin(async move {
+      let repositories: Vec<HubRepository> = self.list(&format!("/v2/repositories/{}/", encode(namespace))).await?;
+      Ok(
+        repositories
+          .into_iter()
+          .map(|r| format!("{}/{}", r.namespace, r.name))
+          .collect(),
+      )
+    })
+  }
+}
+
+/// Projects of a Harbor registry, listed with the Harbor API and the client's credentials.
+#[derive(Debug)]
+pub struct HarborProjects {
+  client: Client,
+}
+
+impl HarborProjects {
+  pub fn new(client: &Client) -> Self {
+    Self {
+      client: client.with_basic_credentials(),
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct HarborName {
+  name: String,
+}
+
+impl NamespaceList for HarborProjects {
+  fn namespaces(&self) -> NamespaceFuture<'_, Vec<String>> {
+    Box::pin(async move {
+      let projects: Vec<HarborName> = get_linked_pages(&self.client, "/api/v2.0/projects?page_size=100").await?;
+      Ok(projects.into_iter().map(|p| p.name).collect())
+    })
+  }
+
+  fn repositories<'a>(&'a self, namespace: &'a str) -> NamespaceFuture<'a, Vec<String>> {
+    Box::pin(async move {
+      let path = format!("/api/v2.0/projects/{}/repositories?page_size=100", encode(namespace));
+      let repositories: Vec<HarborName> = get_linked_pages(&self.client, &path).await?;
+      Ok(repositories.into_iter().map(|r| r.name).collect())
+    })
+  }
+}
+
+/// Groups of a GitLab instance the user is a member of, listed with the GitLab API.
+///
+/// The API is served by GitLab itself rather than its registry, e.g. `gitlab.com` for
+/// `registry.gitlab.com`.
+#[derive(Debug)]
+pub struct GitLabGroups {
+  api: Client,
+}
+
+impl GitLabGroups {
+  /// Use the GitLab API through `api`, a client configured for the GitLab host and authorized
+  /// with a personal access token or an OAuth token, e.g. with `Client::with_bearer_token`.
+  pub fn new(api: Client) -> Self {
+    Self { api }
+  }
+}
+
+#[derive(Deserialize)]
+struct GitLabGroup {
+  full_path: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabRepository {
+  path: String,
+}
+
+impl NamespaceList for GitLabGroups {
+  fn namespaces(&self) -> NamespaceFuture<'_, Vec<String>> {
+    Box::pin(async move {
+      // Without a minimum access level, every public group of the instance is listed.
+      let path = "/api/v4/groups?min_access_level=10&per_page=100";
+      let groups: Vec<GitLabGroup> = get_linked_pages(&self.api, path).await?;
+      Ok(groups.into_iter().map(|g| g.full_path).collect())
+    })
+  }
+
+  fn repositories<'a>(&'a self, namespace: &'a str) -> NamespaceFuture<'a, Vec<String>> {
+    Box::pin(async move {
+      let path = format!(
+        "/api/v4/groups/{}/registry/repositories?per_page=100",
+        encode(namespace)
+      );
+      let repositories: Vec<GitLabRepository> = get_linked_pages(&self.api, &path).await?;
+      Ok(repositories.into_iter().map(|r| r.path).collect())
+    })
+  }
+}
+
+async fn get(client: &Client, path: &str) -> Result<RawResponse> {
+  trace!("Listing namespaces: GET {}", path);
+  client.raw_request(Method::GET, path, HeaderMap::new(), None).await
+}
+
+/// GET the JSON list at `path`, following the `next` links of the `Link` header.
+async fn get_linked_pages<T: DeserializeOwned>(client: &Client, path: &str) -> Result<Vec<T>> {
+  // Raw requests are relative to the registry: only the path and the query of links are used.
+  let base = Url::parse("http://registry.invalid/")?;
+  let mut items = Vec::new();
+  let mut path = Some(path.to_string());
+  while let Some(p) = path.take() {
+    let res = get(client, &p).await?;
+    let next = res
+      .headers
+      .get(header::LINK)
+      .and_then(|link| link.to_str().ok())
+      .and_then(|link| pagination::next_url(link, &base.join(&p).ok()?));
+    items.extend(parse::<Vec<T>>(res)?);
+    path = next.map(path_of);
+  }
+  Ok(items)
+}
+
+fn parse<T: DeserializeOwned>(res: RawResponse) -> Result<T> {
+  if !res.status.is_success() {
+    return Err(Error::UnexpectedHttpStatus(res.status));
+  }
+  Ok(serde_json::from_slice(&res.body)?)
+}
+
+/// The path and the query of `url`.
+fn path_of(url: Url) -> String {
+  url[Position::BeforePath..].to_string()
+}
+
+/// Encode `segment` as a single path segment, including its slashes.
+fn encode(segment: &str) -> String {
+  form_urlencoded::byte_serialize(segment.as_bytes()).collect()
+}
@@ -0,0 +1,594 @@
+//! Docker Content Trust: resolving signed tags with a Notary server.
+//!
+//! Registries enforcing content trust pair with a Notary (v1) server, e.g. `notary.docker.io`
+//! for Docker Hub, which serves the TUF metadata of each repository. [`NotaryClient::target`]
+//! fetches and verifies that metadata, from the root role down to the `targets` role and its
+//! `targets/releases` delegation, and returns the digest the tag was signed for, so that images
+//! can be pinned without the docker CLI.
+//!
+//! Repositories are identified by their globally unique name (GUN), i.e. their fully qualified
+//! name, e.g. `docker.io/library/alpine`.
+//!
+//! The root keys of a repository are either pinned with [`NotaryClient::root_key_ids`], or
+//! trusted on first use and remembered in a [`NotaryClient::trust_dir`], which also keeps the
+//! versions of the metadata seen so that it cannot be rolled back.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{notary::NotaryClient, v2::Client};
+//!
+//! let notary = NotaryClient::new(
+//!   Client::configure().registry("notary.docker.io").build()?,
+//! )
+//! .trust_dir("/var/lib/myapp/trust");
+//! let target = notary.target("docker.io/library/alpine", "latest").await?;
+//! println!("{}", target.digest);
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fs, io,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::trace;
+use reqwest::{header::HeaderMap, Method, StatusCode};
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+  blob_store::staging_beside,
+  errors::{Error, Result},
+  v2::{parse_rfc3339, sha256_hex, Client},
+};
+
+/// Delegation holding the tags signed with `docker trust sign`, preferred over `targets`.
+const RELEASES_ROLE: &str = "targets/releases";
+
+/// The target a tag was signed for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedTarget {
+  pub tag: String,
+  /// Digest of the manifest, e.g. `sha256:...`.
+  pub digest: String,
+  /// Size of the manifest in bytes.
+  pub size: u64,
+  /// Role which signed the tag, `targets/releases` or `targets`.
+  pub role: String,
+}
+
+/// Client resolving signed tags with the TUF metadata of a Notary server.
+#[derive(Debug)]
+pub struct NotaryClient {
+  client: Client,
+  root_key_ids: Vec<String>,
+  trust_dir: Option<PathBuf>,
+}
+
+impl NotaryClient {
+  /// Use the Notary server of `client`, e.g. a client configured for `notary.docker.io`.
+  ///
+  /// The client is authenticated for each repository with its credentials, if any.
+  pub fn new(client: Client) -> Self {
+    Self {
+      client,
+      root_key_ids: Vec::new(),
+      trust_dir: None,
+    }
+  }
+
+  /// Only trust root metadata signed with these keys, by their ID in `root.json`, e.g. as shown
+  /// by `docker trust inspect`.
+  ///
+  /// Either root keys or a [trust directory](Self::trust_dir) are required.
+  pub fn root_key_ids<I, S>(mut self, key_ids: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.root_key_ids = key_ids.into_iter().map(Into::into).collect();
+    self
+  }
+
+  /// Remember the root keys and metadata versions of each repository in `dir`.
+  ///
+  /// Unless root keys are pinned, the root keys of a repository not seen before are trusted if
+  /// they sign its root metadata, like the docker CLI does, and required from then on. Metadata
+  /// older than the version seen before fails with `Error::TrustVerification`.
+  pub fn trust_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+    self.trust_dir = Some(dir.as_ref().to_path_buf());
+    self
+  }
+
+  /// Resolve the target `tag` of the repository `gun` was signed for.
+  ///
+  /// Fails with `Error::NotSigned` if the repository has no trust data or the tag is not
+  /// signed, and with `Error::TrustVerification` if the metadata is not signed by the
+  /// expected keys, does not match the snapshot, has expired or was rolled back, or if neither
+  /// root keys nor a trust directory are configured.
+  pub async fn target(&self, gun: &str, tag: &str) -> Result<SignedTarget> {
+    let invalid = |reason: String| Error::TrustVerification {
+      gun: gun.to_string(),
+      reason,
+    };
+    let not_signed = || Error::NotSigned {
+      gun: gun.to_string(),
+      tag: tag.to_string(),
+    };
+
+    let scope = format!("repository:{}:pull", gun);
+    let client = match self.client.clone().authenticate(&[&scope]).await {
+      // The server does not require authentication.
+      Err(Error::MissingAuthHeader(_)) => self.client.clone(),
+      client => client?,
+    };
+    let fetch = |role: &'static str| fetch(&client, gun, role);
+
+    let state_path = self
+      .trust_dir
+      .as_ref()
+      .map(|dir| dir.join(format!("{}.json", sha256_hex(gun.as_bytes()))));
+    let mut state = match &state_path {
+      Some(path) => TrustState::load(path)?,
+      None => TrustState::default(),
+    };
+    let trusted_keys = match (&self.root_key_ids, &state_path) {
+      (pinned, _) if !pinned.is_empty() => Some(pinned),
+      (_, Some(_)) if !state.root_key_ids.is_empty() => Some(&state.root_key_ids),
+      // Trust on first use.
+      (_, Some(_)) => None,
+      (_, None) => return Err(invalid("neither root keys nor a trust directory are configured".into())),
+    };
+
+    let root_body = fetch("root").await?.ok_or_else(not_signed)?;
+    let root: Root = parse(&root_body, "root").map_err(invalid)?;
+    let mut root_role = root.role("root").map_err(invalid)?;
+    let root_key_ids = root_role.keyids.clone();
+    if let Some(trusted_keys) = trusted_keys {
+      root_role.keyids.retain(|id| trusted_keys.contains(id));
+    }
+    verify(&root_body, "root", &root.keys, &root_role, &root.expires).map_err(invalid)?;
+    state.check_version("root", root.version).map_err(invalid)?;
+
+    let timestamp_body = fetch("timestamp").await?.ok_or_else(not_signed)?;
+    let timestamp: Snapshot = parse(&timestamp_body, "timestamp").map_err(invalid)?;
+    let timestamp_role = root.role("timestamp").map_err(invalid)?;
+    verify(
+      &timestamp_body,
+      "timestamp",
+      &root.keys,
+      &timestamp_role,
+      &timestamp.expires,
+    )
+    .map_err(invalid)?;
+    state.check_version("timestamp", timestamp.version).map_err(invalid)?;
+
+    let snapshot_body = fetch("snapshot").await?.ok_or_else(not_signed)?;
+    timestamp.check("snapshot", &snapshot_body).map_err(invalid)?;
+    let snapshot: Snapshot = parse(&snapshot_body, "snapshot").map_err(invalid)?;
+    let snapshot_role = root.role("snapshot").map_err(invalid)?;
+    verify(
+      &snapshot_body,
+      "snapshot",
+      &root.keys,
+      &snapshot_role,
+      &snapshot.expires,
+    )
+    .map_err(invalid)?;
+    state.check_version("snapshot", snapshot.version).map_err(invalid)?;
+
+    let targets_body = fetch("targets").await?.ok_or_else(not_signed)?;
+    snapshot.check("targets", &targets_body).map_err(invalid)?;
+    let targets: Targets = parse(&targets_body, "targets").map_err(invalid)?;
+    let targets_role = root.role("targets").map_err(invalid)?;
+    verify(&targets_body, "targets", &root.keys, &targets_role, &targets.expires).map_err(invalid)?;
+    state.check_version("targets", targets.version).map_err(invalid)?;
+
+    let releases = targets
+      .delegations
+      .as_ref()
+      .and_then(|d| Some((d, d.roles.iter().find(|r| r.name == RELEASES_ROLE)?)))
+      .filter(|_| snapshot.meta.contains_key(RELEASES_ROLE));
+    let mut released = None;
+    if let Some((delegations, role)) = releases {
+      let body = fetch(RELEASES_ROLE).await?.ok_or_else(not_signed)?;
+      snapshot.check(RELEASES_ROLE, &body).map_err(invalid)?;
+      let releases: Targets = parse(&body, RELEASES_ROLE).map_err(invalid)?;
+      verify(&body, RELEASES_ROLE, &delegations.keys, &role.keys, &releases.expires).map_err(invalid)?;
+      state.check_version(RELEASES_ROLE, releases.version).map_err(invalid)?;
+      let delegated = role.paths.iter().any(|path| tag.starts_with(path.as_str()));
+      released = releases
+        .targets
+        .get(tag)
+        .filter(|_| delegated)
+        .map(|meta| meta.target(tag, RELEASES_ROLE));
+    }
+
+    if let Some(path) = &state_path {
+      state.root_key_ids = root_key_ids;
+      state.save(path)?;
+    }
+    if let Some(target) = released {
+      return target.map_err(invalid);
+    }
+    match targets.targets.get(tag) {
+      Some(meta) => meta.target(tag, "targets").map_err(invalid),
+      None => Err(not_signed()),
+    }
+  }
+}
+
+/// Fetch the metadata of `role`, or `None` if the server has none.
+async fn fetch(client: &Client, gun: &str, role: &str) -> Result<Option<Vec<u8>>> {
+  let path = format!("/v2/{}/_trust/tuf/{}.json", gun, role);
+  trace!("Fetching trust data: GET {}", path);
+  let res = client.raw_request(Method::GET, &path, HeaderMap::new(), None).await?;
+  match res.status {
+    StatusCode::OK => Ok(Some(res.body)),
+    StatusCode::NOT_FOUND => Ok(None),
+    status => Err(Error::UnexpectedHttpStatus(status)),
+  }
+}
+
+/// Root keys and metadata versions of a repository seen before, as kept in a trust directory.
+#[derive(Default, Deserialize, Serialize)]
+struct TrustState {
+  root_key_ids: Vec<String>,
+  versions: BTreeMap<String, u64>,
+}
+
+impl TrustState {
+  fn load(path: &Path) -> Result<Self> {
+    match fs::read(path) {
+      Ok(content) => Ok(serde_json::from_slice(&content)?),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+      Err(e) => Err(e.into()),
+    }
+  }
+
+  fn save(&self, path: &Path) -> Result<()> {
+    let (staged, mut file) = staging_beside(path).create_file()?;
+    io::Write::write_all(&mut file, &serde_json::to_vec(self)?)?;
+    drop(file);
+    staged.persist(path)
+  }
+
+  /// Record the version of the metadata of `role`, unless it is older than the one seen before.
+  fn check_version(&mut self, role: &str, version: u64) -> std::result::Result<(), String> {
+    match self.versions.get(role) {
+      Some(&seen) if version < seen => Err(format!(
+        "{} version {} is older than version {} seen before",
+        role, version, seen
+      )),
+      _ => {
+        self.versions.insert(role.to_string(), version);
+        Ok(())
+      }
+    }
+  }
+}
+
+/// A signed TUF metadata file.
+#[derive(Deserialize)]
+struct Envelope {
+  signed: Value,
+  signatures: Vec<Signature>,
+}
+
+#[derive(Deserialize)]
+struct Signature {
+  keyid: String,
+  sig: String,
+}
+
+#[derive(Deserialize)]
+struct Key {
+  keytype: String,
+  keyval: KeyValue,
+}
+
+#[derive(Deserialize)]
+struct KeyValue {
+  public: String,
+}
+
+#[derive(Clone, Deserialize)]
+struct RoleKeys {
+  keyids: Vec<String>,
+  threshold: usize,
+}
+
+#[derive(Deserialize)]
+struct Root {
+  expires: String,
+  #[serde(default)]
+  version: u64,
+  keys: BTreeMap<String, Value>,
+  roles: BTreeMap<String, RoleKeys>,
+}
+
+impl Root {
+  fn role(&self, name: &str) -> std::result::Result<RoleKeys, String> {
+    self
+      .roles
+      .get(name)
+      .cloned()
+      .ok_or_else(|| format!("root does not list the {} role", name))
+  }
+}
+
+/// The `timestamp` or `snapshot` role: the hashes of the metadata of other roles.
+#[derive(Deserialize)]
+struct Snapshot {
+  expires: String,
+  #[serde(default)]
+  version: u64,
+  meta: BTreeMap<String, FileMeta>,
+}
+
+impl Snapshot {
+  /// Check that `body` is the metadata of `role` this snapshot was taken of.
+  fn check(&self, role: &str, body: &[u8]) -> std::result::Result<(), String> {
+    let meta = self
+      .meta
+      .get(role)
+      .ok_or_else(|| format!("{} is not in the snapshot", role))?;
+    if meta.length != body.len() as u64 || meta.sha256()? != sha256_hex(body) {
+      return Err(format!("{} does not match the snapshot", role));
+    }
+    Ok(())
+  }
+}
+
+#[derive(Deserialize)]
+struct Targets {
+  expires: String,
+  #[serde(default)]
+  version: u64,
+  targets: BTreeMap<String, FileMeta>,
+  delegations: Option<Delegations>,
+}
+
+#[derive(Deserialize)]
+struct Delegations {
+  keys: BTreeMap<String, Value>,
+  roles: Vec<DelegatedRole>,
+}
+
+#[derive(Deserialize)]
+struct DelegatedRole {
+  name: String,
+  #[serde(flatten)]
+  keys: RoleKeys,
+  #[serde(default)]
+  paths: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct FileMeta {
+  length: u64,
+  hashes: BTreeMap<String, String>,
+}
+
+impl FileMeta {
+  /// The hex-encoded sha256 hash of the file; TUF encodes hashes with base64.
+  fn sha256(&self) -> std::result::Result<String, String> {
+    let hash = self.hashes.get("sha256").ok_or("missing sha256 hash")?;
+    let hash = STANDARD
+      .decode(hash)
+      .map_err(|e| format!("invalid sha256 hash: {}", e))?;
+    Ok(hash.iter().map(|b| format!("{:02x}", b)).collect())
+  }
+
+  fn target(&self, tag: &str, role: &str) -> std::result::Result<SignedTarget, String> {
+    Ok(SignedTarget {
+      tag: tag.to_string(),
+      digest: format!("sha256:{}", self.sha256()?),
+      size: self.length,
+      role: role.to_string(),
+    })
+  }
+}
+
+/// Parse the signed part of the metadata of `role`.
+fn parse<T: DeserializeOwned>(body: &[u8], role: &str) -> std::result::Result<T, String> {
+  let envelope: Envelope = serde_json::from_slice(body).map_err(|e| format!("invalid {} metadata: {}", role, e))?;
+  serde_json::from_value(envelope.signed).map_err(|e| format!("invalid {} metadata: {}", role, e))
+}
+
+/// Check that the metadata of `role` has not expired and is signed by at least `threshold` of
+/// the keys of `role_keys`.
+fn verify(
+  body: &[u8],
+  role: &str,
+  keys: &BTreeMap<String, Value>,
+  role_keys: &RoleKeys,
+  expires: &str,
+) -> std::result::Result<(), String> {
+  let expires = parse_rfc3339(expires).ok_or_else(|| format!("invalid expiry '{}' of {}", expires, role))?;
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_secs() as i64;
+  if expires <= now {
+    return Err(format!("{} has expired", role));
+  }
+
+  let envelope: Envelope = serde_json::from_slice(body).map_err(|e| format!("invalid {} metadata: {}", role, e))?;
+  // Signatures are computed over the canonical JSON of the signed part: serde_json sorts the
+  // keys of objects and does not indent.
+  let message = serde_json::to_vec(&envelope.signed).map_err(|e| e.to_string())?;
+  let valid: BTreeSet<&str> = envelope
+    .signatures
+    .iter()
+    .filter(|s| role_keys.keyids.contains(&s.keyid))
+    .filter(|s| {
+      // The ID of a key is the hash of the key itself, so the metadata cannot pass off another
+      // key under a trusted ID.
+      keys
+        .get(&s.keyid)
+        .filter(|key| serde_json::to_vec(key).is_ok_and(|key| sha256_hex(&key) == s.keyid))
+        .is_some_and(|key| verify_signature(key, &s.sig, &message))
+    })
+    .map(|s| s.keyid.as_str())
+    .collect();
+  let threshold = role_keys.threshold.max(1);
+  if valid.len() < threshold {
+    return Err(format!(
+      "{} has {} valid signatures, {} required",
+      role,
+      valid.len(),
+      threshold
+    ));
+  }
+  Ok(())
+}
+
+fn verify_signature(key: &Value, sig: &str, message: &[u8]) -> bool {
+  let Ok(key) = Key::deserialize(key) else {
+    return false;
+  };
+  let (Ok(public), Ok(sig)) = (STANDARD.decode(key.keyval.public), STANDARD.decode(sig)) else {
+    return false;
+  };
+  let algorithm: &dyn VerificationAlgorithm = match key.keytype.as_str() {
+    "ed25519" => &signature::ED25519,
+    "ecdsa" | "ecdsa-x509" => &signature::ECDSA_P256_SHA256_FIXED,
+    "rsa" | "rsa-x509" => &signature::RSA_PSS_2048_8192_SHA256,
+    _ => return false,
+  };
+  // Root keys are usually self-signed certificates, other keys are raw.
+  let certificate;
+  let public = match key.keytype.as_str() {
+    "ed25519" => Some(&public[..]),
+    "ecdsa" | "rsa" => spki_public_key(&public),
+    _ => {
+      certificate = pem_decode(&public);
+      certificate
+        .as_deref()
+        .and_then(certificate_spki)
+        .and_then(spki_public_key)
+    }
+  };
+  public.is_some_and(|public| UnparsedPublicKey::new(algorithm, public).verify(message, &sig).is_ok())
+}
+
+/// Decode the DER content of a PEM block.
+fn pem_decode(pem: &[u8]) -> Option<Vec<u8>> {
+  let pem = std::str::from_utf8(pem).ok()?;
+  let base64: String = pem
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+    .collect();
+  STANDARD.decode(base64).ok()
+}
+
+/// Split the DER element at the start of `der` into its tag, its content and the bytes after it.
+fn der_element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+  let (&tag, rest) = der.split_first()?;
+  let (&length, rest) = rest.split_first()?;
+  let (length, rest) = match length {
+    length if length < 0x80 => (length as usize, rest),
+    length => {
+      let count = (length & 0x7f) as usize;
+      if count == 0 || count > 4 || rest.len() < count {
+        return None;
+      }
+      let length = rest[..count].iter().fold(0, |length, &b| length << 8 | b as usize);
+      (length, &rest[count..])
+    }
+  };
+  (rest.len() >= length).then(|| (tag, &rest[..length], &rest[length..]))
+}
+
+/// The public key of a DER-encoded `SubjectPublicKeyInfo`.
+fn spki_public_key(spki: &[u8]) -> Option<&[u8]> {
+  let (0x30, spki, _) = der_element(spki)? else {
+    return None;
+  };
+  // Skip the algorithm identifier.
+  let (_, _, rest) = der_element(spki)?;
+  let (0x03, key, _) = der_element(rest)? else {
+    return None;
+  };
+  // Keys are a whole number of bytes: the count of unused bits is zero.
+  key.strip_prefix(&[0])
+}
+
+/// The `SubjectPublicKeyInfo` of a DER-encoded X.509 certificate.
+fn certificate_spki(certificate: &[u8]) -> Option<&[u8]> {
+  let (0x30, certificate, _) = der_element(certificate)? else {
+    return None;
+  };
+  let (0x30, tbs, _) = der_element(certificate)? else {
+    return None;
+  };
+  // Skip the optional version, then the serial number, signature algorithm, issuer, validity
+  // and subject.
+  let mut rest = match tbs.first() {
+    Some(0xa0) => der_element(tbs)?.2,
+    _ => tbs,
+  };
+  for _ in 0..5 {
+    rest = der_element(rest)?.2;
+  }
+  let (_, _, after) = der_element(rest)?;
+  Some(&rest[..rest.len() - after.len()])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut der = vec![tag];
+    match content.len() {
+      length if length < 0x80 => der.push(length as u8),
+      length => der.extend([0x82, (length >> 8) as u8, length as u8]),
+    }
+    der.extend(content);
+    der
+  }
+
+  #[test]
+  fn certificate_public_key() {
+    let key = [0x04; 65];
+    let spki = der(
+      0x30,
+      &[der(0x30, &der(0x06, &[1, 2, 3])), der(0x03, &[&[0][..], &key].concat())].concat(),
+    );
+    let name = der(0x30, &der(0x31, &[0; 100]));
+    let tbs = [
+      der(0xa0, &der(0x02, &[2])),
+      der(0x02, &[1]),
+      der(0x30, &der(0x06, &[1, 2, 3])),
+      name.clone(),
+      der(0x30, &[]),
+      name,
+      spki.clone(),
+      der(0xa3, &[]),
+    ]
+    .concat();
+    let certificate = der(0x30, &[der(0x30, &tbs), der(0x30, &[]), der(0x03, &[0])].concat());
+
+    assert_eq!(certificate_spki(&certificate), Some(&spki[..]));
+    assert_eq!(spki_public_key(&spki), Some(&key[..]));
+    assert_eq!(certificate_spki(&certificate[..40]), None);
+  }
+}
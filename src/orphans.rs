@@ -0,0 +1,194 @@
+//! Finding referrer artifacts whose subject no longer exists.
+//!
+//! Signatures, SBOMs and attestations point at the manifest they describe, their subject.
+//! Cleanup tools deleting images routinely leave them behind. [`Client::find_orphaned_referrers`]
+//! finds the artifacts stored under referrer tags whose subject is gone, and
+//! [`Client::delete_orphaned_referrers`] deletes them.
+//!
+//! Referrer tags are the tags of the OCI referrers tag schema, e.g. `sha256-<hex>`, and the
+//! tags of cosign, e.g. `sha256-<hex>.sig`, which are named after the digest of the subject.
+//! Untagged artifacts, as pushed to registries supporting the referrers API, cannot be listed
+//! once their subject is deleted and are not found.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{bulk::BulkOptions, v2::Client};
+//!
+//! let client = Client::configure().registry("localhost:5000").build()?;
+//! let options = BulkOptions::default();
+//! let report = client.find_orphaned_referrers("etcd", &options).await?;
+//! for orphan in &report.orphans {
+//!   println!("{} refers to the deleted {}", orphan.tag, orphan.subject);
+//! }
+//! let deleted = client
+//!   .delete_orphaned_referrers("etcd", &report, &options)
+//!   .await?;
+//! println!("{} manifests deleted", deleted.succeeded().count());
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use futures::TryStreamExt;
+use log::trace;
+
+use crate::{
+  bulk::{self, BulkOptions, BulkReport},
+  copy::{index_children, MANIFEST_MEDIA_TYPES},
+  errors::{Error, Result},
+  v2::{sha256_digest, Client},
+};
+
+/// Suffixes of the tags cosign stores signatures, attestations and SBOMs under.
+const COSIGN_SUFFIXES: [&str; 3] = ["sig", "att", "sbom"];
+
+/// Artifacts stored under a referrer tag whose subject no longer exists.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrphanedReferrer {
+  /// The referrer tag, e.g. `sha256-<hex>` or `sha256-<hex>.sig`.
+  pub tag: String,
+  /// Digest of the deleted subject.
+  pub subject: String,
+  /// Digests of the manifests to delete: the artifacts listed by a referrers index followed by
+  /// the index itself, or the manifest of a cosign tag.
+  pub manifests: Vec<String>,
+}
+
+/// Outcome of [`Client::find_orphaned_referrers`].
+#[derive(Debug, Default)]
+pub struct OrphanReport {
+  /// Number of referrer tags checked.
+  pub tags: usize,
+  pub orphans: Vec<OrphanedReferrer>,
+  /// Referrer tags which could not be checked, e.g. because of network errors.
+  pub errors: Vec<(String, Error)>,
+}
+
+impl Client {
+  /// Find the referrer tags of the repository `name` whose subject no longer exists.
+  ///
+  /// Subjects are checked with a `HEAD` request each, with the concurrency of `options`. Only
+  /// listing the tags fails the whole search: other errors are reported in
+  /// [`OrphanReport::errors`], and the tags concerned are never reported as orphaned.
+  pub async fn find_orphaned_referrers(&self, name: &str, options: &BulkOptions) -> Result<OrphanReport> {
+    let tags: Vec<String> = self.get_tags(name, None).try_collect().await?;
+    let candidates: Vec<(String, String)> = tags
+      .into_iter()
+      .filter_map(|tag| Some((subject_of(&tag)?, tag)))
+      .collect();
+    let mut report = OrphanReport {
+      tags: candidates.len(),
+      ..Default::default()
+    };
+
+    let options = BulkOptions {
+      stop_on_error: false,
+      ..options.clone()
+    };
+    let checked = bulk::run(candidates, &options, |(subject, tag)| async move {
+      self.orphaned_referrer(name, subject, tag).await
+    })
+    .await;
+    for item in checked.items {
+      match item.result {
+        Ok(orphan) => report.orphans.extend(orphan),
+        Err(e) => report.errors.push((item.item.1, e)),
+      }
+    }
+    Ok(report)
+  }
+
+  /// Delete the manifests of the orphaned referrers of `report`, found in the repository `name`.
+  ///
+  /// Deleting a manifest also deletes the referrer tag pointing to it. Only fails, without any
+  /// request, if the client is read-only.
+  pub async fn delete_orphaned_referrers(
+    &self,
+    name: &str,
+    report: &OrphanReport,
+    options: &BulkOptions,
+  ) -> Result<BulkReport<String>> {
+    self.ensure_writable()?;
+    let mut manifests: Vec<String> = Vec::new();
+    for manifest in report.orphans.iter().flat_map(|o| &o.manifests) {
+      if !manifests.contains(manifest) {
+        manifests.push(manifest.clone());
+      }
+    }
+    Ok(self.delete_manifests(name, manifests, options).await)
+  }
+
+  /// The artifacts of the referrer tag `tag`, if its subject no longer exists.
+  async fn orphaned_referrer(&self, name: &str, subject: String, tag: String) -> Result<Option<OrphanedReferrer>> {
+    if self
+      .has_manifest(name, &subject, Some(MANIFEST_MEDIA_TYPES))
+      .await?
+      .is_some()
+    {
+      return Ok(None);
+    }
+    trace!("Subject {} of referrer tag {} does not exist", subject, tag);
+
+    let (manifest, _, digest) = self.get_raw_manifest(name, &tag, Some(MANIFEST_MEDIA_TYPES)).await?;
+    let digest = digest.unwrap_or_else(|| sha256_digest(&manifest));
+    let mut manifests = Vec::new();
+    // Cosign tags point at the artifact itself, others at an index of the referrers.
+    if !tag.contains('.') {
+      manifests.extend(index_children(&manifest)?.into_iter().map(|d| d.digest));
+    }
+    manifests.push(digest);
+    Ok(Some(OrphanedReferrer {
+      tag,
+      subject,
+      manifests,
+    }))
+  }
+}
+
+/// The digest of the subject a referrer tag is named after, if `tag` is one.
+fn subject_of(tag: &str) -> Option<String> {
+  let (digest, suffix) = match tag.split_once('.') {
+    Some((digest, suffix)) => (digest, Some(suffix)),
+    None => (tag, None),
+  };
+  if suffix.is_some_and(|suffix| !COSIGN_SUFFIXES.contains(&suffix)) {
+    return None;
+  }
+  let (algorithm, hex) = digest.split_once('-')?;
+  let length = match algorithm {
+    "sha256" => 64,
+    "sha512" => 128,
+    _ => return None,
+  };
+  let is_hex = hex.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+  (hex.len() == length && is_hex).then(|| format!("{}:{}", algorithm, hex))
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  const HEX: &str = "9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab";
+
+  #[test_case(&format!("sha256-{}", HEX) => Some(format!("sha256:{}", HEX)); "referrers index")]
+  #[test_case(&format!("sha256-{}.sig", HEX) => Some(format!("sha256:{}", HEX)); "cosign signature")]
+  #[test_case(&format!("sha256-{}.att", HEX) => Some(format!("sha256:{}", HEX)); "cosign attestation")]
+  #[test_case(&format!("sha256-{}.txt", HEX) => None; "unknown suffix")]
+  #[test_case(&format!("sha256-{}", &HEX[1..]) => None; "short digest")]
+  #[test_case(&format!("sha256-{}", HEX.to_uppercase()) => None; "uppercase digest")]
+  #[test_case("latest" => None; "tag")]
+  fn referrer_subject(tag: &str) -> Option<String> {
+    subject_of(tag)
+  }
+}
@@ -0,0 +1,178 @@
+//! Parser for `Link` headers, as used to paginate tag lists and the catalog.
+//!
+//! Registries return the URL of the next page of a list in a `Link` header (RFC 8288, formerly
+//! RFC 5988) with the relation type `next`. The client follows these links itself; this module is
+//! for applications paginating other endpoints with `Client::raw_request`.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use docker_registry::pagination;
+//! use url::Url;
+//!
+//! let base =
+//!   Url::parse("https://registry.example.com/v2/busybox/tags/list?n=2")
+//!     .unwrap();
+//! let header = r#"</v2/busybox/tags/list?n=2&last=1.36>; rel="next""#;
+//! let next = pagination::next_url(header, &base).unwrap();
+//! assert_eq!(
+//!   next.as_str(),
+//!   "https://registry.example.com/v2/busybox/tags/list?n=2&last=1.36"
+//! );
+//! ```
+
+use url::Url;
+
+/// A link of a `Link` header: a target URI reference and its parameters.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Link {
+  /// Target of the link, possibly relative to the URL of the response.
+  pub uri: String,
+  /// Parameters of the link, with lowercase names and unquoted values, in order.
+  pub params: Vec<(String, String)>,
+}
+
+impl Link {
+  /// Value of the first parameter named `name`, compared case-insensitively.
+  pub fn param(&self, name: &str) -> Option<&str> {
+    self
+      .params
+      .iter()
+      .find(|(n, _)| n.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+  }
+
+  /// Whether `rel` is one of the relation types of the link.
+  pub fn has_rel(&self, rel: &str) -> bool {
+    self
+      .param("rel")
+      .is_some_and(|rels| rels.split_ascii_whitespace().any(|r| r.eq_ignore_ascii_case(rel)))
+  }
+
+  /// Resolve the target of the link against `base`, the URL of the response.
+  pub fn resolve(&self, base: &Url) -> Result<Url, url::ParseError> {
+    base.join(&self.uri)
+  }
+}
+
+/// Parse the value of a `Link` header into its links.
+///
+/// Parsing is lenient: malformed links are skipped, and parameters without a value have an
+/// empty value.
+pub fn parse_links(value: &str) -> Vec<Link> {
+  let mut links = Vec::new();
+  let mut rest = value;
+  loop {
+    rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    if rest.is_empty() {
+      break;
+    }
+    let link = rest
+      .strip_prefix('<')
+      .and_then(|after| after.split_once('>'))
+      .map(|(uri, after)| {
+        rest = after;
+        Link {
+          uri: uri.trim().to_string(),
+          params: parse_params(&mut rest),
+        }
+      });
+    links.extend(link);
+    // Skip anything left of a malformed link.
+    rest = rest.find(',').map_or("", |i| &rest[i..]);
+  }
+  links
+}
+
+/// Parse the `; name=value` parameters of a link, up to the next link.
+fn parse_params(rest: &mut &str) -> Vec<(String, String)> {
+  let mut params = Vec::new();
+  while let Some(after) = rest.trim_start().strip_prefix(';') {
+    let after = after.trim_start();
+    let name_end = after
+      .find(|c: char| matches!(c, '=' | ';' | ',') || c.is_whitespace())
+      .unwrap_or(after.len());
+    let name = after[..name_end].to_ascii_lowercase();
+    *rest = after[name_end..].trim_start();
+
+    let value = match rest.strip_prefix('=').map(str::trim_start) {
+      Some(after) => match after.strip_prefix('"') {
+        Some(quoted) => {
+          let (value, end) = unquote(quoted);
+          *rest = &quoted[end..];
+          value
+        }
+        None => {
+          let end = after.find([';', ',']).unwrap_or(after.len());
+          *rest = &after[end..];
+          after[..end].trim().to_string()
+        }
+      },
+      None => String::new(),
+    };
+    if !name.is_empty() {
+      params.push((name, value));
+    }
+  }
+  params
+}
+
+/// Unescape a quoted string up to its closing quote, returning it with the length consumed.
+fn unquote(quoted: &str) -> (String, usize) {
+  let mut value = String::new();
+  let mut chars = quoted.char_indices();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '\\' => value.extend(chars.next().map(|(_, c)| c)),
+      '"' => return (value, i + 1),
+      c => value.push(c),
+    }
+  }
+  (value, quoted.len())
+}
+
+/// The first link of a `Link` header with the relation type `next`.
+pub fn next_link(value: &str) -> Option<Link> {
+  parse_links(value).into_iter().find(|l| l.has_rel("next"))
+}
+
+/// The URL of the next page, from the `Link` header of the response received from `base`.
+pub fn next_url(value: &str, base: &Url) -> Option<Url> {
+  next_link(value)?.resolve(base).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  fn link(uri: &str, params: &[(&str, &str)]) -> Link {
+    Link {
+      uri: uri.to_string(),
+      params: params.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+    }
+  }
+
+  #[test_case(r#"</v2/_catalog?n=2&last=b>; rel="next""# => vec![link("/v2/_catalog?n=2&last=b", &[("rel", "next")])]; "registry")]
+  #[test_case("<a>; rel=next, <b>;rel=prev" => vec![link("a", &[("rel", "next")]), link("b", &[("rel", "prev")])]; "multiple links")]
+  #[test_case(r#"<a,b>; title="x, \"y\""; REL=next"# => vec![link("a,b", &[("title", "x, \"y\""), ("rel", "next")])]; "commas and escapes")]
+  #[test_case("<a>; crossorigin; rel=next" => vec![link("a", &[("crossorigin", ""), ("rel", "next")])]; "parameter without value")]
+  #[test_case(r#"garbage, <a>; rel="next"# => vec![link("a", &[("rel", "next")])]; "malformed link and unterminated quote")]
+  #[test_case("<a; rel=next" => Vec::<Link>::new(); "unterminated uri")]
+  #[test_case("" => Vec::<Link>::new(); "empty")]
+  fn parses(value: &str) -> Vec<Link> {
+    parse_links(value)
+  }
+
+  #[test_case(r#"<https://other.example.com/v2/x?last=a>; rel="next""# => Some("https://other.example.com/v2/x?last=a".to_string()); "absolute")]
+  #[test_case(r#"</v2/repo/tags/list?last=a>; rel="next""# => Some("https://registry.example.com/v2/repo/tags/list?last=a".to_string()); "absolute path")]
+  #[test_case(r#"<list?last=a>; rel="next""# => Some("https://registry.example.com/v2/repo/tags/list?last=a".to_string()); "relative path")]
+  #[test_case(r#"<?last=a>; rel="prev first""# => None; "no next")]
+  #[test_case(r#"<?last=b>; rel="prev"; <?last=a>; rel="NEXT last""# => None; "semicolon does not separate links")]
+  #[test_case(r#"<?last=b>; rel="prev", <?last=a>; rel="NEXT last""# => Some("https://registry.example.com/v2/repo/tags/list?last=a".to_string()); "relation types")]
+  fn resolves_next(value: &str) -> Option<String> {
+    let base = Url::parse("https://registry.example.com/v2/repo/tags/list?n=1").unwrap();
+    next_url(value, &base).map(String::from)
+  }
+}
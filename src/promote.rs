@@ -0,0 +1,282 @@
+//! Promoting images between repositories, e.g. from staging to production.
+//!
+//! [`promote`] is the usual deployment pipeline step in one call: it pins the source image to
+//! its digest, checks its signatures and an optional policy, copies it, tags it, and returns a
+//! [`PromotionReport`] which can be signed and archived as evidence of the promotion.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   promote::{promote, PromoteOptions},
+//!   v2::Client,
+//! };
+//!
+//! let staging = Client::configure()
+//!   .registry("staging.example.com")
+//!   .build()?;
+//! let production = Client::configure()
+//!   .registry("registry.example.com")
+//!   .build()?;
+//!
+//! let options = PromoteOptions {
+//!   require_signature: true,
+//!   tags: vec!["stable".to_string()],
+//!   ..Default::default()
+//! };
+//! let report = promote(
+//!   &staging,
+//!   "app",
+//!   "rc1",
+//!   &production,
+//!   "app",
+//!   "1.0.0",
+//!   &options,
+//! )
+//! .await?;
+//! println!("{}", String::from_utf8_lossy(&report.to_bytes()?));
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::{collections::BTreeMap, fmt, sync::Arc, time::UNIX_EPOCH};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  blob_store::rfc3339,
+  copy::{copy_image, CopyOptions, Transform, MANIFEST_MEDIA_TYPES},
+  errors::{Error, Result},
+  signing::cosign_signature_tag,
+  v2::{digest_like, sha256_digest, system_now, Client, Descriptor, HookError, NOTATION_SIGNATURE_ARTIFACT_TYPE},
+};
+
+/// Annotation recording the image a promoted image was copied from, as `registry/name@digest`.
+pub const PROMOTED_FROM_ANNOTATION: &str = "com.github.clowdhaus.docker-registry.promoted-from";
+/// Annotation recording the time of the promotion, as an RFC 3339 timestamp.
+pub const PROMOTED_AT_ANNOTATION: &str = "com.github.clowdhaus.docker-registry.promoted-at";
+
+/// Artifact types of the referrers counted as signatures: Notation and cosign signatures.
+const SIGNATURE_ARTIFACT_TYPES: [&str; 2] = [
+  NOTATION_SIGNATURE_ARTIFACT_TYPE,
+  "application/vnd.dev.cosign.artifact.sig.v1+json",
+];
+
+/// The source image of a promotion, handed to a [`PromotionPolicy`].
+#[derive(Debug)]
+pub struct PromotionSource<'a> {
+  /// The repository of the source image.
+  pub name: &'a str,
+  /// The reference the image was promoted from, as given to [`promote`].
+  pub reference: &'a str,
+  pub digest: &'a str,
+  pub media_type: &'a str,
+  pub manifest: &'a [u8],
+  /// Digests of the signatures of the image.
+  pub signatures: &'a [String],
+}
+
+/// Hook accepting or rejecting the source image of a promotion, e.g. to enforce that it was
+/// scanned or built from a protected branch.
+pub trait PromotionPolicy: fmt::Debug + Send + Sync {
+  /// Accept the image, or fail with the reason of its rejection.
+  fn check(&self, source: &PromotionSource<'_>) -> std::result::Result<(), HookError>;
+}
+
+/// Hook signing promotion reports, with the key of the pipeline.
+pub trait ReportSigner: fmt::Debug + Send + Sync {
+  /// Sign `payload`, the output of [`PromotionReport::payload`].
+  fn sign(&self, payload: &[u8]) -> std::result::Result<Vec<u8>, HookError>;
+}
+
+/// Options of [`promote`].
+#[derive(Clone, Debug, Default)]
+pub struct PromoteOptions {
+  /// Options of the copy; promotion annotations are added to its transforms.
+  pub copy: CopyOptions,
+  /// Reject images without a signature: a referrer with the artifact type of Notation or
+  /// cosign signatures, or a cosign signature tag.
+  pub require_signature: bool,
+  pub policy: Option<Arc<dyn PromotionPolicy>>,
+  /// Record the source and the time of the promotion in the annotations of the copied
+  /// manifests.
+  ///
+  /// Only OCI manifests have annotations, and annotating changes the digest of the image, so
+  /// that signatures of the source no longer apply to the copy.
+  pub annotate: bool,
+  /// Tags pointed at the promoted image, besides the destination reference.
+  pub tags: Vec<String>,
+  /// Signer of the report; the report is not signed without one.
+  pub signer: Option<Arc<dyn ReportSigner>>,
+}
+
+/// Record of a promotion, as returned by [`promote`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PromotionReport {
+  /// The source image, as `registry/name@digest`.
+  pub source: String,
+  /// The reference the image was promoted from, e.g. a tag.
+  pub source_reference: String,
+  /// The destination repository, as `registry/name`.
+  pub destination: String,
+  /// Digest of the promoted image at the destination.
+  pub digest: String,
+  /// Tags pointing at the promoted image, starting with the destination reference.
+  pub tags: Vec<String>,
+  /// Digests of the signatures of the source, if they were looked up.
+  pub signatures: Vec<String>,
+  /// Time of the promotion, in seconds since the Unix epoch.
+  pub promoted_at: u64,
+  /// Base64-encoded signature of the payload of the report, by the [`ReportSigner`].
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub signature: Option<String>,
+}
+
+impl PromotionReport {
+  /// The bytes the report is signed over: the report as JSON, without its signature.
+  pub fn payload(&self) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&PromotionReport {
+      signature: None,
+      ..self.clone()
+    })?)
+  }
+
+  /// The report as JSON, with its signature.
+  pub fn to_bytes(&self) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(self)?)
+  }
+}
+
+/// Promote the image `src_reference` of the repository `src_name` to `dst_reference` of the
+/// repository `dst_name`.
+///
+/// The source is pinned to the digest it is served with, after checking the manifest against
+/// it, so that the image checked is the image copied even if the tag moves meanwhile. Signatures
+/// are looked up if they are required or a policy is set; an image rejected by either fails with
+/// `Error::PromotionRejected`. The image is then copied like [`copy_image`] does, and the
+/// additional tags are pointed at it.
+pub async fn promote(
+  src: &Client,
+  src_name: &str,
+  src_reference: &str,
+  dst: &Client,
+  dst_name: &str,
+  dst_reference: &str,
+  options: &PromoteOptions,
+) -> Result<PromotionReport> {
+  dst.ensure_writable()?;
+  let (manifest, media_type, served) = src
+    .get_raw_manifest(src_name, src_reference, Some(MANIFEST_MEDIA_TYPES))
+    .await?;
+  let digest = match served {
+    Some(served) if digest_like(&served, &manifest) != served => {
+      return Err(Error::PromotionRejected(format!(
+        "manifest of {} does not match its digest {}",
+        src_reference, served
+      )))
+    }
+    Some(served) => served,
+    None => sha256_digest(&manifest),
+  };
+  let source = format!("{}/{}@{}", src.registry_host(), src_name, digest);
+  trace!("Promoting {} to {}:{}", source, dst_name, dst_reference);
+
+  let mut signatures = Vec::new();
+  if options.require_signature || options.policy.is_some() {
+    signatures = find_signatures(src, src_name, &digest).await?;
+    if options.require_signature && signatures.is_empty() {
+      return Err(Error::PromotionRejected(format!("{} is not signed", source)));
+    }
+  }
+  if let Some(policy) = &options.policy {
+    let candidate = PromotionSource {
+      name: src_name,
+      reference: src_reference,
+      digest: &digest,
+      media_type: &media_type,
+      manifest: &manifest,
+      signatures: &signatures,
+    };
+    policy
+      .check(&candidate)
+      .map_err(|e| Error::PromotionRejected(e.to_string()))?;
+  }
+
+  let now = system_now();
+  let mut copy_options = options.copy.clone();
+  if options.annotate {
+    let annotations = BTreeMap::from([
+      (PROMOTED_FROM_ANNOTATION.to_string(), Some(source.clone())),
+      (PROMOTED_AT_ANNOTATION.to_string(), Some(rfc3339(now))),
+    ]);
+    copy_options.transforms.push(Transform::Annotations(annotations));
+  }
+  let promoted = copy_image(src, src_name, &digest, dst, dst_name, dst_reference, &copy_options).await?;
+
+  if !options.tags.is_empty() {
+    let (manifest, media_type, _) = dst
+      .get_raw_manifest(dst_name, &promoted, Some(MANIFEST_MEDIA_TYPES))
+      .await?;
+    for tag in &options.tags {
+      dst.put_manifest(dst_name, tag, &media_type, &manifest).await?;
+    }
+  }
+
+  let mut report = PromotionReport {
+    source,
+    source_reference: src_reference.to_string(),
+    destination: format!("{}/{}", dst.registry_host(), dst_name),
+    digest: promoted,
+    tags: std::iter::once(dst_reference)
+      .chain(options.tags.iter().map(String::as_str))
+      .map(str::to_string)
+      .collect(),
+    signatures,
+    promoted_at: now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+    signature: None,
+  };
+  if let Some(signer) = &options.signer {
+    let signature = signer
+      .sign(&report.payload()?)
+      .map_err(|e| Error::PromotionRejected(format!("cannot sign the report: {}", e)))?;
+    report.signature = Some(STANDARD.encode(signature));
+  }
+  Ok(report)
+}
+
+/// The digests of the signatures of `digest`: signature referrers, then the cosign signature tag.
+async fn find_signatures(client: &Client, name: &str, digest: &str) -> Result<Vec<String>> {
+  let referrers: Vec<Descriptor> = client.get_referrers(name, digest, None).await?;
+  let mut signatures: Vec<String> = referrers
+    .into_iter()
+    .filter(|r| {
+      r.artifact_type
+        .as_deref()
+        .is_some_and(|t| SIGNATURE_ARTIFACT_TYPES.contains(&t))
+    })
+    .map(|r| r.digest)
+    .collect();
+
+  let tag = cosign_signature_tag(digest)?;
+  if client
+    .has_manifest(name, &tag, Some(MANIFEST_MEDIA_TYPES))
+    .await?
+    .is_some()
+  {
+    if let Some(signature) = client.get_manifestref(name, &tag).await? {
+      signatures.push(signature);
+    }
+  }
+  Ok(signatures)
+}
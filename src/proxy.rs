@@ -0,0 +1,178 @@
+//! Building blocks for pull-through caching proxies.
+//!
+//! A registry mirror answers pulls from a local store and only reaches the upstream registry
+//! for content it has not seen yet:
+//!
+//! - a [`BlobStore`] keeps manifests and blobs addressed by digest; content stored by digest never changes, so it never
+//!   needs to be revalidated,
+//! - `Client::get_manifest_cached` resolves tags with a `HEAD` request, which registries such as Docker Hub do not
+//!   count against pull rate limits, and only downloads manifests missing from the store;
+//!   `Client::get_raw_manifest_if_modified` revalidates a cached tag by entity tag,
+//! - `Client::get_blob_cached` downloads and verifies blobs missing from the store,
+//! - `Client::auth_challenge` returns the upstream challenge to relay to the proxy's clients, and
+//!   `Client::with_authorization` forwards the token they present to the upstream registry.
+//!
+//! Content served from the store is not checked against the permissions of the client asking
+//! for it: proxies serving private repositories should check access upstream, for instance
+//! with `Client::has_manifest`, before answering from the store.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{blob_store::FsBlobStore, v2::Client};
+//!
+//! let upstream = Client::configure()
+//!   .registry("registry-1.docker.io")
+//!   .build()?;
+//! let store = FsBlobStore::new("/var/cache/registry");
+//!
+//! // Forward the token presented by the downstream client.
+//! let upstream = upstream.with_authorization("Bearer eyJhbGciOi...")?;
+//! let manifest = upstream
+//!   .get_manifest_cached("library/alpine", "3.20", &store)
+//!   .await?;
+//! for digest in &manifest.referenced_digests()? {
+//!   upstream
+//!     .get_blob_cached("library/alpine", digest, &store)
+//!     .await?;
+//! }
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use log::trace;
+use serde_json::Value;
+
+use crate::{
+  blob_store::{get_bytes, put_bytes, BlobStore},
+  errors::Result,
+  v2::{manifest::ManifestError, sha256_digest, Client, ContentDigest},
+};
+
+/// A manifest served from a `BlobStore` or fetched from the upstream registry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedManifest {
+  /// Raw payload, to serve as is.
+  pub body: Vec<u8>,
+  /// Media type, to serve as `Content-Type`.
+  pub media_type: String,
+  /// Digest of the payload, to serve as `Docker-Content-Digest`.
+  pub digest: String,
+  /// Whether the payload was served from the store.
+  pub cached: bool,
+}
+
+impl CachedManifest {
+  /// Digests of the config and layer blobs of an image manifest, or of the manifests of an index.
+  pub fn referenced_digests(&self) -> Result<Vec<String>> {
+    let value: Value = serde_json::from_slice(&self.body)?;
+    let descriptors = value
+      .get("config")
+      .into_iter()
+      .chain(value.get("layers").and_then(Value::as_array).into_iter().flatten())
+      .chain(value.get("manifests").and_then(Value::as_array).into_iter().flatten());
+    Ok(
+      descriptors
+        .filter_map(|d| d.get("digest").and_then(Value::as_str).map(str::to_string))
+        .collect(),
+    )
+  }
+}
+
+impl Client {
+  /// Fetch a manifest, serving it from `store` when its digest is already stored.
+  ///
+  /// A tag is first resolved with a `HEAD` request; the manifest is only downloaded by that
+  /// digest, verified and stored if the digest is missing from the store. A digest reference is served from the store
+  /// without any request.
+  pub async fn get_manifest_cached(
+    &self,
+    name: &str,
+    reference: &str,
+    store: &dyn BlobStore,
+  ) -> Result<CachedManifest> {
+    let digest = match ContentDigest::try_new(reference) {
+      Ok(_) => Some(reference.to_string()),
+      Err(_) => self.get_manifestref(name, reference).await?,
+    };
+
+    if let Some(digest) = &digest {
+      if store.has(digest).await? {
+        trace!("serving manifest {} from the store", digest);
+        let body = get_bytes(store, digest).await?;
+        return Ok(CachedManifest {
+          media_type: manifest_media_type(&body)?,
+          body,
+          digest: digest.clone(),
+          cached: true,
+        });
+      }
+    }
+
+    // Fetch by the resolved digest, so that a tag moved since the HEAD cannot be stored under it.
+    let (body, media_type, _) = self
+      .get_raw_manifest(name, digest.as_deref().unwrap_or(reference), None)
+      .await?;
+    // The store verifies the payload against the digest the tag resolved to.
+    let digest = digest.unwrap_or_else(|| sha256_digest(&body));
+    put_bytes(store, &digest, body.clone()).await?;
+
+    Ok(CachedManifest {
+      body,
+      media_type: media_type.split(';').next().unwrap_or_default().trim().to_string(),
+      digest,
+      cached: false,
+    })
+  }
+
+  /// Fetch a blob, serving it from `store` when already stored.
+  ///
+  /// Blobs missing from the store are downloaded, verified against `digest` and stored.
+  pub async fn get_blob_cached(&self, name: &str, digest: &str, store: &dyn BlobStore) -> Result<Vec<u8>> {
+    if store.has(digest).await? {
+      trace!("serving blob {} from the store", digest);
+      return get_bytes(store, digest).await;
+    }
+    let data = self.get_blob(name, digest).await?;
+    put_bytes(store, digest, data.clone()).await?;
+    Ok(data)
+  }
+}
+
+/// Media type of a stored manifest, from its `mediaType` field or its shape.
+fn manifest_media_type(body: &[u8]) -> Result<String> {
+  let value: Value = serde_json::from_slice(body)?;
+  if let Some(media_type) = value.get("mediaType").and_then(Value::as_str) {
+    return Ok(media_type.to_string());
+  }
+  match value.get("schemaVersion").and_then(Value::as_u64) {
+    Some(1) => Ok("application/vnd.docker.distribution.manifest.v1+prettyjws".to_string()),
+    Some(2) if value.get("manifests").is_some() => Ok("application/vnd.oci.image.index.v1+json".to_string()),
+    Some(2) => Ok("application/vnd.oci.image.manifest.v1+json".to_string()),
+    _ => Err(ManifestError::Invalid("cannot determine the media type of a stored manifest".to_string()).into()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case(r#"{"schemaVersion":2,"mediaType":"application/vnd.docker.distribution.manifest.v2+json"}"# => "application/vnd.docker.distribution.manifest.v2+json"; "declared")]
+  #[test_case(r#"{"schemaVersion":2,"manifests":[]}"# => "application/vnd.oci.image.index.v1+json"; "oci index")]
+  #[test_case(r#"{"schemaVersion":2,"config":{},"layers":[]}"# => "application/vnd.oci.image.manifest.v1+json"; "oci manifest")]
+  #[test_case(r#"{"schemaVersion":1,"fsLayers":[]}"# => "application/vnd.docker.distribution.manifest.v1+prettyjws"; "schema 1")]
+  fn stored_manifest_media_type(body: &str) -> String {
+    manifest_media_type(body.as_bytes()).unwrap()
+  }
+}
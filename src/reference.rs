@@ -35,6 +35,11 @@ pub static DEFAULT_REGISTRY: &str = "registry-1.docker.io";
 static DEFAULT_TAG: &str = "latest";
 static DEFAULT_SCHEME: &str = "docker";
 
+/// Path component grammar from the distribution spec.
+const PATH_COMPONENT_REGEX: &str = "^[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*$";
+/// Maximum length of a repository name.
+const MAX_REPOSITORY_LENGTH: usize = 255;
+
 /// Image version, either a tag or a digest.
 #[derive(Clone)]
 pub enum Version {
@@ -97,13 +102,95 @@ impl fmt::Display for Version {
   }
 }
 
+/// Maximum length of a tag.
+const MAX_TAG_LENGTH: usize = 128;
+
+/// A validated image tag.
+///
+/// Tags are at most 128 characters of `[A-Za-z0-9_.-]`, and cannot start with `.` or `-`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Tag(String);
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TagParseError {
+  #[error("tag is empty")]
+  Empty,
+  #[error("tag is {0} characters long, the maximum is 128")]
+  TooLong(usize),
+  #[error("tag cannot start with '{0}'")]
+  InvalidStart(char),
+  #[error("tag contains invalid character '{character}' at position {position}, only [A-Za-z0-9_.-] are allowed")]
+  InvalidCharacter { character: char, position: usize },
+}
+
+impl Tag {
+  /// Parse a tag, rejecting tags which registries would refuse.
+  pub fn parse(tag: &str) -> Result<Self, TagParseError> {
+    let first = tag.chars().next().ok_or(TagParseError::Empty)?;
+    if tag.len() > MAX_TAG_LENGTH {
+      return Err(TagParseError::TooLong(tag.chars().count()));
+    }
+    if first == '.' || first == '-' {
+      return Err(TagParseError::InvalidStart(first));
+    }
+    match tag.char_indices().find(|(_, c)| !is_tag_char(*c)) {
+      Some((position, character)) => Err(TagParseError::InvalidCharacter { character, position }),
+      None => Ok(Tag(tag.to_string())),
+    }
+  }
+
+  /// Derive a valid tag from an arbitrary string, such as a branch name or a version.
+  ///
+  /// Invalid characters are replaced with `-`, leading `.` and `-` are removed and the result
+  /// is truncated to 128 characters, e.g. `feature/new-ui` becomes `feature-new-ui`. Returns
+  /// `None` if nothing usable remains.
+  pub fn sanitize(input: &str) -> Option<Self> {
+    let replaced: String = input
+      .trim()
+      .chars()
+      .map(|c| if is_tag_char(c) { c } else { '-' })
+      .collect();
+    let tag: String = replaced
+      .trim_start_matches(['.', '-'])
+      .chars()
+      .take(MAX_TAG_LENGTH)
+      .collect();
+    Tag::parse(&tag).ok()
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+fn is_tag_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-'
+}
+
+impl str::FromStr for Tag {
+  type Err = TagParseError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Tag::parse(s)
+  }
+}
+
+impl fmt::Display for Tag {
+  fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+    write!(f, "{}", self.0)
+  }
+}
+
 /// A registry image reference.
+///
+/// References may name both a tag and a digest, as in `busybox:1.36@sha256:...`: the digest
+/// identifies the image, the tag is only a hint for humans.
 #[derive(Clone, Debug, Default)]
 pub struct Reference {
   raw_input: String,
   registry: String,
   repository: String,
   version: Version,
+  tag_hint: Option<Tag>,
 }
 
 impl Reference {
@@ -115,6 +202,7 @@ impl Reference {
       registry: reg,
       repository,
       version: ver,
+      tag_hint: None,
     }
   }
 
@@ -130,6 +218,24 @@ impl Reference {
     self.version.to_string()
   }
 
+  /// Tag given along with the digest, if any.
+  pub fn tag_hint(&self) -> Option<String> {
+    self.tag_hint.as_ref().map(Tag::to_string)
+  }
+
+  /// Reference to pull the image with `Client::pull_image`: the version, preceded by the tag
+  /// hint if any, as in `1.36@sha256:...`.
+  pub fn pull_reference(&self) -> String {
+    match &self.tag_hint {
+      Some(tag) => format!("{}@{}", tag, self.version),
+      None => self.version(),
+    }
+  }
+
+  fn tag_hint_prefix(&self) -> String {
+    self.tag_hint.as_ref().map(|t| format!(":{}", t)).unwrap_or_default()
+  }
+
   pub fn to_raw_string(&self) -> String {
     self.raw_input.clone()
   }
@@ -137,15 +243,26 @@ impl Reference {
   //TODO(lucab): move this to a real URL type
   pub fn to_url(&self) -> String {
     format!(
-      "{}://{}/{}{:?}",
-      DEFAULT_SCHEME, self.registry, self.repository, self.version
+      "{}://{}/{}{}{:?}",
+      DEFAULT_SCHEME,
+      self.registry,
+      self.repository,
+      self.tag_hint_prefix(),
+      self.version
     )
   }
 }
 
 impl fmt::Display for Reference {
   fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-    write!(f, "{}/{}{:?}", self.registry, self.repository, self.version)
+    write!(
+      f,
+      "{}/{}{}{:?}",
+      self.registry,
+      self.repository,
+      self.tag_hint_prefix(),
+      self.version
+    )
   }
 }
 
@@ -170,6 +287,8 @@ pub enum ReferenceParseError {
   EmptyRepositoryName,
   #[error("repository name too long")]
   RepositoryNameTooLong,
+  #[error("tag hint is invalid")]
+  TagHint(#[from] TagParseError),
 }
 
 fn parse_url(input: &str) -> Result<Reference, ReferenceParseError> {
@@ -218,6 +337,11 @@ fn parse_url(input: &str) -> Result<Reference, ReferenceParseError> {
     }
     (None, None) => (last, Version::default()),
   };
+  // A tag before a digest, as in `busybox:1.36@sha256:...`, is only a hint.
+  let (image_name, tag_hint) = match (&version, image_name.rfind(':')) {
+    (Version::Digest(..), Some(i)) => (image_name[..i].to_string(), Some(Tag::parse(&image_name[i + 1..])?)),
+    _ => (image_name, None),
+  };
   if image_name.is_empty() {
     return Err(ReferenceParseError::EmptyImageName);
   }
@@ -229,34 +353,55 @@ fn parse_url(input: &str) -> Result<Reference, ReferenceParseError> {
   }
   components.push_back(image_name);
 
-  // Check if all path components conform to the regex at
-  // https://docs.docker.com/registry/spec/api/#overview.
-  const REGEX: &str = "^[a-z0-9]+(?:[._-][a-z0-9]+)*$";
-  let path_re = Regex::new(REGEX).expect("hardcoded regex is invalid");
-  components.iter().try_for_each(|component| {
-    if !path_re.is_match(component) {
-      return Err(ReferenceParseError::RegexViolation {
-        component: component.clone(),
-        regex: REGEX,
-      });
-    };
-
-    Ok(())
-  })?;
-
-  // Re-assemble repository name.
+  // Re-assemble and validate repository name.
   let repository = components.into_iter().collect::<Vec<_>>().join("/");
-  if repository.is_empty() {
-    return Err(ReferenceParseError::EmptyRepositoryName);
-  }
-  if repository.len() > 127 {
-    return Err(ReferenceParseError::RepositoryNameTooLong);
-  }
+  validate_repository_name(&repository)?;
 
   Ok(Reference {
     raw_input: input.to_string(),
     registry,
     repository,
     version,
+    tag_hint,
   })
 }
+
+/// Check that a repository name conforms to the [distribution spec][spec].
+///
+/// Names are made of `/`-separated path components of lowercase alphanumerics, optionally
+/// separated by `.`, `_`, `__` or dashes, and are at most 255 characters long.
+///
+/// [spec]: https://github.com/opencontainers/distribution-spec/blob/v1.1.0/spec.md#pulling-manifests
+pub fn validate_repository_name(name: &str) -> Result<(), ReferenceParseError> {
+  if name.is_empty() {
+    return Err(ReferenceParseError::EmptyRepositoryName);
+  }
+  if name.len() > MAX_REPOSITORY_LENGTH {
+    return Err(ReferenceParseError::RepositoryNameTooLong);
+  }
+
+  let path_re = Regex::new(PATH_COMPONENT_REGEX).expect("hardcoded regex is invalid");
+  match name.split('/').find(|component| !path_re.is_match(component)) {
+    Some(component) => Err(ReferenceParseError::RegexViolation {
+      component: component.to_string(),
+      regex: PATH_COMPONENT_REGEX,
+    }),
+    None => Ok(()),
+  }
+}
+
+/// Normalize a repository name and check that it is valid.
+///
+/// Surrounding whitespace, leading, trailing and repeated slashes are removed and the name
+/// is lowercased, e.g. ` /MyOrg//App/ ` becomes `myorg/app`.
+pub fn normalize_repository_name(name: &str) -> Result<String, ReferenceParseError> {
+  let normalized = name
+    .trim()
+    .split('/')
+    .filter(|c| !c.is_empty())
+    .collect::<Vec<_>>()
+    .join("/")
+    .to_lowercase();
+  validate_repository_name(&normalized)?;
+  Ok(normalized)
+}
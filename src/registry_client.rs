@@ -0,0 +1,196 @@
+//! The operations of a registry client as a trait, for mocking the registry in tests.
+//!
+//! Applications taking a [`RegistryClient`] rather than a [`Client`] can be tested against an
+//! implementation of their own, serving canned manifests and blobs from memory, without
+//! starting an HTTP server. The methods have the names and arguments of their counterparts of
+//! `Client`, with boxed futures and streams so that the trait can be used as
+//! `&dyn RegistryClient`.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{registry_client::RegistryClient, v2::Client};
+//! use futures::TryStreamExt;
+//!
+//! // Tests call this with a fake registry instead.
+//! async fn latest_release(
+//!   registry: &dyn RegistryClient,
+//!   name: &str,
+//! ) -> docker_registry::errors::Result<Option<String>> {
+//!   let tags: Vec<String> = registry.get_tags(name, None).try_collect().await?;
+//!   Ok(tags.into_iter().filter(|t| t.starts_with('v')).max())
+//! }
+//!
+//! let client = Client::configure().registry("quay.io").build()?;
+//! println!("{:?}", latest_release(&client, "coreos/etcd").await?);
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::fmt;
+
+use futures::{
+  future::BoxFuture,
+  stream::{BoxStream, StreamExt},
+  FutureExt,
+};
+
+use crate::{
+  errors::Result,
+  mediatypes::MediaTypes,
+  v2::{manifest::Manifest, Client, Descriptor},
+};
+
+/// Future returned by [`RegistryClient`] methods.
+pub type RegistryFuture<'a, T> = BoxFuture<'a, Result<T>>;
+
+/// Stream returned by [`RegistryClient`] methods listing items.
+pub type RegistryStream<'a, T> = BoxStream<'a, Result<T>>;
+
+/// Operations on a registry, implemented by [`Client`].
+pub trait RegistryClient: fmt::Debug + Send + Sync {
+  /// Whether the registry supports the v2 API, see `Client::is_v2_supported`.
+  fn is_v2_supported(&self) -> RegistryFuture<'_, bool>;
+
+  /// List the repositories of the registry, see `Client::get_catalog`.
+  fn get_catalog(&self, paginate: Option<u32>) -> RegistryStream<'_, String>;
+
+  /// List the tags of the repository `name`, see `Client::get_tags`.
+  fn get_tags<'a>(&'a self, name: &'a str, paginate: Option<u32>) -> RegistryStream<'a, String>;
+
+  /// Check if the manifest `reference` of `name` exists, see `Client::has_manifest`.
+  fn has_manifest<'a>(
+    &'a self,
+    name: &'a str,
+    reference: &'a str,
+    mediatypes: Option<&'a [&'a str]>,
+  ) -> RegistryFuture<'a, Option<MediaTypes>>;
+
+  /// Fetch and parse the manifest `reference` of `name`, see `Client::get_manifest`.
+  fn get_manifest<'a>(&'a self, name: &'a str, reference: &'a str) -> RegistryFuture<'a, Manifest>;
+
+  /// Fetch the manifest `reference` of `name` without parsing it, see
+  /// `Client::get_raw_manifest`.
+  fn get_raw_manifest<'a>(
+    &'a self,
+    name: &'a str,
+    reference: &'a str,
+    accept: Option<&'a [&'a str]>,
+  ) -> RegistryFuture<'a, (Vec<u8>, String, Option<String>)>;
+
+  /// Push a manifest, returning its digest, see `Client::put_manifest`.
+  fn put_manifest<'a>(
+    &'a self,
+    name: &'a str,
+    reference: &'a str,
+    media_type: &'a str,
+    body: &'a [u8],
+  ) -> RegistryFuture<'a, String>;
+
+  /// Delete the manifest `reference` of `name`, see `Client::delete_manifest`.
+  fn delete_manifest<'a>(&'a self, name: &'a str, reference: &'a str) -> RegistryFuture<'a, ()>;
+
+  /// Whether the blob `digest` of `name` exists, see `Client::has_blob`.
+  fn has_blob<'a>(&'a self, name: &'a str, digest: &'a str) -> RegistryFuture<'a, bool>;
+
+  /// Download the blob `digest` of `name`, see `Client::get_blob`.
+  fn get_blob<'a>(&'a self, name: &'a str, digest: &'a str) -> RegistryFuture<'a, Vec<u8>>;
+
+  /// Download the blob `digest` of `name` in chunks, see `Client::get_blob_stream`.
+  fn get_blob_stream<'a>(&'a self, name: &'a str, digest: &'a str) -> RegistryFuture<'a, RegistryStream<'a, Vec<u8>>>;
+
+  /// Upload `data` as the blob `digest` of `name`, see `Client::push_blob`.
+  fn push_blob<'a>(&'a self, name: &'a str, data: &'a [u8], digest: &'a str) -> RegistryFuture<'a, String>;
+
+  /// List the referrers of the manifest `digest` of `name`, see `Client::get_referrers`.
+  fn get_referrers<'a>(
+    &'a self,
+    name: &'a str,
+    digest: &'a str,
+    artifact_type: Option<&'a str>,
+  ) -> RegistryFuture<'a, Vec<Descriptor>>;
+}
+
+impl RegistryClient for Client {
+  fn is_v2_supported(&self) -> RegistryFuture<'_, bool> {
+    Client::is_v2_supported(self).boxed()
+  }
+
+  fn get_catalog(&self, paginate: Option<u32>) -> RegistryStream<'_, String> {
+    Client::get_catalog(self, paginate).boxed()
+  }
+
+  fn get_tags<'a>(&'a self, name: &'a str, paginate: Option<u32>) -> RegistryStream<'a, String> {
+    Client::get_tags(self, name, paginate).boxed()
+  }
+
+  fn has_manifest<'a>(
+    &'a self,
+    name: &'a str,
+    reference: &'a str,
+    mediatypes: Option<&'a [&'a str]>,
+  ) -> RegistryFuture<'a, Option<MediaTypes>> {
+    Client::has_manifest(self, name, reference, mediatypes).boxed()
+  }
+
+  fn get_manifest<'a>(&'a self, name: &'a str, reference: &'a str) -> RegistryFuture<'a, Manifest> {
+    Client::get_manifest(self, name, reference).boxed()
+  }
+
+  fn get_raw_manifest<'a>(
+    &'a self,
+    name: &'a str,
+    reference: &'a str,
+    accept: Option<&'a [&'a str]>,
+  ) -> RegistryFuture<'a, (Vec<u8>, String, Option<String>)> {
+    Client::get_raw_manifest(self, name, reference, accept).boxed()
+  }
+
+  fn put_manifest<'a>(
+    &'a self,
+    name: &'a str,
+    reference: &'a str,
+    media_type: &'a str,
+    body: &'a [u8],
+  ) -> RegistryFuture<'a, String> {
+    Client::put_manifest(self, name, reference, media_type, body).boxed()
+  }
+
+  fn delete_manifest<'a>(&'a self, name: &'a str, reference: &'a str) -> RegistryFuture<'a, ()> {
+    Client::delete_manifest(self, name, reference).boxed()
+  }
+
+  fn has_blob<'a>(&'a self, name: &'a str, digest: &'a str) -> RegistryFuture<'a, bool> {
+    Client::has_blob(self, name, digest).boxed()
+  }
+
+  fn get_blob<'a>(&'a self, name: &'a str, digest: &'a str) -> RegistryFuture<'a, Vec<u8>> {
+    Client::get_blob(self, name, digest).boxed()
+  }
+
+  fn get_blob_stream<'a>(&'a self, name: &'a str, digest: &'a str) -> RegistryFuture<'a, RegistryStream<'a, Vec<u8>>> {
+    async move { Ok(Client::get_blob_stream(self, name, digest).await?.boxed()) }.boxed()
+  }
+
+  fn push_blob<'a>(&'a self, name: &'a str, data: &'a [u8], digest: &'a str) -> RegistryFuture<'a, String> {
+    Client::push_blob(self, name, data, digest).boxed()
+  }
+
+  fn get_referrers<'a>(
+    &'a self,
+    name: &'a str,
+    digest: &'a str,
+    artifact_type: Option<&'a str>,
+  ) -> RegistryFuture<'a, Vec<Descriptor>> {
+    Client::get_referrers(self, name, digest, artifact_type).boxed()
+  }
+}
@@ -3,7 +3,13 @@
 // Docker image format is specified at
 // https://github.com/moby/moby/blob/v17.05.0-ce/image/spec/v1.md
 
-use std::{fs, io, path};
+use std::{
+  cell::Cell,
+  fs,
+  io::{self, Read},
+  path,
+  rc::Rc,
+};
 
 use libflate::gzip;
 
@@ -13,6 +19,51 @@ pub enum RenderError {
   WrongTargetPath(path::PathBuf),
   #[error("io error")]
   Io(#[from] std::io::Error),
+  #[error("decompressed layer exceeds the configured limits")]
+  DecompressionLimitExceeded,
+}
+
+/// Limits applied while decompressing layers, to protect against decompression bombs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DecompressionLimits {
+  /// Maximum decompressed size of a single layer, in bytes.
+  pub max_size: Option<u64>,
+  /// Maximum ratio between the decompressed and compressed size of a single layer.
+  pub max_ratio: Option<u64>,
+}
+
+impl DecompressionLimits {
+  /// Maximum number of decompressed bytes allowed for a layer of `compressed` bytes.
+  fn budget(&self, compressed: usize) -> Option<u64> {
+    let by_ratio = self.max_ratio.map(|r| r.saturating_mul(compressed as u64));
+    match (self.max_size, by_ratio) {
+      (Some(a), Some(b)) => Some(a.min(b)),
+      (a, b) => a.or(b),
+    }
+  }
+}
+
+/// Reader failing once more than `budget` bytes went through it.
+struct LimitedReader<R> {
+  inner: R,
+  remaining: Option<u64>,
+  exceeded: Rc<Cell<bool>>,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    if let Some(remaining) = self.remaining.as_mut() {
+      match remaining.checked_sub(n as u64) {
+        Some(r) => *remaining = r,
+        None => {
+          self.exceeded.set(true);
+          return Err(io::Error::new(io::ErrorKind::Other, "decompression limit exceeded"));
+        }
+      }
+    }
+    Ok(n)
+  }
 }
 
 /// Unpack an ordered list of layers to a target directory.
@@ -20,7 +71,21 @@ pub enum RenderError {
 /// Layers must be provided as gzip-compressed tar archives, with lower layers
 /// coming first. Target directory must be an existing absolute path.
 pub fn unpack(layers: &[Vec<u8>], target_dir: &path::Path) -> Result<(), RenderError> {
-  _unpack(layers, target_dir, |mut archive, target_dir| {
+  unpack_with_limits(layers, target_dir, DecompressionLimits::default())
+}
+
+/// Unpack an ordered list of layers to a target directory, enforcing decompression limits.
+///
+/// Layers must be provided as gzip-compressed tar archives, with lower layers
+/// coming first. Target directory must be an existing absolute path.
+/// Unpacking stops with `RenderError::DecompressionLimitExceeded` as soon as a
+/// layer decompresses to more than the limits allow.
+pub fn unpack_with_limits(
+  layers: &[Vec<u8>],
+  target_dir: &path::Path,
+  limits: DecompressionLimits,
+) -> Result<(), RenderError> {
+  _unpack(layers, target_dir, limits, |mut archive, target_dir| {
     Ok(archive.unpack(target_dir)?)
   })
 }
@@ -34,59 +99,87 @@ pub fn filter_unpack<P>(layers: &[Vec<u8>], target_dir: &path::Path, predicate:
 where
   P: Fn(&path::Path) -> bool,
 {
-  _unpack(layers, target_dir, |mut archive, target_dir| {
-    for entry in archive.entries()? {
-      let mut entry = entry?;
-      let path = entry.path()?;
+  _unpack(
+    layers,
+    target_dir,
+    DecompressionLimits::default(),
+    |mut archive, target_dir| {
+      for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?;
 
-      if predicate(&path) {
-        entry.unpack_in(target_dir)?;
+        if predicate(&path) {
+          entry.unpack_in(target_dir)?;
+        }
       }
-    }
 
-    Ok(())
-  })
+      Ok(())
+    },
+  )
 }
 
-fn _unpack<U>(layers: &[Vec<u8>], target_dir: &path::Path, unpacker: U) -> Result<(), RenderError>
+type LayerReader<'a> = LimitedReader<gzip::Decoder<&'a [u8]>>;
+
+fn _unpack<U>(
+  layers: &[Vec<u8>],
+  target_dir: &path::Path,
+  limits: DecompressionLimits,
+  unpacker: U,
+) -> Result<(), RenderError>
 where
-  U: Fn(tar::Archive<gzip::Decoder<&[u8]>>, &path::Path) -> Result<(), RenderError>,
+  U: Fn(tar::Archive<LayerReader>, &path::Path) -> Result<(), RenderError>,
 {
   if !target_dir.is_absolute() || !target_dir.exists() || !target_dir.is_dir() {
     return Err(RenderError::WrongTargetPath(target_dir.to_path_buf()));
   }
   for l in layers {
+    let exceeded = Rc::new(Cell::new(false));
+    let reader = || -> io::Result<LayerReader> {
+      Ok(LimitedReader {
+        inner: gzip::Decoder::new(l.as_slice())?,
+        remaining: limits.budget(l.len()),
+        exceeded: exceeded.clone(),
+      })
+    };
+    let check_limits = |e: RenderError| match exceeded.get() {
+      true => RenderError::DecompressionLimitExceeded,
+      false => e,
+    };
+
     // Unpack layers
-    let gz_dec = gzip::Decoder::new(l.as_slice())?;
-    let mut archive = tar::Archive::new(gz_dec);
+    let mut archive = tar::Archive::new(reader()?);
     archive.set_preserve_permissions(true);
     archive.set_unpack_xattrs(true);
-    unpacker(archive, target_dir)?;
+    unpacker(archive, target_dir).map_err(check_limits)?;
 
     // Clean whiteouts
-    let gz_dec = gzip::Decoder::new(l.as_slice())?;
-    let mut archive = tar::Archive::new(gz_dec);
-    for entry in archive.entries()? {
-      let file = entry?;
-      let path = file.path()?;
-      let parent = path.parent().unwrap_or_else(|| path::Path::new("/"));
-      if let Some(fname) = path.file_name() {
-        let wh_name = fname.to_string_lossy();
-        if wh_name == ".wh..wh..opq" {
-          //TODO(lucab): opaque whiteout, dir removal
-        } else if wh_name.starts_with(".wh.") {
-          let rel_parent = path::PathBuf::from("./".to_string() + &parent.to_string_lossy());
-
-          // Remove real file behind whiteout
-          let real_name = wh_name.trim_start_matches(".wh.");
-          let abs_real_path = target_dir.join(&rel_parent).join(real_name);
-          remove_whiteout(abs_real_path)?;
-
-          // Remove whiteout place-holder
-          let abs_wh_path = target_dir.join(&rel_parent).join(fname);
-          remove_whiteout(abs_wh_path)?;
-        };
-      }
+    let mut archive = tar::Archive::new(reader()?);
+    clean_whiteouts(&mut archive, target_dir).map_err(check_limits)?;
+  }
+  Ok(())
+}
+
+fn clean_whiteouts(archive: &mut tar::Archive<LayerReader>, target_dir: &path::Path) -> Result<(), RenderError> {
+  for entry in archive.entries()? {
+    let file = entry?;
+    let path = file.path()?;
+    let parent = path.parent().unwrap_or_else(|| path::Path::new("/"));
+    if let Some(fname) = path.file_name() {
+      let wh_name = fname.to_string_lossy();
+      if wh_name == ".wh..wh..opq" {
+        //TODO(lucab): opaque whiteout, dir removal
+      } else if wh_name.starts_with(".wh.") {
+        let rel_parent = path::PathBuf::from("./".to_string() + &parent.to_string_lossy());
+
+        // Remove real file behind whiteout
+        let real_name = wh_name.trim_start_matches(".wh.");
+        let abs_real_path = target_dir.join(&rel_parent).join(real_name);
+        remove_whiteout(abs_real_path)?;
+
+        // Remove whiteout place-holder
+        let abs_wh_path = target_dir.join(&rel_parent).join(fname);
+        remove_whiteout(abs_wh_path)?;
+      };
     }
   }
   Ok(())
@@ -107,3 +200,54 @@ fn remove_whiteout(path: path::PathBuf) -> io::Result<()> {
     },
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+
+  use super::*;
+
+  fn zeroes_layer(size: usize) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+      .append_data(&mut header, "zeroes", vec![0u8; size].as_slice())
+      .unwrap();
+    let tarball = builder.into_inner().unwrap();
+
+    let mut encoder = gzip::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(&tarball).unwrap();
+    encoder.finish().into_result().unwrap()
+  }
+
+  #[test]
+  fn unpack_with_limits_rejects_decompression_bombs() {
+    let layers = vec![zeroes_layer(1 << 20)];
+
+    for limits in [
+      DecompressionLimits {
+        max_size: Some(1 << 10),
+        max_ratio: None,
+      },
+      DecompressionLimits {
+        max_size: None,
+        max_ratio: Some(10),
+      },
+    ] {
+      let dir = tempfile::tempdir().unwrap();
+      let res = unpack_with_limits(&layers, dir.path(), limits);
+      assert!(matches!(res, Err(RenderError::DecompressionLimitExceeded)), "{:?}", res);
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let limits = DecompressionLimits {
+      max_size: Some(2 << 20),
+      max_ratio: None,
+    };
+    unpack_with_limits(&layers, dir.path(), limits).unwrap();
+    assert_eq!(fs::metadata(dir.path().join("zeroes")).unwrap().len(), 1 << 20);
+  }
+}
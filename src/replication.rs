@@ -0,0 +1,278 @@
+//! Verifying that images were replicated to another registry.
+//!
+//! After a migration or while mirroring, [`verify_replication`] checks that images available
+//! in a source registry are available unchanged in a destination registry: that references
+//! resolve to the same manifest digests, that every blob they reference exists at the
+//! destination and, optionally, that a sample of the blobs can be downloaded intact.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::{
+//!   replication::{verify_replication, ReplicationOptions, ReplicationRef},
+//!   v2::Client,
+//! };
+//!
+//! let src = Client::configure().registry("quay.io").build()?;
+//! let dst = Client::configure().registry("localhost:5000").build()?;
+//!
+//! let refs =
+//!   vec![ReplicationRef::new("coreos/etcd", "v3.1.0").to("etcd", "v3.1.0")];
+//! let options = ReplicationOptions {
+//!   sample_blobs: 2,
+//!   ..Default::default()
+//! };
+//! let report = verify_replication(&src, &dst, refs, &options).await;
+//! for item in &report.items {
+//!   match &item.result {
+//!     Ok(discrepancies) if discrepancies.is_empty() => {
+//!       println!("{}: ok", item.item)
+//!     }
+//!     Ok(discrepancies) => println!("{}: {:?}", item.item, discrepancies),
+//!     Err(e) => println!("{}: cannot verify: {}", item.item, e),
+//!   }
+//! }
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::fmt;
+
+use futures::StreamExt;
+use log::trace;
+use serde_json::Value;
+
+use crate::{
+  bulk::{self, BulkOptions, BulkReport},
+  copy::{descriptor, layers_of, manifest_kind, ManifestKind, MANIFEST_MEDIA_TYPES},
+  errors::{Error, Result},
+  v2::{digest_like, sha256_digest, Client},
+};
+
+/// An image to verify, by repository and reference at the source and at the destination.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplicationRef {
+  pub source_name: String,
+  pub source_reference: String,
+  pub destination_name: String,
+  pub destination_reference: String,
+}
+
+impl ReplicationRef {
+  /// An image replicated under the same repository and reference.
+  pub fn new(name: &str, reference: &str) -> Self {
+    Self {
+      source_name: name.to_string(),
+      source_reference: reference.to_string(),
+      destination_name: name.to_string(),
+      destination_reference: reference.to_string(),
+    }
+  }
+
+  /// Look the image up under another repository and reference at the destination.
+  pub fn to(mut self, name: &str, reference: &str) -> Self {
+    self.destination_name = name.to_string();
+    self.destination_reference = reference.to_string();
+    self
+  }
+}
+
+impl fmt::Display for ReplicationRef {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.source_name, self.source_reference)?;
+    if self.destination_name != self.source_name || self.destination_reference != self.source_reference {
+      write!(f, " -> {}:{}", self.destination_name, self.destination_reference)?;
+    }
+    Ok(())
+  }
+}
+
+/// Options of [`verify_replication`].
+#[derive(Clone, Debug, Default)]
+pub struct ReplicationOptions {
+  /// Concurrency and error handling across images.
+  pub bulk: BulkOptions,
+  /// Number of layers per image downloaded from the destination to check their content
+  /// against their digest; other blobs are only checked for existence.
+  pub sample_blobs: usize,
+}
+
+/// A difference between the source and the destination of a replicated image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Discrepancy {
+  /// The manifest is not available at the destination.
+  MissingManifest { digest: String },
+  /// The destination reference points to another manifest than the source reference.
+  ManifestMismatch { source: String, destination: String },
+  /// A config or layer blob is not available at the destination.
+  MissingBlob { digest: String },
+  /// A sampled blob downloaded from the destination does not match its digest.
+  CorruptBlob { digest: String },
+}
+
+/// Verify that images were replicated from `src` to `dst`, reporting the discrepancies found
+/// for every image.
+///
+/// Manifest lists and OCI indexes are verified together with every manifest they reference.
+/// Images whose manifest differs at the destination are not verified further. Items only fail
+/// if the source image cannot be read or the destination registry cannot be queried.
+pub async fn verify_replication(
+  src: &Client,
+  dst: &Client,
+  refs: Vec<ReplicationRef>,
+  options: &ReplicationOptions,
+) -> BulkReport<ReplicationRef, Vec<Discrepancy>> {
+  bulk::run(refs, &options.bulk, |r| async move {
+    let mut discrepancies = Vec::new();
+    let (manifest, media_type, digest) = src
+      .get_raw_manifest(&r.source_name, &r.source_reference, Some(MANIFEST_MEDIA_TYPES))
+      .await?;
+    let digest = digest.unwrap_or_else(|| sha256_digest(&manifest));
+
+    match destination_digest(dst, &r.destination_name, &r.destination_reference, &digest).await? {
+      None => discrepancies.push(Discrepancy::MissingManifest { digest }),
+      Some(destination) if destination != digest => discrepancies.push(Discrepancy::ManifestMismatch {
+        source: digest,
+        destination,
+      }),
+      Some(_) => {
+        let verifier = Verifier {
+          src,
+          dst,
+          r: &r,
+          sample_blobs: options.sample_blobs,
+        };
+        verifier.verify(&manifest, &media_type, &mut discrepancies).await?;
+      }
+    }
+    Ok(discrepancies)
+  })
+  .await
+}
+
+/// Digest of the manifest `reference` points to at the destination, computed with the
+/// algorithm of `source_digest`, or `None` if it does not exist.
+///
+/// The digest reported by the `HEAD` request is used when it has the same algorithm; the
+/// manifest is only downloaded and hashed otherwise.
+async fn destination_digest(dst: &Client, name: &str, reference: &str, source_digest: &str) -> Result<Option<String>> {
+  let head = match dst.find_manifest(name, reference).await? {
+    Some(head) => head,
+    None => return Ok(None),
+  };
+  let algorithm = |digest: &str| digest.split_once(':').map(|(algorithm, _)| algorithm.to_string());
+  if let Some(digest) = head.digest {
+    if algorithm(&digest) == algorithm(source_digest) {
+      return Ok(Some(digest));
+    }
+  }
+  let (manifest, _, _) = dst
+    .get_raw_manifest(name, reference, Some(MANIFEST_MEDIA_TYPES))
+    .await?;
+  Ok(Some(digest_like(source_digest, &manifest)))
+}
+
+struct Verifier<'a> {
+  src: &'a Client,
+  dst: &'a Client,
+  r: &'a ReplicationRef,
+  sample_blobs: usize,
+}
+
+impl Verifier<'_> {
+  /// Verify the blobs of an image, or the manifests of an index and their blobs.
+  async fn verify(&self, manifest: &[u8], media_type: &str, discrepancies: &mut Vec<Discrepancy>) -> Result<()> {
+    let value: Value = serde_json::from_slice(manifest)?;
+    match manifest_kind(media_type)? {
+      ManifestKind::Image => self.verify_blobs(&value, discrepancies).await,
+      ManifestKind::Index => {
+        for child in value["manifests"].as_array().into_iter().flatten() {
+          let child = descriptor(child)?;
+          let name = &self.r.destination_name;
+          if self
+            .dst
+            .has_manifest(name, &child.digest, Some(MANIFEST_MEDIA_TYPES))
+            .await?
+            .is_none()
+          {
+            discrepancies.push(Discrepancy::MissingManifest { digest: child.digest });
+            continue;
+          }
+          let (manifest, media_type, _) = self
+            .src
+            .get_raw_manifest(&self.r.source_name, &child.digest, Some(MANIFEST_MEDIA_TYPES))
+            .await?;
+          Box::pin(self.verify(&manifest, &media_type, discrepancies)).await?;
+        }
+        Ok(())
+      }
+    }
+  }
+
+  async fn verify_blobs(&self, manifest: &Value, discrepancies: &mut Vec<Discrepancy>) -> Result<()> {
+    let name = &self.r.destination_name;
+    let config = descriptor(&manifest["config"])?;
+    if !self.dst.has_blob(name, &config.digest).await? {
+      discrepancies.push(Discrepancy::MissingBlob { digest: config.digest });
+    }
+
+    let layers = layers_of(manifest)?;
+    let sampled = sample(layers.len(), self.sample_blobs);
+    for (i, layer) in layers.iter().enumerate() {
+      let layer = descriptor(layer)?;
+      // Foreign layers are downloaded from their URLs, registries do not store them.
+      if layer.media_type.contains("foreign") || layer.media_type.contains("nondistributable") {
+        continue;
+      }
+      if !self.dst.has_blob(name, &layer.digest).await? {
+        discrepancies.push(Discrepancy::MissingBlob { digest: layer.digest });
+      } else if sampled.contains(&i) {
+        trace!("Sampling layer {} at the destination", layer.digest);
+        // The stream verifies the digest of the layer as it goes, without holding it in memory.
+        let chunks = self.dst.get_blob_stream(name, &layer.digest).await?;
+        futures::pin_mut!(chunks);
+        while let Some(chunk) = chunks.next().await {
+          match chunk {
+            Ok(_) => {}
+            Err(Error::ContentDigestParse(_)) => {
+              discrepancies.push(Discrepancy::CorruptBlob { digest: layer.digest });
+              break;
+            }
+            Err(e) => return Err(e),
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Indexes of `count` items evenly spread over `len` items.
+fn sample(len: usize, count: usize) -> Vec<usize> {
+  let count = count.min(len);
+  (0..count).map(|i| i * len / count).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case(5, 0 => Vec::<usize>::new(); "none")]
+  #[test_case(5, 1 => vec![0]; "one")]
+  #[test_case(6, 3 => vec![0, 2, 4]; "spread")]
+  #[test_case(2, 5 => vec![0, 1]; "all")]
+  fn samples_are_spread(len: usize, count: usize) -> Vec<usize> {
+    sample(len, count)
+  }
+}
@@ -0,0 +1,177 @@
+//! Signing payload generation.
+//!
+//! This module builds the payloads that container image signatures are computed over, so that
+//! signer integrations only need to provide the cryptography. Two formats are supported:
+//! the Red Hat "simple signing" format used by `containers/image`, and the cosign payload.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # fn main() {
+//! # fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::signing::SigningPayload;
+//!
+//! let digest =
+//!   "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab";
+//! let payload = SigningPayload::cosign("quay.io/coreos/etcd", digest)?
+//!   .annotation("ci", "true")
+//!   .to_bytes()?;
+//! assert!(payload.starts_with(br#"{"critical":{"identity""#));
+//! #
+//! # Ok(())
+//! # };
+//! # run().unwrap();
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::Result, v2::ContentDigest};
+
+/// Signature type used by simple signing.
+pub const SIMPLE_SIGNING_TYPE: &str = "atomic container signature";
+/// Signature type used by cosign.
+pub const COSIGN_TYPE: &str = "cosign container image signature";
+
+/// A signing payload for an image identified by reference and manifest digest.
+///
+/// Fields serialize in a fixed order, so the output of [`SigningPayload::to_bytes`] is stable
+/// and can be signed directly.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SigningPayload {
+  pub critical: Critical,
+  pub optional: Option<BTreeMap<String, serde_json::Value>>,
+}
+
+/// The `critical` section of a signing payload.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Critical {
+  pub identity: Identity,
+  pub image: Image,
+  #[serde(rename = "type")]
+  pub signature_type: String,
+}
+
+/// The identity the signature is bound to.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Identity {
+  #[serde(rename = "docker-reference")]
+  pub docker_reference: String,
+}
+
+/// The manifest the signature is bound to.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Image {
+  #[serde(rename = "docker-manifest-digest")]
+  pub docker_manifest_digest: String,
+}
+
+impl SigningPayload {
+  /// Create a Red Hat simple signing payload.
+  ///
+  /// The reference should include the tag being signed, e.g. `quay.io/coreos/etcd:v3.5.0`.
+  pub fn simple_signing(docker_reference: &str, manifest_digest: &str) -> Result<Self> {
+    Self::new(
+      docker_reference,
+      manifest_digest,
+      SIMPLE_SIGNING_TYPE,
+      Some(BTreeMap::new()),
+    )
+  }
+
+  /// Create a cosign payload.
+  ///
+  /// Cosign binds signatures to the repository, so the reference should not include a tag.
+  pub fn cosign(docker_reference: &str, manifest_digest: &str) -> Result<Self> {
+    Self::new(docker_reference, manifest_digest, COSIGN_TYPE, None)
+  }
+
+  fn new(
+    docker_reference: &str,
+    manifest_digest: &str,
+    signature_type: &str,
+    optional: Option<BTreeMap<String, serde_json::Value>>,
+  ) -> Result<Self> {
+    ContentDigest::try_new(manifest_digest)?;
+    Ok(SigningPayload {
+      critical: Critical {
+        identity: Identity {
+          docker_reference: docker_reference.to_string(),
+        },
+        image: Image {
+          docker_manifest_digest: manifest_digest.to_string(),
+        },
+        signature_type: signature_type.to_string(),
+      },
+      optional,
+    })
+  }
+
+  /// Add an entry to the `optional` section.
+  ///
+  /// Simple signing uses this for `creator` and `timestamp`, cosign for annotations.
+  pub fn annotation<V: Into<serde_json::Value>>(mut self, key: &str, value: V) -> Self {
+    self
+      .optional
+      .get_or_insert_with(BTreeMap::new)
+      .insert(key.to_string(), value.into());
+    self
+  }
+
+  /// Serialize the payload to the bytes that should be signed.
+  pub fn to_bytes(&self) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(self)?)
+  }
+}
+
+/// The tag cosign stores the signature for `manifest_digest` under, e.g. `sha256-<hex>.sig`.
+pub fn cosign_signature_tag(manifest_digest: &str) -> Result<String> {
+  ContentDigest::try_new(manifest_digest)?;
+  Ok(format!("{}.sig", manifest_digest.replacen(':', "-", 1)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DIGEST: &str = "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab";
+
+  #[test]
+  fn simple_signing_payload() {
+    let payload = SigningPayload::simple_signing("quay.io/coreos/etcd:v3", DIGEST)
+      .unwrap()
+      .annotation("creator", "docker-registry")
+      .to_bytes()
+      .unwrap();
+    let expected = format!(
+      r#"{{"critical":{{"identity":{{"docker-reference":"quay.io/coreos/etcd:v3"}},"image":{{"docker-manifest-digest":"{}"}},"type":"atomic container signature"}},"optional":{{"creator":"docker-registry"}}}}"#,
+      DIGEST
+    );
+    assert_eq!(String::from_utf8(payload).unwrap(), expected);
+  }
+
+  #[test]
+  fn cosign_payload() {
+    let payload = SigningPayload::cosign("quay.io/coreos/etcd", DIGEST)
+      .unwrap()
+      .to_bytes()
+      .unwrap();
+    let expected = format!(
+      r#"{{"critical":{{"identity":{{"docker-reference":"quay.io/coreos/etcd"}},"image":{{"docker-manifest-digest":"{}"}},"type":"cosign container image signature"}},"optional":null}}"#,
+      DIGEST
+    );
+    assert_eq!(String::from_utf8(payload).unwrap(), expected);
+    assert_eq!(
+      cosign_signature_tag(DIGEST).unwrap(),
+      "sha256-9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab.sig"
+    );
+  }
+
+  #[test]
+  fn invalid_digest() {
+    assert!(SigningPayload::cosign("quay.io/coreos/etcd", "latest").is_err());
+  }
+}
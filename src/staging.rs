@@ -0,0 +1,218 @@
+//! Staging directories for content written to disk before it is verified.
+//!
+//! Blob stores writing to disk, such as [`FsBlobStore`](crate::blob_store::FsBlobStore), first
+//! write blobs to a staging file and only move them in place once their digest was verified.
+//! A [`StagingDir`] configures where staging files go and how much free space must be left on
+//! its filesystem; staging files are removed when the write fails or its future is dropped.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use docker_registry::{blob_store::FsBlobStore, staging::StagingDir};
+//!
+//! // Stage on the same filesystem as the store, and keep 1 GiB free.
+//! let staging = StagingDir::new("/srv/images/.staging").min_free_space(1 << 30);
+//! let store = FsBlobStore::new("/srv/images/blobs").staging(staging);
+//! ```
+
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::errors::{Error, Result};
+
+/// Directory holding staging files, and the free space to leave on its filesystem.
+#[derive(Clone, Debug)]
+pub struct StagingDir {
+  dir: PathBuf,
+  min_free_space: u64,
+}
+
+impl Default for StagingDir {
+  /// The temporary directory of the system, e.g. `$TMPDIR`.
+  fn default() -> Self {
+    Self::new(std::env::temp_dir())
+  }
+}
+
+impl StagingDir {
+  pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+    Self {
+      dir: dir.as_ref().to_path_buf(),
+      min_free_space: 0,
+    }
+  }
+
+  /// Refuse to stage content which would leave less than `bytes` free on the filesystem.
+  pub fn min_free_space(mut self, bytes: u64) -> Self {
+    self.min_free_space = bytes;
+    self
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Fail with `Error::InsufficientSpace` unless `bytes` can be staged.
+  ///
+  /// The check is skipped on platforms where free space cannot be queried.
+  pub fn check_space(&self, bytes: u64) -> Result<()> {
+    fs::create_dir_all(&self.dir)?;
+    check_space(&self.dir, bytes.saturating_add(self.min_free_space))
+  }
+
+  /// Create a new, empty staging file, removed unless it is persisted.
+  pub fn create_file(&self) -> Result<(StagingFile, fs::File)> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    fs::create_dir_all(&self.dir)?;
+    loop {
+      let path = self.dir.join(format!(
+        ".staging-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+      ));
+      match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(file) => return Ok((StagingFile::new(path), file)),
+        // Left behind by a process which had the same ID.
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+        Err(e) => return Err(e.into()),
+      }
+    }
+  }
+}
+
+/// Path of a staging file or directory, removed when dropped unless it was persisted.
+///
+/// Dropping happens on errors and when the future writing the file is cancelled alike, so no
+/// staging file outlives the operation which created it.
+#[derive(Debug)]
+pub struct StagingFile {
+  path: Option<PathBuf>,
+}
+
+impl StagingFile {
+  /// Take ownership of `path`, which is removed on drop.
+  pub fn new(path: PathBuf) -> Self {
+    Self { path: Some(path) }
+  }
+
+  pub fn path(&self) -> &Path {
+    self.path.as_deref().unwrap_or(Path::new(""))
+  }
+
+  /// Move the file to `dest`, copying it if `dest` is on another filesystem.
+  pub fn persist(mut self, dest: &Path) -> Result<()> {
+    let path = self.path();
+    match fs::rename(path, dest) {
+      Ok(()) => {}
+      Err(e) if is_cross_device(&e) => {
+        // Copy next to `dest` first, so that `dest` never has partial content.
+        let (copy, _) = StagingDir::new(dest.parent().unwrap_or(Path::new("."))).create_file()?;
+        fs::copy(path, copy.path())?;
+        copy.persist(dest)?;
+        fs::remove_file(self.path())?;
+      }
+      Err(e) => return Err(e.into()),
+    }
+    self.path = None;
+    Ok(())
+  }
+}
+
+#[cfg(unix)]
+fn is_cross_device(e: &io::Error) -> bool {
+  e.raw_os_error() == Some(libc::EXDEV)
+}
+
+#[cfg(not(unix))]
+fn is_cross_device(e: &io::Error) -> bool {
+  // ERROR_NOT_SAME_DEVICE
+  e.raw_os_error() == Some(17)
+}
+
+impl Drop for StagingFile {
+  fn drop(&mut self) {
+    if let Some(path) = self.path.take() {
+      let _ = match path.is_dir() {
+        true => fs::remove_dir_all(&path),
+        false => fs::remove_file(&path),
+      };
+    }
+  }
+}
+
+/// Fail with `Error::InsufficientSpace` unless `bytes` are available on the filesystem of `path`.
+pub(crate) fn check_space(path: &Path, bytes: u64) -> Result<()> {
+  match available_space(path)? {
+    Some(available) if available < bytes => Err(Error::InsufficientSpace {
+      path: path.to_path_buf(),
+      needed: bytes,
+      available,
+    }),
+    _ => Ok(()),
+  }
+}
+
+/// Space available to unprivileged users on the filesystem of `path`, if it can be queried.
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Result<Option<u64>> {
+  use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+  let path = CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+  let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+  // SAFETY: `path` is NUL-terminated and `stat` is valid for writes.
+  if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+    return Err(io::Error::last_os_error().into());
+  }
+  // SAFETY: statvfs succeeded, so it initialized `stat`.
+  let stat = unsafe { stat.assume_init() };
+  #[allow(clippy::unnecessary_cast)]
+  Ok(Some(stat.f_bavail as u64 * stat.f_frsize as u64))
+}
+
+/// Space available to unprivileged users on the filesystem of `path`, if it can be queried.
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Result<Option<u64>> {
+  Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+
+  use super::*;
+
+  #[test]
+  fn staging_files_are_removed_unless_persisted() {
+    let dir = tempfile::tempdir().unwrap();
+    let staging = StagingDir::new(dir.path().join("staging"));
+
+    let (staged, _) = staging.create_file().unwrap();
+    let path = staged.path().to_path_buf();
+    assert!(path.is_file());
+    drop(staged);
+    assert!(!path.exists());
+
+    let (staged, mut file) = staging.create_file().unwrap();
+    file.write_all(b"data").unwrap();
+    staged.persist(&dir.path().join("blob")).unwrap();
+    assert_eq!(fs::read(dir.path().join("blob")).unwrap(), b"data");
+    assert_eq!(fs::read_dir(staging.path()).unwrap().count(), 0);
+  }
+
+  #[test]
+  fn checks_free_space() {
+    let dir = tempfile::tempdir().unwrap();
+    let staging = StagingDir::new(dir.path());
+    staging.check_space(0).unwrap();
+    if available_space(dir.path()).unwrap().is_some() {
+      assert!(matches!(
+        staging.min_free_space(u64::MAX).check_space(1),
+        Err(Error::InsufficientSpace { .. })
+      ));
+    }
+  }
+}
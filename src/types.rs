@@ -0,0 +1,155 @@
+//! Wire types of the registry API.
+//!
+//! This module collects the payloads exchanged with registries: manifests and their parts,
+//! descriptors, tag lists, catalogs, token responses and error bodies. They do not depend on
+//! the client, so that other crates (registry implementations, proxies, test doubles) can
+//! depend on them long-term.
+//!
+//! ## Stability
+//!
+//! The types in this module only evolve additively:
+//!
+//! - fields and variants are not removed or renamed, and their serialized form does not change;
+//! - new fields are optional on the wire, so that payloads of older registries keep parsing;
+//! - structs with public fields and enums are `#[non_exhaustive]`: build them with their constructors and `with_*`
+//!   methods, and match enums with a wildcard arm.
+//!
+//! Unknown fields are ignored when deserializing.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use docker_registry::types::TagList;
+//!
+//! let tags: TagList =
+//!   serde_json::from_str(r#"{"name": "library/busybox", "tags": ["latest"]}"#)
+//!     .unwrap();
+//! assert_eq!(
+//!   tags,
+//!   TagList::new("library/busybox", vec!["latest".to_string()])
+//! );
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+pub use crate::v2::{
+  manifest::{
+    ConfigBlob, Manifest, ManifestList, ManifestSchema1Signed, ManifestSchema2, ManifestSchema2Spec, Platform,
+  },
+  ApiError, ApiErrors, Descriptor,
+};
+
+/// Repositories listed by the catalog endpoint, `/v2/_catalog`.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Catalog {
+  pub repositories: Vec<String>,
+}
+
+impl Catalog {
+  pub fn new(repositories: Vec<String>) -> Self {
+    Self { repositories }
+  }
+}
+
+/// Tags listed by the tag list endpoint, `/v2/<name>/tags/list`.
+///
+/// With pagination, this is the subset of the tags of the repository on one page.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TagList {
+  /// Repository name.
+  pub name: String,
+  pub tags: Vec<String>,
+}
+
+impl TagList {
+  pub fn new(name: &str, tags: Vec<String>) -> Self {
+    Self {
+      name: name.to_string(),
+      tags,
+    }
+  }
+}
+
+/// Response of a token service, for Bearer authentication.
+///
+/// Token services return the token as `token`, as `access_token` for OAuth2 compatibility, or
+/// as both.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TokenAuth {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub token: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub access_token: Option<String>,
+  /// Lifetime of the token, in seconds.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expires_in: Option<u32>,
+  /// Time the token was issued at, in RFC 3339 format.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub issued_at: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub refresh_token: Option<String>,
+}
+
+impl TokenAuth {
+  /// A response carrying `token` as both `token` and `access_token`.
+  pub fn new(token: &str) -> Self {
+    Self {
+      token: Some(token.to_string()),
+      access_token: Some(token.to_string()),
+      ..Default::default()
+    }
+  }
+
+  pub fn with_expires_in(mut self, seconds: u32) -> Self {
+    self.expires_in = Some(seconds);
+    self
+  }
+
+  pub fn with_issued_at(mut self, issued_at: &str) -> Self {
+    self.issued_at = Some(issued_at.to_string());
+    self
+  }
+
+  pub fn with_refresh_token(mut self, refresh_token: &str) -> Self {
+    self.refresh_token = Some(refresh_token.to_string());
+    self
+  }
+
+  /// The token, from `token` or else `access_token`.
+  pub fn bearer_token(&self) -> Option<&str> {
+    self.token.as_deref().or(self.access_token.as_deref())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn token_auth_accepts_either_field() {
+    let token: TokenAuth = serde_json::from_str(r#"{"access_token": "abc", "expires_in": 300}"#).unwrap();
+    assert_eq!(token.bearer_token(), Some("abc"));
+    assert_eq!(token.expires_in, Some(300));
+
+    let token: TokenAuth = serde_json::from_str(r#"{"token": "abc", "access_token": "def"}"#).unwrap();
+    assert_eq!(token.bearer_token(), Some("abc"));
+
+    let token = TokenAuth::new("abc").with_expires_in(60);
+    let json = serde_json::to_value(&token).unwrap();
+    assert_eq!(
+      json,
+      serde_json::json!({"token": "abc", "access_token": "abc", "expires_in": 60})
+    );
+  }
+
+  #[test]
+  fn catalog_round_trips() {
+    let catalog = Catalog::new(vec!["a".to_string(), "b/c".to_string()]);
+    let json = serde_json::to_string(&catalog).unwrap();
+    assert_eq!(json, r#"{"repositories":["a","b/c"]}"#);
+    assert_eq!(serde_json::from_str::<Catalog>(&json).unwrap(), catalog);
+  }
+}
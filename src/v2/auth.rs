@@ -1,4 +1,7 @@
-use std::convert::{TryFrom, TryInto};
+use std::{
+  convert::{TryFrom, TryInto},
+  time::{Duration, UNIX_EPOCH},
+};
 
 use log::{trace, warn};
 use regex_lite::Regex;
@@ -7,6 +10,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
   errors::{Error, Result},
+  types::TokenAuth,
   v2::*,
 };
 
@@ -15,6 +19,8 @@ use crate::{
 pub enum Auth {
   Bearer(BearerAuth),
   Basic(BasicAuth),
+  /// `Authorization` header forwarded verbatim, e.g. by a proxy on behalf of its own clients.
+  Passthrough(HeaderValue),
 }
 
 impl Auth {
@@ -23,6 +29,7 @@ impl Auth {
     match self {
       Auth::Bearer(bearer_auth) => request_builder.bearer_auth(bearer_auth.token.clone()),
       Auth::Basic(basic_auth) => request_builder.basic_auth(basic_auth.user.clone(), basic_auth.password.clone()),
+      Auth::Passthrough(value) => request_builder.header(reqwest::header::AUTHORIZATION, value.clone()),
     }
   }
 }
@@ -36,20 +43,10 @@ pub struct BearerAuth {
   refresh_token: Option<String>,
 }
 
-/// Used to support different response schemas of Bearer HTTP Authentication
-#[derive(Debug, Clone, Default, Deserialize)]
-pub struct MultiTokenBearerAuth {
-  token: Option<String>,
-  access_token: Option<String>,
-  expires_in: Option<u32>,
-  issued_at: Option<String>,
-  refresh_token: Option<String>,
-}
-
-impl TryFrom<MultiTokenBearerAuth> for BearerAuth {
+impl TryFrom<TokenAuth> for BearerAuth {
   type Error = Error;
 
-  fn try_from(value: MultiTokenBearerAuth) -> std::result::Result<Self, Error> {
+  fn try_from(value: TokenAuth) -> std::result::Result<Self, Error> {
     let t = value.token.or(value.access_token).ok_or(Error::NoTokenReceived)?;
 
     Ok(Self {
@@ -61,19 +58,75 @@ impl TryFrom<MultiTokenBearerAuth> for BearerAuth {
   }
 }
 
+impl From<StoredToken> for BearerAuth {
+  fn from(stored: StoredToken) -> Self {
+    Self {
+      token: stored.token,
+      refresh_token: stored.refresh_token,
+      ..Default::default()
+    }
+  }
+}
+
 impl BearerAuth {
+  fn to_stored(&self) -> StoredToken {
+    // Token services which do not report a lifetime issue tokens valid for 60 seconds.
+    let lifetime = Duration::from_secs(self.expires_in.unwrap_or(60).into());
+    let expires_at = (system_now() + lifetime).duration_since(UNIX_EPOCH).unwrap_or_default();
+    StoredToken {
+      token: self.token.clone(),
+      refresh_token: self.refresh_token.clone(),
+      expires_at: expires_at.as_secs(),
+    }
+  }
+
+  /// Exchange a refresh token for a new token, with the OAuth2 flow of the token service.
+  async fn refresh(
+    client: &Client,
+    scopes: &[&str],
+    refresh_token: &str,
+    bearer_header_content: &WwwAuthenticateHeaderContentBearer,
+  ) -> Result<Self> {
+    let url = Url::parse(&bearer_header_content.realm)?;
+    let scope = scopes.join(" ");
+    let mut form = vec![
+      ("grant_type", "refresh_token"),
+      ("refresh_token", refresh_token),
+      ("client_id", env!("CARGO_PKG_NAME")),
+      ("scope", &scope),
+    ];
+    if let Some(service) = &bearer_header_content.service {
+      form.push(("service", service));
+    }
+
+    client.stats.update(|stats| stats.token_requests += 1);
+    let r = client
+      .send_token_request(client.build_reqwest(Method::POST, url).form(&form))
+      .await?;
+    let status = r.status();
+    trace!("authenticate: refresh got status {}", status);
+    if status != StatusCode::OK {
+      return Err(Error::UnexpectedHttpStatus(status));
+    }
+
+    let mut bearer_auth: BearerAuth = r.json::<TokenAuth>().await?.try_into()?;
+    // Token services only return a refresh token when they rotate it.
+    bearer_auth.refresh_token = bearer_auth.refresh_token.or_else(|| Some(refresh_token.to_string()));
+    Ok(bearer_auth)
+  }
+
   async fn try_from_header_content(
     client: Client,
     scopes: &[&str],
     credentials: Option<(String, String)>,
-    bearer_header_content: WwwAuthenticateHeaderContentBearer,
+    bearer_header_content: &WwwAuthenticateHeaderContentBearer,
   ) -> Result<Self> {
     let auth_ep = bearer_header_content.auth_ep(scopes);
     trace!("authenticate: token endpoint: {}", auth_ep);
 
     let url = reqwest::Url::parse(&auth_ep)?;
 
-    let auth_req = {
+    let token_client = {
       Client {
         auth: credentials.map(|(user, password)| {
           Auth::Basic(BasicAuth {
@@ -83,17 +136,29 @@ impl BearerAuth {
         }),
         ..client
       }
-    }
-    .build_reqwest(Method::GET, url);
+    };
+    let auth_req = token_client.build_reqwest(Method::GET, url);
 
-    let r = auth_req.send().await?;
+    token_client.stats.update(|stats| stats.token_requests += 1);
+    let r = token_client.send_token_request(auth_req).await?;
     let status = r.status();
     trace!("authenticate: got status {}", status);
+    if status == StatusCode::UNAUTHORIZED {
+      // The token service refused the credentials for the requested scopes.
+      let access = scopes
+        .iter()
+        .find_map(|scope| scope.strip_prefix("repository:")?.rsplit_once(':'));
+      return Err(Error::Unauthorized {
+        repository: access.map(|(repository, _)| repository.to_string()),
+        action: access.map(|(_, action)| action.to_string()),
+        source: r.json::<ApiErrors>().await.unwrap_or_default(),
+      });
+    }
     if status != StatusCode::OK {
       return Err(Error::UnexpectedHttpStatus(status));
     }
 
-    let bearer_auth: BearerAuth = r.json::<MultiTokenBearerAuth>().await?.try_into()?;
+    let bearer_auth: BearerAuth = r.json::<TokenAuth>().await?.try_into()?;
 
     match bearer_auth.token.as_str() {
       "unauthenticated" | "" => return Err(Error::InvalidAuthToken(bearer_auth.token)),
@@ -220,6 +285,19 @@ pub(crate) struct WwwAuthenticateHeaderContentBearer {
 }
 
 impl WwwAuthenticateHeaderContentBearer {
+  /// Key of the tokens issued by this token service for `scopes` to `user` in a `TokenStore`.
+  fn token_key(&self, scopes: &[&str], user: Option<&str>) -> String {
+    let mut scopes = scopes.to_vec();
+    scopes.sort_unstable();
+    format!(
+      "{}|{}|{}|{}",
+      self.realm,
+      self.service.as_deref().unwrap_or_default(),
+      scopes.join(" "),
+      user.unwrap_or_default()
+    )
+  }
+
   fn auth_ep(&self, scopes: &[&str]) -> String {
     let service = self
       .service
@@ -258,7 +336,7 @@ impl Client {
       reqwest::Url::parse(&ep)?
     };
 
-    let r = self.build_reqwest(Method::GET, url.clone()).send().await?;
+    let r = self.send(self.build_reqwest(Method::GET, url.clone())).await?;
 
     trace!("GET '{}' status: {:?}", r.url(), r.status());
     r.headers()
@@ -270,9 +348,11 @@ impl Client {
   /// Perform registry authentication and return the authenticated client.
   ///
   /// If Bearer authentication is used the returned client will be authorized for the requested scopes.
+  ///
+  /// Bearer tokens are reused from the `Config::token_store` until they expire. Without
+  /// credentials, the user logs in interactively if `Config::device_login` is set; the returned
+  /// client keeps the credentials obtained.
   pub async fn authenticate(mut self, scopes: &[&str]) -> Result<Self> {
-    let credentials = self.credentials.clone();
-
     let client = Client {
       auth: None,
       ..self.clone()
@@ -281,7 +361,10 @@ impl Client {
     let authentication_header = client.get_www_authentication_header().await?;
     let auth = match WwwAuthenticateHeaderContent::from_www_authentication_header(authentication_header)? {
       WwwAuthenticateHeaderContent::Basic(_) => {
-        let basic_auth = credentials
+        self.device_login_if_needed().await?;
+        let basic_auth = self
+          .credentials
+          .clone()
           .map(|(user, password)| BasicAuth {
             user,
             password: Some(password),
@@ -291,8 +374,58 @@ impl Client {
         Auth::Basic(basic_auth)
       }
       WwwAuthenticateHeaderContent::Bearer(bearer_header_content) => {
-        let bearer_auth =
-          BearerAuth::try_from_header_content(client, scopes, credentials, bearer_header_content).await?;
+        let user = match (&self.credentials, &self.device_login) {
+          (Some((user, _)), _) => Some(user.as_str()),
+          (None, Some(login)) => Some(login.username.as_str()),
+          (None, None) => None,
+        };
+        let key = bearer_header_content.token_key(scopes, user);
+        let stored = self.load_token(&key);
+
+        let bearer_auth = match stored {
+          Some(token) if !token.is_expired() => {
+            trace!("authenticate: reusing stored token");
+            token.into()
+          }
+          stored => {
+            let refreshed = match stored.and_then(|t| t.refresh_token) {
+              Some(refresh_token) => BearerAuth::refresh(&client, scopes, &refresh_token, &bearer_header_content)
+                .await
+                .map_err(|e| trace!("authenticate: cannot refresh stored token: {}", e))
+                .ok(),
+              None => None,
+            };
+            let bearer_auth = match refreshed {
+              Some(bearer_auth) => bearer_auth,
+              None => {
+                self.device_login_if_needed().await?;
+                let credentials = self.current_credentials();
+                let token = BearerAuth::try_from_header_content(
+                  client.clone(),
+                  scopes,
+                  credentials.clone(),
+                  &bearer_header_content,
+                )
+                .await;
+                match (token, credentials) {
+                  // The credentials may have been rotated since the client was configured.
+                  (Err(e @ Error::Unauthorized { .. }), Some(rejected)) => {
+                    match self.renew_credentials(&rejected).await? {
+                      Some(renewed) => {
+                        BearerAuth::try_from_header_content(client, scopes, Some(renewed), &bearer_header_content)
+                          .await?
+                      }
+                      None => return Err(e),
+                    }
+                  }
+                  (token, _) => token?,
+                }
+              }
+            };
+            self.save_token(&key, &bearer_auth.to_stored());
+            bearer_auth
+          }
+        };
 
         Auth::Bearer(bearer_auth)
       }
@@ -304,6 +437,102 @@ impl Client {
     Ok(self)
   }
 
+  /// Authenticate again for the challenge of a `401` response, e.g. a `Bearer` challenge asking
+  /// for a scope the current token lacks.
+  ///
+  /// Returns `None` if the response carries no challenge.
+  pub(crate) async fn authenticate_for_challenge(&self, challenge: Option<&HeaderValue>) -> Result<Option<Self>> {
+    let challenge = match challenge {
+      Some(challenge) => WwwAuthenticateHeaderContent::from_www_authentication_header(challenge.clone())?,
+      None => return Ok(None),
+    };
+    let scopes = match &challenge {
+      WwwAuthenticateHeaderContent::Bearer(bearer) => bearer.scope.as_deref().unwrap_or_default(),
+      WwwAuthenticateHeaderContent::Basic(_) => "",
+    };
+    let scopes: Vec<&str> = scopes.split_whitespace().collect();
+    Ok(Some(self.clone().authenticate(&scopes).await?))
+  }
+
+  /// Log in with `Config::device_login` if the client has no credentials.
+  async fn device_login_if_needed(&mut self) -> Result<()> {
+    if let (None, Some(login)) = (&self.credentials, &self.device_login) {
+      self.credentials = Some(self.device_credentials(login).await?);
+    }
+    Ok(())
+  }
+
+  /// Return a client which sends `authorization` as the `Authorization` header of every request.
+  ///
+  /// This lets a proxy forward the credentials presented by its own clients, typically a bearer
+  /// token they obtained from the upstream token service, instead of authenticating itself.
+  pub fn with_authorization(&self, authorization: &str) -> Result<Self> {
+    let mut value = HeaderValue::from_str(authorization)?;
+    value.set_sensitive(true);
+    Ok(Client {
+      auth: Some(Auth::Passthrough(value)),
+      ..self.clone()
+    })
+  }
+
+  /// Return a client which authorizes every request with its credentials, e.g. for the vendor
+  /// APIs of registries, which do not accept registry tokens.
+  pub(crate) fn with_basic_credentials(&self) -> Self {
+    Client {
+      auth: self.credentials.clone().map(|(user, password)| {
+        Auth::Basic(BasicAuth {
+          user,
+          password: Some(password),
+        })
+      }),
+      ..self.clone()
+    }
+  }
+
+  /// The username and password the client was configured with, if any.
+  #[cfg(feature = "gitlab")]
+  pub(crate) fn credentials(&self) -> Option<(&str, &str)> {
+    self
+      .credentials
+      .as_ref()
+      .map(|(user, password)| (user.as_str(), password.as_str()))
+  }
+
+  /// Return a client which authorizes every request with the given bearer token.
+  pub fn with_bearer_token(&self, token: &str) -> Self {
+    Client {
+      auth: Some(Auth::Bearer(BearerAuth {
+        token: token.to_string(),
+        ..Default::default()
+      })),
+      ..self.clone()
+    }
+  }
+
+  /// Fetch the authentication challenge of the registry, if it requires authentication.
+  ///
+  /// Returns the `WWW-Authenticate` header sent by the registry for an unauthorized request to
+  /// the base endpoint, which a proxy relays to its clients so that they fetch tokens from the
+  /// upstream token service. Returns `None` if the registry allows anonymous access.
+  pub async fn auth_challenge(&self) -> Result<Option<String>> {
+    let client = Client {
+      auth: None,
+      ..self.clone()
+    };
+    let url = reqwest::Url::parse(&format!("{}/v2/", self.base_url))?;
+    let r = client.send(client.build_reqwest(Method::GET, url)).await?;
+    trace!("GET '{}' status: {:?}", r.url(), r.status());
+
+    match r.status() {
+      StatusCode::UNAUTHORIZED => match r.headers().get(reqwest::header::WWW_AUTHENTICATE) {
+        Some(v) => Ok(Some(v.to_str()?.to_string())),
+        None => Err(Error::MissingAuthHeader("WWW-Authenticate")),
+      },
+      status if status.is_success() => Ok(None),
+      status => Err(Error::UnexpectedHttpStatus(status)),
+    }
+  }
+
   /// Check whether the client can successfully make requests to the registry.
   ///
   /// This could be due to granted anonymous access or valid credentials.
@@ -316,7 +545,7 @@ impl Client {
     let req = self.build_reqwest(Method::GET, url.clone());
 
     trace!("Sending request to '{}'", url);
-    let resp = req.send().await?;
+    let resp = self.send(req).await?;
     trace!("GET '{:?}'", resp);
 
     let status = resp.status();
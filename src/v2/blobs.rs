@@ -2,13 +2,15 @@ use std::pin::Pin;
 
 use bytes::Bytes;
 use futures::{
-  stream::Stream,
+  future::Either,
+  stream::{Stream, TryStreamExt},
   task::{Context, Poll},
 };
 use log::{error, trace};
 use pin_project::pin_project;
 use reqwest::{self, Method, StatusCode};
 
+use super::deadline::deadline_error;
 use crate::{
   errors::{Error, Result},
   v2::*,
@@ -17,12 +19,9 @@ use crate::{
 impl Client {
   /// Check if a blob exists.
   pub async fn has_blob(&self, name: &str, digest: &str) -> Result<bool> {
-    let url = {
-      let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
-      reqwest::Url::parse(&ep)?
-    };
+    let url = self.repository_url(name, &format!("blobs/{}", digest))?;
 
-    let res = self.build_reqwest(Method::HEAD, url.clone()).send().await?;
+    let res = self.send(self.build_reqwest(Method::HEAD, url.clone())).await?;
 
     trace!("Blob HEAD status: {:?}", res.status());
 
@@ -32,41 +31,58 @@ impl Client {
     }
   }
 
-  pub async fn get_blob_response(&self, name: &str, digest: &str) -> Result<BlobResponse> {
-    let ep = format!("{}/v2/{}/blobs/{}", self.base_url, name, digest);
-    let url = reqwest::Url::parse(&ep)?;
+  /// Size of a blob from its `Content-Length`, with a `HEAD` request, or `None` if it does not exist.
+  pub async fn get_blob_size(&self, name: &str, digest: &str) -> Result<Option<u64>> {
+    let url = self.repository_url(name, &format!("blobs/{}", digest))?;
 
-    let resp = self.build_reqwest(Method::GET, url.clone()).send().await?;
+    let res = self.send(self.build_reqwest(Method::HEAD, url)).await?;
 
-    let status = resp.status();
-    trace!("GET {} status: {}", resp.url(), status);
+    trace!("Blob HEAD status: {:?}", res.status());
 
-    match resp.error_for_status_ref() {
-      Ok(_) => {
-        if let Some(len) = resp.content_length() {
-          trace!("Receiving a blob with {} bytes", len);
-        } else {
-          trace!("Receiving a blob");
-        }
-        Ok(BlobResponse::new(resp, ContentDigest::try_new(digest)?))
-      }
-      Err(_) if status.is_client_error() => Err(ApiErrors::from(resp).await),
-      Err(_) if status.is_server_error() => Err(Error::Server { status }),
-      Err(_) => {
-        error!("Received unexpected HTTP status '{}'", status);
-        Err(Error::UnexpectedHttpStatus(status))
-      }
+    match res.status() {
+      StatusCode::OK => {}
+      StatusCode::NOT_FOUND => return Ok(None),
+      _ => return Err(ApiErrors::from(res).await),
     }
+    // The body of a response to `HEAD` is empty, its length is only in the header.
+    res
+      .headers()
+      .get(reqwest::header::CONTENT_LENGTH)
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.parse().ok())
+      .map(Some)
+      .ok_or(Error::MissingHeader("Content-Length"))
+  }
+
+  pub async fn get_blob_response(&self, name: &str, digest: &str) -> Result<BlobResponse> {
+    let resp = self.send_blob_request(name, digest, None).await?;
+
+    BlobResponse::from_response(resp, ContentDigest::try_new(digest)?).await
   }
 
   /// Retrieve blob.
   pub async fn get_blob(&self, name: &str, digest: &str) -> Result<Vec<u8>> {
-    self.get_blob_response(name, digest).await?.bytes().await
+    self
+      .get_blob_response(name, digest)
+      .await?
+      .bytes()
+      .await
+      .map_err(|e| self.deadline_error(e))
   }
 
   /// Retrieve blob stream.
+  ///
+  /// With [`Config::parallel_downloads`], large blobs are downloaded as several parts in parallel.
   pub async fn get_blob_stream(&self, name: &str, digest: &str) -> Result<impl Stream<Item = Result<Vec<u8>>>> {
-    Ok(self.get_blob_response(name, digest).await?.stream())
+    let response = match &self.parallel_downloads {
+      Some(options) => match self.get_blob_parts(name, digest, options).await? {
+        Download::Parts(parts) => Either::Right(Box::pin(parts)),
+        Download::Whole(response) => Either::Left(response.stream()),
+      },
+      None => Either::Left(self.get_blob_response(name, digest).await?.stream()),
+    };
+    let deadline = self.deadline;
+    Ok(response.map_err(move |e| deadline_error(deadline, e)))
   }
 }
 
@@ -81,6 +97,29 @@ impl BlobResponse {
     Self { resp, digest }
   }
 
+  /// Check the status of a blob download.
+  pub(crate) async fn from_response(resp: reqwest::Response, digest: ContentDigest) -> Result<Self> {
+    let status = resp.status();
+    trace!("GET {} status: {}", resp.url(), status);
+
+    match resp.error_for_status_ref() {
+      Ok(_) => {
+        if let Some(len) = resp.content_length() {
+          trace!("Receiving a blob with {} bytes", len);
+        } else {
+          trace!("Receiving a blob");
+        }
+        Ok(Self::new(resp, digest))
+      }
+      Err(_) if status.is_client_error() => Err(ApiErrors::from(resp).await),
+      Err(_) if status.is_server_error() => Err(Error::Server { status }),
+      Err(_) => {
+        error!("Received unexpected HTTP status '{}'", status);
+        Err(Error::UnexpectedHttpStatus(status))
+      }
+    }
+  }
+
   /// Get size of the blob.
   /// This method can be useful to render progress bar when downloading a blob.
   pub fn size(&self) -> Option<u64> {
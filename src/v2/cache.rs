@@ -0,0 +1,371 @@
+use std::collections::{HashMap, VecDeque};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  sync::Mutex,
+  time::{Duration, SystemTime},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use log::trace;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::{
+  header::{self, HeaderMap},
+  Method, Request, Response, ResponseBuilderExt, StatusCode, Url,
+};
+
+use crate::v2::manifest::Manifest;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{errors::Result, v2::*};
+
+/// Size-bounded cache of manifests fetched by digest.
+///
+/// Content addressed by digest never changes, so entries do not expire; the oldest
+/// entries are evicted once the cached payloads exceed the capacity.
+#[derive(Debug)]
+pub(crate) struct ManifestCache {
+  capacity: u64,
+  used: u64,
+  entries: HashMap<(String, String), (Manifest, u64)>,
+  order: VecDeque<(String, String)>,
+}
+
+impl ManifestCache {
+  pub(crate) fn new(capacity: u64) -> Self {
+    Self {
+      capacity,
+      used: 0,
+      entries: HashMap::new(),
+      order: VecDeque::new(),
+    }
+  }
+
+  pub(crate) fn capacity(&self) -> u64 {
+    self.capacity
+  }
+
+  pub(crate) fn get(&self, name: &str, digest: &str) -> Option<Manifest> {
+    self
+      .entries
+      .get(&(name.to_string(), digest.to_string()))
+      .map(|(m, _)| m.clone())
+  }
+
+  /// Insert a manifest whose payload is `size` bytes long.
+  pub(crate) fn insert(&mut self, name: &str, digest: &str, manifest: Manifest, size: u64) {
+    if size > self.capacity {
+      return;
+    }
+    let key = (name.to_string(), digest.to_string());
+    if self.entries.contains_key(&key) {
+      return;
+    }
+    while self.used + size > self.capacity {
+      match self.order.pop_front() {
+        Some(oldest) => {
+          if let Some((_, s)) = self.entries.remove(&oldest) {
+            self.used -= s;
+          }
+        }
+        None => break,
+      }
+    }
+    self.used += size;
+    self.order.push_back(key.clone());
+    self.entries.insert(key, (manifest, size));
+  }
+}
+
+/// Request headers responses may vary on without preventing them from being cached, as they
+/// are part of the cache key or the same for every request of a client.
+#[cfg(not(target_arch = "wasm32"))]
+const KEYED_HEADERS: &[&str] = &["accept", "accept-encoding", "authorization"];
+
+/// Responses to `GET` requests kept while they are fresh according to their `Cache-Control` or
+/// `Expires` headers, see `Config::response_cache_size`.
+///
+/// The oldest entries are evicted once the cached bodies exceed the capacity; successful
+/// writes to a URL evict the responses cached for it.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub(crate) struct ResponseCache {
+  capacity: u64,
+  used: u64,
+  entries: HashMap<CacheKey, CachedResponse>,
+  order: VecDeque<CacheKey>,
+}
+
+/// URL and keyed headers of a request.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+  url: String,
+  accept: Option<Vec<u8>>,
+  authorization: Option<Vec<u8>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CacheKey {
+  /// The key of `request`, if its response may be cached.
+  fn of(request: &Request) -> Option<Self> {
+    if request.method() != Method::GET || request.headers().contains_key(header::RANGE) {
+      return None;
+    }
+    let value = |name| {
+      request
+        .headers()
+        .get(name)
+        .map(|v: &header::HeaderValue| v.as_bytes().to_vec())
+    };
+    Some(Self {
+      url: request.url().to_string(),
+      accept: value(header::ACCEPT),
+      authorization: value(header::AUTHORIZATION),
+    })
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+struct CachedResponse {
+  response: BufferedResponse,
+  expires_at: SystemTime,
+}
+
+/// A response whose body was read, from which responses can be made again.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub(crate) struct BufferedResponse {
+  url: Url,
+  version: reqwest::Version,
+  status: StatusCode,
+  headers: HeaderMap,
+  body: bytes::Bytes,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl BufferedResponse {
+  /// Read `response`, failing with `Error::ResponseTooLarge` if its body exceeds `limit`.
+  pub(crate) async fn read(response: Response, limit: u64, kind: &'static str) -> Result<Self> {
+    let url = response.url().clone();
+    let version = response.version();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = read_limited(response, limit, kind).await?;
+    Ok(Self {
+      url,
+      version,
+      status,
+      headers,
+      body: body.into(),
+    })
+  }
+
+  pub(crate) fn to_response(&self) -> Response {
+    let mut response = http::Response::builder()
+      .status(self.status)
+      .url(self.url.clone())
+      .body(self.body.clone())
+      .expect("a response with a valid status and without headers is valid");
+    *response.version_mut() = self.version;
+    *response.headers_mut() = self.headers.clone();
+    Response::from(response)
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ResponseCache {
+  pub(crate) fn new(capacity: u64) -> Self {
+    Self {
+      capacity,
+      used: 0,
+      entries: HashMap::new(),
+      order: VecDeque::new(),
+    }
+  }
+
+  pub(crate) fn capacity(&self) -> u64 {
+    self.capacity
+  }
+
+  /// The response cached for `key`, if it is still fresh.
+  fn get(&mut self, key: &CacheKey, now: SystemTime) -> Option<Response> {
+    let cached = self.entries.get(key)?;
+    if cached.expires_at <= now {
+      self.remove(key);
+      return None;
+    }
+    Some(cached.response.to_response())
+  }
+
+  fn insert(&mut self, key: CacheKey, response: CachedResponse) {
+    let size = response.response.body.len() as u64;
+    if size > self.capacity {
+      return;
+    }
+    self.remove(&key);
+    while self.used + size > self.capacity {
+      match self.order.pop_front() {
+        Some(oldest) => self.remove(&oldest),
+        None => break,
+      }
+    }
+    self.used += size;
+    self.order.push_back(key.clone());
+    self.entries.insert(key, response);
+  }
+
+  fn remove(&mut self, key: &CacheKey) {
+    if let Some(removed) = self.entries.remove(key) {
+      self.used -= removed.response.body.len() as u64;
+      self.order.retain(|k| k != key);
+    }
+  }
+
+  /// Evict the responses cached for `url`.
+  fn invalidate(&mut self, url: &Url) {
+    let url = url.as_str();
+    let keys: Vec<CacheKey> = self.entries.keys().filter(|k| k.url == url).cloned().collect();
+    for key in keys {
+      self.remove(&key);
+    }
+  }
+}
+
+/// The time until which a response with `headers`, received at `now`, is fresh, if it may be
+/// cached at all.
+#[cfg(not(target_arch = "wasm32"))]
+fn fresh_until(headers: &HeaderMap, now: SystemTime) -> Option<SystemTime> {
+  let text = |name| headers.get(name).and_then(|v: &header::HeaderValue| v.to_str().ok());
+  let vary = text(header::VARY).unwrap_or_default();
+  if vary
+    .split(',')
+    .map(str::trim)
+    .any(|h| !h.is_empty() && !KEYED_HEADERS.iter().any(|k| k.eq_ignore_ascii_case(h)))
+  {
+    return None;
+  }
+
+  let mut max_age = None;
+  for directive in headers
+    .get_all(header::CACHE_CONTROL)
+    .iter()
+    .filter_map(|v| v.to_str().ok())
+    .flat_map(|v| v.split(','))
+  {
+    let (name, value) = match directive.split_once('=') {
+      Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+      None => (directive.trim(), None),
+    };
+    match name.to_ascii_lowercase().as_str() {
+      "no-store" | "no-cache" => return None,
+      "max-age" => max_age = value.and_then(|v| v.parse::<u64>().ok()),
+      _ => {}
+    }
+  }
+
+  let lifetime = match max_age {
+    Some(max_age) => {
+      let age = text(header::AGE).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+      Duration::from_secs(max_age.saturating_sub(age))
+    }
+    None => {
+      let expires = httpdate::parse_http_date(text(header::EXPIRES)?).ok()?;
+      let date = text(header::DATE)
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .unwrap_or(now);
+      expires.duration_since(date).ok()?
+    }
+  };
+  match lifetime.is_zero() {
+    true => None,
+    false => Some(now + lifetime),
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Client {
+  /// Send `request`, serving it from the response cache if a fresh response is cached, and
+  /// caching its response if the registry allows it.
+  pub(crate) async fn send_cached(&self, cache: &Mutex<ResponseCache>, request: Request) -> Result<Response> {
+    let Some(key) = CacheKey::of(&request) else {
+      let url = request.url().clone();
+      let write = !matches!(*request.method(), Method::HEAD | Method::OPTIONS);
+      let response = self.dispatch(request).await?;
+      if write && response.status().is_success() {
+        cache.lock().unwrap().invalidate(&url);
+      }
+      return Ok(response);
+    };
+
+    if let Some(response) = cache.lock().unwrap().get(&key, system_now()) {
+      trace!("GET '{}' served from the response cache", key.url);
+      self.stats.update(|stats| stats.response_cache_hits += 1);
+      return Ok(response);
+    }
+
+    let response = self.dispatch_coalesced(request).await?;
+    let capacity = cache.lock().unwrap().capacity;
+    let expires_at = match response.status() {
+      StatusCode::OK if response.content_length().is_some_and(|len| len <= capacity) => {
+        fresh_until(response.headers(), system_now())
+      }
+      _ => None,
+    };
+    let Some(expires_at) = expires_at else {
+      return Ok(response);
+    };
+
+    let cached = CachedResponse {
+      response: BufferedResponse::read(response, capacity, "cached response")
+        .await
+        .map_err(|e| self.deadline_error(e))?,
+      expires_at,
+    };
+    let response = cached.response.to_response();
+    cache.lock().unwrap().insert(key, cached);
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+  use crate::v2::manifest::ManifestList;
+
+  #[test]
+  fn evicts_oldest_entries() {
+    let manifest = Manifest::ML(ManifestList::default());
+    let mut cache = ManifestCache::new(10);
+    cache.insert("repo", "sha256:a", manifest.clone(), 4);
+    cache.insert("repo", "sha256:b", manifest.clone(), 4);
+    cache.insert("repo", "sha256:c", manifest.clone(), 4);
+    cache.insert("repo", "sha256:d", manifest, 11);
+
+    assert!(cache.get("repo", "sha256:a").is_none());
+    assert!(cache.get("repo", "sha256:b").is_some());
+    assert!(cache.get("repo", "sha256:c").is_some());
+    assert!(cache.get("repo", "sha256:d").is_none());
+    assert_eq!(cache.used, 8);
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  #[test_case(&[("cache-control", "max-age=60")] => Some(60); "max age")]
+  #[test_case(&[("cache-control", "public, max-age=\"60\""), ("age", "20")] => Some(40); "max age minus age")]
+  #[test_case(&[("cache-control", "max-age=60"), ("expires", "Thu, 01 Jan 1970 00:00:00 GMT")] => Some(60); "max age over expires")]
+  #[test_case(&[("expires", "Thu, 01 Jan 1970 00:01:40 GMT"), ("date", "Thu, 01 Jan 1970 00:00:10 GMT")] => Some(90); "expires")]
+  #[test_case(&[("cache-control", "max-age=60, no-store")] => None; "no store")]
+  #[test_case(&[("cache-control", "No-Cache")] => None; "no cache")]
+  #[test_case(&[("cache-control", "max-age=60"), ("vary", "Accept, Cookie")] => None; "varies on cookie")]
+  #[test_case(&[("cache-control", "max-age=60"), ("vary", "Accept")] => Some(60); "varies on accept")]
+  #[test_case(&[] => None; "no freshness")]
+  fn freshness(headers: &[(&'static str, &str)]) -> Option<u64> {
+    let headers: HeaderMap = headers
+      .iter()
+      .map(|(name, value)| (header::HeaderName::from_static(name), value.parse().unwrap()))
+      .collect();
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+    fresh_until(&headers, now).map(|t| t.duration_since(now).unwrap().as_secs())
+  }
+}
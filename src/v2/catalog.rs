@@ -5,14 +5,8 @@ use futures::{
 };
 use log::trace;
 use reqwest::{Method, RequestBuilder, StatusCode};
-use serde::{Deserialize, Serialize};
 
-use crate::{errors::Result, v2};
-
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct Catalog {
-  pub repositories: Vec<String>,
-}
+use crate::{errors::Result, types::Catalog, v2};
 
 impl v2::Client {
   pub fn get_catalog<'a, 'b: 'a>(&'b self, paginate: Option<u32>) -> impl Stream<Item = Result<String>> + 'a {
@@ -30,7 +24,10 @@ impl v2::Client {
     try_stream! {
         let req = self.build_reqwest(Method::GET, url?);
 
-        let catalog = fetch_catalog(req).await?;
+        let catalog = match fetch_catalog(self, req).await {
+          Err(crate::Error::Unsupported(_)) => Catalog::default(),
+          catalog => catalog?,
+        };
 
         for repo in catalog.repositories {
             yield repo;
@@ -39,12 +36,21 @@ impl v2::Client {
   }
 }
 
-async fn fetch_catalog(req: RequestBuilder) -> Result<Catalog> {
-  let r = req.send().await?;
+async fn fetch_catalog(client: &v2::Client, req: RequestBuilder) -> Result<Catalog> {
+  let r = client.send(req).await?;
   let status = r.status();
   trace!("Got status: {:?}", status);
   match status {
-    StatusCode::OK => r.json::<Catalog>().await.map_err(Into::into),
-    _ => Err(crate::Error::UnexpectedHttpStatus(status)),
+    StatusCode::OK => {
+      let body = v2::read_decoded_limited(r, client.limits.max_catalog_size, "catalog")
+        .await
+        .map_err(|e| client.deadline_error(e))?;
+      client.parse_payload::<Catalog>(&body, v2::Payload::Catalog)
+    }
+    _ => Err(client.soft_fail(
+      v2::OptionalFeature::Catalog,
+      status,
+      crate::Error::UnexpectedHttpStatus(status),
+    )),
   }
 }
@@ -0,0 +1,147 @@
+//! Coalescing of concurrent uploads of the same blob, and of identical concurrent requests.
+
+use std::{
+  collections::HashMap,
+  future::Future,
+  sync::{Arc, Mutex},
+};
+
+use log::trace;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::{header, Method, Request, Response};
+
+use crate::v2::*;
+
+/// An upload in progress, holding the digest returned by the registry once it succeeded.
+type UploadSlot = Arc<futures::lock::Mutex<Option<String>>>;
+
+/// Requests in progress, with the response shared with the identical requests sent meanwhile.
+#[cfg(not(target_arch = "wasm32"))]
+type RequestSlot = Arc<futures::lock::Mutex<Option<Arc<BufferedResponse>>>>;
+
+/// Uploads in progress, by repository and digest, shared by a client and the clients derived
+/// from it.
+#[derive(Debug, Default)]
+pub(crate) struct UploadCoalescer(Mutex<HashMap<(String, String), UploadSlot>>);
+
+impl Client {
+  /// Run `upload` of blob `digest` to `name`, unless another task of the process is already
+  /// uploading it with this client or a client derived from it: the digest returned by that
+  /// upload is then returned, without uploading the blob again.
+  ///
+  /// If that upload fails, the next task waiting for it runs its own `upload`.
+  pub(crate) async fn coalesce_upload<F, Fut>(&self, name: &str, digest: &str, upload: F) -> Result<String>
+  where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<String>>,
+  {
+    let key = (name.to_string(), digest.to_string());
+    let slot = self.uploads.0.lock().unwrap().entry(key.clone()).or_default().clone();
+    let mut uploaded = slot.lock().await;
+    if let Some(uploaded) = &*uploaded {
+      trace!("Blob {} already uploaded to {} by another task", digest, name);
+      self.stats.update(|stats| stats.coalesced_uploads += 1);
+      return Ok(uploaded.clone());
+    }
+
+    let result = upload().await;
+    if let Ok(digest) = &result {
+      *uploaded = Some(digest.clone());
+    }
+    drop(uploaded);
+    // Tasks which joined the upload hold the slot until they are done with it, later ones
+    // start anew.
+    let mut uploads = self.uploads.0.lock().unwrap();
+    if uploads.get(&key).is_some_and(|s| Arc::ptr_eq(s, &slot)) {
+      uploads.remove(&key);
+    }
+    result
+  }
+}
+
+/// Manifest and tag list requests in progress, shared by a client and the clients derived from
+/// it, see `Config::coalesce_requests`.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Default)]
+pub(crate) struct RequestCoalescer(Mutex<HashMap<RequestKey, RequestSlot>>);
+
+/// Method, URL and credentials of a request.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct RequestKey {
+  method: Method,
+  url: String,
+  accept: Option<Vec<u8>>,
+  authorization: Option<Vec<u8>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl RequestKey {
+  /// The key of `request`, if it is a `GET` or `HEAD` request for a manifest or a tag list.
+  fn of(request: &Request) -> Option<Self> {
+    if !matches!(*request.method(), Method::GET | Method::HEAD) || request.headers().contains_key(header::RANGE) {
+      return None;
+    }
+    if !matches!(
+      EndpointClass::of(request.url()),
+      EndpointClass::Manifest | EndpointClass::Tags
+    ) {
+      return None;
+    }
+    let value = |name| {
+      request
+        .headers()
+        .get(name)
+        .map(|v: &header::HeaderValue| v.as_bytes().to_vec())
+    };
+    Some(Self {
+      method: request.method().clone(),
+      url: request.url().to_string(),
+      accept: value(header::ACCEPT),
+      authorization: value(header::AUTHORIZATION),
+    })
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Client {
+  /// Send `request`, sharing the response of an identical manifest or tag list request already
+  /// in progress instead if requests are coalesced.
+  ///
+  /// The body of coalesced responses is read before they are returned, up to the size limit of
+  /// manifests or tag lists. If the request in progress fails, the next identical request
+  /// waiting for it is sent on its own.
+  pub(crate) async fn dispatch_coalesced(&self, request: Request) -> Result<Response> {
+    let (Some(requests), Some(key)) = (&self.coalesced_requests, RequestKey::of(&request)) else {
+      return self.dispatch(request).await;
+    };
+    let slot = requests.0.lock().unwrap().entry(key.clone()).or_default().clone();
+    let mut shared = slot.lock().await;
+    if let Some(shared) = &*shared {
+      trace!("{} '{}' coalesced with an identical request", key.method, key.url);
+      self.stats.update(|stats| stats.coalesced_requests += 1);
+      return Ok(shared.to_response());
+    }
+
+    let (limit, kind) = match EndpointClass::of(request.url()) {
+      EndpointClass::Tags => (self.limits.max_tag_list_size, "tag list"),
+      _ => (self.limits.max_manifest_size, "manifest"),
+    };
+    let result = match self.dispatch(request).await {
+      Ok(response) => BufferedResponse::read(response, limit, kind)
+        .await
+        .map(Arc::new)
+        .map_err(|e| self.deadline_error(e)),
+      Err(e) => Err(e),
+    };
+    if let Ok(response) = &result {
+      *shared = Some(response.clone());
+    }
+    drop(shared);
+    let mut requests = requests.0.lock().unwrap();
+    if requests.get(&key).is_some_and(|s| Arc::ptr_eq(s, &slot)) {
+      requests.remove(&key);
+    }
+    Ok(result?.to_response())
+  }
+}
@@ -0,0 +1,124 @@
+use log::trace;
+use reqwest::{header, Method, StatusCode, Url};
+use serde::Deserialize;
+
+use crate::{errors::Result, v2::*};
+
+/// Result of a conditional fetch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Conditional<T> {
+  /// The resource did not change since the given entity tag.
+  NotModified,
+  /// The resource changed, or no entity tag was given.
+  Modified {
+    value: T,
+    /// Entity tag to pass to the next fetch, if the registry provided one.
+    etag: Option<String>,
+  },
+}
+
+#[derive(Deserialize)]
+struct TagList {
+  #[serde(default)]
+  tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct RepositoryList {
+  #[serde(default)]
+  repositories: Vec<String>,
+}
+
+impl Client {
+  /// List all tags of an image, unless they did not change since `etag`.
+  ///
+  /// Registries which return an `ETag` for tag lists answer `304 Not Modified` when the
+  /// list is unchanged, saving the download of the whole list. The entity tag of the first
+  /// page is used for the whole list.
+  pub async fn get_tags_if_modified(&self, name: &str, etag: Option<&str>) -> Result<Conditional<Vec<String>>> {
+    let url = self.repository_url(name, "tags/list")?;
+    self
+      .fetch_list_if_modified(url, etag, self.limits.max_tag_list_size, "tag list", |body| {
+        Ok(
+          self
+            .parse_payload::<TagList>(body, Payload::TagList(name))?
+            .tags
+            .unwrap_or_default(),
+        )
+      })
+      .await
+  }
+
+  /// List all repositories of the registry, unless the catalog did not change since `etag`.
+  pub async fn get_catalog_if_modified(&self, etag: Option<&str>) -> Result<Conditional<Vec<String>>> {
+    let url = Url::parse(&format!("{}/v2/_catalog", self.base_url))?;
+    self
+      .fetch_list_if_modified(url, etag, self.limits.max_catalog_size, "catalog", |body| {
+        Ok(
+          self
+            .parse_payload::<RepositoryList>(body, Payload::Catalog)?
+            .repositories,
+        )
+      })
+      .await
+  }
+
+  async fn fetch_list_if_modified(
+    &self,
+    url: Url,
+    etag: Option<&str>,
+    limit: u64,
+    kind: &'static str,
+    parse: impl Fn(&[u8]) -> Result<Vec<String>>,
+  ) -> Result<Conditional<Vec<String>>> {
+    let mut request = self
+      .build_reqwest(Method::GET, url.clone())
+      .header(header::ACCEPT, "application/json");
+    if let Some(etag) = etag {
+      request = request.header(header::IF_NONE_MATCH, etag);
+    }
+
+    let mut res = self.send(request).await?;
+    let status = res.status();
+    trace!("GET '{}' status: {:?}", res.url(), status);
+
+    let new_etag = match status {
+      StatusCode::NOT_MODIFIED => return Ok(Conditional::NotModified),
+      StatusCode::OK => match res.headers().get(header::ETAG) {
+        Some(v) => Some(v.to_str()?.to_string()),
+        None => None,
+      },
+      _ => return Err(ApiErrors::from(res).await),
+    };
+
+    let mut value = Vec::new();
+    loop {
+      let next = parse_link(res.headers().get(header::LINK));
+      let body = read_decoded_limited(res, limit, kind)
+        .await
+        .map_err(|e| self.deadline_error(e))?;
+      value.extend(parse(&body)?);
+
+      let next = match next {
+        Some(query) => {
+          let mut next = url.clone();
+          next.set_query(Some(&query));
+          next
+        }
+        None => break,
+      };
+      res = self
+        .send(
+          self
+            .build_reqwest(Method::GET, next)
+            .header(header::ACCEPT, "application/json"),
+        )
+        .await?;
+      if res.status() != StatusCode::OK {
+        return Err(ApiErrors::from(res).await);
+      }
+    }
+
+    Ok(Conditional::Modified { value, etag: new_etag })
+  }
+}
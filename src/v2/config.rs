@@ -1,18 +1,68 @@
+use std::{
+  net::SocketAddr,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
 use log::trace;
-use reqwest::Certificate;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::{Certificate, Identity};
 
-use crate::{mediatypes::MediaTypes, v2::*};
+use crate::{layer::Compression, mediatypes::MediaTypes, v2::*};
 
 /// Configuration for a `Client`.
+///
+/// On `wasm32` targets, connections are made by the `fetch` API of the JavaScript runtime:
+/// certificate, DNS, connection, HTTP version and redirect settings are ignored, and
+/// certificate pins can never be satisfied.
 #[derive(Debug)]
 pub struct Config {
   index: String,
   insecure_registry: bool,
   user_agent: Option<String>,
+  request_id_header: String,
   username: Option<String>,
   password: Option<String>,
+  device_login: Option<DeviceLogin>,
+  token_store: Option<Arc<dyn TokenStore>>,
+  credential_provider: Option<Arc<dyn CredentialProvider>>,
+  identities: Vec<(String, String, String)>,
   accept_invalid_certs: bool,
+  #[cfg(not(target_arch = "wasm32"))]
   root_certificates: Vec<Certificate>,
+  #[cfg(not(target_arch = "wasm32"))]
+  identity: Option<Identity>,
+  pinned_certificates: Vec<String>,
+  request_signer: Option<Arc<dyn RequestSigner>>,
+  interceptors: Vec<Arc<dyn Interceptor>>,
+  #[cfg(all(feature = "test-support", not(target_arch = "wasm32")))]
+  chaos: Option<Arc<crate::chaos::Chaos>>,
+  upload_journal: Option<PathBuf>,
+  resolve_overrides: Vec<(String, SocketAddr)>,
+  ip_preference: IpPreference,
+  connect_timeout: Option<Duration>,
+  http_version: HttpVersion,
+  http2_adaptive_window: bool,
+  max_idle_connections_per_host: Option<usize>,
+  accept_encoding: Vec<Compression>,
+  limits: ResponseLimits,
+  strict_media_types: bool,
+  lenient_parsing: bool,
+  soft_fail: bool,
+  profile: RegistryProfile,
+  check_tag_mutability: bool,
+  read_only: bool,
+  custom_media_types: CustomMediaTypes,
+  manifest_cache_size: u64,
+  reuse_signed_urls: bool,
+  #[cfg(not(target_arch = "wasm32"))]
+  response_cache_size: u64,
+  #[cfg(not(target_arch = "wasm32"))]
+  coalesce_requests: bool,
+  retry_policy: Option<RetryPolicy>,
+  parallel_downloads: Option<ParallelDownloads>,
+  redirect_policy: RedirectPolicy,
   accepted_types: Option<Vec<(MediaTypes, Option<f64>)>>,
 }
 
@@ -36,11 +86,256 @@ impl Config {
   }
 
   /// Add a root certificate the client should trust for TLS verification
+  #[cfg(not(target_arch = "wasm32"))]
   pub fn add_root_certificate(mut self, certificate: Certificate) -> Self {
     self.root_certificates.push(certificate);
     self
   }
 
+  /// Set the client certificate and key presented to registries requiring mutual TLS.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn client_identity(mut self, identity: Option<Identity>) -> Self {
+    self.identity = identity;
+    self
+  }
+
+  /// Resolve `host` to the given address instead of using DNS, like curl's `--resolve`.
+  ///
+  /// The port of `addr` is ignored: connections use the port of the registry URL.
+  pub fn resolve(mut self, host: &str, addr: SocketAddr) -> Self {
+    self.resolve_overrides.push((host.to_owned(), addr));
+    self
+  }
+
+  /// Set which address family to use when connecting to dual-stack registries.
+  pub fn ip_preference(mut self, preference: IpPreference) -> Self {
+    self.ip_preference = preference;
+    self
+  }
+
+  /// Set a timeout for establishing connections, per address tried.
+  pub fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+    self.connect_timeout = timeout;
+    self
+  }
+
+  /// Set which HTTP versions to use with the registry, `HttpVersion::Adaptive` by default.
+  pub fn http_version(mut self, version: HttpVersion) -> Self {
+    self.http_version = version;
+    self
+  }
+
+  /// Size HTTP/2 flow control windows from the measured bandwidth-delay product.
+  ///
+  /// The default windows limit the throughput of each stream on high-latency links, which
+  /// slows down large blob downloads multiplexed over a single connection.
+  pub fn http2_adaptive_window(mut self, enabled: bool) -> Self {
+    self.http2_adaptive_window = enabled;
+    self
+  }
+
+  /// Set how many idle connections to the registry are kept open for reuse, unlimited by default.
+  pub fn max_idle_connections_per_host(mut self, max: Option<usize>) -> Self {
+    self.max_idle_connections_per_host = max;
+    self
+  }
+
+  /// Ask the registry to compress catalogs and tag lists with one of `encodings`, in order of
+  /// preference, e.g. `[Compression::Zstd, Compression::Gzip]`.
+  ///
+  /// Compressed responses are decompressed transparently, which reduces the transfer time of
+  /// large tag lists and catalogs; other responses, e.g. blobs, are never requested compressed.
+  /// Nothing is requested by default.
+  pub fn accept_encoding(mut self, encodings: Vec<Compression>) -> Self {
+    self.accept_encoding = encodings;
+    self
+  }
+
+  /// Set the maximum sizes accepted for manifests, configs, tag lists and catalogs.
+  pub fn response_limits(mut self, limits: ResponseLimits) -> Self {
+    self.limits = limits;
+    self
+  }
+
+  /// Validate fetched manifests against the media type they are served with.
+  ///
+  /// In strict mode, manifests with missing required fields, malformed descriptors or a
+  /// payload which does not match the `Content-Type` are rejected with `ManifestError::Invalid`.
+  pub fn strict_media_types(mut self, strict: bool) -> Self {
+    self.strict_media_types = strict;
+    self
+  }
+
+  /// Tolerate known deviations of registries from the specification when parsing responses.
+  ///
+  /// In lenient mode, tag lists and catalogs with a missing or null list, or with null entries,
+  /// are read as if the list had no such entries, and the keys and media types of manifests are
+  /// fixed when their casing is wrong. Every deviation is logged and collected, see
+  /// `Client::parse_warnings`. By default such responses fail to parse. Unknown fields are
+  /// ignored in both modes.
+  pub fn lenient_parsing(mut self, lenient: bool) -> Self {
+    self.lenient_parsing = lenient;
+    self
+  }
+
+  /// Report optional features the registry does not implement, such as the catalog, deletion
+  /// or the referrers API, as unsupported rather than as failures, so that the same code can
+  /// run against registries supporting different features.
+  ///
+  /// With soft failures, catalogs of registries without one are empty, referrers are empty
+  /// when the registry does not implement them, and deletions the registry refuses to
+  /// implement fail with `Error::Unsupported(OptionalFeature::Deletion)` rather than with the
+  /// answer of the registry. The features found unsupported are collected, see
+  /// `Client::unsupported_features`. Disabled by default.
+  pub fn soft_fail(mut self, soft: bool) -> Self {
+    self.soft_fail = soft;
+    self
+  }
+
+  /// Set the registry implementation whose quirks the client works around, detected from
+  /// responses by default.
+  pub fn registry_profile(mut self, profile: RegistryProfile) -> Self {
+    self.profile = profile;
+    self
+  }
+
+  /// Check whether a tag is protected by immutability rules or locks before pushing to it or
+  /// deleting it, failing early with `Error::TagImmutable`.
+  ///
+  /// This costs a request to the vendor API of the registry per operation, see
+  /// `Client::tag_mutability`; operations by digest are not checked.
+  pub fn check_tag_mutability(mut self, check: bool) -> Self {
+    self.check_tag_mutability = check;
+    self
+  }
+
+  /// Build a client which never modifies the registry, e.g. for audit and reporting tools.
+  ///
+  /// Methods pushing or deleting content fail with `Error::ReadOnlyClient` without sending
+  /// any request, and so do raw requests other than `GET`, `HEAD` and `OPTIONS`. Clients
+  /// derived from a read-only client are read-only too.
+  pub fn read_only(mut self, read_only: bool) -> Self {
+    self.read_only = read_only;
+    self
+  }
+
+  /// Set the policy for following redirects.
+  pub fn redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+    self.redirect_policy = policy;
+    self
+  }
+
+  /// Retry requests which fail transiently according to `policy`; `None` disables retries.
+  pub fn retry_policy(mut self, policy: Option<RetryPolicy>) -> Self {
+    self.retry_policy = policy;
+    self
+  }
+
+  /// Download large blobs streamed with `Client::get_blob_stream` as parts in parallel.
+  pub fn parallel_downloads(mut self, downloads: Option<ParallelDownloads>) -> Self {
+    self.parallel_downloads = downloads;
+    self
+  }
+
+  /// Set the maximum total size, in bytes, of manifests cached after being fetched by digest.
+  ///
+  /// Defaults to 16 MiB; `0` disables the cache.
+  pub fn manifest_cache_size(mut self, size: u64) -> Self {
+    self.manifest_cache_size = size;
+    self
+  }
+
+  /// Whether to download blobs again from the signed storage URL the registry redirected an
+  /// earlier download of the same blob to, while the URL is valid.
+  ///
+  /// This spares the registry the requests of retried and resumed downloads, and of the parts
+  /// of parallel downloads, which are then less likely to be rate limited. Enabled by default.
+  pub fn reuse_signed_urls(mut self, reuse: bool) -> Self {
+    self.reuse_signed_urls = reuse;
+    self
+  }
+
+  /// Set the maximum total size, in bytes, of the bodies of responses cached as allowed by their
+  /// `Cache-Control` or `Expires` headers.
+  ///
+  /// Only successful responses to `GET` requests are cached, for as long as they are fresh;
+  /// responses marked `no-store` or `no-cache`, partial downloads and token requests are not.
+  /// This spares requests to registries and CDNs relying on HTTP caching, e.g. for redirected
+  /// blob downloads. Defaults to `0`, which disables the cache.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn response_cache_size(mut self, size: u64) -> Self {
+    self.response_cache_size = size;
+    self
+  }
+
+  /// Whether identical manifest and tag list requests sent concurrently, with the same URL and
+  /// credentials, share the response of the first one instead of each being sent, e.g. by
+  /// backends of user interfaces fanning out the same lookups.
+  ///
+  /// Coalesced responses are read in full, up to the size limit of manifests or tag lists,
+  /// before being returned. Defaults to `false`.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn coalesce_requests(mut self, coalesce: bool) -> Self {
+    self.coalesce_requests = coalesce;
+    self
+  }
+
+  /// Register a deserializer for a vendor-specific manifest media type.
+  ///
+  /// The media type is added to the `Accept` header of manifest requests, and manifests served
+  /// with it are returned by `get_manifest` as `Manifest::Custom` holding the deserializer output.
+  pub fn register_media_type<F>(mut self, media_type: &str, deserializer: F) -> Self
+  where
+    F: Fn(&[u8]) -> std::result::Result<serde_json::Value, HookError> + Send + Sync + 'static,
+  {
+    self.custom_media_types.insert(media_type, Arc::new(deserializer));
+    self
+  }
+
+  /// Pin the SHA-256 fingerprint of a server certificate.
+  ///
+  /// The fingerprint is computed over the DER encoding of the leaf certificate and may be given
+  /// as plain or colon-separated hex. Once at least one fingerprint is pinned, every server the
+  /// client talks to (including token endpoints and redirect targets) must present one of the
+  /// pinned certificates. The certificate of every connection is checked as soon as its TLS
+  /// handshake completes, before any request is sent over it, so headers and credentials are
+  /// never sent to a server which fails the check, and redirects are followed hop by hop.
+  /// Pinning is checked in addition to CA validation; combine it with
+  /// `accept_invalid_certs(true)` to rely on the pins only.
+  pub fn pin_server_certificate(mut self, sha256_fingerprint: &str) -> Self {
+    self.pinned_certificates.push(sha256_fingerprint.to_owned());
+    self
+  }
+
+  /// Set a hook which signs every request right before it is sent.
+  pub fn request_signer<S: RequestSigner + 'static>(mut self, signer: S) -> Self {
+    self.request_signer = Some(Arc::new(signer));
+    self
+  }
+
+  /// Add a hook which observes every request before it is sent and every response received.
+  ///
+  /// Interceptors are invoked in the order they were added.
+  pub fn with_interceptor<I: Interceptor + 'static>(mut self, interceptor: I) -> Self {
+    self.interceptors.push(Arc::new(interceptor));
+    self
+  }
+
+  /// Inject faults into the requests sent by the client, see [`crate::chaos`].
+  #[cfg(all(feature = "test-support", not(target_arch = "wasm32")))]
+  pub fn chaos(mut self, chaos: crate::chaos::Chaos) -> Self {
+    self.chaos = Some(Arc::new(chaos));
+    self
+  }
+
+  /// Record the blob upload sessions started by the client in a journal file.
+  ///
+  /// This allows cancelling sessions left behind by crashed pushes with `Client::cancel_stale_uploads`.
+  pub fn upload_journal<P: AsRef<Path>>(mut self, path: P) -> Self {
+    self.upload_journal = Some(path.as_ref().to_path_buf());
+    self
+  }
+
   /// Set custom Accept headers
   pub fn accepted_types(mut self, accepted_types: Option<Vec<(MediaTypes, Option<f64>)>>) -> Self {
     self.accepted_types = accepted_types;
@@ -48,11 +343,28 @@ impl Config {
   }
 
   /// Set the user-agent to be used for registry authentication.
+  ///
+  /// This replaces the whole header, including the identification of this library: prefer
+  /// `user_agent_prefix` to identify an application. `None` sends no User-Agent.
   pub fn user_agent(mut self, user_agent: Option<String>) -> Self {
     self.user_agent = user_agent;
     self
   }
 
+  /// Identify the application in the User-Agent, ahead of the library's product token.
+  pub fn user_agent_prefix(mut self, user_agent: UserAgent) -> Self {
+    self.user_agent = Some(user_agent.to_string());
+    self
+  }
+
+  /// Set the header carrying request IDs, `X-Request-Id` by default.
+  ///
+  /// See `Client::correlate`.
+  pub fn request_id_header(mut self, header: &str) -> Self {
+    self.request_id_header = header.to_string();
+    self
+  }
+
   /// Set the username to be used for registry authentication.
   pub fn username(mut self, user: Option<String>) -> Self {
     self.username = user;
@@ -65,6 +377,38 @@ impl Config {
     self
   }
 
+  /// Log in interactively with a device code when authenticating without credentials.
+  pub fn device_login(mut self, login: Option<DeviceLogin>) -> Self {
+    self.device_login = login;
+    self
+  }
+
+  /// Persist the bearer tokens obtained by `Client::authenticate` in `store`, e.g. a
+  /// `FileTokenStore`, and reuse them until they expire, also in later processes.
+  pub fn token_store<S: TokenStore + 'static>(mut self, store: S) -> Self {
+    self.token_store = Some(Arc::new(store));
+    self
+  }
+
+  /// Ask `provider` for fresh credentials when the registry rejects the username and password
+  /// of the client mid-session, e.g. after a password rotation, before failing.
+  ///
+  /// Requests authorized with Basic credentials, and token requests, are sent again once with
+  /// the credentials it returns, which the client and the clients derived from it keep using.
+  pub fn credential_provider<P: CredentialProvider + 'static>(mut self, provider: P) -> Self {
+    self.credential_provider = Some(Arc::new(provider));
+    self
+  }
+
+  /// Register the credential identity `id`, e.g. a tenant of a multi-tenant service, which
+  /// `Client::as_identity` returns a client for.
+  pub fn identity(mut self, id: &str, username: &str, password: &str) -> Self {
+    self
+      .identities
+      .push((id.to_string(), username.to_string(), password.to_string()));
+    self
+  }
+
   /// Read credentials from a JSON config file
   pub fn read_credentials<T: ::std::io::Read>(mut self, reader: T) -> Self {
     if let Ok(creds) = crate::get_credentials(reader, &self.index) {
@@ -75,7 +419,7 @@ impl Config {
   }
 
   /// Return a `Client` to interact with a v2 registry.
-  pub fn build(self) -> Result<Client> {
+  pub fn build(mut self) -> Result<Client> {
     let base = if self.insecure_registry {
       "http://".to_string() + &self.index
     } else {
@@ -87,18 +431,20 @@ impl Config {
       base,
       self.username
     );
-    let creds = match (self.username, self.password) {
+    let creds = match (self.username.take(), self.password.take()) {
       (None, None) => None,
       (u, p) => Some((u.unwrap_or_else(|| "".into()), p.unwrap_or_else(|| "".into()))),
     };
 
-    let mut builder = reqwest::ClientBuilder::new().danger_accept_invalid_certs(self.accept_invalid_certs);
+    #[cfg(feature = "fips")]
+    super::content_digest::check_digests()?;
+    let pinned_certificates = self
+      .pinned_certificates
+      .iter()
+      .map(|f| normalize_fingerprint(f))
+      .collect::<Result<Vec<_>>>()?;
 
-    for ca in self.root_certificates {
-      builder = builder.add_root_certificate(ca)
-    }
-
-    let client = builder.build()?;
+    let client = self.transport(&pinned_certificates)?;
 
     let accepted_types = match self.accepted_types {
       Some(a) => a,
@@ -125,18 +471,142 @@ impl Config {
         ],
       },
     };
+    let identities = Identities::new(self.token_store.clone());
+    for (id, username, password) in &self.identities {
+      identities.insert(id, username, password);
+    }
     let c = Client {
       base_url: base,
       credentials: creds,
+      device_login: self.device_login,
+      identities: Arc::new(identities),
+      identity: None,
+      token_store: self.token_store,
+      credential_renewal: self.credential_provider.map(|p| Arc::new(CredentialRenewal::new(p))),
       user_agent: self.user_agent,
+      accept_encoding: match self.accept_encoding.is_empty() {
+        true => None,
+        false => Some(
+          self
+            .accept_encoding
+            .iter()
+            .map(|c| c.content_encoding())
+            .collect::<Vec<_>>()
+            .join(", "),
+        ),
+      },
+      request_id: None,
+      request_id_header: reqwest::header::HeaderName::try_from(self.request_id_header)?,
       auth: None,
       client,
       accepted_types,
+      pinned_certificates,
+      redirect_policy: self.redirect_policy,
+      request_signer: self.request_signer,
+      interceptors: self.interceptors,
+      #[cfg(all(feature = "test-support", not(target_arch = "wasm32")))]
+      chaos: self.chaos,
+      upload_journal: self.upload_journal.map(|p| Arc::new(UploadJournal::new(p))),
+      uploads: Default::default(),
+      limits: self.limits,
+      strict_media_types: self.strict_media_types,
+      lenient_parsing: self.lenient_parsing,
+      parse_warnings: Default::default(),
+      soft_fail: self.soft_fail,
+      unsupported_features: Default::default(),
+      profile: self.profile,
+      detected_profile: Default::default(),
+      check_tag_mutability: self.check_tag_mutability,
+      read_only: self.read_only,
+      custom_media_types: self.custom_media_types,
+      retry_policy: self.retry_policy,
+      deadline: None,
+      parallel_downloads: self.parallel_downloads,
+      stats: Default::default(),
+      manifest_cache: match self.manifest_cache_size {
+        0 => None,
+        size => Some(Arc::new(Mutex::new(ManifestCache::new(size)))),
+      },
+      signed_urls: self.reuse_signed_urls.then(Default::default),
+      #[cfg(not(target_arch = "wasm32"))]
+      response_cache: match self.response_cache_size {
+        0 => None,
+        size => Some(Arc::new(Mutex::new(ResponseCache::new(size)))),
+      },
+      #[cfg(not(target_arch = "wasm32"))]
+      coalesced_requests: self.coalesce_requests.then(Default::default),
     };
     Ok(c)
   }
 }
 
+impl Config {
+  /// Build the HTTP client, applying the TLS and connection settings.
+  #[cfg(not(target_arch = "wasm32"))]
+  fn transport(&mut self, pinned_certificates: &[String]) -> Result<reqwest::Client> {
+    let pinned = !pinned_certificates.is_empty();
+    // With pinned certificates, redirects are followed by the client, which applies the
+    // redirect policy to every hop.
+    let redirect = match pinned {
+      true => reqwest::redirect::Policy::none(),
+      false => self.redirect_policy.clone().into_reqwest(),
+    };
+    let mut builder = reqwest::ClientBuilder::new()
+      .danger_accept_invalid_certs(self.accept_invalid_certs)
+      .tls_info(pinned)
+      .redirect(redirect);
+    if pinned {
+      builder = builder.connector_layer(super::pinning::PinningLayer::new(pinned_certificates));
+    }
+
+    #[cfg(feature = "rustls")]
+    {
+      builder = builder.use_rustls_tls();
+      for ca in platform_root_certificates() {
+        builder = builder.add_root_certificate(ca.clone())
+      }
+    }
+
+    for ca in std::mem::take(&mut self.root_certificates) {
+      builder = builder.add_root_certificate(ca)
+    }
+
+    if let Some(identity) = self.identity.take() {
+      builder = builder.identity(identity);
+    }
+
+    if self.ip_preference != IpPreference::HappyEyeballs {
+      builder = builder.dns_resolver(Arc::new(dns::FamilyResolver::new(self.ip_preference)));
+    }
+
+    if let Some(timeout) = self.connect_timeout {
+      builder = builder.connect_timeout(timeout);
+    }
+
+    builder = match self.http_version {
+      HttpVersion::Adaptive => builder,
+      HttpVersion::Http1Only => builder.http1_only(),
+      HttpVersion::Http2PriorKnowledge => builder.http2_prior_knowledge(),
+    };
+    builder = builder.http2_adaptive_window(self.http2_adaptive_window);
+    if let Some(max) = self.max_idle_connections_per_host {
+      builder = builder.pool_max_idle_per_host(max);
+    }
+
+    for (host, addr) in &self.resolve_overrides {
+      builder = builder.resolve(host, *addr);
+    }
+
+    Ok(builder.build()?)
+  }
+
+  /// Build the HTTP client, which sends requests with the `fetch` API.
+  #[cfg(target_arch = "wasm32")]
+  fn transport(&mut self, _pinned_certificates: &[String]) -> Result<reqwest::Client> {
+    Ok(reqwest::ClientBuilder::new().build()?)
+  }
+}
+
 impl Default for Config {
   /// Initialize `Config` with default values.
   fn default() -> Self {
@@ -144,11 +614,121 @@ impl Default for Config {
       index: "registry-1.docker.io".into(),
       insecure_registry: false,
       accept_invalid_certs: false,
+      #[cfg(not(target_arch = "wasm32"))]
       root_certificates: Default::default(),
+      #[cfg(not(target_arch = "wasm32"))]
+      identity: None,
+      pinned_certificates: Default::default(),
+      request_signer: None,
+      interceptors: Vec::new(),
+      #[cfg(all(feature = "test-support", not(target_arch = "wasm32")))]
+      chaos: None,
+      upload_journal: None,
+      resolve_overrides: Default::default(),
+      ip_preference: Default::default(),
+      connect_timeout: None,
+      http_version: Default::default(),
+      http2_adaptive_window: false,
+      max_idle_connections_per_host: None,
+      accept_encoding: Vec::new(),
+      limits: Default::default(),
+      strict_media_types: false,
+      lenient_parsing: false,
+      soft_fail: false,
+      profile: Default::default(),
+      check_tag_mutability: false,
+      read_only: false,
+      custom_media_types: Default::default(),
+      manifest_cache_size: 16 << 20,
+      reuse_signed_urls: true,
+      #[cfg(not(target_arch = "wasm32"))]
+      response_cache_size: 0,
+      #[cfg(not(target_arch = "wasm32"))]
+      coalesce_requests: false,
+      retry_policy: None,
+      parallel_downloads: None,
+      redirect_policy: Default::default(),
       accepted_types: None,
       user_agent: Some(crate::USER_AGENT.to_owned()),
+      request_id_header: REQUEST_ID_HEADER.to_string(),
       username: None,
       password: None,
+      device_login: None,
+      token_store: None,
+      credential_provider: None,
+      identities: Vec::new(),
+    }
+  }
+}
+
+/// Normalize a SHA-256 fingerprint to lowercase hex without separators.
+fn normalize_fingerprint(fingerprint: &str) -> Result<String> {
+  let normalized = fingerprint.replace(':', "").to_lowercase();
+  if normalized.len() != 64 || !normalized.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Err(Error::InvalidCertificatePin(fingerprint.to_string()));
+  }
+  Ok(normalized)
+}
+
+/// Root certificates of the platform trust store, loaded once.
+#[cfg(all(feature = "rustls", not(target_arch = "wasm32")))]
+fn platform_root_certificates() -> &'static [Certificate] {
+  static ROOTS: std::sync::OnceLock<Vec<Certificate>> = std::sync::OnceLock::new();
+  ROOTS.get_or_init(load_platform_root_certificates)
+}
+
+/// Load the root certificates of the platform trust store: the system store on Windows and
+/// macOS, the usual CA bundles elsewhere, or `SSL_CERT_FILE` and `SSL_CERT_DIR` when set.
+///
+/// Certificates which cannot be loaded are skipped, as the platform store may hold some that
+/// rustls does not support.
+#[cfg(all(feature = "rustls", not(target_arch = "wasm32")))]
+fn load_platform_root_certificates() -> Vec<Certificate> {
+  let native = rustls_native_certs::load_native_certs();
+  for e in &native.errors {
+    trace!("Failed to load some platform root certificates: {}", e);
+  }
+  let roots: Vec<Certificate> = native
+    .certs
+    .iter()
+    .filter_map(|der| Certificate::from_der(der).ok())
+    .collect();
+  trace!("Loaded {} root certificates from the platform trust store", roots.len());
+  roots
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case("AB:".repeat(31) + "AB" => Some("ab".repeat(32)); "colon separated uppercase")]
+  #[test_case("ab".repeat(32) => Some("ab".repeat(32)); "plain hex")]
+  #[test_case("ab".repeat(31) => None; "too short")]
+  #[test_case("zz".repeat(32) => None; "not hex")]
+  fn fingerprint_normalization(fingerprint: String) -> Option<String> {
+    normalize_fingerprint(&fingerprint).ok()
+  }
+
+  #[cfg(feature = "rustls")]
+  #[test]
+  fn loads_platform_root_certificates() {
+    let bundle = concat!(
+      env!("CARGO_MANIFEST_DIR"),
+      "/tests/fixtures/certs.d/registry.example.com/ca.crt"
+    );
+    let empty = tempfile::tempdir().unwrap();
+    let saved = ["SSL_CERT_FILE", "SSL_CERT_DIR"].map(|name| (name, std::env::var_os(name)));
+    std::env::set_var("SSL_CERT_FILE", bundle);
+    std::env::set_var("SSL_CERT_DIR", empty.path());
+    let roots = load_platform_root_certificates();
+    for (name, value) in saved {
+      match value {
+        Some(value) => std::env::set_var(name, value),
+        None => std::env::remove_var(name),
+      }
     }
+    assert_eq!(roots.len(), 1);
   }
 }
@@ -1,12 +1,13 @@
 use std::str;
 
 /// Implements types and methods for content verification
-use sha2::{self, Digest};
+use self::backend::Hasher;
 
 /// DigestAlgorithm declares the supported algorithms
 #[derive(strum::Display, Clone, Debug)]
 pub enum DigestAlgorithm {
-  Sha256(sha2::Sha256),
+  Sha256(Hasher),
+  Sha512(Hasher),
 }
 
 impl std::str::FromStr for DigestAlgorithm {
@@ -14,7 +15,8 @@ impl std::str::FromStr for DigestAlgorithm {
 
   fn from_str(name: &str) -> Result<Self, Self::Err> {
     match name {
-      "sha256" => Ok(DigestAlgorithm::Sha256(sha2::Sha256::new())),
+      "sha256" => Ok(DigestAlgorithm::Sha256(Hasher::sha256())),
+      "sha512" => Ok(DigestAlgorithm::Sha512(Hasher::sha512())),
       _ => Err(ContentDigestError::AlgorithmUnknown(name.to_string())),
     }
   }
@@ -28,6 +30,8 @@ pub enum ContentDigestError {
   AlgorithmUnknown(String),
   #[error("verification failed: expected '{expected}', got '{got}'")]
   Verify { expected: String, got: String },
+  #[error("digests cannot be computed: {0}")]
+  Unavailable(String),
 }
 
 /// ContentDigest stores a digest and its DigestAlgorithm
@@ -62,7 +66,7 @@ impl ContentDigest {
   }
 
   pub fn verify(self) -> std::result::Result<(), ContentDigestError> {
-    let digest = self.algorithm.digest();
+    let digest = self.algorithm.try_digest()?;
     if digest != self.digest {
       return Err(ContentDigestError::Verify {
         expected: self.digest,
@@ -79,27 +83,181 @@ impl std::fmt::Display for ContentDigest {
   }
 }
 
+/// Check that digests can be computed, which only fails if OpenSSL provides no SHA-2, e.g.
+/// because FIPS mode is required but the FIPS provider cannot be loaded.
+///
+/// Clients run this check when they are built, so that the digests of data they produce can be
+/// computed without handling errors afterwards.
+#[cfg(feature = "fips")]
+pub(crate) fn check_digests() -> Result<(), ContentDigestError> {
+  for algorithm in [
+    DigestAlgorithm::Sha256(Hasher::sha256()),
+    DigestAlgorithm::Sha512(Hasher::sha512()),
+  ] {
+    algorithm.try_digest()?;
+  }
+  Ok(())
+}
+
+/// Compute the `sha256:<hex>` digest of `data`.
+pub(crate) fn sha256_digest(data: &[u8]) -> String {
+  format!("sha256:{}", sha256_hex(data))
+}
+
+/// Compute the hex-encoded sha256 hash of `data`.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+  let mut hasher = Hasher::sha256();
+  hasher.update(data);
+  hasher.finish_hex()
+}
+
+/// Compute the digest of `data` with the algorithm of `reference`, e.g. to compare it with
+/// `reference`, falling back to sha256 if `reference` is not a digest of a supported algorithm.
+#[cfg(feature = "client")]
+pub(crate) fn digest_like(reference: &str, data: &[u8]) -> String {
+  let mut algorithm = DigestAlgorithm::of(reference).unwrap_or_else(|_| DigestAlgorithm::sha256());
+  algorithm.update(data);
+  algorithm.digest()
+}
+
 impl DigestAlgorithm {
-  fn update(&mut self, input: &[u8]) {
+  #[cfg(feature = "client")]
+  pub(crate) fn sha256() -> Self {
+    DigestAlgorithm::Sha256(Hasher::sha256())
+  }
+
+  /// The algorithm of `digest`, selected from its `algorithm:` prefix.
+  #[cfg(feature = "client")]
+  pub(crate) fn of(digest: &str) -> Result<Self, ContentDigestError> {
+    match digest.split_once(':') {
+      Some((algorithm, _)) => algorithm.parse(),
+      None => Err(ContentDigestError::BadDigest(digest.to_string())),
+    }
+  }
+
+  pub(crate) fn update(&mut self, input: &[u8]) {
     match self {
-      DigestAlgorithm::Sha256(hash) => {
+      DigestAlgorithm::Sha256(hash) | DigestAlgorithm::Sha512(hash) => {
         hash.update(input);
       }
     }
   }
 
-  fn digest(self) -> String {
-    let (algo, digest) = match self {
-      DigestAlgorithm::Sha256(hash) => ("sha256", hash.finalize()),
-    };
-    format!("{}:{:x}", algo, &digest)
+  #[cfg(feature = "client")]
+  pub(crate) fn digest(self) -> String {
+    match self {
+      DigestAlgorithm::Sha256(hash) => format!("sha256:{}", hash.finish_hex()),
+      DigestAlgorithm::Sha512(hash) => format!("sha512:{}", hash.finish_hex()),
+    }
+  }
+
+  /// The digest of the input, failing if the hash function is unavailable.
+  pub(crate) fn try_digest(self) -> Result<String, ContentDigestError> {
+    match self {
+      DigestAlgorithm::Sha256(hash) => Ok(format!("sha256:{}", hash.try_finish_hex()?)),
+      DigestAlgorithm::Sha512(hash) => Ok(format!("sha512:{}", hash.try_finish_hex()?)),
+    }
+  }
+}
+
+/// Hash functions implemented in Rust with the RustCrypto crates.
+#[cfg(not(feature = "fips"))]
+mod backend {
+  use sha2::Digest;
+
+  #[derive(Clone, Debug)]
+  pub enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha512(sha2::Sha512),
+  }
+
+  impl Hasher {
+    pub fn sha256() -> Self {
+      Hasher::Sha256(sha2::Sha256::new())
+    }
+
+    pub fn sha512() -> Self {
+      Hasher::Sha512(sha2::Sha512::new())
+    }
+
+    pub fn update(&mut self, input: &[u8]) {
+      match self {
+        Hasher::Sha256(hash) => hash.update(input),
+        Hasher::Sha512(hash) => hash.update(input),
+      }
+    }
+
+    pub fn finish_hex(self) -> String {
+      match self {
+        Hasher::Sha256(hash) => format!("{:x}", hash.finalize()),
+        Hasher::Sha512(hash) => format!("{:x}", hash.finalize()),
+      }
+    }
+
+    pub fn try_finish_hex(self) -> Result<String, super::ContentDigestError> {
+      Ok(self.finish_hex())
+    }
+  }
+}
+
+/// Hash functions provided by OpenSSL, which uses its FIPS provider when configured to.
+///
+/// The FIPS provider is not loaded here: OpenSSL must be configured to load it, e.g. with
+/// `OPENSSL_CONF`, or the application must load it before building clients.
+#[cfg(feature = "fips")]
+mod backend {
+  use std::fmt;
+
+  use openssl::{error::ErrorStack, hash::MessageDigest};
+
+  use super::ContentDigestError;
+
+  /// A hash in progress, or the error which made OpenSSL fail to compute it, reported when the
+  /// hash is finished.
+  #[derive(Clone)]
+  pub struct Hasher(Result<openssl::hash::Hasher, ErrorStack>);
+
+  impl fmt::Debug for Hasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      f.write_str("Hasher(..)")
+    }
+  }
+
+  impl Hasher {
+    pub fn sha256() -> Self {
+      Hasher(openssl::hash::Hasher::new(MessageDigest::sha256()))
+    }
+
+    pub fn sha512() -> Self {
+      Hasher(openssl::hash::Hasher::new(MessageDigest::sha512()))
+    }
+
+    pub fn update(&mut self, input: &[u8]) {
+      if let Ok(hasher) = &mut self.0 {
+        if let Err(e) = hasher.update(input) {
+          self.0 = Err(e);
+        }
+      }
+    }
+
+    /// The hex-encoded hash; digests are only computed by clients after `check_digests`
+    /// succeeded, so OpenSSL cannot fail here.
+    pub fn finish_hex(self) -> String {
+      self
+        .try_finish_hex()
+        .expect("OpenSSL cannot compute SHA-2 digests, check its FIPS configuration")
+    }
+
+    pub fn try_finish_hex(self) -> Result<String, ContentDigestError> {
+      let unavailable = |e: ErrorStack| ContentDigestError::Unavailable(e.to_string());
+      let digest = self.0.and_then(|mut hasher| hasher.finish()).map_err(unavailable)?;
+      Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
   }
 }
 
 #[cfg(test)]
 mod tests {
-  use sha2;
-
   use super::*;
 
   type Fallible<T> = Result<T, crate::Error>;
@@ -143,14 +301,32 @@ mod tests {
     content_digest.verify().map_err(Into::into)
   }
 
+  #[test]
+  fn verify_succeeds_with_sha512() -> Fallible<()> {
+    let digest = "sha512:075acbafc43b4285903d2db3db7be7cebe056d50fba6e8a9f9bcdf7f3a2bba84\
+                  1786c29fa385780cd0bb631e0d44be60a863f9a088c16b131ea94f4ca180844d";
+    #[cfg(feature = "client")]
+    assert_eq!(digest_like(digest, b"somecontent"), digest);
+
+    let mut content_digest = ContentDigest::try_new(digest)?;
+    content_digest.update(b"some");
+    content_digest.update(b"content");
+    content_digest.verify().map_err(Into::into)
+  }
+
+  #[cfg(feature = "client")]
+  #[test]
+  fn digest_like_falls_back_to_sha256() {
+    assert_eq!(digest_like("latest", b""), sha256_digest(b""));
+    assert_eq!(digest_like("md5:abcd", b""), sha256_digest(b""));
+  }
+
   #[test]
   fn verify_fails_with_different_content() -> Fallible<()> {
     let blob: &[u8] = b"somecontent";
     let different_blob: &[u8] = b"someothercontent";
 
-    let mut expected_digest = DigestAlgorithm::Sha256(sha2::Sha256::new());
-    expected_digest.update(different_blob);
-    let expected_digest = expected_digest.digest();
+    let expected_digest = sha256_digest(different_blob);
 
     let mut content_digest = ContentDigest::try_new(&expected_digest)?;
     content_digest.update(blob);
@@ -0,0 +1,101 @@
+//! Correlation of the requests issued for one high-level operation.
+
+use std::{
+  collections::hash_map::RandomState,
+  future::Future,
+  hash::{BuildHasher, Hasher},
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::v2::*;
+
+/// Header carrying the request ID, unless configured otherwise with `Config::request_id_header`.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+impl Client {
+  /// Clone of the client sending `request_id` with every request, including requests to
+  /// token endpoints and retries, so that they can be found in the registry logs.
+  pub fn with_request_id(&self, request_id: &str) -> Self {
+    Self {
+      request_id: Some(request_id.to_string()),
+      ..self.clone()
+    }
+  }
+
+  /// Request ID sent with every request, if any.
+  pub fn request_id(&self) -> Option<&str> {
+    self.request_id.as_deref()
+  }
+
+  /// Run an operation with a client sending the same request ID with every request.
+  ///
+  /// The request ID is `request_id`, or a random one if `None`. Errors of the operation are
+  /// returned as `Error::Correlated`, carrying the request ID. Interceptors see the request
+  /// ID with `InterceptedRequest::request_id`.
+  ///
+  /// ```rust,no_run
+  /// # use tokio;
+  /// # #[tokio::main]
+  /// # async fn main() {
+  /// # async fn run() -> docker_registry::errors::Result<()> {
+  /// use docker_registry::v2::Client;
+  ///
+  /// let client = Client::configure().registry("localhost:5000").build()?;
+  /// let retagged = client
+  ///   .correlate(None, |client| async move {
+  ///     let (manifest, media_type, _) =
+  ///       client.get_raw_manifest("busybox", "latest", None).await?;
+  ///     client
+  ///       .put_manifest("busybox", "stable", &media_type, &manifest)
+  ///       .await
+  ///   })
+  ///   .await;
+  /// if let Err(e) = retagged {
+  ///   println!("push failed, see request {:?}: {}", e.request_id(), e);
+  /// }
+  /// # Ok(())
+  /// # };
+  /// # run().await.unwrap();
+  /// # }
+  /// ```
+  pub async fn correlate<T, F, Fut>(&self, request_id: Option<&str>, operation: F) -> Result<T>
+  where
+    F: FnOnce(Client) -> Fut,
+    Fut: Future<Output = Result<T>>,
+  {
+    let request_id = request_id.map_or_else(new_request_id, str::to_string);
+    let client = self.with_request_id(&request_id);
+    operation(client).await.map_err(|e| match e {
+      Error::Correlated { .. } => e,
+      e => Error::Correlated {
+        request_id,
+        source: Box::new(e),
+      },
+    })
+  }
+}
+
+/// Random request ID, formatted as 32 hex digits like W3C trace IDs.
+fn new_request_id() -> String {
+  static COUNTER: AtomicU64 = AtomicU64::new(0);
+  let mut hasher = RandomState::new().build_hasher();
+  hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+  let high = hasher.finish();
+  if let Ok(since_epoch) = system_now().duration_since(std::time::UNIX_EPOCH) {
+    hasher.write_u128(since_epoch.as_nanos());
+  }
+  format!("{:016x}{:016x}", high, hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn request_ids_are_unique() {
+    let (a, b) = (new_request_id(), new_request_id());
+    assert_eq!(a.len(), 32);
+    assert!(a.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_ne!(a, b);
+  }
+}
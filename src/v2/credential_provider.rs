@@ -0,0 +1,153 @@
+//! Renewal of the credentials registries stop accepting mid-session.
+
+use std::{
+  collections::HashMap,
+  fmt,
+  sync::{Arc, Mutex},
+};
+
+use base64::prelude::*;
+use futures::future::BoxFuture;
+use log::debug;
+use reqwest::header::{self, HeaderValue};
+
+use crate::v2::*;
+
+/// Future returned by [`CredentialProvider::credentials`].
+pub type CredentialFuture<'a> = BoxFuture<'a, std::result::Result<Option<(String, String)>, HookError>>;
+
+/// Hook providing fresh credentials once the registry rejects those of the client, e.g. after
+/// its password was rotated or its personal access token expired.
+///
+/// The provider is asked at most once for every set of rejected credentials, however many
+/// requests they were rejected for: requests fail with the registry's answer if it returns no
+/// other credentials. Providers running blocking code, e.g. a credential helper, should run it
+/// off the async runtime, e.g. with `tokio::task::spawn_blocking`.
+///
+/// Credentials are renewed separately for every identity, see `Client::as_identity`, which
+/// may share a username, e.g. `AWS` for ECR or `oauth2accesstoken` for GCR.
+pub trait CredentialProvider: fmt::Debug + Send + Sync {
+  /// Return credentials replacing those of `username`, which `registry` (host and port)
+  /// rejected for `identity`, or for the client itself if `None`, or `None` if there are none.
+  fn credentials<'a>(&'a self, registry: &'a str, identity: Option<&'a str>, username: &'a str)
+    -> CredentialFuture<'a>;
+}
+
+/// Identity, if any, and configured username of renewed credentials.
+type RenewalKey = (Option<String>, String);
+
+/// Credentials renewed with a [`CredentialProvider`], shared by a client and the clients
+/// derived from it.
+#[derive(Debug)]
+pub(crate) struct CredentialRenewal {
+  provider: Arc<dyn CredentialProvider>,
+  /// Credentials obtained from the provider, by identity and the username it was configured
+  /// with.
+  renewed: Mutex<HashMap<RenewalKey, (String, String)>>,
+  /// Held while the provider is asked, without blocking the tasks waiting for it.
+  renewing: futures::lock::Mutex<()>,
+}
+
+impl CredentialRenewal {
+  pub(crate) fn new(provider: Arc<dyn CredentialProvider>) -> Self {
+    Self {
+      provider,
+      renewed: Mutex::new(HashMap::new()),
+      renewing: futures::lock::Mutex::new(()),
+    }
+  }
+}
+
+impl Client {
+  /// The credentials of the client: those renewed by its credential provider, if any, or else
+  /// those it was configured with.
+  pub(crate) fn current_credentials(&self) -> Option<(String, String)> {
+    Some(self.renewed_credentials().unwrap_or(self.credentials.clone()?))
+  }
+
+  /// The credentials renewed by the credential provider of the client, if any.
+  pub(crate) fn renewed_credentials(&self) -> Option<(String, String)> {
+    let (user, _) = self.credentials.as_ref()?;
+    let renewal = self.credential_renewal.as_ref()?;
+    let renewed = renewal.renewed.lock().unwrap();
+    renewed.get(&(self.identity.clone(), user.clone())).cloned()
+  }
+
+  /// The credentials to send instead of `rejected`, renewing them with the credential provider
+  /// unless another request already did.
+  pub(crate) async fn renew_credentials(&self, rejected: &(String, String)) -> Result<Option<(String, String)>> {
+    let (Some(renewal), Some(configured)) = (&self.credential_renewal, &self.credentials) else {
+      return Ok(None);
+    };
+    // Requests rejected at the same time wait for the first one to ask the provider.
+    let _renewing = renewal.renewing.lock().await;
+    let key = (self.identity.clone(), configured.0.clone());
+    let current = renewal.renewed.lock().unwrap().get(&key).cloned();
+    let current = current.as_ref().unwrap_or(configured);
+    if current != rejected {
+      return Ok(Some(current.clone()));
+    }
+    let fresh = renewal
+      .provider
+      .credentials(&self.registry_host(), self.identity.as_deref(), &rejected.0)
+      .await
+      .map_err(Error::CredentialProvider)?;
+    match fresh {
+      Some(fresh) if fresh != *rejected => {
+        debug!("renewed the credentials of {} for {}", rejected.0, self.base_url);
+        let mut renewed = renewal.renewed.lock().unwrap();
+        renewed.insert(key, fresh.clone());
+        Ok(Some(fresh))
+      }
+      _ => {
+        debug!(
+          "no other credentials than those of {} for {}",
+          rejected.0, self.base_url
+        );
+        Ok(None)
+      }
+    }
+  }
+
+  /// The Basic credentials sent with `request`, and a copy of it to send again with renewed
+  /// credentials if the registry rejects them.
+  pub(crate) fn renewable_request(&self, request: &reqwest::Request) -> Option<((String, String), reqwest::Request)> {
+    self.credential_renewal.as_ref()?;
+    let sent = basic_credentials(request.headers().get(header::AUTHORIZATION)?)?;
+    Some((sent, request.try_clone()?))
+  }
+}
+
+/// The username and password of a Basic `Authorization` header.
+fn basic_credentials(authorization: &HeaderValue) -> Option<(String, String)> {
+  let encoded = authorization.to_str().ok()?.strip_prefix("Basic ")?;
+  let decoded = String::from_utf8(BASE64_STANDARD.decode(encoded.trim()).ok()?).ok()?;
+  let (user, password) = decoded.split_once(':')?;
+  Some((user.to_string(), password.to_string()))
+}
+
+/// Set the Basic `Authorization` header of `request` to `credentials`.
+pub(crate) fn set_basic_credentials(request: &mut reqwest::Request, (user, password): &(String, String)) -> Result<()> {
+  let encoded = BASE64_STANDARD.encode(format!("{}:{}", user, password));
+  let mut value = HeaderValue::from_str(&format!("Basic {}", encoded))?;
+  value.set_sensitive(true);
+  request.headers_mut().insert(header::AUTHORIZATION, value);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn basic_credentials_round_trip() {
+    let client = reqwest::Client::new();
+    let mut request = client.get("http://localhost:5000/v2/").build().unwrap();
+    let credentials = ("user".to_string(), "pass:word".to_string());
+    set_basic_credentials(&mut request, &credentials).unwrap();
+    assert_eq!(
+      basic_credentials(request.headers().get(header::AUTHORIZATION).unwrap()),
+      Some(credentials)
+    );
+  }
+}
@@ -0,0 +1,149 @@
+//! Deadlines bounding every request of composite operations.
+
+use std::time::Duration;
+
+use crate::v2::*;
+
+/// Point in time the requests of a client must complete by, see `Client::with_deadline`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Deadline {
+  start: Instant,
+  budget: Duration,
+}
+
+impl Deadline {
+  /// Time left before the deadline, `None` once it passed.
+  fn remaining(&self) -> Option<Duration> {
+    self
+      .budget
+      .checked_sub(self.start.elapsed())
+      .filter(|remaining| !remaining.is_zero())
+  }
+}
+
+impl Client {
+  /// Clone of the client whose requests must all complete within `budget` from now, e.g. to
+  /// bound a pull, a copy or a cleanup made of many requests.
+  ///
+  /// Every request, including token requests and retries, is sent with a timeout no later
+  /// than the deadline, which also bounds the download of its body; retries which would only
+  /// be sent after the deadline are not sent. Requests and body downloads failing because of
+  /// the deadline fail with `Error::DeadlineExceeded`, and so do requests once the deadline
+  /// passed. The work done until then is kept: copies and pulls fail with
+  /// `Error::DeadlineExceededAfter`, listing the blobs and manifests already at the destination,
+  /// and bulk operations report the items completed in time next to those failing with the
+  /// deadline.
+  ///
+  /// A client which already has an earlier deadline keeps it.
+  ///
+  /// ```rust,no_run
+  /// # use tokio;
+  /// # #[tokio::main]
+  /// # async fn main() {
+  /// # async fn run() -> docker_registry::errors::Result<()> {
+  /// use std::time::Duration;
+  ///
+  /// use docker_registry::{
+  ///   copy::{copy_image, CopyOptions},
+  ///   errors::Error,
+  ///   v2::Client,
+  /// };
+  ///
+  /// let src = Client::configure().registry("quay.io").build()?;
+  /// let dst = Client::configure().registry("localhost:5000").build()?;
+  /// let budget = Duration::from_secs(30);
+  /// let (src, dst) = (src.with_deadline(budget), dst.with_deadline(budget));
+  /// let options = CopyOptions::default();
+  /// match copy_image(
+  ///   &src,
+  ///   "coreos/etcd",
+  ///   "v3.1.0",
+  ///   &dst,
+  ///   "etcd",
+  ///   "v3.1.0",
+  ///   &options,
+  /// )
+  /// .await
+  /// {
+  ///   Err(Error::DeadlineExceededAfter { completed }) => {
+  ///     println!(
+  ///       "copy incomplete, {} blobs copied so far are kept",
+  ///       completed.len()
+  ///     )
+  ///   }
+  ///   result => println!("copied {}", result?),
+  /// }
+  /// # Ok(())
+  /// # };
+  /// # run().await.unwrap();
+  /// # }
+  /// ```
+  pub fn with_deadline(&self, budget: Duration) -> Self {
+    let budget = match self.remaining_time() {
+      Some(remaining) => remaining.min(budget),
+      None => budget,
+    };
+    Self {
+      deadline: Some(Deadline {
+        start: Instant::now(),
+        budget,
+      }),
+      ..self.clone()
+    }
+  }
+
+  /// Time left before the deadline of the client, if it has one; zero once it passed.
+  pub fn remaining_time(&self) -> Option<Duration> {
+    self.deadline.map(|d| d.remaining().unwrap_or_default())
+  }
+
+  /// Bound the timeout of `request` by the deadline of the client, failing with
+  /// `Error::DeadlineExceeded` if it passed.
+  pub(crate) fn apply_deadline(&self, request: &mut reqwest::Request) -> Result<()> {
+    let Some(deadline) = self.deadline else {
+      return Ok(());
+    };
+    let remaining = deadline.remaining().ok_or(Error::DeadlineExceeded)?;
+    let timeout = request.timeout_mut();
+    *timeout = Some(timeout.map_or(remaining, |t| t.min(remaining)));
+    Ok(())
+  }
+
+  /// Whether a request is not worth retrying after `delay`, as it would be sent after the
+  /// deadline of the client.
+  pub(crate) fn past_deadline_after(&self, delay: Duration) -> bool {
+    self
+      .deadline
+      .is_some_and(|d| d.remaining().map_or(true, |remaining| remaining <= delay))
+  }
+
+  /// `Error::DeadlineExceeded` for transport errors caused by the deadline of the client,
+  /// sending a request or reading its body.
+  pub(crate) fn deadline_error(&self, error: impl Into<Error>) -> Error {
+    deadline_error(self.deadline, error.into())
+  }
+}
+
+/// `Error::DeadlineExceeded` for transport timeouts once `deadline` passed.
+pub(crate) fn deadline_error(deadline: Option<Deadline>, error: Error) -> Error {
+  match error {
+    Error::Reqwest(e) if e.is_timeout() && deadline.is_some_and(|d| d.remaining().is_none()) => Error::DeadlineExceeded,
+    error => error,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keeps_earlier_deadlines() {
+    let client = Client::configure().registry("localhost:5000").build().unwrap();
+    assert_eq!(client.remaining_time(), None);
+    let bounded = client.with_deadline(Duration::from_secs(10));
+    assert!(bounded.remaining_time().unwrap() <= Duration::from_secs(10));
+    let extended = bounded.with_deadline(Duration::from_secs(60));
+    assert!(extended.remaining_time().unwrap() <= Duration::from_secs(10));
+    assert!(client.with_deadline(Duration::ZERO).past_deadline_after(Duration::ZERO));
+  }
+}
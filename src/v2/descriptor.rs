@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+  errors::Result,
+  v2::{manifest::Platform, sha256_digest, ContentDigest},
+};
+
+/// A content descriptor, referencing a manifest or blob by digest.
+///
+/// Descriptors are the entries of image manifests (config and layers), of manifest lists and
+/// OCI indexes, and of referrers responses.
+///
+/// Specification is at <https://github.com/opencontainers/image-spec/blob/v1.1.0/descriptor.md>.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Descriptor {
+  #[serde(rename = "mediaType")]
+  pub media_type: String,
+  #[serde(rename = "artifactType", default, skip_serializing_if = "Option::is_none")]
+  pub artifact_type: Option<String>,
+  pub digest: String,
+  pub size: u64,
+  /// Locations the content may also be downloaded from, e.g. for foreign layers.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub urls: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub annotations: Option<HashMap<String, String>>,
+  /// Platform of the referenced image, in manifest lists and OCI indexes.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub platform: Option<Platform>,
+}
+
+impl Descriptor {
+  /// Describe `data` as content of the given media type.
+  pub fn new(media_type: &str, data: &[u8]) -> Self {
+    Self {
+      media_type: media_type.to_string(),
+      digest: sha256_digest(data),
+      size: data.len() as u64,
+      ..Default::default()
+    }
+  }
+
+  /// Describe the content of the given media type, digest and size.
+  pub fn from_digest(media_type: &str, digest: &str, size: u64) -> Self {
+    Self {
+      media_type: media_type.to_string(),
+      digest: digest.to_string(),
+      size,
+      ..Default::default()
+    }
+  }
+
+  pub fn with_artifact_type(mut self, artifact_type: &str) -> Self {
+    self.artifact_type = Some(artifact_type.to_string());
+    self
+  }
+
+  pub fn with_annotations(mut self, annotations: HashMap<String, String>) -> Self {
+    self.annotations = Some(annotations);
+    self
+  }
+
+  pub fn with_platform(mut self, platform: Platform) -> Self {
+    self.platform = Some(platform);
+    self
+  }
+
+  pub fn with_urls(mut self, urls: Vec<String>) -> Self {
+    self.urls = Some(urls);
+    self
+  }
+
+  /// Check that `data` is the content referenced by this descriptor.
+  pub fn verify(&self, data: &[u8]) -> Result<()> {
+    let mut digest = ContentDigest::try_new(&self.digest)?;
+    digest.update(data);
+    Ok(digest.verify()?)
+  }
+
+  /// Get the architecture of the referenced image, or an empty string if the descriptor has no platform.
+  pub fn architecture(&self) -> String {
+    self
+      .platform
+      .as_ref()
+      .map(|p| p.architecture.to_owned())
+      .unwrap_or_default()
+  }
+
+  /// Returns the digest of the referenced content.
+  pub fn digest(&self) -> String {
+    self.digest.to_owned()
+  }
+
+  /// Returns the size in bytes of the referenced content.
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+}
+
+impl TryFrom<&Value> for Descriptor {
+  type Error = serde_json::Error;
+
+  fn try_from(value: &Value) -> std::result::Result<Self, Self::Error> {
+    Descriptor::deserialize(value)
+  }
+}
+
+impl TryFrom<Value> for Descriptor {
+  type Error = serde_json::Error;
+
+  fn try_from(value: Value) -> std::result::Result<Self, Self::Error> {
+    serde_json::from_value(value)
+  }
+}
+
+impl From<Descriptor> for Value {
+  fn from(descriptor: Descriptor) -> Self {
+    // Serializing a struct of strings, integers and string maps cannot fail.
+    serde_json::to_value(descriptor).unwrap_or_default()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test]
+  fn round_trips_index_entries() {
+    let value = json!({
+      "mediaType": "application/vnd.oci.image.manifest.v1+json",
+      "digest": sha256_digest(b"manifest"),
+      "size": 8,
+      "platform": {"architecture": "arm64", "os": "linux", "variant": "v8"},
+      "annotations": {"org.opencontainers.image.ref.name": "v1"},
+    });
+    let descriptor = Descriptor::try_from(&value).unwrap();
+    assert_eq!(descriptor.architecture(), "arm64");
+    assert_eq!(descriptor.platform.as_ref().unwrap().to_string(), "linux/arm64/v8");
+    assert_eq!(Value::from(descriptor.clone()), value);
+
+    assert!(descriptor.verify(b"manifest").is_ok());
+    assert!(descriptor.verify(b"other").is_err());
+    assert_eq!(
+      Descriptor::new("application/vnd.oci.image.manifest.v1+json", b"manifest"),
+      Descriptor {
+        platform: None,
+        annotations: None,
+        ..descriptor
+      }
+    );
+  }
+}
@@ -0,0 +1,190 @@
+//! Interactive login with the OAuth 2.0 device authorization grant (RFC 8628).
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use log::{debug, trace};
+use reqwest::{Method, StatusCode, Url};
+use serde::Deserialize;
+
+use crate::{
+  errors::{Error, Result},
+  types::TokenAuth,
+  v2::*,
+};
+
+/// Code issued by the authorization server, which the user enters at the verification URI.
+#[derive(Debug, Deserialize)]
+pub struct DeviceAuthorization {
+  device_code: String,
+  user_code: String,
+  // Azure AD v1 endpoints predate the RFC and use `verification_url`.
+  #[serde(alias = "verification_url")]
+  verification_uri: String,
+  verification_uri_complete: Option<String>,
+  expires_in: u64,
+  #[serde(default = "default_interval")]
+  interval: u64,
+}
+
+fn default_interval() -> u64 {
+  5
+}
+
+impl DeviceAuthorization {
+  /// The code to enter at the verification URI.
+  pub fn user_code(&self) -> &str {
+    &self.user_code
+  }
+
+  /// The URI at which the user logs in and enters the code.
+  pub fn verification_uri(&self) -> &str {
+    &self.verification_uri
+  }
+
+  /// The verification URI including the code, e.g. to display as a QR code.
+  pub fn verification_uri_complete(&self) -> Option<&str> {
+    self.verification_uri_complete.as_deref()
+  }
+
+  /// How long the code is valid.
+  pub fn expires_in(&self) -> Duration {
+    Duration::from_secs(self.expires_in)
+  }
+}
+
+/// Hook showing the user how to complete a device-code login, e.g. by printing the code and the
+/// URI to the terminal.
+pub trait DevicePrompt: fmt::Debug + Send + Sync {
+  /// Display the code and the URI; the token endpoint is polled once this returns.
+  fn prompt(&self, authorization: &DeviceAuthorization) -> std::result::Result<(), HookError>;
+}
+
+/// Interactive, passwordless login to registries accepting OAuth tokens as passwords, e.g.
+/// GitLab, or whose identity provider offers device-code flows, e.g. Azure AD for ACR.
+///
+/// When set with `Config::device_login` on a client without credentials, `Client::authenticate`
+/// requests a device code, shows it with the [`DevicePrompt`] and polls the token endpoint until
+/// the user has logged in. The access token is then used as the password of `username`, for this
+/// and later authentications of the returned client.
+#[derive(Clone, Debug)]
+pub struct DeviceLogin {
+  authorization_endpoint: Url,
+  token_endpoint: Url,
+  client_id: String,
+  scope: Option<String>,
+  pub(crate) username: String,
+  prompt: Arc<dyn DevicePrompt>,
+}
+
+impl DeviceLogin {
+  /// Log in with the OAuth client `client_id` at the device authorization and token endpoints of
+  /// an authorization server.
+  pub fn new<P: DevicePrompt + 'static>(
+    authorization_endpoint: Url,
+    token_endpoint: Url,
+    client_id: &str,
+    prompt: P,
+  ) -> Self {
+    Self {
+      authorization_endpoint,
+      token_endpoint,
+      client_id: client_id.to_string(),
+      scope: None,
+      username: "oauth2".to_string(),
+      prompt: Arc::new(prompt),
+    }
+  }
+
+  /// Set the OAuth scope to request, e.g. `read_registry write_registry` for GitLab.
+  pub fn scope(mut self, scope: &str) -> Self {
+    self.scope = Some(scope.to_string());
+    self
+  }
+
+  /// Set the username sent with the access token, `oauth2` by default.
+  pub fn username(mut self, username: &str) -> Self {
+    self.username = username.to_string();
+    self
+  }
+}
+
+/// Error response of the token endpoint, see RFC 8628 section 3.5.
+#[derive(Debug, Deserialize)]
+struct TokenError {
+  error: String,
+  error_description: Option<String>,
+}
+
+impl Client {
+  /// Run the device-code flow of `login`, returning the credentials to authenticate with.
+  pub(crate) async fn device_credentials(&self, login: &DeviceLogin) -> Result<(String, String)> {
+    // Requests to the authorization server must not carry the registry's credentials.
+    let client = Client {
+      auth: None,
+      ..self.clone()
+    };
+
+    let mut form = vec![("client_id", login.client_id.as_str())];
+    if let Some(scope) = &login.scope {
+      form.push(("scope", scope));
+    }
+    let r = client
+      .send_token_request(
+        client
+          .build_reqwest(Method::POST, login.authorization_endpoint.clone())
+          .form(&form),
+      )
+      .await?;
+    let status = r.status();
+    trace!("POST '{}' status: {:?}", r.url(), status);
+    if status != StatusCode::OK {
+      return Err(Error::UnexpectedHttpStatus(status));
+    }
+    let authorization: DeviceAuthorization = r.json().await?;
+
+    login.prompt.prompt(&authorization).map_err(Error::DevicePrompt)?;
+
+    let started = time::Instant::now();
+    let mut interval = Duration::from_secs(authorization.interval);
+    let form = [
+      ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+      ("device_code", &authorization.device_code),
+      ("client_id", &login.client_id),
+    ];
+    loop {
+      if started.elapsed() + interval > authorization.expires_in() {
+        return Err(Error::DeviceLogin("the device code expired".to_string()));
+      }
+      time::sleep(interval).await;
+
+      let r = client
+        .send_token_request(
+          client
+            .build_reqwest(Method::POST, login.token_endpoint.clone())
+            .form(&form),
+        )
+        .await?;
+      let status = r.status();
+      trace!("POST '{}' status: {:?}", r.url(), status);
+      if status == StatusCode::OK {
+        let token = r.json::<TokenAuth>().await?;
+        let token = token.access_token.or(token.token).ok_or(Error::NoTokenReceived)?;
+        debug!("Device login succeeded");
+        return Ok((login.username.clone(), token));
+      }
+      if !status.is_client_error() {
+        return Err(Error::UnexpectedHttpStatus(status));
+      }
+      let e: TokenError = r.json().await?;
+      match e.error.as_str() {
+        "authorization_pending" => {}
+        "slow_down" => interval += Duration::from_secs(5),
+        _ => {
+          let reason = e.error_description.map(|d| format!("{}: {}", e.error, d));
+          return Err(Error::DeviceLogin(reason.unwrap_or(e.error)));
+        }
+      }
+      trace!("Device login pending, polling again in {:?}", interval);
+    }
+  }
+}
@@ -0,0 +1,92 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::io;
+use std::net::SocketAddr;
+
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+/// Address family preference for connections to registries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpPreference {
+  /// Use addresses in resolver order, racing the other address family after a short delay ("happy eyeballs").
+  #[default]
+  HappyEyeballs,
+  /// Try IPv4 addresses first and fall back to IPv6.
+  PreferIpv4,
+  /// Try IPv6 addresses first and fall back to IPv4.
+  PreferIpv6,
+  /// Only connect over IPv4.
+  Ipv4Only,
+  /// Only connect over IPv6.
+  Ipv6Only,
+}
+
+impl IpPreference {
+  /// Filter and order resolved addresses according to the preference.
+  ///
+  /// Happy eyeballs keeps the resolver order; the connector races the address families.
+  #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+  fn apply(self, addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v4, v6): (Vec<_>, Vec<_>) = addrs.iter().partition(|addr| addr.is_ipv4());
+    match self {
+      IpPreference::HappyEyeballs => addrs,
+      IpPreference::PreferIpv4 => v4.into_iter().chain(v6).collect(),
+      IpPreference::PreferIpv6 => v6.into_iter().chain(v4).collect(),
+      IpPreference::Ipv4Only => v4,
+      IpPreference::Ipv6Only => v6,
+    }
+  }
+}
+
+/// DNS resolver applying an [`IpPreference`] on top of the system resolver.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub(crate) struct FamilyResolver {
+  preference: IpPreference,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FamilyResolver {
+  pub(crate) fn new(preference: IpPreference) -> Self {
+    Self { preference }
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Resolve for FamilyResolver {
+  fn resolve(&self, name: Name) -> Resolving {
+    let preference = self.preference;
+    Box::pin(async move {
+      let addrs = tokio::net::lookup_host((name.as_str(), 0)).await?.collect::<Vec<_>>();
+      let addrs = preference.apply(addrs);
+      if addrs.is_empty() {
+        let msg = format!("no address of the requested family for {}", name.as_str());
+        return Err(io::Error::new(io::ErrorKind::NotFound, msg).into());
+      }
+      Ok(Box::new(addrs.into_iter()) as Addrs)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  fn addrs() -> Vec<SocketAddr> {
+    ["[::1]:0", "127.0.0.1:0", "[::2]:0", "127.0.0.2:0"]
+      .iter()
+      .map(|a| a.parse().unwrap())
+      .collect()
+  }
+
+  #[test_case(IpPreference::HappyEyeballs => vec!["[::1]:0", "127.0.0.1:0", "[::2]:0", "127.0.0.2:0"]; "happy eyeballs")]
+  #[test_case(IpPreference::PreferIpv4 => vec!["127.0.0.1:0", "127.0.0.2:0", "[::1]:0", "[::2]:0"]; "prefer ipv4")]
+  #[test_case(IpPreference::PreferIpv6 => vec!["[::1]:0", "[::2]:0", "127.0.0.1:0", "127.0.0.2:0"]; "prefer ipv6")]
+  #[test_case(IpPreference::Ipv4Only => vec!["127.0.0.1:0", "127.0.0.2:0"]; "ipv4 only")]
+  #[test_case(IpPreference::Ipv6Only => vec!["[::1]:0", "[::2]:0"]; "ipv6 only")]
+  fn ip_preference_order(preference: IpPreference) -> Vec<String> {
+    preference.apply(addrs()).iter().map(ToString::to_string).collect()
+  }
+}
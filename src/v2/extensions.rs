@@ -0,0 +1,105 @@
+use log::trace;
+use reqwest::{header, Method, StatusCode, Url};
+use serde::Deserialize;
+
+use crate::{errors::Result, v2::*};
+
+/// Path of the OCI extensions discovery endpoint.
+const DISCOVER_PATH: &str = "/v2/_oci/ext/discover";
+
+/// An extension of the registry API, as listed by `Client::extensions`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct RegistryExtension {
+  /// Name of the extension, e.g. `_oci` or `_zot`.
+  pub name: String,
+  /// Location of the documentation of the extension.
+  #[serde(default)]
+  pub url: Option<String>,
+  #[serde(default)]
+  pub description: Option<String>,
+  /// Endpoints of the extension, e.g. `/v2/_zot/ext/search`.
+  #[serde(default)]
+  pub endpoints: Vec<String>,
+}
+
+impl RegistryExtension {
+  /// Whether the extension serves `endpoint`, e.g. `/v2/_zot/ext/search`.
+  ///
+  /// Registries list endpoints with or without the `/v2/` prefix, both are accepted.
+  pub fn serves(&self, endpoint: &str) -> bool {
+    let endpoint = trim_endpoint(endpoint);
+    self.endpoints.iter().any(|e| trim_endpoint(e) == endpoint)
+  }
+}
+
+#[derive(Deserialize)]
+struct ExtensionList {
+  #[serde(default)]
+  extensions: Vec<RegistryExtension>,
+}
+
+fn trim_endpoint(endpoint: &str) -> &str {
+  let endpoint = endpoint.trim_start_matches('/');
+  endpoint.strip_prefix("v2/").unwrap_or(endpoint)
+}
+
+impl Client {
+  /// List the extensions of the registry API the registry implements, with the OCI extensions
+  /// discovery endpoint, `/v2/_oci/ext/discover`.
+  ///
+  /// Returns an empty list if the registry does not serve the discovery endpoint.
+  pub async fn extensions(&self) -> Result<Vec<RegistryExtension>> {
+    let url = Url::parse(&format!("{}{}", self.base_url, DISCOVER_PATH))?;
+    let r = self
+      .send(
+        self
+          .build_reqwest(Method::GET, url)
+          .header(header::ACCEPT, "application/json"),
+      )
+      .await?;
+    let status = r.status();
+    trace!("GET '{}' status: {:?}", r.url(), status);
+    match status {
+      StatusCode::OK => {}
+      StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => return Ok(Vec::new()),
+      _ => return Err(ApiErrors::from(r).await),
+    }
+    let body = read_limited(r, self.limits.max_catalog_size, "extensions")
+      .await
+      .map_err(|e| self.deadline_error(e))?;
+    Ok(serde_json::from_slice::<ExtensionList>(&body)?.extensions)
+  }
+
+  /// Whether one of the extensions of the registry serves `endpoint`, e.g.
+  /// `/v2/_zot/ext/search`.
+  ///
+  /// Registries failing to list their extensions are assumed to serve none.
+  pub async fn has_extension_endpoint(&self, endpoint: &str) -> bool {
+    match self.extensions().await {
+      Ok(extensions) => extensions.iter().any(|e| e.serves(endpoint)),
+      Err(e) => {
+        trace!("Cannot list the extensions of {}: {}", self.base_url, e);
+        false
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case("/v2/_zot/ext/search" => true; "with prefix")]
+  #[test_case("_zot/ext/search" => true; "without prefix")]
+  #[test_case("/v2/_zot/ext/userprefs" => false; "other endpoint")]
+  fn serves(endpoint: &str) -> bool {
+    let extension = RegistryExtension {
+      name: "_zot".to_string(),
+      endpoints: vec!["/v2/_zot/ext/search".to_string()],
+      ..Default::default()
+    };
+    extension.serves(endpoint)
+  }
+}
@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use log::trace;
+use tokio::io::AsyncReadExt;
+
+use crate::{errors::Result, v2::*};
+
+/// Options for uploading blobs from local files with `Client::push_blob_file`.
+#[derive(Clone, Debug)]
+pub struct FileUploadOptions {
+  buffer_size: usize,
+  #[cfg(feature = "mmap")]
+  mmap: bool,
+}
+
+impl Default for FileUploadOptions {
+  fn default() -> Self {
+    Self {
+      buffer_size: 1 << 20,
+      #[cfg(feature = "mmap")]
+      mmap: false,
+    }
+  }
+}
+
+impl FileUploadOptions {
+  /// Set the size of the buffer used to read the file, which bounds the memory used by the upload.
+  pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+    self.buffer_size = buffer_size.max(1);
+    self
+  }
+
+  /// Memory-map the file instead of reading it through a buffer.
+  ///
+  /// The mapped file is sent without copying it into the heap. The file must not be modified
+  /// while it is being uploaded.
+  #[cfg(feature = "mmap")]
+  pub fn mmap(mut self, mmap: bool) -> Self {
+    self.mmap = mmap;
+    self
+  }
+}
+
+impl Client {
+  /// Upload a blob from a local file, streaming it from disk in a single request.
+  ///
+  /// If `digest` is not given, it is computed by reading the file before the upload starts.
+  /// Memory usage is bounded by the buffer size of `options` rather than the file size.
+  pub async fn push_blob_file<P: AsRef<Path>>(
+    &self,
+    name: &str,
+    path: P,
+    digest: Option<&str>,
+    options: &FileUploadOptions,
+  ) -> Result<String> {
+    self.ensure_writable()?;
+    let path = path.as_ref();
+
+    #[cfg(feature = "mmap")]
+    if options.mmap {
+      return self.push_blob_mmap(name, path, digest, options).await;
+    }
+
+    let digest = match digest {
+      Some(d) => d.to_string(),
+      None => file_digest(path, options.buffer_size).await?,
+    };
+
+    let file = tokio::fs::File::open(path).await?;
+    let len = file.metadata().await?.len();
+    trace!("Uploading {} ({} bytes) as {}", path.display(), len, digest);
+
+    let stream = tokio_util::io::ReaderStream::with_capacity(file, options.buffer_size);
+    let body = reqwest::Body::wrap_stream(stream);
+
+    let session = self.start_upload_of(name, Some(digest.as_str())).await?;
+    self.finish_upload_body(&session, &digest, Some((body, len))).await
+  }
+
+  #[cfg(feature = "mmap")]
+  async fn push_blob_mmap(
+    &self,
+    name: &str,
+    path: &Path,
+    digest: Option<&str>,
+    options: &FileUploadOptions,
+  ) -> Result<String> {
+    let file = std::fs::File::open(path)?;
+    // SAFETY: the caller guarantees the file is not modified during the upload.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    let data = bytes::Bytes::from_owner(map);
+    let len = data.len() as u64;
+
+    let digest = match digest {
+      Some(d) => d.to_string(),
+      None => sha256_digest(&data),
+    };
+    trace!("Uploading {} ({} bytes, mapped) as {}", path.display(), len, digest);
+
+    let chunk_size = options.buffer_size;
+    let chunks = (0..data.len())
+      .step_by(chunk_size)
+      .map(move |start| Ok::<_, std::io::Error>(data.slice(start..std::cmp::min(start + chunk_size, data.len()))));
+    let body = reqwest::Body::wrap_stream(futures::stream::iter(chunks));
+
+    let session = self.start_upload_of(name, Some(digest.as_str())).await?;
+    self.finish_upload_body(&session, &digest, Some((body, len))).await
+  }
+}
+
+/// Compute the sha256 digest of a file, reading it through a buffer of `buffer_size` bytes.
+async fn file_digest(path: &Path, buffer_size: usize) -> Result<String> {
+  let mut file = tokio::fs::File::open(path).await?;
+  let mut buf = vec![0; buffer_size];
+  let mut hasher = DigestAlgorithm::sha256();
+  loop {
+    match file.read(&mut buf).await? {
+      0 => break,
+      n => hasher.update(&buf[..n]),
+    }
+  }
+  Ok(hasher.try_digest()?)
+}
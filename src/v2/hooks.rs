@@ -0,0 +1,251 @@
+//! Extension points invoked by the client around outgoing requests.
+
+use std::{collections::BTreeMap, fmt, sync::Arc, time::Duration};
+
+use reqwest::{header::HeaderMap, Method, StatusCode, Url};
+
+use crate::v2::{repository_of, sha256_digest, stats::request_body_len};
+
+/// Boxed error type returned by user-provided hooks.
+pub type HookError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Request metadata handed to a [`RequestSigner`] right before the request is sent.
+#[derive(Debug)]
+pub struct SigningRequest<'a> {
+  method: Method,
+  url: Url,
+  headers: &'a mut HeaderMap,
+  body_digest: Option<String>,
+}
+
+impl<'a> SigningRequest<'a> {
+  pub(crate) fn new(request: &'a mut reqwest::Request) -> Self {
+    // Streaming bodies cannot be hashed without consuming them.
+    let body_digest = match request.body() {
+      None => Some(sha256_digest(&[])),
+      Some(body) => body.as_bytes().map(sha256_digest),
+    };
+    Self {
+      method: request.method().clone(),
+      url: request.url().clone(),
+      headers: request.headers_mut(),
+      body_digest,
+    }
+  }
+
+  /// HTTP method of the request.
+  pub fn method(&self) -> &Method {
+    &self.method
+  }
+
+  /// Full URL of the request, including the query string.
+  pub fn url(&self) -> &Url {
+    &self.url
+  }
+
+  /// Headers set on the request so far.
+  pub fn headers(&self) -> &HeaderMap {
+    self.headers
+  }
+
+  /// Mutable access to the request headers, to add signatures.
+  pub fn headers_mut(&mut self) -> &mut HeaderMap {
+    self.headers
+  }
+
+  /// Digest of the request body (`sha256:<hex>`), or `None` for streaming bodies.
+  ///
+  /// Requests without a body report the digest of the empty string.
+  pub fn body_digest(&self) -> Option<&str> {
+    self.body_digest.as_deref()
+  }
+}
+
+/// Hook to sign every request before it is sent.
+///
+/// This is meant for registries fronted by gateways which require HMAC or SigV4-style
+/// request signatures. The signer is invoked for every request issued by the client,
+/// including requests to token endpoints.
+pub trait RequestSigner: fmt::Debug + Send + Sync {
+  /// Add signature headers to the request.
+  fn sign(&self, request: &mut SigningRequest<'_>) -> Result<(), HookError>;
+}
+
+/// Request metadata handed to an [`Interceptor`] before the request is sent.
+#[derive(Debug)]
+pub struct InterceptedRequest {
+  method: Method,
+  url: Url,
+  headers: HeaderMap,
+  body: Option<Vec<u8>>,
+  body_len: u64,
+  repository: Option<String>,
+  attempt: u32,
+  request_id: Option<String>,
+}
+
+impl InterceptedRequest {
+  pub(crate) fn new(request: &reqwest::Request, attempt: u32, request_id: Option<String>, include_body: bool) -> Self {
+    Self {
+      method: request.method().clone(),
+      url: request.url().clone(),
+      headers: request.headers().clone(),
+      body: match include_body {
+        true => request.body().and_then(|b| b.as_bytes()).map(<[u8]>::to_vec),
+        false => None,
+      },
+      body_len: request_body_len(request),
+      repository: repository_of(request.url()),
+      attempt,
+      request_id,
+    }
+  }
+
+  /// HTTP method of the request.
+  pub fn method(&self) -> &Method {
+    &self.method
+  }
+
+  /// Full URL of the request, including the query string.
+  pub fn url(&self) -> &Url {
+    &self.url
+  }
+
+  /// Headers of the request, including authentication and signature headers.
+  pub fn headers(&self) -> &HeaderMap {
+    &self.headers
+  }
+
+  /// Body of the request, if the interceptor asked for bodies and the body is not streamed.
+  pub fn body(&self) -> Option<&[u8]> {
+    self.body.as_deref()
+  }
+
+  /// Length of the request body, or 0 for streaming bodies of unknown length.
+  pub fn body_len(&self) -> u64 {
+    self.body_len
+  }
+
+  /// Repository the request is about, e.g. `library/busybox`, or `None` for requests to
+  /// other endpoints, as accounted in `ClientStats::repositories`.
+  pub fn repository(&self) -> Option<&str> {
+    self.repository.as_deref()
+  }
+
+  /// Number of times the request was retried before this attempt.
+  pub fn attempt(&self) -> u32 {
+    self.attempt
+  }
+
+  /// Request ID of the operation the request belongs to, see `Client::correlate`.
+  pub fn request_id(&self) -> Option<&str> {
+    self.request_id.as_deref()
+  }
+}
+
+/// Response metadata handed to an [`Interceptor`] once the response headers are received.
+#[derive(Debug)]
+pub struct InterceptedResponse<'a> {
+  pub(crate) url: &'a Url,
+  pub(crate) status: StatusCode,
+  pub(crate) headers: &'a HeaderMap,
+  pub(crate) body: Option<&'a [u8]>,
+  pub(crate) elapsed: Duration,
+}
+
+impl InterceptedResponse<'_> {
+  /// URL the response was received from, after following redirects.
+  pub fn url(&self) -> &Url {
+    self.url
+  }
+
+  /// HTTP status of the response.
+  pub fn status(&self) -> StatusCode {
+    self.status
+  }
+
+  /// Headers of the response.
+  pub fn headers(&self) -> &HeaderMap {
+    self.headers
+  }
+
+  /// Body of the response, if the interceptor asked for bodies.
+  pub fn body(&self) -> Option<&[u8]> {
+    self.body
+  }
+
+  /// Length of the response body announced by its `Content-Length` header.
+  pub fn content_length(&self) -> Option<u64> {
+    self
+      .headers
+      .get(reqwest::header::CONTENT_LENGTH)?
+      .to_str()
+      .ok()?
+      .parse()
+      .ok()
+  }
+
+  /// Time elapsed between sending the request and receiving the response.
+  pub fn elapsed(&self) -> Duration {
+    self.elapsed
+  }
+}
+
+/// Hook observing every request sent by the client and every response it receives.
+///
+/// Interceptors are invoked for every attempt of every request, including requests to token
+/// endpoints and retries, after the request was signed. This is meant for audit logging,
+/// metrics and chaos testing.
+pub trait Interceptor: fmt::Debug + Send + Sync {
+  /// Whether request and response bodies should be handed to the interceptor.
+  ///
+  /// Response bodies are then buffered in memory before being returned to the caller. Only
+  /// bodies whose `Content-Length` fits the `ResponseLimits` of their endpoint are buffered,
+  /// blobs falling under `max_raw_response_size`; larger or unsized bodies are streamed and not
+  /// available. Streamed request bodies are never available either.
+  fn include_bodies(&self) -> bool {
+    false
+  }
+
+  /// Called before the request is sent; returning an error aborts the request.
+  fn on_request(&self, _request: &InterceptedRequest) -> Result<(), HookError> {
+    Ok(())
+  }
+
+  /// Called once a response is received; returning an error discards the response.
+  fn on_response(&self, _request: &InterceptedRequest, _response: &InterceptedResponse<'_>) -> Result<(), HookError> {
+    Ok(())
+  }
+
+  /// Called when the request fails without a response, e.g. on connection errors.
+  fn on_error(&self, _request: &InterceptedRequest, _error: &reqwest::Error) {}
+}
+
+/// Deserializer for a vendor-specific manifest media type.
+pub type ManifestDeserializer = dyn Fn(&[u8]) -> Result<serde_json::Value, HookError> + Send + Sync;
+
+/// Manifest media types registered with `Config::register_media_type`.
+#[derive(Clone, Default)]
+pub(crate) struct CustomMediaTypes(BTreeMap<String, Arc<ManifestDeserializer>>);
+
+impl CustomMediaTypes {
+  pub(crate) fn insert(&mut self, media_type: &str, deserializer: Arc<ManifestDeserializer>) {
+    self.0.insert(media_type.to_string(), deserializer);
+  }
+
+  /// Look up the deserializer for a `Content-Type` value, ignoring its parameters.
+  pub(crate) fn get(&self, content_type: &str) -> Option<Arc<ManifestDeserializer>> {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    self.0.get(media_type).cloned()
+  }
+
+  pub(crate) fn media_types(&self) -> impl Iterator<Item = &str> {
+    self.0.keys().map(String::as_str)
+  }
+}
+
+impl fmt::Debug for CustomMediaTypes {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_set().entries(self.0.keys()).finish()
+  }
+}
@@ -0,0 +1,23 @@
+/// HTTP versions used for connections to registries.
+///
+/// Over HTTP/2, concurrent requests to a registry, e.g. the blob downloads of a copy job, are
+/// multiplexed as streams over a single connection; over HTTP/1.1 each of them needs its own
+/// connection. The number of concurrent requests is set by the operation, e.g. with
+/// [`BulkOptions::concurrency`](crate::bulk::BulkOptions::concurrency), and over HTTP/2 is also
+/// bounded by the maximum number of concurrent streams advertised by the registry: further
+/// requests wait for a stream to complete.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+  /// Use HTTP/2 if the registry offers it during the TLS handshake, and HTTP/1.1 otherwise.
+  ///
+  /// Plain HTTP connections, to insecure registries, always use HTTP/1.1.
+  #[default]
+  Adaptive,
+  /// Only use HTTP/1.1, e.g. to spread downloads over several connections, which load balancers
+  /// may route to different backends.
+  Http1Only,
+  /// Use HTTP/2 without negotiating it, including over plain HTTP ("prior knowledge").
+  ///
+  /// Connections fail with registries which do not support HTTP/2.
+  Http2PriorKnowledge,
+}
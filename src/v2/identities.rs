@@ -0,0 +1,177 @@
+//! Several credential identities sharing one client.
+
+use std::{
+  collections::HashMap,
+  fmt,
+  sync::{Arc, Mutex, OnceLock, RwLock},
+};
+
+use crate::{
+  errors::{Error, Result},
+  v2::*,
+};
+
+/// Identities of a client and of the clients derived from it, by identifier.
+#[derive(Debug)]
+pub(crate) struct Identities {
+  entries: RwLock<HashMap<String, Arc<IdentityEntry>>>,
+  /// The token store of the client, which identities save their tokens to.
+  store: Option<Arc<dyn TokenStore>>,
+}
+
+impl Identities {
+  pub(crate) fn new(store: Option<Arc<dyn TokenStore>>) -> Self {
+    Self {
+      entries: Default::default(),
+      store,
+    }
+  }
+
+  /// Register the identity `id`, replacing any identity with the same identifier.
+  pub(crate) fn insert(&self, id: &str, username: &str, password: &str) {
+    let entry = Arc::new(IdentityEntry {
+      credentials: (username.to_string(), password.to_string()),
+      tokens: Arc::new(IdentityTokens {
+        id: id.to_string(),
+        cache: Default::default(),
+        store: self.store.clone(),
+      }),
+      caches: OnceLock::new(),
+    });
+    self.entries.write().unwrap().insert(id.to_string(), entry);
+  }
+}
+
+/// Credentials of an identity, and the tokens and content obtained with them.
+#[derive(Debug)]
+struct IdentityEntry {
+  credentials: (String, String),
+  tokens: Arc<IdentityTokens>,
+  caches: OnceLock<IdentityCaches>,
+}
+
+/// Caches and coalescers of an identity, configured like those of the client.
+///
+/// Content fetched, and blobs uploaded, with the credentials of one identity are never served
+/// to another identity or to the client itself, which may not have access to them.
+#[derive(Debug)]
+struct IdentityCaches {
+  manifest_cache: Option<Arc<Mutex<ManifestCache>>>,
+  #[cfg(not(target_arch = "wasm32"))]
+  response_cache: Option<Arc<Mutex<ResponseCache>>>,
+  signed_urls: Option<Arc<SignedUrls>>,
+  uploads: Arc<UploadCoalescer>,
+}
+
+impl IdentityCaches {
+  fn like(client: &Client) -> Self {
+    Self {
+      manifest_cache: client
+        .manifest_cache
+        .as_ref()
+        .map(|cache| Arc::new(Mutex::new(ManifestCache::new(cache.lock().unwrap().capacity())))),
+      #[cfg(not(target_arch = "wasm32"))]
+      response_cache: client
+        .response_cache
+        .as_ref()
+        .map(|cache| Arc::new(Mutex::new(ResponseCache::new(cache.lock().unwrap().capacity())))),
+      signed_urls: client.signed_urls.as_ref().map(|_| Default::default()),
+      uploads: Default::default(),
+    }
+  }
+}
+
+/// Token cache of an identity.
+///
+/// Tokens are kept in memory, and saved to the token store of the client, if any, under keys
+/// prefixed with the identity so that identities never share tokens.
+struct IdentityTokens {
+  id: String,
+  cache: Mutex<HashMap<String, StoredToken>>,
+  store: Option<Arc<dyn TokenStore>>,
+}
+
+impl fmt::Debug for IdentityTokens {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("IdentityTokens")
+      .field("id", &self.id)
+      .field("store", &self.store)
+      .finish_non_exhaustive()
+  }
+}
+
+impl TokenStore for IdentityTokens {
+  fn load(&self, key: &str) -> std::result::Result<Option<StoredToken>, HookError> {
+    if let Some(token) = self.cache.lock().unwrap().get(key) {
+      return Ok(Some(token.clone()));
+    }
+    match &self.store {
+      Some(store) => store.load(&format!("{}/{}", self.id, key)),
+      None => Ok(None),
+    }
+  }
+
+  fn save(&self, key: &str, token: &StoredToken) -> std::result::Result<(), HookError> {
+    self.cache.lock().unwrap().insert(key.to_string(), token.clone());
+    match &self.store {
+      Some(store) => store.save(&format!("{}/{}", self.id, key), token),
+      None => Ok(()),
+    }
+  }
+}
+
+impl Client {
+  /// Register the identity `id`, e.g. a tenant of a multi-tenant service, authenticating with
+  /// `username` and `password`, replacing any identity with the same identifier.
+  ///
+  /// Identities are shared by the client and every client derived from it.
+  pub fn add_identity(&self, id: &str, username: &str, password: &str) {
+    self.identities.insert(id, username, password);
+  }
+
+  /// Unregister the identity `id`, dropping its tokens. Returns whether it was registered.
+  ///
+  /// Clients already returned by [`Client::as_identity`] keep its credentials and tokens.
+  pub fn remove_identity(&self, id: &str) -> bool {
+    self.identities.entries.write().unwrap().remove(id).is_some()
+  }
+
+  /// Return a client acting as the identity `id`, registered with `Config::identity` or
+  /// [`Client::add_identity`], or fail with `Error::UnknownIdentity`.
+  ///
+  /// The client shares the connections of this client, but has the credentials of the
+  /// identity and is unauthenticated: call `authenticate` for the scopes of the operation.
+  /// Every identity has its own token cache, so authenticating again as the same identity
+  /// reuses its tokens until they expire, and identities never use each other's tokens. The
+  /// same goes for the manifest and response caches, the signed URLs of blob downloads,
+  /// coalesced uploads and credentials renewed by the `Config::credential_provider`.
+  pub fn as_identity(&self, id: &str) -> Result<Client> {
+    let entry = self
+      .identities
+      .entries
+      .read()
+      .unwrap()
+      .get(id)
+      .cloned()
+      .ok_or_else(|| Error::UnknownIdentity(id.to_string()))?;
+    let caches = entry.caches.get_or_init(|| IdentityCaches::like(self));
+    Ok(Client {
+      credentials: Some(entry.credentials.clone()),
+      manifest_cache: caches.manifest_cache.clone(),
+      #[cfg(not(target_arch = "wasm32"))]
+      response_cache: caches.response_cache.clone(),
+      signed_urls: caches.signed_urls.clone(),
+      uploads: caches.uploads.clone(),
+      token_store: Some(entry.tokens.clone()),
+      device_login: None,
+      auth: None,
+      identity: Some(id.to_string()),
+      ..self.clone()
+    })
+  }
+
+  /// The identity the client acts as, if it was returned by [`Client::as_identity`].
+  pub fn identity(&self) -> Option<&str> {
+    self.identity.as_deref()
+  }
+}
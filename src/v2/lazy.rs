@@ -0,0 +1,540 @@
+//! Lazy pulling of eStargz and zstd:chunked layers.
+//!
+//! Both formats keep layers valid gzip or zstd tar archives, compressed in chunks and followed
+//! by a table of contents (TOC) locating every chunk of every file in the compressed blob. A
+//! [`LazyLayer`] fetches the TOC with range requests, and then only the chunks of the files
+//! which are read, so that files can be read from a remote layer without downloading it, e.g.
+//! to experiment with lazy pulling without a snapshotter.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use tokio;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! # async fn run() -> docker_registry::errors::Result<()> {
+//! #
+//! use docker_registry::v2::{Client, Descriptor};
+//!
+//! let client = Client::configure().registry("ghcr.io").build()?;
+//! let layer = Descriptor::from_digest(
+//!   "application/vnd.oci.image.layer.v1.tar+gzip",
+//!   "sha256:2b0e4b8a0c1e5b7d8f4f2b7c9a3e1d6c5b4a3f2e1d0c9b8a7f6e5d4c3b2a1f0e",
+//!   31_415_926,
+//! );
+//! let layer = client
+//!   .lazy_layer("stargz-containers/python", &layer)
+//!   .await?;
+//! let release = layer.read_file("etc/os-release").await?;
+//! println!("{}", String::from_utf8_lossy(&release));
+//! #
+//! # Ok(())
+//! # };
+//! # run().await.unwrap();
+//! # }
+//! ```
+
+use std::{collections::HashMap, io::Read};
+
+use futures::stream::{self, StreamExt, TryStreamExt};
+use libflate::gzip;
+use serde::Deserialize;
+
+use crate::{
+  errors::{Error, Result},
+  layer::Compression,
+  v2::*,
+};
+
+/// Annotation of eStargz layers with the digest of their TOC.
+pub const ESTARGZ_TOC_DIGEST_ANNOTATION: &str = "containerd.io/snapshot/stargz/toc.digest";
+/// Annotation of zstd:chunked layers with the digest of their compressed TOC.
+pub const ZSTD_CHUNKED_TOC_DIGEST_ANNOTATION: &str = "io.github.containers.zstd-chunked.manifest-checksum";
+
+/// Size of the footer of eStargz layers, a gzip member with the offset of the TOC.
+const ESTARGZ_FOOTER_SIZE: u64 = 51;
+/// Size of the footer of layers written by the original stargz tools.
+const LEGACY_STARGZ_FOOTER_SIZE: u64 = 47;
+/// Name of the TOC in the tar archive at the end of eStargz layers.
+const ESTARGZ_TOC_NAME: &str = "stargz.index.json";
+/// Size of the footer of zstd:chunked layers, the content of a skippable zstd frame.
+#[cfg(feature = "zstd")]
+const ZSTD_CHUNKED_FOOTER_SIZE: u64 = 64;
+/// Magic number ending the footer of zstd:chunked layers.
+#[cfg(feature = "zstd")]
+const ZSTD_CHUNKED_MAGIC: &[u8] = b"GNUlInUx";
+
+/// Chunks fetched at the same time when reading a file.
+const PARALLEL_CHUNKS: usize = 4;
+
+/// Format of a layer which can be pulled lazily.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TocFormat {
+  /// gzip layer with an eStargz (or stargz) TOC.
+  Estargz,
+  /// zstd layer with a zstd:chunked TOC.
+  ZstdChunked,
+}
+
+/// An entry of the TOC of a layer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TocEntry {
+  /// Path of the entry in the layer, without leading `/`.
+  pub name: String,
+  /// Type of the entry: `reg`, `dir`, `symlink`, `hardlink`, `char`, `block` or `fifo`.
+  #[serde(rename = "type")]
+  pub kind: String,
+  /// Size of regular files, in bytes.
+  #[serde(default)]
+  pub size: u64,
+  /// Target of links.
+  #[serde(default)]
+  pub link_name: Option<String>,
+  #[serde(default)]
+  pub mode: i64,
+  #[serde(default)]
+  pub uid: i64,
+  #[serde(default)]
+  pub gid: i64,
+  /// Digest of the content of regular files.
+  #[serde(default)]
+  pub digest: Option<String>,
+  #[serde(default)]
+  offset: u64,
+  #[serde(default)]
+  end_offset: u64,
+  #[serde(default)]
+  chunk_offset: u64,
+  #[serde(default)]
+  chunk_size: u64,
+  #[serde(default)]
+  chunk_digest: Option<String>,
+  #[serde(default)]
+  inner_offset: u64,
+  #[serde(default)]
+  chunk_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Toc {
+  #[serde(default)]
+  entries: Vec<TocEntry>,
+}
+
+/// A chunk of a regular file, and where to find it in the compressed layer.
+#[derive(Clone, Debug)]
+struct Chunk {
+  /// Offset of the chunk in the file.
+  offset: u64,
+  size: u64,
+  /// Range of the compressed stream the chunk is in, in the layer.
+  start: u64,
+  end: u64,
+  /// Offset of the chunk in the decompressed stream.
+  inner_offset: u64,
+  digest: Option<String>,
+  /// Whether the chunk is only made of zeros and stored nowhere.
+  zeros: bool,
+}
+
+/// A remote eStargz or zstd:chunked layer whose files are fetched on demand, see
+/// `Client::lazy_layer`.
+#[derive(Clone, Debug)]
+pub struct LazyLayer {
+  client: Client,
+  name: String,
+  digest: String,
+  format: TocFormat,
+  entries: Vec<TocEntry>,
+  /// Chunks of the regular files, by path.
+  chunks: HashMap<String, Vec<Chunk>>,
+}
+
+impl Client {
+  /// Open the layer `descriptor` of `name` for lazy pulling, fetching its TOC with range
+  /// requests.
+  ///
+  /// The TOC is verified against the digest annotation of the layer if it has one. Fails with
+  /// `Error::InvalidToc` if the layer has no TOC, with `Error::UnsupportedLayerMediaType` if it
+  /// is neither gzip nor zstd, and with `Error::UnexpectedHttpStatus` if the registry does not
+  /// honour range requests.
+  pub async fn lazy_layer(&self, name: &str, descriptor: &Descriptor) -> Result<LazyLayer> {
+    let invalid = |reason: &str| Error::InvalidToc {
+      digest: descriptor.digest.clone(),
+      reason: reason.to_string(),
+    };
+    let annotation = |key: &str| descriptor.annotations.as_ref()?.get(key).cloned();
+    let max_toc_size = self.limits.max_toc_size;
+    let size = descriptor.size;
+
+    let (format, toc, toc_offset) = match Compression::from_media_type(&descriptor.media_type)? {
+      Compression::Gzip => {
+        if size < LEGACY_STARGZ_FOOTER_SIZE {
+          return Err(invalid("no eStargz footer"));
+        }
+        let footer_size = ESTARGZ_FOOTER_SIZE.min(size);
+        let footer = self
+          .get_blob_part(name, &descriptor.digest, size - footer_size, footer_size)
+          .await?;
+        let (toc_offset, footer_size) = estargz_toc_offset(&footer).ok_or_else(|| invalid("no eStargz footer"))?;
+        let toc_end = size - footer_size;
+        if toc_offset >= toc_end || toc_end - toc_offset > max_toc_size {
+          return Err(invalid("TOC out of bounds"));
+        }
+        let compressed = self
+          .get_blob_part(name, &descriptor.digest, toc_offset, toc_end - toc_offset)
+          .await?;
+        let toc = estargz_toc(&compressed, max_toc_size).ok_or_else(|| invalid("no TOC in the last gzip member"))?;
+        if let Some(expected) = annotation(ESTARGZ_TOC_DIGEST_ANNOTATION) {
+          verify(&expected, &toc)?;
+        }
+        (TocFormat::Estargz, toc, toc_offset)
+      }
+      #[cfg(feature = "zstd")]
+      Compression::Zstd => {
+        if size < ZSTD_CHUNKED_FOOTER_SIZE {
+          return Err(invalid("no zstd:chunked footer"));
+        }
+        let footer = self
+          .get_blob_part(
+            name,
+            &descriptor.digest,
+            size - ZSTD_CHUNKED_FOOTER_SIZE,
+            ZSTD_CHUNKED_FOOTER_SIZE,
+          )
+          .await?;
+        let (toc_offset, compressed_size) =
+          zstd_chunked_toc_position(&footer).ok_or_else(|| invalid("no zstd:chunked footer"))?;
+        if compressed_size == 0
+          || compressed_size > max_toc_size
+          || toc_offset.checked_add(compressed_size).map_or(true, |end| end > size)
+        {
+          return Err(invalid("TOC out of bounds"));
+        }
+        let compressed = self
+          .get_blob_part(name, &descriptor.digest, toc_offset, compressed_size)
+          .await?;
+        if let Some(expected) = annotation(ZSTD_CHUNKED_TOC_DIGEST_ANNOTATION) {
+          verify(&expected, &compressed)?;
+        }
+        let mut toc = Vec::new();
+        zstd::Decoder::new(&compressed[..])?
+          .take(max_toc_size + 1)
+          .read_to_end(&mut toc)?;
+        if toc.len() as u64 > max_toc_size {
+          return Err(Error::ResponseTooLarge {
+            kind: "TOC",
+            limit: max_toc_size,
+          });
+        }
+        (TocFormat::ZstdChunked, toc, toc_offset)
+      }
+      Compression::None => return Err(Error::UnsupportedLayerMediaType(descriptor.media_type.clone())),
+    };
+
+    let toc: Toc = serde_json::from_slice(&toc).map_err(|e| invalid(&e.to_string()))?;
+    let chunks = chunks(format, &toc.entries, toc_offset).ok_or_else(|| invalid("chunk out of bounds"))?;
+    Ok(LazyLayer {
+      client: self.clone(),
+      name: name.to_string(),
+      digest: descriptor.digest.clone(),
+      format,
+      entries: toc.entries.into_iter().filter(|e| e.kind != "chunk").collect(),
+      chunks,
+    })
+  }
+}
+
+impl LazyLayer {
+  /// Format of the layer.
+  pub fn format(&self) -> TocFormat {
+    self.format
+  }
+
+  /// The entries of the layer, in the order of the archive.
+  pub fn entries(&self) -> &[TocEntry] {
+    &self.entries
+  }
+
+  /// The entry at `path`, if any.
+  pub fn entry(&self, path: &str) -> Option<&TocEntry> {
+    let path = normalize(path);
+    self.entries.iter().find(|e| normalize(&e.name) == path)
+  }
+
+  /// Read the regular file at `path`, following hard links.
+  ///
+  /// Fails with `Error::LayerFileNotFound` if there is no regular file at `path`, and with
+  /// `Error::ResponseTooLarge` if it is larger than `ResponseLimits::max_lazy_read_size`.
+  pub async fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+    let size = self.regular_file(path)?.size;
+    self.read_at(path, 0, size).await
+  }
+
+  /// Read up to `len` bytes of the regular file at `path` from `offset`, fetching only the
+  /// chunks of the file these bytes are in. Chunks are verified against their digests.
+  ///
+  /// Fails with `Error::LayerFileNotFound` if there is no regular file at `path`, and with
+  /// `Error::ResponseTooLarge` if more than `ResponseLimits::max_lazy_read_size` bytes, or
+  /// chunks larger than `ResponseLimits::max_lazy_chunk_size`, would be read.
+  pub async fn read_at(&self, path: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let file = self.regular_file(path)?;
+    let end = offset.saturating_add(len).min(file.size);
+    if offset >= end {
+      return Ok(Vec::new());
+    }
+    let limit = self.client.limits.max_lazy_read_size;
+    if end - offset > limit {
+      return Err(Error::ResponseTooLarge {
+        kind: "lazy layer read",
+        limit,
+      });
+    }
+    let chunks = self
+      .chunks
+      .get(&normalize(&file.name))
+      .map(Vec::as_slice)
+      .unwrap_or_default();
+    let needed = chunks.iter().filter(|c| c.offset < end && c.offset + c.size > offset);
+
+    let mut data = Vec::with_capacity((end - offset) as usize);
+    let mut fetched = stream::iter(needed)
+      .map(|chunk| async move { Ok::<_, Error>((chunk, self.fetch_chunk(chunk).await?)) })
+      .buffered(PARALLEL_CHUNKS);
+    while let Some((chunk, bytes)) = fetched.try_next().await? {
+      let from = offset.saturating_sub(chunk.offset) as usize;
+      let to = (end - chunk.offset).min(chunk.size) as usize;
+      data.extend_from_slice(&bytes[from..to]);
+    }
+    if data.len() as u64 != end - offset {
+      return Err(Error::InvalidToc {
+        digest: self.digest.clone(),
+        reason: format!("chunks of {} do not cover the file", file.name),
+      });
+    }
+    Ok(data)
+  }
+
+  /// The regular file at `path`, or the one the hard link at `path` points to.
+  fn regular_file(&self, path: &str) -> Result<&TocEntry> {
+    let not_found = || Error::LayerFileNotFound(path.to_string());
+    let entry = self.entry(path).ok_or_else(not_found)?;
+    let entry = match (entry.kind.as_str(), &entry.link_name) {
+      ("hardlink", Some(target)) => self.entry(target).ok_or_else(not_found)?,
+      _ => entry,
+    };
+    match entry.kind.as_str() {
+      "reg" => Ok(entry),
+      _ => Err(not_found()),
+    }
+  }
+
+  /// Fetch and decompress `chunk`, verifying its digest.
+  async fn fetch_chunk(&self, chunk: &Chunk) -> Result<Vec<u8>> {
+    // Chunks are within their file and stream, see `chunks`, so these cannot overflow.
+    let wanted = chunk.inner_offset + chunk.size;
+    let limit = self.client.limits.max_lazy_chunk_size;
+    if wanted > limit || chunk.end - chunk.start > limit {
+      return Err(Error::ResponseTooLarge {
+        kind: "lazy layer chunk",
+        limit,
+      });
+    }
+    if chunk.zeros {
+      return Ok(vec![0; chunk.size as usize]);
+    }
+    let compressed = self
+      .client
+      .get_blob_part(&self.name, &self.digest, chunk.start, chunk.end - chunk.start)
+      .await?;
+    let mut data = Vec::with_capacity(wanted as usize);
+    match self.format {
+      TocFormat::Estargz => gzip::Decoder::new(&compressed[..])?
+        .take(wanted)
+        .read_to_end(&mut data)?,
+      #[cfg(feature = "zstd")]
+      TocFormat::ZstdChunked => zstd::Decoder::new(&compressed[..])?
+        .take(wanted)
+        .read_to_end(&mut data)?,
+      #[cfg(not(feature = "zstd"))]
+      TocFormat::ZstdChunked => return Err(Error::UnsupportedLayerMediaType("zstd:chunked".to_string())),
+    };
+    if data.len() as u64 != wanted {
+      return Err(Error::InvalidToc {
+        digest: self.digest.clone(),
+        reason: format!("chunk at offset {} is truncated", chunk.start),
+      });
+    }
+    let data = data.split_off(chunk.inner_offset as usize);
+    if let Some(expected) = &chunk.digest {
+      verify(expected, &data)?;
+    }
+    Ok(data)
+  }
+}
+
+/// Verify that `data` has the digest `expected`.
+fn verify(expected: &str, data: &[u8]) -> Result<()> {
+  let mut digest = ContentDigest::try_new(expected)?;
+  digest.update(data);
+  Ok(digest.verify()?)
+}
+
+/// Path of an entry as found in TOCs, without leading `./` or `/` nor trailing `/`.
+fn normalize(path: &str) -> String {
+  let path = path.strip_prefix("./").unwrap_or(path);
+  path.trim_matches('/').to_string()
+}
+
+/// The offset of the TOC announced by the eStargz or legacy stargz `footer` ending a layer,
+/// with the size of the footer.
+fn estargz_toc_offset(footer: &[u8]) -> Option<(u64, u64)> {
+  // The offset is in the extra field of the gzip header, after the 10 fixed bytes and XLEN.
+  let parse = |footer: &[u8], prefix: &[u8]| -> Option<u64> {
+    let extra = footer.get(12..)?.strip_prefix(prefix)?;
+    let (offset, magic) = (extra.get(..16)?, extra.get(16..22)?);
+    (footer.get(..3)? == [0x1f, 0x8b, 0x08] && magic == b"STARGZ")
+      .then(|| u64::from_str_radix(std::str::from_utf8(offset).ok()?, 16).ok())?
+  };
+  if footer.len() as u64 == ESTARGZ_FOOTER_SIZE {
+    if let Some(offset) = parse(footer, b"SG\x16\x00") {
+      return Some((offset, ESTARGZ_FOOTER_SIZE));
+    }
+  }
+  let legacy = footer.get(footer.len().checked_sub(LEGACY_STARGZ_FOOTER_SIZE as usize)?..)?;
+  parse(legacy, b"").map(|offset| (offset, LEGACY_STARGZ_FOOTER_SIZE))
+}
+
+/// The TOC in the tar archive of the gzip member `compressed`.
+fn estargz_toc(compressed: &[u8], limit: u64) -> Option<Vec<u8>> {
+  let mut tar = Vec::new();
+  gzip::Decoder::new(compressed)
+    .ok()?
+    // Leave room for the headers of the archive.
+    .take(limit.saturating_add(4096))
+    .read_to_end(&mut tar)
+    .ok()?;
+  let mut archive = tar::Archive::new(&tar[..]);
+  let mut entry = archive
+    .entries()
+    .ok()?
+    .filter_map(|e| e.ok())
+    .find(|e| e.path().is_ok_and(|p| p.as_os_str() == ESTARGZ_TOC_NAME))?;
+  let mut toc = Vec::new();
+  entry.read_to_end(&mut toc).ok()?;
+  (toc.len() as u64 <= limit).then_some(toc)
+}
+
+/// The offset and size of the compressed TOC announced by the zstd:chunked `footer`.
+#[cfg(feature = "zstd")]
+fn zstd_chunked_toc_position(footer: &[u8]) -> Option<(u64, u64)> {
+  let field = |i: usize| Some(u64::from_le_bytes(footer.get(i * 8..(i + 1) * 8)?.try_into().ok()?));
+  // The manifest type of zstd:chunked TOCs is 1, the TOC format of stargz.
+  (footer.get(56..)? == ZSTD_CHUNKED_MAGIC && field(3)? == 1).then_some((field(0)?, field(1)?))
+}
+
+/// The chunks of the regular files of a layer whose compressed streams end with the next
+/// stream or, for eStargz, at the TOC at `toc_offset`; `None` if a chunk is out of bounds, i.e.
+/// past the end of its file or of its stream, or in a stream past the TOC.
+fn chunks(format: TocFormat, entries: &[TocEntry], toc_offset: u64) -> Option<HashMap<String, Vec<Chunk>>> {
+  // eStargz entries have no end offset: a stream ends where the next one starts.
+  let mut starts: Vec<u64> = entries.iter().map(|e| e.offset).filter(|o| *o > 0).collect();
+  starts.push(toc_offset);
+  starts.sort_unstable();
+  starts.dedup();
+
+  let mut chunks: HashMap<String, Vec<Chunk>> = HashMap::new();
+  let mut sizes = HashMap::new();
+  for entry in entries {
+    let name = normalize(&entry.name);
+    let file_size = match entry.kind.as_str() {
+      "reg" => {
+        sizes.insert(name.clone(), entry.size);
+        entry.size
+      }
+      "chunk" => *sizes.get(&name)?,
+      _ => continue,
+    };
+    if file_size == 0 {
+      continue;
+    }
+    let size = match entry.chunk_size {
+      0 => file_size.checked_sub(entry.chunk_offset)?,
+      size => size,
+    };
+    if entry.chunk_offset.checked_add(size)? > file_size {
+      return None;
+    }
+    entry.inner_offset.checked_add(size)?;
+    let zeros = entry.chunk_type.as_deref() == Some("zeros");
+    let end = match format {
+      _ if zeros => entry.offset,
+      TocFormat::Estargz => *starts.iter().find(|s| **s > entry.offset)?,
+      TocFormat::ZstdChunked => entry.end_offset,
+    };
+    if !zeros && (entry.offset >= end || end > toc_offset) {
+      return None;
+    }
+    chunks.entry(name).or_default().push(Chunk {
+      offset: entry.chunk_offset,
+      size,
+      start: entry.offset,
+      end,
+      inner_offset: entry.inner_offset,
+      digest: entry.chunk_digest.clone(),
+      zeros,
+    });
+  }
+  Some(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_estargz_footers() {
+    let mut footer = vec![0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 26, 0];
+    footer.extend_from_slice(b"SG\x16\x00");
+    footer.extend_from_slice(b"00000000000004d2STARGZ");
+    footer.extend_from_slice(&[1, 0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(footer.len() as u64, ESTARGZ_FOOTER_SIZE);
+    assert_eq!(estargz_toc_offset(&footer), Some((1234, ESTARGZ_FOOTER_SIZE)));
+
+    let mut legacy = vec![0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 22, 0];
+    legacy.extend_from_slice(b"00000000000004d2STARGZ");
+    legacy.extend_from_slice(&[1, 0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(estargz_toc_offset(&legacy), Some((1234, LEGACY_STARGZ_FOOTER_SIZE)));
+
+    assert_eq!(estargz_toc_offset(&[0; 51]), None);
+  }
+
+  #[test]
+  fn rejects_out_of_bounds_chunks() {
+    let toc = |chunk: &str| -> Vec<TocEntry> {
+      let json = format!(
+        r#"{{"entries": [
+          {{"name": "f", "type": "reg", "size": 10, "offset": 10, "chunkSize": 5}},
+          {{"name": "f", "type": "chunk", "offset": 20, {chunk}}}
+        ]}}"#
+      );
+      serde_json::from_str::<Toc>(&json).unwrap().entries
+    };
+    assert!(chunks(TocFormat::Estargz, &toc(r#""chunkOffset": 5, "chunkSize": 5"#), 30).is_some());
+    assert!(chunks(TocFormat::Estargz, &toc(r#""chunkOffset": 5, "chunkSize": 6"#), 30).is_none());
+    assert!(chunks(
+      TocFormat::Estargz,
+      &toc(r#""chunkOffset": 18446744073709551615, "chunkSize": 5"#),
+      30
+    )
+    .is_none());
+    assert!(chunks(
+      TocFormat::Estargz,
+      &toc(r#""chunkOffset": 5, "chunkSize": 5, "innerOffset": 18446744073709551615"#),
+      30
+    )
+    .is_none());
+  }
+}
@@ -0,0 +1,274 @@
+//! Lenient parsing of the payloads of non-compliant registries.
+
+use std::{collections::VecDeque, fmt, sync::Mutex};
+
+use log::warn;
+use reqwest::header::HeaderValue;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{errors::Result, v2::*};
+
+/// Maximum number of warnings kept by a client, older warnings are dropped first.
+const MAX_PARSE_WARNINGS: usize = 256;
+
+/// Keys of manifests and descriptors, in the casing of the specifications.
+const MANIFEST_KEYS: &[&str] = &[
+  "annotations",
+  "architecture",
+  "artifactType",
+  "blobSum",
+  "config",
+  "digest",
+  "features",
+  "fsLayers",
+  "history",
+  "layers",
+  "manifests",
+  "mediaType",
+  "name",
+  "os",
+  "os.features",
+  "os.version",
+  "platform",
+  "schemaVersion",
+  "signatures",
+  "size",
+  "subject",
+  "tag",
+  "urls",
+  "v1Compatibility",
+  "variant",
+];
+
+/// Deviation from the specification tolerated by a client with `Config::lenient_parsing`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseWarning {
+  /// Kind of payload the deviation was found in, e.g. `tag list`, `catalog` or `manifest`.
+  pub payload: String,
+  /// Description of the deviation and how it was handled.
+  pub message: String,
+}
+
+impl fmt::Display for ParseWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.payload, self.message)
+  }
+}
+
+/// Warnings shared by a client and the clients derived from it.
+#[derive(Debug, Default)]
+pub(crate) struct ParseWarnings(Mutex<VecDeque<ParseWarning>>);
+
+/// Payload parsed by `Client::parse_payload`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Payload<'a> {
+  /// A page of the tags of the repository.
+  TagList(&'a str),
+  Catalog,
+  Manifest,
+}
+
+impl Payload<'_> {
+  fn kind(&self) -> &'static str {
+    match self {
+      Payload::TagList(_) => "tag list",
+      Payload::Catalog => "catalog",
+      Payload::Manifest => "manifest",
+    }
+  }
+}
+
+impl Client {
+  /// Parse `body` as `payload`, repairing known deviations first if the client is lenient.
+  pub(crate) fn parse_payload<T: DeserializeOwned>(&self, body: &[u8], payload: Payload<'_>) -> Result<T> {
+    if !self.lenient_parsing {
+      return Ok(serde_json::from_slice(body)?);
+    }
+    let mut value: Value = serde_json::from_slice(body)?;
+    let mut messages = Vec::new();
+    repair(&mut value, payload, &mut messages);
+    self.record_parse_warnings(payload, messages);
+    Ok(serde_json::from_value(value)?)
+  }
+
+  /// The `Content-Type` of a manifest, lowercased and without parameters if the client is
+  /// lenient and the registry sent them.
+  pub(crate) fn repair_content_type(&self, content_type: Option<&HeaderValue>) -> Option<HeaderValue> {
+    let content_type = content_type?;
+    if !self.lenient_parsing {
+      return Some(content_type.clone());
+    }
+    let Ok(value) = content_type.to_str() else {
+      return Some(content_type.clone());
+    };
+    let repaired = value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    if repaired == value {
+      return Some(content_type.clone());
+    }
+    self.record_parse_warnings(
+      Payload::Manifest,
+      vec![format!("using content type {} for {}", repaired, value)],
+    );
+    HeaderValue::from_str(&repaired).ok()
+  }
+
+  fn record_parse_warnings(&self, payload: Payload<'_>, messages: Vec<String>) {
+    if messages.is_empty() {
+      return;
+    }
+    let mut warnings = self.parse_warnings.0.lock().unwrap();
+    for message in messages {
+      warn!("Tolerating invalid {}: {}", payload.kind(), message);
+      if warnings.len() == MAX_PARSE_WARNINGS {
+        warnings.pop_front();
+      }
+      warnings.push_back(ParseWarning {
+        payload: payload.kind().to_string(),
+        message,
+      });
+    }
+  }
+
+  /// Deviations tolerated while parsing registry responses, oldest first.
+  ///
+  /// Only clients with `Config::lenient_parsing` collect warnings, and they keep the last 256.
+  /// Clients derived from this one share its warnings.
+  pub fn parse_warnings(&self) -> Vec<ParseWarning> {
+    self.parse_warnings.0.lock().unwrap().iter().cloned().collect()
+  }
+
+  /// Return the warnings collected so far and clear them.
+  pub fn take_parse_warnings(&self) -> Vec<ParseWarning> {
+    self.parse_warnings.0.lock().unwrap().drain(..).collect()
+  }
+}
+
+/// Repair the deviations of `value` from the specification of `payload`, describing each of
+/// them in `messages`.
+fn repair(value: &mut Value, payload: Payload<'_>, messages: &mut Vec<String>) {
+  match payload {
+    Payload::TagList(name) => {
+      let Value::Object(object) = value else { return };
+      if !object.get("name").is_some_and(Value::is_string) {
+        messages.push(format!("missing repository name, using {}", name));
+        object.insert("name".to_string(), Value::String(name.to_string()));
+      }
+      repair_list(object, "tags", messages);
+    }
+    Payload::Catalog => {
+      if let Value::Object(object) = value {
+        repair_list(object, "repositories", messages);
+      }
+    }
+    Payload::Manifest => repair_manifest(value, messages),
+  }
+}
+
+/// Replace a missing or null list `key` with an empty list, and drop its entries which are not
+/// strings.
+fn repair_list(object: &mut serde_json::Map<String, Value>, key: &str, messages: &mut Vec<String>) {
+  match object.get_mut(key) {
+    None | Some(Value::Null) => {
+      messages.push(format!("missing {}, assuming none", key));
+      object.insert(key.to_string(), Value::Array(Vec::new()));
+    }
+    Some(Value::Array(entries)) => {
+      let len = entries.len();
+      entries.retain(Value::is_string);
+      if entries.len() != len {
+        messages.push(format!("ignoring {} invalid entries of {}", len - entries.len(), key));
+      }
+    }
+    Some(_) => {}
+  }
+}
+
+/// Fix the casing of the keys and media types of a manifest, and sizes and schema versions
+/// sent as strings.
+fn repair_manifest(value: &mut Value, messages: &mut Vec<String>) {
+  match value {
+    Value::Object(object) => {
+      let misnamed: Vec<(String, &str)> = object
+        .keys()
+        .filter(|key| !MANIFEST_KEYS.contains(&key.as_str()))
+        .filter_map(|key| {
+          let known = MANIFEST_KEYS.iter().find(|known| known.eq_ignore_ascii_case(key))?;
+          Some((key.clone(), *known))
+        })
+        .collect();
+      for (key, known) in misnamed {
+        if object.contains_key(known) {
+          continue;
+        }
+        messages.push(format!("renaming {} to {}", key, known));
+        let entry = object.remove(&key).unwrap_or_default();
+        object.insert(known.to_string(), entry);
+      }
+      for key in ["mediaType", "artifactType"] {
+        if let Some(Value::String(media_type)) = object.get_mut(key) {
+          if media_type.chars().any(|c| c.is_ascii_uppercase()) {
+            messages.push(format!("lowercasing {} {}", key, media_type));
+            media_type.make_ascii_lowercase();
+          }
+        }
+      }
+      for key in ["schemaVersion", "size"] {
+        if let Some(Value::String(text)) = object.get(key) {
+          if let Ok(number) = text.trim().parse::<u64>() {
+            messages.push(format!("parsing {} {:?} as a number", key, text));
+            object.insert(key.to_string(), number.into());
+          }
+        }
+      }
+      // Annotations are free-form.
+      object
+        .iter_mut()
+        .filter(|(key, _)| *key != "annotations")
+        .for_each(|(_, value)| repair_manifest(value, messages));
+    }
+    Value::Array(values) => values.iter_mut().for_each(|value| repair_manifest(value, messages)),
+    _ => {}
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::*;
+
+  #[test_case::test_case(json!({"name": "repo", "tags": null}), json!({"name": "repo", "tags": []}), 1 ; "null tags")]
+  #[test_case::test_case(json!({"tags": ["v1", null, 2]}), json!({"name": "repo", "tags": ["v1"]}), 2 ; "invalid entries")]
+  #[test_case::test_case(json!({"name": "repo", "tags": ["v1"]}), json!({"name": "repo", "tags": ["v1"]}), 0 ; "compliant")]
+  fn repair_tag_list(mut value: Value, expected: Value, warnings: usize) {
+    let mut messages = Vec::new();
+    repair(&mut value, Payload::TagList("repo"), &mut messages);
+    assert_eq!(value, expected);
+    assert_eq!(messages.len(), warnings);
+  }
+
+  #[test]
+  fn repair_manifest_casing() {
+    let mut value = json!({
+      "SchemaVersion": "2",
+      "MediaType": "application/vnd.OCI.image.manifest.v1+json",
+      "config": {"mediaType": "application/vnd.oci.image.config.v1+json", "Digest": "sha256:aa", "size": 2},
+      "Layers": [],
+      "unknown": 1,
+    });
+    let mut messages = Vec::new();
+    repair(&mut value, Payload::Manifest, &mut messages);
+    assert_eq!(
+      value,
+      json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "config": {"mediaType": "application/vnd.oci.image.config.v1+json", "digest": "sha256:aa", "size": 2},
+        "layers": [],
+        "unknown": 1,
+      })
+    );
+    assert_eq!(messages.len(), 6);
+  }
+}
@@ -0,0 +1,254 @@
+use std::{fmt, io::Read};
+
+use futures::StreamExt;
+use libflate::gzip;
+use reqwest::{header, Response};
+use serde::{
+  de::{IgnoredAny, SeqAccess, Visitor},
+  Deserialize, Deserializer,
+};
+
+use crate::{
+  errors::{Error, Result},
+  layer::Compression,
+  v2::EndpointClass,
+};
+
+/// Maximum sizes accepted for registry responses which are buffered in memory.
+///
+/// Responses exceeding a limit fail with `Error::ResponseTooLarge`, and manifests with too
+/// many layers or children with `Error::ManifestTooComplex`, so that a malicious or broken
+/// registry cannot exhaust the memory of the client. The defaults are generous bounds chosen
+/// for this crate, not values taken from a specification or another client: raise them for
+/// registries hosting unusually large images or indexes.
+#[derive(Clone, Debug)]
+pub struct ResponseLimits {
+  /// Maximum size of a manifest, in bytes.
+  pub max_manifest_size: u64,
+  /// Maximum number of layers of an image manifest.
+  pub max_manifest_layers: usize,
+  /// Maximum number of manifests referenced by a manifest list or an image index.
+  pub max_index_manifests: usize,
+  /// Maximum number of referrers of a manifest, which grow with every signature, SBOM or
+  /// attestation attached to it.
+  pub max_referrers: usize,
+  /// Maximum size of a list of referrers, in bytes.
+  pub max_referrers_size: u64,
+  /// Maximum size of an image config blob, in bytes.
+  pub max_config_size: u64,
+  /// Maximum size of a single page of a tag list, in bytes.
+  pub max_tag_list_size: u64,
+  /// Maximum size of a single page of the catalog, in bytes.
+  pub max_catalog_size: u64,
+  /// Maximum size of a response to `Client::raw_request`, in bytes.
+  pub max_raw_response_size: u64,
+  /// Maximum size of the table of contents of a layer pulled lazily, compressed or not, in
+  /// bytes.
+  pub max_toc_size: u64,
+  /// Maximum size of a chunk of a layer pulled lazily, compressed or not, in bytes. The
+  /// decompressed size includes the data before the chunk in its compressed stream.
+  pub max_lazy_chunk_size: u64,
+  /// Maximum size of a single read of a file of a layer pulled lazily, in bytes.
+  pub max_lazy_read_size: u64,
+}
+
+impl Default for ResponseLimits {
+  fn default() -> Self {
+    Self {
+      max_manifest_size: 4 << 20,
+      max_manifest_layers: 256,
+      max_index_manifests: 1024,
+      max_referrers: 16384,
+      max_referrers_size: 32 << 20,
+      max_config_size: 8 << 20,
+      max_tag_list_size: 32 << 20,
+      max_catalog_size: 32 << 20,
+      max_raw_response_size: 32 << 20,
+      max_toc_size: 64 << 20,
+      max_lazy_chunk_size: 64 << 20,
+      max_lazy_read_size: 1 << 30,
+    }
+  }
+}
+
+impl ResponseLimits {
+  /// Maximum size of a response body from an endpoint of `class`, and what the body is.
+  ///
+  /// Blobs and any other responses not buffered by the client fall back to the raw response
+  /// limit.
+  pub(crate) fn body_limit(&self, class: EndpointClass) -> (u64, &'static str) {
+    match class {
+      EndpointClass::Manifest => (self.max_manifest_size, "manifest"),
+      EndpointClass::Referrers => (self.max_referrers_size, "referrers"),
+      EndpointClass::Tags => (self.max_tag_list_size, "tag list"),
+      EndpointClass::Catalog => (self.max_catalog_size, "catalog"),
+      _ => (self.max_raw_response_size, "raw"),
+    }
+  }
+}
+
+/// Read the whole body of a catalog or tag list response, failing as soon as it grows beyond
+/// `limit` bytes.
+///
+/// Bodies compressed with gzip or zstd, see `Config::accept_encoding`, are decompressed, and
+/// the limit applies to both the compressed and the decompressed body. Bodies with another
+/// `Content-Encoding` fail with `Error::UnsupportedContentEncoding`.
+pub(crate) async fn read_decoded_limited(res: Response, limit: u64, kind: &'static str) -> Result<Vec<u8>> {
+  let encoding = match res.headers().get(header::CONTENT_ENCODING) {
+    None => Compression::None,
+    Some(value) => value
+      .to_str()
+      .ok()
+      .and_then(Compression::from_content_encoding)
+      .ok_or_else(|| Error::UnsupportedContentEncoding {
+        kind,
+        encoding: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+      })?,
+  };
+  let body = read_limited(res, limit, kind).await?;
+  let mut decoder: Box<dyn Read + '_> = match encoding {
+    Compression::None => return Ok(body),
+    Compression::Gzip => Box::new(gzip::MultiDecoder::new(&body[..])?),
+    #[cfg(feature = "zstd")]
+    Compression::Zstd => Box::new(zstd::Decoder::new(&body[..])?),
+  };
+  let mut decoded = Vec::new();
+  decoder.by_ref().take(limit + 1).read_to_end(&mut decoded)?;
+  if decoded.len() as u64 > limit {
+    return Err(Error::ResponseTooLarge { kind, limit });
+  }
+  Ok(decoded)
+}
+
+/// Read the whole response body as sent, without decoding its `Content-Encoding`, failing as
+/// soon as it grows beyond `limit` bytes.
+pub(crate) async fn read_limited(res: Response, limit: u64, kind: &'static str) -> Result<Vec<u8>> {
+  if let Some(len) = res.content_length() {
+    if len > limit {
+      return Err(Error::ResponseTooLarge { kind, limit });
+    }
+  }
+
+  let mut body = Vec::new();
+  let mut chunks = res.bytes_stream();
+  while let Some(chunk) = chunks.next().await.transpose()? {
+    if (body.len() + chunk.len()) as u64 > limit {
+      return Err(Error::ResponseTooLarge { kind, limit });
+    }
+    body.extend_from_slice(&chunk);
+  }
+  Ok(body)
+}
+
+/// Check the number of layers and children of a manifest against the limits.
+///
+/// Payloads which are not JSON manifests are left for the caller to reject.
+pub(crate) fn check_manifest_complexity(body: &[u8], limits: &ResponseLimits) -> Result<()> {
+  let shape: ManifestShape = match serde_json::from_slice(body) {
+    Ok(shape) => shape,
+    Err(_) => return Ok(()),
+  };
+  let checks = [
+    ("layers", shape.layers.0 + shape.fs_layers.0, limits.max_manifest_layers),
+    ("manifests", shape.manifests.0, limits.max_index_manifests),
+  ];
+  for (kind, count, limit) in checks {
+    if count > limit {
+      return Err(Error::ManifestTooComplex { kind, count, limit });
+    }
+  }
+  Ok(())
+}
+
+/// Check the number of referrers in a list of referrers against the limits.
+pub(crate) fn check_referrers_count(body: &[u8], limits: &ResponseLimits) -> Result<()> {
+  let shape: ManifestShape = match serde_json::from_slice(body) {
+    Ok(shape) => shape,
+    Err(_) => return Ok(()),
+  };
+  match shape.manifests.0 {
+    count if count > limits.max_referrers => Err(Error::ManifestTooComplex {
+      kind: "referrers",
+      count,
+      limit: limits.max_referrers,
+    }),
+    _ => Ok(()),
+  }
+}
+
+/// Lengths of the arrays of a manifest, counted without deserializing their elements.
+#[derive(Deserialize)]
+struct ManifestShape {
+  #[serde(default)]
+  layers: Count,
+  #[serde(default, rename = "fsLayers")]
+  fs_layers: Count,
+  #[serde(default)]
+  manifests: Count,
+}
+
+#[derive(Default)]
+struct Count(usize);
+
+impl<'de> Deserialize<'de> for Count {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    struct CountVisitor;
+
+    impl<'de> Visitor<'de> for CountVisitor {
+      type Value = Count;
+
+      fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an array")
+      }
+
+      fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Count, A::Error> {
+        let mut count = 0;
+        while seq.next_element::<IgnoredAny>()?.is_some() {
+          count += 1;
+        }
+        Ok(Count(count))
+      }
+    }
+
+    deserializer.deserialize_seq(CountVisitor)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  fn limits() -> ResponseLimits {
+    ResponseLimits {
+      max_manifest_layers: 2,
+      max_index_manifests: 1,
+      max_referrers: 2,
+      ..Default::default()
+    }
+  }
+
+  #[test_case(r#"{"layers": [{}, {}]}"# => None; "layers within limit")]
+  #[test_case(r#"{"layers": [{}, {}, {}]}"# => Some(("layers", 3)); "too many layers")]
+  #[test_case(r#"{"fsLayers": [{}, {}, {}]}"# => Some(("layers", 3)); "too many schema1 layers")]
+  #[test_case(r#"{"manifests": [{}, {}]}"# => Some(("manifests", 2)); "too many manifests")]
+  #[test_case("not json" => None; "not a manifest")]
+  fn manifest_complexity(body: &str) -> Option<(&'static str, usize)> {
+    match check_manifest_complexity(body.as_bytes(), &limits()) {
+      Ok(()) => None,
+      Err(Error::ManifestTooComplex { kind, count, .. }) => Some((kind, count)),
+      Err(e) => panic!("unexpected error {e}"),
+    }
+  }
+
+  #[test_case(r#"{"manifests": [{}, {}]}"# => None; "referrers within limit")]
+  #[test_case(r#"{"manifests": [{}, {}, {}]}"# => Some(("referrers", 3)); "too many referrers")]
+  fn referrers_count(body: &str) -> Option<(&'static str, usize)> {
+    match check_referrers_count(body.as_bytes(), &limits()) {
+      Ok(()) => None,
+      Err(Error::ManifestTooComplex { kind, count, .. }) => Some((kind, count)),
+      Err(e) => panic!("unexpected error {e}"),
+    }
+  }
+}
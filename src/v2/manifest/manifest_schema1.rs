@@ -1,11 +1,16 @@
 use std::collections::HashMap;
 
+use base64::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use super::{ManifestError, RawManifest};
+use crate::errors::Result;
+
 /// Manifest version 2 schema 1, signed.
 ///
 /// Specification is at <https://docs.docker.com/registry/spec/manifest-v2-1/>.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ManifestSchema1Signed {
   #[serde(rename = "schemaVersion")]
   schema_version: u16,
@@ -16,9 +21,33 @@ pub struct ManifestSchema1Signed {
   fs_layers: Vec<S1Layer>,
   history: Vec<V1Compat>,
   signatures: Vec<Signature>,
+  #[serde(skip)]
+  pub(crate) raw: RawManifest,
+}
+
+/// The manifest without its signatures, which is signed and digested.
+#[derive(Serialize)]
+struct UnsignedManifest<'a> {
+  #[serde(rename = "schemaVersion")]
+  schema_version: u16,
+  name: &'a str,
+  tag: &'a str,
+  architecture: &'a str,
+  #[serde(rename = "fsLayers")]
+  fs_layers: &'a [S1Layer],
+  history: &'a [V1Compat],
 }
 
-#[derive(Debug, Default, Deserialize, Serialize)]
+/// The part of the protected header of a signature locating the signed payload in the manifest.
+#[derive(Deserialize)]
+struct PayloadFormat {
+  #[serde(rename = "formatLength")]
+  format_length: usize,
+  #[serde(rename = "formatTail")]
+  format_tail: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 struct Signature {
   // TODO(lucab): switch to jsonwebtokens crate
   // https://github.com/Keats/rust-jwt/pull/23
@@ -28,13 +57,13 @@ struct Signature {
 }
 
 /// Compatibility entry for version 1 manifest interoperability.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 struct V1Compat {
   #[serde(rename = "v1Compatibility")]
   v1_compat: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 struct S1Layer {
   #[serde(rename = "blobSum")]
   blob_sum: String,
@@ -48,6 +77,40 @@ impl ManifestSchema1Signed {
     self.fs_layers.iter().rev().map(|l| l.blob_sum.clone()).collect()
   }
 
+  /// The signed payload of this manifest, whose digest is the digest of the manifest.
+  ///
+  /// For manifests pulled with `Client::get_manifest`, this is the JSON the signatures were
+  /// computed over, as located by their protected header. Other manifests are serialized
+  /// without their signatures, indented with three spaces as Docker does before signing them.
+  pub fn payload(&self) -> Result<Vec<u8>> {
+    let (Some(raw), Some(signature)) = (self.raw.get(), self.signatures.first()) else {
+      let unsigned = UnsignedManifest {
+        schema_version: self.schema_version,
+        name: &self.name,
+        tag: &self.tag,
+        architecture: &self.architecture,
+        fs_layers: &self.fs_layers,
+        history: &self.history,
+      };
+      let mut payload = Vec::new();
+      let formatter = serde_json::ser::PrettyFormatter::with_indent(b"   ");
+      unsigned.serialize(&mut serde_json::Serializer::with_formatter(&mut payload, formatter))?;
+      return Ok(payload);
+    };
+
+    let invalid = |reason: &str| ManifestError::Invalid(format!("signature protected header {}", reason));
+    let decode = |encoded: &str| BASE64_URL_SAFE_NO_PAD.decode(encoded.trim_end_matches('='));
+    let protected = decode(&signature.protected).map_err(|_| invalid("is not base64"))?;
+    let format: PayloadFormat = serde_json::from_slice(&protected).map_err(|_| invalid("has no format"))?;
+    let tail = decode(&format.format_tail).map_err(|_| invalid("has an invalid format tail"))?;
+    let mut payload = raw
+      .get(..format.format_length)
+      .ok_or_else(|| invalid("has a format length past the manifest"))?
+      .to_vec();
+    payload.extend(tail);
+    Ok(payload)
+  }
+
   /// Get a collection of all image labels stored in the history array of this manifest.
   ///
   /// Note that for this manifest type any `layer` beyond 0 probably returns None.
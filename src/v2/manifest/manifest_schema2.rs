@@ -1,104 +1,119 @@
+#[cfg(feature = "client")]
 use log::trace;
+#[cfg(feature = "client")]
 use reqwest::Method;
 use serde::{Deserialize, Serialize};
 
+use super::RawManifest;
+#[cfg(feature = "client")]
 use crate::errors::Result;
+#[cfg(feature = "client")]
 pub use crate::v2::ApiErrors;
+pub use crate::v2::Descriptor;
 
 /// Manifest version 2 schema 2.
 ///
 /// Specification is at <https://docs.docker.com/registry/spec/manifest-v2-2/>.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ManifestSchema2Spec {
   #[serde(rename = "schemaVersion")]
   schema_version: u16,
   #[serde(rename = "mediaType")]
   media_type: String,
   config: Config,
-  layers: Vec<S2Layer>,
+  layers: Vec<Descriptor>,
+  /// Fields not modeled here, e.g. `annotations`, `subject` or `artifactType`, kept so that
+  /// serializing the manifest again does not drop them.
+  #[serde(flatten)]
+  other: serde_json::Map<String, serde_json::Value>,
+  #[serde(skip)]
+  pub(crate) raw: RawManifest,
 }
 
 /// Super-type for combining a ManifestSchema2 with a ConfigBlob.
-#[derive(Debug, Default)]
+#[non_exhaustive]
+#[derive(Clone, Debug, Default)]
 pub struct ManifestSchema2 {
   pub manifest_spec: ManifestSchema2Spec,
   pub config_blob: ConfigBlob,
 }
 
-#[derive(Debug, Default, Deserialize, Serialize, Clone)]
-pub struct Config {
-  #[serde(rename = "mediaType")]
-  pub media_type: String,
-  pub size: u64,
-  pub digest: String,
-}
+/// Descriptor of the config blob of a manifest.
+pub type Config = Descriptor;
 
 /// Partial representation of a container image (application/vnd.docker.container.image.v1+json).
 ///
 /// The remaining fields according to [the image spec v1][image-spec-v1] are not covered.
 ///
 /// [image-spec-v1]: https://github.com/moby/moby/blob/a30990b3c8d0d42280fa501287859e1d2393a951/image/spec/v1.md#image-json-description
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ConfigBlob {
   architecture: String,
-}
-
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct S2Layer {
-  #[serde(rename = "mediaType")]
-  media_type: String,
-  size: u64,
-  digest: String,
-  urls: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  os: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  created: Option<String>,
 }
 
 /// Manifest List.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ManifestList {
   #[serde(rename = "schemaVersion")]
   schema_version: u16,
   #[serde(rename = "mediaType")]
   media_type: String,
-  pub manifests: Vec<ManifestObj>,
+  manifests: Vec<ManifestObj>,
+  /// Fields not modeled here, e.g. `annotations`, `subject` or `artifactType`, kept so that
+  /// serializing the manifest list again does not drop them.
+  #[serde(flatten)]
+  other: serde_json::Map<String, serde_json::Value>,
+  #[serde(skip)]
+  pub(crate) raw: RawManifest,
 }
 
-/// Manifest object.
-#[derive(Debug, Default, Deserialize, Serialize)]
-pub struct ManifestObj {
-  #[serde(rename = "mediaType")]
-  media_type: String,
-  size: u64,
-  pub digest: String,
-  pub platform: Platform,
-}
+/// Manifest object, the descriptor of a manifest in a manifest list.
+pub type ManifestObj = Descriptor;
 
 /// Platform-related manifest entries.
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Platform {
   pub architecture: String,
   pub os: String,
-  #[serde(rename = "os.version")]
+  #[serde(rename = "os.version", default, skip_serializing_if = "Option::is_none")]
   pub os_version: Option<String>,
-  #[serde(rename = "os.features")]
+  #[serde(rename = "os.features", default, skip_serializing_if = "Option::is_none")]
   pub os_features: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
   pub variant: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
   pub features: Option<Vec<String>>,
 }
 
 impl ManifestSchema2Spec {
+  /// Get the media type declared by this manifest.
+  pub fn media_type(&self) -> &str {
+    &self.media_type
+  }
+
   /// Get `Config` object referenced by this manifest.
   pub fn config(&self) -> &Config {
     &self.config
   }
 
+  /// Get the descriptors of the layers of this manifest, base layer first.
+  pub fn layers(&self) -> &[Descriptor] {
+    &self.layers
+  }
+
   /// Fetch the config blob for this manifest
+  #[cfg(feature = "client")]
   pub(crate) async fn fetch_config_blob(self, client: crate::v2::Client, repo: String) -> Result<ManifestSchema2> {
-    let url = {
-      let ep = format!("{}/v2/{}/blobs/{}", client.base_url.clone(), repo, self.config.digest);
-      reqwest::Url::parse(&ep)?
-    };
+    let url = client.repository_url(&repo, &format!("blobs/{}", self.config.digest))?;
 
-    let r = client.build_reqwest(Method::GET, url.clone()).send().await?;
+    let r = client.send(client.build_reqwest(Method::GET, url.clone())).await?;
 
     let status = r.status();
     trace!("GET {:?}: {}", url, &status);
@@ -107,7 +122,10 @@ impl ManifestSchema2Spec {
       return Err(ApiErrors::from(r).await);
     }
 
-    let config_blob = r.json::<ConfigBlob>().await?;
+    let body = crate::v2::read_limited(r, client.limits.max_config_size, "config")
+      .await
+      .map_err(|e| client.deadline_error(e))?;
+    let config_blob = serde_json::from_slice::<ConfigBlob>(&body)?;
 
     Ok(ManifestSchema2 {
       manifest_spec: self,
@@ -117,6 +135,14 @@ impl ManifestSchema2Spec {
 }
 
 impl ManifestSchema2 {
+  /// Combine a manifest with its config blob.
+  pub fn new(manifest_spec: ManifestSchema2Spec, config_blob: ConfigBlob) -> Self {
+    Self {
+      manifest_spec,
+      config_blob,
+    }
+  }
+
   /// List digests of all layers referenced by this manifest.
   ///
   /// The returned layers list is ordered starting with the base image first.
@@ -128,24 +154,88 @@ impl ManifestSchema2 {
   pub fn architecture(&self) -> String {
     self.config_blob.architecture.to_owned()
   }
+
+  /// Get the operating system from the config, if available.
+  pub fn os(&self) -> Option<String> {
+    self.config_blob.os.to_owned()
+  }
+
+  /// Get the image creation timestamp from the config, if available.
+  pub fn created(&self) -> Option<String> {
+    self.config_blob.created.to_owned()
+  }
+
+  /// List digests and sizes of the config and layer blobs referenced by this manifest.
+  pub fn blob_sizes(&self) -> Vec<(String, u64)> {
+    std::iter::once((self.manifest_spec.config.digest.clone(), self.manifest_spec.config.size))
+      .chain(self.manifest_spec.layers.iter().map(|l| (l.digest.clone(), l.size)))
+      .collect()
+  }
+
+  /// Total size in bytes of the config and layer blobs referenced by this manifest.
+  pub fn size(&self) -> u64 {
+    self.manifest_spec.config.size + self.manifest_spec.layers.iter().map(|l| l.size).sum::<u64>()
+  }
 }
 
-impl ManifestObj {
-  /// Get the architecture of the manifest object
-  pub fn architecture(&self) -> String {
-    self.platform.architecture.to_owned()
+impl Platform {
+  pub fn new(architecture: &str, os: &str) -> Self {
+    Self {
+      architecture: architecture.to_string(),
+      os: os.to_string(),
+      ..Default::default()
+    }
+  }
+
+  pub fn with_variant(mut self, variant: &str) -> Self {
+    self.variant = Some(variant.to_string());
+    self
+  }
+
+  pub fn with_os_version(mut self, os_version: &str) -> Self {
+    self.os_version = Some(os_version.to_string());
+    self
   }
+}
 
-  /// Returns the sha digest of the manifest object
-  pub fn digest(&self) -> String {
-    self.digest.to_owned()
+impl std::fmt::Display for Platform {
+  /// Format the platform as `os/architecture[/variant]`.
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}/{}", self.os, self.architecture)?;
+    if let Some(variant) = &self.variant {
+      write!(f, "/{}", variant)?;
+    }
+    Ok(())
   }
 }
 
 impl ManifestList {
-  /// Get architecture of all the manifests
+  /// Get the media type declared by this manifest list.
+  pub fn media_type(&self) -> &str {
+    &self.media_type
+  }
+
+  /// Get the descriptors of the manifests of this manifest list.
+  pub fn manifests(&self) -> &[ManifestObj] {
+    &self.manifests
+  }
+
+  /// Replace the descriptors of the manifests of this manifest list.
+  ///
+  /// The bytes the manifest list was pulled with no longer describe it, so `Manifest::to_bytes`
+  /// and `Manifest::digest` serialize it anew.
+  pub fn set_manifests(&mut self, manifests: Vec<ManifestObj>) {
+    self.manifests = manifests;
+    self.raw = RawManifest::default();
+  }
+
+  /// Get architecture of all the manifests which declare a platform.
   pub fn architectures(&self) -> Vec<String> {
-    self.manifests.iter().map(|mo| mo.architecture()).collect()
+    self
+      .manifests
+      .iter()
+      .filter_map(|mo| mo.platform.as_ref().map(|p| p.architecture.clone()))
+      .collect()
   }
 
   /// Get the digest for all the manifest images in the ManifestList
@@ -1,13 +1,20 @@
+use std::{fmt, sync::Arc};
+#[cfg(feature = "client")]
 use std::{iter::FromIterator, str::FromStr};
 
+#[cfg(feature = "client")]
 use log::{debug, trace};
+#[cfg(feature = "client")]
 use reqwest::{self, header, StatusCode, Url};
 
-use crate::{
-  errors::{Error, Result},
-  mediatypes,
-  v2::*,
-};
+#[cfg(feature = "client")]
+use crate::errors::Error;
+#[cfg(feature = "client")]
+use crate::v2::*;
+use crate::{errors::Result, mediatypes, v2::sha256_digest};
+
+mod validate;
+pub use self::validate::{validate_config, validate_manifest, Violation};
 
 mod manifest_schema1;
 pub use self::manifest_schema1::*;
@@ -17,6 +24,23 @@ pub use self::manifest_schema2::{
   ConfigBlob, ManifestList, ManifestObj, ManifestSchema2, ManifestSchema2Spec, Platform,
 };
 
+/// Metadata of a manifest, as returned by `Client::head_manifest`.
+#[cfg(feature = "client")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ManifestHead {
+  /// Digest of the manifest, if the registry reported one.
+  pub digest: Option<String>,
+  /// Media type of the manifest.
+  pub media_type: Option<String>,
+  /// Size of the manifest, in bytes.
+  pub size: Option<u64>,
+  /// Time the manifest was last pushed or retagged, from the `Last-Modified` header.
+  ///
+  /// Not all registries report it.
+  pub last_modified: Option<std::time::SystemTime>,
+}
+
+#[cfg(feature = "client")]
 impl Client {
   /// Fetch an image manifest.
   ///
@@ -34,16 +58,53 @@ impl Client {
   /// The name and reference parameters identify the image.
   /// The reference may be either a tag or digest.
   pub async fn get_manifest_and_ref(&self, name: &str, reference: &str) -> Result<(Manifest, Option<String>)> {
+    let by_digest = ContentDigest::try_new(reference).is_ok();
+    if by_digest {
+      if let Some(manifest) = self.cached_manifest(name, reference) {
+        trace!("manifest {}@{} served from cache", name, reference);
+        return Ok((manifest, Some(reference.to_string())));
+      }
+    }
+
+    let (manifest, content_digest, body) = self.fetch_manifest(name, reference).await?;
+
+    // Only cache payloads which actually match the requested digest.
+    if by_digest && digest_like(reference, &body) == reference {
+      if let Some(Ok(mut cache)) = self.manifest_cache.as_ref().map(|c| c.lock()) {
+        cache.insert(name, reference, manifest.clone(), body.len() as u64);
+      }
+    }
+    Ok((manifest, content_digest))
+  }
+
+  /// Fetch an image manifest by digest.
+  ///
+  /// Manifests addressed by digest are immutable, so the result is cached (see
+  /// `Config::manifest_cache_size`) and repeated calls are served without a request.
+  pub async fn get_manifest_by_digest(&self, name: &str, digest: &str) -> Result<Manifest> {
+    ContentDigest::try_new(digest)?;
+    self.get_manifest(name, digest).await
+  }
+
+  fn cached_manifest(&self, name: &str, digest: &str) -> Option<Manifest> {
+    let cached = self.manifest_cache.as_ref()?.lock().ok()?.get(name, digest);
+    self.stats.update(|stats| match cached {
+      Some(_) => stats.manifest_cache_hits += 1,
+      None => stats.manifest_cache_misses += 1,
+    });
+    cached
+  }
+
+  /// Fetch and parse a manifest, returning it with its digest and raw payload.
+  async fn fetch_manifest(&self, name: &str, reference: &str) -> Result<(Manifest, Option<String>, Vec<u8>)> {
     let url = self.build_url(name, reference)?;
 
-    let accept_headers = build_accept_headers(&self.accepted_types);
+    let accept_headers = self.manifest_accept_headers();
 
     let client_spare0 = self.clone();
 
     let res = self
-      .build_reqwest(Method::GET, url.clone())
-      .headers(accept_headers)
-      .send()
+      .send(self.build_reqwest(Method::GET, url.clone()).headers(accept_headers))
       .await?;
 
     let status = res.status();
@@ -64,65 +125,276 @@ impl Client {
     };
 
     let header_content_type = headers.get(header::CONTENT_TYPE);
+
+    let custom = match header_content_type {
+      Some(v) => self.custom_media_types.get(v.to_str()?),
+      None => None,
+    };
+    if let Some(deserialize) = custom {
+      let body = read_limited(res, self.limits.max_manifest_size, "manifest")
+        .await
+        .map_err(|e| self.deadline_error(e))?;
+      check_manifest_complexity(&body, &self.limits)?;
+      let value = deserialize(&body).map_err(ManifestError::CustomDeserializer)?;
+      return Ok((Manifest::Custom(value), content_digest, body));
+    }
+
+    let header_content_type = self.repair_content_type(header_content_type);
+    let header_content_type = header_content_type.as_ref();
     let media_type = evaluate_media_type(header_content_type, &url)?;
 
     trace!("content-type: {:?}, media-type: {:?}", header_content_type, media_type);
 
-    match media_type {
-      mediatypes::MediaTypes::ManifestV2S1Signed => Ok((
-        res.json::<ManifestSchema1Signed>().await.map(Manifest::S1Signed)?,
-        content_digest,
-      )),
-      mediatypes::MediaTypes::ManifestV2S2 | mediatypes::MediaTypes::OciImageManifest => {
-        let m = res.json::<ManifestSchema2Spec>().await?;
-        Ok((
-          m.fetch_config_blob(client_spare0, name.to_string())
-            .await
-            .map(Manifest::S2)?,
-          content_digest,
-        ))
+    let body = read_limited(res, self.limits.max_manifest_size, "manifest")
+      .await
+      .map_err(|e| self.deadline_error(e))?;
+    check_manifest_complexity(&body, &self.limits)?;
+
+    if self.strict_media_types {
+      let violations = validate_manifest(&body, &media_type);
+      if !violations.is_empty() {
+        let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        return Err(ManifestError::Invalid(violations.join("; ")).into());
       }
-      mediatypes::MediaTypes::ManifestList | mediatypes::MediaTypes::OciImageIndexV1 => {
-        Ok((res.json::<ManifestList>().await.map(Manifest::ML)?, content_digest))
+    }
+
+    let manifest = match media_type {
+      mediatypes::MediaTypes::ManifestV2S1Signed => self
+        .parse_payload::<ManifestSchema1Signed>(&body, Payload::Manifest)
+        .map(|mut m| {
+          m.raw = RawManifest::new(&body);
+          Manifest::S1Signed(m)
+        })?,
+      mediatypes::MediaTypes::ManifestV2S2 | mediatypes::MediaTypes::OciImageManifest => {
+        let mut m = self.parse_payload::<ManifestSchema2Spec>(&body, Payload::Manifest)?;
+        m.raw = RawManifest::new(&body);
+        m.fetch_config_blob(client_spare0, name.to_string())
+          .await
+          .map(Manifest::S2)?
       }
-      unsupported => Err(Error::UnsupportedMediaType(unsupported)),
+      mediatypes::MediaTypes::ManifestList | mediatypes::MediaTypes::OciImageIndexV1 => self
+        .parse_payload::<ManifestList>(&body, Payload::Manifest)
+        .map(|mut m| {
+          m.raw = RawManifest::new(&body);
+          Manifest::ML(m)
+        })?,
+      unsupported => return Err(Error::UnsupportedMediaType(unsupported)),
+    };
+    Ok((manifest, content_digest, body))
+  }
+
+  /// Fetch a manifest without parsing it.
+  ///
+  /// Returns the raw payload, its media type as reported by the registry and its digest,
+  /// if the registry returned one. `accept` overrides the media types sent in the `Accept` header.
+  pub async fn get_raw_manifest(
+    &self,
+    name: &str,
+    reference: &str,
+    accept: Option<&[&str]>,
+  ) -> Result<(Vec<u8>, String, Option<String>)> {
+    match self.fetch_raw_manifest(name, reference, accept, None).await? {
+      Conditional::Modified { value, .. } => Ok(value),
+      Conditional::NotModified => Err(Error::UnexpectedHttpStatus(StatusCode::NOT_MODIFIED)),
     }
   }
 
-  fn build_url(&self, name: &str, reference: &str) -> Result<Url> {
-    let ep = format!("{}/v2/{}/manifests/{}", self.base_url.clone(), name, reference);
-    reqwest::Url::parse(&ep).map_err(Error::from)
+  /// Fetch a manifest without parsing it, unless it did not change since `etag`.
+  ///
+  /// Registries usually return the quoted digest of the manifest as its entity tag, so a tag
+  /// can be revalidated with a single request which downloads nothing when it was not moved.
+  pub async fn get_raw_manifest_if_modified(
+    &self,
+    name: &str,
+    reference: &str,
+    etag: Option<&str>,
+  ) -> Result<Conditional<(Vec<u8>, String, Option<String>)>> {
+    self.fetch_raw_manifest(name, reference, None, etag).await
   }
 
-  /// Fetch content digest for a particular tag.
-  pub async fn get_manifestref(&self, name: &str, reference: &str) -> Result<Option<String>> {
+  async fn fetch_raw_manifest(
+    &self,
+    name: &str,
+    reference: &str,
+    accept: Option<&[&str]>,
+    etag: Option<&str>,
+  ) -> Result<Conditional<(Vec<u8>, String, Option<String>)>> {
     let url = self.build_url(name, reference)?;
 
-    let accept_headers = build_accept_headers(&self.accepted_types);
+    let mut accept_headers = match accept {
+      Some(types) => {
+        let value = header::HeaderValue::from_str(&types.join(", "))?;
+        header::HeaderMap::from_iter(vec![(header::ACCEPT, value)])
+      }
+      None => build_accept_headers(&self.accepted_types),
+    };
+    if let Some(etag) = etag {
+      accept_headers.insert(header::IF_NONE_MATCH, header::HeaderValue::from_str(etag)?);
+    }
 
     let res = self
-      .build_reqwest(Method::HEAD, url)
-      .headers(accept_headers)
-      .send()
+      .send(self.build_reqwest(Method::GET, url).headers(accept_headers))
       .await?;
 
     let status = res.status();
-    trace!("HEAD '{}' status: {:?}", res.url(), status);
+    trace!("GET '{}' status: {:?}", res.url(), status);
 
     match status {
       StatusCode::OK => {}
+      StatusCode::NOT_MODIFIED if etag.is_some() => return Ok(Conditional::NotModified),
       _ => return Err(ApiErrors::from(res).await),
     }
 
     let headers = res.headers();
     let content_digest = match headers.get("docker-content-digest") {
-      Some(content_digest_value) => Some(content_digest_value.to_str()?.to_string()),
-      None => {
-        debug!("cannot find manifestref in headers");
-        None
-      }
+      Some(v) => Some(v.to_str()?.to_string()),
+      None => None,
+    };
+    let media_type = match headers.get(header::CONTENT_TYPE) {
+      Some(v) => v.to_str()?.to_string(),
+      None => return Err(Error::MissingHeader("Content-Type")),
+    };
+    let new_etag = match headers.get(header::ETAG) {
+      Some(v) => Some(v.to_str()?.to_string()),
+      None => None,
+    };
+
+    let body = read_limited(res, self.limits.max_manifest_size, "manifest")
+      .await
+      .map_err(|e| self.deadline_error(e))?;
+    check_manifest_complexity(&body, &self.limits)?;
+    let content_digest = content_digest.or_else(|| Some(sha256_digest(&body)));
+    Ok(Conditional::Modified {
+      value: (body, media_type, content_digest),
+      etag: new_etag,
+    })
+  }
+
+  /// Delete a manifest.
+  ///
+  /// The reference should be a digest: most registries do not support deleting by tag. With
+  /// `Config::soft_fail`, registries which do not support deletion fail it with
+  /// `Error::Unsupported`.
+  pub async fn delete_manifest(&self, name: &str, reference: &str) -> Result<()> {
+    self.check_tag_mutability(name, reference, TagOperation::Delete).await?;
+    let url = self.build_url(name, reference)?;
+
+    let res = self.send(self.build_reqwest(Method::DELETE, url)).await?;
+
+    let status = res.status();
+    trace!("DELETE '{}' status: {:?}", res.url(), status);
+
+    match status {
+      StatusCode::ACCEPTED | StatusCode::OK | StatusCode::NO_CONTENT => Ok(()),
+      _ => Err(self.soft_fail(OptionalFeature::Deletion, status, ApiErrors::from(res).await)),
+    }
+  }
+
+  /// Upload a manifest.
+  ///
+  /// The reference may be either a tag or the digest of `body`. Returns the digest of the
+  /// manifest as reported by the registry.
+  pub async fn put_manifest(&self, name: &str, reference: &str, media_type: &str, body: &[u8]) -> Result<String> {
+    let (digest, _) = self
+      .put_manifest_with_headers(name, reference, media_type, body)
+      .await?;
+    Ok(digest)
+  }
+
+  /// Upload a manifest as `put_manifest` does, also returning the headers of the response.
+  pub(crate) async fn put_manifest_with_headers(
+    &self,
+    name: &str,
+    reference: &str,
+    media_type: &str,
+    body: &[u8],
+  ) -> Result<(String, header::HeaderMap)> {
+    if ContentDigest::try_new(reference).is_err() {
+      crate::reference::Tag::parse(reference)?;
+    }
+    self.check_tag_mutability(name, reference, TagOperation::Push).await?;
+    let url = self.build_url(name, reference)?;
+
+    let res = self
+      .send(
+        self
+          .build_reqwest(Method::PUT, url)
+          .header(header::CONTENT_TYPE, media_type)
+          .body(body.to_vec()),
+      )
+      .await?;
+
+    let status = res.status();
+    trace!("PUT '{}' status: {:?}", res.url(), status);
+
+    match status {
+      StatusCode::CREATED | StatusCode::OK => {}
+      _ => return Err(ApiErrors::from(res).await),
+    }
+
+    let digest = match res.headers().get("docker-content-digest") {
+      Some(v) => v.to_str()?.to_string(),
+      None => sha256_digest(body),
     };
-    Ok(content_digest)
+    Ok((digest, res.headers().clone()))
+  }
+
+  /// Build the `Accept` header for manifest requests, including registered custom media types.
+  fn manifest_accept_headers(&self) -> header::HeaderMap {
+    let mut headers = build_accept_headers(&self.accepted_types);
+    let custom: Vec<&str> = self.custom_media_types.media_types().collect();
+    if !custom.is_empty() {
+      let mut accept = headers[header::ACCEPT].to_str().unwrap_or_default().to_string();
+      for media_type in custom {
+        accept.push(',');
+        accept.push_str(media_type);
+      }
+      if let Ok(value) = header::HeaderValue::from_str(&accept) {
+        headers.insert(header::ACCEPT, value);
+      }
+    }
+    headers
+  }
+
+  fn build_url(&self, name: &str, reference: &str) -> Result<Url> {
+    self.repository_url(name, &format!("manifests/{}", reference))
+  }
+
+  /// Fetch content digest for a particular tag.
+  pub async fn get_manifestref(&self, name: &str, reference: &str) -> Result<Option<String>> {
+    Ok(self.head_manifest(name, reference).await?.digest)
+  }
+
+  /// Fetch the metadata of a manifest with a `HEAD` request, without downloading it.
+  pub async fn head_manifest(&self, name: &str, reference: &str) -> Result<ManifestHead> {
+    let res = self.send_manifest_head(name, reference).await?;
+    match res.status() {
+      StatusCode::OK => manifest_head(res.headers()),
+      _ => Err(ApiErrors::from(res).await),
+    }
+  }
+
+  /// Like `head_manifest`, but `None` if the manifest does not exist.
+  pub(crate) async fn find_manifest(&self, name: &str, reference: &str) -> Result<Option<ManifestHead>> {
+    let res = self.send_manifest_head(name, reference).await?;
+    match res.status() {
+      StatusCode::OK => Ok(Some(manifest_head(res.headers())?)),
+      StatusCode::NOT_FOUND => Ok(None),
+      _ => Err(ApiErrors::from(res).await),
+    }
+  }
+
+  async fn send_manifest_head(&self, name: &str, reference: &str) -> Result<Response> {
+    let url = self.build_url(name, reference)?;
+
+    let accept_headers = self.manifest_accept_headers();
+
+    let res = self
+      .send(self.build_reqwest(Method::HEAD, url).headers(accept_headers))
+      .await?;
+
+    trace!("HEAD '{}' status: {:?}", res.url(), res.status());
+    Ok(res)
   }
 
   /// Check if an image manifest exists.
@@ -154,11 +426,8 @@ impl Client {
     trace!("HEAD {:?}", url);
 
     let r = self
-      .build_reqwest(Method::HEAD, url.clone())
-      .headers(accept_headers)
-      .send()
-      .await
-      .map_err(Error::from)?;
+      .send(self.build_reqwest(Method::HEAD, url.clone()).headers(accept_headers))
+      .await?;
 
     let status = r.status();
 
@@ -176,6 +445,38 @@ impl Client {
   }
 }
 
+/// Metadata of a manifest from the headers of a response to `HEAD`.
+#[cfg(feature = "client")]
+fn manifest_head(headers: &header::HeaderMap) -> Result<ManifestHead> {
+  let content_digest = match headers.get("docker-content-digest") {
+    Some(content_digest_value) => Some(content_digest_value.to_str()?.to_string()),
+    None => {
+      debug!("cannot find manifestref in headers");
+      None
+    }
+  };
+  let media_type = match headers.get(header::CONTENT_TYPE) {
+    Some(v) => Some(v.to_str()?.to_string()),
+    None => None,
+  };
+  // The body of a response to `HEAD` is empty, its length is only in the header.
+  let size = headers
+    .get(header::CONTENT_LENGTH)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.parse().ok());
+  let last_modified = headers
+    .get(header::LAST_MODIFIED)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| httpdate::parse_http_date(v).ok());
+  Ok(ManifestHead {
+    digest: content_digest,
+    media_type,
+    size,
+    last_modified,
+  })
+}
+
+#[cfg(feature = "client")]
 fn to_mimes(v: &[&str]) -> Vec<mime::Mime> {
   let res = v
     .iter()
@@ -191,6 +492,7 @@ fn to_mimes(v: &[&str]) -> Vec<mime::Mime> {
 }
 
 // Evaluate the `MediaTypes` from the the request header.
+#[cfg(feature = "client")]
 fn evaluate_media_type(
   content_type: Option<&reqwest::header::HeaderValue>,
   url: &Url,
@@ -229,6 +531,7 @@ fn evaluate_media_type(
   }
 }
 
+#[cfg(feature = "client")]
 fn build_accept_headers(accepted_types: &[(MediaTypes, Option<f64>)]) -> header::HeaderMap {
   let accepted_types_string = accepted_types
     .iter()
@@ -252,15 +555,45 @@ fn build_accept_headers(accepted_types: &[(MediaTypes, Option<f64>)]) -> header:
   )])
 }
 
+/// Bytes of a manifest as the registry served them, kept to compute its digest.
+#[derive(Clone, Default)]
+pub(crate) struct RawManifest(Option<Arc<[u8]>>);
+
+impl RawManifest {
+  #[cfg(feature = "client")]
+  fn new(body: &[u8]) -> Self {
+    Self(Some(body.into()))
+  }
+
+  pub(crate) fn get(&self) -> Option<&[u8]> {
+    self.0.as_deref()
+  }
+}
+
+impl fmt::Debug for RawManifest {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.0 {
+      Some(raw) => write!(f, "RawManifest({} bytes)", raw.len()),
+      None => f.write_str("RawManifest(None)"),
+    }
+  }
+}
+
 /// Umbrella type for common actions on the different manifest schema types
-#[derive(Debug)]
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+// Boxing the schema 2 variant would break matching on it, and manifests are not stored in bulk.
+#[allow(clippy::large_enum_variant)]
 pub enum Manifest {
   S1Signed(manifest_schema1::ManifestSchema1Signed),
   S2(manifest_schema2::ManifestSchema2),
   ML(manifest_schema2::ManifestList),
+  /// A manifest of a media type registered with `Config::register_media_type`.
+  Custom(serde_json::Value),
 }
 
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum ManifestError {
   #[error("no architecture in manifest")]
   NoArchitecture,
@@ -270,9 +603,84 @@ pub enum ManifestError {
   LayerDigestsUnsupported(String),
   #[error("manifest {0} does not support the 'architecture' method")]
   ArchitectureNotSupported(String),
+  #[error("manifest is invalid: {0}")]
+  Invalid(String),
+  #[cfg(feature = "client")]
+  #[error("custom manifest deserializer failed: {0}")]
+  CustomDeserializer(HookError),
 }
 
 impl Manifest {
+  /// Validate this manifest against the schema for its media type.
+  ///
+  /// Returns every violation found; an empty list means the manifest is valid. This is useful
+  /// for checking manifests generated locally before pushing them.
+  pub fn validate(&self) -> Vec<Violation> {
+    let (value, media_type) = match self {
+      Manifest::S1Signed(m) => (serde_json::to_value(m), mediatypes::MediaTypes::ManifestV2S1Signed),
+      Manifest::S2(m) => (
+        serde_json::to_value(&m.manifest_spec),
+        m.manifest_spec
+          .media_type()
+          .parse()
+          .unwrap_or(mediatypes::MediaTypes::ManifestV2S2),
+      ),
+      Manifest::ML(m) => (
+        serde_json::to_value(m),
+        m.media_type().parse().unwrap_or(mediatypes::MediaTypes::ManifestList),
+      ),
+      // Custom media types have no known schema.
+      Manifest::Custom(_) => return Vec::new(),
+    };
+    match value {
+      Ok(value) => validate::validate_manifest_value(&value, &media_type),
+      Err(e) => vec![Violation {
+        path: String::new(),
+        message: format!("cannot serialize manifest: {}", e),
+      }],
+    }
+  }
+
+  /// The bytes of this manifest, to push with `Client::put_manifest`.
+  ///
+  /// Manifests pulled with `Client::get_manifest` keep the bytes the registry served, unknown
+  /// fields and formatting included, until they are changed, e.g. with
+  /// `ManifestList::set_manifests`. Other manifests are serialized to compact JSON, with the
+  /// fields known to this crate first, then the other fields they were deserialized with,
+  /// e.g. `annotations` or `subject`, and keys of custom manifests, sorted.
+  pub fn to_bytes(&self) -> Result<Vec<u8>> {
+    let bytes = match self {
+      Manifest::S1Signed(m) => match m.raw.get() {
+        Some(raw) => raw.to_vec(),
+        None => serde_json::to_vec(m)?,
+      },
+      Manifest::S2(m) => match m.manifest_spec.raw.get() {
+        Some(raw) => raw.to_vec(),
+        None => serde_json::to_vec(&m.manifest_spec)?,
+      },
+      Manifest::ML(m) => match m.raw.get() {
+        Some(raw) => raw.to_vec(),
+        None => serde_json::to_vec(m)?,
+      },
+      Manifest::Custom(value) => serde_json::to_vec(value)?,
+    };
+    Ok(bytes)
+  }
+
+  /// The digest of this manifest, `sha256:<hex>`, computed as the registry does.
+  ///
+  /// This is the digest of [`Manifest::to_bytes`], which the registry returns once they are
+  /// pushed, so tools can pin it beforehand. Schema 1 manifests are the exception: their digest
+  /// is that of their payload without the signatures, see
+  /// [`ManifestSchema1Signed::payload`].
+  pub fn digest(&self) -> Result<String> {
+    let bytes = match self {
+      Manifest::S1Signed(m) => m.payload()?,
+      _ => self.to_bytes()?,
+    };
+    Ok(sha256_digest(&bytes))
+  }
+
   /// List digests of all layers referenced by this manifest, if available.
   /// For ManifestList, returns the digests of all the manifest list images.
   ///
@@ -317,11 +725,12 @@ impl Manifest {
       Manifest::S1Signed(m) => Ok([m.architecture.clone()].to_vec()),
       Manifest::S2(m) => Ok([m.architecture()].to_vec()),
       Manifest::ML(m) => Ok(m.architectures()),
+      Manifest::Custom(_) => Err(ManifestError::ArchitectureNotSupported(format!("{:?}", self)).into()),
     }
   }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "client"))]
 mod tests {
   use test_case::test_case;
 
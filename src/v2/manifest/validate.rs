@@ -0,0 +1,472 @@
+use std::{collections::HashMap, fmt, sync::OnceLock};
+
+use regex_lite::Regex;
+use serde_json::{Map, Value};
+
+use crate::mediatypes::MediaTypes;
+
+/// Digest grammar from the OCI image-spec descriptor definition.
+const DIGEST_REGEX: &str = r"^[a-z0-9]+(?:[.+_-][a-z0-9]+)*:[a-zA-Z0-9=_-]+$";
+
+/// Largest size a descriptor may declare, as JSON integers above 2^53 are not portable.
+const MAX_DESCRIPTOR_SIZE: u64 = (1 << 53) - 1;
+
+/// JSON schemas of the OCI image-spec v1.1.0, vendored from its `schema` directory.
+const SCHEMAS: [(&str, &str); 6] = [
+  ("config-schema.json", include_str!("schemas/config-schema.json")),
+  (
+    "content-descriptor.json",
+    include_str!("schemas/content-descriptor.json"),
+  ),
+  ("defs-descriptor.json", include_str!("schemas/defs-descriptor.json")),
+  ("defs.json", include_str!("schemas/defs.json")),
+  (
+    "image-index-schema.json",
+    include_str!("schemas/image-index-schema.json"),
+  ),
+  (
+    "image-manifest-schema.json",
+    include_str!("schemas/image-manifest-schema.json"),
+  ),
+];
+/// A single schema violation found while validating a manifest or config.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+  /// JSON path of the offending field, e.g. `layers[2].digest`.
+  pub path: String,
+  /// Description of the problem.
+  pub message: String,
+}
+
+impl Violation {
+  fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+    Violation {
+      path: path.into(),
+      message: message.into(),
+    }
+  }
+}
+
+impl fmt::Display for Violation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.path.is_empty() {
+      write!(f, "{}", self.message)
+    } else {
+      write!(f, "{}: {}", self.path, self.message)
+    }
+  }
+}
+
+/// Validate a raw manifest or index payload against the schema for `media_type`.
+///
+/// Image manifests and indexes are checked against the vendored OCI image-spec
+/// `image-manifest-schema.json` and `image-index-schema.json` schemas, which Docker schema 2
+/// manifests and manifest lists follow too, plus rules the schemas cannot express, e.g. digest
+/// lengths. An empty result means the payload is valid.
+pub fn validate_manifest(body: &[u8], media_type: &MediaTypes) -> Vec<Violation> {
+  match serde_json::from_slice::<Value>(body) {
+    Ok(value) => validate_manifest_value(&value, media_type),
+    Err(e) => vec![Violation::new("", format!("payload is not valid JSON: {}", e))],
+  }
+}
+
+/// Validate a raw image configuration against the vendored OCI image-spec `config-schema.json`
+/// schema.
+pub fn validate_config(body: &[u8]) -> Vec<Violation> {
+  let value: Value = match serde_json::from_slice(body) {
+    Ok(v) => v,
+    Err(e) => return vec![Violation::new("", format!("payload is not valid JSON: {}", e))],
+  };
+
+  let mut violations = schemas().validate("config-schema.json", &value);
+  let diff_ids = value.pointer("/rootfs/diff_ids").and_then(Value::as_array);
+  for (i, id) in diff_ids.into_iter().flatten().enumerate() {
+    if id.is_string() {
+      validate_digest(&format!("rootfs.diff_ids[{}]", i), id, &mut violations);
+    }
+  }
+  violations
+}
+
+pub(crate) fn validate_manifest_value(value: &Value, media_type: &MediaTypes) -> Vec<Violation> {
+  let obj = match value.as_object() {
+    Some(o) => o,
+    None => return vec![Violation::new("", "payload is not a JSON object")],
+  };
+  let mut violations = Vec::new();
+
+  match obj.get("mediaType") {
+    Some(Value::String(declared)) if declared != &media_type.to_string() => violations.push(Violation::new(
+      "mediaType",
+      format!("'{}' does not match '{}'", declared, media_type),
+    )),
+    None if matches!(media_type, MediaTypes::ManifestV2S2 | MediaTypes::ManifestList) => {
+      violations.push(Violation::new("mediaType", "is required"))
+    }
+    _ => {}
+  }
+
+  match media_type {
+    MediaTypes::ManifestV2S2 | MediaTypes::OciImageManifest => {
+      validate_descriptor("config", obj.get("config"), &mut violations);
+      validate_descriptor("subject", obj.get("subject"), &mut violations);
+      validate_descriptors("layers", obj.get("layers"), &mut violations);
+      if obj.contains_key("manifests") {
+        violations.push(Violation::new("manifests", "not allowed in an image manifest"));
+      }
+      violations.extend(schemas().validate("image-manifest-schema.json", value));
+    }
+    MediaTypes::ManifestList | MediaTypes::OciImageIndexV1 => {
+      validate_descriptor("subject", obj.get("subject"), &mut violations);
+      validate_descriptors("manifests", obj.get("manifests"), &mut violations);
+      for field in ["config", "layers"] {
+        if obj.contains_key(field) {
+          violations.push(Violation::new(field, "not allowed in a manifest list"));
+        }
+      }
+      violations.extend(schemas().validate("image-index-schema.json", value));
+    }
+    _ => {
+      let expected_schema = match media_type {
+        MediaTypes::ManifestV2S1 | MediaTypes::ManifestV2S1Signed => 1,
+        _ => 2,
+      };
+      if obj.get("schemaVersion").and_then(Value::as_u64) != Some(expected_schema) {
+        violations.push(Violation::new("schemaVersion", format!("must be {}", expected_schema)));
+      }
+    }
+  }
+
+  violations
+}
+
+fn validate_descriptors(path: &str, value: Option<&Value>, violations: &mut Vec<Violation>) {
+  let descriptors = value.and_then(Value::as_array).into_iter().flatten();
+  for (i, d) in descriptors.enumerate() {
+    validate_descriptor(&format!("{}[{}]", path, i), Some(d), violations);
+  }
+}
+
+/// Check the descriptor rules the schemas cannot express; its structure is left to the schemas.
+fn validate_descriptor(path: &str, value: Option<&Value>, violations: &mut Vec<Violation>) {
+  let obj = match value.and_then(Value::as_object) {
+    Some(o) => o,
+    None => return,
+  };
+
+  if let Some(digest @ Value::String(d)) = obj.get("digest") {
+    if digest_regex().is_match(d) {
+      validate_digest(&format!("{}.digest", path), digest, violations);
+    }
+  }
+
+  if let Some(Value::Number(size)) = obj.get("size") {
+    match size.as_u64() {
+      Some(size) if size > MAX_DESCRIPTOR_SIZE => {
+        violations.push(Violation::new(format!("{}.size", path), "is out of bounds"))
+      }
+      Some(_) => {}
+      None => violations.push(Violation::new(
+        format!("{}.size", path),
+        "must be a non-negative integer",
+      )),
+    }
+  }
+}
+
+fn validate_digest(path: &str, value: &Value, violations: &mut Vec<Violation>) {
+  let digest = match value.as_str() {
+    Some(d) => d,
+    None => return violations.push(Violation::new(path, "must be a string")),
+  };
+  if !digest_regex().is_match(digest) {
+    violations.push(Violation::new(path, format!("'{}' is malformed", digest)));
+  } else if let Some((algorithm @ ("sha256" | "sha512"), hex)) = digest.split_once(':') {
+    let len = if algorithm == "sha256" { 64 } else { 128 };
+    if hex.len() != len || !hex.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+      violations.push(Violation::new(
+        path,
+        format!("'{}' is not a valid {} digest", digest, algorithm),
+      ));
+    }
+  }
+}
+
+fn digest_regex() -> &'static Regex {
+  static DIGEST: OnceLock<Regex> = OnceLock::new();
+  DIGEST.get_or_init(|| Regex::new(DIGEST_REGEX).expect("hardcoded regex is invalid"))
+}
+
+fn schemas() -> &'static Schemas {
+  static SCHEMAS: OnceLock<Schemas> = OnceLock::new();
+  SCHEMAS.get_or_init(Schemas::load)
+}
+
+/// The vendored schemas, with their patterns compiled once.
+///
+/// Only the draft-04 keywords used by the image-spec schemas are evaluated: `$ref`, `type`,
+/// `enum`, `pattern`, `minimum`, `maximum`, `required`, `properties`, `patternProperties`,
+/// `items` and `oneOf`; annotations such as `format` are ignored.
+struct Schemas {
+  documents: HashMap<&'static str, Value>,
+  patterns: HashMap<String, Regex>,
+}
+
+impl Schemas {
+  fn load() -> Self {
+    let documents: HashMap<_, Value> = SCHEMAS
+      .iter()
+      .map(|(name, body)| (*name, serde_json::from_str(body).expect("vendored schema is invalid")))
+      .collect();
+    let mut patterns = HashMap::new();
+    for document in documents.values() {
+      collect_patterns(document, &mut patterns);
+    }
+    Schemas { documents, patterns }
+  }
+
+  fn validate(&self, document: &'static str, value: &Value) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    self.check(document, &self.documents[document], value, "", &mut violations);
+    violations
+  }
+
+  fn check(&self, document: &'static str, schema: &Value, value: &Value, path: &str, violations: &mut Vec<Violation>) {
+    // In draft-04, keywords next to `$ref` are ignored.
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+      let (document, schema) = self.resolve(document, reference);
+      return self.check(document, schema, value, path, violations);
+    }
+
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+      if !has_type(value, expected) {
+        return violations.push(Violation::new(path, format!("must be {}", type_name(expected))));
+      }
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+      if !allowed.contains(value) {
+        violations.push(Violation::new(
+          path,
+          format!("must be one of {}", Value::from(allowed.clone())),
+        ));
+      }
+    }
+    if let Some(alternatives) = schema.get("oneOf").and_then(Value::as_array) {
+      let matching = alternatives
+        .iter()
+        .filter(|alternative| {
+          let mut found = Vec::new();
+          self.check(document, alternative, value, path, &mut found);
+          found.is_empty()
+        })
+        .count();
+      if matching != 1 {
+        violations.push(Violation::new(path, "must match exactly one of the allowed schemas"));
+      }
+    }
+
+    match value {
+      Value::String(s) => {
+        if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+          if !self.patterns[pattern].is_match(s) {
+            violations.push(Violation::new(path, format!("'{}' does not match '{}'", s, pattern)));
+          }
+        }
+      }
+      Value::Number(n) => {
+        let n = n.as_f64().unwrap_or_default();
+        if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+          if n < minimum {
+            violations.push(Violation::new(path, format!("must be at least {}", minimum)));
+          }
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+          if n > maximum {
+            violations.push(Violation::new(path, format!("must be at most {}", maximum)));
+          }
+        }
+      }
+      Value::Array(items) => {
+        if let Some(item_schema) = schema.get("items") {
+          for (i, item) in items.iter().enumerate() {
+            self.check(document, item_schema, item, &format!("{}[{}]", path, i), violations);
+          }
+        }
+      }
+      Value::Object(obj) => self.check_object(document, schema, obj, path, violations),
+      Value::Null | Value::Bool(_) => {}
+    }
+  }
+
+  fn check_object(
+    &self,
+    document: &'static str,
+    schema: &Value,
+    obj: &Map<String, Value>,
+    path: &str,
+    violations: &mut Vec<Violation>,
+  ) {
+    let required = schema.get("required").and_then(Value::as_array).into_iter().flatten();
+    for field in required.filter_map(Value::as_str) {
+      if !obj.contains_key(field) {
+        violations.push(Violation::new(join(path, field), "is required"));
+      }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let pattern_properties = schema.get("patternProperties").and_then(Value::as_object);
+    for (key, value) in obj {
+      if let Some(property) = properties.and_then(|p| p.get(key)) {
+        self.check(document, property, value, &join(path, key), violations);
+      }
+      for (pattern, property) in pattern_properties.into_iter().flatten() {
+        if self.patterns[pattern.as_str()].is_match(key) {
+          self.check(document, property, value, &join(path, key), violations);
+        }
+      }
+    }
+  }
+
+  /// Resolve a reference such as `defs.json#/definitions/int64`, relative to `document`.
+  fn resolve(&self, document: &'static str, reference: &str) -> (&'static str, &Value) {
+    let (name, pointer) = reference.split_once('#').unwrap_or((reference, ""));
+    let (name, target) = match name {
+      "" => (document, &self.documents[document]),
+      name => {
+        let (name, target) = self
+          .documents
+          .get_key_value(name)
+          .expect("vendored schema references a missing document");
+        (*name, target)
+      }
+    };
+    let schema = target
+      .pointer(pointer)
+      .expect("vendored schema references a missing definition");
+    (name, schema)
+  }
+}
+
+fn collect_patterns(schema: &Value, patterns: &mut HashMap<String, Regex>) {
+  match schema {
+    Value::Object(obj) => {
+      let mut sources: Vec<&str> = obj.get("pattern").and_then(Value::as_str).into_iter().collect();
+      if let Some(pattern_properties) = obj.get("patternProperties").and_then(Value::as_object) {
+        sources.extend(pattern_properties.keys().map(String::as_str));
+      }
+      for source in sources {
+        let regex = Regex::new(source).expect("vendored schema pattern is invalid");
+        patterns.insert(source.to_string(), regex);
+      }
+      obj.values().for_each(|v| collect_patterns(v, patterns));
+    }
+    Value::Array(items) => items.iter().for_each(|v| collect_patterns(v, patterns)),
+    _ => {}
+  }
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+  match expected {
+    "array" => value.is_array(),
+    "boolean" => value.is_boolean(),
+    "integer" => value.is_i64() || value.is_u64(),
+    "null" => value.is_null(),
+    "number" => value.is_number(),
+    "object" => value.is_object(),
+    "string" => value.is_string(),
+    _ => true,
+  }
+}
+
+fn type_name(expected: &str) -> String {
+  match expected {
+    "null" => "null".to_string(),
+    "array" | "integer" | "object" => format!("an {}", expected),
+    _ => format!("a {}", expected),
+  }
+}
+
+fn join(path: &str, key: &str) -> String {
+  match path {
+    "" => key.to_string(),
+    path => format!("{}.{}", path, key),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DIGEST: &str = "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab";
+
+  #[test]
+  fn valid_oci_manifest() {
+    let body = serde_json::json!({
+      "schemaVersion": 2,
+      "config": { "mediaType": "application/vnd.oci.image.config.v1+json", "size": 8, "digest": DIGEST },
+      "layers": [{ "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip", "size": 8, "digest": DIGEST }],
+    });
+    assert!(validate_manifest(body.to_string().as_bytes(), &MediaTypes::OciImageManifest).is_empty());
+  }
+
+  #[test]
+  fn invalid_descriptors() {
+    let body = serde_json::json!({
+      "schemaVersion": 2,
+      "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+      "config": { "mediaType": "application/vnd.docker.container.image.v1+json", "size": -1, "digest": "sha256:abc" },
+      "layers": {},
+    });
+    let paths: Vec<String> = validate_manifest(body.to_string().as_bytes(), &MediaTypes::ManifestV2S2)
+      .into_iter()
+      .map(|v| v.path)
+      .collect();
+    assert_eq!(paths, vec!["config.digest", "config.size", "layers"]);
+  }
+
+  #[test]
+  fn config_requires_rootfs() {
+    let body = br#"{"architecture": "amd64", "os": "linux"}"#;
+    assert_eq!(validate_config(body), vec![Violation::new("rootfs", "is required")]);
+  }
+
+  #[test]
+  fn descriptor_annotations_paths() {
+    let body = serde_json::json!({
+      "schemaVersion": 2,
+      "config": { "mediaType": "application/vnd.oci.image.config.v1+json", "size": 8, "digest": DIGEST },
+      "layers": [{
+        "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+        "size": 8,
+        "digest": DIGEST,
+        "annotations": { "org.example.count": 3 },
+      }],
+      "annotations": { "org.example.name": "ok" },
+    });
+    assert_eq!(
+      validate_manifest(body.to_string().as_bytes(), &MediaTypes::OciImageManifest),
+      vec![Violation::new(
+        "layers[0].annotations.org.example.count",
+        "must be a string"
+      )]
+    );
+  }
+
+  #[test]
+  fn index_platform_and_media_type() {
+    let body = serde_json::json!({
+      "schemaVersion": 2,
+      "manifests": [{
+        "mediaType": "not a media type",
+        "size": 8,
+        "digest": DIGEST,
+        "platform": { "architecture": "amd64" },
+      }],
+    });
+    let paths: Vec<String> = validate_manifest(body.to_string().as_bytes(), &MediaTypes::OciImageIndexV1)
+      .into_iter()
+      .map(|v| v.path)
+      .collect();
+    assert_eq!(paths, vec!["manifests[0].mediaType", "manifests[0].platform.os"]);
+  }
+}
@@ -26,46 +26,309 @@
 //! ```
 
 use std::fmt;
+#[cfg(feature = "client")]
+use std::sync::{Arc, Mutex};
 
+#[cfg(feature = "client")]
 use futures::prelude::*;
-use log::trace;
+#[cfg(feature = "client")]
+use log::{debug, trace};
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+use reqwest::ResponseBuilderExt;
+#[cfg(feature = "client")]
 use reqwest::{Method, Response, StatusCode, Url};
 use serde::{Deserialize, Serialize};
 
-use crate::{
-  errors::{self, *},
-  mediatypes::MediaTypes,
-};
+use crate::errors;
+#[cfg(feature = "client")]
+use crate::{errors::*, mediatypes::MediaTypes};
 
+#[cfg(feature = "client")]
 mod config;
+#[cfg(feature = "client")]
 pub use self::config::Config;
 
+#[cfg(feature = "client")]
 mod catalog;
 
+#[cfg(feature = "client")]
+mod dns;
+#[cfg(feature = "client")]
+pub use self::dns::IpPreference;
+
+#[cfg(feature = "client")]
+mod http_version;
+#[cfg(feature = "client")]
+pub use self::http_version::HttpVersion;
+
+#[cfg(feature = "client")]
 mod auth;
+#[cfg(feature = "client")]
 pub use auth::WwwHeaderParseError;
 
+#[cfg(feature = "client")]
+mod token_store;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub use self::token_store::FileTokenStore;
+#[cfg(feature = "client")]
+pub use self::token_store::{StoredToken, TokenStore};
+
+#[cfg(feature = "client")]
+mod credential_provider;
+#[cfg(feature = "client")]
+use self::credential_provider::{set_basic_credentials, CredentialRenewal};
+#[cfg(feature = "client")]
+pub use self::credential_provider::{CredentialFuture, CredentialProvider};
+
+#[cfg(feature = "client")]
+mod device_login;
+#[cfg(feature = "client")]
+pub use self::device_login::{DeviceAuthorization, DeviceLogin, DevicePrompt};
+
 pub mod manifest;
 
+#[cfg(feature = "client")]
+mod extensions;
+#[cfg(feature = "client")]
+pub use self::extensions::RegistryExtension;
+
+#[cfg(feature = "client")]
+mod identities;
+#[cfg(feature = "client")]
+use self::identities::Identities;
+
+#[cfg(feature = "client")]
+mod leniency;
+#[cfg(feature = "client")]
+pub use self::leniency::ParseWarning;
+#[cfg(feature = "client")]
+pub(crate) use self::leniency::{ParseWarnings, Payload};
+
+#[cfg(feature = "client")]
+mod soft_fail;
+#[cfg(feature = "client")]
+pub use self::soft_fail::OptionalFeature;
+#[cfg(feature = "client")]
+pub(crate) use self::soft_fail::UnsupportedFeatures;
+
+#[cfg(feature = "client")]
+mod mutability;
+#[cfg(feature = "client")]
+pub(crate) use self::mutability::TagOperation;
+#[cfg(feature = "client")]
+pub use self::mutability::{MutabilityProvider, TagMutability};
+
+#[cfg(feature = "client")]
+mod search;
+#[cfg(feature = "client")]
+pub use self::search::{SearchProvider, SearchResult};
+
+#[cfg(feature = "client")]
 mod tags;
+#[cfg(feature = "client")]
+pub(crate) use self::tags::parse_link;
 
+#[cfg(feature = "client")]
 mod blobs;
 
+#[cfg(feature = "client")]
+mod uploads;
+#[cfg(feature = "client")]
+pub use self::uploads::{UploadJournal, UploadRange, UploadSession};
+
+#[cfg(feature = "client")]
+mod hooks;
+#[cfg(feature = "client")]
+pub(crate) use self::hooks::CustomMediaTypes;
+#[cfg(feature = "client")]
+pub use self::hooks::{
+  HookError, InterceptedRequest, InterceptedResponse, Interceptor, ManifestDeserializer, RequestSigner, SigningRequest,
+};
+
+#[cfg(feature = "client")]
+mod conditional;
+#[cfg(feature = "client")]
+pub use self::conditional::Conditional;
+
+#[cfg(feature = "client")]
+mod profile;
+#[cfg(feature = "client")]
+pub(crate) use self::profile::DetectedProfile;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+use self::profile::ProfiledRequest;
+#[cfg(feature = "client")]
+pub use self::profile::RegistryProfile;
+
+#[cfg(feature = "client")]
+mod pinning;
+
+#[cfg(feature = "client")]
+mod redirect;
+#[cfg(feature = "client")]
+pub use self::redirect::{RedirectError, RedirectPolicy};
+
+#[cfg(feature = "client")]
+mod retry;
+#[cfg(feature = "client")]
+pub use self::retry::RetryPolicy;
+
+#[cfg(feature = "client")]
+mod lazy;
+#[cfg(feature = "client")]
+pub use self::lazy::{
+  LazyLayer, TocEntry, TocFormat, ESTARGZ_TOC_DIGEST_ANNOTATION, ZSTD_CHUNKED_TOC_DIGEST_ANNOTATION,
+};
+
+#[cfg(feature = "client")]
+mod ranged;
+#[cfg(feature = "client")]
+use self::ranged::Download;
+#[cfg(feature = "client")]
+pub use self::ranged::ParallelDownloads;
+
+#[cfg(feature = "client")]
+mod coalesce;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub(crate) use self::coalesce::RequestCoalescer;
+#[cfg(feature = "client")]
+pub(crate) use self::coalesce::UploadCoalescer;
+
+#[cfg(feature = "client")]
+mod signed_urls;
+#[cfg(feature = "client")]
+pub(crate) use self::signed_urls::SignedUrls;
+
+#[cfg(feature = "client")]
+mod stats;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+use self::stats::count_received;
+#[cfg(feature = "client")]
+use self::stats::request_body_len;
+#[cfg(feature = "client")]
+pub(crate) use self::stats::StatsCollector;
+#[cfg(feature = "client")]
+pub use self::stats::{ClientStats, EndpointClass, TransferStats};
+
+#[cfg(feature = "client")]
+mod cache;
+#[cfg(feature = "client")]
+pub(crate) use self::cache::ManifestCache;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub(crate) use self::cache::{BufferedResponse, ResponseCache};
+
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+mod file_upload;
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+pub use self::file_upload::FileUploadOptions;
+
+mod descriptor;
+pub use self::descriptor::Descriptor;
+
+#[cfg(feature = "client")]
+mod referrers;
+#[cfg(feature = "client")]
+pub use self::referrers::{
+  NotationSignature, ReferrerFilter, EMPTY_CONFIG_MEDIA_TYPE, NOTATION_SIGNATURE_ARTIFACT_TYPE,
+};
+
+#[cfg(feature = "client")]
+mod rate_limit;
+#[cfg(feature = "client")]
+pub use self::rate_limit::{RateLimit, RATELIMIT_PREVIEW_REPOSITORY};
+
+#[cfg(feature = "client")]
+mod raw;
+#[cfg(feature = "client")]
+pub use self::raw::RawResponse;
+
+#[cfg(feature = "client")]
+mod time;
+#[cfg(feature = "client")]
+pub(crate) use self::time::parse_rfc3339;
+#[cfg(feature = "client")]
+pub(crate) use self::time::sleep;
+#[cfg(feature = "client")]
+pub(crate) use self::time::system_now;
+#[cfg(feature = "client")]
+pub(crate) use self::time::Instant;
+
+#[cfg(feature = "client")]
+mod correlation;
+#[cfg(feature = "client")]
+mod deadline;
+#[cfg(feature = "client")]
+pub use self::correlation::REQUEST_ID_HEADER;
+#[cfg(feature = "client")]
+use self::deadline::Deadline;
+
+#[cfg(feature = "client")]
+mod user_agent;
+#[cfg(feature = "client")]
+pub use self::user_agent::UserAgent;
+
+#[cfg(feature = "client")]
+mod limits;
+#[cfg(feature = "client")]
+pub use self::limits::ResponseLimits;
+#[cfg(feature = "client")]
+pub(crate) use self::limits::{check_manifest_complexity, check_referrers_count, read_decoded_limited, read_limited};
+
 mod content_digest;
-pub(crate) use self::content_digest::ContentDigest;
 pub use self::content_digest::ContentDigestError;
+#[cfg(feature = "client")]
+pub(crate) use self::content_digest::{digest_like, sha256_hex, DigestAlgorithm};
+pub(crate) use self::content_digest::{sha256_digest, ContentDigest};
 
 /// A Client to make outgoing API requests to a registry.
+#[cfg(feature = "client")]
 #[derive(Clone, Debug)]
 pub struct Client {
   base_url: String,
   credentials: Option<(String, String)>,
+  device_login: Option<DeviceLogin>,
+  token_store: Option<Arc<dyn TokenStore>>,
+  credential_renewal: Option<Arc<CredentialRenewal>>,
+  identities: Arc<Identities>,
+  identity: Option<String>,
   user_agent: Option<String>,
+  accept_encoding: Option<String>,
+  request_id: Option<String>,
+  request_id_header: reqwest::header::HeaderName,
   auth: Option<auth::Auth>,
   client: reqwest::Client,
   accepted_types: Vec<(MediaTypes, Option<f64>)>,
+  pinned_certificates: Vec<String>,
+  redirect_policy: RedirectPolicy,
+  request_signer: Option<Arc<dyn RequestSigner>>,
+  interceptors: Vec<Arc<dyn Interceptor>>,
+  #[cfg(all(feature = "test-support", not(target_arch = "wasm32")))]
+  chaos: Option<Arc<crate::chaos::Chaos>>,
+  upload_journal: Option<Arc<UploadJournal>>,
+  uploads: Arc<UploadCoalescer>,
+  limits: ResponseLimits,
+  strict_media_types: bool,
+  lenient_parsing: bool,
+  parse_warnings: Arc<ParseWarnings>,
+  soft_fail: bool,
+  unsupported_features: Arc<UnsupportedFeatures>,
+  profile: RegistryProfile,
+  detected_profile: Arc<DetectedProfile>,
+  check_tag_mutability: bool,
+  read_only: bool,
+  custom_media_types: CustomMediaTypes,
+  manifest_cache: Option<Arc<Mutex<ManifestCache>>>,
+  signed_urls: Option<Arc<SignedUrls>>,
+  #[cfg(not(target_arch = "wasm32"))]
+  response_cache: Option<Arc<Mutex<ResponseCache>>>,
+  #[cfg(not(target_arch = "wasm32"))]
+  coalesced_requests: Option<Arc<RequestCoalescer>>,
+  retry_policy: Option<RetryPolicy>,
+  deadline: Option<Deadline>,
+  parallel_downloads: Option<ParallelDownloads>,
+  stats: Arc<StatsCollector>,
 }
 
+#[cfg(feature = "client")]
 impl Client {
   pub fn configure() -> Config {
     Config::default()
@@ -102,7 +365,7 @@ impl Client {
       self.build_reqwest(Method::GET, url)
     })?;
 
-    let response = request.send().await?;
+    let response = self.send(request).await?;
 
     let b = match (response.status(), response.headers().get(api_header)) {
       (StatusCode::OK, Some(x)) => Ok((x == api_version, true)),
@@ -118,25 +381,334 @@ impl Client {
 
   /// Takes reqwest's async RequestBuilder and injects an authentication header if a token is present
   fn build_reqwest(&self, method: Method, url: Url) -> reqwest::RequestBuilder {
+    let endpoint = EndpointClass::of(&url);
     let mut builder = self.client.request(method, url);
 
     if let Some(auth) = &self.auth {
-      builder = auth.add_auth_headers(builder);
+      builder = match (auth, self.renewed_credentials()) {
+        (auth::Auth::Basic(_), Some((user, password))) => builder.basic_auth(user, Some(password)),
+        _ => auth.add_auth_headers(builder),
+      };
     };
 
     if let Some(ua) = &self.user_agent {
       builder = builder.header(reqwest::header::USER_AGENT, ua.as_str());
     };
 
+    if let Some(request_id) = &self.request_id {
+      builder = builder.header(&self.request_id_header, request_id.as_str());
+    };
+
+    if let Some(encodings) = &self.accept_encoding {
+      if matches!(endpoint, EndpointClass::Catalog | EndpointClass::Tags) {
+        builder = builder.header(reqwest::header::ACCEPT_ENCODING, encodings.as_str());
+      }
+    };
+
     builder
   }
+
+  /// Build the URL of an endpoint of repository `name`, rejecting invalid repository names
+  /// before any request is sent.
+  pub(crate) fn repository_url(&self, name: &str, path: &str) -> Result<Url> {
+    crate::reference::validate_repository_name(name)?;
+    Ok(Url::parse(&format!("{}/v2/{}/{}", self.base_url, name, path))?)
+  }
+
+  /// The registry, as the host and port of its base URL, e.g. `localhost:5000`.
+  pub(crate) fn registry_host(&self) -> String {
+    match Url::parse(&self.base_url) {
+      Ok(url) => url[url::Position::BeforeHost..url::Position::AfterPort].to_string(),
+      Err(_) => self.base_url.clone(),
+    }
+  }
+
+  /// Whether the client refuses to modify the registry, see [`Config::read_only`].
+  pub fn is_read_only(&self) -> bool {
+    self.read_only
+  }
+
+  /// Fail with `Error::ReadOnlyClient` if the client is read-only, before any request is sent.
+  pub(crate) fn ensure_writable(&self) -> Result<()> {
+    match self.read_only {
+      true => Err(Error::ReadOnlyClient),
+      false => Ok(()),
+    }
+  }
+
+  /// Send a request and apply the client-wide checks on its response.
+  ///
+  /// Read-only clients refuse every request but `GET`, `HEAD` and `OPTIONS`. Requests whose
+  /// Basic credentials are rejected are sent again once with the credentials renewed by the
+  /// `Config::credential_provider`, if any.
+  pub(crate) async fn send(&self, request: reqwest::RequestBuilder) -> Result<Response> {
+    let request = request.build()?;
+    if self.read_only && !matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+      debug!("refusing {} {} of a read-only client", request.method(), request.url());
+      return Err(Error::ReadOnlyClient);
+    }
+    let renewable = self.renewable_request(&request);
+    let response = self.send_checked(request).await?;
+    if response.status() != StatusCode::UNAUTHORIZED {
+      return Ok(response);
+    }
+    let Some((rejected, mut request)) = renewable else {
+      return Ok(response);
+    };
+    match self.renew_credentials(&rejected).await? {
+      Some(credentials) => {
+        set_basic_credentials(&mut request, &credentials)?;
+        self.send_checked(request).await
+      }
+      None => Ok(response),
+    }
+  }
+
+  /// Send a request which passed the client-wide checks, from the response cache if possible.
+  async fn send_checked(&self, request: reqwest::Request) -> Result<Response> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(cache) = &self.response_cache {
+      return self.send_cached(cache, request).await;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    return self.dispatch_coalesced(request).await;
+    #[cfg(target_arch = "wasm32")]
+    self.dispatch(request).await
+  }
+
+  /// Send a request to an authorization server, which read-only clients send too although
+  /// token requests may be `POST` requests.
+  pub(crate) async fn send_token_request(&self, request: reqwest::RequestBuilder) -> Result<Response> {
+    self.dispatch(request.build()?).await
+  }
+
+  pub(crate) async fn dispatch(&self, mut request: reqwest::Request) -> Result<Response> {
+    if let Some(signer) = &self.request_signer {
+      signer
+        .sign(&mut SigningRequest::new(&mut request))
+        .map_err(Error::RequestSigning)?;
+    }
+    self.dispatch_unsigned(request).await
+  }
+
+  /// Send a request without signing it, e.g. to a presigned storage URL, retrying it with the
+  /// retry policy.
+  pub(crate) async fn dispatch_unsigned(&self, mut request: reqwest::Request) -> Result<Response> {
+    let mut attempt = 0;
+    loop {
+      self.apply_deadline(&mut request)?;
+      // Keep a copy to resend if the request may be retried; streaming bodies cannot be copied.
+      let retry = match &self.retry_policy {
+        Some(policy) if policy.allows(&request, attempt) => request.try_clone().map(|r| (policy, r)),
+        _ => None,
+      };
+
+      let intercepted = match self.interceptors.is_empty() {
+        true => None,
+        false => Some(self.intercept_request(&request, attempt)?),
+      };
+      let endpoint = EndpointClass::of(request.url());
+      let repository = repository_of(request.url());
+      let method = request.method().clone();
+      let sent = request_body_len(&request);
+      #[cfg(not(target_arch = "wasm32"))]
+      let authorized = request.headers().contains_key(reqwest::header::AUTHORIZATION);
+      let started = Instant::now();
+      let result = match self.pinned_certificates.is_empty() {
+        true => self.execute(request).await,
+        false => self.execute_pinned(request).await?,
+      };
+      if let Ok(response) = &result {
+        self.detect_profile(response.headers());
+      }
+      self.stats.update(|stats| match &result {
+        Ok(response) => {
+          *stats
+            .responses
+            .entry((endpoint, response.status().as_u16()))
+            .or_default() += 1;
+          // Bodies are counted as they are read, see `count_received`.
+          #[cfg(not(target_arch = "wasm32"))]
+          let received = 0;
+          #[cfg(target_arch = "wasm32")]
+          let received = match method == Method::HEAD {
+            true => 0,
+            false => response.content_length().unwrap_or(0),
+          };
+          stats.record_transfer(repository.clone(), sent, received);
+        }
+        Err(_) => {
+          *stats.failed_requests.entry(endpoint).or_default() += 1;
+          stats.record_transfer(repository.clone(), 0, 0);
+        }
+      });
+      #[cfg(not(target_arch = "wasm32"))]
+      let result = result.map(|response| count_received(self.stats.clone(), repository, response));
+      let result = match (&intercepted, result) {
+        (Some(intercepted), Ok(response)) => Ok(self.intercept_response(intercepted, response, started).await?),
+        (Some(intercepted), Err(e)) => {
+          for interceptor in &self.interceptors {
+            interceptor.on_error(intercepted, &e);
+          }
+          Err(e)
+        }
+        (None, result) => result,
+      };
+      #[cfg(not(target_arch = "wasm32"))]
+      let result = result.map(|mut response| {
+        response.extensions_mut().insert(RequestMethod(method));
+        self.mark_profiled_request(authorized, &mut response);
+        response
+      });
+
+      let (policy, next) = match retry {
+        Some(retry) => retry,
+        None => break self.check_response(result),
+      };
+      let delay = match &result {
+        Ok(response) => policy.response_delay(response, attempt),
+        Err(e) => policy.error_delay(e, attempt),
+      };
+      let delay = match delay {
+        Some(delay) => delay,
+        None => break self.check_response(result),
+      };
+      if self.past_deadline_after(delay) {
+        debug!("not retrying {} {} past the deadline", next.method(), next.url());
+        break Err(Error::DeadlineExceeded);
+      }
+
+      debug!(
+        "retrying {} {} in {:?} (attempt {}, request id {:?})",
+        next.method(),
+        next.url(),
+        delay,
+        attempt + 1,
+        self.request_id
+      );
+      time::sleep(delay).await;
+      self.stats.update(|stats| stats.retries += 1);
+      request = next;
+      attempt += 1;
+    }
+  }
+
+  async fn execute(&self, request: reqwest::Request) -> reqwest::Result<Response> {
+    #[cfg(all(feature = "test-support", not(target_arch = "wasm32")))]
+    if let Some(chaos) = &self.chaos {
+      return chaos.execute(&self.client, request).await;
+    }
+    self.client.execute(request).await
+  }
+
+  fn intercept_request(&self, request: &reqwest::Request, attempt: u32) -> Result<InterceptedRequest> {
+    let include_body = self.interceptors.iter().any(|i| i.include_bodies());
+    let intercepted = InterceptedRequest::new(request, attempt, self.request_id.clone(), include_body);
+    for interceptor in &self.interceptors {
+      interceptor.on_request(&intercepted).map_err(Error::Interceptor)?;
+    }
+    Ok(intercepted)
+  }
+
+  async fn intercept_response(
+    &self,
+    request: &InterceptedRequest,
+    response: Response,
+    started: Instant,
+  ) -> Result<Response> {
+    let elapsed = started.elapsed();
+    // Responses cannot be rebuilt on wasm, so bodies are never handed to interceptors there.
+    // Bodies are only buffered if they are known to fit the limit of their endpoint, so that
+    // large blobs keep streaming.
+    #[cfg(not(target_arch = "wasm32"))]
+    if self.interceptors.iter().any(|i| i.include_bodies()) {
+      let (limit, kind) = self.limits.body_limit(EndpointClass::of(response.url()));
+      if response.content_length().is_some_and(|len| len <= limit) {
+        return self
+          .intercept_buffered_response(request, response, elapsed, limit, kind)
+          .await;
+      }
+    }
+
+    let intercepted = InterceptedResponse {
+      url: response.url(),
+      status: response.status(),
+      headers: response.headers(),
+      body: None,
+      elapsed,
+    };
+    for interceptor in &self.interceptors {
+      interceptor
+        .on_response(request, &intercepted)
+        .map_err(Error::Interceptor)?;
+    }
+    Ok(response)
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  async fn intercept_buffered_response(
+    &self,
+    request: &InterceptedRequest,
+    response: Response,
+    elapsed: std::time::Duration,
+    limit: u64,
+    kind: &'static str,
+  ) -> Result<Response> {
+    // Buffer the body, then rebuild the response with the same metadata, including the TLS
+    // information needed to check pinned certificates.
+    let url = response.url().clone();
+    let status = response.status();
+    let version = response.version();
+    let headers = response.headers().clone();
+    let extensions = response.extensions().clone();
+    let body = bytes::Bytes::from(
+      read_limited(response, limit, kind)
+        .await
+        .map_err(|e| self.deadline_error(e))?,
+    );
+
+    let intercepted = InterceptedResponse {
+      url: &url,
+      status,
+      headers: &headers,
+      body: Some(&body),
+      elapsed,
+    };
+    for interceptor in &self.interceptors {
+      let include_body = interceptor.include_bodies();
+      let intercepted = InterceptedResponse {
+        body: intercepted.body.filter(|_| include_body),
+        ..intercepted
+      };
+      interceptor
+        .on_response(request, &intercepted)
+        .map_err(Error::Interceptor)?;
+    }
+
+    let mut builder = http::Response::builder().status(status).version(version);
+    if let Some(parts) = builder.extensions_mut() {
+      *parts = extensions;
+    }
+    if let Some(parts) = builder.headers_mut() {
+      *parts = headers;
+    }
+    let response = builder.url(url).body(body).map_err(|e| Error::Interceptor(e.into()))?;
+    Ok(Response::from(response))
+  }
+
+  fn check_response(&self, result: reqwest::Result<Response>) -> Result<Response> {
+    let response = result.map_err(|e| self.deadline_error(e))?;
+    self.verify_pinned_certificate(&response)?;
+    Ok(response)
+  }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ApiError {
   code: String,
   message: Option<String>,
-  detail: Option<Box<serde_json::value::RawValue>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  detail: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, thiserror::Error)]
@@ -145,6 +717,24 @@ pub struct ApiErrors {
 }
 
 impl ApiError {
+  /// Create an error with the given code, e.g. `MANIFEST_UNKNOWN`.
+  pub fn new(code: &str) -> Self {
+    Self {
+      code: code.to_string(),
+      ..Default::default()
+    }
+  }
+
+  pub fn with_message(mut self, message: &str) -> Self {
+    self.message = Some(message.to_string());
+    self
+  }
+
+  pub fn with_detail(mut self, detail: serde_json::Value) -> Self {
+    self.detail = Some(detail);
+    self
+  }
+
   /// Return the API error code.
   pub fn code(&self) -> &str {
     &self.code
@@ -153,6 +743,34 @@ impl ApiError {
   pub fn message(&self) -> Option<&str> {
     self.message.as_deref()
   }
+
+  /// Return the error detail, whose structure depends on the registry and the error code.
+  pub fn detail(&self) -> Option<&serde_json::Value> {
+    self.detail.as_ref()
+  }
+
+  /// Return the digest the error refers to, e.g. for `BLOB_UNKNOWN` and `MANIFEST_UNKNOWN`.
+  ///
+  /// Registries report it either as the whole detail or in a `digest` field.
+  pub fn digest(&self) -> Option<&str> {
+    let digest = match self.detail.as_ref()? {
+      serde_json::Value::String(s) => s.as_str(),
+      serde_json::Value::Object(o) => o.get("digest").or_else(|| o.get("Digest"))?.as_str()?,
+      _ => return None,
+    };
+    ContentDigest::try_new(digest).ok().map(|_| digest)
+  }
+
+  /// Return the tag the error refers to, e.g. for `MANIFEST_UNKNOWN`.
+  ///
+  /// Registries report it either in a `tag` field or as an `unknown tag=<tag>` message.
+  pub fn tag(&self) -> Option<&str> {
+    match self.detail.as_ref()? {
+      serde_json::Value::String(s) => s.strip_prefix("unknown tag="),
+      serde_json::Value::Object(o) => o.get("tag").or_else(|| o.get("Tag"))?.as_str(),
+      _ => None,
+    }
+  }
 }
 impl fmt::Display for ApiError {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -168,13 +786,88 @@ impl fmt::Display for ApiError {
 }
 
 impl ApiErrors {
+  /// Create an error body listing `errors`.
+  pub fn new(errors: Vec<ApiError>) -> Self {
+    Self { errors: Some(errors) }
+  }
+
   /// Create a new ApiErrors from a API Json response.
   /// Returns an ApiError if the content is a valid per
   /// https://github.com/opencontainers/distribution-spec/blob/main/spec.md#error-codes
+  ///
+  /// Responses with status 401 become `Error::Unauthorized`, and 403 `Error::Denied` unless
+  /// classified otherwise, with the repository and the action of the request; their body is
+  /// optional.
+  #[cfg(feature = "client")]
   pub async fn from(r: Response) -> errors::Error {
-    match r.json::<ApiErrors>().await {
-      Ok(e) => errors::Error::Api(e),
-      Err(e) => errors::Error::Reqwest(e),
+    let status = r.status();
+    let profiled = profiled_request(&r);
+    let status = match (status, profiled) {
+      // Artifactory answers requests without credentials for repositories they may not read as
+      // if the repositories did not exist.
+      (StatusCode::NOT_FOUND, Some((RegistryProfile::Artifactory, false))) => StatusCode::UNAUTHORIZED,
+      (status, _) => status,
+    };
+    // Nexus group repositories refuse writes.
+    let read_only = status == StatusCode::METHOD_NOT_ALLOWED
+      && matches!(profiled, Some((RegistryProfile::Nexus, _)))
+      && method_of(&r).is_some_and(|m| !matches!(*m, Method::GET | Method::HEAD));
+    let (repository, action) = match status {
+      StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => (repository_of(r.url()), action_of(&r)),
+      _ if read_only => (repository_of(r.url()), None),
+      _ => (None, None),
+    };
+    let errors = match r.json::<ApiErrors>().await {
+      Ok(e) => e,
+      Err(_) if read_only || matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => ApiErrors::default(),
+      Err(e) => return errors::Error::Reqwest(e),
+    };
+    if read_only {
+      return errors::Error::ReadOnlyRepository {
+        repository,
+        source: errors,
+      };
+    }
+    match (status, errors.classify()) {
+      (StatusCode::UNAUTHORIZED, errors::Error::Api(source)) => errors::Error::Unauthorized {
+        repository,
+        action,
+        source,
+      },
+      (StatusCode::FORBIDDEN, errors::Error::Api(source)) => errors::Error::Denied {
+        repository,
+        action,
+        source,
+      },
+      (_, error) => error,
+    }
+  }
+
+  /// Turn the errors into the most specific `Error` variant.
+  ///
+  /// Registries do not have dedicated error codes for tags protected by immutability or
+  /// retention rules, so these are recognized from the messages used by Harbor and ACR:
+  /// such errors become `Error::TagImmutable` or `Error::RetentionLocked`, everything else
+  /// `Error::Api`.
+  pub fn classify(self) -> errors::Error {
+    let messages: Vec<String> = self
+      .errors
+      .iter()
+      .flatten()
+      .filter_map(|e| e.message())
+      .map(str::to_lowercase)
+      .collect();
+    let mentions = |needles: &[&str]| messages.iter().any(|m| needles.iter().any(|n| m.contains(n)));
+
+    if mentions(&["retention", "soft-deleted", "soft deleted"]) {
+      errors::Error::RetentionLocked(self)
+    } else if mentions(&[
+      "immutable",
+      "operation is disallowed on this registry, repository or image",
+    ]) {
+      errors::Error::TagImmutable(self)
+    } else {
+      errors::Error::Api(self)
     }
   }
 
@@ -184,6 +877,59 @@ impl ApiErrors {
   }
 }
 
+/// Method of the request a response answers, recorded by `Client::send` for `ApiErrors::from`.
+#[cfg(feature = "client")]
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Clone)]
+struct RequestMethod(Method);
+
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+fn method_of(r: &Response) -> Option<&Method> {
+  r.extensions().get::<RequestMethod>().map(|m| &m.0)
+}
+
+/// Responses have no extensions to record the method in.
+#[cfg(all(feature = "client", target_arch = "wasm32"))]
+fn method_of(_: &Response) -> Option<&Method> {
+  None
+}
+
+/// The profile the registry was handled with, and whether the request carried credentials.
+#[cfg(all(feature = "client", not(target_arch = "wasm32")))]
+fn profiled_request(r: &Response) -> Option<(RegistryProfile, bool)> {
+  let profiled = r.extensions().get::<ProfiledRequest>()?;
+  Some((profiled.profile, profiled.authorized))
+}
+
+#[cfg(all(feature = "client", target_arch = "wasm32"))]
+fn profiled_request(_: &Response) -> Option<(RegistryProfile, bool)> {
+  None
+}
+
+/// The repository of a request to the API, e.g. `library/busybox` for
+/// `/v2/library/busybox/manifests/latest`.
+#[cfg(feature = "client")]
+pub(crate) fn repository_of(url: &Url) -> Option<String> {
+  let path = url.path().strip_prefix("/v2/")?;
+  // Repository names may contain these segments too: the last one follows the name.
+  ["/manifests/", "/blobs/", "/tags/list", "/referrers/"]
+    .iter()
+    .filter_map(|segment| path.rfind(segment))
+    .max()
+    .map(|end| path[..end].to_string())
+}
+
+/// The action a request needs in token scopes: `pull`, `push` or `delete`.
+#[cfg(feature = "client")]
+fn action_of(r: &Response) -> Option<String> {
+  let action = match *method_of(r)? {
+    Method::GET | Method::HEAD => "pull",
+    Method::DELETE => "delete",
+    _ => "push",
+  };
+  Some(action.to_string())
+}
+
 impl fmt::Display for ApiErrors {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     if self.errors.is_none() {
@@ -0,0 +1,202 @@
+use log::trace;
+use reqwest::{Method, StatusCode, Url};
+use serde::Deserialize;
+use url::form_urlencoded;
+
+use crate::{
+  errors::{Error, Result},
+  v2::*,
+};
+
+/// API of a registry exposing whether tags are protected, used by `Client::tag_mutability_with`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MutabilityProvider {
+  /// The artifacts API of Harbor, reporting the tags matched by immutability rules,
+  /// authenticated with the client's credentials.
+  Harbor,
+  /// The `/acr/v1` API of Azure Container Registry, reporting the changeable attributes of
+  /// tags as set by `az acr repository update`.
+  ///
+  /// Without credentials, the client's token needs the `metadata_read` action on the repository.
+  Acr,
+}
+
+/// Whether a tag may be overwritten and deleted, as reported by `Client::tag_mutability`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TagMutability {
+  /// Pushing a manifest to the tag is allowed.
+  pub writable: bool,
+  /// Deleting the tag, or the manifest through it, is allowed.
+  pub deletable: bool,
+}
+
+#[derive(Deserialize)]
+struct HarborArtifact {
+  #[serde(default)]
+  tags: Option<Vec<HarborTag>>,
+}
+
+#[derive(Deserialize)]
+struct HarborTag {
+  name: String,
+  #[serde(default)]
+  immutable: bool,
+}
+
+#[derive(Deserialize)]
+struct AcrTag {
+  tag: AcrTagAttributes,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AcrTagAttributes {
+  changeable_attributes: AcrChangeableAttributes,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AcrChangeableAttributes {
+  #[serde(default = "enabled")]
+  write_enabled: bool,
+  #[serde(default = "enabled")]
+  delete_enabled: bool,
+}
+
+fn enabled() -> bool {
+  true
+}
+
+/// Suffix of the hosts of Azure Container Registry.
+const ACR_HOST_SUFFIX: &str = ".azurecr.io";
+
+impl Client {
+  /// Check whether the tag `tag` of the repository `name` may be overwritten and deleted.
+  ///
+  /// Registries on `*.azurecr.io` are queried with [`MutabilityProvider::Acr`], others with
+  /// [`MutabilityProvider::Harbor`]. Returns `None` if the registry does not serve the API, or
+  /// the tag does not exist.
+  pub async fn tag_mutability(&self, name: &str, tag: &str) -> Result<Option<TagMutability>> {
+    let url = Url::parse(&self.base_url)?;
+    let provider = match url.host_str() {
+      Some(host) if host.ends_with(ACR_HOST_SUFFIX) => MutabilityProvider::Acr,
+      _ => MutabilityProvider::Harbor,
+    };
+    self.tag_mutability_with(provider, name, tag).await
+  }
+
+  /// Check whether the tag `tag` of the repository `name` may be overwritten and deleted, with
+  /// the API of `provider`.
+  ///
+  /// Harbor repositories are named `<project>/<repository>`: names without a project return
+  /// `None`. The retention rules of Harbor and the soft delete of ACR are not reported: they
+  /// surface as `Error::RetentionLocked` when the operation fails.
+  pub async fn tag_mutability_with(
+    &self,
+    provider: MutabilityProvider,
+    name: &str,
+    tag: &str,
+  ) -> Result<Option<TagMutability>> {
+    let mut url = Url::parse(&self.base_url)?;
+    let client = match provider {
+      MutabilityProvider::Harbor => {
+        let Some((project, repository)) = name.split_once('/') else {
+          return Ok(None);
+        };
+        // Harbor expects the repository to be encoded twice, its slashes included.
+        url.set_path(&format!(
+          "/api/v2.0/projects/{}/repositories/{}/artifacts/{}",
+          encode(project),
+          encode(&encode(repository)),
+          encode(tag)
+        ));
+        url
+          .query_pairs_mut()
+          .append_pair("with_tag", "true")
+          .append_pair("with_immutable_status", "true");
+        self.with_basic_credentials()
+      }
+      MutabilityProvider::Acr => {
+        url.set_path(&format!("/acr/v1/{}/_tags/{}", name, encode(tag)));
+        match self.credentials {
+          Some(_) => self.with_basic_credentials(),
+          None => self.clone(),
+        }
+      }
+    };
+
+    let r = client.send(client.build_reqwest(Method::GET, url)).await?;
+    let status = r.status();
+    trace!("GET '{}' status: {:?}", r.url(), status);
+    match status {
+      StatusCode::OK => {}
+      StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => return Ok(None),
+      StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => return Err(ApiErrors::from(r).await),
+      _ => return Err(Error::UnexpectedHttpStatus(status)),
+    }
+    let body = read_limited(r, self.limits.max_manifest_size, "tag attributes")
+      .await
+      .map_err(|e| self.deadline_error(e))?;
+
+    let mutability = match provider {
+      MutabilityProvider::Harbor => serde_json::from_slice::<HarborArtifact>(&body)?
+        .tags
+        .into_iter()
+        .flatten()
+        .find(|t| t.name == tag)
+        .map(|t| TagMutability {
+          writable: !t.immutable,
+          deletable: !t.immutable,
+        }),
+      MutabilityProvider::Acr => {
+        let attributes = serde_json::from_slice::<AcrTag>(&body)?.tag.changeable_attributes;
+        Some(TagMutability {
+          writable: attributes.write_enabled,
+          deletable: attributes.delete_enabled,
+        })
+      }
+    };
+    Ok(mutability)
+  }
+
+  /// Fail with `Error::TagImmutable` if `reference` is a tag which the registry reports as
+  /// protected against `operation`, when [`Config::check_tag_mutability`] is set.
+  ///
+  /// The check is best effort: failing to query the registry lets the operation proceed.
+  pub(crate) async fn check_tag_mutability(&self, name: &str, reference: &str, operation: TagOperation) -> Result<()> {
+    if !self.check_tag_mutability || ContentDigest::try_new(reference).is_ok() {
+      return Ok(());
+    }
+    let mutability = match self.tag_mutability(name, reference).await {
+      Ok(Some(mutability)) => mutability,
+      Ok(None) => return Ok(()),
+      Err(e) => {
+        trace!("Cannot check whether {}:{} is immutable: {}", name, reference, e);
+        return Ok(());
+      }
+    };
+    let (allowed, verb) = match operation {
+      TagOperation::Push => (mutability.writable, "overwritten"),
+      TagOperation::Delete => (mutability.deletable, "deleted"),
+    };
+    if allowed {
+      return Ok(());
+    }
+    let message = format!("tag {} of {} cannot be {}", reference, name, verb);
+    Err(Error::TagImmutable(ApiErrors::new(vec![
+      ApiError::new("DENIED").with_message(&message)
+    ])))
+  }
+}
+
+/// Operation on a tag checked by `Client::check_tag_mutability`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum TagOperation {
+  Push,
+  Delete,
+}
+
+/// Encode `segment` as a single path segment, including its slashes.
+fn encode(segment: &str) -> String {
+  form_urlencoded::byte_serialize(segment.as_bytes()).collect()
+}
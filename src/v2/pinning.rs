@@ -0,0 +1,304 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  error::Error as StdError,
+  fmt,
+  sync::Arc,
+  task::{Context, Poll},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use futures::future::BoxFuture;
+#[cfg(not(target_arch = "wasm32"))]
+use hyper_util::client::legacy::connect::Connection;
+#[cfg(not(target_arch = "wasm32"))]
+use log::debug;
+use log::trace;
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::{header, Method, StatusCode};
+use reqwest::{Request, Response};
+
+use crate::{
+  errors::{Error, Result},
+  v2::*,
+};
+
+/// Headers which are never forwarded when a redirect leads to a different host or port.
+#[cfg(not(target_arch = "wasm32"))]
+const SENSITIVE_HEADERS: [header::HeaderName; 4] = [
+  header::AUTHORIZATION,
+  header::COOKIE,
+  header::PROXY_AUTHORIZATION,
+  header::WWW_AUTHENTICATE,
+];
+
+impl Client {
+  /// Send a request to a server whose certificate must match a pinned fingerprint.
+  ///
+  /// The transport checks the certificate of every connection as soon as its TLS handshake
+  /// completes, see [`PinningLayer`], so the request, and its credentials, only go out over a
+  /// connection which passed the check. Redirects are followed here rather than by the
+  /// transport, so that the redirect policy applies to each hop. The outer error is a pin or
+  /// redirect failure, the inner one comes from the transport.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub(crate) async fn execute_pinned(&self, mut request: Request) -> Result<reqwest::Result<Response>> {
+    let origin = request.url().host_str().map(str::to_string);
+    let max_redirects = self.redirect_policy.max_redirects_allowed();
+    let mut hops = 0;
+    loop {
+      let host = request.url().host_str().unwrap_or_default().to_string();
+      let replay = request.try_clone();
+      let response = match self.execute(request).await {
+        Ok(response) => response,
+        Err(e) => match pin_rejection(&e) {
+          Some(rejected) => {
+            return Err(Error::CertificatePinMismatch {
+              host,
+              fingerprint: rejected.fingerprint.clone(),
+            })
+          }
+          None => return Ok(Err(e)),
+        },
+      };
+      self.verify_pinned_certificate(&response)?;
+
+      let location = match redirect_location(&response) {
+        Some(location) => location,
+        None => return Ok(Ok(response)),
+      };
+      // Streaming bodies cannot be sent again, so the redirect is handed back as-is.
+      let mut next = match replay {
+        Some(next) if max_redirects > 0 => next,
+        _ => return Ok(Ok(response)),
+      };
+      if hops >= max_redirects {
+        return Err(RedirectError::TooManyRedirects(max_redirects).into());
+      }
+      let host = location.host_str().unwrap_or_default().to_string();
+      if !self.redirect_policy.is_allowed(&host, origin.as_deref()) {
+        return Err(RedirectError::HostNotAllowed(host).into());
+      }
+
+      debug!("following redirect from {} to {}", response.url(), location);
+      redirect_request(&mut next, response.status(), location);
+      request = next;
+      hops += 1;
+    }
+  }
+
+  /// The TLS session is not exposed on wasm, so no certificate can match and nothing is sent.
+  #[cfg(target_arch = "wasm32")]
+  pub(crate) async fn execute_pinned(&self, request: Request) -> Result<reqwest::Result<Response>> {
+    Err(Error::CertificatePinMismatch {
+      host: request.url().host_str().unwrap_or_default().to_string(),
+      fingerprint: None,
+    })
+  }
+
+  /// Ensure the certificate presented by the server matches one of the pinned fingerprints, if any.
+  pub(crate) fn verify_pinned_certificate(&self, response: &Response) -> Result<()> {
+    if self.pinned_certificates.is_empty() {
+      return Ok(());
+    }
+
+    let host = response.url().host_str().unwrap_or_default().to_string();
+    // The TLS session is not exposed on wasm, so no certificate can match.
+    #[cfg(not(target_arch = "wasm32"))]
+    let fingerprint = response
+      .extensions()
+      .get::<reqwest::tls::TlsInfo>()
+      .and_then(|info| info.peer_certificate())
+      .map(sha256_hex);
+    #[cfg(target_arch = "wasm32")]
+    let fingerprint: Option<String> = None;
+
+    match fingerprint {
+      Some(ref f) if self.pinned_certificates.contains(f) => {
+        trace!("Certificate for {} matches a pinned fingerprint", host);
+        Ok(())
+      }
+      fingerprint => Err(Error::CertificatePinMismatch { host, fingerprint }),
+    }
+  }
+}
+
+/// Transport layer rejecting connections whose server certificate matches none of the pinned
+/// fingerprints, before any request is sent over them.
+///
+/// Connections without TLS are rejected too, as they present no certificate.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub(crate) struct PinningLayer(Arc<[String]>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl PinningLayer {
+  pub(crate) fn new(fingerprints: &[String]) -> Self {
+    Self(fingerprints.into())
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<S> tower_layer::Layer<S> for PinningLayer {
+  type Service = PinningConnector<S>;
+
+  fn layer(&self, inner: S) -> Self::Service {
+    PinningConnector {
+      inner,
+      fingerprints: self.0.clone(),
+    }
+  }
+}
+
+/// Connector wrapped by [`PinningLayer`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub(crate) struct PinningConnector<S> {
+  inner: S,
+  fingerprints: Arc<[String]>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<S, R> tower_service::Service<R> for PinningConnector<S>
+where
+  S: tower_service::Service<R>,
+  S::Response: Connection + Send + 'static,
+  S::Error: Into<BoxError>,
+  S::Future: Send + 'static,
+{
+  type Response = S::Response;
+  type Error = BoxError;
+  type Future = BoxFuture<'static, std::result::Result<S::Response, BoxError>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), BoxError>> {
+    self.inner.poll_ready(cx).map_err(Into::into)
+  }
+
+  fn call(&mut self, target: R) -> Self::Future {
+    let fingerprints = self.fingerprints.clone();
+    let connecting = self.inner.call(target);
+    Box::pin(async move {
+      let connection = connecting.await.map_err(Into::into)?;
+      let mut extensions = http::Extensions::new();
+      connection.connected().get_extras(&mut extensions);
+      let fingerprint = extensions
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .map(sha256_hex);
+      match fingerprint {
+        Some(ref f) if fingerprints.contains(f) => Ok(connection),
+        fingerprint => Err(Box::new(PinRejected { fingerprint }) as BoxError),
+      }
+    })
+  }
+}
+
+/// Connection rejected by [`PinningLayer`], with the fingerprint of the certificate presented,
+/// if any.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+struct PinRejected {
+  fingerprint: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl fmt::Display for PinRejected {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("server certificate matches no pinned fingerprint")
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StdError for PinRejected {}
+
+/// The rejection by [`PinningLayer`] a transport error comes from, if any.
+#[cfg(not(target_arch = "wasm32"))]
+fn pin_rejection(error: &reqwest::Error) -> Option<&PinRejected> {
+  let mut source = error.source();
+  while let Some(e) = source {
+    if let Some(rejected) = e.downcast_ref::<PinRejected>() {
+      return Some(rejected);
+    }
+    source = e.source();
+  }
+  None
+}
+
+/// The target of a redirect response, resolved against the URL of the response.
+#[cfg(not(target_arch = "wasm32"))]
+fn redirect_location(response: &Response) -> Option<Url> {
+  if !response.status().is_redirection() || response.status() == StatusCode::NOT_MODIFIED {
+    return None;
+  }
+  let location = response.headers().get(header::LOCATION)?.to_str().ok()?;
+  response.url().join(location).ok()
+}
+
+/// Turn `request` into the request for the next hop, the way browsers and the transport do.
+#[cfg(not(target_arch = "wasm32"))]
+fn redirect_request(request: &mut Request, status: StatusCode, location: Url) {
+  let switch_to_get = match status {
+    StatusCode::SEE_OTHER => request.method() != Method::HEAD,
+    StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => request.method() == Method::POST,
+    _ => false,
+  };
+  if switch_to_get {
+    *request.method_mut() = Method::GET;
+    *request.body_mut() = None;
+    for name in [header::CONTENT_TYPE, header::CONTENT_LENGTH, header::CONTENT_ENCODING] {
+      request.headers_mut().remove(name);
+    }
+  }
+  let previous = request.url();
+  if previous.host_str() != location.host_str() || previous.port_or_known_default() != location.port_or_known_default()
+  {
+    for name in &SENSITIVE_HEADERS {
+      request.headers_mut().remove(name);
+    }
+  }
+  *request.url_mut() = location;
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn redirects_drop_credentials_across_hosts() {
+    let mut request = Request::new(
+      Method::GET,
+      "https://registry.example.com/v2/a/blobs/x".parse().unwrap(),
+    );
+    request
+      .headers_mut()
+      .insert(header::AUTHORIZATION, "Bearer t".parse().unwrap());
+    redirect_request(
+      &mut request,
+      StatusCode::TEMPORARY_REDIRECT,
+      "https://registry.example.com/v2/b/blobs/x".parse().unwrap(),
+    );
+    assert!(request.headers().contains_key(header::AUTHORIZATION));
+
+    redirect_request(
+      &mut request,
+      StatusCode::TEMPORARY_REDIRECT,
+      "https://bucket.s3.amazonaws.com/x".parse().unwrap(),
+    );
+    assert!(!request.headers().contains_key(header::AUTHORIZATION));
+    assert_eq!(request.url().host_str(), Some("bucket.s3.amazonaws.com"));
+  }
+
+  #[test]
+  fn see_other_switches_to_get() {
+    let mut request = Request::new(Method::POST, "https://registry.example.com/token".parse().unwrap());
+    *request.body_mut() = Some(b"grant_type=refresh_token".to_vec().into());
+    redirect_request(
+      &mut request,
+      StatusCode::SEE_OTHER,
+      "https://registry.example.com/other".parse().unwrap(),
+    );
+    assert_eq!(request.method(), Method::GET);
+    assert!(request.body().is_none());
+  }
+}
@@ -0,0 +1,175 @@
+//! Compatibility with registries deviating from the distribution specification.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use log::debug;
+use reqwest::header::{self, HeaderMap};
+use serde::Deserialize;
+
+use crate::v2::*;
+
+/// Headers set by Artifactory on every response.
+const ARTIFACTORY_HEADERS: &[&str] = &["x-artifactory-id", "x-jfrog-version"];
+
+/// Prefix of the `Server` header of Nexus responses, e.g. `Nexus/3.68.1-02 (OSS)`.
+const NEXUS_SERVER_PREFIX: &str = "Nexus/";
+
+/// Maximum size of the requests uploading blobs to Nexus.
+pub(crate) const NEXUS_MAX_UPLOAD_REQUEST_SIZE: usize = 64 << 20;
+
+/// Registry implementation whose quirks the client works around, see `Config::registry_profile`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegistryProfile {
+  /// Detect the implementation from the headers of the responses of the registry, e.g. of
+  /// `Client::is_v2_supported`. Until it is detected, the registry is assumed to be standard.
+  #[default]
+  Auto,
+  /// A registry following the distribution specification.
+  Standard,
+  /// JFrog Artifactory, including its virtual repositories:
+  ///
+  /// - tag lists are paginated with `last` when Artifactory omits the `Link` header of full pages;
+  /// - `Client::push_blob_chunked` uploads the blob in a single request, as Artifactory handles uploads spanning many
+  ///   chunks poorly;
+  /// - `404 Not Found` answering a request without credentials becomes `Error::Unauthorized`, as Artifactory hides the
+  ///   repositories anonymous users may not read.
+  Artifactory,
+  /// Sonatype Nexus Repository 3:
+  ///
+  /// - tag lists are paginated with the `continuationToken` of their pages, or else with `last` when Nexus omits the
+  ///   `Link` header of full pages;
+  /// - `405 Method Not Allowed` answering a write becomes `Error::ReadOnlyRepository`, as group repositories only
+  ///   serve reads;
+  /// - blobs are uploaded in requests of at most 64 MiB, to stay below the request size limits of the proxies Nexus is
+  ///   usually deployed behind.
+  Nexus,
+}
+
+/// Registry implementation detected from responses, shared by a client and the clients derived
+/// from it.
+#[derive(Debug, Default)]
+pub(crate) struct DetectedProfile(AtomicU8);
+
+impl DetectedProfile {
+  const UNKNOWN: u8 = 0;
+  const ARTIFACTORY: u8 = 1;
+  const NEXUS: u8 = 2;
+}
+
+/// Profile the registry was handled with when it answered a request, recorded by `Client::send`
+/// for `ApiErrors::from`.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy)]
+pub(crate) struct ProfiledRequest {
+  pub(crate) profile: RegistryProfile,
+  /// Whether the request carried credentials.
+  pub(crate) authorized: bool,
+}
+
+#[derive(Deserialize)]
+struct NexusPage {
+  #[serde(rename = "continuationToken")]
+  continuation_token: Option<String>,
+}
+
+impl Client {
+  /// The implementation of the registry, as configured or detected so far.
+  pub fn registry_profile(&self) -> RegistryProfile {
+    match (self.profile, self.detected_profile.0.load(Ordering::Relaxed)) {
+      (RegistryProfile::Auto, DetectedProfile::ARTIFACTORY) => RegistryProfile::Artifactory,
+      (RegistryProfile::Auto, DetectedProfile::NEXUS) => RegistryProfile::Nexus,
+      (RegistryProfile::Auto, _) => RegistryProfile::Standard,
+      (profile, _) => profile,
+    }
+  }
+
+  /// Record the implementation of the registry if the client detects it and `headers` reveal it.
+  pub(crate) fn detect_profile(&self, headers: &HeaderMap) {
+    let detected = &self.detected_profile.0;
+    if self.profile != RegistryProfile::Auto || detected.load(Ordering::Relaxed) != DetectedProfile::UNKNOWN {
+      return;
+    }
+    let server = headers.get(header::SERVER).and_then(|v| v.to_str().ok());
+    let (profile, name) = if ARTIFACTORY_HEADERS.iter().any(|h| headers.contains_key(*h)) {
+      (DetectedProfile::ARTIFACTORY, "Artifactory")
+    } else if server.is_some_and(|s| s.starts_with(NEXUS_SERVER_PREFIX)) {
+      (DetectedProfile::NEXUS, "Nexus")
+    } else {
+      return;
+    };
+    debug!("detected {} at {}", name, self.base_url);
+    detected.store(profile, Ordering::Relaxed);
+  }
+
+  /// Record the profile the registry was handled with in `response`.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub(crate) fn mark_profiled_request(&self, authorized: bool, response: &mut reqwest::Response) {
+    let profile = self.registry_profile();
+    if profile != RegistryProfile::Standard {
+      response
+        .extensions_mut()
+        .insert(ProfiledRequest { profile, authorized });
+    }
+  }
+
+  /// The query of the page following `tags`, for registries which do not always link the next
+  /// page with the `Link` header.
+  pub(crate) fn next_tags_page(&self, paginate: Option<u32>, tags: &[String], body: &[u8]) -> Option<String> {
+    match self.registry_profile() {
+      RegistryProfile::Artifactory => next_page_after(paginate, tags),
+      RegistryProfile::Nexus => {
+        let token = serde_json::from_slice::<NexusPage>(body).ok()?.continuation_token;
+        match token {
+          Some(token) => {
+            let mut query = url::form_urlencoded::Serializer::new(String::new());
+            if let Some(n) = paginate {
+              query.append_pair("n", &n.to_string());
+            }
+            Some(query.append_pair("continuationToken", &token).finish())
+          }
+          None => next_page_after(paginate, tags),
+        }
+      }
+      RegistryProfile::Auto | RegistryProfile::Standard => None,
+    }
+  }
+
+  /// The maximum size of the requests uploading blobs, if the registry limits it.
+  pub(crate) fn max_upload_request_size(&self) -> Option<usize> {
+    match self.registry_profile() {
+      RegistryProfile::Nexus => Some(NEXUS_MAX_UPLOAD_REQUEST_SIZE),
+      _ => None,
+    }
+  }
+}
+
+/// The query of the page following `tags` with the `last` parameter, if the page is full.
+fn next_page_after(paginate: Option<u32>, tags: &[String]) -> Option<String> {
+  let n = paginate?;
+  let last = tags.last()?;
+  match tags.len() == n as usize {
+    true => Some(
+      url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("n", &n.to_string())
+        .append_pair("last", last)
+        .finish(),
+    ),
+    false => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case(Some(2), &["a", "b"] => Some("n=2&last=b".to_string()); "full page")]
+  #[test_case(Some(3), &["a", "b"] => None; "last page")]
+  #[test_case(None, &["a", "b"] => None; "not paginated")]
+  #[test_case(Some(2), &[] => None; "empty")]
+  fn next_page(paginate: Option<u32>, tags: &[&str]) -> Option<String> {
+    let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+    next_page_after(paginate, &tags)
+  }
+}
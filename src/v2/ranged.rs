@@ -0,0 +1,196 @@
+use async_stream::try_stream;
+use futures::{stream, Stream, StreamExt};
+use log::trace;
+use reqwest::{header, StatusCode};
+
+use crate::{
+  errors::{Error, Result},
+  v2::{blobs::BlobResponse, *},
+};
+
+/// Downloads of blobs as several `Range` requests in parallel.
+///
+/// Registries backed by object storage, such as S3, often serve a single download well below
+/// the available bandwidth; downloading parts of a large blob concurrently is then several times
+/// faster. Parts are streamed in order, and the blob is verified against its digest as a whole.
+/// At most `parallelism` parts are buffered in memory.
+///
+/// Registries which ignore `Range` answer the first request with the whole blob, which is then
+/// streamed as without parallel downloads.
+#[derive(Clone, Debug)]
+pub struct ParallelDownloads {
+  part_size: u64,
+  parallelism: usize,
+}
+
+impl Default for ParallelDownloads {
+  fn default() -> Self {
+    Self {
+      part_size: 16 << 20,
+      parallelism: 4,
+    }
+  }
+}
+
+impl ParallelDownloads {
+  /// Set the size of the parts, 16 MiB by default; smaller blobs are downloaded in one request.
+  pub fn part_size(mut self, bytes: u64) -> Self {
+    self.part_size = bytes.max(1);
+    self
+  }
+
+  /// Set how many parts are downloaded concurrently, 4 by default.
+  pub fn parallelism(mut self, parallelism: usize) -> Self {
+    self.parallelism = parallelism.max(1);
+    self
+  }
+}
+
+/// A blob downloaded in parts, or as a whole if the registry does not support ranges.
+pub(crate) enum Download<S> {
+  Parts(S),
+  Whole(Box<BlobResponse>),
+}
+
+impl Client {
+  /// Download a blob as parts in parallel, starting with the first part to learn its size.
+  pub(crate) async fn get_blob_parts(
+    &self,
+    name: &str,
+    digest: &str,
+    options: &ParallelDownloads,
+  ) -> Result<Download<impl Stream<Item = Result<Vec<u8>>>>> {
+    let mut content_digest = ContentDigest::try_new(digest)?;
+    let part_size = options.part_size;
+    let parallelism = options.parallelism;
+
+    let first = self.send_blob_request(name, digest, Some(&range(0, part_size))).await?;
+    let (end, total) = match (first.status(), content_range(first.headers())) {
+      (StatusCode::PARTIAL_CONTENT, Ok((0, end, Some(total)))) => (end, total),
+      // Empty blobs have no range to download.
+      (StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE, _) => {
+        return Ok(Download::Whole(Box::new(self.get_blob_response(name, digest).await?)));
+      }
+      _ => {
+        trace!("Ranges not supported, downloading {} in one request", digest);
+        let response = BlobResponse::from_response(first, content_digest).await?;
+        return Ok(Download::Whole(Box::new(response)));
+      }
+    };
+    trace!(
+      "Downloading {} bytes of {} in parts of {} bytes",
+      total,
+      digest,
+      part_size
+    );
+
+    let client = self.clone();
+    let (name, digest) = (name.to_string(), digest.to_string());
+    Ok(Download::Parts(try_stream! {
+      let part = first.bytes().await?;
+      content_digest.update(&part);
+      yield part.to_vec();
+
+      let starts = (end + 1..total).step_by(usize::try_from(part_size).unwrap_or(usize::MAX));
+      let mut parts = stream::iter(starts)
+        .map(|start| client.get_blob_part(&name, &digest, start, part_size.min(total - start)))
+        .buffered(parallelism);
+      while let Some(part) = parts.next().await {
+        let part = part?;
+        content_digest.update(&part);
+        yield part;
+      }
+      content_digest.verify()?;
+    }))
+  }
+
+  /// Download the bytes of a blob of `size` bytes from `start` on, as parts in parallel.
+  ///
+  /// The parts are not verified: the caller hashes the whole blob. Registries which ignore
+  /// `Range` fail the first part with `Error::UnexpectedHttpStatus(StatusCode::OK)`.
+  pub(crate) fn get_blob_range(
+    &self,
+    name: &str,
+    digest: &str,
+    start: u64,
+    size: u64,
+    options: &ParallelDownloads,
+  ) -> Result<impl Stream<Item = Result<Vec<u8>>>> {
+    self.repository_url(name, &format!("blobs/{}", digest))?;
+    let part_size = options.part_size;
+    let client = self.clone();
+    let (name, digest) = (name.to_string(), digest.to_string());
+    let starts = (start..size).step_by(usize::try_from(part_size).unwrap_or(usize::MAX));
+    Ok(
+      stream::iter(starts)
+        .map(move |start| {
+          let (client, name, digest) = (client.clone(), name.clone(), digest.clone());
+          async move {
+            client
+              .get_blob_part(&name, &digest, start, part_size.min(size - start))
+              .await
+          }
+        })
+        .buffered(options.parallelism),
+    )
+  }
+
+  pub(crate) async fn get_blob_part(&self, name: &str, digest: &str, start: u64, len: u64) -> Result<Vec<u8>> {
+    let res = self.send_blob_request(name, digest, Some(&range(start, len))).await?;
+
+    let status = res.status();
+    match status {
+      StatusCode::PARTIAL_CONTENT => {}
+      _ if status.is_client_error() => return Err(ApiErrors::from(res).await),
+      _ if status.is_server_error() => return Err(Error::Server { status }),
+      // The registry stopped honoring ranges, e.g. a different backend answered.
+      _ => return Err(Error::UnexpectedHttpStatus(status)),
+    }
+    match content_range(res.headers())? {
+      (s, e, _) if s == start && e == start + len - 1 => Ok(res.bytes().await?.to_vec()),
+      (s, e, _) => Err(Error::ContentRangeParse(format!("bytes {}-{}", s, e))),
+    }
+  }
+}
+
+/// The `Range` header value requesting `len` bytes from `start`.
+fn range(start: u64, len: u64) -> String {
+  format!("bytes={}-{}", start, start + len - 1)
+}
+
+/// Parse `Content-Range: bytes <start>-<end>/<total>`, where `total` may be unknown (`*`).
+fn content_range(headers: &header::HeaderMap) -> Result<(u64, u64, Option<u64>)> {
+  let value = headers
+    .get(header::CONTENT_RANGE)
+    .ok_or(Error::MissingHeader("Content-Range"))?
+    .to_str()?;
+  let parse = || {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+    let total = match total {
+      "*" => None,
+      total => Some(total.parse().ok()?),
+    };
+    (start <= end).then_some((start, end, total))
+  };
+  parse().ok_or_else(|| Error::ContentRangeParse(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case("bytes 0-1023/4096" => Some((0, 1023, Some(4096))); "known total")]
+  #[test_case("bytes 1024-2047/*" => Some((1024, 2047, None)); "unknown total")]
+  #[test_case("bytes */4096" => None; "unsatisfied")]
+  #[test_case("bytes 10-5/4096" => None; "reversed")]
+  #[test_case("items 0-1/2" => None; "unit")]
+  fn parses_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(header::CONTENT_RANGE, value.parse().unwrap());
+    content_range(&headers).ok()
+  }
+}
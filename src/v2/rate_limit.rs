@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use log::trace;
+use reqwest::{header::HeaderMap, Method, StatusCode};
+
+use crate::{
+  errors::{Error, Result},
+  v2::*,
+};
+
+/// Repository used by Docker Hub to report pull rate limits without consuming a pull.
+pub const RATELIMIT_PREVIEW_REPOSITORY: &str = "ratelimitpreview/test";
+
+/// Docker Hub pull rate limit, as reported by the `ratelimit-*` response headers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+  /// Maximum number of pulls allowed within the window.
+  pub limit: u64,
+  /// Number of pulls left within the current window.
+  pub remaining: u64,
+  /// Length of the window the limit applies to, if reported.
+  pub window: Option<Duration>,
+  /// Source the limit is accounted to (client IP or user ID), if reported.
+  pub source: Option<String>,
+}
+
+impl RateLimit {
+  /// Parse the rate limit from response headers.
+  ///
+  /// Returns `Ok(None)` if the registry reports no limit, which is the case for accounts
+  /// without pull limits.
+  pub fn from_headers(headers: &HeaderMap) -> Result<Option<Self>> {
+    let (limit, remaining) = match (headers.get("ratelimit-limit"), headers.get("ratelimit-remaining")) {
+      (Some(limit), Some(remaining)) => (limit.to_str()?, remaining.to_str()?),
+      _ => return Ok(None),
+    };
+    let (limit, window) = parse_quota(limit)?;
+    let (remaining, _) = parse_quota(remaining)?;
+    let source = match headers.get("docker-ratelimit-source") {
+      Some(source) => Some(source.to_str()?.to_string()),
+      None => None,
+    };
+
+    Ok(Some(Self {
+      limit,
+      remaining,
+      window: window.map(Duration::from_secs),
+      source,
+    }))
+  }
+}
+
+/// Parse a quota header value of the form `100;w=21600`.
+fn parse_quota(value: &str) -> Result<(u64, Option<u64>)> {
+  let invalid = || Error::RateLimitParse(value.to_string());
+  let mut parts = value.split(';').map(str::trim);
+  let quota = parts.next().unwrap_or_default().parse().map_err(|_| invalid())?;
+  let mut window = None;
+  for part in parts {
+    if let Some(w) = part.strip_prefix("w=") {
+      window = Some(w.parse().map_err(|_| invalid())?);
+    }
+  }
+  Ok((quota, window))
+}
+
+impl Client {
+  /// Fetch the current Docker Hub pull rate limit of this client.
+  ///
+  /// This performs the documented `HEAD` request on the `ratelimitpreview/test` manifest,
+  /// which does not count as a pull. The client is authenticated for that repository with its
+  /// configured credentials, so the limit reported is the one of the account, or of the client IP
+  /// for anonymous clients.
+  pub async fn hub_rate_limit(&self) -> Result<Option<RateLimit>> {
+    let scope = format!("repository:{}:pull", RATELIMIT_PREVIEW_REPOSITORY);
+    let client = self.clone().authenticate(&[&scope]).await?;
+    let url = client.repository_url(RATELIMIT_PREVIEW_REPOSITORY, "manifests/latest")?;
+
+    trace!("HEAD {:?}", url);
+
+    let r = client.send(client.build_reqwest(Method::HEAD, url)).await?;
+    match r.status() {
+      StatusCode::OK => RateLimit::from_headers(r.headers()),
+      status => Err(Error::UnexpectedHttpStatus(status)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case("100;w=21600" => (100, Some(21600)); "with window")]
+  #[test_case("76" => (76, None); "without window")]
+  #[test_case("5000; w=3600" => (5000, Some(3600)); "with whitespace")]
+  fn quota(value: &str) -> (u64, Option<u64>) {
+    parse_quota(value).unwrap()
+  }
+
+  #[test_case(""; "empty")]
+  #[test_case("many;w=21600"; "not a number")]
+  #[test_case("100;w=day"; "invalid window")]
+  fn quota_invalid(value: &str) {
+    assert!(parse_quota(value).is_err());
+  }
+}
@@ -0,0 +1,69 @@
+use log::trace;
+use reqwest::{
+  header::{self, HeaderMap},
+  Method, StatusCode, Url,
+};
+
+use crate::{errors::Result, v2::*};
+
+/// Response to a request sent with `Client::raw_request`.
+#[derive(Debug)]
+pub struct RawResponse {
+  pub status: StatusCode,
+  pub headers: HeaderMap,
+  pub body: Vec<u8>,
+}
+
+impl Client {
+  /// Send a request to an arbitrary endpoint of the registry.
+  ///
+  /// This is an escape hatch for vendor-specific endpoints which are not covered by this
+  /// client. The request goes through the same authentication, signing and certificate checks
+  /// as every other request. `path` is relative to the registry base URL, e.g.
+  /// `/api/v4/projects/1/registry/repositories`.
+  ///
+  /// A `401` response is answered by authenticating for its challenge, e.g. for the scope the
+  /// endpoint asks for, and sending the request once more. Responses with an error status are
+  /// otherwise returned as-is rather than turned into an error. Bodies are returned as sent,
+  /// except for catalogs and tag lists compressed as asked by `Config::accept_encoding`.
+  pub async fn raw_request(
+    &self,
+    method: Method,
+    path: &str,
+    headers: HeaderMap,
+    body: Option<Vec<u8>>,
+  ) -> Result<RawResponse> {
+    let url = Url::parse(&format!("{}/{}", self.base_url, path.trim_start_matches('/')))?;
+    let request = |client: &Client| {
+      let request = client
+        .build_reqwest(method.clone(), url.clone())
+        .headers(headers.clone());
+      match &body {
+        Some(body) => request.body(body.clone()),
+        None => request,
+      }
+    };
+
+    let mut res = self.send(request(self)).await?;
+    if res.status() == StatusCode::UNAUTHORIZED {
+      let challenge = res.headers().get(header::WWW_AUTHENTICATE);
+      match self.authenticate_for_challenge(challenge).await {
+        Ok(Some(client)) => res = client.send(request(&client)).await?,
+        Ok(None) => {}
+        Err(e) => trace!("raw request '{}': cannot authenticate: {}", res.url(), e),
+      }
+    }
+    let status = res.status();
+    trace!("raw request '{}' status: {:?}", res.url(), status);
+
+    let headers = res.headers().clone();
+    let limit = self.limits.max_raw_response_size;
+    // Catalogs and tag lists are the only responses asked for compressed.
+    let body = match EndpointClass::of(&url) {
+      EndpointClass::Catalog | EndpointClass::Tags => read_decoded_limited(res, limit, "raw").await,
+      _ => read_limited(res, limit, "raw").await,
+    }
+    .map_err(|e| self.deadline_error(e))?;
+    Ok(RawResponse { status, headers, body })
+  }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+
+/// Policy for following HTTP redirects, e.g. to blob storage backends.
+///
+/// Regardless of the policy, `Authorization`, `Cookie` and `Proxy-Authorization` headers are
+/// never forwarded when a redirect leads to a different host or port, so registry
+/// credentials do not leak to storage backends such as S3.
+#[derive(Clone, Debug)]
+pub struct RedirectPolicy {
+  max_redirects: usize,
+  allowed_hosts: Option<Vec<String>>,
+}
+
+impl Default for RedirectPolicy {
+  /// Follow up to 10 redirects to any host.
+  fn default() -> Self {
+    Self {
+      max_redirects: 10,
+      allowed_hosts: None,
+    }
+  }
+}
+
+impl RedirectPolicy {
+  /// Do not follow redirects; `3xx` responses are returned as-is.
+  pub fn none() -> Self {
+    Self::default().max_redirects(0)
+  }
+
+  /// Set the maximum number of redirects followed for a single request.
+  pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+    self.max_redirects = max_redirects;
+    self
+  }
+
+  /// Only follow redirects to the given hosts, besides the host the request was sent to.
+  ///
+  /// Entries starting with `*.` match any subdomain, e.g. `*.s3.amazonaws.com`.
+  pub fn allow_host(mut self, host: &str) -> Self {
+    self
+      .allowed_hosts
+      .get_or_insert_with(Vec::new)
+      .push(host.to_ascii_lowercase());
+    self
+  }
+
+  /// The maximum number of redirects followed for a single request.
+  #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+  pub(crate) fn max_redirects_allowed(&self) -> usize {
+    self.max_redirects
+  }
+
+  #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+  pub(crate) fn is_allowed(&self, host: &str, origin: Option<&str>) -> bool {
+    let allowed_hosts = match &self.allowed_hosts {
+      None => return true,
+      Some(hosts) => hosts,
+    };
+    let host = host.to_ascii_lowercase();
+    if origin.map(str::to_ascii_lowercase).as_deref() == Some(host.as_str()) {
+      return true;
+    }
+    allowed_hosts.iter().any(|allowed| match allowed.strip_prefix("*.") {
+      Some(suffix) => host.strip_suffix(suffix).is_some_and(|prefix| prefix.ends_with('.')),
+      None => *allowed == host,
+    })
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  pub(crate) fn into_reqwest(self) -> reqwest::redirect::Policy {
+    if self.max_redirects == 0 {
+      return reqwest::redirect::Policy::none();
+    }
+    reqwest::redirect::Policy::custom(move |attempt| {
+      if attempt.previous().len() > self.max_redirects {
+        return attempt.error(RedirectError::TooManyRedirects(self.max_redirects));
+      }
+      let host = attempt.url().host_str().unwrap_or_default().to_string();
+      let origin = attempt.previous().first().and_then(|u| u.host_str());
+      if self.is_allowed(&host, origin) {
+        attempt.follow()
+      } else {
+        attempt.error(RedirectError::HostNotAllowed(host))
+      }
+    })
+  }
+}
+
+/// Reason for refusing to follow a redirect.
+#[derive(Debug)]
+pub enum RedirectError {
+  TooManyRedirects(usize),
+  HostNotAllowed(String),
+}
+
+impl fmt::Display for RedirectError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RedirectError::TooManyRedirects(max) => write!(f, "more than {} redirects", max),
+      RedirectError::HostNotAllowed(host) => write!(f, "redirect to host '{}' is not allowed", host),
+    }
+  }
+}
+
+impl std::error::Error for RedirectError {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn allowed_hosts() {
+    let policy = RedirectPolicy::default()
+      .allow_host("*.s3.amazonaws.com")
+      .allow_host("cdn.example.com");
+    assert!(policy.is_allowed("bucket.s3.amazonaws.com", Some("registry.example.com")));
+    assert!(policy.is_allowed("CDN.example.com", Some("registry.example.com")));
+    assert!(policy.is_allowed("registry.example.com", Some("registry.example.com")));
+    assert!(!policy.is_allowed("s3.amazonaws.com", Some("registry.example.com")));
+    assert!(!policy.is_allowed("evils3.amazonaws.com", Some("registry.example.com")));
+    assert!(!policy.is_allowed("example.com", Some("registry.example.com")));
+    assert!(RedirectPolicy::default().is_allowed("example.com", None));
+  }
+}
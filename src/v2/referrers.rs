@@ -0,0 +1,322 @@
+//! Referrers API and Notation signatures.
+//!
+//! Referrers are artifacts (signatures, SBOMs, attestations) which point at a manifest through
+//! their `subject` field. They are discovered with the [referrers API][referrers-api], falling back
+//! to the referrers tag schema for registries which do not implement it.
+//!
+//! [referrers-api]: https://github.com/opencontainers/distribution-spec/blob/v1.1.0/spec.md#listing-referrers
+
+use std::collections::HashMap;
+
+use log::trace;
+use reqwest::{header, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::Result, mediatypes::MediaTypes, v2::*};
+
+/// Artifact type of Notation signatures.
+pub const NOTATION_SIGNATURE_ARTIFACT_TYPE: &str = "application/vnd.cncf.notary.signature";
+/// Media type of the OCI empty descriptor, used as config of artifacts.
+pub const EMPTY_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+
+const EMPTY_CONFIG: &[u8] = b"{}";
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ReferrersIndex {
+  #[serde(rename = "schemaVersion", default = "schema_version")]
+  schema_version: u16,
+  #[serde(rename = "mediaType", default = "index_media_type")]
+  media_type: String,
+  #[serde(default)]
+  manifests: Vec<Descriptor>,
+}
+
+fn schema_version() -> u16 {
+  2
+}
+
+fn index_media_type() -> String {
+  MediaTypes::OciImageIndexV1.to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ArtifactManifest {
+  #[serde(rename = "schemaVersion")]
+  schema_version: u16,
+  #[serde(rename = "mediaType")]
+  media_type: String,
+  #[serde(rename = "artifactType", default, skip_serializing_if = "Option::is_none")]
+  artifact_type: Option<String>,
+  config: Descriptor,
+  layers: Vec<Descriptor>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  subject: Option<Descriptor>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  annotations: Option<HashMap<String, String>>,
+}
+
+/// A Notation signature attached to a manifest.
+#[derive(Clone, Debug)]
+pub struct NotationSignature {
+  /// Descriptor of the signature manifest.
+  pub descriptor: Descriptor,
+  /// Media type of the envelope, `application/jose+json` or `application/cose`.
+  pub envelope_media_type: String,
+  /// The signature envelope.
+  pub envelope: Vec<u8>,
+}
+
+/// Criteria of the referrers returned by `Client::list_referrers`.
+///
+/// The artifact type is sent to the registry, and checked again for registries ignoring it;
+/// annotations are matched against the descriptors of the referrers, which carry the annotations
+/// of their manifests, so that no unrelated artifact is downloaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReferrerFilter {
+  artifact_type: Option<String>,
+  annotations: Vec<(String, Option<String>)>,
+}
+
+impl ReferrerFilter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Only return referrers of the artifact type `artifact_type`.
+  pub fn artifact_type(mut self, artifact_type: &str) -> Self {
+    self.artifact_type = Some(artifact_type.to_string());
+    self
+  }
+
+  /// Only return referrers annotated with `key` set to `value`.
+  pub fn annotation(mut self, key: &str, value: &str) -> Self {
+    self.annotations.push((key.to_string(), Some(value.to_string())));
+    self
+  }
+
+  /// Only return referrers annotated with `key`, whatever its value.
+  pub fn has_annotation(mut self, key: &str) -> Self {
+    self.annotations.push((key.to_string(), None));
+    self
+  }
+
+  /// Whether the referrer described by `descriptor` matches the filter.
+  pub fn matches(&self, descriptor: &Descriptor) -> bool {
+    let artifact_type = self.artifact_type.as_deref();
+    let annotation = |key: &str| descriptor.annotations.as_ref()?.get(key);
+    artifact_type.map_or(true, |t| descriptor.artifact_type.as_deref() == Some(t))
+      && self
+        .annotations
+        .iter()
+        .all(|(key, value)| match (annotation(key), value) {
+          (Some(actual), Some(expected)) => actual == expected,
+          (Some(_), None) => true,
+          (None, _) => false,
+        })
+  }
+}
+
+impl Client {
+  /// List the referrers of the manifest identified by `digest` which match `filter`.
+  pub async fn list_referrers(&self, name: &str, digest: &str, filter: &ReferrerFilter) -> Result<Vec<Descriptor>> {
+    let referrers = self
+      .get_referrers(name, digest, filter.artifact_type.as_deref())
+      .await?;
+    Ok(referrers.into_iter().filter(|d| filter.matches(d)).collect())
+  }
+
+  /// List the referrers of the manifest identified by `digest`.
+  ///
+  /// If `artifact_type` is given, only referrers of that type are returned. Registries without
+  /// support for the referrers API are queried through the referrers tag schema instead.
+  pub async fn get_referrers(&self, name: &str, digest: &str, artifact_type: Option<&str>) -> Result<Vec<Descriptor>> {
+    let mut url = self.repository_url(name, &format!("referrers/{}", digest))?;
+    if let Some(artifact_type) = artifact_type {
+      url.query_pairs_mut().append_pair("artifactType", artifact_type);
+    }
+
+    let res = self
+      .send(
+        self
+          .build_reqwest(Method::GET, url)
+          .header(header::ACCEPT, MediaTypes::OciImageIndexV1.to_string()),
+      )
+      .await?;
+
+    let status = res.status();
+    trace!("GET '{}' status: {:?}", res.url(), status);
+
+    let body = match status {
+      StatusCode::OK => read_limited(res, self.limits.max_referrers_size, "referrers")
+        .await
+        .map_err(|e| self.deadline_error(e))?,
+      StatusCode::NOT_FOUND => match self.get_referrers_tag(name, digest).await? {
+        Some(body) => body,
+        None => return Ok(Vec::new()),
+      },
+      _ => match self.soft_fail(OptionalFeature::Referrers, status, ApiErrors::from(res).await) {
+        Error::Unsupported(_) => return Ok(Vec::new()),
+        e => return Err(e),
+      },
+    };
+
+    check_referrers_count(&body, &self.limits)?;
+    let index: ReferrersIndex = serde_json::from_slice(&body)?;
+    Ok(
+      index
+        .manifests
+        .into_iter()
+        .filter(|d| artifact_type.map_or(true, |t| d.artifact_type.as_deref() == Some(t)))
+        .collect(),
+    )
+  }
+
+  /// Fetch the index stored under the referrers tag schema (`<alg>-<ref>`), if any.
+  async fn get_referrers_tag(&self, name: &str, digest: &str) -> Result<Option<Vec<u8>>> {
+    let url = self.repository_url(name, &format!("manifests/{}", referrers_tag(digest)?))?;
+
+    let res = self
+      .send(
+        self
+          .build_reqwest(Method::GET, url)
+          .header(header::ACCEPT, MediaTypes::OciImageIndexV1.to_string()),
+      )
+      .await?;
+
+    let status = res.status();
+    trace!("GET '{}' status: {:?}", res.url(), status);
+
+    match status {
+      StatusCode::OK => Ok(Some(
+        read_limited(res, self.limits.max_referrers_size, "referrers")
+          .await
+          .map_err(|e| self.deadline_error(e))?,
+      )),
+      StatusCode::NOT_FOUND => Ok(None),
+      _ => Err(ApiErrors::from(res).await),
+    }
+  }
+
+  /// Fetch all Notation signatures of the manifest identified by `digest`.
+  pub async fn get_notation_signatures(&self, name: &str, digest: &str) -> Result<Vec<NotationSignature>> {
+    let referrers = self
+      .get_referrers(name, digest, Some(NOTATION_SIGNATURE_ARTIFACT_TYPE))
+      .await?;
+
+    let mut signatures = Vec::with_capacity(referrers.len());
+    for descriptor in referrers {
+      let accept = [descriptor.media_type.as_str()];
+      let (body, _, _) = self.get_raw_manifest(name, &descriptor.digest, Some(&accept)).await?;
+      let manifest: ArtifactManifest = serde_json::from_slice(&body)?;
+      for layer in manifest.layers {
+        let envelope = self.get_blob(name, &layer.digest).await?;
+        signatures.push(NotationSignature {
+          descriptor: descriptor.clone(),
+          envelope_media_type: layer.media_type,
+          envelope,
+        });
+      }
+    }
+    Ok(signatures)
+  }
+
+  /// Push a Notation signature envelope as a referrer of `subject`.
+  ///
+  /// Notation expects the signing certificate thumbprints in the
+  /// `io.cncf.notary.x509chain.thumbprint#S256` annotation. Registries which do not confirm the
+  /// subject with an `OCI-Subject` header have no referrers API, so the signature is also added
+  /// to the index stored under the referrers tag schema. Returns the digest of the signature
+  /// manifest.
+  pub async fn push_notation_signature(
+    &self,
+    name: &str,
+    subject: &Descriptor,
+    envelope_media_type: &str,
+    envelope: &[u8],
+    annotations: HashMap<String, String>,
+  ) -> Result<String> {
+    self.ensure_writable()?;
+    let config_digest = sha256_digest(EMPTY_CONFIG);
+    if !self.has_blob(name, &config_digest).await? {
+      self.push_blob(name, EMPTY_CONFIG, &config_digest).await?;
+    }
+
+    let envelope_digest = sha256_digest(envelope);
+    self.push_blob(name, envelope, &envelope_digest).await?;
+
+    let manifest = ArtifactManifest {
+      schema_version: 2,
+      media_type: MediaTypes::OciImageManifest.to_string(),
+      artifact_type: Some(NOTATION_SIGNATURE_ARTIFACT_TYPE.to_string()),
+      config: Descriptor {
+        media_type: EMPTY_CONFIG_MEDIA_TYPE.to_string(),
+        digest: config_digest,
+        size: EMPTY_CONFIG.len() as u64,
+        ..Default::default()
+      },
+      layers: vec![Descriptor {
+        media_type: envelope_media_type.to_string(),
+        digest: envelope_digest,
+        size: envelope.len() as u64,
+        ..Default::default()
+      }],
+      subject: Some(Descriptor {
+        artifact_type: None,
+        annotations: None,
+        ..subject.clone()
+      }),
+      annotations: if annotations.is_empty() {
+        None
+      } else {
+        Some(annotations)
+      },
+    };
+    let body = serde_json::to_vec(&manifest)?;
+    let media_type = MediaTypes::OciImageManifest.to_string();
+    let (digest, headers) = self
+      .put_manifest_with_headers(name, &sha256_digest(&body), &media_type, &body)
+      .await?;
+
+    let confirmed = headers.get("oci-subject").and_then(|v| v.to_str().ok());
+    if confirmed != Some(subject.digest.as_str()) {
+      let descriptor = Descriptor {
+        media_type,
+        artifact_type: manifest.artifact_type,
+        digest: digest.clone(),
+        size: body.len() as u64,
+        annotations: manifest.annotations,
+        ..Default::default()
+      };
+      self.add_to_referrers_tag(name, &subject.digest, descriptor).await?;
+    }
+    Ok(digest)
+  }
+
+  /// Add `descriptor` to the index stored under the referrers tag schema for `subject`.
+  async fn add_to_referrers_tag(&self, name: &str, subject: &str, descriptor: Descriptor) -> Result<()> {
+    let mut index = match self.get_referrers_tag(name, subject).await? {
+      Some(body) => serde_json::from_slice(&body)?,
+      None => ReferrersIndex {
+        schema_version: schema_version(),
+        media_type: index_media_type(),
+        manifests: Vec::new(),
+      },
+    };
+    if index.manifests.iter().any(|d| d.digest == descriptor.digest) {
+      return Ok(());
+    }
+    index.manifests.push(descriptor);
+
+    let body = serde_json::to_vec(&index)?;
+    self
+      .put_manifest(name, &referrers_tag(subject)?, &index.media_type, &body)
+      .await?;
+    Ok(())
+  }
+}
+
+/// The tag under which the referrers of `digest` are stored by registries without a referrers API.
+fn referrers_tag(digest: &str) -> Result<String> {
+  ContentDigest::try_new(digest)?;
+  Ok(digest.replacen(':', "-", 1))
+}
@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use reqwest::{header, Method, Request, Response, StatusCode};
+
+use crate::v2::Client;
+
+/// Policy for retrying requests which failed transiently.
+///
+/// Requests are retried on connection errors, timeouts and `408`, `429`, `500`, `502`,
+/// `503` and `504` responses. Only idempotent requests (`GET`, `HEAD`, `PUT`, `DELETE`) are
+/// retried by default: a `PUT` of a manifest has the same effect when repeated. The `PUT`
+/// completing a blob upload is not idempotent, as the registry forgets the session once the
+/// blob is committed: it is only sent again after checking that the blob does not exist yet.
+/// Non-idempotent steps such as `PATCH` chunk uploads must be opted in with
+/// [`RetryPolicy::retry_non_idempotent`]. Requests with streaming bodies are never retried.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+  max_retries: u32,
+  initial_backoff: Duration,
+  max_backoff: Duration,
+  retry_non_idempotent: bool,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_retries: 3,
+      initial_backoff: Duration::from_millis(200),
+      max_backoff: Duration::from_secs(10),
+      retry_non_idempotent: false,
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// Set the maximum number of retries of a single request.
+  pub fn max_retries(mut self, max_retries: u32) -> Self {
+    self.max_retries = max_retries;
+    self
+  }
+
+  /// Set the delay before the first retry; it doubles with every further retry.
+  pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+    self.initial_backoff = backoff;
+    self
+  }
+
+  /// Set the maximum delay between retries, including delays requested with `Retry-After`.
+  pub fn max_backoff(mut self, backoff: Duration) -> Self {
+    self.max_backoff = backoff;
+    self
+  }
+
+  /// Also retry non-idempotent requests (`POST`, `PATCH`).
+  pub fn retry_non_idempotent(mut self, retry: bool) -> Self {
+    self.retry_non_idempotent = retry;
+    self
+  }
+
+  /// Whether `request` may be sent again after `attempt` retries.
+  pub(crate) fn allows(&self, request: &Request, attempt: u32) -> bool {
+    attempt < self.max_retries
+      && !is_upload_completion(request)
+      && (self.retry_non_idempotent || is_idempotent(request.method()))
+  }
+
+  /// The delay before retrying a request which returned `response`, if it should be retried.
+  pub(crate) fn response_delay(&self, response: &Response, attempt: u32) -> Option<Duration> {
+    match response.status() {
+      StatusCode::REQUEST_TIMEOUT
+      | StatusCode::TOO_MANY_REQUESTS
+      | StatusCode::INTERNAL_SERVER_ERROR
+      | StatusCode::BAD_GATEWAY
+      | StatusCode::SERVICE_UNAVAILABLE
+      | StatusCode::GATEWAY_TIMEOUT => {}
+      _ => return None,
+    }
+    let retry_after = response
+      .headers()
+      .get(header::RETRY_AFTER)
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.trim().parse().ok())
+      .map(Duration::from_secs);
+    Some(match retry_after {
+      Some(d) => d.min(self.max_backoff),
+      None => self.backoff(attempt),
+    })
+  }
+
+  /// The delay before retrying a request which failed with `error`, if it should be retried.
+  pub(crate) fn error_delay(&self, error: &reqwest::Error, attempt: u32) -> Option<Duration> {
+    if is_connect(error) || error.is_timeout() {
+      Some(self.backoff(attempt))
+    } else {
+      None
+    }
+  }
+
+  /// The delay before attempting an operation again after `attempt` retries, if allowed.
+  pub(crate) fn attempt_delay(&self, attempt: u32) -> Option<Duration> {
+    (attempt < self.max_retries).then(|| self.backoff(attempt))
+  }
+
+  fn backoff(&self, attempt: u32) -> Duration {
+    self
+      .initial_backoff
+      .saturating_mul(2u32.saturating_pow(attempt))
+      .min(self.max_backoff)
+  }
+}
+
+impl Client {
+  /// This client, retrying requests with `policy` unless it was configured with a retry policy.
+  pub(crate) fn with_default_retry_policy(&self, policy: &RetryPolicy) -> Client {
+    Client {
+      retry_policy: Some(self.retry_policy.clone().unwrap_or_else(|| policy.clone())),
+      ..self.clone()
+    }
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn is_connect(error: &reqwest::Error) -> bool {
+  error.is_connect()
+}
+
+/// Whether `error` is a network failure; `fetch` does not tell connection errors apart.
+#[cfg(target_arch = "wasm32")]
+fn is_connect(error: &reqwest::Error) -> bool {
+  error.is_request()
+}
+
+/// Whether `request` completes a blob upload, which `Client::finish_upload` retries itself.
+fn is_upload_completion(request: &Request) -> bool {
+  request.method() == Method::PUT
+    && request.url().path().contains("/blobs/uploads/")
+    && request.url().query_pairs().any(|(key, _)| key == "digest")
+}
+
+fn is_idempotent(method: &Method) -> bool {
+  matches!(
+    *method,
+    Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn backoff_is_capped() {
+    let policy = RetryPolicy::default().max_backoff(Duration::from_secs(1));
+    assert_eq!(policy.backoff(0), Duration::from_millis(200));
+    assert_eq!(policy.backoff(2), Duration::from_millis(800));
+    assert_eq!(policy.backoff(3), Duration::from_secs(1));
+    assert_eq!(policy.backoff(40), Duration::from_secs(1));
+  }
+
+  #[test]
+  fn only_idempotent_requests_by_default() {
+    let url = reqwest::Url::parse("https://example.com/v2/").unwrap();
+    let put = Request::new(Method::PUT, url.clone());
+    let patch = Request::new(Method::PATCH, url);
+
+    let policy = RetryPolicy::default();
+    assert!(policy.allows(&put, 0));
+    assert!(!policy.allows(&put, 3));
+    assert!(!policy.allows(&patch, 0));
+    assert!(policy.retry_non_idempotent(true).allows(&patch, 0));
+  }
+
+  #[test]
+  fn upload_completion_is_not_retried() {
+    let url = reqwest::Url::parse("https://example.com/v2/repo/blobs/uploads/u1?digest=sha256:abc").unwrap();
+    let finish = Request::new(Method::PUT, url);
+    assert!(!RetryPolicy::default().retry_non_idempotent(true).allows(&finish, 0));
+  }
+}
@@ -0,0 +1,236 @@
+use log::trace;
+use reqwest::{Method, StatusCode, Url};
+use serde::Deserialize;
+
+use crate::{
+  errors::{Error, Result},
+  v2::*,
+};
+
+/// Search API of a registry, used by `Client::search_with`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchProvider {
+  /// The `/v1/search` endpoint of Docker Hub, also served by Nexus and Artifactory.
+  Docker,
+  /// The `/api/v2.0/search` endpoint of Harbor, authenticated with the client's credentials.
+  Harbor,
+  /// The GraphQL search extension of zot, `/v2/_zot/ext/search`.
+  Zot,
+}
+
+/// A repository found by `Client::search`, normalized across providers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchResult {
+  /// Name of the repository, as used with the registry API.
+  pub name: String,
+  pub description: Option<String>,
+  pub stars: Option<u64>,
+  pub pulls: Option<u64>,
+  /// Whether the repository is an official image, on Docker Hub.
+  pub official: bool,
+}
+
+#[derive(Deserialize)]
+struct DockerSearch {
+  results: Vec<DockerResult>,
+}
+
+#[derive(Deserialize)]
+struct DockerResult {
+  name: String,
+  description: Option<String>,
+  star_count: Option<u64>,
+  pull_count: Option<u64>,
+  #[serde(default)]
+  is_official: bool,
+}
+
+#[derive(Deserialize)]
+struct HarborSearch {
+  #[serde(default)]
+  repository: Vec<HarborResult>,
+}
+
+#[derive(Deserialize)]
+struct HarborResult {
+  repository_name: String,
+  pull_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ZotResponse {
+  data: Option<ZotData>,
+  #[serde(default)]
+  errors: Vec<ZotError>,
+}
+
+#[derive(Deserialize)]
+struct ZotError {
+  message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ZotData {
+  global_search: ZotSearch,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ZotSearch {
+  #[serde(default)]
+  repos: Vec<ZotRepo>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ZotRepo {
+  name: String,
+  newest_image: Option<ZotImage>,
+  star_count: Option<u64>,
+  download_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ZotImage {
+  description: Option<String>,
+}
+
+/// Endpoint of the search extension of zot.
+const ZOT_SEARCH_PATH: &str = "/v2/_zot/ext/search";
+
+/// Hosts of Docker Hub, whose search is served by the index.
+const DOCKER_HUB_HOSTS: [&str; 3] = ["registry-1.docker.io", "docker.io", "index.docker.io"];
+
+/// Maximum number of results requested from the Docker search API, which caps it at 100.
+const DOCKER_PAGE_SIZE: u32 = 100;
+
+impl Client {
+  /// Search the repositories of the registry matching `query`.
+  ///
+  /// Registries often disable the catalog but allow searching. Docker Hub is searched with
+  /// [`SearchProvider::Docker`], registries listing the search extension of zot with
+  /// [`SearchProvider::Zot`]; other registries with [`SearchProvider::Harbor`], falling back to
+  /// `Docker` if they do not serve the Harbor API. Fails with `Error::SearchUnsupported` if
+  /// neither is available.
+  pub async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+    let url = Url::parse(&self.base_url)?;
+    if url.host_str().is_some_and(|host| DOCKER_HUB_HOSTS.contains(&host)) {
+      return self.search_with(SearchProvider::Docker, query).await;
+    }
+    if self.has_extension_endpoint(ZOT_SEARCH_PATH).await {
+      return self.search_with(SearchProvider::Zot, query).await;
+    }
+    match self.search_with(SearchProvider::Harbor, query).await {
+      Err(Error::SearchUnsupported(_)) => self.search_with(SearchProvider::Docker, query).await,
+      result => result,
+    }
+  }
+
+  /// Search the repositories of the registry matching `query` with the API of `provider`.
+  ///
+  /// Only the first page of results of the Docker search API is returned, up to 100 results.
+  pub async fn search_with(&self, provider: SearchProvider, query: &str) -> Result<Vec<SearchResult>> {
+    let mut url = Url::parse(&self.base_url)?;
+    let client = match provider {
+      SearchProvider::Docker => {
+        if url.host_str().is_some_and(|host| DOCKER_HUB_HOSTS.contains(&host)) {
+          url.set_host(Some("index.docker.io"))?;
+        }
+        url.set_path("/v1/search");
+        url
+          .query_pairs_mut()
+          .append_pair("q", query)
+          .append_pair("n", &DOCKER_PAGE_SIZE.to_string());
+        // Registry tokens are not valid for the search API.
+        Client {
+          auth: None,
+          ..self.clone()
+        }
+      }
+      SearchProvider::Harbor => {
+        url.set_path("/api/v2.0/search");
+        url.query_pairs_mut().append_pair("q", query);
+        self.with_basic_credentials()
+      }
+      SearchProvider::Zot => {
+        url.set_path(ZOT_SEARCH_PATH);
+        // JSON strings are valid GraphQL strings.
+        let graphql = format!(
+          "{{GlobalSearch(query: {}) {{Repos {{Name StarCount DownloadCount NewestImage {{Description}}}}}}}}",
+          serde_json::Value::from(query)
+        );
+        url.query_pairs_mut().append_pair("query", &graphql);
+        self.clone()
+      }
+    };
+
+    let r = client.send(client.build_reqwest(Method::GET, url)).await?;
+    let status = r.status();
+    trace!("GET '{}' status: {:?}", r.url(), status);
+    match status {
+      StatusCode::OK => {}
+      StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED => {
+        return Err(Error::SearchUnsupported(r.url().to_string()))
+      }
+      StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => return Err(ApiErrors::from(r).await),
+      _ => return Err(Error::UnexpectedHttpStatus(status)),
+    }
+    let body = read_limited(r, self.limits.max_catalog_size, "search results")
+      .await
+      .map_err(|e| self.deadline_error(e))?;
+
+    let results = match provider {
+      SearchProvider::Docker => serde_json::from_slice::<DockerSearch>(&body)?
+        .results
+        .into_iter()
+        .map(|r| SearchResult {
+          // Official images are listed without their `library/` namespace.
+          name: match r.is_official && !r.name.contains('/') {
+            true => format!("library/{}", r.name),
+            false => r.name,
+          },
+          description: r.description.filter(|d| !d.is_empty()),
+          stars: r.star_count,
+          pulls: r.pull_count,
+          official: r.is_official,
+        })
+        .collect(),
+      SearchProvider::Harbor => serde_json::from_slice::<HarborSearch>(&body)?
+        .repository
+        .into_iter()
+        .map(|r| SearchResult {
+          name: r.repository_name,
+          pulls: r.pull_count,
+          ..Default::default()
+        })
+        .collect(),
+      SearchProvider::Zot => {
+        let response = serde_json::from_slice::<ZotResponse>(&body)?;
+        // GraphQL errors come with a 200 status and no data.
+        let Some(data) = response.data else {
+          let errors = response
+            .errors
+            .iter()
+            .map(|e| ApiError::new("GRAPHQL").with_message(&e.message))
+            .collect();
+          return Err(Error::Api(ApiErrors::new(errors)));
+        };
+        data
+          .global_search
+          .repos
+          .into_iter()
+          .map(|r| SearchResult {
+            name: r.name,
+            description: r.newest_image.and_then(|i| i.description).filter(|d| !d.is_empty()),
+            stars: r.star_count,
+            pulls: r.download_count,
+            official: false,
+          })
+          .collect()
+      }
+    };
+    Ok(results)
+  }
+}
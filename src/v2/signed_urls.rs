@@ -0,0 +1,161 @@
+//! Reuse of the signed storage URLs registries redirect blob downloads to.
+
+use std::{
+  collections::HashMap,
+  sync::Mutex,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::trace;
+use reqwest::{header, Method, Response, Url};
+
+use crate::{errors::Result, v2::*};
+
+/// Maximum number of signed URLs kept by a client.
+const MAX_SIGNED_URLS: usize = 1024;
+
+/// Signed URLs are no longer used this long before they expire, so that downloads started with
+/// them have time to complete.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Lifetime assumed for signed URLs whose query does not tell when they expire.
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Storage URLs blob downloads were redirected to, by repository and digest, shared by a
+/// client and the clients derived from it, except those acting as an identity: every identity
+/// has its own, as a URL signed for one identity grants access without asking the registry.
+#[derive(Debug, Default)]
+pub(crate) struct SignedUrls(Mutex<HashMap<(String, String), (Url, SystemTime)>>);
+
+impl SignedUrls {
+  /// The signed URL of blob `digest` of `name`, if it is still valid at `now`.
+  fn get(&self, name: &str, digest: &str, now: SystemTime) -> Option<Url> {
+    let urls = self.0.lock().unwrap();
+    let (url, expires_at) = urls.get(&(name.to_string(), digest.to_string()))?;
+    (now + EXPIRY_MARGIN < *expires_at).then(|| url.clone())
+  }
+
+  fn insert(&self, name: &str, digest: &str, url: Url, now: SystemTime) {
+    let expires_at = expiry(&url, now);
+    let mut urls = self.0.lock().unwrap();
+    if urls.len() >= MAX_SIGNED_URLS {
+      urls.retain(|_, (_, expires_at)| now + EXPIRY_MARGIN < *expires_at);
+    }
+    if urls.len() >= MAX_SIGNED_URLS {
+      urls.clear();
+    }
+    urls.insert((name.to_string(), digest.to_string()), (url, expires_at));
+  }
+
+  fn remove(&self, name: &str, digest: &str) {
+    self.0.lock().unwrap().remove(&(name.to_string(), digest.to_string()));
+  }
+}
+
+/// When the signed `url` expires, from the parameters of the query signing it for S3, Google
+/// Cloud Storage, CloudFront or Azure Blob Storage.
+fn expiry(url: &Url, now: SystemTime) -> SystemTime {
+  let query: HashMap<String, String> = url
+    .query_pairs()
+    .map(|(k, v)| (k.to_ascii_lowercase(), v.into_owned()))
+    .collect();
+  let param = |key: &str| query.get(key).map(String::as_str);
+  let signed_for = |date: &str, expires: &str| -> Option<i64> {
+    let date = param(date)?;
+    let timestamp = format!(
+      "{}-{}-{}T{}:{}:{}Z",
+      date.get(0..4)?,
+      date.get(4..6)?,
+      date.get(6..8)?,
+      date.get(9..11)?,
+      date.get(11..13)?,
+      date.get(13..15)?
+    );
+    Some(parse_rfc3339(&timestamp)? + param(expires)?.parse::<i64>().ok()?)
+  };
+  let expires = signed_for("x-amz-date", "x-amz-expires")
+    .or_else(|| signed_for("x-goog-date", "x-goog-expires"))
+    .or_else(|| param("expires")?.parse().ok())
+    .or_else(|| parse_rfc3339(param("se")?));
+  match expires {
+    Some(secs) => UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64),
+    None => now + DEFAULT_LIFETIME,
+  }
+}
+
+impl Client {
+  /// Send a `GET` request for blob `digest` of `name`, for the bytes in `range` if given.
+  ///
+  /// If the registry redirected an earlier download of the blob to a signed storage URL which
+  /// is still valid, the request is sent there, without the credentials of the registry. The
+  /// registry is only asked again if the storage rejects the URL.
+  pub(crate) async fn send_blob_request(&self, name: &str, digest: &str, range: Option<&str>) -> Result<Response> {
+    let url = self.repository_url(name, &format!("blobs/{}", digest))?;
+    if let Some(signed_urls) = &self.signed_urls {
+      if let Some(signed) = signed_urls.get(name, digest, system_now()) {
+        // The URL is signed for the storage: neither credentials nor signatures of requests
+        // to the registry are sent there.
+        let storage = Client {
+          auth: None,
+          ..self.clone()
+        };
+        let mut request = storage.build_reqwest(Method::GET, signed);
+        if let Some(range) = range {
+          request = request.header(header::RANGE, range);
+        }
+        match storage.dispatch_unsigned(request.build()?).await {
+          Ok(res) if res.status().is_success() => return Ok(res),
+          Ok(res) => trace!("Signed URL of {} rejected with status {}", digest, res.status()),
+          Err(e) => trace!("Signed URL of {} failed: {}", digest, e),
+        }
+        signed_urls.remove(name, digest);
+      }
+    }
+
+    let mut request = self.build_reqwest(Method::GET, url.clone());
+    if let Some(range) = range {
+      request = request.header(header::RANGE, range);
+    }
+    let res = self.send(request).await?;
+    if let Some(signed_urls) = &self.signed_urls {
+      if res.status().is_success() && res.url().origin() != url.origin() {
+        trace!(
+          "Download of {} redirected to {}",
+          digest,
+          res.url().host_str().unwrap_or_default()
+        );
+        signed_urls.insert(name, digest, res.url().clone(), system_now());
+      }
+    }
+    Ok(res)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case("https://bucket.s3.amazonaws.com/blob?X-Amz-Date=20310304T050607Z&X-Amz-Expires=1200" => 1930367167 + 1200; "s3")]
+  #[test_case("https://storage.googleapis.com/blob?X-Goog-Date=20310304T050607Z&X-Goog-Expires=600" => 1930367167 + 600; "gcs")]
+  #[test_case("https://cdn.example.com/blob?Expires=1930367167&Signature=abc" => 1930367167; "cloudfront")]
+  #[test_case("https://account.blob.core.windows.net/blob?se=2031-03-04T05%3A06%3A07Z&sig=abc" => 1930367167; "azure")]
+  #[test_case("https://cdn.example.com/blob?token=abc" => 1000 + 60; "unknown")]
+  fn signed_url_expiry(url: &str) -> u64 {
+    let now = UNIX_EPOCH + Duration::from_secs(1000);
+    let expiry = expiry(&Url::parse(url).unwrap(), now);
+    expiry.duration_since(UNIX_EPOCH).unwrap().as_secs()
+  }
+
+  #[test]
+  fn expires_signed_urls_early() {
+    let urls = SignedUrls::default();
+    let now = UNIX_EPOCH + Duration::from_secs(1000);
+    let url = Url::parse("https://cdn.example.com/blob?Expires=1100").unwrap();
+    urls.insert("repo", "sha256:aa", url.clone(), now);
+    assert_eq!(urls.get("repo", "sha256:aa", now), Some(url));
+    assert_eq!(urls.get("repo", "sha256:aa", now + Duration::from_secs(80)), None);
+    assert_eq!(urls.get("other", "sha256:aa", now), None);
+  }
+}
@@ -0,0 +1,89 @@
+//! Soft failures of the optional features registries may not implement.
+
+use std::{collections::BTreeSet, fmt, sync::Mutex};
+
+use log::debug;
+use reqwest::StatusCode;
+
+use crate::{errors::Error, v2::*};
+
+/// Optional feature of the registry API, which registries may not implement, see
+/// `Config::soft_fail`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum OptionalFeature {
+  /// Listing the repositories of the registry, `/v2/_catalog`.
+  Catalog,
+  /// Deleting manifests.
+  Deletion,
+  /// Listing the referrers of manifests.
+  Referrers,
+}
+
+impl fmt::Display for OptionalFeature {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      OptionalFeature::Catalog => "the catalog",
+      OptionalFeature::Deletion => "deletion",
+      OptionalFeature::Referrers => "referrers",
+    })
+  }
+}
+
+/// Optional features the registry does not implement, shared by a client and the clients
+/// derived from it.
+#[derive(Debug, Default)]
+pub(crate) struct UnsupportedFeatures(Mutex<BTreeSet<OptionalFeature>>);
+
+impl Client {
+  /// The optional features the registry answered it does not implement, for clients with
+  /// `Config::soft_fail`.
+  ///
+  /// Clients derived from this one share them.
+  pub fn unsupported_features(&self) -> Vec<OptionalFeature> {
+    self.unsupported_features.0.lock().unwrap().iter().copied().collect()
+  }
+
+  /// `Error::Unsupported(feature)` instead of `error`, which a response with `status` to a
+  /// request for `feature` failed with, if the client soft-fails and the response tells that
+  /// the registry does not implement `feature`.
+  pub(crate) fn soft_fail(&self, feature: OptionalFeature, status: StatusCode, error: Error) -> Error {
+    if !self.soft_fail || !is_unsupported(feature, status, &error) {
+      return error;
+    }
+    debug!("{} does not support {}: {}", self.base_url, feature, error);
+    self.unsupported_features.0.lock().unwrap().insert(feature);
+    Error::Unsupported(feature)
+  }
+}
+
+/// Whether `error`, of a response with `status` to a request for `feature`, tells that the
+/// registry does not implement `feature`.
+fn is_unsupported(feature: OptionalFeature, status: StatusCode, error: &Error) -> bool {
+  match error {
+    // Writes refused by read-only repositories are supported elsewhere in the registry.
+    Error::ReadOnlyRepository { .. } => false,
+    Error::Api(errors) if errors.errors().iter().flatten().any(|e| e.code() == "UNSUPPORTED") => true,
+    _ => match status {
+      StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => true,
+      // Registries without a catalog do not route its endpoint.
+      StatusCode::NOT_FOUND => feature == OptionalFeature::Catalog,
+      _ => false,
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case(OptionalFeature::Catalog, StatusCode::NOT_FOUND => true; "catalog not found")]
+  #[test_case(OptionalFeature::Deletion, StatusCode::METHOD_NOT_ALLOWED => true; "deletion not allowed")]
+  #[test_case(OptionalFeature::Referrers, StatusCode::NOT_IMPLEMENTED => true; "referrers not implemented")]
+  #[test_case(OptionalFeature::Deletion, StatusCode::NOT_FOUND => false; "manifest not found")]
+  #[test_case(OptionalFeature::Catalog, StatusCode::UNAUTHORIZED => false; "unauthorized")]
+  fn detects_unsupported_features(feature: OptionalFeature, status: StatusCode) -> bool {
+    is_unsupported(feature, status, &Error::UnexpectedHttpStatus(status))
+  }
+}
@@ -0,0 +1,253 @@
+use std::{collections::BTreeMap, sync::Mutex};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  pin::Pin,
+  sync::Arc,
+  task::{ready, Context, Poll},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use http_body::{Body, Frame, SizeHint};
+use reqwest::{header, Url};
+
+use crate::v2::Client;
+
+/// Class of registry endpoint a request is sent to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EndpointClass {
+  /// The API version check, `/v2/`.
+  Base,
+  /// Manifests, `/v2/<name>/manifests/<reference>`.
+  Manifest,
+  /// Blob downloads and deletions, `/v2/<name>/blobs/<digest>`.
+  Blob,
+  /// Blob uploads, `/v2/<name>/blobs/uploads/`.
+  Upload,
+  /// Tag listings, `/v2/<name>/tags/list`.
+  Tags,
+  /// The catalog, `/v2/_catalog`.
+  Catalog,
+  /// Referrers, `/v2/<name>/referrers/<digest>`.
+  Referrers,
+  /// Token endpoints, redirects to storage backends and anything else.
+  Other,
+}
+
+impl EndpointClass {
+  /// The class of the endpoint `url` points to.
+  pub fn of(url: &Url) -> Self {
+    let path = url.path();
+    if !path.starts_with("/v2/") {
+      EndpointClass::Other
+    } else if path == "/v2/" {
+      EndpointClass::Base
+    } else if path == "/v2/_catalog" {
+      EndpointClass::Catalog
+    } else if path.contains("/blobs/uploads/") {
+      EndpointClass::Upload
+    } else if path.contains("/manifests/") {
+      EndpointClass::Manifest
+    } else if path.contains("/blobs/") {
+      EndpointClass::Blob
+    } else if path.contains("/referrers/") {
+      EndpointClass::Referrers
+    } else if path.ends_with("/tags/list") {
+      EndpointClass::Tags
+    } else {
+      EndpointClass::Other
+    }
+  }
+}
+
+/// Cumulative counters of the requests made by a client, see [`Client::stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientStats {
+  /// Responses received, by endpoint and status code, including responses which were retried.
+  pub responses: BTreeMap<(EndpointClass, u16), u64>,
+  /// Requests which failed without a response, e.g. because of connection errors or timeouts.
+  pub failed_requests: BTreeMap<EndpointClass, u64>,
+  /// Bytes of request bodies sent, when their length was known.
+  pub bytes_sent: u64,
+  /// Bytes of response bodies read, with or without a `Content-Length`, e.g. chunked ones.
+  ///
+  /// Bodies which were not read to the end, e.g. of cancelled downloads, count the bytes read
+  /// so far. On WebAssembly, bodies are counted as announced by their `Content-Length` instead.
+  pub bytes_received: u64,
+  /// Blob uploads which were not sent because another task was uploading the same blob to the
+  /// same repository, and reused its result instead.
+  pub coalesced_uploads: u64,
+  /// Manifest and tag list requests which were not sent because an identical request was in
+  /// progress, whose response they shared, see `Config::coalesce_requests`.
+  pub coalesced_requests: u64,
+  /// Manifests fetched by digest and served from the manifest cache.
+  pub manifest_cache_hits: u64,
+  /// Manifests fetched by digest which were not in the manifest cache.
+  pub manifest_cache_misses: u64,
+  /// Requests served from the response cache, see `Config::response_cache_size`.
+  pub response_cache_hits: u64,
+  /// Requests sent again by the retry policy.
+  pub retries: u64,
+  /// Tokens requested from the token service of the registry.
+  pub token_requests: u64,
+  /// Requests and bytes transferred by repository, for requests to the endpoints of a
+  /// repository; downloads redirected to a storage backend count for the repository.
+  pub repositories: BTreeMap<String, TransferStats>,
+}
+
+/// Requests and bytes transferred for a repository, see [`ClientStats::repositories`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransferStats {
+  /// Requests sent, with or without a response.
+  pub requests: u64,
+  /// Bytes of request bodies sent, when their length was known.
+  pub bytes_sent: u64,
+  /// Bytes of response bodies read, as counted in [`ClientStats::bytes_received`].
+  pub bytes_received: u64,
+}
+
+impl ClientStats {
+  /// Number of requests sent, with or without a response.
+  pub fn requests(&self) -> u64 {
+    self.responses.values().chain(self.failed_requests.values()).sum()
+  }
+
+  /// Number of responses received from `endpoint`, with any status.
+  pub fn responses_from(&self, endpoint: EndpointClass) -> u64 {
+    self
+      .responses
+      .iter()
+      .filter(|((e, _), _)| *e == endpoint)
+      .map(|(_, count)| count)
+      .sum()
+  }
+
+  /// Count a request to `repository`, if any, and the bytes it transferred.
+  pub(crate) fn record_transfer(&mut self, repository: Option<String>, sent: u64, received: u64) {
+    self.bytes_sent += sent;
+    self.bytes_received += received;
+    if let Some(repository) = repository {
+      let transfer = self.repositories.entry(repository).or_default();
+      transfer.requests += 1;
+      transfer.bytes_sent += sent;
+      transfer.bytes_received += received;
+    }
+  }
+
+  /// Count `received` more bytes of the body of a response from `repository`.
+  fn record_received(&mut self, repository: Option<&str>, received: u64) {
+    self.bytes_received += received;
+    if let Some(transfer) = repository.and_then(|r| self.repositories.get_mut(r)) {
+      transfer.bytes_received += received;
+    }
+  }
+}
+
+/// Counters shared by a client and the clients derived from it.
+#[derive(Debug, Default)]
+pub(crate) struct StatsCollector(Mutex<ClientStats>);
+
+impl StatsCollector {
+  pub(crate) fn update(&self, f: impl FnOnce(&mut ClientStats)) {
+    if let Ok(mut stats) = self.0.lock() {
+      f(&mut stats)
+    }
+  }
+}
+
+/// `response`, counting the bytes of its body in `stats` as they are read.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn count_received(
+  stats: Arc<StatsCollector>,
+  repository: Option<String>,
+  mut response: reqwest::Response,
+) -> reqwest::Response {
+  use reqwest::ResponseBuilderExt;
+
+  let mut builder = http::Response::builder()
+    .status(response.status())
+    .version(response.version());
+  if let Some(extensions) = builder.extensions_mut() {
+    *extensions = std::mem::take(response.extensions_mut());
+  }
+  if let Some(headers) = builder.headers_mut() {
+    *headers = std::mem::take(response.headers_mut());
+  }
+  let builder = builder.url(response.url().clone());
+  let body = CountedBody {
+    inner: http::Response::<reqwest::Body>::from(response).into_body(),
+    stats,
+    repository,
+  };
+  match builder.body(reqwest::Body::wrap(body)) {
+    Ok(response) => response.into(),
+    Err(_) => unreachable!("the parts are those of a valid response"),
+  }
+}
+
+/// Response body counting the bytes read from it, see [`count_received`].
+#[cfg(not(target_arch = "wasm32"))]
+#[pin_project::pin_project]
+struct CountedBody {
+  #[pin]
+  inner: reqwest::Body,
+  stats: Arc<StatsCollector>,
+  repository: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Body for CountedBody {
+  type Data = bytes::Bytes;
+  type Error = reqwest::Error;
+
+  fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+    let this = self.project();
+    let frame = ready!(this.inner.poll_frame(cx));
+    if let Some(data) = frame.as_ref().and_then(|f| f.as_ref().ok()).and_then(Frame::data_ref) {
+      let received = data.len() as u64;
+      this
+        .stats
+        .update(|stats| stats.record_received(this.repository.as_deref(), received));
+    }
+    Poll::Ready(frame)
+  }
+
+  fn is_end_stream(&self) -> bool {
+    self.inner.is_end_stream()
+  }
+
+  fn size_hint(&self) -> SizeHint {
+    self.inner.size_hint()
+  }
+}
+
+/// Length of the body of `request`, from its bytes or else its `Content-Length`.
+pub(crate) fn request_body_len(request: &reqwest::Request) -> u64 {
+  match request.body().and_then(|body| body.as_bytes()) {
+    Some(bytes) => bytes.len() as u64,
+    None => request
+      .headers()
+      .get(header::CONTENT_LENGTH)
+      .and_then(|v| v.to_str().ok())
+      .and_then(|v| v.parse().ok())
+      .unwrap_or(0),
+  }
+}
+
+impl Client {
+  /// Counters of the requests made since this client was built, or since the last reset.
+  ///
+  /// Clients derived from this one, e.g. by `Client::authenticate`, share its counters.
+  pub fn stats(&self) -> ClientStats {
+    self.stats.0.lock().map(|stats| stats.clone()).unwrap_or_default()
+  }
+
+  /// Reset the counters, returning their values before the reset.
+  pub fn reset_stats(&self) -> ClientStats {
+    self
+      .stats
+      .0
+      .lock()
+      .map(|mut stats| std::mem::take(&mut *stats))
+      .unwrap_or_default()
+  }
+}
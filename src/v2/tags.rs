@@ -1,22 +1,8 @@
-use std::fmt::Debug;
-
 use async_stream::try_stream;
 use log::debug;
 use reqwest::{self, header, Url};
 
-use crate::{errors::Result, v2::*};
-
-/// A chunk of tags for an image.
-///
-/// This contains a non-strict subset of the whole list of tags
-/// for an image, depending on pagination option at request time.
-#[derive(Debug, Default, Deserialize, Serialize)]
-struct TagsChunk {
-  /// Image repository name.
-  name: String,
-  /// Subset of tags.
-  tags: Vec<String>,
-}
+use crate::{errors::Result, types::TagList, v2::*};
 
 impl Client {
   /// List existing tags for an image.
@@ -25,12 +11,13 @@ impl Client {
     name: &'c str,
     paginate: Option<u32>,
   ) -> impl Stream<Item = Result<String>> + 'a {
-    let base_url = format!("{}/v2/{}/tags/list", self.base_url, name);
+    let base_url = self.repository_url(name, "tags/list").map(String::from);
     let mut link: Option<String> = None;
 
     try_stream! {
+        let base_url = base_url?;
         loop {
-            let (tags_chunk, last) = self.fetch_tags_chunk(paginate, &base_url, &link).await?;
+            let (tags_chunk, last) = self.fetch_tags_chunk(name, paginate, &base_url, &link).await?;
             for tag in tags_chunk.tags {
                 yield tag;
             }
@@ -46,10 +33,11 @@ impl Client {
 
   async fn fetch_tags_chunk(
     &self,
+    name: &str,
     paginate: Option<u32>,
     base_url: &str,
     link: &Option<String>,
-  ) -> Result<(TagsChunk, Option<String>)> {
+  ) -> Result<(TagList, Option<String>)> {
     let url_paginated = match (paginate, link) {
       (Some(p), None) => format!("{}?n={}", base_url, p),
       (None, Some(l)) => format!("{}?{}", base_url, l),
@@ -59,11 +47,16 @@ impl Client {
     let url = Url::parse(&url_paginated)?;
 
     let resp = self
-      .build_reqwest(Method::GET, url.clone())
-      .header(header::ACCEPT, "application/json")
-      .send()
-      .await?
-      .error_for_status()?;
+      .send(
+        self
+          .build_reqwest(Method::GET, url.clone())
+          .header(header::ACCEPT, "application/json"),
+      )
+      .await?;
+    if matches!(resp.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+      return Err(ApiErrors::from(resp).await);
+    }
+    let resp = resp.error_for_status()?;
 
     // ensure the CONTENT_TYPE header is application/json
     let ct_hdr = resp.headers().get(header::CONTENT_TYPE).cloned();
@@ -84,36 +77,26 @@ impl Client {
     let next = parse_link(resp.headers().get(header::LINK));
     trace!("next_page {:?}", next);
 
-    let tags_chunk = resp.json::<TagsChunk>().await?;
+    let body = read_decoded_limited(resp, self.limits.max_tag_list_size, "tag list")
+      .await
+      .map_err(|e| self.deadline_error(e))?;
+    let tags_chunk = self.parse_payload::<TagList>(&body, Payload::TagList(name))?;
+    let next = match next {
+      None => self.next_tags_page(paginate, &tags_chunk.tags, &body),
+      next => next,
+    };
     Ok((tags_chunk, next))
   }
 }
 
-/// Parse a `Link` header.
+/// Parse a `Link` header, returning the query of the next page.
 ///
 /// Format is described at https://docs.docker.com/registry/spec/api/#listing-image-tags#pagination.
-fn parse_link(hdr: Option<&header::HeaderValue>) -> Option<String> {
-  // TODO(lucab): this a brittle string-matching parser. Investigate
-  // whether there is a a common library to do this, in the future.
-
-  // Raw Header value bytes.
-  let hval = match hdr {
-    Some(v) => v,
-    None => return None,
-  };
-
-  // Header value string.
-  let sval = match hval.to_str() {
-    Ok(v) => v.to_owned(),
-    _ => return None,
-  };
-
-  // Query parameters for next page URL.
-  let uri = sval.trim_end_matches(">; rel=\"next\"");
-  let query: Vec<&str> = uri.splitn(2, '?').collect();
-  match query.get(1) {
-    //use the entire query param string since some registries have different ways of pagination
-    Some(v) if !v.is_empty() => Some(v.to_string()),
+pub(crate) fn parse_link(hdr: Option<&header::HeaderValue>) -> Option<String> {
+  let link = crate::pagination::next_link(hdr?.to_str().ok()?)?;
+  // Use the entire query param string since some registries have different ways of pagination.
+  match link.uri.split_once('?') {
+    Some((_, query)) if !query.is_empty() => Some(query.to_string()),
     _ => None,
   }
 }
@@ -0,0 +1,119 @@
+//! Clocks and timers, which the standard library and tokio do not provide on `wasm32` targets.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod imp {
+  pub(crate) use std::time::Instant;
+  use std::time::{Duration, SystemTime};
+
+  pub(crate) fn system_now() -> SystemTime {
+    SystemTime::now()
+  }
+
+  pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+  }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod imp {
+  use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+  use wasm_bindgen::JsCast;
+
+  /// Point in time, measured with the JavaScript clock in milliseconds.
+  #[derive(Clone, Copy, Debug)]
+  pub(crate) struct Instant(f64);
+
+  impl Instant {
+    pub(crate) fn now() -> Self {
+      Instant(js_sys::Date::now())
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+      Duration::from_secs_f64((js_sys::Date::now() - self.0).max(0.0) / 1000.0)
+    }
+  }
+
+  pub(crate) fn system_now() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+  }
+
+  /// Wait for `duration` with the `setTimeout` function of the JavaScript global object.
+  pub(crate) async fn sleep(duration: Duration) {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+      let global = js_sys::global();
+      let set_timeout = js_sys::Reflect::get(&global, &"setTimeout".into())
+        .ok()
+        .and_then(|f| f.dyn_into::<js_sys::Function>().ok());
+      let scheduled = set_timeout.is_some_and(|f| {
+        f.call2(&global, &resolve, &(duration.as_millis() as f64).into())
+          .is_ok()
+      });
+      if !scheduled {
+        let _ = resolve.call0(&global);
+      }
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+  }
+}
+
+pub(crate) use self::imp::{sleep, system_now, Instant};
+
+/// Parse an RFC 3339 timestamp into seconds since the Unix epoch.
+pub(crate) fn parse_rfc3339(timestamp: &str) -> Option<i64> {
+  let number = |start: usize, end: usize| -> Option<i64> {
+    let digits = timestamp.get(start..end)?;
+    digits
+      .bytes()
+      .all(|b| b.is_ascii_digit())
+      .then(|| digits.parse().ok())?
+  };
+  let (year, month, day) = (number(0, 4)?, number(5, 7)?, number(8, 10)?);
+  let (hour, minute, second) = (number(11, 13)?, number(14, 16)?, number(17, 19)?);
+  let mut rest = timestamp.get(19..)?;
+  if let Some(fraction) = rest.strip_prefix('.') {
+    rest = fraction.trim_start_matches(|c: char| c.is_ascii_digit());
+  }
+  let offset = match rest {
+    "Z" | "z" => 0,
+    _ => {
+      let sign = match rest.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+      };
+      let offset_hours: i64 = rest.get(1..3)?.parse().ok()?;
+      let offset_minutes: i64 = rest.get(4..6)?.parse().ok()?;
+      sign * (offset_hours * 3600 + offset_minutes * 60)
+    }
+  };
+  if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+    return None;
+  }
+
+  // Days since the epoch of the civil date, see http://howardhinnant.github.io/date_algorithms.html
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  let days = era * 146097 + day_of_era - 719468;
+  Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case("1970-01-01T00:00:00Z" => Some(0); "epoch")]
+  #[test_case("2031-03-04T05:06:07Z" => Some(1930367167); "utc")]
+  #[test_case("2031-03-04T05:06:07.123456789Z" => Some(1930367167); "fraction")]
+  #[test_case("2031-03-04T07:06:07+02:00" => Some(1930367167); "offset")]
+  #[test_case("2031-03-04 05:06:07" => None; "no offset")]
+  #[test_case("2031-13-04T05:06:07Z" => None; "invalid month")]
+  fn rfc3339(timestamp: &str) -> Option<i64> {
+    parse_rfc3339(timestamp)
+  }
+}
@@ -0,0 +1,210 @@
+//! Persistence of bearer tokens across processes.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  collections::BTreeMap,
+  fs, io,
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+use std::{
+  fmt,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::blob_store::staging_beside;
+use crate::v2::*;
+
+/// Tokens expiring sooner than this are not reused, so they do not expire mid-request.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+
+/// A bearer token obtained from a token service, as saved in a [`TokenStore`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StoredToken {
+  pub(crate) token: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) refresh_token: Option<String>,
+  /// Expiry, in seconds since the Unix epoch.
+  pub(crate) expires_at: u64,
+}
+
+impl StoredToken {
+  /// Time the token expires at.
+  pub fn expires_at(&self) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(self.expires_at)
+  }
+
+  /// Whether the token has expired, or is about to.
+  pub fn is_expired(&self) -> bool {
+    self.expires_at() <= system_now() + EXPIRY_MARGIN
+  }
+
+  /// Whether the token can be renewed without credentials once expired.
+  pub fn has_refresh_token(&self) -> bool {
+    self.refresh_token.is_some()
+  }
+}
+
+/// Hook persisting the bearer tokens obtained by `Client::authenticate`, e.g. in a keychain.
+///
+/// Tokens are identified by a key covering the token service, the requested scopes and the
+/// username. Errors of the store are logged and otherwise ignored: authentication then falls
+/// back to the token service.
+pub trait TokenStore: fmt::Debug + Send + Sync {
+  /// Return the token saved for `key`, if any.
+  fn load(&self, key: &str) -> std::result::Result<Option<StoredToken>, HookError>;
+
+  /// Save the token for `key`, replacing any previous one.
+  fn save(&self, key: &str, token: &StoredToken) -> std::result::Result<(), HookError>;
+}
+
+/// Token store in a JSON file, readable by its owner only on Unix.
+///
+/// Expired tokens which cannot be refreshed are dropped from the file when saving. Saves hold an
+/// advisory lock on `<path>.lock`, so that processes sharing the file do not lose each other's
+/// tokens; on platforms other than Unix, only saves within the process are serialized.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct FileTokenStore {
+  path: PathBuf,
+  lock: Mutex<()>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileTokenStore {
+  /// Use the JSON file at `path`; the file is created on first save.
+  pub fn new<P: AsRef<Path>>(path: P) -> Self {
+    Self {
+      path: path.as_ref().to_path_buf(),
+      lock: Mutex::new(()),
+    }
+  }
+
+  fn read(&self) -> std::result::Result<BTreeMap<String, StoredToken>, HookError> {
+    match fs::read(&self.path) {
+      Ok(content) => Ok(serde_json::from_slice(&content)?),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+      Err(e) => Err(e.into()),
+    }
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TokenStore for FileTokenStore {
+  fn load(&self, key: &str) -> std::result::Result<Option<StoredToken>, HookError> {
+    let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(self.read()?.remove(key))
+  }
+
+  fn save(&self, key: &str, token: &StoredToken) -> std::result::Result<(), HookError> {
+    let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+    let _file_lock = FileLock::acquire(&self.path)?;
+    let mut tokens = self.read()?;
+    tokens.retain(|_, t| !t.is_expired() || t.has_refresh_token());
+    tokens.insert(key.to_string(), token.clone());
+
+    // Write to a staging file first, so a crash never leaves a truncated file behind.
+    let (staged, mut file) = staging_beside(&self.path).create_file()?;
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    io::Write::write_all(&mut file, &serde_json::to_vec(&tokens)?)?;
+    drop(file);
+    staged.persist(&self.path)?;
+    Ok(())
+  }
+}
+
+/// Exclusive advisory lock on the lock file `<path>.lock` of a file shared by processes, released
+/// when dropped. Only Unix has such locks: elsewhere, the lock file is merely created.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct FileLock {
+  _file: fs::File,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileLock {
+  pub(crate) fn acquire(path: &Path) -> io::Result<Self> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+      fs::create_dir_all(parent)?;
+    }
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = fs::OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(lock_path)?;
+    #[cfg(unix)]
+    {
+      use std::os::unix::io::AsRawFd;
+
+      // SAFETY: the descriptor stays open for as long as `file`.
+      while unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+          return Err(e);
+        }
+      }
+    }
+    Ok(Self { _file: file })
+  }
+}
+
+impl Client {
+  /// Load the token saved for `key`, if a store is configured.
+  pub(crate) fn load_token(&self, key: &str) -> Option<StoredToken> {
+    let store = self.token_store.as_ref()?;
+    store.load(key).unwrap_or_else(|e| {
+      warn!("cannot load token from {:?}: {}", store, e);
+      None
+    })
+  }
+
+  /// Save the token for `key`, if a store is configured.
+  pub(crate) fn save_token(&self, key: &str, token: &StoredToken) {
+    if let Some(store) = &self.token_store {
+      if let Err(e) = store.save(key, token) {
+        warn!("cannot save token to {:?}: {}", store, e);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn concurrent_stores_keep_all_tokens() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tokens.json");
+    let token = StoredToken {
+      token: "token".to_string(),
+      refresh_token: None,
+      expires_at: u64::MAX / 2,
+    };
+
+    // Separate stores share no in-process lock, as in separate processes.
+    std::thread::scope(|scope| {
+      for i in 0..8 {
+        let (path, token) = (&path, &token);
+        scope.spawn(move || {
+          let store = FileTokenStore::new(path);
+          for j in 0..10 {
+            store.save(&format!("{i}-{j}"), token).unwrap();
+          }
+        });
+      }
+    });
+
+    let store = FileTokenStore::new(&path);
+    assert_eq!(store.read().unwrap().len(), 80);
+  }
+}
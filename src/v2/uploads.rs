@@ -0,0 +1,722 @@
+use std::{
+  fs, io,
+  path::{Path, PathBuf},
+  sync::Mutex,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::{Stream, TryStreamExt};
+use log::{debug, trace, warn};
+use reqwest::{header, Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::StreamReader;
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::token_store::FileLock;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::blob_store::staging_beside;
+use crate::{
+  errors::{Error, Result},
+  v2::*,
+};
+
+/// An in-progress blob upload session.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct UploadSession {
+  name: String,
+  location: String,
+  uuid: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  digest: Option<String>,
+  started_at: u64,
+  #[serde(default)]
+  offset: u64,
+}
+
+impl UploadSession {
+  /// Repository the blob is uploaded to.
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// Absolute URL of the upload session, as returned by the registry.
+  pub fn location(&self) -> &str {
+    &self.location
+  }
+
+  /// Session identifier from the `Docker-Upload-UUID` header, or else the last segment of the
+  /// initial upload URL.
+  pub fn uuid(&self) -> Option<&str> {
+    self.uuid.as_deref()
+  }
+
+  /// Digest of the blob being uploaded, if it was known when the session started.
+  pub fn digest(&self) -> Option<&str> {
+    self.digest.as_deref()
+  }
+
+  /// Number of bytes the registry has committed so far.
+  pub fn offset(&self) -> u64 {
+    self.offset
+  }
+
+  /// Time the session was started at.
+  pub fn started_at(&self) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(self.started_at)
+  }
+
+  /// Whether `other` is the same session, possibly at a later location.
+  ///
+  /// Registries may move a session to a new URL after each chunk, so sessions are told apart
+  /// by repository, digest and session identifier rather than by location.
+  fn is_same(&self, other: &UploadSession) -> bool {
+    self.name == other.name
+      && self.digest == other.digest
+      && match (&self.uuid, &other.uuid) {
+        (Some(a), Some(b)) => a == b,
+        _ => self.location == other.location,
+      }
+  }
+}
+
+/// Persistent record of the upload sessions started by a client.
+///
+/// Sessions are added when an upload starts and removed once it is completed or
+/// cancelled, so sessions left behind by crashed processes can be cancelled later
+/// with [`Client::cancel_stale_uploads`]. Updates hold an advisory lock on `<path>.lock`, so
+/// that processes sharing the journal do not lose each other's sessions.
+#[derive(Debug)]
+pub struct UploadJournal {
+  path: PathBuf,
+  lock: Mutex<()>,
+}
+
+impl UploadJournal {
+  /// Use the JSON file at `path` as journal; the file is created on first write.
+  pub fn new<P: AsRef<Path>>(path: P) -> Self {
+    Self {
+      path: path.as_ref().to_path_buf(),
+      lock: Mutex::new(()),
+    }
+  }
+
+  /// List the sessions currently recorded in the journal.
+  pub fn sessions(&self) -> Result<Vec<UploadSession>> {
+    let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+    self.read()
+  }
+
+  /// Record a new session, or the new location and offset of a recorded one.
+  pub fn record(&self, session: &UploadSession) -> Result<()> {
+    self.update(|sessions| match sessions.iter_mut().find(|s| s.is_same(session)) {
+      Some(recorded) => *recorded = session.clone(),
+      None => sessions.push(session.clone()),
+    })
+  }
+
+  /// Remove a session, if present.
+  pub fn remove(&self, session: &UploadSession) -> Result<()> {
+    self.update(|sessions| sessions.retain(|s| !s.is_same(session)))
+  }
+
+  fn update<F: FnOnce(&mut Vec<UploadSession>)>(&self, f: F) -> Result<()> {
+    let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+    #[cfg(not(target_arch = "wasm32"))]
+    let _file_lock = FileLock::acquire(&self.path)?;
+    let mut sessions = self.read()?;
+    f(&mut sessions);
+    self.write(&sessions)
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  fn write(&self, sessions: &[UploadSession]) -> Result<()> {
+    // Write to a staging file first, so a crash never leaves a truncated journal behind.
+    let (staged, mut file) = staging_beside(&self.path).create_file()?;
+    io::Write::write_all(&mut file, &serde_json::to_vec(sessions)?)?;
+    drop(file);
+    staged.persist(&self.path)
+  }
+
+  #[cfg(target_arch = "wasm32")]
+  fn write(&self, sessions: &[UploadSession]) -> Result<()> {
+    Ok(fs::write(&self.path, serde_json::to_vec(sessions)?)?)
+  }
+
+  fn read(&self) -> Result<Vec<UploadSession>> {
+    match fs::read(&self.path) {
+      Ok(content) => Ok(serde_json::from_slice(&content)?),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+      Err(e) => Err(e.into()),
+    }
+  }
+}
+
+/// Byte range committed by the registry for an upload session.
+///
+/// This is parsed from the `Range` header of upload responses, which has the form
+/// `0-<end>` with an inclusive end. Some registries (e.g. Artifactory) prefix it with `bytes=`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UploadRange {
+  pub start: u64,
+  pub end: u64,
+}
+
+impl UploadRange {
+  /// Parse the value of a `Range` header.
+  pub fn parse(value: &str) -> Result<Self> {
+    let range = value.trim();
+    let range = range.strip_prefix("bytes=").unwrap_or(range);
+    let (start, end) = range
+      .split_once('-')
+      .ok_or_else(|| Error::UploadRangeParse(value.to_string()))?;
+    let parse = |s: &str| {
+      s.trim()
+        .parse::<u64>()
+        .map_err(|_| Error::UploadRangeParse(value.to_string()))
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+      return Err(Error::UploadRangeParse(value.to_string()));
+    }
+    Ok(Self { start, end })
+  }
+
+  /// Offset at which the upload should continue.
+  ///
+  /// Registries report an empty upload as `0-0`, which is indistinguishable from a single
+  /// committed byte; it is treated as empty so that the upload restarts from the beginning.
+  pub fn next_offset(&self) -> u64 {
+    self.next_offset_after(0)
+  }
+
+  /// Offset at which the upload should continue, knowing that the registry committed at least
+  /// `committed` bytes, which tells `0-0` for a single committed byte apart from an empty upload.
+  pub(crate) fn next_offset_after(&self, committed: u64) -> u64 {
+    match (self.start, self.end) {
+      (0, 0) if committed == 0 => 0,
+      (_, end) => end + 1,
+    }
+  }
+}
+
+/// Maximum number of consecutive chunk uploads without progress before giving up.
+const MAX_STALLED_CHUNKS: usize = 3;
+
+/// Size of the chunks of blobs uploaded as they are downloaded, see `Client::upload_blob_stream`.
+const STREAMED_UPLOAD_CHUNK_SIZE: usize = 16 << 20;
+
+impl Client {
+  /// Start a new blob upload session in the given repository.
+  pub async fn start_upload(&self, name: &str) -> Result<UploadSession> {
+    self.start_upload_of(name, None).await
+  }
+
+  /// Start an upload session for the blob with the given digest, if known.
+  pub(crate) async fn start_upload_of(&self, name: &str, digest: Option<&str>) -> Result<UploadSession> {
+    let url = self.repository_url(name, "blobs/uploads/")?;
+
+    let res = self.send(self.build_reqwest(Method::POST, url.clone())).await?;
+    let status = res.status();
+    trace!("POST {} status: {}", url, status);
+
+    match status {
+      StatusCode::ACCEPTED => {}
+      _ => return Err(ApiErrors::from(res).await),
+    }
+
+    let location = upload_location(&url, res.headers())?;
+    let uuid = match res.headers().get("docker-upload-uuid") {
+      Some(v) => Some(v.to_str()?.to_string()),
+      None => location
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string),
+    };
+    let session = UploadSession {
+      name: name.to_string(),
+      location: location.to_string(),
+      uuid,
+      digest: digest.map(str::to_string),
+      started_at: time::system_now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs(),
+      offset: 0,
+    };
+
+    if let Some(journal) = &self.upload_journal {
+      journal.record(&session)?;
+    }
+    Ok(session)
+  }
+
+  /// Query the registry for the progress of an upload session, updating the session offset.
+  pub async fn upload_status(&self, session: &mut UploadSession) -> Result<UploadRange> {
+    let url = Url::parse(&session.location)?;
+    let res = self.send(self.build_reqwest(Method::GET, url.clone())).await?;
+    let status = res.status();
+    trace!("GET {} status: {}", url, status);
+
+    match status {
+      StatusCode::NO_CONTENT | StatusCode::OK => {}
+      _ => return Err(ApiErrors::from(res).await),
+    }
+    let committed = session.offset;
+    self.update_session(session, &url, res.headers(), committed)
+  }
+
+  /// Upload a chunk of data starting at the session offset.
+  ///
+  /// Returns the range committed by the registry. The session offset is updated to the
+  /// committed range, which may differ from the data sent: if the registry rejects the
+  /// chunk with `416 Range Not Satisfiable`, the upload progress is queried and the
+  /// session resynchronized so the caller can continue from the reported offset.
+  pub async fn upload_chunk(&self, session: &mut UploadSession, data: &[u8]) -> Result<UploadRange> {
+    let url = Url::parse(&session.location)?;
+    let start = session.offset;
+    let end = (start + data.len() as u64).saturating_sub(1);
+
+    let res = self
+      .send(
+        self
+          .build_reqwest(Method::PATCH, url.clone())
+          .header(header::CONTENT_TYPE, "application/octet-stream")
+          .header(header::CONTENT_RANGE, format!("{}-{}", start, end))
+          .header(header::CONTENT_LENGTH, data.len())
+          .body(data.to_vec()),
+      )
+      .await?;
+    let status = res.status();
+    trace!("PATCH {} ({}-{}) status: {}", url, start, end, status);
+
+    match status {
+      // The registry accepted the chunk, so it committed at least one byte of it.
+      StatusCode::ACCEPTED | StatusCode::NO_CONTENT => {
+        let committed = start + data.len().min(1) as u64;
+        self.update_session(session, &url, res.headers(), committed)
+      }
+      StatusCode::RANGE_NOT_SATISFIABLE => {
+        debug!("Registry rejected range {}-{}, resynchronizing upload", start, end);
+        self.upload_status(session).await
+      }
+      _ => Err(ApiErrors::from(res).await),
+    }
+  }
+
+  /// Complete an upload session, optionally sending the final chunk of data.
+  ///
+  /// Returns the digest of the blob as reported by the registry.
+  pub async fn finish_upload(&self, session: &UploadSession, digest: &str, data: Option<&[u8]>) -> Result<String> {
+    let body = match data {
+      Some(data) if !data.is_empty() => Some((reqwest::Body::from(data.to_vec()), data.len() as u64)),
+      _ => None,
+    };
+    self.finish_upload_body(session, digest, body).await
+  }
+
+  /// Complete an upload session, sending `body` of the given length as the final chunk.
+  ///
+  /// Completing an upload is not idempotent: the registry may have committed the blob before the
+  /// response was lost, and then no longer knows the session. With a retry policy, the blob is
+  /// looked up before the request is sent again, and an existing blob completes the upload.
+  /// Streaming bodies are never sent again.
+  pub(crate) async fn finish_upload_body(
+    &self,
+    session: &UploadSession,
+    digest: &str,
+    body: Option<(reqwest::Body, u64)>,
+  ) -> Result<String> {
+    let mut url = Url::parse(&session.location)?;
+    url.query_pairs_mut().append_pair("digest", digest);
+
+    let replay = match &body {
+      None => Some(None),
+      Some((body, len)) => body.as_bytes().map(|bytes| Some((bytes.to_vec(), *len))),
+    };
+    let policy = self.retry_policy.as_ref().filter(|_| replay.is_some());
+
+    let mut body = body;
+    let mut attempt = 0;
+    let res = loop {
+      let mut req = self.build_reqwest(Method::PUT, url.clone());
+      req = match body.take() {
+        Some((body, len)) => req
+          .header(header::CONTENT_LENGTH, len)
+          .header(header::CONTENT_TYPE, "application/octet-stream")
+          .body(body),
+        None => req.header(header::CONTENT_LENGTH, 0),
+      };
+
+      let result = self.send(req).await;
+      let delay = match (policy, &result) {
+        (Some(policy), Ok(res)) => policy.response_delay(res, attempt),
+        (Some(policy), Err(Error::Reqwest(e))) => policy.error_delay(e, attempt),
+        _ => None,
+      };
+      let delay = match policy.and_then(|p| p.attempt_delay(attempt)).and(delay) {
+        Some(delay) => delay,
+        None => break result?,
+      };
+
+      if self.has_blob(&session.name, digest).await? {
+        debug!("blob {} was committed before the upload failed", digest);
+        if let Some(journal) = &self.upload_journal {
+          journal.remove(session)?;
+        }
+        return Ok(digest.to_string());
+      }
+      debug!("retrying PUT {} in {:?} (attempt {})", url, delay, attempt + 1);
+      time::sleep(delay).await;
+      body = replay
+        .clone()
+        .flatten()
+        .map(|(bytes, len)| (reqwest::Body::from(bytes), len));
+      attempt += 1;
+    };
+    let status = res.status();
+    trace!("PUT {} status: {}", url, status);
+
+    match status {
+      StatusCode::CREATED | StatusCode::NO_CONTENT | StatusCode::OK => {}
+      _ => return Err(ApiErrors::from(res).await),
+    }
+
+    if let Some(journal) = &self.upload_journal {
+      journal.remove(session)?;
+    }
+
+    match res.headers().get("docker-content-digest") {
+      Some(v) => Ok(v.to_str()?.to_string()),
+      None => Ok(digest.to_string()),
+    }
+  }
+
+  /// Upload a blob in a single request.
+  ///
+  /// Blobs larger than the request size limit of the registry are uploaded in chunks, see
+  /// [`RegistryProfile::Nexus`]. Tasks pushing the same blob to the same repository at the
+  /// same time with this client, or clients derived from it, upload it once: the others wait
+  /// for that upload and return its result.
+  pub async fn push_blob(&self, name: &str, data: &[u8], digest: &str) -> Result<String> {
+    self
+      .coalesce_upload(name, digest, || self.upload_blob(name, data, digest))
+      .await
+  }
+
+  /// Upload a blob as `push_blob` does, without waiting for concurrent uploads of the blob.
+  pub(crate) async fn upload_blob(&self, name: &str, data: &[u8], digest: &str) -> Result<String> {
+    if let Some(limit) = self.max_upload_request_size().filter(|limit| data.len() > *limit) {
+      return self.upload_blob_chunked(name, data, digest, limit).await;
+    }
+    self.push_blob_monolithic(name, data, digest).await
+  }
+
+  async fn push_blob_monolithic(&self, name: &str, data: &[u8], digest: &str) -> Result<String> {
+    let session = self.start_upload_of(name, Some(digest)).await?;
+    self.finish_upload(&session, digest, Some(data)).await
+  }
+
+  /// Upload a blob in chunks of at most `chunk_size` bytes.
+  ///
+  /// Chunks rejected by the registry, or only partially committed, are resent from the
+  /// offset reported by the registry instead of failing the whole upload. Blobs pushed to
+  /// Artifactory are uploaded in a single request, see [`RegistryProfile::Artifactory`].
+  /// Concurrent pushes of the same blob are coalesced as with `push_blob`.
+  pub async fn push_blob_chunked(&self, name: &str, data: &[u8], digest: &str, chunk_size: usize) -> Result<String> {
+    self
+      .coalesce_upload(name, digest, || {
+        self.upload_blob_chunked(name, data, digest, chunk_size)
+      })
+      .await
+  }
+
+  async fn upload_blob_chunked(&self, name: &str, data: &[u8], digest: &str, chunk_size: usize) -> Result<String> {
+    if self.registry_profile() == RegistryProfile::Artifactory {
+      return self.push_blob_monolithic(name, data, digest).await;
+    }
+    let chunk_size = match self.max_upload_request_size() {
+      Some(limit) => chunk_size.min(limit),
+      None => chunk_size,
+    };
+    let chunk_size = chunk_size.max(1);
+    let mut session = self.start_upload_of(name, Some(digest)).await?;
+
+    let mut stalled = 0;
+    while (session.offset as usize) < data.len() {
+      let start = session.offset as usize;
+      let end = std::cmp::min(start + chunk_size, data.len());
+      self.upload_chunk(&mut session, &data[start..end]).await?;
+
+      if session.offset as usize == end {
+        stalled = 0;
+      } else {
+        stalled += 1;
+        if stalled >= MAX_STALLED_CHUNKS {
+          return Err(Error::UploadStalled(session.offset));
+        }
+      }
+    }
+
+    self.finish_upload(&session, digest, None).await
+  }
+
+  /// Upload a blob read from `reader`, in chunks of at most `chunk_size` bytes.
+  ///
+  /// The digest is computed while reading. If `digest` is given and does not match the
+  /// data, the upload is cancelled before it is committed. Returns the digest of the blob.
+  pub async fn push_blob_reader<R>(
+    &self,
+    name: &str,
+    reader: R,
+    digest: Option<&str>,
+    chunk_size: usize,
+  ) -> Result<String>
+  where
+    R: AsyncRead + Send,
+  {
+    self.upload_reader(name, reader, digest, chunk_size).await
+  }
+
+  /// Upload the blob `digest` downloaded as `chunks`, as `push_blob_reader` does, in chunks of
+  /// at most `STREAMED_UPLOAD_CHUNK_SIZE` bytes or the request size limit of the registry.
+  ///
+  /// Errors of `chunks` fail the upload as they are.
+  pub(crate) async fn upload_blob_stream<S>(&self, name: &str, chunks: S, digest: &str) -> Result<String>
+  where
+    S: Stream<Item = Result<Vec<u8>>>,
+  {
+    let chunk_size = self.max_upload_request_size().unwrap_or(STREAMED_UPLOAD_CHUNK_SIZE);
+    let reader = StreamReader::new(
+      chunks
+        .map_ok(bytes::Bytes::from)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e)),
+    );
+    self.upload_reader(name, reader, Some(digest), chunk_size).await
+  }
+
+  async fn upload_reader<R: AsyncRead>(
+    &self,
+    name: &str,
+    reader: R,
+    digest: Option<&str>,
+    chunk_size: usize,
+  ) -> Result<String> {
+    tokio::pin!(reader);
+    let mut buf = vec![0; chunk_size.max(1)];
+    let mut hasher = match digest {
+      Some(digest) => DigestAlgorithm::of(digest)?,
+      None => DigestAlgorithm::sha256(),
+    };
+    // Only start the session once there is data, or the end of it, to upload.
+    let mut len = read_full(&mut reader, &mut buf).await.map_err(read_error)?;
+    let mut session = self.start_upload_of(name, digest).await?;
+
+    while len > 0 {
+      hasher.update(&buf[..len]);
+
+      // Resend the part of the chunk which the registry did not commit. Data before the
+      // chunk cannot be read again, so progress must not go backwards.
+      let chunk_start = session.offset;
+      let chunk_end = chunk_start + len as u64;
+      let mut stalled = 0;
+      while session.offset < chunk_end {
+        if session.offset < chunk_start {
+          return Err(Error::UploadStalled(session.offset));
+        }
+        let previous = session.offset;
+        let from = (session.offset - chunk_start) as usize;
+        self.upload_chunk(&mut session, &buf[from..len]).await?;
+
+        if session.offset > previous {
+          stalled = 0;
+        } else {
+          stalled += 1;
+          if stalled >= MAX_STALLED_CHUNKS {
+            return Err(Error::UploadStalled(session.offset));
+          }
+        }
+      }
+      len = read_full(&mut reader, &mut buf).await.map_err(read_error)?;
+    }
+
+    let computed = hasher.try_digest()?;
+    if let Some(expected) = digest {
+      if expected != computed {
+        self.cancel_upload(&session).await?;
+        return Err(
+          ContentDigestError::Verify {
+            expected: expected.to_string(),
+            got: computed,
+          }
+          .into(),
+        );
+      }
+    }
+    self.finish_upload(&session, &computed, None).await
+  }
+
+  /// Update the session to the range reported by the registry, which is known to have committed
+  /// at least `committed` bytes.
+  fn update_session(
+    &self,
+    session: &mut UploadSession,
+    url: &Url,
+    headers: &header::HeaderMap,
+    committed: u64,
+  ) -> Result<UploadRange> {
+    let range = match headers.get(header::RANGE) {
+      Some(v) => UploadRange::parse(v.to_str()?)?,
+      None => return Err(Error::MissingHeader("Range")),
+    };
+    session.offset = range.next_offset_after(committed);
+    if headers.contains_key(header::LOCATION) {
+      let location = upload_location(url, headers)?.to_string();
+      if location != session.location {
+        session.location = location;
+        // Keep the journal pointing at the current location, so the session can still be cancelled.
+        if let Some(journal) = &self.upload_journal {
+          journal.record(session)?;
+        }
+      }
+    }
+    Ok(range)
+  }
+
+  /// Cancel an upload session, discarding any uploaded data.
+  ///
+  /// Sessions which the registry does not know (anymore) are considered cancelled.
+  pub async fn cancel_upload(&self, session: &UploadSession) -> Result<()> {
+    let url = Url::parse(&session.location)?;
+    let res = self.send(self.build_reqwest(Method::DELETE, url.clone())).await?;
+    let status = res.status();
+    trace!("DELETE {} status: {}", url, status);
+
+    match status {
+      StatusCode::NO_CONTENT | StatusCode::OK | StatusCode::NOT_FOUND => {}
+      _ => return Err(ApiErrors::from(res).await),
+    }
+
+    if let Some(journal) = &self.upload_journal {
+      journal.remove(session)?;
+    }
+    Ok(())
+  }
+
+  /// Cancel the sessions recorded in the configured journal which are older than `max_age`.
+  ///
+  /// Returns the cancelled sessions. Sessions which fail to cancel are kept in the journal.
+  pub async fn cancel_stale_uploads(&self, max_age: Duration) -> Result<Vec<UploadSession>> {
+    self.ensure_writable()?;
+    let journal = self.upload_journal.as_ref().ok_or(Error::NoUploadJournal)?;
+    let now = time::system_now();
+
+    let mut cancelled = Vec::new();
+    for session in journal.sessions()? {
+      let age = now.duration_since(session.started_at()).unwrap_or_default();
+      if age < max_age {
+        continue;
+      }
+      match self.cancel_upload(&session).await {
+        Ok(()) => cancelled.push(session),
+        Err(e) => warn!("failed to cancel upload session {}: {}", session.location, e),
+      }
+    }
+    Ok(cancelled)
+  }
+}
+
+/// Resolve the `Location` header of an upload response, which may be relative.
+pub(crate) fn upload_location(url: &Url, headers: &header::HeaderMap) -> Result<Url> {
+  let location = headers
+    .get(header::LOCATION)
+    .ok_or(Error::MissingHeader("Location"))?
+    .to_str()?;
+  Ok(url.join(location)?)
+}
+
+/// Fill `buf` from `reader`, returning fewer bytes only at the end of the stream.
+/// The error of a stream uploaded with `Client::upload_blob_stream`, or else `error` itself.
+fn read_error(error: io::Error) -> Error {
+  if !error.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+    return error.into();
+  }
+  match error.into_inner().map(|inner| inner.downcast::<Error>()) {
+    Some(Ok(error)) => *error,
+    _ => unreachable!("the inner error is an `Error`"),
+  }
+}
+
+async fn read_full<R: AsyncRead + Unpin + ?Sized>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+  let mut len = 0;
+  while len < buf.len() {
+    match reader.read(&mut buf[len..]).await? {
+      0 => break,
+      n => len += n,
+    }
+  }
+  Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case("0-1023" => Some((0, 1023, 1024)); "docker distribution")]
+  #[test_case("bytes=0-1023" => Some((0, 1023, 1024)); "artifactory")]
+  #[test_case("0-0" => Some((0, 0, 0)); "empty upload")]
+  #[test_case(" 0 - 9 " => Some((0, 9, 10)); "whitespace")]
+  #[test_case("10-0" => None; "inverted")]
+  #[test_case("1024" => None; "missing end")]
+  #[test_case("bytes=a-b" => None; "not numeric")]
+  fn upload_range_parse(value: &str) -> Option<(u64, u64, u64)> {
+    UploadRange::parse(value)
+      .ok()
+      .map(|r| (r.start, r.end, r.next_offset()))
+  }
+
+  #[test_case("0-0", 0 => 0; "empty upload")]
+  #[test_case("0-0", 1 => 1; "single byte committed")]
+  #[test_case("0-9", 1 => 10; "range")]
+  fn upload_range_next_offset_after(value: &str, committed: u64) -> u64 {
+    UploadRange::parse(value).unwrap().next_offset_after(committed)
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn journal_keeps_sessions_of_concurrent_writers() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("uploads.json");
+    // Journals of the same file in other processes, as far as the in-process lock is concerned.
+    std::thread::scope(|scope| {
+      for writer in 0..4 {
+        let journal = UploadJournal::new(&path);
+        scope.spawn(move || {
+          for i in 0..10 {
+            let location = format!("https://registry.example/v2/repo/blobs/uploads/{writer}-{i}");
+            let session = UploadSession {
+              name: "repo".to_string(),
+              location,
+              uuid: Some(format!("{writer}-{i}")),
+              digest: None,
+              started_at: 0,
+              offset: 0,
+            };
+            journal.record(&session).unwrap();
+          }
+        });
+      }
+    });
+
+    assert_eq!(UploadJournal::new(&path).sessions().unwrap().len(), 40);
+    let mut files: Vec<_> = fs::read_dir(dir.path())
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name())
+      .collect();
+    files.sort();
+    assert_eq!(files, ["uploads.json", "uploads.json.lock"]);
+  }
+}
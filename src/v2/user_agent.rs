@@ -0,0 +1,88 @@
+//! Composition of the User-Agent header.
+
+use std::fmt;
+
+/// User-Agent identifying an application, sent ahead of the library's own product token.
+///
+/// Registry operators identify clients by their User-Agent: the header is made of the products
+/// and comments added here, in order, followed by [`USER_AGENT`](crate::USER_AGENT), for example
+/// `my-tool/1.2.0 (ci) docker-registry/0.6.0`.
+///
+/// Characters which are not allowed in product tokens are replaced by `-`, and parentheses and
+/// backslashes in comments are escaped, so that the header always parses.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserAgent {
+  tokens: Vec<String>,
+}
+
+impl UserAgent {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a product token, `name/version` or just `name`.
+  pub fn product(mut self, name: &str, version: Option<&str>) -> Self {
+    let mut token = sanitize_token(name);
+    if let Some(version) = version {
+      token.push('/');
+      token.push_str(&sanitize_token(version));
+    }
+    self.tokens.push(token);
+    self
+  }
+
+  /// Add a comment, usually details about the preceding product.
+  pub fn comment(mut self, comment: &str) -> Self {
+    let mut token = String::from("(");
+    for c in comment.chars().filter(|c| !c.is_control()) {
+      if matches!(c, '(' | ')' | '\\') {
+        token.push('\\');
+      }
+      token.push(c);
+    }
+    token.push(')');
+    self.tokens.push(token);
+    self
+  }
+}
+
+impl fmt::Display for UserAgent {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for token in &self.tokens {
+      write!(f, "{} ", token)?;
+    }
+    f.write_str(crate::USER_AGENT)
+  }
+}
+
+/// Replace the characters which are not `tchar`s (RFC 9110) by `-`.
+fn sanitize_token(s: &str) -> String {
+  let token: String = s
+    .chars()
+    .map(|c| match c {
+      'a'..='z' | 'A'..='Z' | '0'..='9' => c,
+      '!' | '#' | '$' | '%' | '&' | '\'' | '*' | '+' | '-' | '.' | '^' | '_' | '`' | '|' | '~' => c,
+      _ => '-',
+    })
+    .collect();
+  if token.is_empty() {
+    "-".to_string()
+  } else {
+    token
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use test_case::test_case;
+
+  use super::*;
+
+  #[test_case(UserAgent::new() => crate::USER_AGENT.to_string(); "library only")]
+  #[test_case(UserAgent::new().product("my-tool", Some("1.2.0")).comment("ci") => format!("my-tool/1.2.0 (ci) {}", crate::USER_AGENT); "product and comment")]
+  #[test_case(UserAgent::new().product("my tool", None).product("", Some("1/2")) => format!("my-tool -/1-2 {}", crate::USER_AGENT); "invalid token characters")]
+  #[test_case(UserAgent::new().comment("a (b) \\ c\n") => format!("(a \\(b\\) \\\\ c) {}", crate::USER_AGENT); "escaped comment")]
+  fn renders(ua: UserAgent) -> String {
+    ua.to_string()
+  }
+}
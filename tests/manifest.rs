@@ -31,10 +31,7 @@ fn deserialize_manifest_v2s2_config() -> Result<docker_registry::v2::manifest::M
   };
 
   Ok(docker_registry::v2::manifest::Manifest::S2(
-    docker_registry::v2::manifest::ManifestSchema2 {
-      manifest_spec,
-      config_blob,
-    },
+    docker_registry::v2::manifest::ManifestSchema2::new(manifest_spec, config_blob),
   ))
 }
 
@@ -63,6 +60,77 @@ fn test_manifest_v2s2() -> Result<(), Box<dyn std::error::Error>> {
   Ok(())
 }
 
+#[test]
+fn test_manifest_v2s2_digest() -> Result<(), Box<dyn std::error::Error>> {
+  use sha2::Digest;
+
+  let manifest = deserialize_manifest_v2s2_config()?;
+  let bytes = manifest.to_bytes()?;
+  assert_eq!(manifest.digest()?, format!("sha256:{:x}", sha2::Sha256::digest(&bytes)));
+
+  // Manifests which were not pulled serialize the same way once parsed again.
+  let manifest_spec: docker_registry::v2::manifest::ManifestSchema2Spec = serde_json::from_slice(&bytes)?;
+  let reparsed = docker_registry::v2::manifest::Manifest::S2(docker_registry::v2::manifest::ManifestSchema2::new(
+    manifest_spec,
+    Default::default(),
+  ));
+  assert_eq!(reparsed.to_bytes()?, bytes);
+  Ok(())
+}
+
+#[test]
+fn test_manifest_digest_keeps_unmodeled_fields() -> Result<(), Box<dyn std::error::Error>> {
+  use docker_registry::v2::manifest::{Manifest, ManifestList, ManifestSchema2, ManifestSchema2Spec};
+  use serde_json::json;
+
+  let descriptor = json!({
+    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+    "digest": "sha256:9391a94f7498d07a595f560d60350d428b1259d622e19beee61a2363edc4eb94",
+    "size": 528,
+  });
+  let subject = json!({"subject": descriptor, "annotations": {"org.example": "value"}});
+
+  // Annotations, subjects and artifact types of manifests which were not pulled are pushed,
+  // and digested, along with the fields known to this crate.
+  let artifact = json!({
+    "schemaVersion": 2,
+    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+    "artifactType": "application/vnd.example+json",
+    "config": descriptor,
+    "layers": [],
+    "subject": subject["subject"],
+    "annotations": subject["annotations"],
+  });
+  let spec: ManifestSchema2Spec = serde_json::from_value(artifact.clone())?;
+  let manifest = Manifest::S2(ManifestSchema2::new(spec, Default::default()));
+  assert_eq!(
+    serde_json::from_slice::<serde_json::Value>(&manifest.to_bytes()?)?,
+    artifact
+  );
+
+  let index = json!({
+    "schemaVersion": 2,
+    "mediaType": "application/vnd.oci.image.index.v1+json",
+    "manifests": [descriptor],
+    "subject": subject["subject"],
+    "annotations": subject["annotations"],
+  });
+  let mut list: ManifestList = serde_json::from_value(index.clone())?;
+  assert_eq!(serde_json::to_value(&list)?, index);
+
+  // Changing the manifests of a list changes its bytes and digest.
+  let before = Manifest::ML(list.clone()).digest()?;
+  list.set_manifests(Vec::new());
+  let manifest = Manifest::ML(list);
+  let bytes = manifest.to_bytes()?;
+  assert_eq!(
+    serde_json::from_slice::<serde_json::Value>(&bytes)?["manifests"],
+    json!([])
+  );
+  assert_ne!(manifest.digest()?, before);
+  Ok(())
+}
+
 #[test]
 fn test_deserialize_oci_image_manifest() {
   let f = fs::File::open("tests/fixtures/manifest_oci_image_manifest.json").expect("Missing fixture");
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+
+use docker_registry::{
+  archive::{pull_to_tar, ArchiveFormat, ArchiveOptions},
+  copy::{LayerFilter, PullOptions},
+};
+use serde_json::{json, Value};
+
+use crate::mock::copy::{blob_mock, descriptor, digest, manifest_mock, LAYER_TYPE, OCI_MANIFEST};
+
+fn entries(archive: &[u8]) -> BTreeMap<String, Vec<u8>> {
+  let mut archive = tar::Archive::new(archive);
+  archive
+    .entries()
+    .unwrap()
+    .map(|entry| {
+      let mut entry = entry.unwrap();
+      let path = entry.path().unwrap().display().to_string();
+      let mut data = Vec::new();
+      std::io::Read::read_to_end(&mut entry, &mut data).unwrap();
+      (path, data)
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn test_archive_docker_save() {
+  let mut server = mockito::Server::new_async().await;
+
+  let config = serde_json::to_vec(&json!({"architecture": "amd64", "os": "linux"})).unwrap();
+  let layer: &[u8] = b"layer";
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": [descriptor(LAYER_TYPE, layer)],
+  });
+  let manifest_bytes = serde_json::to_vec(&manifest).unwrap();
+  let blob_path = |data: &[u8]| format!("blobs/sha256/{}", &digest(data)[7..]);
+
+  let mocks = vec![
+    manifest_mock(&mut server, "repo", "v1", &manifest),
+    blob_mock(&mut server, "repo", &config),
+    blob_mock(&mut server, "repo", layer),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = ArchiveOptions {
+    format: ArchiveFormat::DockerSave,
+    ..Default::default()
+  };
+  let mut archive = Vec::new();
+  let image = pull_to_tar(&client, "repo", "v1", &mut archive, &options)
+    .await
+    .unwrap();
+  assert_eq!(image.descriptor.digest, digest(&manifest_bytes));
+
+  let entries = entries(&archive);
+  assert_eq!(entries[&blob_path(layer)], layer);
+  assert_eq!(entries[&blob_path(&config)], config);
+  assert_eq!(entries[&blob_path(&manifest_bytes)], manifest_bytes);
+  let index: Value = serde_json::from_slice(&entries["index.json"]).unwrap();
+  assert_eq!(index["manifests"][0]["digest"], digest(&manifest_bytes));
+  assert_eq!(
+    index["manifests"][0]["annotations"]["org.opencontainers.image.ref.name"],
+    "v1"
+  );
+  assert!(entries.contains_key("oci-layout"));
+  let docker_manifest: Value = serde_json::from_slice(&entries["manifest.json"]).unwrap();
+  assert_eq!(
+    docker_manifest,
+    json!([{"Config": blob_path(&config), "RepoTags": ["repo:v1"], "Layers": [blob_path(layer)]}])
+  );
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_archive_size_mismatch() {
+  let mut server = mockito::Server::new_async().await;
+
+  let config = b"{}";
+  let mut layer = descriptor(LAYER_TYPE, b"layer");
+  layer["size"] = json!(4);
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", config),
+    "layers": [layer],
+  });
+
+  let mocks = vec![
+    manifest_mock(&mut server, "repo", "v1", &manifest),
+    blob_mock(&mut server, "repo", config),
+    blob_mock(&mut server, "repo", b"layer"),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let mut archive = Vec::new();
+  let res = pull_to_tar(&client, "repo", "v1", &mut archive, &ArchiveOptions::default()).await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::Manifest(e)) if e.to_string().contains("is not 4 bytes long")
+  ));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_archive_refuses_filtered_layers() {
+  let mut server = mockito::Server::new_async().await;
+
+  let config = b"{}";
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", config),
+    "layers": [descriptor(LAYER_TYPE, b"layer"), descriptor("application/vnd.in-toto+json", b"{}")],
+  });
+
+  let manifest_mock = manifest_mock(&mut server, "repo", "v1", &manifest);
+  let blob_mock = server.mock("GET", mockito::Matcher::Any).expect(0).create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = ArchiveOptions {
+    pull: PullOptions {
+      layer_filter: LayerFilter::default().exclude_media_type("application/vnd.in-toto+json"),
+      ..Default::default()
+    },
+    ..Default::default()
+  };
+  let mut archive = Vec::new();
+  let res = pull_to_tar(&client, "repo", "v1", &mut archive, &options).await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::LayerNotArchived(layer)) if layer == digest(b"{}")
+  ));
+  assert!(archive.is_empty());
+
+  manifest_mock.assert_async().await;
+  blob_mock.assert_async().await;
+}
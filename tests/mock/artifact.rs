@@ -0,0 +1,134 @@
+use docker_registry::{
+  artifact::{ArtifactOptions, TITLE_ANNOTATION},
+  errors::Error,
+  v2::{Client, ParallelDownloads},
+};
+use mockito::{Mock, ServerGuard};
+use serde_json::{json, Value};
+
+use crate::mock::copy::{descriptor, digest, manifest_mock, OCI_MANIFEST};
+
+const MODEL: &[u8] = b"model weights";
+const MODEL_TYPE: &str = "application/vnd.example.model.v1";
+
+fn client(server: &ServerGuard) -> Client {
+  Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+}
+
+fn options() -> ArtifactOptions {
+  ArtifactOptions::default().parts(ParallelDownloads::default().part_size(4).parallelism(2))
+}
+
+/// A manifest with `MODEL` as its only layer, titled `model.bin`.
+fn manifest() -> Value {
+  let mut layer = descriptor("application/octet-stream", MODEL);
+  layer["annotations"] = json!({TITLE_ANNOTATION: "model.bin"});
+  json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "artifactType": MODEL_TYPE,
+    "config": descriptor("application/vnd.oci.empty.v1+json", b"{}"),
+    "layers": [layer],
+  })
+}
+
+/// Mocks the ranges of `MODEL` from `start` on, in parts of 4 bytes.
+fn range_mocks(server: &mut ServerGuard, start: usize) -> Vec<Mock> {
+  let path = format!("/v2/models/blobs/{}", digest(MODEL));
+  (start..MODEL.len())
+    .step_by(4)
+    .map(|start| {
+      let end = (start + 3).min(MODEL.len() - 1);
+      server
+        .mock("GET", path.as_str())
+        .match_header("range", format!("bytes={start}-{end}").as_str())
+        .with_status(206)
+        .with_header("Content-Range", &format!("bytes {start}-{end}/{}", MODEL.len()))
+        .with_body(&MODEL[start..=end])
+        .create()
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn test_pull_artifact_resumes() {
+  let mut server = mockito::Server::new_async().await;
+  let _manifest = manifest_mock(&mut server, "models", "7b", &manifest());
+  let ranges = range_mocks(&mut server, 5);
+
+  let dir = tempfile::tempdir().unwrap();
+  std::fs::write(dir.path().join("model.bin.partial"), &MODEL[..5]).unwrap();
+  let artifact = client(&server)
+    .pull_artifact_with("models", "7b", dir.path(), &options())
+    .await
+    .unwrap();
+
+  assert_eq!(artifact.digest, digest(&serde_json::to_vec(&manifest()).unwrap()));
+  assert_eq!(artifact.artifact_type.as_deref(), Some(MODEL_TYPE));
+  assert_eq!(artifact.files.len(), 1);
+  let file = &artifact.files[0];
+  assert_eq!(file.path, dir.path().join("model.bin"));
+  assert_eq!((file.size, file.reused), (MODEL.len() as u64, 5));
+  assert_eq!(std::fs::read(&file.path).unwrap(), MODEL);
+  assert!(!dir.path().join("model.bin.partial").exists());
+  for mock in ranges {
+    mock.assert_async().await;
+  }
+
+  // Files already pulled are not downloaded again.
+  let again = client(&server)
+    .pull_artifact_with("models", "7b", dir.path(), &options())
+    .await
+    .unwrap();
+  assert_eq!(again.files[0].reused, MODEL.len() as u64);
+}
+
+#[tokio::test]
+async fn test_pull_artifact_without_ranges() {
+  let mut server = mockito::Server::new_async().await;
+  let _manifest = manifest_mock(&mut server, "models", "7b", &manifest());
+  let blob = server
+    .mock("GET", format!("/v2/models/blobs/{}", digest(MODEL)).as_str())
+    .with_status(200)
+    .with_body(MODEL)
+    .expect(2)
+    .create();
+
+  let dir = tempfile::tempdir().unwrap();
+  std::fs::write(dir.path().join("model.bin.partial"), &MODEL[..5]).unwrap();
+  // One part at a time: the first range request, answered in full, is the only one sent.
+  let options = options().parts(ParallelDownloads::default().part_size(4).parallelism(1));
+  let artifact = client(&server)
+    .pull_artifact_with("models", "7b", dir.path(), &options)
+    .await
+    .unwrap();
+
+  assert_eq!(artifact.files[0].reused, 0);
+  assert_eq!(std::fs::read(dir.path().join("model.bin")).unwrap(), MODEL);
+  blob.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_pull_artifact_corrupted_partial() {
+  let mut server = mockito::Server::new_async().await;
+  let _manifest = manifest_mock(&mut server, "models", "7b", &manifest());
+  let _ranges = range_mocks(&mut server, 5);
+
+  let dir = tempfile::tempdir().unwrap();
+  let partial = dir.path().join("model.bin.partial");
+  std::fs::write(&partial, b"MODEL").unwrap();
+  match client(&server)
+    .pull_artifact_with("models", "7b", dir.path(), &options())
+    .await
+  {
+    Err(Error::ContentDigestParse(_)) => {}
+    res => panic!("unexpected result {:?}", res),
+  }
+  // The next pull starts over.
+  assert!(!partial.exists());
+  assert!(!dir.path().join("model.bin").exists());
+}
@@ -0,0 +1,139 @@
+use docker_registry::{
+  errors::Error,
+  v2::{Client, RegistryProfile},
+};
+use futures::stream::StreamExt;
+use mockito::{Matcher, Mock, ServerGuard};
+use serde_json::json;
+
+const ARTIFACTORY_ID: &str = "b1ee1d8f6bbb8a0ba3b6e3a2b1d8c0e66c0b7bd3";
+
+fn client(server: &ServerGuard, profile: RegistryProfile) -> Client {
+  Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .registry_profile(profile)
+    .build()
+    .unwrap()
+}
+
+/// Mocks a page of tags as served by Artifactory, without a `Link` header.
+fn tags_mock(server: &mut ServerGuard, query: Vec<Matcher>, tags: &[&str]) -> Mock {
+  server
+    .mock("GET", "/v2/docker-virtual/app/tags/list")
+    .match_query(Matcher::AllOf(query))
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_header("X-JFrog-Version", "Artifactory/7.77.5 77705900")
+    .with_header("X-Artifactory-Id", ARTIFACTORY_ID)
+    .with_body(json!({"name": "docker-virtual/app", "tags": tags}).to_string())
+    .create()
+}
+
+#[tokio::test]
+async fn test_artifactory_tags_pagination() {
+  let mut server = mockito::Server::new_async().await;
+  let _ping = server
+    .mock("GET", "/v2/")
+    .with_status(200)
+    .with_header("Docker-Distribution-Api-Version", "registry/2.0")
+    .with_header("X-JFrog-Version", "Artifactory/7.77.5 77705900")
+    .with_header("X-Artifactory-Id", ARTIFACTORY_ID)
+    .create();
+  let first = tags_mock(
+    &mut server,
+    vec![Matcher::UrlEncoded("n".into(), "2".into())],
+    &["1.0", "1.1"],
+  );
+  let second = tags_mock(
+    &mut server,
+    vec![
+      Matcher::UrlEncoded("n".into(), "2".into()),
+      Matcher::UrlEncoded("last".into(), "1.1".into()),
+    ],
+    &["2.0"],
+  );
+
+  let client = client(&server, RegistryProfile::Auto);
+  assert_eq!(client.registry_profile(), RegistryProfile::Standard);
+  assert!(client.is_v2_supported().await.unwrap());
+  assert_eq!(client.registry_profile(), RegistryProfile::Artifactory);
+
+  let tags: Vec<_> = client
+    .get_tags("docker-virtual/app", Some(2))
+    .map(Result::unwrap)
+    .collect()
+    .await;
+  assert_eq!(tags, ["1.0", "1.1", "2.0"]);
+  first.assert_async().await;
+  second.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_artifactory_anonymous_not_found() {
+  let mut server = mockito::Server::new_async().await;
+  let _manifest = server
+    .mock("GET", "/v2/docker-local/app/manifests/latest")
+    .with_status(404)
+    .with_header("Content-Type", "application/json")
+    .with_header("X-Artifactory-Id", ARTIFACTORY_ID)
+    .with_body(
+      json!({"errors": [{
+        "code": "NAME_UNKNOWN",
+        "message": "Repository name not known to registry.",
+        "detail": {"name": "docker-local/app"},
+      }]})
+      .to_string(),
+    )
+    .create();
+
+  let client = client(&server, RegistryProfile::Artifactory);
+  match client.get_manifest("docker-local/app", "latest").await {
+    Err(Error::Unauthorized { repository, action, .. }) => {
+      assert_eq!(repository.as_deref(), Some("docker-local/app"));
+      assert_eq!(action.as_deref(), Some("pull"));
+    }
+    res => panic!("unexpected result {:?}", res),
+  }
+
+  // Authenticated clients see the repository does not exist.
+  let res = client
+    .with_bearer_token("token")
+    .get_manifest("docker-local/app", "latest")
+    .await;
+  assert!(matches!(res, Err(Error::Api(_))), "{:?}", res);
+  let res = self::client(&server, RegistryProfile::Standard)
+    .get_manifest("docker-local/app", "latest")
+    .await;
+  assert!(matches!(res, Err(Error::Api(_))), "{:?}", res);
+}
+
+#[tokio::test]
+async fn test_artifactory_chunked_upload() {
+  let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+  let mut server = mockito::Server::new_async().await;
+  let start = server
+    .mock("POST", "/v2/docker-local/app/blobs/uploads/")
+    .with_status(202)
+    .with_header("Location", "/v2/docker-local/app/blobs/uploads/4a7c0b9e")
+    .with_header("Docker-Upload-Uuid", "4a7c0b9e")
+    .with_header("X-Artifactory-Id", ARTIFACTORY_ID)
+    .create();
+  let chunks = server.mock("PATCH", Matcher::Any).expect(0).create();
+  let finish = server
+    .mock("PUT", "/v2/docker-local/app/blobs/uploads/4a7c0b9e")
+    .match_query(Matcher::UrlEncoded("digest".into(), digest.into()))
+    .match_body("hello")
+    .with_status(201)
+    .with_header("Docker-Content-Digest", digest)
+    .create();
+
+  let pushed = client(&server, RegistryProfile::Artifactory)
+    .push_blob_chunked("docker-local/app", b"hello", digest, 2)
+    .await
+    .unwrap();
+  assert_eq!(pushed, digest);
+  for mock in [start, chunks, finish] {
+    mock.assert_async().await;
+  }
+}
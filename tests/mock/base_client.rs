@@ -84,6 +84,483 @@ async fn test_base_custom_useragent() {
   assert!(res);
 }
 
+#[tokio::test]
+async fn test_base_useragent_prefix() {
+  let ua = format!("my-tool/1.2.0 (ci) docker-registry/{}", env!("CARGO_PKG_VERSION"));
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", "/v2/")
+    .match_header("user-agent", ua.as_str())
+    .with_status(200)
+    .with_header(API_VERSION_K, API_VERSION_V)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .user_agent_prefix(
+      docker_registry::v2::UserAgent::new()
+        .product("my-tool", Some("1.2.0"))
+        .comment("ci"),
+    )
+    .build()
+    .unwrap();
+
+  let res = client.is_v2_supported().await.unwrap();
+
+  mock.assert_async().await;
+  assert!(res);
+}
+
+#[tokio::test]
+async fn test_base_pinned_certificate_without_tls() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", "/v2/")
+    .with_status(200)
+    .with_header(API_VERSION_K, API_VERSION_V)
+    .expect(0)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .pin_server_certificate(&"ab".repeat(32))
+    .build()
+    .unwrap();
+
+  let res = client.is_v2_supported().await;
+
+  mock.assert_async().await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::CertificatePinMismatch { fingerprint: None, .. })
+  ));
+}
+
+#[test]
+fn test_base_invalid_certificate_pin() {
+  let res = docker_registry::v2::Client::configure()
+    .pin_server_certificate("not-a-fingerprint")
+    .build();
+
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::InvalidCertificatePin(_))
+  ));
+}
+
+#[tokio::test]
+async fn test_base_resolve_override() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.socket_address();
+
+  let mock = server
+    .mock("GET", "/v2/")
+    .match_header("host", format!("registry.invalid:{}", addr.port()).as_str())
+    .with_status(200)
+    .with_header(API_VERSION_K, API_VERSION_V)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&format!("registry.invalid:{}", addr.port()))
+    .insecure_registry(true)
+    .resolve("registry.invalid", addr)
+    .build()
+    .unwrap();
+
+  let res = client.is_v2_supported().await.unwrap();
+
+  mock.assert_async().await;
+  assert!(res);
+}
+
+#[tokio::test]
+async fn test_base_http_version() {
+  use docker_registry::v2::HttpVersion;
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", "/v2/")
+    .with_status(200)
+    .with_header(API_VERSION_K, API_VERSION_V)
+    .expect(3)
+    .create();
+
+  for version in [
+    HttpVersion::Adaptive,
+    HttpVersion::Http1Only,
+    HttpVersion::Http2PriorKnowledge,
+  ] {
+    let client = docker_registry::v2::Client::configure()
+      .registry(&addr)
+      .insecure_registry(true)
+      .http_version(version)
+      .http2_adaptive_window(true)
+      .max_idle_connections_per_host(Some(1))
+      .build()
+      .unwrap();
+
+    assert!(client.is_v2_supported().await.unwrap(), "{:?}", version);
+  }
+
+  mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_base_manifest_size_limit() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", "/v2/repo/manifests/latest")
+    .with_status(200)
+    .with_header(
+      "Content-Type",
+      "application/vnd.docker.distribution.manifest.list.v2+json",
+    )
+    .with_body_from_file("tests/fixtures/manifest_list_v2.json")
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .response_limits(docker_registry::v2::ResponseLimits {
+      max_manifest_size: 64,
+      ..Default::default()
+    })
+    .build()
+    .unwrap();
+
+  let res = client.get_manifest("repo", "latest").await;
+
+  mock.assert_async().await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::ResponseTooLarge { limit: 64, .. })
+  ));
+}
+
+#[tokio::test]
+async fn test_base_manifest_digest() {
+  use base64::prelude::*;
+  use sha2::Digest;
+
+  let sha256 = |data: &[u8]| format!("sha256:{:x}", sha2::Sha256::digest(data));
+
+  // Schema 1 manifests are digested without their signatures, which are spliced into the
+  // signed payload before its closing brace.
+  let payload = r#"{
+   "schemaVersion": 1,
+   "name": "repo",
+   "tag": "latest",
+   "architecture": "amd64",
+   "fsLayers": [
+      {
+         "blobSum": "sha256:a3ed95caeb02ffe68cdd9fd84406680ae93d633cb16422d00e8a7c22955b46d4"
+      }
+   ],
+   "history": [
+      {
+         "v1Compatibility": "{\"id\":\"e45a5af57b00862e5ef5782a9925979a02ba2b12dff832fd0991335f4a11e5c5\"}"
+      }
+   ]
+}"#;
+  let format_length = payload.len() - 2;
+  let protected = BASE64_URL_SAFE_NO_PAD.encode(format!(
+    r#"{{"formatLength":{format_length},"formatTail":"{}","time":"2015-04-08T18:52:59Z"}}"#,
+    BASE64_URL_SAFE_NO_PAD.encode("\n}")
+  ));
+  let signed = format!(
+    "{},\n   \"signatures\": [\n      {{\n         \"header\": {{\"alg\": \"ES256\"}},\n         \"signature\": \"c2ln\",\n         \"protected\": \"{protected}\"\n      }}\n   ]\n}}",
+    &payload[..format_length]
+  );
+  let list = std::fs::read("tests/fixtures/manifest_list_v2.json").unwrap();
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mocks = vec![
+    server
+      .mock("GET", "/v2/repo/manifests/v1")
+      .with_status(200)
+      .with_header(
+        "Content-Type",
+        "application/vnd.docker.distribution.manifest.v1+prettyjws",
+      )
+      .with_header("Docker-Content-Digest", &sha256(payload.as_bytes()))
+      .with_body(&signed)
+      .create(),
+    server
+      .mock("GET", "/v2/repo/manifests/list")
+      .with_status(200)
+      .with_header(
+        "Content-Type",
+        "application/vnd.docker.distribution.manifest.list.v2+json",
+      )
+      .with_header("Docker-Content-Digest", &sha256(&list))
+      .with_body(&list)
+      .expect(2)
+      .create(),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  for reference in ["v1", "list"] {
+    let (manifest, digest) = client.get_manifest_and_ref("repo", reference).await.unwrap();
+    assert_eq!(Some(manifest.digest().unwrap()), digest, "{}", reference);
+  }
+
+  // Manifests which were not pulled are serialized, as Docker signs schema 1 manifests.
+  let manifest: docker_registry::v2::manifest::ManifestSchema1Signed = serde_json::from_str(&signed).unwrap();
+  assert_eq!(manifest.payload().unwrap(), payload.as_bytes());
+  let (manifest, pulled) = client.get_manifest_and_ref("repo", "list").await.unwrap();
+  let docker_registry::v2::manifest::Manifest::ML(list) = manifest else {
+    panic!("not a manifest list");
+  };
+  // Changed manifest lists no longer have the digest they were pulled with.
+  let mut changed = list.clone();
+  changed.set_manifests(list.manifests()[1..].to_vec());
+  let changed = docker_registry::v2::manifest::Manifest::ML(changed);
+  assert_ne!(Some(changed.digest().unwrap()), pulled);
+  assert_eq!(changed.digest().unwrap(), sha256(&changed.to_bytes().unwrap()));
+  let list: docker_registry::v2::manifest::ManifestList =
+    serde_json::from_value(serde_json::to_value(list).unwrap()).unwrap();
+  let manifest = docker_registry::v2::manifest::Manifest::ML(list);
+  assert_eq!(manifest.digest().unwrap(), sha256(&manifest.to_bytes().unwrap()));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_base_manifest_complexity_limit() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", "/v2/repo/manifests/latest")
+    .with_status(200)
+    .with_header(
+      "Content-Type",
+      "application/vnd.docker.distribution.manifest.list.v2+json",
+    )
+    .with_body_from_file("tests/fixtures/manifest_list_v2.json")
+    .expect(2)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .response_limits(docker_registry::v2::ResponseLimits {
+      max_index_manifests: 1,
+      ..Default::default()
+    })
+    .build()
+    .unwrap();
+
+  let res = client.get_manifest("repo", "latest").await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::ManifestTooComplex {
+      kind: "manifests",
+      limit: 1,
+      ..
+    })
+  ));
+  let res = client.get_raw_manifest("repo", "latest", None).await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::ManifestTooComplex { .. })
+  ));
+
+  mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_base_strict_media_types() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  // A Docker manifest list served as an OCI image manifest.
+  let mock = server
+    .mock("GET", "/v2/repo/manifests/latest")
+    .with_status(200)
+    .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+    .with_body_from_file("tests/fixtures/manifest_list_v2.json")
+    .expect(1)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .strict_media_types(true)
+    .build()
+    .unwrap();
+
+  let res = client.get_manifest("repo", "latest").await;
+
+  mock.assert_async().await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::Manifest(
+      docker_registry::v2::manifest::ManifestError::Invalid(_)
+    ))
+  ));
+}
+
+#[tokio::test]
+async fn test_base_raw_request() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("DELETE", "/api/v4/projects/1/registry/repositories/2/tags")
+    .match_header("user-agent", "custom-ua/1.0")
+    .match_body("name_regex_delete=.*")
+    .with_status(202)
+    .with_header("X-Vendor", "yes")
+    .with_body("202")
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .user_agent(Some("custom-ua/1.0".to_string()))
+    .build()
+    .unwrap();
+
+  let res = client
+    .raw_request(
+      reqwest::Method::DELETE,
+      "/api/v4/projects/1/registry/repositories/2/tags",
+      reqwest::header::HeaderMap::new(),
+      Some(b"name_regex_delete=.*".to_vec()),
+    )
+    .await
+    .unwrap();
+
+  mock.assert_async().await;
+  assert_eq!(res.status, reqwest::StatusCode::ACCEPTED);
+  assert_eq!(res.headers["x-vendor"], "yes");
+  assert_eq!(res.body, b"202");
+}
+
+#[tokio::test]
+async fn test_base_raw_request_reauthenticates() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+  let challenge = format!(r#"Bearer realm="http://{addr}/token",service="registry",scope="repository:repo:pull""#);
+
+  let denied = server
+    .mock("GET", "/v2/repo/_vendor/info")
+    .match_header("authorization", mockito::Matcher::Missing)
+    .with_status(401)
+    .with_header("WWW-Authenticate", &challenge)
+    .create();
+  let ping = server
+    .mock("GET", "/v2/")
+    .with_status(401)
+    .with_header(
+      "WWW-Authenticate",
+      &format!(r#"Bearer realm="http://{addr}/token",service="registry""#),
+    )
+    .create();
+  let token = server
+    .mock("GET", "/token")
+    .match_query(mockito::Matcher::UrlEncoded(
+      "scope".into(),
+      "repository:repo:pull".into(),
+    ))
+    .with_status(200)
+    .with_body(r#"{"token": "scoped"}"#)
+    .create();
+  let allowed = server
+    .mock("GET", "/v2/repo/_vendor/info")
+    .match_header("authorization", "Bearer scoped")
+    .with_status(200)
+    .with_body("ok")
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let res = client
+    .raw_request(
+      reqwest::Method::GET,
+      "/v2/repo/_vendor/info",
+      reqwest::header::HeaderMap::new(),
+      None,
+    )
+    .await
+    .unwrap();
+
+  assert_eq!(res.status, reqwest::StatusCode::OK);
+  assert_eq!(res.body, b"ok");
+  for mock in [denied, ping, token, allowed] {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_base_custom_media_type() {
+  let media_type = "application/vnd.example.app.v1+json";
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", "/v2/repo/manifests/latest")
+    .match_header(
+      "accept",
+      mockito::Matcher::Regex(format!(",{}$", regex_escape(media_type))),
+    )
+    .with_status(200)
+    .with_header("Content-Type", &format!("{media_type}; charset=utf-8"))
+    .with_body(r#"{"app": "demo"}"#)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .register_media_type(media_type, |body| {
+      let mut value: serde_json::Value = serde_json::from_slice(body)?;
+      value["parsed"] = true.into();
+      Ok(value)
+    })
+    .build()
+    .unwrap();
+
+  let manifest = client.get_manifest("repo", "latest").await.unwrap();
+
+  mock.assert_async().await;
+  match manifest {
+    docker_registry::v2::manifest::Manifest::Custom(value) => {
+      assert_eq!(value, serde_json::json!({"app": "demo", "parsed": true}))
+    }
+    other => panic!("unexpected manifest {other:?}"),
+  }
+}
+
+fn regex_escape(s: &str) -> String {
+  s.replace('.', "\\.").replace('+', "\\+")
+}
+
 /// Test that we properly deserialize API error payload and can access error contents.
 #[test_case::test_case("tests/fixtures/api_error_fixture_with_detail.json".to_string() ; "API error with detail")]
 #[test_case::test_case("tests/fixtures/api_error_fixture_without_detail.json".to_string() ; "API error without detail")]
@@ -129,6 +606,97 @@ fn test_base_api_error(fixture: String) {
   mock.assert();
 }
 
+#[tokio::test]
+async fn test_base_access_errors() {
+  use docker_registry::errors::Error;
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mocks = [
+    server
+      .mock("GET", "/v2/org/repo/manifests/latest")
+      .with_status(401)
+      .with_header(API_VERSION_K, API_VERSION_V)
+      .with_body(r#"{"errors": [{"code": "UNAUTHORIZED", "message": "authentication required"}]}"#)
+      .create_async()
+      .await,
+    server
+      .mock("PUT", "/v2/org/repo/manifests/latest")
+      .with_status(403)
+      .with_body(r#"{"errors": [{"code": "DENIED", "message": "requested access to the resource is denied"}]}"#)
+      .create_async()
+      .await,
+    // Registries and proxies may refuse requests without an error body.
+    server
+      .mock("DELETE", "/v2/org/repo/manifests/latest")
+      .with_status(403)
+      .create_async()
+      .await,
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let access = |e: Error| match e {
+    Error::Unauthorized { repository, action, .. } => ("unauthorized", repository, action),
+    Error::Denied { repository, action, .. } => ("denied", repository, action),
+    e => panic!("unexpected error {e}"),
+  };
+  let expected = |kind, action: &str| (kind, Some("org/repo".to_string()), Some(action.to_string()));
+
+  let e = client.get_manifest("org/repo", "latest").await.unwrap_err();
+  assert_eq!(
+    e.to_string(),
+    "authentication required to pull org/repo: ((UNAUTHORIZED), message: authentication required)"
+  );
+  assert_eq!(access(e), expected("unauthorized", "pull"));
+  let e = client
+    .put_manifest(
+      "org/repo",
+      "latest",
+      "application/vnd.oci.image.manifest.v1+json",
+      b"{}",
+    )
+    .await
+    .unwrap_err();
+  assert_eq!(access(e), expected("denied", "push"));
+  let e = client.delete_manifest("org/repo", "latest").await.unwrap_err();
+  assert_eq!(access(e), expected("denied", "delete"));
+
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[test_case::test_case(r#"{"code": "BLOB_UNKNOWN", "detail": "sha256:891f22d1ce0bed0f4d354ce3de87dc18cacb32318e9c5cd42ebbb8a29c4eee76"}"# => (Some("sha256:891f22d1ce0bed0f4d354ce3de87dc18cacb32318e9c5cd42ebbb8a29c4eee76".to_string()), None) ; "digest string")]
+#[test_case::test_case(r#"{"code": "BLOB_UNKNOWN", "detail": {"digest": "sha256:891f22d1ce0bed0f4d354ce3de87dc18cacb32318e9c5cd42ebbb8a29c4eee76"}}"# => (Some("sha256:891f22d1ce0bed0f4d354ce3de87dc18cacb32318e9c5cd42ebbb8a29c4eee76".to_string()), None) ; "digest object")]
+#[test_case::test_case(r#"{"code": "MANIFEST_UNKNOWN", "detail": {"Tag": "latest"}}"# => (None, Some("latest".to_string())) ; "tag object")]
+#[test_case::test_case(r#"{"code": "MANIFEST_UNKNOWN", "detail": "unknown tag=v1"}"# => (None, Some("v1".to_string())) ; "tag string")]
+#[test_case::test_case(r#"{"code": "UNAUTHORIZED", "detail": [{"Type": "repository"}]}"# => (None, None) ; "access detail")]
+fn test_base_api_error_detail(error: &str) -> (Option<String>, Option<String>) {
+  let error: docker_registry::v2::ApiError = serde_json::from_str(error).unwrap();
+  assert!(error.detail().is_some());
+  (error.digest().map(String::from), error.tag().map(String::from))
+}
+
+#[test_case::test_case(r#"{"code": "PRECONDITION", "message": "Failed to process request due to 'busybox:latest' configured as immutable."}"# => "immutable" ; "harbor immutable")]
+#[test_case::test_case(r#"{"code": "DENIED", "message": "The operation is disallowed on this registry, repository or image."}"# => "immutable" ; "acr locked")]
+#[test_case::test_case(r#"{"code": "DENIED", "message": "Tag is protected by a retention policy."}"# => "retention" ; "retention")]
+#[test_case::test_case(r#"{"code": "DENIED", "message": "requested access to the resource is denied"}"# => "api" ; "denied")]
+fn test_base_api_error_classify(error: &str) -> &'static str {
+  let errors: docker_registry::v2::ApiErrors = serde_json::from_str(&format!(r#"{{"errors": [{error}]}}"#)).unwrap();
+  match errors.classify() {
+    docker_registry::errors::Error::TagImmutable(_) => "immutable",
+    docker_registry::errors::Error::RetentionLocked(_) => "retention",
+    docker_registry::errors::Error::Api(_) => "api",
+    e => panic!("unexpected error {e}"),
+  }
+}
+
 mod test_custom_root_certificate {
   use std::{
     error::Error,
@@ -274,3 +842,58 @@ mod test_custom_root_certificate {
     println!("Done");
   }
 }
+
+#[tokio::test]
+async fn test_base_hub_rate_limit() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+  let realm = format!("{}/token", server.url());
+
+  let challenge = server
+    .mock("GET", "/v2/")
+    .with_status(401)
+    .with_header(API_VERSION_K, API_VERSION_V)
+    .with_header(
+      "WWW-Authenticate",
+      &format!("Bearer realm=\"{realm}\",service=\"registry.docker.io\""),
+    )
+    .create();
+  let token = server
+    .mock("GET", "/token")
+    .match_query(mockito::Matcher::UrlEncoded(
+      "scope".into(),
+      "repository:ratelimitpreview/test:pull".into(),
+    ))
+    .with_status(200)
+    .with_body(r#"{"token": "abcdef"}"#)
+    .create();
+  let head = server
+    .mock("HEAD", "/v2/ratelimitpreview/test/manifests/latest")
+    .match_header("authorization", "Bearer abcdef")
+    .with_status(200)
+    .with_header("ratelimit-limit", "100;w=21600")
+    .with_header("ratelimit-remaining", "76;w=21600")
+    .with_header("docker-ratelimit-source", "192.0.2.1")
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let rate_limit = client.hub_rate_limit().await.unwrap().unwrap();
+
+  challenge.assert_async().await;
+  token.assert_async().await;
+  head.assert_async().await;
+  assert_eq!(
+    rate_limit,
+    docker_registry::v2::RateLimit {
+      limit: 100,
+      remaining: 76,
+      window: Some(std::time::Duration::from_secs(21600)),
+      source: Some("192.0.2.1".to_string()),
+    }
+  );
+}
@@ -126,6 +126,46 @@ async fn get_blobs_fails_with_inconsistent_layer() -> Fallible<()> {
   Ok(())
 }
 
+#[tokio::test]
+async fn get_blobs_verifies_sha512_digest() -> Fallible<()> {
+  let name = "my-repo/my-image";
+  let blob = b"hello";
+  let digest = format!("sha512:{:x}", sha2::Sha512::digest(blob));
+  let digest2 = format!("sha512:{:x}", sha2::Sha512::digest(b"hello2"));
+  let ep = format!("/v2/{name}/blobs/{digest}");
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", ep.as_str())
+    .with_status(200)
+    .with_body(blob)
+    .create();
+  let inconsistent = server
+    .mock(
+      "GET",
+      format!("/v2/{name}/blobs/sha512:{:x}", sha2::Sha512::digest(b"hello2")).as_str(),
+    )
+    .with_status(200)
+    .with_body(blob)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  assert_eq!(client.get_blob(name, &digest).await?, blob);
+  assert!(client.get_blob(name, &digest2).await.is_err());
+
+  mock.assert_async().await;
+  inconsistent.assert_async().await;
+
+  Ok(())
+}
+
 #[tokio::test]
 async fn get_blobs_stream() -> Fallible<()> {
   let name = "my-repo/my-image";
@@ -161,3 +201,82 @@ async fn get_blobs_stream() -> Fallible<()> {
 
   Ok(())
 }
+
+#[tokio::test]
+async fn get_blobs_stream_parallel_ranges() -> Fallible<()> {
+  let name = "my-repo/my-image";
+  let blob = b"hello world!";
+  let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+  let ep = format!("/v2/{name}/blobs/{digest}");
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mut mocks = Vec::new();
+  for (start, end) in [(0, 4), (5, 9), (10, 11)] {
+    let mock = server
+      .mock("GET", ep.as_str())
+      .match_header("range", format!("bytes={start}-{end}").as_str())
+      .with_status(206)
+      .with_header("Content-Range", &format!("bytes {start}-{end}/{}", blob.len()))
+      .with_body(&blob[start..=end])
+      .create();
+    mocks.push(mock);
+  }
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .parallel_downloads(Some(
+      docker_registry::v2::ParallelDownloads::default()
+        .part_size(5)
+        .parallelism(2),
+    ))
+    .build()
+    .unwrap();
+
+  let chunks = client.get_blob_stream(name, &digest).await?;
+  let received: Vec<Vec<u8>> = chunks.collect::<Vec<_>>().await.into_iter().collect::<Result<_, _>>()?;
+
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+  assert_eq!(received.len(), 3);
+  assert_eq!(received.concat(), blob.to_vec());
+
+  Ok(())
+}
+
+#[tokio::test]
+async fn get_blobs_stream_parallel_ranges_unsupported() -> Fallible<()> {
+  let name = "my-repo/my-image";
+  let blob = b"hello world!";
+  let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+  let ep = format!("/v2/{name}/blobs/{digest}");
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  // The range is ignored and the whole blob is returned.
+  let mock = server
+    .mock("GET", ep.as_str())
+    .match_header("range", "bytes=0-4")
+    .with_status(200)
+    .with_body(blob)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .parallel_downloads(Some(docker_registry::v2::ParallelDownloads::default().part_size(5)))
+    .build()
+    .unwrap();
+
+  let chunks = client.get_blob_stream(name, &digest).await?;
+  let received: Vec<Vec<u8>> = chunks.collect::<Vec<_>>().await.into_iter().collect::<Result<_, _>>()?;
+
+  mock.assert_async().await;
+  assert_eq!(received.concat(), blob.to_vec());
+
+  Ok(())
+}
@@ -0,0 +1,83 @@
+use docker_registry::{
+  build::{BuiltImage, ImageConfig, LayerSource},
+  layer::Compression,
+  v2::Descriptor,
+};
+use mockito::Matcher;
+use serde_json::json;
+
+use super::copy::{descriptor, digest, tar_layer, upload_mocks, LAYER_TYPE, OCI_MANIFEST};
+
+fn client(server: &mockito::ServerGuard) -> docker_registry::v2::Client {
+  docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_build_and_push() {
+  let mut server = mockito::Server::new_async().await;
+
+  let tar = tar_layer(&[("app", b"binary")]);
+  let layer = Compression::Gzip.compress(&tar).unwrap();
+  let config = ImageConfig {
+    entrypoint: Some(vec!["/app".to_string()]),
+    user: Some("65532".to_string()),
+    ..Default::default()
+  };
+  let expected_config = serde_json::to_vec(&json!({
+    "architecture": "amd64",
+    "os": "linux",
+    "config": {"Entrypoint": ["/app"], "User": "65532"},
+    "rootfs": {"type": "layers", "diff_ids": [digest(&tar)]},
+  }))
+  .unwrap();
+  let image = BuiltImage::new(&config, vec![LayerSource::tar(tar.clone())]).unwrap();
+  assert_eq!(image.config(), expected_config);
+
+  let mut mocks = vec![server
+    .mock("PUT", "/v2/app/manifests/v1")
+    .match_header("Content-Type", OCI_MANIFEST)
+    .match_body(Matcher::PartialJson(json!({
+      "config": {"digest": digest(&expected_config), "size": expected_config.len()},
+      "layers": [descriptor(LAYER_TYPE, &layer)],
+    })))
+    .with_status(201)
+    .with_header("Docker-Content-Digest", &image.digest())
+    .create()];
+  mocks.extend(upload_mocks(&mut server, "app", &layer));
+  mocks.extend(upload_mocks(&mut server, "app", &expected_config));
+
+  let digest = client(&server)
+    .build_and_push("app", "v1", &config, vec![LayerSource::tar(tar)])
+    .await
+    .unwrap();
+  assert_eq!(digest, image.digest());
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_build_existing_layer_missing() {
+  let mut server = mockito::Server::new_async().await;
+
+  let layer = Compression::Gzip.compress(&tar_layer(&[("a", b"a")])).unwrap();
+  let mock = server
+    .mock("HEAD", format!("/v2/app/blobs/{}", digest(&layer)).as_str())
+    .with_status(404)
+    .create();
+
+  let existing = LayerSource::Existing {
+    descriptor: Descriptor::new(LAYER_TYPE, &layer),
+    diff_id: digest(b"a"),
+  };
+  let err = client(&server)
+    .build_and_push("app", "v1", &ImageConfig::default(), vec![existing])
+    .await
+    .unwrap_err();
+  assert!(matches!(err, docker_registry::errors::Error::BlobNotFound(d) if d == digest(&layer)));
+  mock.assert_async().await;
+}
@@ -0,0 +1,294 @@
+use docker_registry::{
+  bulk::{BulkOptions, DeleteTagsOptions, SharedDigestPolicy, TaskStatus, WorkQueue},
+  errors::Error,
+};
+
+#[tokio::test]
+async fn test_bulk_delete_manifests_reports_each_item() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let deleted = server
+    .mock("DELETE", "/v2/repo/manifests/sha256:aaaa")
+    .with_status(202)
+    .create();
+  let missing = server
+    .mock("DELETE", "/v2/repo/manifests/sha256:bbbb")
+    .with_status(404)
+    .with_body(r#"{"errors": [{"code": "MANIFEST_UNKNOWN", "message": "manifest unknown"}]}"#)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let references = vec!["sha256:aaaa".to_string(), "sha256:bbbb".to_string()];
+  let report = client
+    .delete_manifests("repo", references, &BulkOptions::default())
+    .await;
+
+  assert!(!report.is_success());
+  assert_eq!(
+    report.succeeded().map(|i| i.item.as_str()).collect::<Vec<_>>(),
+    vec!["sha256:aaaa"]
+  );
+  let failed: Vec<_> = report.failed().collect();
+  assert_eq!(failed.len(), 1);
+  assert_eq!(failed[0].error_codes(), vec!["MANIFEST_UNKNOWN"]);
+
+  deleted.assert_async().await;
+  missing.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_bulk_delete_manifests_immutable_tag() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let immutable = server
+    .mock("DELETE", "/v2/repo/manifests/sha256:aaaa")
+    .with_status(412)
+    .with_body(
+      r#"{"errors": [{"code": "PRECONDITION", "message": "Failed to process request due to 'repo:v1' configured as immutable."}]}"#,
+    )
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let report = client
+    .delete_manifests("repo", vec!["sha256:aaaa".to_string()], &BulkOptions::default())
+    .await;
+
+  let failed: Vec<_> = report.failed().collect();
+  assert_eq!(failed.len(), 1);
+  assert!(matches!(failed[0].result, Err(Error::TagImmutable(_))));
+  assert_eq!(failed[0].error_codes(), vec!["PRECONDITION"]);
+
+  immutable.assert_async().await;
+}
+
+fn head_mock(server: &mut mockito::ServerGuard, tag: &str, digest: &str) -> mockito::Mock {
+  server
+    .mock("HEAD", format!("/v2/repo/manifests/{tag}").as_str())
+    .with_status(200)
+    .with_header("Docker-Content-Digest", digest)
+    .expect_at_least(1)
+    .create()
+}
+
+#[tokio::test]
+async fn test_bulk_delete_tags_deduplicates_digests() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mocks = vec![
+    head_mock(&mut server, "v0", "sha256:bbbb"),
+    head_mock(&mut server, "v1", "sha256:aaaa"),
+    head_mock(&mut server, "latest", "sha256:aaaa"),
+    server
+      .mock("DELETE", "/v2/repo/manifests/sha256:aaaa")
+      .with_status(429)
+      .with_header("Retry-After", "0")
+      .expect(1)
+      .create(),
+    server
+      .mock("DELETE", "/v2/repo/manifests/sha256:aaaa")
+      .with_status(202)
+      .expect(1)
+      .create(),
+    server
+      .mock("DELETE", "/v2/repo/manifests/sha256:bbbb")
+      .with_status(202)
+      .expect(1)
+      .create(),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let tags = ["v1", "v0", "latest", "v1"].map(String::from).to_vec();
+  let report = client
+    .delete_tags("repo", tags, &DeleteTagsOptions::default())
+    .await
+    .unwrap();
+
+  assert!(report.is_success());
+  let deleted: Vec<_> = report
+    .items
+    .iter()
+    .map(|i| (i.item.as_str(), i.result.as_ref().unwrap().as_str()))
+    .collect();
+  assert_eq!(
+    deleted,
+    vec![("v1", "sha256:aaaa"), ("v0", "sha256:bbbb"), ("latest", "sha256:aaaa")]
+  );
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_bulk_delete_tags_shared_digest_policies() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mocks = vec![
+    head_mock(&mut server, "v0", "sha256:bbbb"),
+    head_mock(&mut server, "v1", "sha256:aaaa"),
+    head_mock(&mut server, "latest", "sha256:aaaa"),
+    head_mock(&mut server, "stable", "sha256:aaaa"),
+    server
+      .mock("GET", "/v2/repo/tags/list")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(r#"{"name": "repo", "tags": ["latest", "stable", "v0", "v1"]}"#)
+      .create(),
+    server
+      .mock("DELETE", "/v2/repo/manifests/sha256:bbbb")
+      .with_status(202)
+      .expect(2)
+      .create(),
+    server
+      .mock("DELETE", "/v2/repo/manifests/sha256:aaaa")
+      .expect(0)
+      .create(),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let shared_tags = |report: &docker_registry::bulk::BulkReport<String, String>| -> Vec<(String, Vec<String>)> {
+    report
+      .failed()
+      .map(|i| match &i.result {
+        Err(Error::SharedDigest { digest, tags }) => {
+          assert_eq!(digest, "sha256:aaaa");
+          (i.item.clone(), tags.clone())
+        }
+        other => panic!("unexpected result {:?}", other),
+      })
+      .collect()
+  };
+
+  let options = DeleteTagsOptions {
+    shared_digests: SharedDigestPolicy::Skip,
+    ..Default::default()
+  };
+  let tags = ["v1", "v0", "latest"].map(String::from).to_vec();
+  let report = client.delete_tags("repo", tags, &options).await.unwrap();
+  assert_eq!(
+    report.succeeded().map(|i| i.item.as_str()).collect::<Vec<_>>(),
+    vec!["v0"]
+  );
+  assert_eq!(
+    shared_tags(&report),
+    vec![
+      ("v1".to_string(), vec!["latest".to_string()]),
+      ("latest".to_string(), vec!["v1".to_string()]),
+    ]
+  );
+
+  let options = DeleteTagsOptions {
+    shared_digests: SharedDigestPolicy::ProtectOtherTags,
+    ..Default::default()
+  };
+  let tags = ["v1", "v0", "latest"].map(String::from).to_vec();
+  let report = client.delete_tags("repo", tags, &options).await.unwrap();
+  assert_eq!(
+    report.succeeded().map(|i| i.item.as_str()).collect::<Vec<_>>(),
+    vec!["v0"]
+  );
+  assert_eq!(
+    shared_tags(&report),
+    vec![
+      ("v1".to_string(), vec!["stable".to_string()]),
+      ("latest".to_string(), vec!["stable".to_string()]),
+    ]
+  );
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_bulk_delete_queued_tags() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mocks = vec![
+    server
+      .mock("HEAD", "/v2/repo/manifests/v0")
+      .with_status(200)
+      .with_header("Docker-Content-Digest", "sha256:bbbb")
+      .expect(2)
+      .create(),
+    server
+      .mock("HEAD", "/v2/repo/manifests/v1")
+      .with_status(200)
+      .with_header("Docker-Content-Digest", "sha256:aaaa")
+      .expect(1)
+      .create(),
+    server
+      .mock("DELETE", "/v2/repo/manifests/sha256:bbbb")
+      .with_status(404)
+      .with_body(r#"{"errors": [{"code": "MANIFEST_UNKNOWN", "message": "manifest unknown"}]}"#)
+      .expect(1)
+      .create(),
+    server
+      .mock("DELETE", "/v2/repo/manifests/sha256:bbbb")
+      .with_status(202)
+      .expect(1)
+      .create(),
+    server
+      .mock("DELETE", "/v2/repo/manifests/sha256:aaaa")
+      .with_status(202)
+      .expect(1)
+      .create(),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("cleanup.jsonl");
+  let queue = WorkQueue::open(&path).unwrap();
+  queue.push(["v1", "v0"].map(String::from)).unwrap();
+  let report = client
+    .delete_queued_tags("repo", &queue, &DeleteTagsOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(report.failed().map(|i| i.item.as_str()).collect::<Vec<_>>(), vec!["v0"]);
+
+  // Reopening the queue only retries the failed tag.
+  let queue = WorkQueue::<String>::open(&path).unwrap();
+  let report = client
+    .delete_queued_tags("repo", &queue, &DeleteTagsOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(
+    report.succeeded().map(|i| i.item.as_str()).collect::<Vec<_>>(),
+    vec!["v0"]
+  );
+  assert!(queue.tasks().iter().all(|(_, status)| *status == TaskStatus::Done));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
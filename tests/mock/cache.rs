@@ -0,0 +1,89 @@
+#[tokio::test]
+async fn test_cache_manifest_by_digest() {
+  let digest = "sha256:891f22d1ce0bed0f4d354ce3de87dc18cacb32318e9c5cd42ebbb8a29c4eee76";
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", format!("/v2/repo/manifests/{digest}").as_str())
+    .with_status(200)
+    .with_header(
+      "Content-Type",
+      "application/vnd.docker.distribution.manifest.list.v2+json",
+    )
+    .with_body_from_file("tests/fixtures/manifest_list_v2.json")
+    .expect(1)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  for _ in 0..3 {
+    let manifest = client.get_manifest_by_digest("repo", digest).await.unwrap();
+    assert!(matches!(manifest, docker_registry::v2::manifest::Manifest::ML(_)));
+  }
+
+  mock.assert_async().await;
+  assert!(client.get_manifest_by_digest("repo", "latest").await.is_err());
+}
+
+#[tokio::test]
+async fn test_cache_responses() {
+  use futures::TryStreamExt;
+
+  let mut server = mockito::Server::new_async().await;
+  let manifest = std::fs::read("tests/fixtures/manifest_oci_image_manifest.json").unwrap();
+  let media_type = "application/vnd.oci.image.manifest.v1+json";
+
+  let tags = server
+    .mock("GET", "/v2/repo/tags/list")
+    .with_status(200)
+    .with_header("Cache-Control", "public, max-age=60")
+    .with_body(r#"{"name": "repo", "tags": ["v1"]}"#)
+    .expect(1)
+    .create();
+  let get_manifest = server
+    .mock("GET", "/v2/repo/manifests/v1")
+    .with_status(200)
+    .with_header("Content-Type", media_type)
+    .with_header("Expires", "Thu, 01 Jan 2099 00:00:00 GMT")
+    .with_body(&manifest)
+    .expect(2)
+    .create();
+  let put_manifest = server.mock("PUT", "/v2/repo/manifests/v1").with_status(201).create();
+  let uncacheable = server
+    .mock("GET", "/v2/other/tags/list")
+    .with_status(200)
+    .with_header("Cache-Control", "no-store")
+    .with_body(r#"{"name": "other", "tags": ["v1"]}"#)
+    .expect(2)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .response_cache_size(1 << 20)
+    .build()
+    .unwrap();
+
+  for _ in 0..2 {
+    let tags: Vec<String> = client.get_tags("repo", None).try_collect().await.unwrap();
+    assert_eq!(tags, vec!["v1"]);
+    let tags: Vec<String> = client.get_tags("other", None).try_collect().await.unwrap();
+    assert_eq!(tags, vec!["v1"]);
+    let (body, _, _) = client.get_raw_manifest("repo", "v1", None).await.unwrap();
+    assert_eq!(body, manifest);
+  }
+  // Pushing the manifest evicts the cached one.
+  client.put_manifest("repo", "v1", media_type, &manifest).await.unwrap();
+  client.get_raw_manifest("repo", "v1", None).await.unwrap();
+
+  for mock in [tags, get_manifest, put_manifest, uncacheable] {
+    mock.assert_async().await;
+  }
+  assert_eq!(client.stats().response_cache_hits, 2);
+}
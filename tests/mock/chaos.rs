@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use docker_registry::{
+  chaos::{Chaos, EndpointClass, Faults},
+  errors::Error,
+  v2::RetryPolicy,
+};
+
+static DIGEST: &str = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+fn client(addr: &str, chaos: Chaos) -> docker_registry::v2::Client {
+  docker_registry::v2::Client::configure()
+    .registry(addr)
+    .insecure_registry(true)
+    .retry_policy(Some(
+      RetryPolicy::default().max_retries(2).initial_backoff(Duration::ZERO),
+    ))
+    .chaos(chaos)
+    .build()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_chaos_error_responses_are_retried() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mocks = vec![
+    server
+      .mock("GET", "/v2/")
+      .with_status(200)
+      .with_header("Docker-Distribution-API-Version", "registry/2.0")
+      .create(),
+    server
+      .mock("GET", format!("/v2/repo/blobs/{DIGEST}").as_str())
+      .expect(0)
+      .create(),
+  ];
+
+  let faults = Faults::default().too_many_requests(1.0);
+  let client = client(&addr, Chaos::new(1).endpoint(EndpointClass::Blob, faults));
+
+  assert!(client.is_v2_supported().await.unwrap());
+  match client.get_blob("repo", DIGEST).await {
+    Err(Error::Api(e)) => assert_eq!(e.errors().as_ref().unwrap()[0].code(), "TOOMANYREQUESTS"),
+    other => panic!("unexpected result {:?}", other),
+  }
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_chaos_dropped_connections() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", format!("/v2/repo/blobs/{DIGEST}").as_str())
+    .expect(0)
+    .create();
+
+  let client = client(&addr, Chaos::new(1).faults(Faults::default().drop_connection(1.0)));
+  match client.get_blob("repo", DIGEST).await {
+    Err(Error::Reqwest(e)) => assert!(e.is_connect()),
+    other => panic!("unexpected result {:?}", other),
+  }
+
+  mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_chaos_truncated_bodies() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", format!("/v2/repo/blobs/{DIGEST}").as_str())
+    .with_status(200)
+    .with_body("hello")
+    .create();
+
+  let client = client(&addr, Chaos::new(1).faults(Faults::default().truncate_body(1.0)));
+  assert!(client.get_blob("repo", DIGEST).await.is_err());
+
+  mock.assert_async().await;
+}
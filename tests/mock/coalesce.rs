@@ -0,0 +1,63 @@
+#[tokio::test]
+async fn test_coalesce_requests() {
+  use futures::TryStreamExt;
+
+  let mut server = mockito::Server::new_async().await;
+  let manifest = std::fs::read("tests/fixtures/manifest_oci_image_manifest.json").unwrap();
+  let media_type = "application/vnd.oci.image.manifest.v1+json";
+
+  let tags = server
+    .mock("GET", "/v2/repo/tags/list")
+    .with_status(200)
+    .with_body(r#"{"name": "repo", "tags": ["v1"]}"#)
+    .expect(1)
+    .create();
+  let get_manifest = server
+    .mock("GET", "/v2/repo/manifests/v1")
+    .with_status(200)
+    .with_header("Content-Type", media_type)
+    .with_body(&manifest)
+    .expect(2)
+    .create();
+  let missing = server
+    .mock("HEAD", "/v2/repo/manifests/v2")
+    .with_status(404)
+    .expect(1)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .coalesce_requests(true)
+    .build()
+    .unwrap();
+  let derived = client.clone();
+
+  let (first, second, third) = futures::join!(
+    client.get_raw_manifest("repo", "v1", None),
+    derived.get_raw_manifest("repo", "v1", None),
+    client.get_raw_manifest("repo", "v1", None),
+  );
+  for result in [first, second, third] {
+    assert_eq!(result.unwrap().0, manifest);
+  }
+  let (first, second) = futures::join!(
+    client.get_tags("repo", None).try_collect::<Vec<String>>(),
+    client.get_tags("repo", None).try_collect::<Vec<String>>(),
+  );
+  assert_eq!(first.unwrap(), vec!["v1"]);
+  assert_eq!(second.unwrap(), vec!["v1"]);
+  let (first, second) = futures::join!(
+    client.has_manifest("repo", "v2", None),
+    client.has_manifest("repo", "v2", None),
+  );
+  assert_eq!(first.unwrap(), None);
+  assert_eq!(second.unwrap(), None);
+  // Requests sent once the identical ones completed are sent again.
+  client.get_raw_manifest("repo", "v1", None).await.unwrap();
+
+  for mock in [tags, get_manifest, missing] {
+    mock.assert_async().await;
+  }
+  assert_eq!(client.stats().coalesced_requests, 4);
+}
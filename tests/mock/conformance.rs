@@ -0,0 +1,58 @@
+use docker_registry::conformance::{Category, Conformance, Outcome};
+
+#[tokio::test]
+async fn test_conformance_fails_without_test_content() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let ping = server.mock("GET", "/v2/").with_status(200).create();
+  let uploads = server
+    .mock("POST", "/v2/conformance/test/blobs/uploads/")
+    .with_status(403)
+    .with_header("Content-Type", "application/json")
+    .with_body(r#"{"errors":[{"code":"DENIED","message":"requested access to the resource is denied"}]}"#)
+    .expect_at_least(1)
+    .create();
+  let manifests = server
+    .mock("PUT", "/v2/conformance/test/manifests/conformance")
+    .with_status(403)
+    .expect(1)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  let report = Conformance::new(client, "conformance/test")
+    .categories(&[Category::Pull, Category::ContentManagement])
+    .run()
+    .await
+    .unwrap();
+
+  // The push checks run as setup, but are only reported when selected.
+  assert_eq!(report.registry, addr);
+  assert!(!report.is_success());
+  assert_eq!(report.passed(), 0);
+  assert_eq!(report.failed(), 2);
+  assert_eq!(
+    report
+      .checks
+      .iter()
+      .map(|c| (c.category, c.name.as_str()))
+      .collect::<Vec<_>>(),
+    vec![(Category::Pull, "setup"), (Category::ContentManagement, "setup")]
+  );
+  assert_eq!(
+    report.checks[0].outcome,
+    Outcome::Failed("the test content could not be pushed: push blob monolithically failed".to_string())
+  );
+
+  let json = serde_json::to_value(&report).unwrap();
+  assert_eq!(json["checks"][1]["category"], "content-management");
+  assert_eq!(json["checks"][1]["outcome"]["status"], "failed");
+
+  ping.assert_async().await;
+  uploads.assert_async().await;
+  manifests.assert_async().await;
+}
@@ -0,0 +1,959 @@
+use docker_registry::{
+  blob_store::{BlobStore, FsBlobStore, MemoryBlobStore},
+  bulk::{BulkOptions, WorkQueue},
+  copy::{
+    copy_image, copy_images, pull_to_store, push_from_store, push_index, CopyOptions, CopyTask, LayerFilter,
+    MissingContent, PullOptions, Transform,
+  },
+  dir_transport,
+  layer::{self, Compression},
+  staging::StagingDir,
+};
+use mockito::{Matcher, Mock, ServerGuard};
+use serde_json::{json, Value};
+use sha2::Digest;
+
+pub(crate) const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+pub(crate) const LAYER_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+const ATTESTATION_TYPE: &str = "application/vnd.in-toto+json";
+
+pub(crate) fn digest(data: &[u8]) -> String {
+  format!("sha256:{:x}", sha2::Sha256::digest(data))
+}
+
+pub(crate) fn descriptor(media_type: &str, data: &[u8]) -> Value {
+  json!({"mediaType": media_type, "digest": digest(data), "size": data.len()})
+}
+
+pub(crate) fn blob_mock(server: &mut ServerGuard, name: &str, data: &[u8]) -> Mock {
+  server
+    .mock("GET", format!("/v2/{name}/blobs/{}", digest(data)).as_str())
+    .with_status(200)
+    .with_body(data)
+    .create()
+}
+
+/// Mocks the upload of a blob which is not yet present in repository `name`.
+pub(crate) fn upload_mocks(server: &mut ServerGuard, name: &str, data: &[u8]) -> Vec<Mock> {
+  let location = format!("/v2/{name}/blobs/uploads/{}", &digest(data)[7..19]);
+  vec![
+    server
+      .mock("HEAD", format!("/v2/{name}/blobs/{}", digest(data)).as_str())
+      .with_status(404)
+      .create(),
+    server
+      .mock("POST", format!("/v2/{name}/blobs/uploads/").as_str())
+      .with_status(202)
+      .with_header("Location", &location)
+      .create(),
+    server
+      .mock("PUT", location.as_str())
+      .match_query(Matcher::UrlEncoded("digest".into(), digest(data)))
+      .match_body(data.to_vec())
+      .with_status(201)
+      .create(),
+  ]
+}
+
+/// Mocks the upload of a blob which is not yet present in repository `name`, streamed from
+/// another repository or a store in a single chunk.
+pub(crate) fn streamed_upload_mocks(server: &mut ServerGuard, name: &str, data: &[u8]) -> Vec<Mock> {
+  let location = format!("/v2/{name}/blobs/uploads/{}", &digest(data)[7..19]);
+  vec![
+    server
+      .mock("HEAD", format!("/v2/{name}/blobs/{}", digest(data)).as_str())
+      .with_status(404)
+      .create(),
+    server
+      .mock("POST", format!("/v2/{name}/blobs/uploads/").as_str())
+      .with_status(202)
+      .with_header("Location", &location)
+      .create(),
+    server
+      .mock("PATCH", location.as_str())
+      .match_body(data.to_vec())
+      .with_status(202)
+      .with_header("Location", &location)
+      .with_header("Range", &format!("0-{}", data.len() - 1))
+      .create(),
+    server
+      .mock("PUT", location.as_str())
+      .match_query(Matcher::UrlEncoded("digest".into(), digest(data)))
+      .match_body(Vec::new())
+      .with_status(201)
+      .create(),
+  ]
+}
+
+struct Image {
+  config: Value,
+  layer: &'static [u8],
+  attestation: &'static [u8],
+  manifest: Value,
+}
+
+fn image() -> Image {
+  let layer: &[u8] = b"layer";
+  let attestation: &[u8] = b"attestation";
+  let config = json!({
+    "architecture": "amd64",
+    "os": "linux",
+    "rootfs": {"type": "layers", "diff_ids": [digest(b"diff-layer"), digest(b"diff-attestation")]},
+    "history": [{"created_by": "ADD layer"}, {"created_by": "ADD attestation"}],
+  });
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &serde_json::to_vec(&config).unwrap()),
+    "layers": [descriptor(LAYER_TYPE, layer), descriptor(ATTESTATION_TYPE, attestation)],
+  });
+  Image {
+    config,
+    layer,
+    attestation,
+    manifest,
+  }
+}
+
+pub(crate) fn manifest_mock(server: &mut ServerGuard, name: &str, reference: &str, manifest: &Value) -> Mock {
+  server
+    .mock("GET", format!("/v2/{name}/manifests/{reference}").as_str())
+    .with_status(200)
+    .with_header("Content-Type", OCI_MANIFEST)
+    .with_body(serde_json::to_vec(manifest).unwrap())
+    .create()
+}
+
+#[tokio::test]
+async fn test_copy_pull_image_layer_filter() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+
+  let mocks = vec![
+    manifest_mock(&mut server, "repo", "v1", &image.manifest),
+    blob_mock(&mut server, "repo", &serde_json::to_vec(&image.config).unwrap()),
+    blob_mock(&mut server, "repo", image.layer),
+    blob_mock(&mut server, "repo", image.attestation).expect(0),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = PullOptions {
+    layer_filter: LayerFilter::default().exclude_media_type(ATTESTATION_TYPE),
+    ..Default::default()
+  };
+  let pulled = client.pull_image("repo", "v1", &options).await.unwrap();
+
+  assert_eq!(pulled.tag.as_deref(), Some("v1"));
+  assert_eq!(pulled.media_type, OCI_MANIFEST);
+  assert_eq!(pulled.layers.len(), 1);
+  assert_eq!(pulled.layers[0].1, image.layer);
+  assert_eq!(pulled.skipped.len(), 1);
+  assert_eq!(pulled.skipped[0].media_type, ATTESTATION_TYPE);
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_pull_image_verifies_manifest_digest() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+
+  let mocks = vec![
+    server
+      .mock("GET", "/v2/repo/manifests/v1")
+      .with_status(200)
+      .with_header("Content-Type", OCI_MANIFEST)
+      .with_header("Docker-Content-Digest", &digest(b"other"))
+      .with_body(serde_json::to_vec(&image.manifest).unwrap())
+      .create(),
+    manifest_mock(&mut server, "repo", &digest(b"pinned"), &image.manifest),
+    blob_mock(&mut server, "repo", &serde_json::to_vec(&image.config).unwrap()).expect(0),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  for reference in ["v1".to_string(), digest(b"pinned")] {
+    let res = client.pull_image("repo", &reference, &PullOptions::default()).await;
+    assert!(matches!(
+      res,
+      Err(docker_registry::errors::Error::ContentDigestParse(_))
+    ));
+  }
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_pull_image_tag_hint() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+  let manifest_digest = digest(&serde_json::to_vec(&image.manifest).unwrap());
+
+  let mocks = vec![
+    manifest_mock(&mut server, "repo", &manifest_digest, &image.manifest),
+    blob_mock(&mut server, "repo", &serde_json::to_vec(&image.config).unwrap()),
+    blob_mock(&mut server, "repo", image.layer),
+    blob_mock(&mut server, "repo", image.attestation),
+    server
+      .mock("HEAD", "/v2/repo/manifests/v1")
+      .with_status(200)
+      .with_header("Docker-Content-Digest", &digest(b"moved"))
+      .create(),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  // The tag is not resolved: the image is pulled by digest even though the tag moved.
+  let reference = format!("v1@{manifest_digest}");
+  let pulled = client
+    .pull_image("repo", &reference, &PullOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(pulled.digest, manifest_digest);
+  assert_eq!(pulled.tag.as_deref(), Some("v1"));
+
+  let options = PullOptions {
+    verify_tag: true,
+    ..Default::default()
+  };
+  let res = client.pull_image("repo", &reference, &options).await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::TagMismatch { tag, expected, actual })
+      if tag == "v1" && expected == manifest_digest && actual == digest(b"moved")
+  ));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_pull_to_store_and_push_from_store() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+  let config = serde_json::to_vec(&image.config).unwrap();
+  let manifest = serde_json::to_vec(&image.manifest).unwrap();
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "src", "v1", &image.manifest).expect(2),
+    blob_mock(&mut server, "src", &config),
+    blob_mock(&mut server, "src", image.layer),
+    blob_mock(&mut server, "src", image.attestation),
+    server
+      .mock("PUT", "/v2/dst/manifests/v1")
+      .match_body(Matcher::Exact(String::from_utf8(manifest.clone()).unwrap()))
+      .with_status(201)
+      .with_header("Docker-Content-Digest", &digest(&manifest))
+      .create(),
+  ];
+  // Uploads are started in order: config first, then layers.
+  for data in [&config, image.layer, image.attestation] {
+    mocks.extend(streamed_upload_mocks(&mut server, "dst", data));
+  }
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let store = MemoryBlobStore::new();
+  let stored = pull_to_store(&client, "src", "v1", &store, &PullOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(stored.descriptor.digest, digest(&manifest));
+  assert_eq!(stored.tag.as_deref(), Some("v1"));
+  assert_eq!(stored.layers.len(), 2);
+  let mut expected = vec![
+    digest(&config),
+    digest(&manifest),
+    digest(image.layer),
+    digest(image.attestation),
+  ];
+  expected.sort();
+  assert_eq!(store.digests(), expected);
+
+  // Blobs already in the store are not downloaded again.
+  pull_to_store(&client, "src", "v1", &store, &PullOptions::default())
+    .await
+    .unwrap();
+  assert!(store.has(&digest(image.layer)).await.unwrap());
+
+  let pushed = push_from_store(&store, &stored.descriptor, &client, "dst", "v1")
+    .await
+    .unwrap();
+  assert_eq!(pushed, digest(&manifest));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_pull_to_store_insufficient_space() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+
+  let mocks = vec![
+    manifest_mock(&mut server, "src", "v1", &image.manifest),
+    blob_mock(&mut server, "src", &serde_json::to_vec(&image.config).unwrap()).expect(0),
+    blob_mock(&mut server, "src", image.layer).expect(0),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let dir = tempfile::tempdir().unwrap();
+  let staging = StagingDir::new(dir.path().join("staging")).min_free_space(u64::MAX);
+  let store = FsBlobStore::new(dir.path().join("blobs")).staging(staging);
+  let res = pull_to_store(&client, "src", "v1", &store, &PullOptions::default()).await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::InsufficientSpace { .. })
+  ));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_dir_transport() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+  let config = serde_json::to_vec(&image.config).unwrap();
+  let manifest = serde_json::to_vec(&image.manifest).unwrap();
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "src", "v1", &image.manifest),
+    blob_mock(&mut server, "src", &config),
+    blob_mock(&mut server, "src", image.layer),
+    blob_mock(&mut server, "src", image.attestation).expect(0),
+    server
+      .mock("PUT", "/v2/dst/manifests/v1")
+      .match_header("content-type", OCI_MANIFEST)
+      .match_body(Matcher::Exact(String::from_utf8(manifest.clone()).unwrap()))
+      .with_status(201)
+      .with_header("Docker-Content-Digest", &digest(&manifest))
+      .create(),
+  ];
+  mocks.extend(streamed_upload_mocks(&mut server, "dst", &config));
+  mocks.extend(streamed_upload_mocks(&mut server, "dst", image.layer));
+  mocks.push(
+    server
+      .mock("HEAD", format!("/v2/dst/blobs/{}", digest(image.attestation)).as_str())
+      .with_status(200)
+      .create(),
+  );
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let dir = tempfile::tempdir().unwrap();
+  let options = PullOptions {
+    layer_filter: LayerFilter::default().exclude_media_type(ATTESTATION_TYPE),
+    ..Default::default()
+  };
+  dir_transport::pull_to_dir(&client, "src", "v1", dir.path(), &options)
+    .await
+    .unwrap();
+  assert_eq!(std::fs::read(dir.path().join("manifest.json")).unwrap(), manifest);
+  assert_eq!(
+    std::fs::read_to_string(dir.path().join("version")).unwrap(),
+    dir_transport::VERSION
+  );
+  assert_eq!(
+    std::fs::read(dir.path().join(&digest(image.layer)[7..])).unwrap(),
+    image.layer
+  );
+  assert!(!dir.path().join(&digest(image.attestation)[7..]).exists());
+
+  let pushed = dir_transport::push_from_dir(dir.path(), &client, "dst", "v1")
+    .await
+    .unwrap();
+  assert_eq!(pushed, digest(&manifest));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_image_layer_filter() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+
+  let mut expected_config = image.config.clone();
+  expected_config["rootfs"]["diff_ids"] = json!([digest(b"diff-layer")]);
+  expected_config["history"] = json!([{"created_by": "ADD layer"}]);
+  let expected_config = serde_json::to_vec(&expected_config).unwrap();
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "src", "v1", &image.manifest),
+    blob_mock(&mut server, "src", &serde_json::to_vec(&image.config).unwrap()),
+    blob_mock(&mut server, "src", image.layer),
+    blob_mock(&mut server, "src", image.attestation).expect(0),
+    server
+      .mock("PUT", "/v2/dst/manifests/v1")
+      .match_header("content-type", OCI_MANIFEST)
+      .match_body(Matcher::PartialJson(json!({
+        "config": {"digest": digest(&expected_config), "size": expected_config.len()},
+        "layers": [descriptor(LAYER_TYPE, image.layer)],
+      })))
+      .with_status(201)
+      .create(),
+  ];
+  mocks.extend(streamed_upload_mocks(&mut server, "dst", image.layer));
+  mocks.extend(upload_mocks(&mut server, "dst", &expected_config));
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = CopyOptions {
+    layer_filter: LayerFilter::default().exclude_media_type(ATTESTATION_TYPE),
+    ..Default::default()
+  };
+  copy_image(&client, "src", "v1", &client, "dst", "v1", &options)
+    .await
+    .unwrap();
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_image_rejects_corrupt_blobs() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+  let config = serde_json::to_vec(&image.config).unwrap();
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": [descriptor(LAYER_TYPE, image.layer)],
+  });
+  let mocks = [
+    manifest_mock(&mut server, "src", "v1", &manifest),
+    server
+      .mock("HEAD", format!("/v2/dst/blobs/{}", digest(&config)).as_str())
+      .with_status(200)
+      .expect_at_most(1)
+      .create(),
+    server
+      .mock("HEAD", format!("/v2/dst/blobs/{}", digest(image.layer)).as_str())
+      .with_status(404)
+      .create(),
+    server
+      .mock("GET", format!("/v2/src/blobs/{}", digest(image.layer)).as_str())
+      .with_status(200)
+      .with_body("corrupt")
+      .create(),
+    // The blob fits in one chunk, so its digest is checked before an upload is started.
+    server.mock("POST", Matcher::Any).expect(0).create(),
+    server.mock("PUT", Matcher::Any).expect(0).create(),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let res = copy_image(&client, "src", "v1", &client, "dst", "v1", &CopyOptions::default()).await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::ContentDigestParse(
+      docker_registry::v2::ContentDigestError::Verify { .. }
+    ))
+  ));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_image_unchanged() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+  let manifest = serde_json::to_vec(&image.manifest).unwrap();
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "src", "v1", &image.manifest),
+    server
+      .mock("PUT", "/v2/dst/manifests/v1")
+      .match_body(Matcher::Exact(String::from_utf8(manifest.clone()).unwrap()))
+      .with_status(201)
+      .with_header("Docker-Content-Digest", &digest(&manifest))
+      .create(),
+  ];
+  for data in [
+    image.layer,
+    image.attestation,
+    &serde_json::to_vec(&image.config).unwrap(),
+  ] {
+    mocks.push(
+      server
+        .mock("HEAD", format!("/v2/dst/blobs/{}", digest(data)).as_str())
+        .with_status(200)
+        .create(),
+    );
+  }
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let copied = copy_image(&client, "src", "v1", &client, "dst", "v1", &CopyOptions::default())
+    .await
+    .unwrap();
+  assert_eq!(copied, digest(&manifest));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_images_resumes_queue() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+  let manifest = serde_json::to_vec(&image.manifest).unwrap();
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "src", "v1", &image.manifest).expect(1),
+    server
+      .mock("PUT", "/v2/dst/manifests/v1")
+      .with_status(201)
+      .with_header("Docker-Content-Digest", &digest(&manifest))
+      .expect(1)
+      .create(),
+  ];
+  for data in [
+    image.layer,
+    image.attestation,
+    &serde_json::to_vec(&image.config).unwrap(),
+  ] {
+    mocks.push(
+      server
+        .mock("HEAD", format!("/v2/dst/blobs/{}", digest(data)).as_str())
+        .with_status(200)
+        .create(),
+    );
+  }
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("copy.jsonl");
+  let queue = WorkQueue::open(&path).unwrap();
+  queue.push(vec![CopyTask::new("src", "v1").to("dst", "v1")]).unwrap();
+  let report = copy_images(
+    &client,
+    &client,
+    &queue,
+    &CopyOptions::default(),
+    &BulkOptions::default(),
+  )
+  .await
+  .unwrap();
+  assert_eq!(report.items[0].result.as_ref().unwrap(), &digest(&manifest));
+
+  // A restarted job does not copy the image again.
+  let queue = WorkQueue::open(&path).unwrap();
+  let report = copy_images(
+    &client,
+    &client,
+    &queue,
+    &CopyOptions::default(),
+    &BulkOptions::default(),
+  )
+  .await
+  .unwrap();
+  assert!(report.items.is_empty());
+  assert!(queue.is_complete());
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+pub(crate) fn tar_layer(files: &[(&str, &[u8])]) -> Vec<u8> {
+  let mut builder = tar::Builder::new(Vec::new());
+  for (path, data) in files {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, path, *data).unwrap();
+  }
+  builder.into_inner().unwrap()
+}
+
+#[tokio::test]
+async fn test_copy_image_squash() {
+  let mut server = mockito::Server::new_async().await;
+
+  let lower_tar = tar_layer(&[("a", b"lower"), ("b", b"b")]);
+  let upper_tar = tar_layer(&[("a", b"upper"), (".wh.b", b"")]);
+  let lower = Compression::Gzip.compress(&lower_tar).unwrap();
+  let upper = Compression::Gzip.compress(&upper_tar).unwrap();
+  let config = json!({
+    "architecture": "amd64",
+    "os": "linux",
+    "created": "2024-01-01T00:00:00Z",
+    "rootfs": {"type": "layers", "diff_ids": [digest(&lower_tar), digest(&upper_tar)]},
+    "history": [
+      {"created_by": "ADD a b"},
+      {"created_by": "ENV x=y", "empty_layer": true},
+      {"created_by": "RUN rm b"},
+    ],
+  });
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &serde_json::to_vec(&config).unwrap()),
+    "layers": [descriptor(LAYER_TYPE, &lower), descriptor(LAYER_TYPE, &upper)],
+  });
+
+  let squashed_tar = layer::squash(&[lower_tar, upper_tar]).unwrap();
+  let squashed = Compression::Gzip.compress(&squashed_tar).unwrap();
+  let mut expected_config = config.clone();
+  expected_config["rootfs"]["diff_ids"] = json!([digest(&squashed_tar)]);
+  expected_config["history"] = json!([
+    {"created_by": "ENV x=y", "empty_layer": true},
+    {"created_by": "squash", "comment": "squashed 2 layers", "created": "2024-01-01T00:00:00Z"},
+  ]);
+  let expected_config = serde_json::to_vec(&expected_config).unwrap();
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "src", "v1", &manifest),
+    blob_mock(&mut server, "src", &serde_json::to_vec(&config).unwrap()),
+    blob_mock(&mut server, "src", &lower),
+    blob_mock(&mut server, "src", &upper),
+    server
+      .mock("PUT", "/v2/dst/manifests/v1")
+      .match_body(Matcher::PartialJson(json!({
+        "config": {"digest": digest(&expected_config)},
+        "layers": [descriptor(LAYER_TYPE, &squashed)],
+      })))
+      .with_status(201)
+      .create(),
+  ];
+  mocks.extend(upload_mocks(&mut server, "dst", &squashed));
+  mocks.extend(upload_mocks(&mut server, "dst", &expected_config));
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = CopyOptions {
+    transforms: vec![Transform::Squash],
+    ..Default::default()
+  };
+  copy_image(&client, "src", "v1", &client, "dst", "v1", &options)
+    .await
+    .unwrap();
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_image_recompress() {
+  let mut server = mockito::Server::new_async().await;
+
+  let tar = tar_layer(&[("a", b"content")]);
+  let gzipped = Compression::Gzip.compress(&tar).unwrap();
+  let config = serde_json::to_vec(&json!({
+    "rootfs": {"type": "layers", "diff_ids": [digest(&tar)]},
+  }))
+  .unwrap();
+  let mut layer = descriptor(LAYER_TYPE, &gzipped);
+  layer["annotations"] = json!({"org.example.kind": "app"});
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": [layer],
+  });
+
+  let mut expected_layer = descriptor("application/vnd.oci.image.layer.v1.tar", &tar);
+  expected_layer["annotations"] = json!({"org.example.kind": "app"});
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "src", "v1", &manifest),
+    blob_mock(&mut server, "src", &config),
+    blob_mock(&mut server, "src", &gzipped),
+    server
+      .mock("PUT", "/v2/dst/manifests/v1")
+      .match_body(Matcher::PartialJson(json!({
+        "config": {"digest": digest(&config)},
+        "layers": [expected_layer],
+      })))
+      .with_status(201)
+      .create(),
+    server
+      .mock("HEAD", format!("/v2/dst/blobs/{}", digest(&config)).as_str())
+      .with_status(200)
+      .create(),
+  ];
+  mocks.extend(upload_mocks(&mut server, "dst", &tar));
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = CopyOptions {
+    transforms: vec![Transform::Recompress {
+      compression: Compression::None,
+      level: None,
+    }],
+    ..Default::default()
+  };
+  copy_image(&client, "src", "v1", &client, "dst", "v1", &options)
+    .await
+    .unwrap();
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_image_annotations_and_labels() {
+  let mut server = mockito::Server::new_async().await;
+  let mut image = image();
+  image.config["config"] = json!({"Labels": {"stage": "build", "team": "infra"}});
+  image.manifest["config"] = descriptor(
+    "application/vnd.oci.image.config.v1+json",
+    &serde_json::to_vec(&image.config).unwrap(),
+  );
+  image.manifest["annotations"] = json!({"org.opencontainers.image.source": "https://example.com/repo"});
+
+  let mut expected_config = image.config.clone();
+  expected_config["config"]["Labels"] = json!({"team": "infra", "vcs-ref": "abc123"});
+  let expected_config = serde_json::to_vec(&expected_config).unwrap();
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "src", "v1", &image.manifest),
+    blob_mock(&mut server, "src", &serde_json::to_vec(&image.config).unwrap()),
+    // Layers are left untouched, so they are not downloaded.
+    blob_mock(&mut server, "src", image.layer).expect(0),
+    blob_mock(&mut server, "src", image.attestation).expect(0),
+    server
+      .mock("PUT", "/v2/dst/manifests/v1")
+      .match_body(Matcher::PartialJson(json!({
+        "config": {"digest": digest(&expected_config)},
+        "layers": image.manifest["layers"],
+        "annotations": {
+          "org.opencontainers.image.source": "https://example.com/repo",
+          "org.opencontainers.image.revision": "abc123",
+        },
+      })))
+      .with_status(201)
+      .create(),
+  ];
+  for data in [image.layer, image.attestation] {
+    mocks.push(
+      server
+        .mock("HEAD", format!("/v2/dst/blobs/{}", digest(data)).as_str())
+        .with_status(200)
+        .create(),
+    );
+  }
+  mocks.extend(upload_mocks(&mut server, "dst", &expected_config));
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = CopyOptions {
+    transforms: vec![
+      Transform::Annotations(
+        [(
+          "org.opencontainers.image.revision".to_string(),
+          Some("abc123".to_string()),
+        )]
+        .into(),
+      ),
+      Transform::Labels(
+        [
+          ("stage".to_string(), None),
+          ("vcs-ref".to_string(), Some("abc123".to_string())),
+        ]
+        .into(),
+      ),
+    ],
+    ..Default::default()
+  };
+  copy_image(&client, "src", "v1", &client, "dst", "v1", &options)
+    .await
+    .unwrap();
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+
+fn head_manifest_mock(server: &mut ServerGuard, name: &str, manifest: &[u8], status: usize) -> Mock {
+  server
+    .mock("HEAD", format!("/v2/{name}/manifests/{}", digest(manifest)).as_str())
+    .with_status(status)
+    .with_header("Content-Type", OCI_MANIFEST)
+    .create()
+}
+
+#[tokio::test]
+async fn test_copy_push_index_missing_content() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+  let config = serde_json::to_vec(&image.config).unwrap();
+  let present = serde_json::to_vec(&image.manifest).unwrap();
+  let absent = b"{\"schemaVersion\":2}".to_vec();
+  let index = serde_json::to_vec(&json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_INDEX,
+    "manifests": [descriptor(OCI_MANIFEST, &present), descriptor(OCI_MANIFEST, &absent)],
+  }))
+  .unwrap();
+
+  let mut mocks = vec![
+    head_manifest_mock(&mut server, "dst", &present, 200),
+    head_manifest_mock(&mut server, "dst", &absent, 404),
+    manifest_mock(&mut server, "dst", &digest(&present), &image.manifest),
+    server.mock("PUT", "/v2/dst/manifests/v1").expect(0).create(),
+  ];
+  for (data, status) in [(&config[..], 200), (image.layer, 404), (image.attestation, 200)] {
+    mocks.push(
+      server
+        .mock("HEAD", format!("/v2/dst/blobs/{}", digest(data)).as_str())
+        .with_status(status)
+        .create(),
+    );
+  }
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let res = push_index(&client, "dst", "v1", OCI_INDEX, &index, None).await;
+  let Err(docker_registry::errors::Error::MissingContent { repository, missing }) = res else {
+    panic!("unexpected result {:?}", res);
+  };
+  assert_eq!(repository, "dst");
+  assert_eq!(
+    missing,
+    vec![
+      MissingContent {
+        manifest: digest(&present),
+        digest: digest(image.layer),
+        kind: "layer",
+      },
+      MissingContent {
+        manifest: digest(&absent),
+        digest: digest(&absent),
+        kind: "manifest",
+      },
+    ]
+  );
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_copy_push_index_copies_missing_children() {
+  let mut server = mockito::Server::new_async().await;
+  let image = image();
+  let config = serde_json::to_vec(&image.config).unwrap();
+  let manifest = serde_json::to_vec(&image.manifest).unwrap();
+  let index = serde_json::to_vec(&json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_INDEX,
+    "manifests": [descriptor(OCI_MANIFEST, &manifest)],
+  }))
+  .unwrap();
+
+  let mut mocks = vec![
+    head_manifest_mock(&mut server, "dst", &manifest, 404),
+    manifest_mock(&mut server, "src", &digest(&manifest), &image.manifest),
+    blob_mock(&mut server, "src", &config),
+    blob_mock(&mut server, "src", image.layer),
+    blob_mock(&mut server, "src", image.attestation),
+    server
+      .mock("PUT", format!("/v2/dst/manifests/{}", digest(&manifest)).as_str())
+      .match_body(manifest.clone())
+      .with_status(201)
+      .create(),
+    server
+      .mock("PUT", "/v2/dst/manifests/v1")
+      .match_body(index.clone())
+      .with_status(201)
+      .with_header("Docker-Content-Digest", &digest(&index))
+      .create(),
+  ];
+  for data in [&config[..], image.layer, image.attestation] {
+    mocks.extend(streamed_upload_mocks(&mut server, "dst", data));
+  }
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let pushed = push_index(&client, "dst", "v1", OCI_INDEX, &index, Some((&client, "src")))
+    .await
+    .unwrap();
+  assert_eq!(pushed, digest(&index));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
@@ -0,0 +1,86 @@
+static API_VERSION_K: &str = "Docker-Distribution-API-Version";
+static API_VERSION_V: &str = "registry/2.0";
+
+#[derive(Clone, Debug, Default)]
+struct RequestIds(std::sync::Arc<std::sync::Mutex<Vec<Option<String>>>>);
+
+impl docker_registry::v2::Interceptor for RequestIds {
+  fn on_request(
+    &self,
+    request: &docker_registry::v2::InterceptedRequest,
+  ) -> Result<(), docker_registry::v2::HookError> {
+    self.0.lock().unwrap().push(request.request_id().map(str::to_string));
+    Ok(())
+  }
+}
+
+#[tokio::test]
+async fn test_correlation_request_ids() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+  let blob = b"hello";
+  let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+  let mocks = vec![
+    server
+      .mock("GET", format!("/v2/repo/blobs/{digest}").as_str())
+      .match_header("x-correlation-id", "push-1")
+      .with_status(200)
+      .with_body(blob)
+      .create(),
+    server
+      .mock("GET", "/v2/repo/manifests/missing")
+      .match_header("x-correlation-id", "push-1")
+      .with_status(404)
+      .with_header("Content-Type", "application/json")
+      .with_body(r#"{"errors": [{"code": "MANIFEST_UNKNOWN", "message": "manifest unknown"}]}"#)
+      .create(),
+    server
+      .mock("GET", "/v2/")
+      .match_header(
+        "x-correlation-id",
+        mockito::Matcher::Regex("^[0-9a-f]{32}$".to_string()),
+      )
+      .with_status(200)
+      .with_header(API_VERSION_K, API_VERSION_V)
+      .create(),
+  ];
+
+  let ids = RequestIds::default();
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .request_id_header("X-Correlation-Id")
+    .with_interceptor(ids.clone())
+    .build()
+    .unwrap();
+
+  let res = client
+    .correlate(Some("push-1"), |client| async move {
+      assert_eq!(client.request_id(), Some("push-1"));
+      assert_eq!(client.get_blob("repo", digest).await?, blob);
+      client.get_manifest("repo", "missing").await
+    })
+    .await;
+  let err = res.unwrap_err();
+  assert_eq!(err.request_id(), Some("push-1"));
+  assert!(err.to_string().ends_with("(request id push-1)"));
+  assert!(matches!(
+    err,
+    docker_registry::errors::Error::Correlated { source, .. } if matches!(*source, docker_registry::errors::Error::Api(_))
+  ));
+
+  let res = client
+    .correlate(None, |client| async move { client.is_v2_supported().await })
+    .await;
+  assert!(res.unwrap());
+  assert!(client.request_id().is_none());
+
+  let ids = ids.0.lock().unwrap().clone();
+  assert_eq!(ids[..2], [Some("push-1".to_string()), Some("push-1".to_string())]);
+  assert_eq!(ids[2].as_ref().map(String::len), Some(32));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
@@ -0,0 +1,255 @@
+use std::{
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use docker_registry::v2::{Client, CredentialFuture, CredentialProvider};
+use mockito::{Matcher, ServerGuard};
+use serde_json::json;
+
+/// Provider returning `password` for `user`, counting how often it is asked.
+#[derive(Debug)]
+struct RotatedPassword {
+  password: Option<&'static str>,
+  calls: Arc<AtomicUsize>,
+}
+
+impl CredentialProvider for RotatedPassword {
+  fn credentials<'a>(
+    &'a self,
+    registry: &'a str,
+    identity: Option<&'a str>,
+    username: &'a str,
+  ) -> CredentialFuture<'a> {
+    Box::pin(async move {
+      assert!(registry.starts_with("127.0.0.1:"), "{registry}");
+      assert_eq!((identity, username), (None, "user"));
+      self.calls.fetch_add(1, Ordering::SeqCst);
+      // Like a credential helper taking its time.
+      tokio::time::sleep(Duration::from_millis(100)).await;
+      Ok(self.password.map(|p| (username.to_string(), p.to_string())))
+    })
+  }
+}
+
+fn basic(password: &str) -> String {
+  format!("Basic {}", STANDARD.encode(format!("user:{password}")))
+}
+
+fn client(server: &ServerGuard, password: Option<&'static str>) -> (Client, Arc<AtomicUsize>) {
+  let calls = Arc::new(AtomicUsize::new(0));
+  let client = Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .username(Some("user".to_string()))
+    .password(Some("old".to_string()))
+    .credential_provider(RotatedPassword {
+      password,
+      calls: calls.clone(),
+    })
+    .build()
+    .unwrap();
+  (client, calls)
+}
+
+#[tokio::test]
+async fn test_credential_provider_renews_basic_credentials() {
+  let mut server = mockito::Server::new_async().await;
+  let mocks = [
+    server
+      .mock("GET", "/v2/")
+      .with_status(401)
+      .with_header("WWW-Authenticate", r#"Basic realm="Registry""#)
+      .create(),
+    server
+      .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+      .match_header("Authorization", basic("old").as_str())
+      .with_status(401)
+      .expect(1)
+      .create(),
+    server
+      .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+      .match_header("Authorization", basic("new").as_str())
+      .with_status(200)
+      .expect(2)
+      .create(),
+  ];
+
+  let (client, calls) = client(&server, Some("new"));
+  let client = client.authenticate(&[]).await.unwrap();
+  assert!(client.has_blob("repo", "sha256:aaaa").await.unwrap());
+  // Later requests, also of derived clients, use the renewed credentials right away.
+  let derived = client.with_request_id("id");
+  assert!(derived.has_blob("repo", "sha256:aaaa").await.unwrap());
+  assert_eq!(calls.load(Ordering::SeqCst), 1);
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_credential_provider_without_fresh_credentials() {
+  let mut server = mockito::Server::new_async().await;
+  let mocks = [
+    server
+      .mock("GET", "/v2/")
+      .with_status(401)
+      .with_header("WWW-Authenticate", r#"Basic realm="Registry""#)
+      .create(),
+    server
+      .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+      .match_header("Authorization", basic("old").as_str())
+      .with_status(401)
+      .expect(1)
+      .create(),
+  ];
+
+  let (client, calls) = client(&server, None);
+  let client = client.authenticate(&[]).await.unwrap();
+  assert!(!client.has_blob("repo", "sha256:aaaa").await.unwrap());
+  assert_eq!(calls.load(Ordering::SeqCst), 1);
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_credential_provider_renews_token_credentials() {
+  let mut server = mockito::Server::new_async().await;
+  let challenge = format!(r#"Bearer realm="{}/token",service="registry""#, server.url());
+  let mocks = [
+    server
+      .mock("GET", "/v2/")
+      .with_status(401)
+      .with_header("WWW-Authenticate", &challenge)
+      .create(),
+    server
+      .mock("GET", "/token")
+      .match_query(Matcher::Any)
+      .match_header("Authorization", basic("old").as_str())
+      .with_status(401)
+      .expect(1)
+      .create(),
+    server
+      .mock("GET", "/token")
+      .match_query(Matcher::Any)
+      .match_header("Authorization", basic("new").as_str())
+      .with_status(200)
+      .with_body(json!({"token": "t1"}).to_string())
+      .expect(1)
+      .create(),
+  ];
+
+  let (client, calls) = client(&server, Some("new"));
+  client.authenticate(&["repository:repo:pull"]).await.unwrap();
+  assert_eq!(calls.load(Ordering::SeqCst), 1);
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_credential_provider_asked_once_for_concurrent_requests() {
+  let mut server = mockito::Server::new_async().await;
+  let mocks = [
+    server
+      .mock("GET", "/v2/")
+      .with_status(401)
+      .with_header("WWW-Authenticate", r#"Basic realm="Registry""#)
+      .create(),
+    server
+      .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+      .match_header("Authorization", basic("old").as_str())
+      .with_status(401)
+      .expect_at_least(1)
+      .create(),
+    server
+      .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+      .match_header("Authorization", basic("new").as_str())
+      .with_status(200)
+      .expect(3)
+      .create(),
+  ];
+
+  let (client, calls) = client(&server, Some("new"));
+  let client = client.authenticate(&[]).await.unwrap();
+  let (first, second, third) = tokio::join!(
+    client.has_blob("repo", "sha256:aaaa"),
+    client.has_blob("repo", "sha256:aaaa"),
+    client.has_blob("repo", "sha256:aaaa"),
+  );
+  assert!(first.unwrap() && second.unwrap() && third.unwrap());
+  assert_eq!(calls.load(Ordering::SeqCst), 1);
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
+
+/// Provider rotating the password of the tenant it is asked for.
+#[derive(Debug)]
+struct RotatedTenantPassword;
+
+impl CredentialProvider for RotatedTenantPassword {
+  fn credentials<'a>(
+    &'a self,
+    _registry: &'a str,
+    identity: Option<&'a str>,
+    username: &'a str,
+  ) -> CredentialFuture<'a> {
+    Box::pin(async move { Ok(identity.map(|id| (username.to_string(), format!("{id}-new")))) })
+  }
+}
+
+#[tokio::test]
+async fn test_credential_provider_renews_per_identity() {
+  let mut server = mockito::Server::new_async().await;
+  let tenant_basic = |password: &str| format!("Basic {}", STANDARD.encode(format!("AWS:{password}")));
+  let mocks = [
+    server
+      .mock("GET", "/v2/")
+      .with_status(401)
+      .with_header("WWW-Authenticate", r#"Basic realm="Registry""#)
+      .expect(2)
+      .create(),
+    server
+      .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+      .match_header("Authorization", tenant_basic("acme-old").as_str())
+      .with_status(401)
+      .expect(1)
+      .create(),
+    server
+      .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+      .match_header("Authorization", tenant_basic("acme-new").as_str())
+      .with_status(200)
+      .expect(2)
+      .create(),
+    // Another tenant with the same username keeps its own password.
+    server
+      .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+      .match_header("Authorization", tenant_basic("globex-old").as_str())
+      .with_status(200)
+      .expect(1)
+      .create(),
+  ];
+
+  let client = Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .identity("acme", "AWS", "acme-old")
+    .identity("globex", "AWS", "globex-old")
+    .credential_provider(RotatedTenantPassword)
+    .build()
+    .unwrap();
+  let acme = client.as_identity("acme").unwrap().authenticate(&[]).await.unwrap();
+  assert!(acme.has_blob("repo", "sha256:aaaa").await.unwrap());
+  assert!(acme.has_blob("repo", "sha256:aaaa").await.unwrap());
+  let globex = client.as_identity("globex").unwrap().authenticate(&[]).await.unwrap();
+  assert!(globex.has_blob("repo", "sha256:aaaa").await.unwrap());
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
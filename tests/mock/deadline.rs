@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use docker_registry::{
+  bulk::BulkOptions,
+  copy::{copy_image, CopyOptions},
+  errors::Error,
+  v2::{Client, RetryPolicy},
+};
+use serde_json::json;
+
+use crate::mock::copy::{descriptor, digest, manifest_mock, LAYER_TYPE, OCI_MANIFEST};
+
+fn client(server: &mockito::ServerGuard) -> Client {
+  Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .retry_policy(Some(RetryPolicy::default().max_retries(3)))
+    .build()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_deadline_bounds_slow_requests() {
+  // A server accepting connections without ever answering.
+  let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+  let client = Client::configure()
+    .registry(&listener.local_addr().unwrap().to_string())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+    .with_deadline(Duration::from_millis(200));
+
+  let err = client.has_blob("repo", "sha256:aaaa").await.unwrap_err();
+  assert!(matches!(err, Error::DeadlineExceeded), "{err:?}");
+  // Requests are not sent at all once the deadline passed.
+  let err = client.has_blob("repo", "sha256:aaaa").await.unwrap_err();
+  assert!(matches!(err, Error::DeadlineExceeded), "{err:?}");
+}
+
+#[tokio::test]
+async fn test_deadline_skips_late_retries() {
+  let mut server = mockito::Server::new_async().await;
+  let mock = server
+    .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+    .with_status(503)
+    .with_header("Retry-After", "60")
+    .expect(1)
+    .create();
+
+  let client = client(&server).with_deadline(Duration::from_secs(5));
+  let err = client.has_blob("repo", "sha256:aaaa").await.unwrap_err();
+  assert!(matches!(err, Error::DeadlineExceeded), "{err:?}");
+  mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_deadline_keeps_partial_results() {
+  let mut server = mockito::Server::new_async().await;
+  let mocks = [
+    server
+      .mock("GET", "/v2/repo/manifests/v1")
+      .with_status(200)
+      .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+      .with_body("{}")
+      .create(),
+    server
+      .mock("GET", "/v2/repo/manifests/v2")
+      .with_status(200)
+      .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+      .with_chunked_body(|_| {
+        std::thread::sleep(Duration::from_secs(1));
+        Ok(())
+      })
+      .create(),
+  ];
+
+  let client = client(&server).with_deadline(Duration::from_millis(500));
+  let options = BulkOptions {
+    concurrency: 1,
+    ..Default::default()
+  };
+  let report = docker_registry::bulk::run(vec!["v1", "v2", "v3"], &options, |tag| {
+    let client = &client;
+    async move { client.get_raw_manifest("repo", tag, None).await }
+  })
+  .await;
+  assert_eq!(report.succeeded().map(|i| i.item).collect::<Vec<_>>(), ["v1"]);
+  assert_eq!(report.failed().count(), 2);
+  // The body of the second manifest was cut off by the deadline, and the last tag was not
+  // requested, the deadline having passed.
+  assert!(
+    matches!(report.items[1].result, Err(Error::DeadlineExceeded)),
+    "{:?}",
+    report.items[1].result
+  );
+  assert!(matches!(report.items[2].result, Err(Error::DeadlineExceeded)));
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_deadline_reports_copied_blobs() {
+  let mut server = mockito::Server::new_async().await;
+  let (present, slow): (&[u8], &[u8]) = (b"present", b"slow");
+  let config = serde_json::to_vec(&json!({"architecture": "amd64", "os": "linux"})).unwrap();
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": [descriptor(LAYER_TYPE, present), descriptor(LAYER_TYPE, slow)],
+  });
+  let mocks = [
+    manifest_mock(&mut server, "src", "v1", &manifest),
+    server
+      .mock("HEAD", format!("/v2/dst/blobs/{}", digest(present)).as_str())
+      .with_status(200)
+      .create(),
+    server
+      .mock("HEAD", format!("/v2/dst/blobs/{}", digest(slow)).as_str())
+      .with_status(404)
+      .create(),
+    server
+      .mock("GET", format!("/v2/src/blobs/{}", digest(slow)).as_str())
+      .with_status(200)
+      .with_chunked_body(|_| {
+        std::thread::sleep(Duration::from_secs(1));
+        Ok(())
+      })
+      .create(),
+  ];
+
+  let client = client(&server).with_deadline(Duration::from_millis(500));
+  let err = copy_image(&client, "src", "v1", &client, "dst", "v1", &CopyOptions::default())
+    .await
+    .unwrap_err();
+  match err {
+    Error::DeadlineExceededAfter { completed } => assert_eq!(completed, [digest(present)]),
+    err => panic!("{err:?}"),
+  }
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
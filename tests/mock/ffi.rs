@@ -0,0 +1,135 @@
+use std::{
+  ffi::{c_char, CStr, CString},
+  ptr,
+};
+
+use docker_registry::ffi::*;
+use serde_json::{json, Value};
+
+use crate::mock::copy::{blob_mock, descriptor, digest, manifest_mock, LAYER_TYPE, OCI_MANIFEST};
+
+/// Take the JSON string handed over by the library.
+fn take_json(s: *mut c_char) -> Value {
+  let value = serde_json::from_slice(unsafe { CStr::from_ptr(s) }.to_bytes()).unwrap();
+  unsafe { docker_registry_string_free(s) };
+  value
+}
+
+fn last_error() -> String {
+  let message = docker_registry_last_error();
+  assert!(!message.is_null());
+  unsafe { CStr::from_ptr(message) }.to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_ffi_inspect_tags_and_pull() {
+  let mut server = mockito::Server::new();
+  let layer: &[u8] = b"layer";
+  let config = serde_json::to_vec(&json!({"architecture": "amd64", "os": "linux"})).unwrap();
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": [descriptor(LAYER_TYPE, layer)],
+  });
+  let manifest_digest = digest(&serde_json::to_vec(&manifest).unwrap());
+
+  let mocks = vec![
+    server.mock("GET", "/v2/").with_status(200).expect_at_least(1).create(),
+    manifest_mock(&mut server, "repo", "v1", &manifest).expect(2),
+    server
+      .mock("HEAD", "/v2/repo/manifests/v1")
+      .with_status(200)
+      .with_header("Content-Type", OCI_MANIFEST)
+      .with_header("Last-Modified", "Sun, 06 Nov 1994 08:49:37 GMT")
+      .create(),
+    blob_mock(&mut server, "repo", &config).expect(2),
+    blob_mock(&mut server, "repo", layer),
+    server
+      .mock("GET", "/v2/repo/tags/list")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(r#"{"name": "repo", "tags": ["v1", "v2"]}"#)
+      .create(),
+    server
+      .mock("GET", "/v2/repo/manifests/missing")
+      .with_status(404)
+      .with_header("Content-Type", "application/json")
+      .with_body(r#"{"errors": [{"code": "MANIFEST_UNKNOWN", "message": "manifest unknown"}]}"#)
+      .create(),
+  ];
+
+  let config_json = CString::new(json!({"registry": server.host_with_port(), "insecure": true}).to_string()).unwrap();
+  let mut client = ptr::null_mut();
+  assert_eq!(
+    unsafe { docker_registry_client_new(config_json.as_ptr(), &mut client) },
+    Status::Ok
+  );
+  assert!(docker_registry_last_error().is_null());
+
+  let repo = CString::new("repo").unwrap();
+  let v1 = CString::new("v1").unwrap();
+  let mut out = ptr::null_mut();
+  assert_eq!(
+    unsafe { docker_registry_inspect(client, repo.as_ptr(), v1.as_ptr(), &mut out) },
+    Status::Ok
+  );
+  let inspected = take_json(out);
+  assert_eq!(inspected["mediaType"], OCI_MANIFEST);
+  assert_eq!(inspected["manifest"], manifest);
+  assert_eq!(inspected["config"]["architecture"], "amd64");
+  assert_eq!(inspected["lastModified"], 784111777);
+
+  assert_eq!(
+    unsafe { docker_registry_list_tags(client, repo.as_ptr(), &mut out) },
+    Status::Ok
+  );
+  assert_eq!(take_json(out)["tags"], json!(["v1", "v2"]));
+
+  let dir = tempfile::tempdir().unwrap();
+  let dir_path = CString::new(dir.path().to_str().unwrap()).unwrap();
+  assert_eq!(
+    unsafe { docker_registry_pull_to_layout(client, repo.as_ptr(), v1.as_ptr(), dir_path.as_ptr(), &mut out) },
+    Status::Ok
+  );
+  assert_eq!(take_json(out)["digest"], manifest_digest);
+  let index: Value = serde_json::from_slice(&std::fs::read(dir.path().join("index.json")).unwrap()).unwrap();
+  assert_eq!(index["manifests"][0]["digest"], manifest_digest);
+  assert_eq!(
+    index["manifests"][0]["annotations"]["org.opencontainers.image.ref.name"],
+    "v1"
+  );
+  let layer_path = dir.path().join("blobs/sha256").join(&digest(layer)[7..]);
+  assert_eq!(std::fs::read(layer_path).unwrap(), layer);
+  assert!(dir.path().join("oci-layout").is_file());
+
+  let missing = CString::new("missing").unwrap();
+  assert_eq!(
+    unsafe { docker_registry_inspect(client, repo.as_ptr(), missing.as_ptr(), &mut out) },
+    Status::NotFound
+  );
+  assert!(last_error().contains("MANIFEST_UNKNOWN"));
+
+  assert_eq!(
+    unsafe { docker_registry_list_tags(client, ptr::null(), &mut out) },
+    Status::InvalidArgument
+  );
+  assert_eq!(last_error(), "name is null");
+
+  unsafe { docker_registry_client_free(client) };
+  for mock in mocks {
+    mock.assert();
+  }
+}
+
+#[test]
+fn test_ffi_invalid_config() {
+  let config_json = CString::new(r#"{"insecure": true}"#).unwrap();
+  let mut client = ptr::null_mut();
+  assert_eq!(
+    unsafe { docker_registry_client_new(config_json.as_ptr(), &mut client) },
+    Status::InvalidArgument
+  );
+  assert!(client.is_null());
+  assert!(last_error().contains("registry"));
+}
@@ -0,0 +1,200 @@
+use docker_registry::{
+  bulk::{BulkOptions, WorkQueue},
+  errors::Error,
+  gitlab::{GitLabCleanup, GitLabRegistry, GitLabTag},
+  v2::Client,
+};
+use mockito::{Matcher, Mock, ServerGuard};
+use serde_json::json;
+
+fn gitlab(server: &ServerGuard, user: &str) -> GitLabRegistry {
+  GitLabRegistry::new(
+    Client::configure()
+      .registry(&server.host_with_port())
+      .insecure_registry(true)
+      .username(Some(user.to_string()))
+      .password(Some("glpat-secret".to_string()))
+      .build()
+      .unwrap(),
+  )
+}
+
+/// Mocks the registry repositories of the project `group/project`, and the lookup of projects
+/// in `group/project`, which do not exist.
+fn repositories_mock(server: &mut ServerGuard) -> [Mock; 2] {
+  [
+    server
+      .mock(
+        "GET",
+        Matcher::Regex(r"^/api/v4/projects/group%2Fproject%2F\w+/registry/repositories".to_string()),
+      )
+      .match_query(Matcher::Any)
+      .with_status(404)
+      .with_body(r#"{"message": "404 Project Not Found"}"#)
+      .create(),
+    server
+      .mock("GET", "/api/v4/projects/group%2Fproject/registry/repositories")
+      .match_query(Matcher::UrlEncoded("per_page".into(), "100".into()))
+      .match_header("private-token", "glpat-secret")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(
+        json!([
+          {"id": 11, "name": "", "path": "group/project", "project_id": 7},
+          {"id": 12, "name": "app", "path": "group/project/app", "project_id": 7},
+        ])
+        .to_string(),
+      )
+      .create(),
+  ]
+}
+
+#[tokio::test]
+async fn test_gitlab_tag_details() {
+  let mut server = mockito::Server::new_async().await;
+  let _repositories = repositories_mock(&mut server);
+  let _tag = server
+    .mock("GET", "/api/v4/projects/7/registry/repositories/12/tags/v1")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(
+      json!({
+        "name": "v1",
+        "path": "group/project/app:v1",
+        "location": "registry.gitlab.com/group/project/app:v1",
+        "revision": "9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab",
+        "digest": "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab",
+        "created_at": "2024-05-02T10:11:12.000+00:00",
+        "total_size": 2819706,
+      })
+      .to_string(),
+    )
+    .create();
+  let _missing = server
+    .mock("GET", "/api/v4/projects/7/registry/repositories/12/tags/v2")
+    .with_status(404)
+    .create();
+
+  let gitlab = gitlab(&server, "user");
+  let repository = gitlab.repository("group/project/app").await.unwrap();
+  assert_eq!((repository.project_id, repository.id), (7, 12));
+  assert_eq!(
+    gitlab.tag("group/project/app", "v1").await.unwrap(),
+    Some(GitLabTag {
+      name: "v1".to_string(),
+      digest: Some("sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab".to_string()),
+      created_at: Some("2024-05-02T10:11:12.000+00:00".to_string()),
+      total_size: Some(2819706),
+    })
+  );
+  assert_eq!(gitlab.tag("group/project/app", "v2").await.unwrap(), None);
+  assert!(matches!(
+    gitlab.repository("group/project/other").await,
+    Err(Error::RepositoryNotFound(name)) if name == "group/project/other"
+  ));
+}
+
+#[tokio::test]
+async fn test_gitlab_delete_tags() {
+  let mut server = mockito::Server::new_async().await;
+  let _repositories = repositories_mock(&mut server);
+  let deletes: Vec<_> = ["v1", "v2"]
+    .into_iter()
+    .map(|tag| {
+      server
+        .mock(
+          "DELETE",
+          format!("/api/v4/projects/7/registry/repositories/12/tags/{}", tag).as_str(),
+        )
+        .with_status(200)
+        .create()
+    })
+    .collect();
+  let bulk = server
+    .mock("DELETE", "/api/v4/projects/7/registry/repositories/12/tags")
+    .match_body(Matcher::AllOf(vec![
+      Matcher::UrlEncoded("name_regex_delete".into(), ".*".into()),
+      Matcher::UrlEncoded("name_regex_keep".into(), "^v.*".into()),
+      Matcher::UrlEncoded("keep_n".into(), "5".into()),
+      Matcher::UrlEncoded("older_than".into(), "7d".into()),
+    ]))
+    .with_status(202)
+    .create();
+
+  let gitlab = gitlab(&server, "user");
+  let report = gitlab
+    .delete_tags(
+      "group/project/app",
+      vec!["v1".to_string(), "v2".to_string()],
+      &BulkOptions::default(),
+    )
+    .await
+    .unwrap();
+  assert!(report.is_success());
+  gitlab
+    .delete_tags_matching(
+      "group/project/app",
+      &GitLabCleanup::new(".*").keep_regex("^v.*").keep_n(5).older_than("7d"),
+    )
+    .await
+    .unwrap();
+  for mock in deletes.iter().chain([&bulk]) {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_gitlab_delete_queued_tags() {
+  let mut server = mockito::Server::new_async().await;
+  let _repositories = repositories_mock(&mut server);
+  let deletes: Vec<_> = ["v1", "v2"]
+    .into_iter()
+    .map(|tag| {
+      server
+        .mock(
+          "DELETE",
+          format!("/api/v4/projects/7/registry/repositories/12/tags/{}", tag).as_str(),
+        )
+        .with_status(200)
+        .expect(1)
+        .create()
+    })
+    .collect();
+
+  let dir = tempfile::tempdir().unwrap();
+  let queue = WorkQueue::open(dir.path().join("cleanup.jsonl")).unwrap();
+  queue.push(["v1", "v2"].map(String::from)).unwrap();
+  let gitlab = gitlab(&server, "user");
+  for attempted in [2, 0] {
+    let report = gitlab
+      .delete_queued_tags("group/project/app", &queue, &BulkOptions::default())
+      .await
+      .unwrap();
+    assert!(report.is_success());
+    assert_eq!(report.items.len(), attempted);
+  }
+  assert!(queue.is_complete());
+  for mock in deletes {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_gitlab_job_token() {
+  let mut server = mockito::Server::new_async().await;
+  let repositories = server
+    .mock("GET", "/api/v4/projects/group%2Fproject/registry/repositories")
+    .match_query(Matcher::Any)
+    .match_header("job-token", "glpat-secret")
+    .match_header("private-token", Matcher::Missing)
+    .with_status(200)
+    .with_body(json!([{"id": 11, "path": "group/project", "project_id": 7}]).to_string())
+    .create();
+
+  let repository = gitlab(&server, "gitlab-ci-token")
+    .repository("group/project")
+    .await
+    .unwrap();
+  assert_eq!(repository.id, 11);
+  repositories.assert_async().await;
+}
@@ -0,0 +1,103 @@
+use docker_registry::{
+  errors::Error,
+  helm::{ChartMetadata, CHART_MEDIA_TYPE, CONFIG_MEDIA_TYPE, PROVENANCE_MEDIA_TYPE},
+  v2::Client,
+};
+use mockito::ServerGuard;
+use serde_json::{json, Value};
+
+use crate::mock::copy::{blob_mock, descriptor, digest, manifest_mock, OCI_MANIFEST};
+
+const CHART: &[u8] = b"packaged chart";
+const PROVENANCE: &[u8] = b"-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA512\n";
+
+fn client(server: &ServerGuard) -> Client {
+  Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+}
+
+fn config() -> Vec<u8> {
+  json!({
+    "apiVersion": "v2",
+    "name": "nginx",
+    "version": "1.2.3",
+    "appVersion": "1.25.4",
+    "type": "application",
+    "keywords": ["web"],
+    "maintainers": [{"name": "ops", "email": "ops@example.com"}],
+    "dependencies": [{"name": "common", "version": "2.x.x", "repository": "oci://registry.example.com/charts"}],
+  })
+  .to_string()
+  .into_bytes()
+}
+
+fn manifest(config_type: &str, layers: &[(&str, &[u8])]) -> Value {
+  json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor(config_type, &config()),
+    "layers": layers.iter().map(|(t, data)| descriptor(t, data)).collect::<Vec<_>>(),
+  })
+}
+
+#[tokio::test]
+async fn test_pull_helm_chart() {
+  let mut server = mockito::Server::new_async().await;
+  let manifest = manifest(
+    CONFIG_MEDIA_TYPE,
+    &[(CHART_MEDIA_TYPE, CHART), (PROVENANCE_MEDIA_TYPE, PROVENANCE)],
+  );
+  let _manifest = manifest_mock(&mut server, "charts/nginx", "1.2.3", &manifest);
+  let _blobs = [
+    blob_mock(&mut server, "charts/nginx", &config()),
+    blob_mock(&mut server, "charts/nginx", CHART),
+    blob_mock(&mut server, "charts/nginx", PROVENANCE),
+  ];
+
+  let chart = client(&server).pull_helm_chart("charts/nginx", "1.2.3").await.unwrap();
+  assert_eq!(chart.digest, digest(&serde_json::to_vec(&manifest).unwrap()));
+  assert_eq!(chart.chart, CHART);
+  assert_eq!(chart.provenance.as_deref().map(str::as_bytes), Some(PROVENANCE));
+
+  let metadata: &ChartMetadata = &chart.metadata;
+  assert_eq!((metadata.name.as_str(), metadata.version.as_str()), ("nginx", "1.2.3"));
+  assert_eq!(metadata.app_version.as_deref(), Some("1.25.4"));
+  assert_eq!(metadata.chart_type.as_deref(), Some("application"));
+  assert_eq!(metadata.maintainers[0].email.as_deref(), Some("ops@example.com"));
+  assert_eq!(
+    metadata.dependencies[0].repository.as_deref(),
+    Some("oci://registry.example.com/charts")
+  );
+}
+
+#[tokio::test]
+async fn test_pull_helm_chart_unsigned() {
+  let mut server = mockito::Server::new_async().await;
+  let manifest = manifest(CONFIG_MEDIA_TYPE, &[(CHART_MEDIA_TYPE, CHART)]);
+  let _manifest = manifest_mock(&mut server, "charts/nginx", "1.2.3", &manifest);
+  let _blobs = [
+    blob_mock(&mut server, "charts/nginx", &config()),
+    blob_mock(&mut server, "charts/nginx", CHART),
+  ];
+
+  let chart = client(&server).pull_helm_chart("charts/nginx", "1.2.3").await.unwrap();
+  assert_eq!(chart.chart, CHART);
+  assert_eq!(chart.provenance, None);
+}
+
+#[tokio::test]
+async fn test_pull_helm_chart_not_a_chart() {
+  let mut server = mockito::Server::new_async().await;
+  let manifest = manifest("application/vnd.oci.image.config.v1+json", &[(CHART_MEDIA_TYPE, CHART)]);
+  let _manifest = manifest_mock(&mut server, "library/nginx", "latest", &manifest);
+
+  let res = client(&server).helm_chart_metadata("library/nginx", "latest").await;
+  assert!(
+    matches!(&res, Err(Error::NotHelmChart(r)) if r == "library/nginx:latest"),
+    "{:?}",
+    res
+  );
+}
@@ -0,0 +1,173 @@
+static API_VERSION_K: &str = "Docker-Distribution-API-Version";
+static API_VERSION_V: &str = "registry/2.0";
+
+#[derive(Debug)]
+struct DigestSigner;
+
+impl docker_registry::v2::RequestSigner for DigestSigner {
+  fn sign(&self, request: &mut docker_registry::v2::SigningRequest<'_>) -> Result<(), docker_registry::v2::HookError> {
+    let signature = format!(
+      "{} {} {}",
+      request.method(),
+      request.url().path(),
+      request.body_digest().unwrap()
+    );
+    request.headers_mut().insert("x-signature", signature.parse()?);
+    Ok(())
+  }
+}
+
+#[tokio::test]
+async fn test_hooks_request_signer() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mock = server
+    .mock("GET", "/v2/")
+    .match_header(
+      "x-signature",
+      "GET /v2/ sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+    )
+    .with_status(200)
+    .with_header(API_VERSION_K, API_VERSION_V)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .request_signer(DigestSigner)
+    .build()
+    .unwrap();
+
+  let res = client.is_v2_supported().await.unwrap();
+
+  mock.assert_async().await;
+  assert!(res);
+}
+
+#[derive(Clone, Debug, Default)]
+struct AuditLog {
+  include_bodies: bool,
+  entries: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl docker_registry::v2::Interceptor for AuditLog {
+  fn include_bodies(&self) -> bool {
+    self.include_bodies
+  }
+
+  fn on_request(
+    &self,
+    request: &docker_registry::v2::InterceptedRequest,
+  ) -> Result<(), docker_registry::v2::HookError> {
+    if request.url().path().ends_with("/forbidden") {
+      return Err("blocked by policy".into());
+    }
+    Ok(())
+  }
+
+  fn on_response(
+    &self,
+    request: &docker_registry::v2::InterceptedRequest,
+    response: &docker_registry::v2::InterceptedResponse<'_>,
+  ) -> Result<(), docker_registry::v2::HookError> {
+    self.entries.lock().unwrap().push(format!(
+      "{} {} {:?} {} {:?} {:?}",
+      request.method(),
+      request.url().path(),
+      request.repository(),
+      response.status().as_u16(),
+      response.content_length(),
+      response.body().map(String::from_utf8_lossy)
+    ));
+    Ok(())
+  }
+}
+
+#[tokio::test]
+async fn test_hooks_interceptor() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+  let blob = b"hello";
+  let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+  let mocks = vec![
+    server
+      .mock("GET", format!("/v2/repo/blobs/{digest}").as_str())
+      .with_status(200)
+      .with_body(blob)
+      .expect(2)
+      .create(),
+    server.mock("GET", "/v2/repo/blobs/forbidden").expect(0).create(),
+  ];
+
+  for include_bodies in [false, true] {
+    let log = AuditLog {
+      include_bodies,
+      ..Default::default()
+    };
+    let client = docker_registry::v2::Client::configure()
+      .registry(&addr)
+      .insecure_registry(true)
+      .with_interceptor(log.clone())
+      .build()
+      .unwrap();
+
+    // Buffered bodies are handed back to the caller intact.
+    assert_eq!(client.get_blob("repo", digest).await.unwrap(), blob);
+    assert!(matches!(
+      client.get_blob("repo", "forbidden").await,
+      Err(docker_registry::errors::Error::Interceptor(_))
+    ));
+
+    let body = match include_bodies {
+      true => "Some(\"hello\")",
+      false => "None",
+    };
+    assert_eq!(
+      *log.entries.lock().unwrap(),
+      vec![format!("GET /v2/repo/blobs/{digest} Some(\"repo\") 200 Some(5) {body}")]
+    );
+  }
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_hooks_interceptor_streams_large_bodies() {
+  let mut server = mockito::Server::new_async().await;
+  let blob = b"hello";
+  let digest = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+  let mock = server
+    .mock("GET", format!("/v2/repo/blobs/{digest}").as_str())
+    .with_status(200)
+    .with_body(blob)
+    .create();
+
+  let log = AuditLog {
+    include_bodies: true,
+    ..Default::default()
+  };
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .response_limits(docker_registry::v2::ResponseLimits {
+      max_raw_response_size: 4,
+      ..Default::default()
+    })
+    .with_interceptor(log.clone())
+    .build()
+    .unwrap();
+
+  // The blob exceeds the limit, so it is streamed to the caller without being buffered.
+  assert_eq!(client.get_blob("repo", digest).await.unwrap(), blob);
+  assert_eq!(
+    *log.entries.lock().unwrap(),
+    vec![format!("GET /v2/repo/blobs/{digest} Some(\"repo\") 200 Some(5) None")]
+  );
+
+  mock.assert_async().await;
+}
@@ -0,0 +1,211 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use docker_registry::{errors::Error, v2::Client};
+use mockito::{Matcher, Mock, ServerGuard};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+/// Mocks the token service issuing `token` to `user`, once.
+fn token_mock(server: &mut ServerGuard, user: &str, token: &str) -> Mock {
+  let basic = format!("Basic {}", STANDARD.encode(format!("{}:secret", user)));
+  server
+    .mock("GET", "/token")
+    .match_query(Matcher::UrlEncoded("scope".into(), "repository:repo:pull".into()))
+    .match_header("Authorization", basic.as_str())
+    .with_status(200)
+    .with_body(json!({"token": token, "expires_in": 300}).to_string())
+    .expect(1)
+    .create()
+}
+
+#[tokio::test]
+async fn test_identities() {
+  let mut server = mockito::Server::new_async().await;
+  let challenge = format!(r#"Bearer realm="{}/token",service="registry""#, server.url());
+  let _challenge = server
+    .mock("GET", "/v2/")
+    .with_status(401)
+    .with_header("WWW-Authenticate", &challenge)
+    .create();
+  let tokens = [
+    token_mock(&mut server, "acme", "acme-token"),
+    token_mock(&mut server, "globex", "globex-token"),
+  ];
+  let manifests: Vec<_> = [("acme-token", 200), ("globex-token", 404)]
+    .into_iter()
+    .map(|(token, status)| {
+      server
+        .mock("HEAD", "/v2/repo/manifests/latest")
+        .match_header("Authorization", format!("Bearer {}", token).as_str())
+        .with_status(status)
+        .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+        .expect(2)
+        .create()
+    })
+    .collect();
+
+  let client = Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .identity("acme", "acme", "secret")
+    .build()
+    .unwrap();
+  client.add_identity("globex", "globex", "secret");
+
+  // Every operation selects its identity; tokens are fetched once per identity.
+  for _ in 0..2 {
+    for (id, found) in [("acme", true), ("globex", false)] {
+      let tenant = client
+        .as_identity(id)
+        .unwrap()
+        .authenticate(&["repository:repo:pull"])
+        .await
+        .unwrap();
+      assert_eq!(tenant.identity(), Some(id));
+      let manifest = tenant.has_manifest("repo", "latest", None).await.unwrap();
+      assert_eq!(manifest.is_some(), found);
+    }
+  }
+  for mock in tokens.iter().chain(&manifests) {
+    mock.assert_async().await;
+  }
+
+  assert!(client.remove_identity("globex"));
+  match client.as_identity("globex") {
+    Err(Error::UnknownIdentity(id)) => assert_eq!(id, "globex"),
+    res => panic!("unexpected result {:?}", res.map(|_| ())),
+  }
+}
+
+#[tokio::test]
+async fn test_identities_do_not_share_manifests() {
+  let mut server = mockito::Server::new_async().await;
+  let challenge = format!(r#"Bearer realm="{}/token",service="registry""#, server.url());
+  let _challenge = server
+    .mock("GET", "/v2/")
+    .with_status(401)
+    .with_header("WWW-Authenticate", &challenge)
+    .create();
+  let _tokens = [
+    token_mock(&mut server, "acme", "acme-token"),
+    token_mock(&mut server, "globex", "globex-token"),
+  ];
+  let manifest = serde_json::to_vec(&json!({
+    "schemaVersion": 2,
+    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+    "config": {
+      "mediaType": "application/vnd.oci.image.config.v1+json",
+      "digest": "sha256:9d99a75171aea000c711b34c0e5e3f28d3d537dd99d110eafbfbc2bd8e52c2bf",
+      "size": 37,
+    },
+    "layers": [],
+  }))
+  .unwrap();
+  let digest = format!("sha256:{:x}", Sha256::digest(&manifest));
+  let path = format!("/v2/repo/manifests/{}", digest);
+  let manifests = [
+    server
+      .mock("GET", path.as_str())
+      .match_header("Authorization", "Bearer acme-token")
+      .with_status(200)
+      .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+      .with_body(&manifest)
+      .expect(1)
+      .create(),
+    server
+      .mock("GET", path.as_str())
+      .match_header("Authorization", "Bearer globex-token")
+      .with_status(404)
+      .expect(1)
+      .create(),
+    server
+      .mock(
+        "GET",
+        "/v2/repo/blobs/sha256:9d99a75171aea000c711b34c0e5e3f28d3d537dd99d110eafbfbc2bd8e52c2bf",
+      )
+      .match_header("Authorization", "Bearer acme-token")
+      .with_status(200)
+      .with_body(r#"{"architecture":"amd64","os":"linux"}"#)
+      .expect(1)
+      .create(),
+  ];
+
+  let client = Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .identity("acme", "acme", "secret")
+    .identity("globex", "globex", "secret")
+    .build()
+    .unwrap();
+
+  // The manifest cached for acme, twice fetched, is not served to globex.
+  for (id, found) in [("acme", true), ("acme", true), ("globex", false)] {
+    let tenant = client
+      .as_identity(id)
+      .unwrap()
+      .authenticate(&["repository:repo:pull"])
+      .await
+      .unwrap();
+    assert_eq!(tenant.get_manifest_by_digest("repo", &digest).await.is_ok(), found);
+  }
+  for mock in &manifests {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_identities_do_not_share_signed_urls() {
+  let mut server = mockito::Server::new_async().await;
+  let mut storage = mockito::Server::new_async().await;
+  let challenge = format!(r#"Bearer realm="{}/token",service="registry""#, server.url());
+  let _challenge = server
+    .mock("GET", "/v2/")
+    .with_status(401)
+    .with_header("WWW-Authenticate", &challenge)
+    .create();
+  let _tokens = [
+    token_mock(&mut server, "acme", "acme-token"),
+    token_mock(&mut server, "globex", "globex-token"),
+  ];
+  let blob = b"blob";
+  let digest = format!("sha256:{:x}", Sha256::digest(blob));
+  let path = format!("/v2/repo/blobs/{}", digest);
+  let signed = "/bucket/blob?Expires=4102444800&Signature=abc";
+  let mocks = [
+    server
+      .mock("GET", path.as_str())
+      .match_header("Authorization", "Bearer acme-token")
+      .with_status(307)
+      .with_header("Location", &format!("{}{}", storage.url(), signed))
+      .expect(1)
+      .create(),
+    server
+      .mock("GET", path.as_str())
+      .match_header("Authorization", "Bearer globex-token")
+      .with_status(404)
+      .expect(1)
+      .create(),
+    storage.mock("GET", signed).with_body(blob).expect(1).create(),
+  ];
+
+  let client = Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .identity("acme", "acme", "secret")
+    .identity("globex", "globex", "secret")
+    .build()
+    .unwrap();
+
+  // The URL signed for acme is not used by globex.
+  for (id, found) in [("acme", true), ("globex", false)] {
+    let tenant = client
+      .as_identity(id)
+      .unwrap()
+      .authenticate(&["repository:repo:pull"])
+      .await
+      .unwrap();
+    assert_eq!(tenant.get_blob("repo", &digest).await.is_ok(), found);
+  }
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
@@ -0,0 +1,150 @@
+use docker_registry::integrity::{BlobCheck, Corruption, VerifyOptions};
+use mockito::{Mock, ServerGuard};
+use serde_json::json;
+
+use crate::mock::copy::{blob_mock, descriptor, digest, manifest_mock, LAYER_TYPE, OCI_MANIFEST};
+
+fn client(server: &ServerGuard) -> docker_registry::v2::Client {
+  docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+}
+
+fn blob_head_mock(server: &mut ServerGuard, data: &[u8], size: Option<usize>) -> Mock {
+  let mock = server.mock("HEAD", format!("/v2/repo/blobs/{}", digest(data)).as_str());
+  match size {
+    Some(size) => mock.with_status(200).with_header("Content-Length", &size.to_string()),
+    None => mock.with_status(404),
+  }
+  .create()
+}
+
+#[tokio::test]
+async fn test_verify_repository() {
+  let mut server = mockito::Server::new_async().await;
+
+  let config = serde_json::to_vec(&json!({"architecture": "amd64", "os": "linux"})).unwrap();
+  let (layer1, layer2, layer3): (&[u8], &[u8], &[u8]) = (b"layer1", b"layer2", b"layer3");
+  let v1 = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": [descriptor(LAYER_TYPE, layer1), descriptor(LAYER_TYPE, layer2)],
+  });
+  let v2 = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": v1["config"],
+    "layers": [descriptor(LAYER_TYPE, layer3)],
+  });
+  let v1_digest = digest(&serde_json::to_vec(&v1).unwrap());
+
+  let mocks = [
+    server
+      .mock("GET", "/v2/repo/tags/list")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(r#"{"name":"repo","tags":["v1","v2","gone"]}"#)
+      .create(),
+    server
+      .mock("GET", "/v2/repo/manifests/v1")
+      .with_status(200)
+      .with_header("Content-Type", OCI_MANIFEST)
+      .with_header("Docker-Content-Digest", &v1_digest)
+      .with_body(serde_json::to_vec(&v1).unwrap())
+      .create(),
+    server
+      .mock("GET", "/v2/repo/manifests/v2")
+      .with_status(200)
+      .with_header("Content-Type", OCI_MANIFEST)
+      .with_header("Docker-Content-Digest", &digest(b"another manifest"))
+      .with_body(serde_json::to_vec(&v2).unwrap())
+      .create(),
+    server
+      .mock("GET", "/v2/repo/manifests/gone")
+      .with_status(404)
+      .with_body(r#"{"errors":[{"code":"MANIFEST_UNKNOWN","message":"manifest unknown"}]}"#)
+      .create(),
+    blob_head_mock(&mut server, &config, Some(config.len())),
+    blob_head_mock(&mut server, layer1, None),
+    blob_head_mock(&mut server, layer2, Some(1)),
+    blob_head_mock(&mut server, layer3, None).expect(0),
+  ];
+
+  let report = client(&server)
+    .verify_repository("repo", &VerifyOptions::default())
+    .await
+    .unwrap();
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+
+  assert_eq!((report.tags, report.manifests, report.blobs), (3, 1, 3));
+  assert!(report.errors.is_empty(), "{:?}", report.errors);
+  assert!(!report.is_intact());
+  let corruptions = report.corruptions;
+  assert_eq!(corruptions.len(), 4, "{:?}", corruptions);
+  assert!(corruptions.contains(&Corruption::MissingManifest {
+    reference: "gone".to_string()
+  }));
+  assert!(corruptions.contains(&Corruption::CorruptManifest {
+    reference: "v2".to_string(),
+    expected: digest(b"another manifest"),
+    actual: digest(&serde_json::to_vec(&v2).unwrap()),
+  }));
+  assert!(corruptions.contains(&Corruption::MissingBlob {
+    digest: digest(layer1),
+    manifest: v1_digest.clone(),
+  }));
+  assert!(corruptions.contains(&Corruption::SizeMismatch {
+    digest: digest(layer2),
+    manifest: v1_digest,
+    expected: layer2.len() as u64,
+    actual: 1,
+  }));
+}
+
+#[tokio::test]
+async fn test_verify_repository_download() {
+  let mut server = mockito::Server::new_async().await;
+
+  let config = serde_json::to_vec(&json!({"architecture": "amd64", "os": "linux"})).unwrap();
+  let layer: &[u8] = b"layer";
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": [descriptor(LAYER_TYPE, layer)],
+  });
+
+  let mocks = [
+    manifest_mock(&mut server, "repo", "v1", &manifest),
+    blob_mock(&mut server, "repo", &config),
+    server
+      .mock("GET", format!("/v2/repo/blobs/{}", digest(layer)).as_str())
+      .with_status(200)
+      .with_body("corrupted")
+      .create(),
+  ];
+
+  let options = VerifyOptions {
+    blob_check: BlobCheck::Download,
+    tags: Some(vec!["v1".to_string()]),
+    ..Default::default()
+  };
+  let report = client(&server).verify_repository("repo", &options).await.unwrap();
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+
+  assert!(report.errors.is_empty(), "{:?}", report.errors);
+  assert_eq!(
+    report.corruptions,
+    vec![Corruption::CorruptBlob {
+      digest: digest(layer),
+      manifest: digest(&serde_json::to_vec(&manifest).unwrap()),
+    }]
+  );
+}
@@ -0,0 +1,73 @@
+use docker_registry::inventory::{Inventory, InventoryOptions};
+
+static MANIFEST_LIST_DIGEST: &str = "sha256:0ccb8ea1fa5f5f7b3b9d4dd4d0f1a3c8d5ad0f0c8b6e1b0d1b6e1b0d1b6e1b0d";
+
+#[tokio::test]
+async fn test_inventory_collect_and_refresh() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let catalog = server
+    .mock("GET", "/v2/_catalog")
+    .with_status(200)
+    .with_body(r#"{"repositories": ["repo"]}"#)
+    .expect(2)
+    .create();
+  let tags = server
+    .mock("GET", "/v2/repo/tags/list")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(r#"{"name": "repo", "tags": ["latest"]}"#)
+    .expect(2)
+    .create();
+  let head = server
+    .mock("HEAD", "/v2/repo/manifests/latest")
+    .with_status(200)
+    .with_header("Docker-Content-Digest", MANIFEST_LIST_DIGEST)
+    .with_header("Last-Modified", "Sun, 06 Nov 1994 08:49:37 GMT")
+    .expect(2)
+    .create();
+  let get = server
+    .mock("GET", "/v2/repo/manifests/latest")
+    .with_status(200)
+    .with_header(
+      "Content-Type",
+      "application/vnd.docker.distribution.manifest.list.v2+json",
+    )
+    .with_header("Docker-Content-Digest", MANIFEST_LIST_DIGEST)
+    .with_body_from_file("tests/fixtures/manifest_list_v2.json")
+    .expect(1)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = InventoryOptions {
+    expand_manifest_lists: false,
+    ..Default::default()
+  };
+  let inventory = Inventory::collect(&client, &options).await.unwrap();
+  assert_eq!(inventory.entries.len(), 1);
+
+  let entry = &inventory.entries[0];
+  assert_eq!(entry.repository, "repo");
+  assert_eq!(entry.tag, "latest");
+  assert_eq!(entry.digest.as_deref(), Some(MANIFEST_LIST_DIGEST));
+  assert_eq!(entry.platforms, vec!["linux/ppc64le", "linux/amd64"]);
+  assert_eq!(entry.size, Some(7143 + 7682));
+  assert_eq!(entry.blobs.len(), 2);
+  assert_eq!(entry.last_modified, Some(784111777));
+  assert_eq!(inventory.storage_report(1).total_bytes, 7143 + 7682);
+
+  // The digest did not change, so the manifest must not be downloaded again.
+  let refreshed = inventory.refresh(&client, &options).await.unwrap();
+  assert_eq!(refreshed.entries, inventory.entries);
+
+  catalog.assert_async().await;
+  tags.assert_async().await;
+  head.assert_async().await;
+  get.assert_async().await;
+}
@@ -0,0 +1,255 @@
+use docker_registry::{
+  layer::Compression,
+  v2::{Descriptor, TocFormat, ESTARGZ_TOC_DIGEST_ANNOTATION},
+};
+use serde_json::json;
+use sha2::Digest;
+
+static LAYER_DIGEST: &str = "sha256:8e4c9d3b2a1f0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d";
+
+fn sha256(data: &[u8]) -> String {
+  format!("sha256:{:x}", sha2::Sha256::digest(data))
+}
+
+/// Mock answering the request for `len` bytes of `blob` from `start`.
+fn range_mock(server: &mut mockito::ServerGuard, blob: &[u8], start: usize, len: usize) -> mockito::Mock {
+  let end = start + len - 1;
+  server
+    .mock("GET", format!("/v2/repo/blobs/{LAYER_DIGEST}").as_str())
+    .match_header("range", format!("bytes={start}-{end}").as_str())
+    .with_status(206)
+    .with_header("Content-Range", &format!("bytes {start}-{end}/{}", blob.len()))
+    .with_body(&blob[start..=end])
+    .create()
+}
+
+/// The 51 bytes gzip member ending eStargz layers, pointing at the TOC at `offset`.
+fn estargz_footer(offset: usize) -> Vec<u8> {
+  let mut footer = vec![0x1f, 0x8b, 0x08, 0x04, 0, 0, 0, 0, 0, 0xff, 26, 0];
+  footer.extend_from_slice(b"SG\x16\x00");
+  footer.extend_from_slice(format!("{offset:016x}STARGZ").as_bytes());
+  footer.extend_from_slice(&[1, 0, 0, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0]);
+  footer
+}
+
+#[tokio::test]
+async fn test_lazy_estargz_layer() {
+  let hello = b"hello world\n";
+  let big = b"0123456789";
+
+  // Every chunk is a gzip member of its own, the TOC a tar archive in the last one.
+  let mut blob = Compression::Gzip.compress(b"tar header of hello").unwrap();
+  let hello_offset = blob.len();
+  blob.extend(Compression::Gzip.compress(hello).unwrap());
+  let big_offset = blob.len();
+  blob.extend(Compression::Gzip.compress(&big[..6]).unwrap());
+  let second_offset = blob.len();
+  blob.extend(Compression::Gzip.compress(&big[6..]).unwrap());
+  let toc_offset = blob.len();
+
+  let toc = serde_json::to_vec(&json!({
+    "version": 1,
+    "entries": [
+      {"name": "etc/", "type": "dir", "mode": 0o755},
+      {"name": "etc/hello", "type": "reg", "size": hello.len(), "offset": hello_offset, "digest": sha256(hello), "chunkDigest": sha256(hello)},
+      {"name": "etc/hi", "type": "hardlink", "linkName": "etc/hello"},
+      {"name": "big", "type": "reg", "size": big.len(), "offset": big_offset, "chunkSize": 6, "chunkDigest": sha256(&big[..6])},
+      {"name": "big", "type": "chunk", "offset": second_offset, "chunkOffset": 6, "chunkDigest": sha256(&big[6..])},
+    ],
+  }))
+  .unwrap();
+  let mut tar = tar::Builder::new(Vec::new());
+  let mut header = tar::Header::new_gnu();
+  header.set_size(toc.len() as u64);
+  header.set_cksum();
+  tar.append_data(&mut header, "stargz.index.json", &toc[..]).unwrap();
+  blob.extend(Compression::Gzip.compress(&tar.into_inner().unwrap()).unwrap());
+  let footer_offset = blob.len();
+  blob.extend(estargz_footer(toc_offset));
+
+  let mut server = mockito::Server::new_async().await;
+  let mocks = [
+    range_mock(&mut server, &blob, footer_offset, 51),
+    range_mock(&mut server, &blob, toc_offset, footer_offset - toc_offset),
+    range_mock(&mut server, &blob, hello_offset, big_offset - hello_offset).expect(2),
+    range_mock(&mut server, &blob, big_offset, second_offset - big_offset).expect(0),
+    range_mock(&mut server, &blob, second_offset, toc_offset - second_offset),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  let mut descriptor = Descriptor::from_digest(
+    "application/vnd.oci.image.layer.v1.tar+gzip",
+    LAYER_DIGEST,
+    blob.len() as u64,
+  );
+  descriptor.annotations = Some([(ESTARGZ_TOC_DIGEST_ANNOTATION.to_string(), sha256(&toc))].into());
+
+  let layer = client.lazy_layer("repo", &descriptor).await.unwrap();
+  assert_eq!(layer.format(), TocFormat::Estargz);
+  assert_eq!(layer.entries().len(), 4);
+  assert_eq!(layer.entry("/etc").unwrap().kind, "dir");
+
+  assert_eq!(layer.read_file("etc/hello").await.unwrap(), hello);
+  assert_eq!(layer.read_file("etc/hi").await.unwrap(), hello);
+  // Only the chunk holding the bytes read is fetched.
+  assert_eq!(layer.read_at("big", 7, 100).await.unwrap(), &big[7..]);
+  assert!(matches!(
+    layer.read_file("etc").await,
+    Err(docker_registry::errors::Error::LayerFileNotFound(_))
+  ));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_lazy_layer_limits() {
+  // A sparse file whose single chunk of zeros is too large to hold in memory.
+  let size = 1u64 << 40;
+  let mut blob = Compression::Gzip.compress(b"tar header of sparse").unwrap();
+  let toc_offset = blob.len();
+  let toc = serde_json::to_vec(&json!({
+    "version": 1,
+    "entries": [
+      {"name": "sparse", "type": "reg", "size": size, "offset": toc_offset, "chunkType": "zeros"},
+    ],
+  }))
+  .unwrap();
+  let mut tar = tar::Builder::new(Vec::new());
+  let mut header = tar::Header::new_gnu();
+  header.set_size(toc.len() as u64);
+  header.set_cksum();
+  tar.append_data(&mut header, "stargz.index.json", &toc[..]).unwrap();
+  blob.extend(Compression::Gzip.compress(&tar.into_inner().unwrap()).unwrap());
+  let footer_offset = blob.len();
+  blob.extend(estargz_footer(toc_offset));
+
+  let mut server = mockito::Server::new_async().await;
+  let mocks = [
+    range_mock(&mut server, &blob, footer_offset, 51),
+    range_mock(&mut server, &blob, toc_offset, footer_offset - toc_offset),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  let mut descriptor = Descriptor::from_digest(
+    "application/vnd.oci.image.layer.v1.tar+gzip",
+    LAYER_DIGEST,
+    blob.len() as u64,
+  );
+  descriptor.annotations = Some([(ESTARGZ_TOC_DIGEST_ANNOTATION.to_string(), sha256(&toc))].into());
+
+  let layer = client.lazy_layer("repo", &descriptor).await.unwrap();
+  assert!(matches!(
+    layer.read_file("sparse").await,
+    Err(docker_registry::errors::Error::ResponseTooLarge {
+      kind: "lazy layer read",
+      ..
+    })
+  ));
+  assert!(matches!(
+    layer.read_at("sparse", 0, 16).await,
+    Err(docker_registry::errors::Error::ResponseTooLarge {
+      kind: "lazy layer chunk",
+      ..
+    })
+  ));
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_lazy_layer_without_toc() {
+  let tar: Vec<u8> = (0..1024u32)
+    .flat_map(|i| i.wrapping_mul(2654435761).to_le_bytes())
+    .collect();
+  let blob = Compression::Gzip.compress(&tar).unwrap();
+
+  let mut server = mockito::Server::new_async().await;
+  let footer = range_mock(&mut server, &blob, blob.len() - 51, 51);
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  let descriptor = Descriptor::from_digest(
+    "application/vnd.oci.image.layer.v1.tar+gzip",
+    LAYER_DIGEST,
+    blob.len() as u64,
+  );
+
+  let res = client.lazy_layer("repo", &descriptor).await;
+  assert!(matches!(res, Err(docker_registry::errors::Error::InvalidToc { .. })));
+  footer.assert_async().await;
+}
+
+#[cfg(feature = "zstd")]
+#[tokio::test]
+async fn test_lazy_zstd_chunked_layer() {
+  use docker_registry::v2::ZSTD_CHUNKED_TOC_DIGEST_ANNOTATION;
+
+  let data = b"zstd chunked content";
+  let mut blob = zstd::encode_all(&b"tar header"[..], 3).unwrap();
+  let offset = blob.len();
+  blob.extend(zstd::encode_all(&data[..], 3).unwrap());
+  let end_offset = blob.len();
+
+  let toc = serde_json::to_vec(&json!({
+    "version": 1,
+    "entries": [
+      {"name": "data", "type": "reg", "size": data.len(), "offset": offset, "endOffset": end_offset, "digest": sha256(data), "chunkDigest": sha256(data)},
+    ],
+  }))
+  .unwrap();
+  let compressed_toc = zstd::encode_all(&toc[..], 3).unwrap();
+  // The TOC and the footer are stored in skippable frames.
+  blob.extend(0x184d2a50u32.to_le_bytes());
+  blob.extend((compressed_toc.len() as u32).to_le_bytes());
+  let toc_offset = blob.len();
+  blob.extend(&compressed_toc);
+  blob.extend(0x184d2a50u32.to_le_bytes());
+  blob.extend(64u32.to_le_bytes());
+  let footer_offset = blob.len();
+  for field in [toc_offset, compressed_toc.len(), toc.len(), 1, 0, 0, 0] {
+    blob.extend((field as u64).to_le_bytes());
+  }
+  blob.extend(b"GNUlInUx");
+
+  let mut server = mockito::Server::new_async().await;
+  let mocks = [
+    range_mock(&mut server, &blob, footer_offset, 64),
+    range_mock(&mut server, &blob, toc_offset, compressed_toc.len()),
+    range_mock(&mut server, &blob, offset, end_offset - offset),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  let mut descriptor = Descriptor::from_digest(
+    "application/vnd.oci.image.layer.v1.tar+zstd",
+    LAYER_DIGEST,
+    blob.len() as u64,
+  );
+  descriptor.annotations = Some([(ZSTD_CHUNKED_TOC_DIGEST_ANNOTATION.to_string(), sha256(&compressed_toc))].into());
+
+  let layer = client.lazy_layer("repo", &descriptor).await.unwrap();
+  assert_eq!(layer.format(), TocFormat::ZstdChunked);
+  assert_eq!(layer.read_file("data").await.unwrap(), data);
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
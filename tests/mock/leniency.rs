@@ -0,0 +1,87 @@
+use docker_registry::{
+  errors::Error,
+  v2::{manifest::Manifest, Client},
+};
+use futures::stream::StreamExt;
+use serde_json::json;
+
+fn client(server: &mockito::ServerGuard, lenient: bool) -> Client {
+  Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .lenient_parsing(lenient)
+    .build()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_lenient_parsing_lists() {
+  let mut server = mockito::Server::new_async().await;
+  let _tags = server
+    .mock("GET", "/v2/repo/tags/list")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(json!({"name": "repo", "tags": ["v1", null, "v2"]}).to_string())
+    .create();
+  let _catalog = server
+    .mock("GET", "/v2/_catalog")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(json!({"repositories": null, "next": "/v2/_catalog?last=repo"}).to_string())
+    .create();
+
+  let strict = client(&server, false);
+  let tags: Vec<_> = strict.get_tags("repo", None).collect().await;
+  assert!(matches!(tags[..], [Err(Error::Json(_))]), "{:?}", tags);
+  assert!(strict.parse_warnings().is_empty());
+
+  let lenient = client(&server, true);
+  let tags: Vec<_> = lenient.get_tags("repo", None).map(Result::unwrap).collect().await;
+  assert_eq!(tags, ["v1", "v2"]);
+  assert_eq!(lenient.get_catalog(None).count().await, 0);
+
+  let warnings: Vec<_> = lenient.take_parse_warnings().iter().map(ToString::to_string).collect();
+  assert_eq!(
+    warnings,
+    [
+      "tag list: ignoring 1 invalid entries of tags",
+      "catalog: missing repositories, assuming none"
+    ]
+  );
+  assert!(lenient.parse_warnings().is_empty());
+}
+
+#[tokio::test]
+async fn test_lenient_parsing_manifest() {
+  let mut server = mockito::Server::new_async().await;
+  let _index = server
+    .mock("GET", "/v2/repo/manifests/latest")
+    .with_status(200)
+    .with_header("Content-Type", "Application/vnd.oci.image.index.v1+json; charset=utf-8")
+    .with_body(
+      json!({
+        "SchemaVersion": 2,
+        "MediaType": "application/vnd.oci.image.index.v1+json",
+        "Manifests": [{
+          "mediaType": "application/vnd.oci.image.manifest.v1+json",
+          "Digest": "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab",
+          "size": "528",
+          "platform": {"Architecture": "amd64", "os": "linux"},
+        }],
+      })
+      .to_string(),
+    )
+    .create();
+
+  assert!(client(&server, false).get_manifest("repo", "latest").await.is_err());
+
+  let lenient = client(&server, true);
+  match lenient.get_manifest("repo", "latest").await.unwrap() {
+    Manifest::ML(index) => {
+      assert_eq!(index.architectures(), ["amd64"]);
+      assert_eq!(index.manifests()[0].size, 528);
+    }
+    manifest => panic!("unexpected manifest {:?}", manifest),
+  }
+  assert_eq!(lenient.parse_warnings().len(), 7);
+}
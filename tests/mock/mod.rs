@@ -1,6 +1,55 @@
 mod api_version;
+mod archive;
+mod artifact;
+mod artifactory;
 mod base_client;
 mod blobs_download;
+mod build;
+mod bulk;
+mod cache;
 mod catalog;
+#[cfg(feature = "test-support")]
+mod chaos;
+mod coalesce;
+#[cfg(feature = "test-support")]
+mod conformance;
+mod copy;
+mod correlation;
+mod credential_provider;
+mod deadline;
+mod device_login;
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "gitlab")]
+mod gitlab;
+#[cfg(feature = "helm")]
+mod helm;
+mod hooks;
+mod identities;
+mod integrity;
+mod inventory;
+mod lazy;
+mod leniency;
+mod mutability;
+mod mutate;
+mod namespaces;
+mod nexus;
+#[cfg(feature = "notary")]
+mod notary;
+mod orphans;
+mod pinning;
+mod promote;
+mod proxy;
+mod read_only;
+mod redirect;
+mod referrers;
+mod registry_client;
+mod replication;
+mod search;
+mod session;
+mod soft_fail;
+mod stats;
 mod tags_dockerv2;
 mod tags_quay;
+mod token_store;
+mod uploads;
@@ -0,0 +1,141 @@
+use docker_registry::{
+  bulk::DeleteTagsOptions,
+  errors::Error,
+  v2::{Client, MutabilityProvider, TagMutability},
+};
+use mockito::{Matcher, Mock, ServerGuard};
+use serde_json::json;
+
+const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+fn client(server: &ServerGuard, check: bool) -> Client {
+  Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .check_tag_mutability(check)
+    .build()
+    .unwrap()
+}
+
+/// Mocks the Harbor artifact of `v1` in `team/app` of the project `proj`, immutable or not.
+fn harbor_mock(server: &mut ServerGuard, immutable: bool) -> Mock {
+  server
+    .mock("GET", "/api/v2.0/projects/proj/repositories/team%252Fapp/artifacts/v1")
+    .match_query(Matcher::AllOf(vec![
+      Matcher::UrlEncoded("with_tag".into(), "true".into()),
+      Matcher::UrlEncoded("with_immutable_status".into(), "true".into()),
+    ]))
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(
+      json!({
+        "digest": "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab",
+        "tags": [{"name": "latest", "immutable": false}, {"name": "v1", "immutable": immutable}],
+      })
+      .to_string(),
+    )
+    .create()
+}
+
+#[tokio::test]
+async fn test_tag_mutability_harbor() {
+  let mut server = mockito::Server::new_async().await;
+  let _artifact = harbor_mock(&mut server, true);
+  let _missing = server
+    .mock("GET", "/api/v2.0/projects/proj/repositories/team%252Fapp/artifacts/v2")
+    .match_query(Matcher::Any)
+    .with_status(404)
+    .create();
+
+  let client = client(&server, false);
+  assert_eq!(
+    client.tag_mutability("proj/team/app", "v1").await.unwrap(),
+    Some(TagMutability {
+      writable: false,
+      deletable: false
+    })
+  );
+  assert_eq!(client.tag_mutability("proj/team/app", "v2").await.unwrap(), None);
+  // Harbor repositories always belong to a project.
+  assert_eq!(client.tag_mutability("app", "v1").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_tag_mutability_acr() {
+  let mut server = mockito::Server::new_async().await;
+  let _tag = server
+    .mock("GET", "/acr/v1/team/app/_tags/v1")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(
+      json!({
+        "registry": "example.azurecr.io",
+        "imageName": "team/app",
+        "tag": {
+          "name": "v1",
+          "digest": "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab",
+          "changeableAttributes": {
+            "deleteEnabled": false,
+            "writeEnabled": true,
+            "readEnabled": true,
+            "listEnabled": true,
+          },
+        },
+      })
+      .to_string(),
+    )
+    .create();
+
+  let mutability = client(&server, false)
+    .tag_mutability_with(MutabilityProvider::Acr, "team/app", "v1")
+    .await
+    .unwrap();
+  assert_eq!(
+    mutability,
+    Some(TagMutability {
+      writable: true,
+      deletable: false
+    })
+  );
+}
+
+#[tokio::test]
+async fn test_check_tag_mutability() {
+  let mut server = mockito::Server::new_async().await;
+  let _artifact = harbor_mock(&mut server, true);
+  let writes = server.mock("PUT", Matcher::Any).expect(0).create();
+  let deletes = server.mock("DELETE", Matcher::Any).expect(0).create();
+
+  let client = client(&server, true);
+  match client.put_manifest("proj/team/app", "v1", OCI_MANIFEST, b"{}").await {
+    Err(Error::TagImmutable(e)) => {
+      let messages: Vec<_> = e.errors().iter().flatten().filter_map(|e| e.message()).collect();
+      assert_eq!(messages, ["tag v1 of proj/team/app cannot be overwritten"]);
+    }
+    res => panic!("unexpected result {:?}", res),
+  }
+  let report = client
+    .delete_tags("proj/team/app", vec!["v1".to_string()], &DeleteTagsOptions::default())
+    .await
+    .unwrap();
+  assert!(matches!(report.items[0].result, Err(Error::TagImmutable(_))));
+  writes.assert_async().await;
+  deletes.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_check_tag_mutability_mutable() {
+  let mut server = mockito::Server::new_async().await;
+  let artifact = harbor_mock(&mut server, false);
+  let write = server
+    .mock("PUT", "/v2/proj/team/app/manifests/v1")
+    .with_status(201)
+    .create();
+
+  client(&server, true)
+    .put_manifest("proj/team/app", "v1", OCI_MANIFEST, b"{}")
+    .await
+    .unwrap();
+  artifact.assert_async().await;
+  write.assert_async().await;
+}
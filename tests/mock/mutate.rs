@@ -0,0 +1,244 @@
+use docker_registry::{
+  layer::Compression,
+  mutate::{AppendLayerOptions, MutableImage},
+};
+use mockito::Matcher;
+use serde_json::json;
+
+use super::copy::{
+  blob_mock, descriptor, digest, manifest_mock, streamed_upload_mocks, tar_layer, upload_mocks, LAYER_TYPE,
+  OCI_MANIFEST,
+};
+
+#[tokio::test]
+async fn test_mutate_push() {
+  let mut server = mockito::Server::new_async().await;
+
+  let base_tar = tar_layer(&[("bin/app", b"app")]);
+  let base = Compression::Gzip.compress(&base_tar).unwrap();
+  let config = json!({
+    "architecture": "amd64",
+    "os": "linux",
+    "config": {"Env": ["PATH=/usr/bin", "LOG_LEVEL=info"]},
+    "rootfs": {"type": "layers", "diff_ids": [digest(&base_tar)]},
+    "history": [{"created_by": "COPY app /bin/app"}],
+  });
+  let config_blob = serde_json::to_vec(&config).unwrap();
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config_blob),
+    "layers": [descriptor(LAYER_TYPE, &base)],
+  });
+
+  let settings_tar = tar_layer(&[("etc/app.toml", b"debug = true")]);
+  let settings = Compression::Gzip.compress(&settings_tar).unwrap();
+  let created = "2024-01-01T00:00:00Z";
+  let expected_config = serde_json::to_vec(&json!({
+    "architecture": "amd64",
+    "os": "linux",
+    "created": created,
+    "config": {
+      "Env": ["PATH=/usr/bin", "LOG_LEVEL=debug"],
+      "User": "1000",
+      "ExposedPorts": {"8080/tcp": {}},
+      "Labels": {"team": "infra"},
+    },
+    "rootfs": {"type": "layers", "diff_ids": [digest(&base_tar), digest(&settings_tar)]},
+    "history": [
+      {"created_by": "COPY app /bin/app"},
+      {"created_by": "ENV LOG_LEVEL=debug", "empty_layer": true, "created": created},
+      {"created_by": "USER 1000", "empty_layer": true, "created": created},
+      {"created_by": "EXPOSE 8080/tcp", "empty_layer": true, "created": created},
+      {"created_by": "LABEL team=\"infra\"", "empty_layer": true, "created": created},
+      {"created_by": "COPY app.toml /etc/app.toml", "created": created},
+    ],
+  }))
+  .unwrap();
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "app", "v1", &manifest),
+    blob_mock(&mut server, "app", &config_blob),
+    server
+      .mock("HEAD", format!("/v2/app/blobs/{}", digest(&base)).as_str())
+      .with_status(200)
+      .create(),
+    server
+      .mock("PUT", "/v2/app/manifests/v2")
+      .match_body(Matcher::PartialJson(json!({
+        "config": {"digest": digest(&expected_config), "size": expected_config.len()},
+        "layers": [descriptor(LAYER_TYPE, &base), descriptor(LAYER_TYPE, &settings)],
+      })))
+      .with_status(201)
+      .create(),
+  ];
+  mocks.extend(upload_mocks(&mut server, "app", &settings));
+  mocks.extend(upload_mocks(&mut server, "app", &expected_config));
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let mut image = MutableImage::load(&client, "app", "v1").await.unwrap();
+  image
+    .created(created)
+    .env("LOG_LEVEL", "debug")
+    .user("1000")
+    .expose_port("8080")
+    .label("team", "infra");
+  let layer = image
+    .add_layer(&settings_tar, Compression::Gzip, "COPY app.toml /etc/app.toml")
+    .unwrap();
+  assert_eq!(layer, digest(&settings));
+
+  let pushed = image.push(&client, "app", "v2").await.unwrap();
+  assert_eq!(pushed, image.digest().unwrap());
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_mutate_append_layer() {
+  let mut server = mockito::Server::new_async().await;
+
+  let base_tar = tar_layer(&[("bin/app", b"app")]);
+  let base = Compression::Gzip.compress(&base_tar).unwrap();
+  let config = json!({
+    "rootfs": {"type": "layers", "diff_ids": [digest(&base_tar)]},
+    "history": [{"created_by": "COPY app /bin/app"}],
+  });
+  let config_blob = serde_json::to_vec(&config).unwrap();
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config_blob),
+    "layers": [descriptor(LAYER_TYPE, &base)],
+  });
+
+  let added_tar = tar_layer(&[("etc/app.toml", b"debug = true")]);
+  let added = Compression::Gzip.compress(&added_tar).unwrap();
+  let expected_config = serde_json::to_vec(&json!({
+    "rootfs": {"type": "layers", "diff_ids": [digest(&base_tar), digest(&added_tar)]},
+    "history": [{"created_by": "COPY app /bin/app"}, {"created_by": "ADD app.toml /etc/"}],
+  }))
+  .unwrap();
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "app", "latest", &manifest),
+    blob_mock(&mut server, "app", &config_blob),
+    // The base layer is neither downloaded nor uploaded again.
+    blob_mock(&mut server, "app", &base).expect(0),
+    server
+      .mock("HEAD", format!("/v2/app/blobs/{}", digest(&base)).as_str())
+      .with_status(200)
+      .create(),
+    server
+      .mock("PUT", "/v2/app/manifests/latest")
+      .match_body(Matcher::PartialJson(json!({
+        "config": {"digest": digest(&expected_config)},
+        "layers": [descriptor(LAYER_TYPE, &base), descriptor(LAYER_TYPE, &added)],
+      })))
+      .with_status(201)
+      .create(),
+  ];
+  mocks.extend(upload_mocks(&mut server, "app", &added));
+  mocks.extend(upload_mocks(&mut server, "app", &expected_config));
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = AppendLayerOptions {
+    created_by: "ADD app.toml /etc/".to_string(),
+    ..Default::default()
+  };
+  client
+    .append_layer("app", "latest", added_tar.as_slice(), &options)
+    .await
+    .unwrap();
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+/// An image made of the given layers, with one history entry per layer.
+fn layered_image(layers: &[&[u8]]) -> (serde_json::Value, Vec<u8>) {
+  let tars: Vec<Vec<u8>> = layers.iter().map(|l| tar_layer(&[("file", l)])).collect();
+  let config = json!({
+    "rootfs": {"type": "layers", "diff_ids": tars.iter().map(|t| digest(t)).collect::<Vec<_>>()},
+    "history": tars.iter().map(|t| json!({"created_by": digest(t)})).collect::<Vec<_>>(),
+  });
+  let config = serde_json::to_vec(&config).unwrap();
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": tars.iter().map(|t| descriptor("application/vnd.oci.image.layer.v1.tar", t)).collect::<Vec<_>>(),
+  });
+  (manifest, config)
+}
+
+#[tokio::test]
+async fn test_mutate_rebase() {
+  let mut server = mockito::Server::new_async().await;
+
+  let (old_base, old_base_config) = layered_image(&[b"old base"]);
+  let (new_base, new_base_config) = layered_image(&[b"new base"]);
+  let (app, app_config) = layered_image(&[b"old base", b"app"]);
+  let (rebased, rebased_config) = layered_image(&[b"new base", b"app"]);
+  let new_base_layer = tar_layer(&[("file", b"new base")]);
+  let app_layer = tar_layer(&[("file", b"app")]);
+
+  let mut mocks = vec![
+    manifest_mock(&mut server, "base", "v1", &old_base),
+    blob_mock(&mut server, "base", &old_base_config),
+    manifest_mock(&mut server, "base", "v2", &new_base),
+    blob_mock(&mut server, "base", &new_base_config),
+    manifest_mock(&mut server, "app", "v1", &app),
+    blob_mock(&mut server, "app", &app_config),
+    // The layer of the new base is copied over from the base repository.
+    blob_mock(&mut server, "base", &new_base_layer),
+    server
+      .mock("HEAD", format!("/v2/app/blobs/{}", digest(&app_layer)).as_str())
+      .with_status(200)
+      .create(),
+    server
+      .mock("PUT", "/v2/app/manifests/v1-rebased")
+      .match_body(Matcher::PartialJson(json!({
+        "config": {"digest": digest(&rebased_config)},
+        "layers": rebased["layers"],
+      })))
+      .with_status(201)
+      .create(),
+  ];
+  mocks.extend(streamed_upload_mocks(&mut server, "app", &new_base_layer));
+  mocks.extend(upload_mocks(&mut server, "app", &rebased_config));
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let old_base = MutableImage::load(&client, "base", "v1").await.unwrap();
+  let new_base = MutableImage::load(&client, "base", "v2").await.unwrap();
+  let mut image = MutableImage::load(&client, "app", "v1").await.unwrap();
+
+  // The new base is not a base of the image.
+  let res = image.rebase(&new_base, &old_base);
+  assert!(matches!(res, Err(docker_registry::errors::Error::BaseMismatch(_))));
+
+  image.rebase(&old_base, &new_base).unwrap();
+  image.push(&client, "app", "v1-rebased").await.unwrap();
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
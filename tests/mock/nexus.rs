@@ -0,0 +1,96 @@
+use docker_registry::{
+  errors::Error,
+  v2::{Client, RegistryProfile},
+};
+use futures::stream::StreamExt;
+use mockito::Matcher;
+use serde_json::json;
+
+const NEXUS_SERVER: &str = "Nexus/3.68.1-02 (OSS)";
+const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+fn client(server: &mockito::ServerGuard, profile: RegistryProfile) -> Client {
+  Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .registry_profile(profile)
+    .build()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_nexus_tags_pagination() {
+  let mut server = mockito::Server::new_async().await;
+  let _ping = server
+    .mock("GET", "/v2/")
+    .with_status(200)
+    .with_header("Docker-Distribution-Api-Version", "registry/2.0")
+    .with_header("Server", NEXUS_SERVER)
+    .create();
+  let first = server
+    .mock("GET", "/v2/docker-group/app/tags/list")
+    .match_query(Matcher::UrlEncoded("n".into(), "2".into()))
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_header("Server", NEXUS_SERVER)
+    .with_body(
+      json!({
+        "name": "docker-group/app",
+        "tags": ["1.0", "1.1"],
+        "continuationToken": "8f4ad6fc3bd1ef4d",
+      })
+      .to_string(),
+    )
+    .create();
+  let second = server
+    .mock("GET", "/v2/docker-group/app/tags/list")
+    .match_query(Matcher::AllOf(vec![
+      Matcher::UrlEncoded("n".into(), "2".into()),
+      Matcher::UrlEncoded("continuationToken".into(), "8f4ad6fc3bd1ef4d".into()),
+    ]))
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_header("Server", NEXUS_SERVER)
+    .with_body(json!({"name": "docker-group/app", "tags": ["2.0"]}).to_string())
+    .create();
+
+  let client = client(&server, RegistryProfile::Auto);
+  assert!(client.is_v2_supported().await.unwrap());
+  assert_eq!(client.registry_profile(), RegistryProfile::Nexus);
+
+  let tags: Vec<_> = client
+    .get_tags("docker-group/app", Some(2))
+    .map(Result::unwrap)
+    .collect()
+    .await;
+  assert_eq!(tags, ["1.0", "1.1", "2.0"]);
+  first.assert_async().await;
+  second.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_nexus_group_repository() {
+  let mut server = mockito::Server::new_async().await;
+  let _push = server
+    .mock("PUT", "/v2/docker-group/app/manifests/latest")
+    .with_status(405)
+    .with_header("Content-Type", "text/html")
+    .with_header("Server", NEXUS_SERVER)
+    .with_body("<html><body>Method Not Allowed</body></html>")
+    .create();
+
+  let res = client(&server, RegistryProfile::Nexus)
+    .put_manifest("docker-group/app", "latest", OCI_MANIFEST, b"{}")
+    .await;
+  match res {
+    Err(Error::ReadOnlyRepository { repository, .. }) => {
+      assert_eq!(repository.as_deref(), Some("docker-group/app"))
+    }
+    res => panic!("unexpected result {:?}", res),
+  }
+
+  let res = client(&server, RegistryProfile::Standard)
+    .put_manifest("docker-group/app", "latest", OCI_MANIFEST, b"{}")
+    .await;
+  assert!(!matches!(res, Err(Error::ReadOnlyRepository { .. })), "{:?}", res);
+}
@@ -0,0 +1,300 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use docker_registry::{errors::Error, notary::NotaryClient, v2::Client};
+use mockito::ServerGuard;
+use ring::{
+  rand::SystemRandom,
+  signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+const GUN: &str = "docker.io/library/alpine";
+const EXPIRES: &str = "2100-01-01T00:00:00Z";
+/// DER prefix of the `SubjectPublicKeyInfo` of a P-256 key.
+const P256_SPKI_PREFIX: &str = "3059301306072a8648ce3d020106082a8648ce3d030107034200";
+
+enum Signer {
+  Ed25519(Ed25519KeyPair),
+  Ecdsa(EcdsaKeyPair),
+}
+
+impl Signer {
+  fn ed25519(seed: u8) -> Self {
+    Signer::Ed25519(Ed25519KeyPair::from_seed_unchecked(&[seed; 32]).unwrap())
+  }
+
+  fn ecdsa() -> Self {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+    Signer::Ecdsa(EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap())
+  }
+
+  /// The key as listed in TUF metadata.
+  fn key(&self) -> Value {
+    let (keytype, public) = match self {
+      Signer::Ed25519(key) => ("ed25519", key.public_key().as_ref().to_vec()),
+      Signer::Ecdsa(key) => {
+        let prefix: Vec<u8> = (0..P256_SPKI_PREFIX.len())
+          .step_by(2)
+          .map(|i| u8::from_str_radix(&P256_SPKI_PREFIX[i..i + 2], 16).unwrap())
+          .collect();
+        ("ecdsa", [&prefix[..], key.public_key().as_ref()].concat())
+      }
+    };
+    json!({"keytype": keytype, "keyval": {"private": null, "public": STANDARD.encode(public)}})
+  }
+
+  fn id(&self) -> String {
+    format!("{:x}", Sha256::digest(serde_json::to_vec(&self.key()).unwrap()))
+  }
+
+  fn method(&self) -> &str {
+    match self {
+      Signer::Ed25519(_) => "ed25519",
+      Signer::Ecdsa(_) => "ecdsa",
+    }
+  }
+
+  fn sign(&self, message: &[u8]) -> Vec<u8> {
+    match self {
+      Signer::Ed25519(key) => key.sign(message).as_ref().to_vec(),
+      Signer::Ecdsa(key) => key.sign(&SystemRandom::new(), message).unwrap().as_ref().to_vec(),
+    }
+  }
+}
+
+fn envelope(signed: Value, signer: &Signer) -> Vec<u8> {
+  let sig = signer.sign(&serde_json::to_vec(&signed).unwrap());
+  let signatures = json!([{"keyid": signer.id(), "method": signer.method(), "sig": STANDARD.encode(sig)}]);
+  serde_json::to_vec_pretty(&json!({"signed": signed, "signatures": signatures})).unwrap()
+}
+
+fn meta(body: &[u8]) -> Value {
+  json!({"length": body.len(), "hashes": {"sha256": STANDARD.encode(Sha256::digest(body))}})
+}
+
+fn role(signer: &Signer) -> Value {
+  json!({"keyids": [signer.id()], "threshold": 1})
+}
+
+struct Repository {
+  root: Signer,
+  targets: Signer,
+  releases: Signer,
+  timestamp: Signer,
+}
+
+impl Repository {
+  fn new() -> Self {
+    Self {
+      root: Signer::ed25519(1),
+      targets: Signer::ecdsa(),
+      releases: Signer::ed25519(2),
+      timestamp: Signer::ed25519(3),
+    }
+  }
+
+  /// Serve the metadata of a repository signing `latest` in both `targets` and its delegation,
+  /// and `v1` in `targets` only.
+  fn serve(&self, server: &mut ServerGuard, tamper: bool) {
+    let keys = json!({
+      self.root.id(): self.root.key(),
+      self.targets.id(): self.targets.key(),
+      self.timestamp.id(): self.timestamp.key(),
+    });
+    let root = envelope(
+      json!({
+        "_type": "Root",
+        "consistent_snapshot": false,
+        "expires": EXPIRES,
+        "keys": keys,
+        "roles": {
+          "root": role(&self.root),
+          "snapshot": role(&self.timestamp),
+          "targets": role(&self.targets),
+          "timestamp": role(&self.timestamp),
+        },
+        "version": 1,
+      }),
+      &self.root,
+    );
+    let releases = envelope(
+      json!({
+        "_type": "Targets",
+        "delegations": {"keys": {}, "roles": []},
+        "expires": EXPIRES,
+        "targets": {"latest": meta(b"released manifest")},
+        "version": 1,
+      }),
+      &self.releases,
+    );
+    let mut targets = envelope(
+      json!({
+        "_type": "Targets",
+        "delegations": {
+          "keys": {self.releases.id(): self.releases.key()},
+          "roles": [{
+            "name": "targets/releases",
+            "keyids": [self.releases.id()],
+            "threshold": 1,
+            "paths": [""],
+          }],
+        },
+        "expires": EXPIRES,
+        "targets": {"latest": meta(b"manifest"), "v1": meta(b"v1 manifest")},
+        "version": 1,
+      }),
+      &self.targets,
+    );
+    let snapshot = envelope(
+      json!({
+        "_type": "Snapshot",
+        "expires": EXPIRES,
+        "meta": {"root": meta(&root), "targets": meta(&targets), "targets/releases": meta(&releases)},
+        "version": 1,
+      }),
+      &self.timestamp,
+    );
+    let timestamp = envelope(
+      json!({
+        "_type": "Timestamp",
+        "expires": EXPIRES,
+        "meta": {"snapshot": meta(&snapshot)},
+        "version": 1,
+      }),
+      &self.timestamp,
+    );
+    if tamper {
+      let at = targets.windows(2).position(|w| w == b"v1").unwrap();
+      targets[at + 1] = b'2';
+    }
+
+    // The server allows anonymous access.
+    server.mock("GET", "/v2/").with_status(200).create();
+    for (name, body) in [
+      ("root", root),
+      ("timestamp", timestamp),
+      ("snapshot", snapshot),
+      ("targets", targets),
+      ("targets/releases", releases),
+    ] {
+      let path = format!("/v2/{}/_trust/tuf/{}.json", GUN, name);
+      server
+        .mock("GET", path.as_str())
+        .with_status(200)
+        .with_body(body)
+        .create();
+    }
+  }
+}
+
+fn notary(server: &ServerGuard) -> NotaryClient {
+  let client = Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  NotaryClient::new(client)
+}
+
+#[tokio::test]
+async fn test_notary_target() {
+  let mut server = mockito::Server::new_async().await;
+  let repository = Repository::new();
+  repository.serve(&mut server, false);
+  let notary = notary(&server).root_key_ids([repository.root.id()]);
+
+  let latest = notary.target(GUN, "latest").await.unwrap();
+  assert_eq!(
+    (latest.digest, latest.size, latest.role.as_str()),
+    (
+      format!("sha256:{:x}", Sha256::digest(b"released manifest")),
+      17,
+      "targets/releases"
+    )
+  );
+  let v1 = notary.target(GUN, "v1").await.unwrap();
+  assert_eq!(
+    (v1.digest, v1.role.as_str()),
+    (format!("sha256:{:x}", Sha256::digest(b"v1 manifest")), "targets")
+  );
+  match notary.target(GUN, "v2").await {
+    Err(Error::NotSigned { tag, .. }) => assert_eq!(tag, "v2"),
+    res => panic!("unexpected result {:?}", res),
+  }
+}
+
+#[tokio::test]
+async fn test_notary_untrusted_root() {
+  let mut server = mockito::Server::new_async().await;
+  let repository = Repository::new();
+  repository.serve(&mut server, false);
+  let notary = notary(&server).root_key_ids([Signer::ed25519(4).id()]);
+
+  match notary.target(GUN, "latest").await {
+    Err(Error::TrustVerification { reason, .. }) => assert_eq!(reason, "root has 0 valid signatures, 1 required"),
+    res => panic!("unexpected result {:?}", res),
+  }
+}
+
+#[tokio::test]
+async fn test_notary_tampered_targets() {
+  let mut server = mockito::Server::new_async().await;
+  let repository = Repository::new();
+  repository.serve(&mut server, true);
+  let notary = notary(&server).root_key_ids([repository.root.id()]);
+
+  match notary.target(GUN, "v1").await {
+    Err(Error::TrustVerification { reason, .. }) => assert_eq!(reason, "targets does not match the snapshot"),
+    res => panic!("unexpected result {:?}", res),
+  }
+}
+
+#[tokio::test]
+async fn test_notary_requires_trusted_root() {
+  let mut server = mockito::Server::new_async().await;
+  let repository = Repository::new();
+  repository.serve(&mut server, false);
+
+  match notary(&server).target(GUN, "latest").await {
+    Err(Error::TrustVerification { reason, .. }) => {
+      assert_eq!(reason, "neither root keys nor a trust directory are configured")
+    }
+    res => panic!("unexpected result {:?}", res),
+  }
+}
+
+#[tokio::test]
+async fn test_notary_trust_dir() {
+  let dir = tempfile::tempdir().unwrap();
+  let mut server = mockito::Server::new_async().await;
+  let repository = Repository::new();
+  repository.serve(&mut server, false);
+
+  // The root is trusted on first use.
+  let trusting = notary(&server).trust_dir(dir.path());
+  assert_eq!(trusting.target(GUN, "v1").await.unwrap().role, "targets");
+
+  // Metadata older than seen before is refused.
+  let state_path = std::fs::read_dir(dir.path()).unwrap().next().unwrap().unwrap().path();
+  let mut state: serde_json::Value = serde_json::from_slice(&std::fs::read(&state_path).unwrap()).unwrap();
+  assert_eq!(state["root_key_ids"], json!([repository.root.id()]));
+  state["versions"]["targets"] = json!(2);
+  std::fs::write(&state_path, serde_json::to_vec(&state).unwrap()).unwrap();
+  match trusting.target(GUN, "v1").await {
+    Err(Error::TrustVerification { reason, .. }) => {
+      assert_eq!(reason, "targets version 1 is older than version 2 seen before")
+    }
+    res => panic!("unexpected result {:?}", res),
+  }
+
+  // Another root is not trusted once one was seen.
+  let mut server = mockito::Server::new_async().await;
+  let mut other = Repository::new();
+  other.root = Signer::ed25519(4);
+  other.serve(&mut server, false);
+  match notary(&server).trust_dir(dir.path()).target(GUN, "v1").await {
+    Err(Error::TrustVerification { reason, .. }) => assert_eq!(reason, "root has 0 valid signatures, 1 required"),
+    res => panic!("unexpected result {:?}", res),
+  }
+}
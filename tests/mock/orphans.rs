@@ -0,0 +1,107 @@
+use docker_registry::bulk::BulkOptions;
+use mockito::ServerGuard;
+use serde_json::json;
+
+use crate::mock::copy::{descriptor, digest, manifest_mock, OCI_MANIFEST};
+
+fn client(server: &ServerGuard) -> docker_registry::v2::Client {
+  docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+}
+
+/// The referrer tag of `subject` with an optional cosign suffix.
+fn referrer_tag(subject: &str, suffix: &str) -> String {
+  format!("{}{}", subject.replacen(':', "-", 1), suffix)
+}
+
+#[tokio::test]
+async fn test_orphaned_referrers() {
+  let mut server = mockito::Server::new_async().await;
+
+  let (present, deleted, deleted_too) = (digest(b"present"), digest(b"deleted"), digest(b"deleted too"));
+  let signature = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.empty.v1+json", b"{}"),
+    "layers": [descriptor("application/vnd.dev.cosign.simplesigning.v1+json", b"payload")],
+  });
+  let sbom = descriptor(OCI_MANIFEST, b"sbom");
+  let index = json!({
+    "schemaVersion": 2,
+    "mediaType": "application/vnd.oci.image.index.v1+json",
+    "manifests": [sbom],
+  });
+  let tags = [
+    "latest".to_string(),
+    referrer_tag(&present, ".sig"),
+    referrer_tag(&deleted, ".sig"),
+    referrer_tag(&deleted_too, ""),
+  ];
+
+  let _tags = server
+    .mock("GET", "/v2/repo/tags/list")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(json!({"name": "repo", "tags": tags}).to_string())
+    .create();
+  let _present = server
+    .mock("HEAD", format!("/v2/repo/manifests/{}", present).as_str())
+    .with_status(200)
+    .with_header("Content-Type", OCI_MANIFEST)
+    .create();
+  for subject in [&deleted, &deleted_too] {
+    server
+      .mock("HEAD", format!("/v2/repo/manifests/{}", subject).as_str())
+      .with_status(404)
+      .create();
+  }
+  let _signature = manifest_mock(&mut server, "repo", &tags[2], &signature);
+  let _index = manifest_mock(&mut server, "repo", &tags[3], &index);
+
+  let client = client(&server);
+  let options = BulkOptions::default();
+  let report = client.find_orphaned_referrers("repo", &options).await.unwrap();
+  assert_eq!(report.tags, 3);
+  assert!(report.errors.is_empty(), "{:?}", report.errors);
+
+  let signature_digest = digest(&serde_json::to_vec(&signature).unwrap());
+  let index_digest = digest(&serde_json::to_vec(&index).unwrap());
+  let mut orphans: Vec<_> = report
+    .orphans
+    .iter()
+    .map(|o| (o.tag.as_str(), o.subject.as_str(), o.manifests.clone()))
+    .collect();
+  orphans.sort();
+  let mut expected = vec![
+    (tags[2].as_str(), deleted.as_str(), vec![signature_digest.clone()]),
+    (
+      tags[3].as_str(),
+      deleted_too.as_str(),
+      vec![sbom["digest"].as_str().unwrap().to_string(), index_digest.clone()],
+    ),
+  ];
+  expected.sort();
+  assert_eq!(orphans, expected);
+
+  let deletions: Vec<_> = [&signature_digest, sbom["digest"].as_str().unwrap(), &index_digest]
+    .into_iter()
+    .map(|digest| {
+      server
+        .mock("DELETE", format!("/v2/repo/manifests/{}", digest).as_str())
+        .with_status(202)
+        .create()
+    })
+    .collect();
+  let deleted = client
+    .delete_orphaned_referrers("repo", &report, &options)
+    .await
+    .unwrap();
+  assert!(deleted.is_success());
+  assert_eq!(deleted.items.len(), 3);
+  for mock in deletions {
+    mock.assert_async().await;
+  }
+}
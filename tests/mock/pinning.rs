@@ -0,0 +1,93 @@
+use std::{
+  io::{Read, Write},
+  net::TcpListener,
+  path::PathBuf,
+  thread::JoinHandle,
+};
+
+use base64::prelude::*;
+use docker_registry::{errors::Error, v2::Client};
+use native_tls::{Identity, TlsAcceptor};
+use sha2::Digest;
+
+fn read_certificate(file_name: &str) -> Vec<u8> {
+  std::fs::read(
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+      .join("certificate/output")
+      .join(file_name),
+  )
+  .unwrap()
+}
+
+/// SHA-256 fingerprint of the DER encoding of the test server certificate.
+fn server_fingerprint() -> String {
+  let pem = String::from_utf8(read_certificate("localhost.crt")).unwrap();
+  // The leaf certificate comes first, followed by the CA.
+  let base64: String = pem.lines().skip(1).take_while(|l| !l.starts_with("-----")).collect();
+  let der = BASE64_STANDARD.decode(base64).unwrap();
+  format!("{:x}", sha2::Sha256::digest(der))
+}
+
+/// Serve a single TLS connection, answering the request it carries, if any, as a v2 registry.
+/// Returns the bytes received over the connection.
+fn serve_once() -> (String, JoinHandle<Vec<u8>>) {
+  let identity = Identity::from_pkcs8(
+    &read_certificate("localhost.crt"),
+    &read_certificate("localhost-key-pkcs8.pem"),
+  )
+  .unwrap();
+  let acceptor = TlsAcceptor::new(identity).unwrap();
+  let listener = TcpListener::bind("localhost:0").unwrap();
+  let host = format!("localhost:{}", listener.local_addr().unwrap().port());
+  let server = std::thread::spawn(move || {
+    let (stream, _) = listener.accept().unwrap();
+    let mut stream = acceptor.accept(stream).unwrap();
+    let mut received = Vec::new();
+    let mut buf = [0; 4096];
+    while !received.windows(4).any(|w| w == b"\r\n\r\n") {
+      match stream.read(&mut buf) {
+        Ok(0) | Err(_) => return received,
+        Ok(n) => received.extend_from_slice(&buf[..n]),
+      }
+    }
+    stream
+      .write_all(b"HTTP/1.1 200 OK\r\nDocker-Distribution-API-Version: registry/2.0\r\nContent-Length: 0\r\n\r\n")
+      .unwrap();
+    received
+  });
+  (host, server)
+}
+
+fn client(host: &str, fingerprint: &str) -> Client {
+  Client::configure()
+    .registry(host)
+    .accept_invalid_certs(true)
+    .pin_server_certificate(fingerprint)
+    .username(Some("user".to_string()))
+    .password(Some("secret".to_string()))
+    .build()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_pinning_checks_the_connection_before_sending() {
+  let (host, server) = serve_once();
+  let res = client(&host, &"ab".repeat(32)).is_v2_supported().await;
+  match res {
+    Err(Error::CertificatePinMismatch { host: h, fingerprint }) => {
+      assert_eq!(h, "localhost");
+      assert_eq!(fingerprint, Some(server_fingerprint()));
+    }
+    res => panic!("unexpected result {res:?}"),
+  }
+  // The connection was dropped once the handshake completed, before anything was sent.
+  assert!(server.join().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_pinning_sends_over_the_checked_connection() {
+  let (host, server) = serve_once();
+  // The server accepts one connection only: the request goes out without a probe first.
+  assert!(client(&host, &server_fingerprint()).is_v2_supported().await.unwrap());
+  assert!(server.join().unwrap().starts_with(b"GET /v2/ "));
+}
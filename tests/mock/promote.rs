@@ -0,0 +1,195 @@
+use std::sync::{Arc, Mutex};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use docker_registry::{
+  errors::Error,
+  promote::{promote, PromoteOptions, PromotionPolicy, PromotionSource, ReportSigner, PROMOTED_FROM_ANNOTATION},
+  v2::{Client, HookError},
+};
+use mockito::{Matcher, Mock, ServerGuard};
+use serde_json::{json, Value};
+
+use crate::mock::copy::{blob_mock, descriptor, digest, LAYER_TYPE, OCI_MANIFEST};
+
+/// Policy recording the digests and signatures it was asked to check.
+#[derive(Debug, Default)]
+struct Policy(Mutex<Vec<(String, Vec<String>)>>);
+
+impl PromotionPolicy for Policy {
+  fn check(&self, source: &PromotionSource<'_>) -> Result<(), HookError> {
+    let checked = (source.digest.to_string(), source.signatures.to_vec());
+    self.0.lock().unwrap().push(checked);
+    Ok(())
+  }
+}
+
+/// Signer "signing" with the length of the payload.
+#[derive(Debug)]
+struct Signer;
+
+impl ReportSigner for Signer {
+  fn sign(&self, payload: &[u8]) -> Result<Vec<u8>, HookError> {
+    Ok(payload.len().to_string().into_bytes())
+  }
+}
+
+fn client(server: &ServerGuard) -> Client {
+  Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+}
+
+/// Mocks the source image `src:rc1`, returning its manifest, and its blobs.
+fn source_mocks(server: &mut ServerGuard) -> (Value, Vec<Mock>) {
+  let config = json!({
+    "architecture": "amd64",
+    "os": "linux",
+    "rootfs": {"type": "layers", "diff_ids": [digest(b"diff")]},
+  });
+  let config = serde_json::to_vec(&config).unwrap();
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": [descriptor(LAYER_TYPE, b"layer")],
+  });
+  let body = serde_json::to_vec(&manifest).unwrap();
+  let mut mocks = Vec::new();
+  for reference in ["rc1".to_string(), digest(&body)] {
+    mocks.push(
+      server
+        .mock("GET", format!("/v2/src/manifests/{}", reference).as_str())
+        .with_status(200)
+        .with_header("Content-Type", OCI_MANIFEST)
+        .with_header("Docker-Content-Digest", &digest(&body))
+        .with_body(&body)
+        .create(),
+    );
+  }
+  mocks.push(blob_mock(server, "src", &config));
+  for data in [&config[..], b"layer"] {
+    mocks.push(
+      server
+        .mock("HEAD", format!("/v2/dst/blobs/{}", digest(data)).as_str())
+        .with_status(200)
+        .create(),
+    );
+  }
+  // Neither the referrers API nor the referrers tag schema list any signature.
+  let referrers_tag = digest(&body).replacen(':', "-", 1);
+  for path in [
+    format!("/v2/src/referrers/{}", digest(&body)),
+    format!("/v2/src/manifests/{}", referrers_tag),
+  ] {
+    mocks.push(server.mock("GET", path.as_str()).with_status(404).create());
+  }
+  (manifest, mocks)
+}
+
+fn signature_tag(manifest: &Value) -> String {
+  format!(
+    "{}.sig",
+    digest(&serde_json::to_vec(manifest).unwrap()).replacen(':', "-", 1)
+  )
+}
+
+#[tokio::test]
+async fn test_promote() {
+  let mut server = mockito::Server::new_async().await;
+  let (manifest, _source) = source_mocks(&mut server);
+  let source_digest = digest(&serde_json::to_vec(&manifest).unwrap());
+  let signature = digest(b"signature");
+  let promoted = digest(b"promoted");
+
+  let _signature = server
+    .mock(
+      "HEAD",
+      format!("/v2/src/manifests/{}", signature_tag(&manifest)).as_str(),
+    )
+    .with_status(200)
+    .with_header("Content-Type", OCI_MANIFEST)
+    .with_header("Docker-Content-Digest", &signature)
+    .create();
+  let push = server
+    .mock("PUT", "/v2/dst/manifests/1.0.0")
+    .match_body(Matcher::PartialJson(json!({
+      "annotations": {PROMOTED_FROM_ANNOTATION: format!("{}/src@{}", server.host_with_port(), source_digest)},
+    })))
+    .with_status(201)
+    .with_header("Docker-Content-Digest", &promoted)
+    .create();
+  let _promoted = server
+    .mock("GET", format!("/v2/dst/manifests/{}", promoted).as_str())
+    .with_status(200)
+    .with_header("Content-Type", OCI_MANIFEST)
+    .with_body("{}")
+    .create();
+  let retag = server
+    .mock("PUT", "/v2/dst/manifests/stable")
+    .match_body("{}")
+    .with_status(201)
+    .create();
+
+  let client = client(&server);
+  let policy = Arc::new(Policy::default());
+  let options = PromoteOptions {
+    require_signature: true,
+    policy: Some(policy.clone()),
+    annotate: true,
+    tags: vec!["stable".to_string()],
+    signer: Some(Arc::new(Signer)),
+    ..Default::default()
+  };
+  let report = promote(&client, "src", "rc1", &client, "dst", "1.0.0", &options)
+    .await
+    .unwrap();
+
+  assert_eq!(
+    *policy.0.lock().unwrap(),
+    [(source_digest.clone(), vec![signature.clone()])]
+  );
+  assert_eq!(
+    report.source,
+    format!("{}/src@{}", server.host_with_port(), source_digest)
+  );
+  assert_eq!(report.destination, format!("{}/dst", server.host_with_port()));
+  assert_eq!(report.digest, promoted);
+  assert_eq!(report.tags, ["1.0.0", "stable"]);
+  assert_eq!(report.signatures, [signature]);
+  // The signature covers the report without its signature.
+  let payload = report.payload().unwrap();
+  assert!(serde_json::from_slice::<Value>(&payload)
+    .unwrap()
+    .get("signature")
+    .is_none());
+  assert_eq!(report.signature, Some(STANDARD.encode(payload.len().to_string())));
+  push.assert_async().await;
+  retag.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_promote_unsigned() {
+  let mut server = mockito::Server::new_async().await;
+  let (manifest, _source) = source_mocks(&mut server);
+  let _signature = server
+    .mock(
+      "HEAD",
+      format!("/v2/src/manifests/{}", signature_tag(&manifest)).as_str(),
+    )
+    .with_status(404)
+    .create();
+  let push = server.mock("PUT", Matcher::Any).expect(0).create();
+
+  let options = PromoteOptions {
+    require_signature: true,
+    ..Default::default()
+  };
+  let client = client(&server);
+  match promote(&client, "src", "rc1", &client, "dst", "1.0.0", &options).await {
+    Err(Error::PromotionRejected(reason)) => assert!(reason.ends_with("is not signed"), "{}", reason),
+    res => panic!("unexpected result {:?}", res),
+  }
+  push.assert_async().await;
+}
@@ -0,0 +1,176 @@
+use docker_registry::{
+  blob_store::{get_bytes, MemoryBlobStore},
+  v2::Conditional,
+};
+use mockito::Matcher;
+use serde_json::json;
+
+use super::copy::{descriptor, digest, LAYER_TYPE, OCI_MANIFEST};
+
+#[tokio::test]
+async fn test_proxy_cached_pull_with_token_passthrough() {
+  let mut server = mockito::Server::new_async().await;
+
+  let layer: &[u8] = b"layer";
+  let config: &[u8] = b"{}";
+  let manifest = serde_json::to_vec(&json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", config),
+    "layers": [descriptor(LAYER_TYPE, layer)],
+  }))
+  .unwrap();
+  let auth = Matcher::Exact("Bearer downstream-token".to_string());
+
+  let mocks = vec![
+    server
+      .mock("HEAD", "/v2/library/alpine/manifests/3.20")
+      .match_header("authorization", auth.clone())
+      .with_status(200)
+      .with_header("Docker-Content-Digest", &digest(&manifest))
+      .expect(2)
+      .create(),
+    server
+      .mock(
+        "GET",
+        format!("/v2/library/alpine/manifests/{}", digest(&manifest)).as_str(),
+      )
+      .match_header("authorization", auth.clone())
+      .with_status(200)
+      .with_header("Content-Type", OCI_MANIFEST)
+      .with_body(&manifest)
+      .expect(1)
+      .create(),
+    server
+      .mock("GET", format!("/v2/library/alpine/blobs/{}", digest(config)).as_str())
+      .match_header("authorization", auth.clone())
+      .with_status(200)
+      .with_body(config)
+      .expect(1)
+      .create(),
+    server
+      .mock("GET", format!("/v2/library/alpine/blobs/{}", digest(layer)).as_str())
+      .match_header("authorization", auth.clone())
+      .with_status(200)
+      .with_body(layer)
+      .expect(1)
+      .create(),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+    .with_authorization("Bearer downstream-token")
+    .unwrap();
+  let store = MemoryBlobStore::new();
+
+  for cached in [false, true] {
+    let pulled = client
+      .get_manifest_cached("library/alpine", "3.20", &store)
+      .await
+      .unwrap();
+    assert_eq!(pulled.cached, cached);
+    assert_eq!(pulled.body, manifest);
+    assert_eq!(pulled.media_type, OCI_MANIFEST);
+    assert_eq!(pulled.digest, digest(&manifest));
+    assert_eq!(
+      pulled.referenced_digests().unwrap(),
+      vec![digest(config), digest(layer)]
+    );
+
+    for blob in pulled.referenced_digests().unwrap() {
+      client.get_blob_cached("library/alpine", &blob, &store).await.unwrap();
+    }
+  }
+
+  // Pulling by digest is answered from the store alone.
+  let pulled = client
+    .get_manifest_cached("library/alpine", &digest(&manifest), &store)
+    .await
+    .unwrap();
+  assert!(pulled.cached);
+  assert_eq!(get_bytes(&store, &digest(layer)).await.unwrap(), layer);
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_proxy_auth_challenge() {
+  let mut server = mockito::Server::new_async().await;
+  let challenge = format!(
+    "Bearer realm=\"{}/token\",service=\"registry.example.com\"",
+    server.url()
+  );
+
+  let mock = server
+    .mock("GET", "/v2/")
+    .match_header("authorization", Matcher::Missing)
+    .with_status(401)
+    .with_header("WWW-Authenticate", &challenge)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+    .with_bearer_token("upstream-token");
+
+  assert_eq!(client.auth_challenge().await.unwrap(), Some(challenge));
+  mock.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_proxy_manifest_not_modified() {
+  let mut server = mockito::Server::new_async().await;
+  let manifest = br#"{"schemaVersion":2}"#;
+  let etag = format!("\"{}\"", digest(manifest));
+
+  let mocks = vec![
+    server
+      .mock("GET", "/v2/library/alpine/manifests/3.20")
+      .match_header("if-none-match", Matcher::Missing)
+      .with_status(200)
+      .with_header("Content-Type", OCI_MANIFEST)
+      .with_header("ETag", &etag)
+      .with_body(manifest)
+      .create(),
+    server
+      .mock("GET", "/v2/library/alpine/manifests/3.20")
+      .match_header("if-none-match", etag.as_str())
+      .with_status(304)
+      .create(),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let fetched = client
+    .get_raw_manifest_if_modified("library/alpine", "3.20", None)
+    .await
+    .unwrap();
+  assert_eq!(
+    fetched,
+    Conditional::Modified {
+      value: (manifest.to_vec(), OCI_MANIFEST.to_string(), Some(digest(manifest))),
+      etag: Some(etag.clone()),
+    }
+  );
+
+  let fetched = client
+    .get_raw_manifest_if_modified("library/alpine", "3.20", Some(&etag))
+    .await
+    .unwrap();
+  assert_eq!(fetched, Conditional::NotModified);
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
@@ -0,0 +1,94 @@
+use docker_registry::{
+  bulk::{BulkOptions, DeleteTagsOptions},
+  copy::{copy_image, CopyOptions},
+  errors::Error,
+  orphans::OrphanReport,
+  v2::Client,
+};
+use mockito::Matcher;
+use reqwest::{header::HeaderMap, Method};
+
+const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+#[tokio::test]
+async fn test_read_only_client() {
+  let mut server = mockito::Server::new_async().await;
+  let requests: Vec<_> = ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"]
+    .into_iter()
+    .map(|method| server.mock(method, Matcher::Any).expect(0).create())
+    .collect();
+
+  let client = Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .read_only(true)
+    .build()
+    .unwrap();
+  assert!(client.is_read_only());
+
+  let digest = "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab";
+  let results = [
+    client.put_manifest("repo", "latest", OCI_MANIFEST, b"{}").await.err(),
+    client.delete_manifest("repo", digest).await.err(),
+    client.push_blob("repo", b"blob", digest).await.err(),
+    client.start_upload("repo").await.err(),
+    copy_image(
+      &client,
+      "src",
+      "latest",
+      &client,
+      "dst",
+      "latest",
+      &CopyOptions::default(),
+    )
+    .await
+    .err(),
+    client
+      .delete_tags("repo", vec!["latest".to_string()], &DeleteTagsOptions::default())
+      .await
+      .err(),
+    client
+      .raw_request(Method::DELETE, "/api/v2.0/projects/library", HeaderMap::new(), None)
+      .await
+      .err(),
+    client
+      .delete_orphaned_referrers("repo", &OrphanReport::default(), &BulkOptions::default())
+      .await
+      .err(),
+  ];
+  for result in results {
+    assert!(matches!(result, Some(Error::ReadOnlyClient)), "{:?}", result);
+  }
+
+  let deleted = client
+    .delete_manifests("repo", vec![digest.to_string()], &BulkOptions::default())
+    .await;
+  assert!(matches!(deleted.items[0].result, Err(Error::ReadOnlyClient)));
+  for mock in requests {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_read_only_client_reads() {
+  let mut server = mockito::Server::new_async().await;
+  let tags = server
+    .mock("GET", "/v2/repo/tags/list")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(r#"{"name": "repo", "tags": ["latest"]}"#)
+    .create();
+
+  let client = Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .read_only(true)
+    .build()
+    .unwrap();
+  let listed = client
+    .raw_request(Method::GET, "/v2/repo/tags/list", HeaderMap::new(), None)
+    .await
+    .unwrap();
+  assert_eq!(listed.status, 200);
+  tags.assert_async().await;
+}
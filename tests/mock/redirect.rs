@@ -0,0 +1,170 @@
+use docker_registry::v2::{HookError, RedirectPolicy, RequestSigner, SigningRequest};
+
+#[derive(Debug)]
+struct BearerSigner;
+
+impl RequestSigner for BearerSigner {
+  fn sign(&self, request: &mut SigningRequest<'_>) -> Result<(), HookError> {
+    request.headers_mut().insert("authorization", "Bearer secret".parse()?);
+    Ok(())
+  }
+}
+
+const BLOB: &[u8] = b"hello";
+const DIGEST: &str = "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+#[tokio::test]
+async fn test_redirect_strips_authorization_cross_host() {
+  let name = "my-repo/my-image";
+  let mut registry = mockito::Server::new_async().await;
+  let mut storage = mockito::Server::new_async().await;
+  let location = format!("{}/bucket/blob?sig=abc", storage.url());
+
+  let redirect = registry
+    .mock("GET", format!("/v2/{name}/blobs/{DIGEST}").as_str())
+    .match_header("authorization", "Bearer secret")
+    .with_status(307)
+    .with_header("Location", &location)
+    .create();
+  let blob = storage
+    .mock("GET", "/bucket/blob?sig=abc")
+    .match_header("authorization", mockito::Matcher::Missing)
+    .with_status(200)
+    .with_body(BLOB)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&registry.host_with_port())
+    .insecure_registry(true)
+    .request_signer(BearerSigner)
+    .build()
+    .unwrap();
+
+  let res = client.get_blob(name, DIGEST).await.unwrap();
+  assert_eq!(res, BLOB);
+
+  redirect.assert_async().await;
+  blob.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_redirect_host_not_allowed() {
+  let name = "my-repo/my-image";
+  let mut registry = mockito::Server::new_async().await;
+  let mut storage = mockito::Server::new_async().await;
+  let location = format!("http://localhost:{}/bucket/blob", storage.socket_address().port());
+
+  let redirect = registry
+    .mock("GET", format!("/v2/{name}/blobs/{DIGEST}").as_str())
+    .with_status(307)
+    .with_header("Location", &location)
+    .create();
+  let blob = storage.mock("GET", "/bucket/blob").with_body(BLOB).expect(0).create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&registry.host_with_port())
+    .insecure_registry(true)
+    .redirect_policy(RedirectPolicy::default().allow_host("*.s3.amazonaws.com"))
+    .build()
+    .unwrap();
+
+  let res = client.get_blob(name, DIGEST).await;
+  assert!(res.is_err());
+
+  redirect.assert_async().await;
+  blob.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_redirect_max_redirects() {
+  let name = "my-repo/my-image";
+  let mut registry = mockito::Server::new_async().await;
+  let mut storage = mockito::Server::new_async().await;
+  let location = format!("{}/bucket/blob", storage.url());
+
+  let redirect = registry
+    .mock("GET", format!("/v2/{name}/blobs/{DIGEST}").as_str())
+    .with_status(307)
+    .with_header("Location", &location)
+    .create();
+  let blob = storage.mock("GET", "/bucket/blob").with_body(BLOB).expect(0).create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&registry.host_with_port())
+    .insecure_registry(true)
+    .redirect_policy(RedirectPolicy::none())
+    .build()
+    .unwrap();
+
+  let res = client.get_blob(name, DIGEST).await;
+  assert!(res.is_err());
+
+  redirect.assert_async().await;
+  blob.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_redirect_reuses_signed_url() {
+  let name = "my-repo/my-image";
+  let mut registry = mockito::Server::new_async().await;
+  let mut storage = mockito::Server::new_async().await;
+  let path = "/bucket/blob?Expires=4102444800&Signature=abc";
+
+  let redirect = registry
+    .mock("GET", format!("/v2/{name}/blobs/{DIGEST}").as_str())
+    .match_header("range", "bytes=0-1")
+    .with_status(307)
+    .with_header("Location", &format!("{}{}", storage.url(), path))
+    .expect(1)
+    .create();
+  let mut parts = Vec::new();
+  for (start, end) in [(0, 1), (2, 3), (4, 4)] {
+    parts.push(
+      storage
+        .mock("GET", path)
+        .match_header("range", format!("bytes={start}-{end}").as_str())
+        .match_header("authorization", mockito::Matcher::Missing)
+        .with_status(206)
+        .with_header("Content-Range", &format!("bytes {start}-{end}/5"))
+        .with_body(&BLOB[start..=end])
+        .expect(1)
+        .create(),
+    );
+  }
+  // Once the storage rejects the signed URL, the registry is asked again.
+  let expired = storage
+    .mock("GET", path)
+    .match_header("range", mockito::Matcher::Missing)
+    .with_status(403)
+    .expect(1)
+    .create();
+  let renewed = registry
+    .mock("GET", format!("/v2/{name}/blobs/{DIGEST}").as_str())
+    .match_header("range", mockito::Matcher::Missing)
+    .with_status(307)
+    .with_header("Location", &format!("{}/bucket/blob?sig=renewed", storage.url()))
+    .expect(1)
+    .create();
+  let blob = storage
+    .mock("GET", "/bucket/blob?sig=renewed")
+    .with_body(BLOB)
+    .expect(1)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&registry.host_with_port())
+    .insecure_registry(true)
+    .request_signer(BearerSigner)
+    .parallel_downloads(Some(docker_registry::v2::ParallelDownloads::default().part_size(2)))
+    .build()
+    .unwrap();
+
+  let stream = client.get_blob_stream(name, DIGEST).await.unwrap();
+  let downloaded: Vec<Vec<u8>> = futures::TryStreamExt::try_collect(stream).await.unwrap();
+  assert_eq!(downloaded.concat(), BLOB);
+  assert_eq!(client.get_blob(name, DIGEST).await.unwrap(), BLOB);
+
+  for mock in parts.iter().chain([&redirect, &expired, &renewed, &blob]) {
+    mock.assert_async().await;
+  }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use docker_registry::v2::{Descriptor, ReferrerFilter, NOTATION_SIGNATURE_ARTIFACT_TYPE};
+
+static SUBJECT: &str = "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab";
+static SIGNATURE: &str = "sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+static ENVELOPE: &str = "sha256:baa5a0964d3320fbc0c6a922140453c8513ea24ab8fd0577034804a967248096";
+
+#[tokio::test]
+async fn test_referrers_notation_signatures_tag_fallback() {
+  let name = "repo";
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let index = serde_json::json!({
+    "schemaVersion": 2,
+    "mediaType": "application/vnd.oci.image.index.v1+json",
+    "manifests": [
+      {
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "artifactType": NOTATION_SIGNATURE_ARTIFACT_TYPE,
+        "digest": SIGNATURE,
+        "size": 100,
+      },
+      {
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "artifactType": "application/spdx+json",
+        "digest": SUBJECT,
+        "size": 100,
+      },
+    ],
+  });
+  let signature = serde_json::json!({
+    "schemaVersion": 2,
+    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+    "artifactType": NOTATION_SIGNATURE_ARTIFACT_TYPE,
+    "config": { "mediaType": "application/vnd.oci.empty.v1+json", "digest": SUBJECT, "size": 2 },
+    "layers": [{ "mediaType": "application/jose+json", "digest": ENVELOPE, "size": 3 }],
+  });
+
+  let referrers = server
+    .mock("GET", format!("/v2/{name}/referrers/{SUBJECT}").as_str())
+    .match_query(mockito::Matcher::UrlEncoded(
+      "artifactType".into(),
+      NOTATION_SIGNATURE_ARTIFACT_TYPE.into(),
+    ))
+    .with_status(404)
+    .create();
+  let tag = server
+    .mock(
+      "GET",
+      format!("/v2/{name}/manifests/{}", SUBJECT.replace(':', "-")).as_str(),
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+    .with_body(index.to_string())
+    .create();
+  let manifest = server
+    .mock("GET", format!("/v2/{name}/manifests/{SIGNATURE}").as_str())
+    .with_status(200)
+    .with_header("Content-Type", "application/vnd.oci.image.manifest.v1+json")
+    .with_body(signature.to_string())
+    .create();
+  let blob = server
+    .mock("GET", format!("/v2/{name}/blobs/{ENVELOPE}").as_str())
+    .with_status(200)
+    .with_body("baz")
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let signatures = client.get_notation_signatures(name, SUBJECT).await.unwrap();
+  assert_eq!(signatures.len(), 1);
+  assert_eq!(signatures[0].descriptor.digest, SIGNATURE);
+  assert_eq!(signatures[0].envelope_media_type, "application/jose+json");
+  assert_eq!(signatures[0].envelope, b"baz");
+
+  referrers.assert_async().await;
+  tag.assert_async().await;
+  manifest.assert_async().await;
+  blob.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_referrers_push_notation_signature() {
+  let name = "repo";
+  let location = format!("/v2/{name}/blobs/uploads/1");
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let head = server
+    .mock("HEAD", mockito::Matcher::Regex(format!("^/v2/{name}/blobs/sha256:")))
+    .with_status(404)
+    .create();
+  let post = server
+    .mock("POST", format!("/v2/{name}/blobs/uploads/").as_str())
+    .with_status(202)
+    .with_header("Location", &location)
+    .expect(2)
+    .create();
+  let put_blob = server
+    .mock("PUT", location.as_str())
+    .match_query(mockito::Matcher::Any)
+    .with_status(201)
+    .expect(2)
+    .create();
+  let put_manifest = server
+    .mock("PUT", mockito::Matcher::Regex(format!("^/v2/{name}/manifests/sha256:")))
+    .match_header("content-type", "application/vnd.oci.image.manifest.v1+json")
+    .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+      "artifactType": NOTATION_SIGNATURE_ARTIFACT_TYPE,
+      "layers": [{ "mediaType": "application/jose+json", "digest": ENVELOPE, "size": 3 }],
+      "subject": { "digest": SUBJECT },
+    })))
+    .with_status(201)
+    .with_header("Docker-Content-Digest", SIGNATURE)
+    .with_header("OCI-Subject", SUBJECT)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let subject = Descriptor::from_digest("application/vnd.oci.image.manifest.v1+json", SUBJECT, 100);
+  let digest = client
+    .push_notation_signature(name, &subject, "application/jose+json", b"baz", HashMap::new())
+    .await
+    .unwrap();
+  assert_eq!(digest, SIGNATURE);
+
+  head.assert_async().await;
+  post.assert_async().await;
+  put_blob.assert_async().await;
+  put_manifest.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_referrers_push_notation_signature_tag_fallback() {
+  let name = "repo";
+  let location = format!("/v2/{name}/blobs/uploads/1");
+  let tag = SUBJECT.replacen(':', "-", 1);
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let head = server
+    .mock("HEAD", mockito::Matcher::Regex(format!("^/v2/{name}/blobs/sha256:")))
+    .with_status(200)
+    .expect_at_least(1)
+    .create();
+  let post = server
+    .mock("POST", format!("/v2/{name}/blobs/uploads/").as_str())
+    .with_status(202)
+    .with_header("Location", &location)
+    .create();
+  let put_blob = server
+    .mock("PUT", location.as_str())
+    .match_query(mockito::Matcher::Any)
+    .with_status(201)
+    .create();
+  // No `OCI-Subject` header: the registry does not index the subject itself.
+  let put_manifest = server
+    .mock("PUT", mockito::Matcher::Regex(format!("^/v2/{name}/manifests/sha256:")))
+    .with_status(201)
+    .with_header("Docker-Content-Digest", SIGNATURE)
+    .create();
+  let get_tag = server
+    .mock("GET", format!("/v2/{name}/manifests/{tag}").as_str())
+    .with_status(404)
+    .create();
+  let put_tag = server
+    .mock("PUT", format!("/v2/{name}/manifests/{tag}").as_str())
+    .match_header("content-type", "application/vnd.oci.image.index.v1+json")
+    .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+      "schemaVersion": 2,
+      "manifests": [{
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "artifactType": NOTATION_SIGNATURE_ARTIFACT_TYPE,
+        "digest": SIGNATURE,
+      }],
+    })))
+    .with_status(201)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let subject = Descriptor::from_digest("application/vnd.oci.image.manifest.v1+json", SUBJECT, 100);
+  let digest = client
+    .push_notation_signature(name, &subject, "application/jose+json", b"baz", HashMap::new())
+    .await
+    .unwrap();
+  assert_eq!(digest, SIGNATURE);
+
+  for mock in [head, post, put_blob, put_manifest, get_tag, put_tag] {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_referrers_list_filtered() {
+  let mut server = mockito::Server::new_async().await;
+
+  // The registry ignores `artifactType`: the client filters the referrers itself.
+  let index = serde_json::json!({
+    "schemaVersion": 2,
+    "mediaType": "application/vnd.oci.image.index.v1+json",
+    "manifests": [
+      {
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "artifactType": NOTATION_SIGNATURE_ARTIFACT_TYPE,
+        "digest": SIGNATURE,
+        "size": 100,
+        "annotations": {"io.cncf.notary.x509chain.thumbprint#S256": "[\"abc\"]", "team": "infra"},
+      },
+      {
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "artifactType": NOTATION_SIGNATURE_ARTIFACT_TYPE,
+        "digest": ENVELOPE,
+        "size": 100,
+        "annotations": {"team": "web"},
+      },
+      {
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "artifactType": "application/spdx+json",
+        "digest": SUBJECT,
+        "size": 100,
+        "annotations": {"team": "infra"},
+      },
+    ],
+  });
+  let referrers = server
+    .mock("GET", format!("/v2/repo/referrers/{SUBJECT}").as_str())
+    .match_query(mockito::Matcher::UrlEncoded(
+      "artifactType".into(),
+      NOTATION_SIGNATURE_ARTIFACT_TYPE.into(),
+    ))
+    .with_status(200)
+    .with_header("Content-Type", "application/vnd.oci.image.index.v1+json")
+    .with_body(index.to_string())
+    .expect(2)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let signatures = ReferrerFilter::new().artifact_type(NOTATION_SIGNATURE_ARTIFACT_TYPE);
+  let filter = signatures.clone().annotation("team", "infra");
+  let found = client.list_referrers("repo", SUBJECT, &filter).await.unwrap();
+  assert_eq!(found.iter().map(|d| d.digest.as_str()).collect::<Vec<_>>(), [SIGNATURE]);
+
+  let filter = signatures.has_annotation("io.cncf.notary.x509chain.thumbprint#S256");
+  let found = client.list_referrers("repo", SUBJECT, &filter).await.unwrap();
+  assert_eq!(found.iter().map(|d| d.digest.as_str()).collect::<Vec<_>>(), [SIGNATURE]);
+  referrers.assert_async().await;
+}
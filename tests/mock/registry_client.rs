@@ -0,0 +1,56 @@
+use docker_registry::{registry_client::RegistryClient, v2::Client};
+use futures::TryStreamExt;
+use mockito::Matcher;
+use sha2::Digest;
+
+/// Read a repository through the trait only, as code written against it would.
+async fn tags_and_blob(registry: &dyn RegistryClient, digest: &str) -> (Vec<String>, bool, Vec<u8>) {
+  let tags = registry.get_tags("repo", None).try_collect().await.unwrap();
+  let has_blob = registry.has_blob("repo", digest).await.unwrap();
+  let chunks: Vec<Vec<u8>> = registry
+    .get_blob_stream("repo", digest)
+    .await
+    .unwrap()
+    .try_collect()
+    .await
+    .unwrap();
+  (tags, has_blob, chunks.concat())
+}
+
+#[tokio::test]
+async fn test_registry_client_trait() {
+  let mut server = mockito::Server::new_async().await;
+  let blob = b"hello";
+  let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+  let mocks = [
+    server
+      .mock("GET", "/v2/repo/tags/list")
+      .match_query(Matcher::Any)
+      .with_status(200)
+      .with_body(r#"{"name": "repo", "tags": ["v1", "v2"]}"#)
+      .create(),
+    server
+      .mock("HEAD", format!("/v2/repo/blobs/{digest}").as_str())
+      .with_status(200)
+      .create(),
+    server
+      .mock("GET", format!("/v2/repo/blobs/{digest}").as_str())
+      .with_status(200)
+      .with_body(blob)
+      .create(),
+  ];
+
+  let client = Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  let (tags, has_blob, content) = tags_and_blob(&client, &digest).await;
+  assert_eq!(tags, ["v1", "v2"]);
+  assert!(has_blob);
+  assert_eq!(content, blob);
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+}
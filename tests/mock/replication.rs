@@ -0,0 +1,100 @@
+use docker_registry::replication::{verify_replication, Discrepancy, ReplicationOptions, ReplicationRef};
+use mockito::{Mock, ServerGuard};
+use serde_json::json;
+
+use crate::mock::copy::{blob_mock, descriptor, digest, manifest_mock, LAYER_TYPE, OCI_MANIFEST};
+
+fn client(server: &ServerGuard) -> docker_registry::v2::Client {
+  docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap()
+}
+
+fn head_mock(server: &mut ServerGuard, path: &str, status: usize) -> Mock {
+  server
+    .mock("HEAD", path)
+    .with_status(status)
+    .with_header("Content-Type", OCI_MANIFEST)
+    .create()
+}
+
+#[tokio::test]
+async fn test_verify_replication() {
+  let mut src = mockito::Server::new_async().await;
+  let mut dst = mockito::Server::new_async().await;
+
+  let config = serde_json::to_vec(&json!({"architecture": "amd64", "os": "linux"})).unwrap();
+  let (layer1, layer2): (&[u8], &[u8]) = (b"layer1", b"layer2");
+  let manifest = json!({
+    "schemaVersion": 2,
+    "mediaType": OCI_MANIFEST,
+    "config": descriptor("application/vnd.oci.image.config.v1+json", &config),
+    "layers": [descriptor(LAYER_TYPE, layer1), descriptor(LAYER_TYPE, layer2)],
+  });
+  let other = json!({"schemaVersion": 2, "mediaType": OCI_MANIFEST, "config": manifest["config"], "layers": []});
+  let manifest_digest = digest(&serde_json::to_vec(&manifest).unwrap());
+
+  let mocks = vec![
+    manifest_mock(&mut src, "repo", "v1", &manifest).expect(3),
+    head_mock(&mut dst, "/v2/repo/manifests/v1", 200),
+    manifest_mock(&mut dst, "repo", "v1", &manifest),
+    head_mock(&mut dst, &format!("/v2/repo/blobs/{}", digest(&config)), 200),
+    head_mock(&mut dst, &format!("/v2/repo/blobs/{}", digest(layer1)), 200),
+    dst
+      .mock("GET", format!("/v2/repo/blobs/{}", digest(layer1)).as_str())
+      .with_status(200)
+      .with_body("corrupted")
+      .create(),
+    head_mock(&mut dst, &format!("/v2/repo/blobs/{}", digest(layer2)), 404),
+    head_mock(&mut dst, "/v2/missing/manifests/v1", 404),
+    // The digest reported by HEAD is used without downloading the manifest.
+    dst
+      .mock("HEAD", "/v2/other/manifests/v1")
+      .with_status(200)
+      .with_header("Content-Type", OCI_MANIFEST)
+      .with_header("Docker-Content-Digest", &digest(&serde_json::to_vec(&other).unwrap()))
+      .create(),
+    manifest_mock(&mut dst, "other", "v1", &other).expect(0),
+    blob_mock(&mut dst, "repo", layer2).expect(0),
+  ];
+
+  let refs = vec![
+    ReplicationRef::new("repo", "v1"),
+    ReplicationRef::new("repo", "v1").to("missing", "v1"),
+    ReplicationRef::new("repo", "v1").to("other", "v1"),
+  ];
+  let options = ReplicationOptions {
+    sample_blobs: 1,
+    ..Default::default()
+  };
+  let report = verify_replication(&client(&src), &client(&dst), refs, &options).await;
+
+  let results: Vec<_> = report
+    .items
+    .iter()
+    .map(|i| i.result.as_ref().unwrap().clone())
+    .collect();
+  assert_eq!(
+    results,
+    vec![
+      vec![
+        Discrepancy::CorruptBlob { digest: digest(layer1) },
+        Discrepancy::MissingBlob { digest: digest(layer2) },
+      ],
+      vec![Discrepancy::MissingManifest {
+        digest: manifest_digest.clone()
+      }],
+      vec![Discrepancy::ManifestMismatch {
+        source: manifest_digest,
+        destination: digest(&serde_json::to_vec(&other).unwrap()),
+      }],
+    ]
+  );
+  assert_eq!(report.items[1].item.to_string(), "repo:v1 -> missing:v1");
+
+  for mock in mocks {
+    mock.assert_async().await;
+  }
+}
@@ -0,0 +1,24 @@
+use docker_registry::session::{HostTls, Session};
+
+#[tokio::test]
+async fn test_session_per_host_tls() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+  let port = server.socket_address().port();
+
+  let ping = server
+    .mock("GET", "/v2/")
+    .with_status(200)
+    .with_header("Docker-Distribution-API-Version", "registry/2.0")
+    .create();
+
+  let session = Session::with_config(|_| docker_registry::v2::Client::configure().username(Some("user".into())))
+    .host(&addr, HostTls::default().insecure_registry(true));
+
+  // Only the configured host is reached over plain HTTP.
+  assert!(session.client(&addr).unwrap().is_v2_supported().await.unwrap());
+  let other = session.client(&format!("localhost:{port}")).unwrap();
+  assert!(other.is_v2_supported().await.is_err());
+
+  ping.assert_async().await;
+}
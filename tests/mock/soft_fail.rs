@@ -0,0 +1,61 @@
+use docker_registry::{errors::Error, v2::OptionalFeature};
+use futures::StreamExt;
+use reqwest::StatusCode;
+
+static DIGEST: &str = "sha256:2b4f6e8a0c1d3e5f7a9b0c2d4e6f8a1b3c5d7e9f0a2b4c6d8e0f1a3b5c7d9e2f";
+
+#[tokio::test]
+async fn test_soft_fail_optional_features() {
+  let mut server = mockito::Server::new_async().await;
+  let catalog = server.mock("GET", "/v2/_catalog").with_status(404).expect(2).create();
+  let referrers = server
+    .mock("GET", format!("/v2/repo/referrers/{DIGEST}").as_str())
+    .with_status(405)
+    .create();
+  let delete = server
+    .mock("DELETE", format!("/v2/repo/manifests/{DIGEST}").as_str())
+    .with_status(405)
+    .with_header("Content-Type", "application/json")
+    .with_body(r#"{"errors":[{"code":"UNSUPPORTED","message":"The operation is unsupported."}]}"#)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .soft_fail(true)
+    .build()
+    .unwrap();
+
+  let repos = client.get_catalog(None).collect::<Vec<_>>().await;
+  assert!(repos.is_empty());
+  assert_eq!(client.get_referrers("repo", DIGEST, None).await.unwrap(), vec![]);
+  assert!(matches!(
+    client.delete_manifest("repo", DIGEST).await,
+    Err(Error::Unsupported(OptionalFeature::Deletion))
+  ));
+  assert_eq!(
+    client.unsupported_features(),
+    vec![
+      OptionalFeature::Catalog,
+      OptionalFeature::Deletion,
+      OptionalFeature::Referrers
+    ]
+  );
+
+  // Without soft failures, the registry's answer is returned as is.
+  let strict = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  let repos = strict.get_catalog(None).collect::<Vec<_>>().await;
+  assert!(matches!(
+    repos.as_slice(),
+    [Err(Error::UnexpectedHttpStatus(StatusCode::NOT_FOUND))]
+  ));
+  assert!(strict.unsupported_features().is_empty());
+
+  for mock in [catalog, referrers, delete] {
+    mock.assert_async().await;
+  }
+}
@@ -0,0 +1,109 @@
+#[tokio::test]
+async fn test_stats() {
+  use docker_registry::v2::{ClientStats, EndpointClass, RetryPolicy, TransferStats};
+
+  let digest = "sha256:891f22d1ce0bed0f4d354ce3de87dc18cacb32318e9c5cd42ebbb8a29c4eee76";
+  let manifest_len = std::fs::metadata("tests/fixtures/manifest_list_v2.json").unwrap().len();
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let mocks = [
+    server
+      .mock("GET", format!("/v2/repo/manifests/{digest}").as_str())
+      .with_status(200)
+      .with_header(
+        "Content-Type",
+        "application/vnd.docker.distribution.manifest.list.v2+json",
+      )
+      .with_body_from_file("tests/fixtures/manifest_list_v2.json")
+      .create(),
+    server
+      .mock("HEAD", "/v2/repo/blobs/sha256:aaaa")
+      .with_status(503)
+      .expect(2)
+      .create(),
+  ];
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .retry_policy(Some(
+      RetryPolicy::default()
+        .max_retries(1)
+        .initial_backoff(std::time::Duration::ZERO),
+    ))
+    .build()
+    .unwrap();
+
+  for _ in 0..2 {
+    client.get_manifest_by_digest("repo", digest).await.unwrap();
+  }
+  assert!(!client.has_blob("repo", "sha256:aaaa").await.unwrap());
+  for mock in &mocks {
+    mock.assert_async().await;
+  }
+
+  let stats = client.stats();
+  assert_eq!(stats.requests(), 3);
+  assert_eq!(stats.responses[&(EndpointClass::Manifest, 200)], 1);
+  assert_eq!(stats.responses[&(EndpointClass::Blob, 503)], 2);
+  assert_eq!(stats.responses_from(EndpointClass::Blob), 2);
+  assert_eq!(stats.bytes_received, manifest_len);
+  assert_eq!((stats.manifest_cache_hits, stats.manifest_cache_misses), (1, 1));
+  assert_eq!(stats.retries, 1);
+  assert_eq!(
+    stats.repositories["repo"],
+    TransferStats {
+      requests: 3,
+      bytes_sent: 0,
+      bytes_received: manifest_len,
+    }
+  );
+
+  // Clients derived from the client share its counters.
+  let derived = client.with_bearer_token("token");
+  assert_eq!(derived.reset_stats(), stats);
+  assert_eq!(client.stats(), ClientStats::default());
+}
+
+#[tokio::test]
+async fn test_stats_count_bytes_read() {
+  use futures::TryStreamExt;
+  use sha2::Digest;
+
+  let blob = b"a blob sent in chunks";
+  let digest = format!("sha256:{:x}", sha2::Sha256::digest(blob));
+
+  let mut server = mockito::Server::new_async().await;
+  let mock = server
+    .mock("GET", format!("/v2/repo/blobs/{digest}").as_str())
+    .with_status(200)
+    .with_chunked_body(|w| {
+      for chunk in blob.chunks(8) {
+        w.write_all(chunk)?;
+      }
+      Ok(())
+    })
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let chunks: Vec<Vec<u8>> = client
+    .get_blob_stream("repo", &digest)
+    .await
+    .unwrap()
+    .try_collect()
+    .await
+    .unwrap();
+  assert_eq!(chunks.concat(), blob);
+  mock.assert_async().await;
+
+  let stats = client.stats();
+  assert_eq!(stats.bytes_received, blob.len() as u64);
+  assert_eq!(stats.repositories["repo"].bytes_received, blob.len() as u64);
+}
@@ -145,3 +145,152 @@ fn test_dockerv2_tags_missing_header() {
   mock.assert();
   assert_eq!(vec!["t1", "t2"], res);
 }
+
+#[tokio::test]
+async fn test_dockerv2_tags_if_modified() {
+  let name = "repo";
+  let etag = "\"sha256:e0b4c6e0\"";
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let first = server
+    .mock("GET", format!("/v2/{name}/tags/list").as_str())
+    .match_header("if-none-match", mockito::Matcher::Missing)
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_header("ETag", etag)
+    .with_header("Link", &format!("</v2/{name}/tags/list?n=1&last=t1>; rel=\"next\""))
+    .with_body(r#"{"name": "repo", "tags": ["t1"]}"#)
+    .create();
+  let second_page = server
+    .mock("GET", format!("/v2/{name}/tags/list?n=1&last=t1").as_str())
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(r#"{"name": "repo", "tags": ["t2"]}"#)
+    .create();
+  let unchanged = server
+    .mock("GET", format!("/v2/{name}/tags/list").as_str())
+    .match_header("if-none-match", etag)
+    .with_status(304)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let res = client.get_tags_if_modified(name, None).await.unwrap();
+  assert_eq!(
+    res,
+    docker_registry::v2::Conditional::Modified {
+      value: vec!["t1".to_string(), "t2".to_string()],
+      etag: Some(etag.to_string()),
+    }
+  );
+
+  let res = client.get_tags_if_modified(name, Some(etag)).await.unwrap();
+  assert_eq!(res, docker_registry::v2::Conditional::NotModified);
+
+  first.assert_async().await;
+  second_page.assert_async().await;
+  unchanged.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_dockerv2_tags_compressed() {
+  use docker_registry::layer::Compression;
+
+  let tags: Vec<String> = (0..1000).map(|i| format!("v{i}")).collect();
+  let body = serde_json::to_vec(&serde_json::json!({"name": "repo", "tags": tags})).unwrap();
+  let compressed = Compression::Gzip.compress(&body).unwrap();
+
+  let encodings = vec![
+    #[cfg(feature = "zstd")]
+    Compression::Zstd,
+    Compression::Gzip,
+  ];
+  let accept_encoding = match cfg!(feature = "zstd") {
+    true => "zstd, gzip",
+    false => "gzip",
+  };
+
+  let mut server = mockito::Server::new_async().await;
+  let list = server
+    .mock("GET", "/v2/repo/tags/list")
+    .match_header("accept-encoding", accept_encoding)
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_header("Content-Encoding", "gzip")
+    .with_body(&compressed)
+    .expect(2)
+    .create();
+  // Other endpoints are not asked to compress their responses.
+  let manifest = server
+    .mock("HEAD", "/v2/repo/manifests/v1")
+    .match_header("accept-encoding", mockito::Matcher::Missing)
+    .with_status(404)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .accept_encoding(encodings.clone())
+    .build()
+    .unwrap();
+
+  let res: Vec<String> = client.get_tags("repo", None).map(Result::unwrap).collect().await;
+  assert_eq!(res, tags);
+  assert_eq!(client.has_manifest("repo", "v1", None).await.unwrap(), None);
+
+  // The size limit applies to the decompressed tag list.
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .accept_encoding(encodings)
+    .response_limits(docker_registry::v2::ResponseLimits {
+      max_tag_list_size: compressed.len() as u64 + 1,
+      ..Default::default()
+    })
+    .build()
+    .unwrap();
+  let res: Vec<_> = client.get_tags("repo", None).collect().await;
+  assert!(matches!(
+    res[..],
+    [Err(docker_registry::errors::Error::ResponseTooLarge { .. })]
+  ));
+
+  list.assert_async().await;
+  manifest.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_dockerv2_tags_unsupported_encoding() {
+  use docker_registry::layer::Compression;
+
+  let mut server = mockito::Server::new_async().await;
+  let list = server
+    .mock("GET", "/v2/repo/tags/list")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_header("Content-Encoding", "br")
+    .with_body(b"\x1b\x2a\x00\xf8")
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .accept_encoding(vec![Compression::Gzip])
+    .build()
+    .unwrap();
+
+  let res: Vec<_> = client.get_tags("repo", None).collect().await;
+  match &res[..] {
+    [Err(docker_registry::errors::Error::UnsupportedContentEncoding { kind, encoding })] => {
+      assert_eq!((*kind, encoding.as_str()), ("tag list", "br"));
+    }
+    res => panic!("{res:?}"),
+  }
+  list.assert_async().await;
+}
@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use docker_registry::v2::{Client, FileTokenStore};
+use mockito::{Matcher, Mock, ServerGuard};
+use serde_json::json;
+
+fn challenge_mock(server: &mut ServerGuard) -> Mock {
+  let challenge = format!(r#"Bearer realm="{}/token",service="registry""#, server.url());
+  server
+    .mock("GET", "/v2/")
+    .with_status(401)
+    .with_header("WWW-Authenticate", &challenge)
+    .expect(2)
+    .create()
+}
+
+fn client(server: &ServerGuard, path: &Path) -> Client {
+  Client::configure()
+    .registry(&server.host_with_port())
+    .insecure_registry(true)
+    .token_store(FileTokenStore::new(path))
+    .build()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_token_store_reuses_tokens() {
+  let mut server = mockito::Server::new_async().await;
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("tokens.json");
+
+  let challenge = challenge_mock(&mut server);
+  let token = server
+    .mock("GET", "/token")
+    .match_query(Matcher::UrlEncoded("scope".into(), "repository:repo:pull".into()))
+    .with_status(200)
+    .with_body(json!({"token": "t1", "expires_in": 300}).to_string())
+    .expect(1)
+    .create();
+  let manifest = server
+    .mock("HEAD", "/v2/repo/manifests/latest")
+    .match_header("Authorization", "Bearer t1")
+    .with_status(404)
+    .expect(2)
+    .create();
+
+  // Each client stands for a process: the second one reuses the token of the first.
+  for _ in 0..2 {
+    let client = client(&server, &path)
+      .authenticate(&["repository:repo:pull"])
+      .await
+      .unwrap();
+    assert_eq!(client.has_manifest("repo", "latest", None).await.unwrap(), None);
+  }
+
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+  }
+  challenge.assert_async().await;
+  token.assert_async().await;
+  manifest.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_token_store_refreshes_expired_tokens() {
+  let mut server = mockito::Server::new_async().await;
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("tokens.json");
+
+  let challenge = challenge_mock(&mut server);
+  let token = server
+    .mock("GET", "/token")
+    .match_query(Matcher::Any)
+    .with_status(200)
+    .with_body(json!({"token": "t1", "expires_in": 0, "refresh_token": "r1"}).to_string())
+    .expect(1)
+    .create();
+  let refresh = server
+    .mock("POST", "/token")
+    .match_body(Matcher::AllOf(vec![
+      Matcher::UrlEncoded("grant_type".into(), "refresh_token".into()),
+      Matcher::UrlEncoded("refresh_token".into(), "r1".into()),
+      Matcher::UrlEncoded("service".into(), "registry".into()),
+      Matcher::UrlEncoded("scope".into(), "repository:repo:pull".into()),
+    ]))
+    .with_status(200)
+    .with_body(json!({"access_token": "t2", "expires_in": 300}).to_string())
+    .expect(1)
+    .create();
+
+  for _ in 0..2 {
+    client(&server, &path)
+      .authenticate(&["repository:repo:pull"])
+      .await
+      .unwrap();
+  }
+
+  // The refresh token is kept for the refreshed token.
+  let stored: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+  let stored = stored.as_object().unwrap().values().next().unwrap();
+  assert_eq!(
+    (&stored["token"], &stored["refresh_token"]),
+    (&json!("t2"), &json!("r1"))
+  );
+  challenge.assert_async().await;
+  token.assert_async().await;
+  refresh.assert_async().await;
+}
@@ -0,0 +1,523 @@
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_uploads_cancel_stale_sessions() {
+  let name = "repo";
+  let location = format!("/v2/{name}/blobs/uploads/c5ec2e36");
+
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let post = server
+    .mock("POST", format!("/v2/{name}/blobs/uploads/").as_str())
+    .with_status(202)
+    .with_header("Location", &location)
+    .with_header("Docker-Upload-UUID", "c5ec2e36")
+    .create();
+  let delete = server.mock("DELETE", location.as_str()).with_status(204).create();
+
+  let dir = tempfile::tempdir().unwrap();
+  let journal = dir.path().join("uploads.json");
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .upload_journal(&journal)
+    .build()
+    .unwrap();
+
+  let session = client.start_upload(name).await.unwrap();
+  assert_eq!(session.location(), format!("http://{addr}{location}"));
+  assert_eq!(session.uuid(), Some("c5ec2e36"));
+
+  // A fresh session is not stale yet.
+  let cancelled = client.cancel_stale_uploads(Duration::from_secs(3600)).await.unwrap();
+  assert!(cancelled.is_empty());
+
+  // A new client reading the same journal can clean up after the first one.
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .upload_journal(&journal)
+    .build()
+    .unwrap();
+  let cancelled = client.cancel_stale_uploads(Duration::ZERO).await.unwrap();
+  assert_eq!(cancelled, vec![session]);
+
+  let journal = docker_registry::v2::UploadJournal::new(&journal);
+  assert!(journal.sessions().unwrap().is_empty());
+
+  post.assert_async().await;
+  delete.assert_async().await;
+}
+
+static BLOB: &[u8] = b"abcdefgh";
+static BLOB_DIGEST: &str = "sha256:9c56cc51b374c3ba189210d5b6d4bf57790d351c96c47c02190ecf1e430635ab";
+
+fn upload_mock(server: &mut mockito::ServerGuard, location: &str, content_range: &str) -> mockito::Mock {
+  server
+    .mock("PATCH", location)
+    .match_header("content-range", content_range)
+    .match_header("content-type", "application/octet-stream")
+}
+
+fn finish_mock(server: &mut mockito::ServerGuard, location: &str) -> mockito::Mock {
+  server
+    .mock("PUT", location)
+    .match_query(mockito::Matcher::UrlEncoded("digest".into(), BLOB_DIGEST.into()))
+    .with_status(201)
+    .with_header("Docker-Content-Digest", BLOB_DIGEST)
+    .create()
+}
+
+#[tokio::test]
+async fn test_uploads_journal_follows_moved_sessions() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let start = server
+    .mock("POST", "/v2/repo/blobs/uploads/")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .with_header("Range", "0-0")
+    .create();
+  let chunk1 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "0-3")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u2")
+    .with_header("Range", "0-3")
+    .create();
+  let failed = upload_mock(&mut server, "/v2/repo/blobs/uploads/u2", "4-7")
+    .with_status(400)
+    .create();
+  let delete = server
+    .mock("DELETE", "/v2/repo/blobs/uploads/u2")
+    .with_status(204)
+    .create();
+
+  let dir = tempfile::tempdir().unwrap();
+  let journal = dir.path().join("uploads.json");
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .upload_journal(&journal)
+    .build()
+    .unwrap();
+
+  assert!(client.push_blob_chunked("repo", BLOB, BLOB_DIGEST, 4).await.is_err());
+
+  let sessions = docker_registry::v2::UploadJournal::new(&journal).sessions().unwrap();
+  assert_eq!(sessions.len(), 1);
+  assert_eq!(
+    sessions[0].location(),
+    format!("http://{addr}/v2/repo/blobs/uploads/u2")
+  );
+  assert_eq!(sessions[0].uuid(), Some("u1"));
+  assert_eq!(sessions[0].digest(), Some(BLOB_DIGEST));
+
+  let cancelled = client.cancel_stale_uploads(Duration::ZERO).await.unwrap();
+  assert_eq!(cancelled.len(), 1);
+  assert!(docker_registry::v2::UploadJournal::new(&journal)
+    .sessions()
+    .unwrap()
+    .is_empty());
+
+  for mock in [start, chunk1, failed, delete] {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_uploads_chunked_resync_after_416() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let start = server
+    .mock("POST", "/v2/repo/blobs/uploads/")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .with_header("Range", "0-0")
+    .create();
+  let chunk1 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "0-3")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u2")
+    .with_header("Range", "0-3")
+    .create();
+  let rejected = upload_mock(&mut server, "/v2/repo/blobs/uploads/u2", "4-7")
+    .with_status(416)
+    .create();
+  // The registry only committed two bytes of the rejected chunk.
+  let status = server
+    .mock("GET", "/v2/repo/blobs/uploads/u2")
+    .with_status(204)
+    .with_header("Location", "/v2/repo/blobs/uploads/u2")
+    .with_header("Range", "0-5")
+    .create();
+  let chunk2 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u2", "6-7")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u3")
+    .with_header("Range", "0-7")
+    .create();
+  let finish = finish_mock(&mut server, "/v2/repo/blobs/uploads/u3");
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let digest = client.push_blob_chunked("repo", BLOB, BLOB_DIGEST, 4).await.unwrap();
+  assert_eq!(digest, BLOB_DIGEST);
+
+  for mock in [start, chunk1, rejected, status, chunk2, finish] {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_uploads_chunked_partial_commit_artifactory() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let start = server
+    .mock("POST", "/v2/repo/blobs/uploads/")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .create();
+  // Artifactory prefixes ranges with `bytes=` and may commit less than it received.
+  let chunk1 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "0-3")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .with_header("Range", "bytes=0-1")
+    .create();
+  let chunk2 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "2-5")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .with_header("Range", "bytes=0-5")
+    .create();
+  let chunk3 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "6-7")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .with_header("Range", "bytes=0-7")
+    .create();
+  let finish = finish_mock(&mut server, "/v2/repo/blobs/uploads/u1");
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let digest = client.push_blob_chunked("repo", BLOB, BLOB_DIGEST, 4).await.unwrap();
+  assert_eq!(digest, BLOB_DIGEST);
+
+  for mock in [start, chunk1, chunk2, chunk3, finish] {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_uploads_single_byte_chunks() {
+  let data = b"ab";
+  let digest = format!("sha256:{:x}", <sha2::Sha256 as sha2::Digest>::digest(data));
+
+  for reader in [false, true] {
+    let mut server = mockito::Server::new_async().await;
+    let start = server
+      .mock("POST", "/v2/repo/blobs/uploads/")
+      .with_status(202)
+      .with_header("Location", "/v2/repo/blobs/uploads/u1")
+      .with_header("Range", "0-0")
+      .create();
+    // A single committed byte is reported as `0-0`, like an empty upload.
+    let chunk1 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "0-0")
+      .with_status(202)
+      .with_header("Location", "/v2/repo/blobs/uploads/u1")
+      .with_header("Range", "0-0")
+      .expect(1)
+      .create();
+    let chunk2 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "1-1")
+      .with_status(202)
+      .with_header("Location", "/v2/repo/blobs/uploads/u1")
+      .with_header("Range", "0-1")
+      .expect(1)
+      .create();
+    let finish = server
+      .mock("PUT", "/v2/repo/blobs/uploads/u1")
+      .match_query(mockito::Matcher::UrlEncoded("digest".into(), digest.clone()))
+      .with_status(201)
+      .with_header("Docker-Content-Digest", &digest)
+      .create();
+
+    let client = docker_registry::v2::Client::configure()
+      .registry(&server.host_with_port())
+      .insecure_registry(true)
+      .build()
+      .unwrap();
+    let pushed = match reader {
+      false => client.push_blob_chunked("repo", data, &digest, 1).await,
+      true => client.push_blob_reader("repo", &data[..], Some(&digest), 1).await,
+    };
+    assert_eq!(pushed.unwrap(), digest);
+
+    for mock in [start, chunk1, chunk2, finish] {
+      mock.assert_async().await;
+    }
+  }
+}
+
+#[tokio::test]
+async fn test_uploads_from_reader() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let start = server
+    .mock("POST", "/v2/repo/blobs/uploads/")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .create();
+  let chunk1 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "0-4")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .with_header("Range", "0-4")
+    .create();
+  // Only part of the last chunk is committed, the rest is resent.
+  let chunk2 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "5-7")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u2")
+    .with_header("Range", "0-5")
+    .create();
+  let chunk3 = upload_mock(&mut server, "/v2/repo/blobs/uploads/u2", "6-7")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u2")
+    .with_header("Range", "0-7")
+    .create();
+  let finish = finish_mock(&mut server, "/v2/repo/blobs/uploads/u2");
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let digest = client.push_blob_reader("repo", BLOB, None, 5).await.unwrap();
+  assert_eq!(digest, BLOB_DIGEST);
+
+  for mock in [start, chunk1, chunk2, chunk3, finish] {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_uploads_from_reader_digest_mismatch() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let start = server
+    .mock("POST", "/v2/repo/blobs/uploads/")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .create();
+  let chunk = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "0-7")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .with_header("Range", "0-7")
+    .create();
+  let cancel = server
+    .mock("DELETE", "/v2/repo/blobs/uploads/u1")
+    .with_status(204)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let wrong = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+  let res = client.push_blob_reader("repo", BLOB, Some(wrong), 1024).await;
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::ContentDigestParse(
+      docker_registry::v2::ContentDigestError::Verify { .. }
+    ))
+  ));
+
+  for mock in [start, chunk, cancel] {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_uploads_from_file() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let start = server
+    .mock("POST", "/v2/repo/blobs/uploads/")
+    .with_status(202)
+    .with_header("Location", "/v2/repo/blobs/uploads/u1")
+    .create();
+  let finish = server
+    .mock("PUT", "/v2/repo/blobs/uploads/u1")
+    .match_query(mockito::Matcher::UrlEncoded("digest".into(), BLOB_DIGEST.into()))
+    .match_header("content-length", "8")
+    .match_body("abcdefgh")
+    .with_status(201)
+    .create();
+
+  let mut file = tempfile::NamedTempFile::new().unwrap();
+  std::io::Write::write_all(&mut file, BLOB).unwrap();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+
+  let options = docker_registry::v2::FileUploadOptions::default().buffer_size(3);
+  let digest = client
+    .push_blob_file("repo", file.path(), None, &options)
+    .await
+    .unwrap();
+  assert_eq!(digest, BLOB_DIGEST);
+
+  start.assert_async().await;
+  finish.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_uploads_retry_idempotent_only() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  let unavailable = server
+    .mock("PUT", "/v2/repo/blobs/uploads/u1")
+    .match_query(mockito::Matcher::Any)
+    .with_status(503)
+    .with_header("Retry-After", "0")
+    .expect(1)
+    .create();
+  let finish = finish_mock(&mut server, "/v2/repo/blobs/uploads/u1");
+  let patch = upload_mock(&mut server, "/v2/repo/blobs/uploads/u1", "0-7")
+    .with_status(503)
+    .expect(1)
+    .create();
+  // The blob is looked up before the upload is completed again.
+  let lookup = server
+    .mock("HEAD", format!("/v2/repo/blobs/{BLOB_DIGEST}").as_str())
+    .with_status(404)
+    .expect(1)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .retry_policy(Some(
+      docker_registry::v2::RetryPolicy::default().initial_backoff(std::time::Duration::ZERO),
+    ))
+    .build()
+    .unwrap();
+
+  let location = format!("http://{addr}/v2/repo/blobs/uploads/u1");
+  let mut session: docker_registry::v2::UploadSession = serde_json::from_value(serde_json::json!({
+    "name": "repo",
+    "location": location,
+    "uuid": null,
+    "started_at": 0,
+  }))
+  .unwrap();
+
+  // PATCH is not idempotent and fails on the first error.
+  assert!(client.upload_chunk(&mut session, BLOB).await.is_err());
+
+  // Completing the upload by digest is retried once the blob is known to be missing.
+  let digest = client.finish_upload(&session, BLOB_DIGEST, None).await.unwrap();
+  assert_eq!(digest, BLOB_DIGEST);
+
+  for mock in [unavailable, finish, patch, lookup] {
+    mock.assert_async().await;
+  }
+}
+
+#[tokio::test]
+async fn test_uploads_completion_committed_before_failure() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+
+  // The registry committed the blob, but the response was lost.
+  let unavailable = server
+    .mock("PUT", "/v2/repo/blobs/uploads/u1")
+    .match_query(mockito::Matcher::Any)
+    .with_status(502)
+    .expect(1)
+    .create();
+  let lookup = server
+    .mock("HEAD", format!("/v2/repo/blobs/{BLOB_DIGEST}").as_str())
+    .with_status(200)
+    .expect(1)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .retry_policy(Some(
+      docker_registry::v2::RetryPolicy::default().initial_backoff(std::time::Duration::ZERO),
+    ))
+    .build()
+    .unwrap();
+
+  let session: docker_registry::v2::UploadSession = serde_json::from_value(serde_json::json!({
+    "name": "repo",
+    "location": format!("http://{addr}/v2/repo/blobs/uploads/u1"),
+    "uuid": null,
+    "started_at": 0,
+  }))
+  .unwrap();
+
+  let digest = client.finish_upload(&session, BLOB_DIGEST, Some(BLOB)).await.unwrap();
+  assert_eq!(digest, BLOB_DIGEST);
+
+  unavailable.assert_async().await;
+  lookup.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_uploads_coalesce_concurrent_pushes() {
+  let mut server = mockito::Server::new_async().await;
+  let addr = server.host_with_port();
+  let location = "/v2/repo/blobs/uploads/c5ec2e36";
+
+  let start = server
+    .mock("POST", "/v2/repo/blobs/uploads/")
+    .with_status(202)
+    .with_header("Location", location)
+    .expect(1)
+    .create();
+  let finish = server
+    .mock("PUT", location)
+    .match_query(mockito::Matcher::UrlEncoded("digest".into(), BLOB_DIGEST.into()))
+    .match_body(BLOB.to_vec())
+    .with_status(201)
+    .with_header("Docker-Content-Digest", BLOB_DIGEST)
+    .expect(1)
+    .create();
+
+  let client = docker_registry::v2::Client::configure()
+    .registry(&addr)
+    .insecure_registry(true)
+    .build()
+    .unwrap();
+  let derived = client.clone();
+
+  // Both tasks push the same blob to the same repository: only the first one uploads it.
+  let (first, second) = futures::join!(
+    client.push_blob("repo", BLOB, BLOB_DIGEST),
+    derived.push_blob_chunked("repo", BLOB, BLOB_DIGEST, 3),
+  );
+  assert_eq!(first.unwrap(), BLOB_DIGEST);
+  assert_eq!(second.unwrap(), BLOB_DIGEST);
+  assert_eq!(client.stats().coalesced_uploads, 1);
+
+  start.assert_async().await;
+  finish.assert_async().await;
+}
@@ -84,7 +84,7 @@ fn test_dockerio_anonymous_auth() {
 }
 
 /// Check that when requesting an image that does not exist
-/// we get an Api error.
+/// we get an authentication error, as Docker Hub does not tell them apart.
 #[test]
 fn test_dockerio_anonymous_non_existent_image() {
   let runtime = Runtime::new().unwrap();
@@ -106,7 +106,11 @@ fn test_dockerio_anonymous_non_existent_image() {
 
   let res = runtime.block_on(futcheck);
   assert!(res.is_err());
-  assert!(matches!(res, Err(docker_registry::errors::Error::Api(_))));
+  assert!(matches!(
+    res,
+    Err(docker_registry::errors::Error::Unauthorized { repository: Some(r), action: Some(a), .. })
+      if r == image && a == "pull"
+  ));
 }
 
 /// Test that we can deserialize OCI image manifest, as is
@@ -117,3 +117,70 @@ fn ipv4_registry_and_library_with_tag() -> Result<(), Box<dyn std::error::Error>
 
   Ok(())
 }
+
+#[test]
+fn repository_names() {
+  use docker_registry::reference::{normalize_repository_name, validate_repository_name};
+
+  for valid in ["busybox", "library/busybox", "a__b/c-d--e/f.g_h", "0/1"] {
+    assert!(validate_repository_name(valid).is_ok(), "{}", valid);
+  }
+  for invalid in [
+    "",
+    "Busybox",
+    "a//b",
+    "/a",
+    "a/",
+    "a___b",
+    "a.-b",
+    "-a",
+    &"a".repeat(256),
+  ] {
+    assert!(validate_repository_name(invalid).is_err(), "{}", invalid);
+  }
+
+  assert_eq!(normalize_repository_name(" /MyOrg//App/ ").unwrap(), "myorg/app");
+  assert!(normalize_repository_name("my org/app").is_err());
+}
+
+#[test]
+fn tags() {
+  use docker_registry::reference::{Tag, TagParseError};
+
+  for valid in ["latest", "v1.2.3", "_internal", "1.0-rc.1", &"a".repeat(128)] {
+    assert_eq!(Tag::parse(valid).unwrap().as_str(), valid);
+  }
+  assert_eq!(Tag::parse(""), Err(TagParseError::Empty));
+  assert_eq!(Tag::parse(&"a".repeat(129)), Err(TagParseError::TooLong(129)));
+  assert_eq!(Tag::parse(".hidden"), Err(TagParseError::InvalidStart('.')));
+  assert_eq!(
+    Tag::parse("feature/x"),
+    Err(TagParseError::InvalidCharacter {
+      character: '/',
+      position: 7
+    })
+  );
+
+  assert_eq!(Tag::sanitize("feature/new-ui").unwrap().as_str(), "feature-new-ui");
+  assert_eq!(Tag::sanitize("--v1.0 beta").unwrap().as_str(), "v1.0-beta");
+  assert_eq!(Tag::sanitize(&"b".repeat(200)).unwrap().as_str().len(), 128);
+  assert_eq!(Tag::sanitize("/.."), None);
+}
+
+#[test]
+fn tag_hint() {
+  let digest = "sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+  let dkr_ref = Reference::from_str(&format!("quay.io/busybox:1.36@{digest}")).unwrap();
+
+  assert_eq!(dkr_ref.repository(), "busybox");
+  assert_eq!(dkr_ref.version(), digest);
+  assert_eq!(dkr_ref.tag_hint().as_deref(), Some("1.36"));
+  assert_eq!(dkr_ref.pull_reference(), format!("1.36@{digest}"));
+  assert_eq!(dkr_ref.to_string(), format!("quay.io/busybox:1.36@{digest}"));
+
+  let dkr_ref = Reference::from_str(&format!("busybox@{digest}")).unwrap();
+  assert_eq!(dkr_ref.tag_hint(), None);
+  assert_eq!(dkr_ref.pull_reference(), digest);
+
+  assert!(Reference::from_str(&format!("busybox:-bad@{digest}")).is_err());
+}